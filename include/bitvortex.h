@@ -0,0 +1,46 @@
+/* bitvortex C interface (build the crate with the `capi` feature) */
+#ifndef BITVORTEX_H
+#define BITVORTEX_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define BV_OK 0
+#define BV_INVALID -1
+#define BV_NOT_FOUND -2
+#define BV_EXISTS -3
+#define BV_PENDING 1
+#define BV_FINISHED 2
+
+typedef struct DataBucket DataBucket;
+typedef struct DataBucketBlob DataBucketBlob;
+typedef struct BvPipeline BvPipeline;
+
+DataBucket *bv_bucket_new(void);
+void bv_bucket_free(DataBucket *bucket);
+size_t bv_bucket_len(const DataBucket *bucket);
+int bv_bucket_add_blob(DataBucket *bucket, DataBucketBlob *blob);
+int bv_bucket_get_f64(const DataBucket *bucket, const char *name, const double **data, size_t *len);
+int bv_bucket_get_i64(const DataBucket *bucket, const char *name, const int64_t **data, size_t *len);
+int bv_bucket_remove(DataBucket *bucket, const char *name);
+
+DataBucketBlob *bv_blob_new_f64(const char *name, const double *data, size_t len);
+DataBucketBlob *bv_blob_new_i64(const char *name, const int64_t *data, size_t len);
+void bv_blob_free(DataBucketBlob *blob);
+
+BvPipeline *bv_pipeline_scale(double factor);
+BvPipeline *bv_pipeline_offset(double offset);
+int bv_pipeline_push(BvPipeline *pipeline, const double *data, size_t len);
+int bv_pipeline_close(BvPipeline *pipeline);
+int bv_pipeline_pull(BvPipeline *pipeline, double *value);
+void bv_pipeline_free(BvPipeline *pipeline);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,49 @@
+/* bitvortex plugin interface (loaded by the crate with the `plugin` feature) */
+#ifndef BITVORTEX_PLUGIN_H
+#define BITVORTEX_PLUGIN_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+#define BV_PLUGIN_ABI_VERSION 1
+
+#define BV_ITEMS_BYTES 0
+#define BV_ITEMS_TEXT 1
+#define BV_ITEMS_NUMBER 2
+
+typedef struct BvParam {
+    const char *key;
+    const char *value;
+} BvParam;
+
+typedef void (*BvEmit)(void *context, const uint8_t *data, size_t len);
+
+typedef struct BvPipeDescriptor {
+    const char *kind;
+    const char *help;
+    uint32_t input;
+    uint32_t output;
+    void *(*create)(const BvParam *params, size_t count);
+    int (*process)(void *state, const uint8_t *data, size_t len, BvEmit emit, void *context);
+    int (*finish)(void *state, BvEmit emit, void *context);
+    void (*free)(void *state);
+} BvPipeDescriptor;
+
+typedef struct BvPlugin {
+    uint32_t abi_version;
+    const char *name;
+    const BvPipeDescriptor *pipes;
+    size_t pipe_count;
+} BvPlugin;
+
+const BvPlugin *bv_plugin_entry(void);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,238 @@
+//! ack
+//!
+//! At least once delivery: sinks acknowledge the items they processed back to the source
+//! that produced them
+//!
+//! an `AckingSource` numbers the items of its input with consecutive offsets and wraps them
+//! in `Delivery`s, its committed position is the offset of the first item not acknowledged
+//! yet and only advances once every item before it was acknowledged, so a source resuming
+//! from the committed position after a failure replays everything that did not land, an
+//! item dropped without acknowledgement holds the position back
+
+use crate::{Sink, Source};
+use futures::future::LocalBoxFuture;
+use futures::{Stream, StreamExt};
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+
+type CommitCallback = Box<dyn FnMut(u64) + Send>;
+
+struct TrackerState {
+    position: u64,
+    next: u64,
+    pending: BTreeSet<u64>,
+    on_commit: Option<CommitCallback>,
+}
+
+/// AckTracker
+/// The offsets delivered by a source and not acknowledged yet, clones share the tracking
+#[derive(Clone)]
+pub struct AckTracker {
+    state: Arc<Mutex<TrackerState>>,
+}
+
+impl AckTracker {
+    /// constructor for offsets starting at a committed position
+    pub fn new(position: u64) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(TrackerState {
+                position,
+                next: position,
+                pending: BTreeSet::new(),
+                on_commit: None,
+            })),
+        }
+    }
+    /// the offset of the first item not acknowledged yet
+    pub fn position(&self) -> u64 {
+        self.state.lock().unwrap().position
+    }
+    /// the number of delivered items waiting on an acknowledgement
+    pub fn pending(&self) -> usize {
+        self.state.lock().unwrap().pending.len()
+    }
+    /// set the function called with the new position whenever it advances
+    pub fn on_commit<F: FnMut(u64) + Send + 'static>(&self, on_commit: F) {
+        self.state.lock().unwrap().on_commit = Some(Box::new(on_commit));
+    }
+    fn deliver(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        let offset = state.next;
+        state.next += 1;
+        state.pending.insert(offset);
+        offset
+    }
+    fn acknowledge(&self, offset: u64) {
+        let mut state = self.state.lock().unwrap();
+        if !state.pending.remove(&offset) {
+            return;
+        }
+        let position = state.pending.first().copied().unwrap_or(state.next);
+        if position > state.position {
+            state.position = position;
+            if let Some(on_commit) = state.on_commit.as_mut() {
+                on_commit(position);
+            }
+        }
+    }
+}
+
+/// Delivery
+/// An item along with the handle acknowledging it to its source
+pub struct Delivery<T> {
+    pub value: T,
+    offset: u64,
+    tracker: AckTracker,
+}
+
+impl<T> Delivery<T> {
+    /// the offset of the item in its source
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+    /// transform the value keeping the acknowledgement handle
+    pub fn map<U, F: FnOnce(T) -> U>(self, function: F) -> Delivery<U> {
+        Delivery {
+            value: function(self.value),
+            offset: self.offset,
+            tracker: self.tracker,
+        }
+    }
+    /// acknowledge the item, returning its value
+    pub fn ack(self) -> T {
+        self.tracker.acknowledge(self.offset);
+        self.value
+    }
+}
+
+/// AckingSource
+/// A source delivering the items of its input for acknowledgement
+pub struct AckingSource<T> {
+    input: Rc<dyn Source<T>>,
+    tracker: AckTracker,
+}
+
+impl<T> AckingSource<T> {
+    /// constructor numbering the items of the input from a committed position
+    pub fn new(input: Rc<dyn Source<T>>, position: u64) -> Self {
+        Self {
+            input,
+            tracker: AckTracker::new(position),
+        }
+    }
+    /// the tracker of the delivered items
+    pub fn tracker(&self) -> AckTracker {
+        self.tracker.clone()
+    }
+}
+
+impl<T: 'static> Source<Delivery<T>> for AckingSource<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = Delivery<T>>> {
+        let tracker = self.tracker.clone();
+        Box::new(Pin::from(self.input.stream()).map(move |value| Delivery {
+            value,
+            offset: tracker.deliver(),
+            tracker: tracker.clone(),
+        }))
+    }
+}
+
+type Handler<T> = Rc<RefCell<dyn FnMut(T) -> Result<(), &'static str>>>;
+
+/// AckingSink
+/// A sink handing the value of every delivery to a function and acknowledging it once the
+/// function succeeded, the first failure ends the drain leaving the item unacknowledged
+pub struct AckingSink<T> {
+    handler: Handler<T>,
+    input: Option<Rc<dyn Source<Delivery<T>>>>,
+}
+
+impl<T: 'static> AckingSink<T> {
+    /// constructor
+    pub fn new<F: FnMut(T) -> Result<(), &'static str> + 'static>(handler: F) -> Self {
+        Self {
+            handler: Rc::new(RefCell::new(handler)),
+            input: None,
+        }
+    }
+}
+
+impl<T: 'static> Sink<Delivery<T>> for AckingSink<T> {
+    input_connection!(Delivery<T>);
+    fn drain(&self) -> LocalBoxFuture<'static, Result<(), &'static str>> {
+        let input = self.input.clone();
+        let handler = self.handler.clone();
+        Box::pin(async move {
+            let mut stream = Pin::from(input.ok_or("Sink has no input")?.stream());
+            while let Some(delivery) = stream.next().await {
+                let Delivery {
+                    value,
+                    offset,
+                    tracker,
+                } = delivery;
+                (handler.borrow_mut())(value)?;
+                tracker.acknowledge(offset);
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_position_waits_for_every_ack() {
+        let source = AckingSource::new(Rc::new(MockSource::new().items(['a', 'b', 'c'])), 10);
+        let tracker = source.tracker();
+        let commits = Arc::new(Mutex::new(Vec::new()));
+        let recorded = commits.clone();
+        tracker.on_commit(move |position| recorded.lock().unwrap().push(position));
+        let deliveries: Vec<Delivery<char>> = block_on(Pin::from(source.stream()).collect());
+        let offsets: Vec<u64> = deliveries.iter().map(Delivery::offset).collect();
+        assert_eq!(offsets, vec![10, 11, 12]);
+        let mut deliveries = deliveries.into_iter();
+        let (first, second, third) = (
+            deliveries.next().unwrap(),
+            deliveries.next().unwrap(),
+            deliveries.next().unwrap(),
+        );
+        assert_eq!(second.map(|c| c.to_ascii_uppercase()).ack(), 'B');
+        assert_eq!(
+            tracker.position(),
+            10,
+            "Position skipped an unacknowledged item"
+        );
+        first.ack();
+        assert_eq!(tracker.position(), 12);
+        third.ack();
+        assert_eq!(tracker.position(), 13);
+        assert_eq!(tracker.pending(), 0);
+        assert_eq!(*commits.lock().unwrap(), vec![12, 13]);
+    }
+
+    #[test]
+    fn test_sink_acks_processed_items() {
+        let source = AckingSource::new(Rc::new(MockSource::new().items([1, 2, -3, 4])), 0);
+        let tracker = source.tracker();
+        let landed = Rc::new(RefCell::new(Vec::new()));
+        let output = landed.clone();
+        let mut sink = AckingSink::new(move |item: i32| {
+            if item < 0 {
+                return Err("Negative item");
+            }
+            output.borrow_mut().push(item);
+            Ok(())
+        });
+        sink.pipe(Rc::new(source)).unwrap();
+        assert_eq!(block_on(sink.drain()), Err("Negative item"));
+        assert_eq!(*landed.borrow(), vec![1, 2]);
+        assert_eq!(tracker.position(), 2, "Failed item was committed");
+    }
+}
@@ -0,0 +1,35 @@
+//! analysis
+//!
+//! Pipes estimating relations between and within numeric streams, and the frequencies and
+//! distinct counts of their keys
+
+/// xcorr
+/// Sub module for cross-correlating streams and estimating their lag
+pub mod xcorr;
+
+/// regression
+/// Sub module for fitting linear models over streams of samples
+pub mod regression;
+
+/// kmeans
+/// Sub module for clustering points with streaming k-means
+pub mod kmeans;
+
+/// count_min
+/// Sub module for estimating key frequencies with count-min sketches
+pub mod count_min;
+
+/// cardinality
+/// Sub module for estimating distinct counts with HyperLogLog
+pub mod cardinality;
+
+/// top_k
+/// Sub module for finding heavy hitters with space-saving summaries
+pub mod top_k;
+
+pub use cardinality::{Cardinality, CardinalityPipe, HyperLogLog};
+pub use count_min::{CountMinPipe, CountMinSketch};
+pub use kmeans::{Clusters, StreamingKMeansPipe};
+pub use regression::{Fitting, OnlineRegressionPipe, RegressionFit};
+pub use top_k::{HeavyHitter, SpaceSaving, TopK, TopKPipe};
+pub use xcorr::{XCorr, XCorrPipe};
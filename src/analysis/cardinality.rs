@@ -0,0 +1,268 @@
+//! cardinality
+//!
+//! Estimating the number of distinct values of a stream with HyperLogLog
+//!
+//! a sketch of `2^precision` registers estimates any number of distinct values with a
+//! relative standard error of about `1.04 / sqrt(2^precision)`, in one byte per register,
+//! and merging two sketches estimates the distinct values of their union
+
+use crate::event::{Event, Timed};
+use crate::pipes::bloom::seeded_hash;
+use crate::window::{Accumulator, Firing, SideOutput, Trigger, WindowKind, Windowing};
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::hash::Hash;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// HyperLogLog
+/// Registers estimating the number of distinct values added
+#[derive(Clone, Debug, PartialEq)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    /// constructor of a sketch of `2^precision` registers, precision in `[4, 16]`
+    pub fn new(precision: u8) -> Result<Self, &'static str> {
+        if !(4..=16).contains(&precision) {
+            return Err("HyperLogLog precision must lie in [4, 16]");
+        }
+        Ok(Self {
+            precision,
+            registers: vec![0; 1 << precision],
+        })
+    }
+    /// the number of bits indexing the registers
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+    /// add a value
+    pub fn insert<K: Hash + ?Sized>(&mut self, value: &K) {
+        let hash = seeded_hash(value, 0);
+        let register = (hash >> (64 - self.precision)) as usize;
+        // the rank of the first set bit after the index bits, the last bit set as a sentinel
+        let rank = ((hash << self.precision) | 1 << (self.precision - 1)).leading_zeros() + 1;
+        self.registers[register] = self.registers[register].max(rank as u8);
+    }
+    /// the estimated number of distinct values added
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let estimate = alpha * m * m / sum;
+        let empty = self.registers.iter().filter(|r| **r == 0).count();
+        // small cardinalities are estimated better by counting the empty registers
+        if estimate <= 2.5 * m && empty > 0 {
+            return (m * (m / empty as f64).ln()).round() as u64;
+        }
+        estimate.round() as u64
+    }
+    /// add the values of a sketch of the same precision
+    pub fn merge(&mut self, other: &HyperLogLog) -> Result<(), &'static str> {
+        if self.precision != other.precision {
+            return Err("Cannot merge sketches of different precisions");
+        }
+        for (register, other) in self.registers.iter_mut().zip(&other.registers) {
+            *register = (*register).max(*other);
+        }
+        Ok(())
+    }
+    /// forget every value
+    pub fn clear(&mut self) {
+        self.registers.fill(0);
+    }
+}
+
+/// Cardinality
+/// The estimated number of distinct values of one key within a window
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cardinality<K = ()> {
+    pub key: K,
+    pub start: i64,
+    pub end: i64,
+    pub distinct: u64,
+    pub firing: Firing,
+}
+
+/// the sketch of one key in one window
+impl<T: Hash> Accumulator<T> for HyperLogLog {
+    type Output = u64;
+    fn add(&mut self, event: &Event<T>) {
+        self.insert(&event.value);
+    }
+    fn merge(&mut self, other: Self) {
+        // the sketches of a pipe share their precision
+        let _ = HyperLogLog::merge(self, &other);
+    }
+    fn emit(&mut self, _close: bool) -> u64 {
+        self.estimate()
+    }
+}
+
+/// CardinalityPipe
+/// A pipe estimating the number of distinct values of a timed stream within windows,
+/// optionally per key, with a HyperLogLog sketch per window and key
+///
+/// windows are assigned, fired and closed as by `WindowPipe`, with the same triggers,
+/// allowed lateness and late output, but only keep their sketches
+pub struct CardinalityPipe<T, K = ()> {
+    windowing: Windowing<T, K>,
+    precision: u8,
+    input: Option<Rc<dyn Source<Timed<T>>>>,
+}
+
+impl<T> CardinalityPipe<T, ()> {
+    /// constructor counting the distinct values over every event, with sketches of a
+    /// precision in `[4, 16]`
+    pub fn new(kind: WindowKind, precision: u8) -> Result<Self, &'static str> {
+        CardinalityPipe::keyed(kind, precision, |_: &T| ())
+    }
+}
+
+impl<T, K> CardinalityPipe<T, K> {
+    /// constructor counting the distinct values of every key separately
+    pub fn keyed<F: Fn(&T) -> K + 'static>(
+        kind: WindowKind,
+        precision: u8,
+        key: F,
+    ) -> Result<Self, &'static str> {
+        HyperLogLog::new(precision)?;
+        Ok(Self {
+            windowing: Windowing::new(kind, key)?,
+            precision,
+            input: None,
+        })
+    }
+    /// set the trigger deciding when windows fire
+    pub fn set_trigger(&mut self, trigger: impl Trigger + 'static) {
+        self.windowing.set_trigger(trigger);
+    }
+    /// set how long (in ticks of event time) windows accept events after firing
+    pub fn set_allowed_lateness(&mut self, allowed_lateness: i64) -> Result<(), &'static str> {
+        self.windowing.set_allowed_lateness(allowed_lateness)
+    }
+    /// the source of the events arriving after every window covering them closed
+    pub fn late_output(&self) -> SideOutput<Event<T>> {
+        self.windowing.late_output()
+    }
+}
+
+impl<T, K> Source<Cardinality<K>> for CardinalityPipe<T, K>
+where
+    T: Hash + 'static,
+    K: PartialEq + Clone + 'static,
+{
+    fn stream(&self) -> Box<dyn Stream<Item = Cardinality<K>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let precision = self.precision;
+        let sketch = move || HyperLogLog::new(precision).unwrap();
+        let counts = self.windowing.fire(input, sketch).map(|fired| Cardinality {
+            key: fired.key,
+            start: fired.start,
+            end: fired.end,
+            distinct: fired.output,
+            firing: fired.firing,
+        });
+        Box::new(counts)
+    }
+}
+
+impl<T, K> Pipe<Timed<T>, Cardinality<K>> for CardinalityPipe<T, K>
+where
+    T: Hash + 'static,
+    K: PartialEq + Clone + 'static,
+{
+    input_connection!(Timed<T>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_hyper_log_log() {
+        let mut sketch = HyperLogLog::new(12).unwrap();
+        assert_eq!(sketch.estimate(), 0);
+        for value in 0..100u32 {
+            sketch.insert(&value);
+            sketch.insert(&value);
+        }
+        assert!(sketch.estimate().abs_diff(100) <= 2);
+        let mut other = HyperLogLog::new(12).unwrap();
+        for value in 0..100_000u32 {
+            other.insert(&value);
+        }
+        let estimate = other.estimate();
+        assert!(estimate.abs_diff(100_000) < 5_000, "{} distinct", estimate);
+        sketch.merge(&other).unwrap();
+        assert_eq!(sketch.estimate(), estimate);
+        assert!(sketch.merge(&HyperLogLog::new(10).unwrap()).is_err());
+        assert!(HyperLogLog::new(17).is_err());
+    }
+
+    #[test]
+    fn test_cardinality_pipe() {
+        let event = |timestamp, value| Timed::Event(Event::new(timestamp, value));
+        let mut pipe = CardinalityPipe::keyed(
+            WindowKind::Tumbling { size: 10 },
+            10,
+            |value: &(char, u32)| value.0,
+        )
+        .unwrap();
+        pipe.pipe(Rc::new(MockSource::new().items([
+            event(1, ('a', 1)),
+            event(2, ('b', 1)),
+            event(3, ('a', 2)),
+            event(4, ('a', 1)),
+            event(12, ('a', 3)),
+            Timed::Watermark(10),
+            event(5, ('b', 2)),
+            event(15, ('b', 4)),
+            event(16, ('b', 4)),
+        ])))
+        .unwrap();
+        let late = pipe.late_output();
+        let (counts, late) = block_on(async {
+            futures::join!(
+                Pin::from(pipe.stream()).collect::<Vec<_>>(),
+                Pin::from(late.stream()).collect::<Vec<_>>()
+            )
+        });
+        let counts: Vec<(char, i64, u64)> = counts
+            .into_iter()
+            .map(|count| (count.key, count.start, count.distinct))
+            .collect();
+        assert_eq!(
+            counts,
+            vec![('a', 0, 2), ('b', 0, 1), ('a', 10, 1), ('b', 10, 1)]
+        );
+        assert_eq!(late, vec![Event::new(5, ('b', 2))], "Late event dropped");
+        let mut pipe = CardinalityPipe::new(WindowKind::Session { gap: 5 }, 10).unwrap();
+        let event = |timestamp, value: u32| Timed::Event(Event::new(timestamp, value));
+        pipe.pipe(Rc::new(MockSource::new().items([
+            event(0, 1),
+            event(3, 2),
+            event(6, 1),
+            event(20, 3),
+        ])))
+        .unwrap();
+        let counts: Vec<(i64, i64, u64)> = block_on(Pin::from(pipe.stream()).collect::<Vec<_>>())
+            .into_iter()
+            .map(|count| (count.start, count.end, count.distinct))
+            .collect();
+        assert_eq!(counts, vec![(0, 11, 2), (20, 25, 1)], "Sessions not merged");
+    }
+}
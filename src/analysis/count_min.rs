@@ -0,0 +1,271 @@
+//! count_min
+//!
+//! Estimating the frequencies of the keys of a stream with a count-min sketch
+//!
+//! a sketch of `width` counters on each of `depth` rows never underestimates a count and
+//! overestimates it by at most `e / width` of the total count on all but a
+//! `exp(-depth)` share of the keys, whatever the number of distinct keys
+
+use crate::data_bucket::{DataBlob, DataBucket, DataBucketBlob, MetaData};
+use crate::pipes::bloom::seeded_hash;
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// CountMinSketch
+/// Counters estimating the frequencies of keys
+#[derive(Clone, Debug, PartialEq)]
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    counts: Vec<u64>,
+    total: u64,
+}
+
+impl CountMinSketch {
+    /// constructor of a sketch with `depth` rows of `width` counters
+    pub fn new(width: usize, depth: usize) -> Result<Self, &'static str> {
+        if width == 0 || depth == 0 {
+            return Err("Sketch dimensions must be strictly positive");
+        }
+        Ok(Self {
+            width,
+            depth,
+            counts: vec![0; width * depth],
+            total: 0,
+        })
+    }
+    /// constructor of a sketch overestimating counts by at most `epsilon` of the total
+    /// count but with a probability `delta`, both in `(0, 1)`
+    pub fn with_error(epsilon: f64, delta: f64) -> Result<Self, &'static str> {
+        let valid = |value: f64| value > 0.0 && value < 1.0;
+        if !valid(epsilon) || !valid(delta) {
+            return Err("Sketch error bounds must lie in (0, 1)");
+        }
+        let width = (std::f64::consts::E / epsilon).ceil() as usize;
+        Self::new(width, (1.0 / delta).ln().ceil().max(1.0) as usize)
+    }
+    fn cells<K: Hash + ?Sized>(&self, key: &K) -> impl Iterator<Item = usize> {
+        let (first, second) = (seeded_hash(key, 0), seeded_hash(key, 1) | 1);
+        let width = self.width;
+        (0..self.depth).map(move |row| {
+            let hash = first.wrapping_add((row as u64).wrapping_mul(second));
+            row * width + (hash % width as u64) as usize
+        })
+    }
+    /// count a key a number of times
+    pub fn add<K: Hash + ?Sized>(&mut self, key: &K, count: u64) {
+        for cell in self.cells(key).collect::<Vec<usize>>() {
+            self.counts[cell] = self.counts[cell].saturating_add(count);
+        }
+        self.total = self.total.saturating_add(count);
+    }
+    /// the estimated count of a key, never below its true count
+    pub fn estimate<K: Hash + ?Sized>(&self, key: &K) -> u64 {
+        self.cells(key)
+            .map(|cell| self.counts[cell])
+            .min()
+            .unwrap_or(0)
+    }
+    /// the sum of every count added
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+    /// add the counts of a sketch of the same dimensions
+    pub fn merge(&mut self, other: &CountMinSketch) -> Result<(), &'static str> {
+        if (self.width, self.depth) != (other.width, other.depth) {
+            return Err("Cannot merge sketches of different dimensions");
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count = count.saturating_add(*other);
+        }
+        self.total = self.total.saturating_add(other.total);
+        Ok(())
+    }
+    /// reset every count
+    pub fn clear(&mut self) {
+        self.counts.fill(0);
+        self.total = 0;
+    }
+}
+
+/// CountMinPipe
+/// A pipe counting the keys of its input in a count-min sketch, emitting a frequency table
+/// every number of items and once more when the input ends
+///
+/// a table is a bucket of the `keys` seen since the previous table (as text, by decreasing
+/// estimated count) with their estimated `counts` since the stream started and the `total`
+/// count. The sketch starts over with every stream and can be queried while it runs
+pub struct CountMinPipe<T, K = T> {
+    sketch: Rc<RefCell<CountMinSketch>>,
+    key: Rc<dyn Fn(&T) -> K>,
+    every: usize,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T: Hash + Eq + Clone + ToString + 'static> CountMinPipe<T> {
+    /// constructor counting whole items, emitting every 1000 items
+    pub fn new(sketch: CountMinSketch) -> Self {
+        Self::by_key(sketch, T::clone)
+    }
+}
+
+impl<T, K> CountMinPipe<T, K> {
+    /// constructor counting the keys extracted from the items
+    pub fn by_key<F: Fn(&T) -> K + 'static>(sketch: CountMinSketch, key: F) -> Self {
+        Self {
+            sketch: Rc::new(RefCell::new(sketch)),
+            key: Rc::new(key),
+            every: 1000,
+            input: None,
+        }
+    }
+    /// set the number of items between tables, at least 1
+    pub fn with_every(mut self, every: usize) -> Self {
+        self.every = every.max(1);
+        self
+    }
+    /// the estimated count of a key in the current stream
+    pub fn estimate<Q: Hash + ?Sized>(&self, key: &Q) -> u64 {
+        self.sketch.borrow().estimate(key)
+    }
+    /// the sketch of the current stream
+    pub fn sketch(&self) -> CountMinSketch {
+        self.sketch.borrow().clone()
+    }
+}
+
+/// the frequency table of the keys seen in a period
+fn table<K: Hash + ToString>(sketch: &CountMinSketch, keys: Vec<K>) -> DataBucket {
+    let mut rows: Vec<(u64, String)> = keys
+        .iter()
+        .map(|key| (sketch.estimate(key), key.to_string()))
+        .collect();
+    // a stable sort keeps keys of the same count in order of appearance
+    rows.sort_by_key(|row| std::cmp::Reverse(row.0));
+    let meta = |name: &str, len: usize| {
+        let mut meta = MetaData {
+            name: name.to_string(),
+            dimensions: vec![len],
+            unitary_dimensions: vec![1],
+            ..Default::default()
+        };
+        meta.record(
+            "count_min",
+            &[
+                ("width", sketch.width.to_string()),
+                ("depth", sketch.depth.to_string()),
+            ],
+        );
+        meta
+    };
+    let (counts, keys): (Vec<u64>, Vec<String>) = rows.into_iter().unzip();
+    let mut bucket = DataBucket::new();
+    bucket.add_blob(DataBucketBlob::Str(DataBlob::new(
+        keys,
+        meta("keys", counts.len()),
+    )));
+    bucket.add_blob(DataBucketBlob::U64(DataBlob::new(
+        counts.clone(),
+        meta("counts", counts.len()),
+    )));
+    bucket.add_blob(DataBucketBlob::U64(DataBlob::new(
+        vec![sketch.total],
+        meta("total", 1),
+    )));
+    bucket
+}
+
+impl<T: 'static, K: Hash + Eq + ToString + 'static> Source<DataBucket> for CountMinPipe<T, K> {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBucket>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let (sketch, key, every) = (self.sketch.clone(), self.key.clone(), self.every);
+        sketch.borrow_mut().clear();
+        // the keys of the period with their position of first appearance
+        let mut seen: HashMap<K, usize> = HashMap::new();
+        let mut pending = 0;
+        let tables = input
+            .map(Some)
+            .chain(stream::iter([None]))
+            .map(move |item| {
+                let mut sketch = sketch.borrow_mut();
+                if let Some(item) = &item {
+                    let key = key(item);
+                    sketch.add(&key, 1);
+                    let next = seen.len();
+                    seen.entry(key).or_insert(next);
+                    pending += 1;
+                }
+                if pending == 0 || item.is_some() && pending < every {
+                    return None;
+                }
+                pending = 0;
+                let mut keys: Vec<(K, usize)> = seen.drain().collect();
+                keys.sort_by_key(|(_, position)| *position);
+                Some(table(
+                    &sketch,
+                    keys.into_iter().map(|(key, _)| key).collect(),
+                ))
+            });
+        Box::new(tables.filter_map(futures::future::ready))
+    }
+}
+
+impl<T: 'static, K: Hash + Eq + ToString + 'static> Pipe<T, DataBucket> for CountMinPipe<T, K> {
+    input_connection!(T);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_count_min_sketch() {
+        let mut sketch = CountMinSketch::with_error(0.001, 0.01).unwrap();
+        assert_eq!((sketch.width, sketch.depth), (2719, 5));
+        for key in 0..20_000u32 {
+            sketch.add(&key, 1 + (key % 10 == 0) as u64 * 99);
+        }
+        assert_eq!(sketch.total(), 18_000 + 2_000 * 100);
+        let heavy = sketch.estimate(&10u32);
+        assert!((100..100 + 218).contains(&heavy));
+        let errors: u64 = (0..20_000u32)
+            .map(|key| sketch.estimate(&key) - 1 - (key % 10 == 0) as u64 * 99)
+            .sum();
+        assert!(errors / 20_000 < 218);
+        let mut other = CountMinSketch::with_error(0.001, 0.01).unwrap();
+        other.add("late", 3);
+        sketch.merge(&other).unwrap();
+        assert!(sketch.estimate("late") >= 3);
+        assert!(sketch.merge(&CountMinSketch::new(10, 5).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_count_min_pipe() {
+        let words = ["to", "be", "or", "not", "to", "be", "to"];
+        let mut pipe = CountMinPipe::new(CountMinSketch::new(1024, 4).unwrap()).with_every(4);
+        pipe.pipe(Rc::new(MockSource::new().items(words))).unwrap();
+        let tables: Vec<DataBucket> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(tables.len(), 2);
+        let column = |table: &DataBucket, name: &str| table.get_blob(&name.to_string()).cloned();
+        match (column(&tables[1], "keys"), column(&tables[1], "counts")) {
+            (Some(DataBucketBlob::Str(keys)), Some(DataBucketBlob::U64(counts))) => {
+                assert_eq!(keys.get_data(), &vec!["to", "be"]);
+                assert_eq!(counts.get_data(), &vec![3, 2]);
+            }
+            _ => panic!("Missing frequency table"),
+        }
+        assert_eq!(pipe.estimate(&"to"), 3);
+        assert_eq!(pipe.sketch().total(), 7);
+    }
+}
@@ -0,0 +1,413 @@
+//! kmeans
+//!
+//! Clustering the points of timed streams of blobs with streaming k-means within windows
+//!
+//! every unit of a blob is a point, units with an invalid element are left out. Centroids
+//! start as the farthest points of the first blobs of a window from one another and every
+//! blob then moves them as a mini batch: each centroid steps towards the mean of the points
+//! closest to it by the share those points have of its weight. Merging sessions moves the
+//! centroids of one towards those of the other the same way, as points of their weights
+
+use crate::data_bucket::{DataBlob, DataBucket, DataBucketBlob, MetaData};
+use crate::event::{Event, Timed};
+use crate::window::{Accumulator, Firing, SideOutput, Trigger, WindowKind, Windowing};
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::pin::Pin;
+use std::rc::Rc;
+
+fn squared_distance(a: &[f64], b: &[f64]) -> f64 {
+    a.iter().zip(b).map(|(a, b)| (a - b) * (a - b)).sum()
+}
+
+/// the clustering of one key in one window
+struct Clustering {
+    k: usize,
+    decay: f64,
+    dimension: Option<usize>,
+    centroids: Vec<Vec<f64>>,
+    weights: Vec<f64>,
+    /// squared distances of the points of the window to their centroids before they moved
+    inertia: f64,
+    /// the first blob that could not be clustered
+    failure: Option<&'static str>,
+}
+
+impl Clustering {
+    fn new(k: usize, decay: f64) -> Self {
+        Self {
+            k,
+            decay,
+            dimension: None,
+            centroids: Vec::new(),
+            weights: Vec::new(),
+            inertia: 0.0,
+            failure: None,
+        }
+    }
+    fn nearest(&self, point: &[f64]) -> (usize, f64) {
+        let distances = self.centroids.iter().map(|c| squared_distance(c, point));
+        distances
+            .enumerate()
+            .fold((0, f64::INFINITY), |best, (at, distance)| {
+                match distance < best.1 {
+                    true => (at, distance),
+                    false => best,
+                }
+            })
+    }
+    fn check_dimension(&mut self, size: usize) -> bool {
+        if *self.dimension.get_or_insert(size) != size {
+            self.failure = Some("Blob points do not match the centroid dimensions");
+        }
+        self.failure.is_none()
+    }
+    /// move the centroids towards a mini batch of weighted points
+    fn fit(&mut self, points: &[(&[f64], f64)]) {
+        // seed the missing centroids with the points farthest from the others
+        while self.centroids.len() < self.k {
+            let farthest = points
+                .iter()
+                .map(|(point, _)| (point, self.nearest(point).1))
+                .filter(|(_, distance)| *distance > 0.0)
+                .max_by(|a, b| a.1.total_cmp(&b.1));
+            match farthest {
+                Some((point, _)) => {
+                    self.centroids.push(point.to_vec());
+                    self.weights.push(0.0);
+                }
+                None => break,
+            }
+        }
+        let assigned: Vec<(usize, f64)> = points
+            .iter()
+            .map(|(point, _)| self.nearest(point))
+            .collect();
+        for ((point, weight), (centroid, distance)) in points.iter().zip(assigned) {
+            self.inertia += weight * distance;
+            self.weights[centroid] += weight;
+            let step = weight / self.weights[centroid];
+            for (value, target) in self.centroids[centroid].iter_mut().zip(point.iter()) {
+                *value += (*target - *value) * step;
+            }
+        }
+    }
+    fn learn(&mut self, blob: &DataBlob<f64>) {
+        let size = blob.unit_size().max(1);
+        if self.failure.is_some() || !self.check_dimension(size) {
+            return;
+        }
+        let points: Vec<(&[f64], f64)> = blob
+            .get_data()
+            .chunks_exact(size)
+            .enumerate()
+            .filter(|(at, _)| (at * size..(at + 1) * size).all(|index| blob.is_valid(index)))
+            .map(|(_, point)| (point, 1.0))
+            .collect();
+        for weight in self.weights.iter_mut() {
+            *weight *= self.decay;
+        }
+        self.fit(&points);
+    }
+    /// the bucket of the centroids
+    fn bucket(&self) -> DataBucket {
+        let dimension = self.dimension.unwrap_or(0);
+        let blob = |name: &str, data: Vec<f64>, dimensions: Vec<usize>, unit: usize| {
+            let mut meta = MetaData {
+                name: name.to_string(),
+                dimensions,
+                unitary_dimensions: vec![unit],
+                ..Default::default()
+            };
+            meta.record("streaming_kmeans", &[("k", self.k.to_string())]);
+            DataBucketBlob::Float64(DataBlob::new(data, meta))
+        };
+        let count = self.centroids.len();
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(blob(
+            "centroids",
+            self.centroids.concat(),
+            vec![count, dimension],
+            dimension,
+        ));
+        bucket.add_blob(blob("weights", self.weights.clone(), vec![count], 1));
+        bucket.add_blob(blob("inertia", vec![self.inertia], vec![1], 1));
+        bucket
+    }
+}
+
+impl Accumulator<DataBlob<f64>> for Clustering {
+    type Output = Result<DataBucket, &'static str>;
+    fn add(&mut self, event: &Event<DataBlob<f64>>) {
+        self.learn(&event.value);
+    }
+    fn merge(&mut self, mut other: Self) {
+        let Some(dimension) = other.dimension else {
+            return;
+        };
+        if self.dimension.is_none() {
+            other.failure = self.failure.or(other.failure);
+            *self = other;
+            return;
+        }
+        self.failure = self.failure.or(other.failure);
+        if self.failure.is_some() || !self.check_dimension(dimension) {
+            return;
+        }
+        // the inertia of the other points around their new centroids, as far as their own
+        // centroids tell
+        self.inertia += other.inertia;
+        let points: Vec<(&[f64], f64)> = other
+            .centroids
+            .iter()
+            .zip(&other.weights)
+            .map(|(centroid, weight)| (centroid.as_slice(), *weight))
+            .collect();
+        self.fit(&points);
+    }
+    fn emit(&mut self, _close: bool) -> Self::Output {
+        match self.failure {
+            Some(failure) => Err(failure),
+            None => Ok(self.bucket()),
+        }
+    }
+}
+
+/// Clusters
+/// The centroids fitted to the points of one key within a window
+///
+/// the bucket holds the `centroids` (one per unit), their `weights` (the decayed number of
+/// points they were fitted to) and the `inertia` of the window (the sum of the squared
+/// distances of its points to their centroids before they moved)
+#[derive(Clone)]
+pub struct Clusters<K = ()> {
+    pub key: K,
+    pub start: i64,
+    pub end: i64,
+    pub centroids: DataBucket,
+    pub firing: Firing,
+}
+
+/// StreamingKMeansPipe
+/// A pipe clustering the points of a timed stream of blobs into `k` clusters within
+/// windows, optionally per key, emitting the centroids every time a window fires
+///
+/// windows are assigned, fired and closed as by `WindowPipe`, with the same triggers,
+/// allowed lateness and late output, but only keep their clustering. Every window starts
+/// over, the stream ends at the first window fed points of different dimensions
+pub struct StreamingKMeansPipe<K = ()> {
+    windowing: Windowing<DataBlob<f64>, K>,
+    k: usize,
+    decay: f64,
+    input: Option<Rc<dyn Source<Timed<DataBlob<f64>>>>>,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl StreamingKMeansPipe<()> {
+    /// constructor for a number of clusters over every blob
+    pub fn new(kind: WindowKind, k: usize) -> Result<Self, &'static str> {
+        StreamingKMeansPipe::keyed(kind, k, |_: &DataBlob<f64>| ())
+    }
+}
+
+impl<K> StreamingKMeansPipe<K> {
+    /// constructor clustering the blobs of every key separately
+    pub fn keyed<F: Fn(&DataBlob<f64>) -> K + 'static>(
+        kind: WindowKind,
+        k: usize,
+        key: F,
+    ) -> Result<Self, &'static str> {
+        if k == 0 {
+            return Err("Number of clusters must be strictly positive");
+        }
+        Ok(Self {
+            windowing: Windowing::new(kind, key)?,
+            k,
+            decay: 1.0,
+            input: None,
+            error: Rc::new(Cell::new(None)),
+        })
+    }
+    /// set the factor in `(0, 1]` weighing the points of every past blob of a window down
+    /// (1, never forgetting, by default)
+    pub fn with_decay(mut self, decay: f64) -> Result<Self, &'static str> {
+        if !(decay > 0.0 && decay <= 1.0) {
+            return Err("Decay must lie in (0, 1]");
+        }
+        self.decay = decay;
+        Ok(self)
+    }
+    /// set the trigger deciding when windows fire
+    pub fn set_trigger(&mut self, trigger: impl Trigger + 'static) {
+        self.windowing.set_trigger(trigger);
+    }
+    /// set how long (in ticks of event time) windows accept events after firing
+    pub fn set_allowed_lateness(&mut self, allowed_lateness: i64) -> Result<(), &'static str> {
+        self.windowing.set_allowed_lateness(allowed_lateness)
+    }
+    /// the source of the events arriving after every window covering them closed
+    pub fn late_output(&self) -> SideOutput<Event<DataBlob<f64>>> {
+        self.windowing.late_output()
+    }
+    /// the failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl<K: PartialEq + Clone + 'static> Source<Clusters<K>> for StreamingKMeansPipe<K> {
+    fn stream(&self) -> Box<dyn Stream<Item = Clusters<K>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let error = self.error.clone();
+        error.set(None);
+        let (k, decay) = (self.k, self.decay);
+        let clusters = self
+            .windowing
+            .fire(input, move || Clustering::new(k, decay))
+            .map(move |fired| match fired.output {
+                Ok(centroids) => Some(Clusters {
+                    key: fired.key,
+                    start: fired.start,
+                    end: fired.end,
+                    centroids,
+                    firing: fired.firing,
+                }),
+                Err(failure) => {
+                    error.set(Some(failure));
+                    None
+                }
+            })
+            .take_while(|clusters| futures::future::ready(clusters.is_some()))
+            .filter_map(futures::future::ready);
+        Box::new(clusters)
+    }
+}
+
+impl<K: PartialEq + Clone + 'static> Pipe<Timed<DataBlob<f64>>, Clusters<K>>
+    for StreamingKMeansPipe<K>
+{
+    input_connection!(Timed<DataBlob<f64>>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::mask::ValidityMask;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn points(values: &[f64]) -> DataBlob<f64> {
+        let meta = MetaData {
+            unitary_dimensions: vec![2],
+            ..Default::default()
+        };
+        DataBlob::new(values.to_vec(), meta)
+    }
+
+    fn get(clusters: &Clusters, name: &str) -> Vec<f64> {
+        match clusters.centroids.get_blob(&name.to_string()) {
+            Some(DataBucketBlob::Float64(blob)) => blob.get_data().clone(),
+            _ => panic!("Missing blob {}", name),
+        }
+    }
+
+    fn close(actual: &[f64], expected: &[f64]) -> bool {
+        actual.len() == expected.len()
+            && actual
+                .iter()
+                .zip(expected)
+                .all(|(a, e)| (a - e).abs() < 1e-12)
+    }
+
+    fn batches() -> [DataBlob<f64>; 3] {
+        [
+            points(&[0.0, 0.0, 10.0, 10.0, 0.0, 0.0]),
+            points(&[1.0, 0.0, 11.0, 10.0]),
+            points(&[0.0, 1.0, 10.0, 11.0, 99.0, 99.0])
+                .with_mask(ValidityMask::from_bools([
+                    true, true, true, true, true, false,
+                ]))
+                .unwrap(),
+        ]
+    }
+
+    fn run(pipe: &mut StreamingKMeansPipe, events: Vec<Timed<DataBlob<f64>>>) -> Vec<Clusters> {
+        pipe.pipe(Rc::new(MockSource::new().items(events))).unwrap();
+        block_on(Pin::from(pipe.stream()).collect())
+    }
+
+    #[test]
+    fn test_streaming_kmeans() {
+        let event = |timestamp, blob| Timed::Event(Event::new(timestamp, blob));
+        let [first, second, third] = batches();
+        let mut pipe = StreamingKMeansPipe::new(WindowKind::Tumbling { size: 10 }, 2).unwrap();
+        let clusters = run(
+            &mut pipe,
+            vec![
+                event(1, first.clone()),
+                event(2, second),
+                Timed::Watermark(10),
+                event(12, third),
+            ],
+        );
+        assert_eq!(clusters.len(), 2, "Last window not flushed");
+        assert_eq!((clusters[0].start, clusters[0].firing), (0, Firing::OnTime));
+        let expected = [1.0 / 3.0, 0.0, 10.5, 10.0];
+        assert!(close(&get(&clusters[0], "centroids"), &expected));
+        assert_eq!(get(&clusters[0], "weights"), vec![3.0, 2.0]);
+        assert_eq!(get(&clusters[0], "inertia"), vec![2.0]);
+        // every window starts over, seeding from its own points
+        assert_eq!(clusters[1].start, 10);
+        assert_eq!(get(&clusters[1], "centroids"), vec![10.0, 11.0, 0.0, 1.0]);
+        assert_eq!(get(&clusters[1], "weights"), vec![1.0, 1.0]);
+        let meta = MetaData {
+            unitary_dimensions: vec![3],
+            ..Default::default()
+        };
+        let other = DataBlob::new(vec![1.0, 2.0, 3.0], meta);
+        let mut pipe = StreamingKMeansPipe::new(WindowKind::Tumbling { size: 10 }, 3)
+            .unwrap()
+            .with_decay(0.5)
+            .unwrap();
+        let clusters = run(
+            &mut pipe,
+            vec![
+                event(1, first.clone()),
+                Timed::Watermark(10),
+                event(12, first),
+                event(13, other),
+            ],
+        );
+        // the first blob holds two distinct points for three clusters
+        assert_eq!(get(&clusters[0], "weights"), vec![2.0, 1.0]);
+        assert_eq!(clusters.len(), 1);
+        assert_eq!(
+            pipe.error(),
+            Some("Blob points do not match the centroid dimensions")
+        );
+        assert!(StreamingKMeansPipe::new(WindowKind::Tumbling { size: 10 }, 0).is_err());
+    }
+
+    #[test]
+    fn test_streaming_kmeans_sessions() {
+        let event = |timestamp, blob| Timed::Event(Event::new(timestamp, blob));
+        let [first, second, third] = batches();
+        let mut pipe = StreamingKMeansPipe::new(WindowKind::Session { gap: 5 }, 2).unwrap();
+        let clusters = run(
+            &mut pipe,
+            vec![event(0, first), event(3, second), event(20, third)],
+        );
+        let windows: Vec<(i64, i64)> = clusters.iter().map(|c| (c.start, c.end)).collect();
+        assert_eq!(windows, vec![(0, 8), (20, 25)], "Sessions not merged");
+        // merging the centroids of the second blob moves them as its points would
+        let expected = [1.0 / 3.0, 0.0, 10.5, 10.0];
+        assert!(close(&get(&clusters[0], "centroids"), &expected));
+        assert_eq!(get(&clusters[0], "weights"), vec![3.0, 2.0]);
+        assert_eq!(get(&clusters[0], "inertia"), vec![2.0]);
+    }
+}
@@ -0,0 +1,301 @@
+//! regression
+//!
+//! Fitting linear models incrementally over streams of samples
+//!
+//! a sample pairs the features of an observation with its target, the model predicts the
+//! target as the dot product of the features with its coefficients plus an intercept.
+//! Errors are measured before the model learns from each sample, so they estimate how the
+//! model does on data it has not seen
+
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Fitting
+/// How the coefficients are updated with every sample
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fitting {
+    /// recursive least squares, weighing older samples down by a forgetting factor in
+    /// `(0, 1]` (1 weighs every sample the same)
+    RecursiveLeastSquares { forgetting: f64 },
+    /// stochastic gradient descent on the squared error with a learning rate
+    GradientDescent { learning_rate: f64 },
+}
+
+/// RegressionFit
+/// The state of a model after a number of samples
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegressionFit {
+    /// the weight of every feature
+    pub coefficients: Vec<f64>,
+    /// the constant term, 0 when fitted without one
+    pub intercept: f64,
+    /// number of samples learned from
+    pub samples: u64,
+    /// mean squared error of the predictions since the last fit emitted
+    pub mse: f64,
+    /// mean absolute error of the predictions since the last fit emitted
+    pub mae: f64,
+}
+
+/// the incremental state of a model, the intercept last among the weights
+struct Model {
+    fitting: Fitting,
+    intercept: bool,
+    weights: Vec<f64>,
+    /// the inverse correlation matrix of recursive least squares, row after row
+    covariance: Vec<f64>,
+    samples: u64,
+    squared: f64,
+    absolute: f64,
+    pending: u64,
+}
+
+impl Model {
+    fn new(features: usize, fitting: Fitting, intercept: bool) -> Self {
+        let size = features + intercept as usize;
+        let mut covariance = Vec::new();
+        if let Fitting::RecursiveLeastSquares { .. } = fitting {
+            // a large initial covariance lets the first samples set the weights
+            covariance = vec![0.0; size * size];
+            for i in 0..size {
+                covariance[i * size + i] = 1e6;
+            }
+        }
+        Self {
+            fitting,
+            intercept,
+            weights: vec![0.0; size],
+            covariance,
+            samples: 0,
+            squared: 0.0,
+            absolute: 0.0,
+            pending: 0,
+        }
+    }
+    fn learn(&mut self, features: &[f64], target: f64) -> Result<(), &'static str> {
+        let size = self.weights.len();
+        if features.len() + self.intercept as usize != size {
+            return Err("Sample does not have the expected number of features");
+        }
+        let x: Vec<f64> = match self.intercept {
+            true => features.iter().copied().chain([1.0]).collect(),
+            false => features.to_vec(),
+        };
+        let prediction: f64 = x.iter().zip(&self.weights).map(|(x, w)| x * w).sum();
+        let error = target - prediction;
+        match self.fitting {
+            Fitting::RecursiveLeastSquares { forgetting } => {
+                let p = &mut self.covariance;
+                let px: Vec<f64> = (0..size)
+                    .map(|i| (0..size).map(|j| p[i * size + j] * x[j]).sum())
+                    .collect();
+                let denominator = forgetting + x.iter().zip(&px).map(|(x, p)| x * p).sum::<f64>();
+                let gain: Vec<f64> = px.iter().map(|p| p / denominator).collect();
+                for (weight, gain) in self.weights.iter_mut().zip(&gain) {
+                    *weight += gain * error;
+                }
+                // the covariance is symmetric, so xᵀP is the transpose of Px
+                for i in 0..size {
+                    for j in 0..size {
+                        p[i * size + j] = (p[i * size + j] - gain[i] * px[j]) / forgetting;
+                    }
+                }
+            }
+            Fitting::GradientDescent { learning_rate } => {
+                for (weight, x) in self.weights.iter_mut().zip(&x) {
+                    *weight += learning_rate * error * x;
+                }
+            }
+        }
+        self.samples += 1;
+        self.pending += 1;
+        self.squared += error * error;
+        self.absolute += error.abs();
+        Ok(())
+    }
+    /// the fit so far, starting a new period of errors
+    fn fit(&mut self) -> RegressionFit {
+        let features = self.weights.len() - self.intercept as usize;
+        let pending = self.pending.max(1) as f64;
+        let fit = RegressionFit {
+            coefficients: self.weights[..features].to_vec(),
+            intercept: match self.intercept {
+                true => self.weights[features],
+                false => 0.0,
+            },
+            samples: self.samples,
+            mse: self.squared / pending,
+            mae: self.absolute / pending,
+        };
+        (self.squared, self.absolute, self.pending) = (0.0, 0.0, 0);
+        fit
+    }
+}
+
+/// OnlineRegressionPipe
+/// A pipe fitting a linear model to the `(features, target)` samples of its input,
+/// emitting the fit every number of samples and once more when the input ends
+///
+/// every stream starts over from an untrained model, it ends at the first sample without
+/// the expected number of features
+pub struct OnlineRegressionPipe {
+    features: usize,
+    fitting: Fitting,
+    intercept: bool,
+    every: u64,
+    input: Option<Rc<dyn Source<(Vec<f64>, f64)>>>,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl OnlineRegressionPipe {
+    /// constructor for samples of a number of features, emitting every 100 samples
+    pub fn new(features: usize, fitting: Fitting) -> Result<Self, &'static str> {
+        match fitting {
+            Fitting::RecursiveLeastSquares { forgetting }
+                if !(forgetting > 0.0 && forgetting <= 1.0) =>
+            {
+                return Err("Forgetting factor must lie in (0, 1]")
+            }
+            Fitting::GradientDescent { learning_rate }
+                if learning_rate.is_nan() || learning_rate <= 0.0 =>
+            {
+                return Err("Learning rate must be strictly positive")
+            }
+            _ => (),
+        }
+        Ok(Self {
+            features,
+            fitting,
+            intercept: true,
+            every: 100,
+            input: None,
+            error: Rc::new(Cell::new(None)),
+        })
+    }
+    /// constructor fitting by recursive least squares without forgetting
+    pub fn least_squares(features: usize) -> Self {
+        Self::new(features, Fitting::RecursiveLeastSquares { forgetting: 1.0 }).unwrap()
+    }
+    /// set whether the model has a constant term (true by default)
+    pub fn with_intercept(mut self, intercept: bool) -> Self {
+        self.intercept = intercept;
+        self
+    }
+    /// set the number of samples between fits, at least 1
+    pub fn with_every(mut self, every: u64) -> Self {
+        self.every = every.max(1);
+        self
+    }
+    /// the failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl Source<RegressionFit> for OnlineRegressionPipe {
+    fn stream(&self) -> Box<dyn Stream<Item = RegressionFit>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let error = self.error.clone();
+        error.set(None);
+        let every = self.every;
+        let model = Model::new(self.features, self.fitting, self.intercept);
+        let fits = input
+            .map(Some)
+            .chain(stream::iter([None]))
+            .scan(model, move |model, sample| {
+                let fit = match sample {
+                    // a last fit covers the samples since the previous one
+                    None if model.pending > 0 => Some(Some(model.fit())),
+                    None => None,
+                    Some((features, target)) => match model.learn(&features, target) {
+                        Ok(()) if model.pending == every => Some(Some(model.fit())),
+                        Ok(()) => Some(None),
+                        Err(failure) => {
+                            error.set(Some(failure));
+                            None
+                        }
+                    },
+                };
+                futures::future::ready(fit)
+            });
+        Box::new(fits.filter_map(futures::future::ready))
+    }
+}
+
+impl Pipe<(Vec<f64>, f64), RegressionFit> for OnlineRegressionPipe {
+    input_connection!((Vec<f64>, f64));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn samples(len: usize) -> Vec<(Vec<f64>, f64)> {
+        (0..len)
+            .map(|i| {
+                let (a, b) = ((i % 7) as f64, ((i * 3) % 11) as f64 - 5.0);
+                (vec![a, b], 2.0 * a - 0.5 * b + 3.0)
+            })
+            .collect()
+    }
+
+    fn run(pipe: &OnlineRegressionPipe) -> Vec<RegressionFit> {
+        block_on(Pin::from(pipe.stream()).collect())
+    }
+
+    #[test]
+    fn test_least_squares() {
+        let mut pipe = OnlineRegressionPipe::least_squares(2).with_every(40);
+        pipe.pipe(Rc::new(MockSource::new().items(samples(100))))
+            .unwrap();
+        let fits = run(&pipe);
+        let counts: Vec<u64> = fits.iter().map(|fit| fit.samples).collect();
+        assert_eq!(counts, vec![40, 80, 100]);
+        let last = &fits[2];
+        assert!((last.coefficients[0] - 2.0).abs() < 1e-6);
+        assert!((last.coefficients[1] + 0.5).abs() < 1e-6);
+        assert!((last.intercept - 3.0).abs() < 1e-4);
+        assert!(last.mse < 1e-8 && fits[0].mse > last.mse);
+        let mut broken = samples(3);
+        broken[1].0.pop();
+        pipe.pipe(Rc::new(MockSource::new().items(broken))).unwrap();
+        assert!(run(&pipe).is_empty());
+        assert_eq!(
+            pipe.error(),
+            Some("Sample does not have the expected number of features")
+        );
+    }
+
+    #[test]
+    fn test_gradient_descent() {
+        let fitting = Fitting::GradientDescent {
+            learning_rate: 0.01,
+        };
+        let mut pipe = OnlineRegressionPipe::new(2, fitting)
+            .unwrap()
+            .with_intercept(false)
+            .with_every(1000);
+        let data: Vec<(Vec<f64>, f64)> = samples(5000)
+            .into_iter()
+            .map(|(x, _)| (x.clone(), 1.5 * x[0] + 0.25 * x[1]))
+            .collect();
+        pipe.pipe(Rc::new(MockSource::new().items(data))).unwrap();
+        let fits = run(&pipe);
+        assert_eq!(fits.len(), 5);
+        assert_eq!(fits[4].intercept, 0.0);
+        assert!((fits[4].coefficients[0] - 1.5).abs() < 1e-3);
+        assert!((fits[4].coefficients[1] - 0.25).abs() < 1e-3);
+        assert!(fits[4].mae < fits[0].mae);
+        let diverging = Fitting::RecursiveLeastSquares { forgetting: 0.0 };
+        assert!(OnlineRegressionPipe::new(2, diverging).is_err());
+    }
+}
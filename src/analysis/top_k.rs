@@ -0,0 +1,374 @@
+//! top_k
+//!
+//! Finding the most frequent keys of a stream with the space-saving algorithm
+//!
+//! a summary of `capacity` counters holds every key more frequent than `1 / capacity` of the
+//! items seen, when a new key arrives with every counter taken it replaces the key of the
+//! smallest count and inherits that count as its error, so counts never fall below the true
+//! frequency and exceed it by at most their error
+
+use crate::event::{Event, Timed};
+use crate::window::{Accumulator, Firing, SideOutput, Trigger, WindowKind, Windowing};
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// HeavyHitter
+/// A key with its approximate count
+#[derive(Clone, Debug, PartialEq)]
+pub struct HeavyHitter<K> {
+    pub key: K,
+    /// the estimated count, never below the true count
+    pub count: u64,
+    /// the most the count may exceed the true count by
+    pub error: u64,
+}
+
+/// SpaceSaving
+/// Counters of the most frequent keys in bounded memory
+#[derive(Clone, Debug)]
+pub struct SpaceSaving<K> {
+    capacity: usize,
+    /// the count, error and order of arrival of every key held
+    counters: HashMap<K, (u64, u64, u64)>,
+    arrivals: u64,
+    total: u64,
+}
+
+impl<K: Hash + Eq + Clone> SpaceSaving<K> {
+    /// constructor of a summary of a number of counters
+    pub fn new(capacity: usize) -> Result<Self, &'static str> {
+        if capacity == 0 {
+            return Err("Number of counters must be strictly positive");
+        }
+        Ok(Self {
+            capacity,
+            counters: HashMap::new(),
+            arrivals: 0,
+            total: 0,
+        })
+    }
+    /// count a key once
+    pub fn insert(&mut self, key: K) {
+        self.total += 1;
+        if let Some(counter) = self.counters.get_mut(&key) {
+            counter.0 += 1;
+            return;
+        }
+        self.arrivals += 1;
+        if self.counters.len() < self.capacity {
+            self.counters.insert(key, (1, 0, self.arrivals));
+            return;
+        }
+        // the smallest count, the oldest key among equal counts
+        let smallest = self
+            .counters
+            .iter()
+            .min_by_key(|(_, (count, _, arrival))| (*count, *arrival))
+            .map(|(key, (count, _, _))| (key.clone(), *count));
+        if let Some((evicted, count)) = smallest {
+            self.counters.remove(&evicted);
+            self.counters.insert(key, (count + 1, count, self.arrivals));
+        }
+    }
+    /// the `k` keys of the largest counts, by decreasing count then order of arrival
+    pub fn top(&self, k: usize) -> Vec<HeavyHitter<K>> {
+        let mut counters: Vec<(&K, &(u64, u64, u64))> = self.counters.iter().collect();
+        counters.sort_by_key(|(_, (count, _, arrival))| (std::cmp::Reverse(*count), *arrival));
+        counters
+            .into_iter()
+            .take(k)
+            .map(|(key, (count, error, _))| HeavyHitter {
+                key: key.clone(),
+                count: *count,
+                error: *error,
+            })
+            .collect()
+    }
+    /// the number of items counted
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+    /// add the counts of a summary of the same capacity, a key missing from a full summary
+    /// counts as its smallest count with that much error, so counts never fall below the
+    /// true frequencies of the union
+    pub fn merge(&mut self, other: &SpaceSaving<K>) -> Result<(), &'static str> {
+        if self.capacity != other.capacity {
+            return Err("Cannot merge summaries of different capacities");
+        }
+        let floor = |summary: &SpaceSaving<K>| match summary.counters.len() < summary.capacity {
+            true => 0,
+            false => summary
+                .counters
+                .values()
+                .map(|counter| counter.0)
+                .min()
+                .unwrap_or(0),
+        };
+        let (ours, theirs) = (floor(self), floor(other));
+        let mut counters: Vec<(K, (u64, u64, u64))> = Vec::new();
+        for (key, (count, error, arrival)) in &self.counters {
+            let (other_count, other_error) = match other.counters.get(key) {
+                Some((count, error, _)) => (*count, *error),
+                None => (theirs, theirs),
+            };
+            counters.push((
+                key.clone(),
+                (count + other_count, error + other_error, *arrival),
+            ));
+        }
+        for (key, (count, error, arrival)) in &other.counters {
+            if !self.counters.contains_key(key) {
+                let arrival = self.arrivals + arrival;
+                counters.push((key.clone(), (count + ours, error + ours, arrival)));
+            }
+        }
+        counters.sort_by_key(|(_, (count, _, arrival))| (std::cmp::Reverse(*count), *arrival));
+        counters.truncate(self.capacity);
+        self.counters = counters.into_iter().collect();
+        self.arrivals += other.arrivals;
+        self.total += other.total;
+        Ok(())
+    }
+}
+
+/// TopK
+/// The most frequent keys within a window
+#[derive(Clone, Debug, PartialEq)]
+pub struct TopK<K> {
+    pub start: i64,
+    pub end: i64,
+    /// the number of events of the window
+    pub total: u64,
+    /// the heavy hitters by decreasing count
+    pub hitters: Vec<HeavyHitter<K>>,
+    pub firing: Firing,
+}
+
+type KeyFunction<T, K> = Rc<dyn Fn(&T) -> K>;
+
+/// the summary of the keys of one window
+struct Ranking<T, K> {
+    key: KeyFunction<T, K>,
+    k: usize,
+    summary: SpaceSaving<K>,
+}
+
+impl<T, K: Hash + Eq + Clone> Accumulator<T> for Ranking<T, K> {
+    type Output = (u64, Vec<HeavyHitter<K>>);
+    fn add(&mut self, event: &Event<T>) {
+        self.summary.insert((self.key)(&event.value));
+    }
+    fn merge(&mut self, other: Self) {
+        // the summaries of a pipe share their capacity
+        let _ = self.summary.merge(&other.summary);
+    }
+    fn emit(&mut self, _close: bool) -> Self::Output {
+        (self.summary.total(), self.summary.top(self.k))
+    }
+}
+
+/// TopKPipe
+/// A pipe finding the `k` most frequent keys of a timed stream within windows, with a
+/// space-saving summary per window
+///
+/// windows are assigned, fired and closed as by `WindowPipe`, with the same triggers,
+/// allowed lateness and late output, but only keep their summaries
+pub struct TopKPipe<T, K = T> {
+    windowing: Windowing<T, ()>,
+    key: KeyFunction<T, K>,
+    k: usize,
+    capacity: usize,
+    input: Option<Rc<dyn Source<Timed<T>>>>,
+}
+
+impl<T: Clone + 'static> TopKPipe<T> {
+    /// constructor ranking whole values, with 10 counters per key ranked
+    pub fn new(kind: WindowKind, k: usize) -> Result<Self, &'static str> {
+        Self::by_key(kind, k, T::clone)
+    }
+}
+
+impl<T, K> TopKPipe<T, K> {
+    /// constructor ranking the keys extracted from the values
+    pub fn by_key<F: Fn(&T) -> K + 'static>(
+        kind: WindowKind,
+        k: usize,
+        key: F,
+    ) -> Result<Self, &'static str> {
+        if k == 0 {
+            return Err("Number of keys ranked must be strictly positive");
+        }
+        Ok(Self {
+            windowing: Windowing::new(kind, |_: &T| ())?,
+            key: Rc::new(key),
+            k,
+            capacity: k.saturating_mul(10),
+            input: None,
+        })
+    }
+    /// set the number of counters of every window, at least `k`, more counters make
+    /// the counts more accurate
+    pub fn with_capacity(mut self, capacity: usize) -> Result<Self, &'static str> {
+        if capacity < self.k {
+            return Err("Number of counters must be at least the number of keys ranked");
+        }
+        self.capacity = capacity;
+        Ok(self)
+    }
+    /// set the trigger deciding when windows fire
+    pub fn set_trigger(&mut self, trigger: impl Trigger + 'static) {
+        self.windowing.set_trigger(trigger);
+    }
+    /// set how long (in ticks of event time) windows accept events after firing
+    pub fn set_allowed_lateness(&mut self, allowed_lateness: i64) -> Result<(), &'static str> {
+        self.windowing.set_allowed_lateness(allowed_lateness)
+    }
+    /// the source of the events arriving after every window covering them closed
+    pub fn late_output(&self) -> SideOutput<Event<T>> {
+        self.windowing.late_output()
+    }
+}
+
+impl<T, K> Source<TopK<K>> for TopKPipe<T, K>
+where
+    T: 'static,
+    K: Hash + Eq + Clone + 'static,
+{
+    fn stream(&self) -> Box<dyn Stream<Item = TopK<K>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let (key, k, capacity) = (self.key.clone(), self.k, self.capacity);
+        let ranking = move || Ranking {
+            key: key.clone(),
+            k,
+            summary: SpaceSaving::new(capacity).unwrap(),
+        };
+        let ranks = self.windowing.fire(input, ranking).map(|fired| TopK {
+            start: fired.start,
+            end: fired.end,
+            total: fired.output.0,
+            hitters: fired.output.1,
+            firing: fired.firing,
+        });
+        Box::new(ranks)
+    }
+}
+
+impl<T, K> Pipe<Timed<T>, TopK<K>> for TopKPipe<T, K>
+where
+    T: 'static,
+    K: Hash + Eq + Clone + 'static,
+{
+    input_connection!(Timed<T>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_space_saving() {
+        let mut summary = SpaceSaving::new(3).unwrap();
+        for key in "aabacadaeab".chars() {
+            summary.insert(key);
+        }
+        assert_eq!(summary.total(), 11);
+        let top = summary.top(2);
+        assert_eq!(
+            top[0],
+            HeavyHitter {
+                key: 'a',
+                count: 6,
+                error: 0
+            }
+        );
+        // b was evicted by d then came back in place of d, the oldest of the smallest counts
+        assert_eq!((top[1].key, top[1].count, top[1].error), ('b', 3, 2));
+        assert!(SpaceSaving::<char>::new(0).is_err());
+        let mut other = SpaceSaving::new(3).unwrap();
+        for key in "bbbbcc".chars() {
+            other.insert(key);
+        }
+        summary.merge(&other).unwrap();
+        assert_eq!(summary.total(), 17);
+        let top = summary.top(3);
+        let counts: Vec<(char, u64, u64)> = top.iter().map(|h| (h.key, h.count, h.error)).collect();
+        // c, missing from the full summary, counts as its smallest count with that error
+        assert_eq!(counts, vec![('b', 7, 2), ('a', 6, 0), ('c', 4, 2)]);
+        assert!(summary.merge(&SpaceSaving::new(2).unwrap()).is_err());
+    }
+
+    #[test]
+    fn test_top_k_pipe() {
+        let event = |timestamp, value| Timed::Event(Event::new(timestamp, value));
+        let mut pipe = TopKPipe::new(WindowKind::Tumbling { size: 10 }, 2).unwrap();
+        pipe.pipe(Rc::new(MockSource::new().items([
+            event(0, "get"),
+            event(1, "put"),
+            event(2, "get"),
+            event(3, "del"),
+            event(11, "put"),
+            Timed::Watermark(10),
+            event(4, "get"),
+            event(12, "put"),
+        ])))
+        .unwrap();
+        let late = pipe.late_output();
+        let (windows, late) = block_on(async {
+            futures::join!(
+                Pin::from(pipe.stream()).collect::<Vec<TopK<&str>>>(),
+                Pin::from(late.stream()).collect::<Vec<_>>()
+            )
+        });
+        assert_eq!(late, vec![Event::new(4, "get")], "Late event dropped");
+        let spans: Vec<(i64, u64)> = windows.iter().map(|w| (w.start, w.total)).collect();
+        assert_eq!(spans, vec![(0, 4), (10, 2)]);
+        let ranks = |window: &TopK<&'static str>| -> Vec<(&str, u64)> {
+            window.hitters.iter().map(|h| (h.key, h.count)).collect()
+        };
+        assert_eq!(ranks(&windows[0]), vec![("get", 2), ("put", 1)]);
+        assert_eq!(ranks(&windows[1]), vec![("put", 2)]);
+        assert!(TopKPipe::<u8>::new(WindowKind::Tumbling { size: 10 }, 2)
+            .unwrap()
+            .with_capacity(1)
+            .is_err());
+    }
+
+    #[test]
+    fn test_late_firings() {
+        let event = |timestamp, value| Timed::Event(Event::new(timestamp, value));
+        let mut pipe = TopKPipe::new(WindowKind::Tumbling { size: 10 }, 1).unwrap();
+        pipe.set_allowed_lateness(5).unwrap();
+        pipe.pipe(Rc::new(MockSource::new().items([
+            event(1, 'a'),
+            Timed::Watermark(10),
+            event(2, 'b'),
+            event(3, 'b'),
+            Timed::Watermark(20),
+        ])))
+        .unwrap();
+        let firings: Vec<(Firing, char, u64)> =
+            block_on(Pin::from(pipe.stream()).collect::<Vec<_>>())
+                .iter()
+                .map(|window| (window.firing, window.hitters[0].key, window.total))
+                .collect();
+        // every late event fires the window again, b ties with a then overtakes it
+        assert_eq!(
+            firings,
+            vec![
+                (Firing::OnTime, 'a', 1),
+                (Firing::Late, 'a', 2),
+                (Firing::Late, 'b', 3)
+            ]
+        );
+    }
+}
@@ -0,0 +1,211 @@
+//! xcorr
+//!
+//! Windowed cross-correlation between a stream and a reference stream
+//!
+//! the samples of both streams are paired by position, a window of paired samples is
+//! correlated at every lag `k` within the maximum, pairing `input[i]` with
+//! `reference[i - k]`, so a positive best lag means the input trails the reference
+
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// XCorr
+/// The cross-correlation of one window
+#[derive(Clone, Debug, PartialEq)]
+pub struct XCorr {
+    /// position of the first paired sample of the window in the streams
+    pub start: usize,
+    /// the Pearson coefficient of every lag from `-max_lag` to `max_lag`, over the samples
+    /// overlapping at that lag, NaN when either side is constant
+    pub coefficients: Vec<f64>,
+    /// the lag of the largest coefficient, the smallest in magnitude on ties
+    pub best_lag: i64,
+    /// the largest coefficient
+    pub best: f64,
+}
+
+/// the Pearson coefficient of two series of the same length
+fn pearson(x: &[f64], y: &[f64]) -> f64 {
+    let n = x.len() as f64;
+    let (mx, my) = (x.iter().sum::<f64>() / n, y.iter().sum::<f64>() / n);
+    let (mut xy, mut xx, mut yy) = (0.0, 0.0, 0.0);
+    for (a, b) in x.iter().zip(y) {
+        xy += (a - mx) * (b - my);
+        xx += (a - mx) * (a - mx);
+        yy += (b - my) * (b - my);
+    }
+    match xx > 0.0 && yy > 0.0 {
+        true => xy / (xx * yy).sqrt(),
+        false => f64::NAN,
+    }
+}
+
+/// cross-correlate a window of an input and a reference of the same length at every lag
+/// up to a maximum shorter than the window
+pub fn cross_correlate(input: &[f64], reference: &[f64], max_lag: usize, start: usize) -> XCorr {
+    let n = input.len().min(reference.len());
+    let max_lag = max_lag.min(n.saturating_sub(1)) as i64;
+    let coefficients: Vec<f64> = (-max_lag..=max_lag)
+        .map(|lag| {
+            let shift = lag.unsigned_abs() as usize;
+            match lag >= 0 {
+                true => pearson(&input[shift..n], &reference[..n - shift]),
+                false => pearson(&input[..n - shift], &reference[shift..n]),
+            }
+        })
+        .collect();
+    let (mut best_lag, mut best) = (0i64, f64::NAN);
+    for (lag, coefficient) in (-max_lag..=max_lag).zip(&coefficients) {
+        let closer = coefficient == &best && lag.abs() < best_lag.abs();
+        if coefficient > &best || best.is_nan() && !coefficient.is_nan() || closer {
+            (best_lag, best) = (lag, *coefficient);
+        }
+    }
+    XCorr {
+        start,
+        coefficients,
+        best_lag,
+        best,
+    }
+}
+
+/// XCorrPipe
+/// A pipe cross-correlating windows of its input with a reference stream, emitting one
+/// correlation per window
+///
+/// windows of `window` paired samples start every `hop` samples (the window size by
+/// default), trailing samples too few for a window are dropped and the output stops with
+/// the shortest of the two streams
+pub struct XCorrPipe {
+    reference: Rc<dyn Source<f64>>,
+    window: usize,
+    max_lag: usize,
+    hop: usize,
+    input: Option<Rc<dyn Source<f64>>>,
+}
+
+impl XCorrPipe {
+    /// constructor correlating against a reference at lags up to `max_lag` samples
+    pub fn new(
+        reference: Rc<dyn Source<f64>>,
+        window: usize,
+        max_lag: usize,
+    ) -> Result<Self, &'static str> {
+        if window == 0 {
+            return Err("Window must be strictly positive");
+        }
+        if max_lag >= window {
+            return Err("Lags must be shorter than the window");
+        }
+        Ok(Self {
+            reference,
+            window,
+            max_lag,
+            hop: window,
+            input: None,
+        })
+    }
+    /// set the number of samples between the starts of windows
+    pub fn with_hop(mut self, hop: usize) -> Result<Self, &'static str> {
+        if hop == 0 {
+            return Err("Hop must be strictly positive");
+        }
+        self.hop = hop;
+        Ok(self)
+    }
+}
+
+impl Source<XCorr> for XCorrPipe {
+    fn stream(&self) -> Box<dyn Stream<Item = XCorr>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let (window, max_lag, hop) = (self.window, self.max_lag, self.hop);
+        let mut pending: VecDeque<(f64, f64)> = VecDeque::with_capacity(window);
+        // samples to let go by before the next window when hops exceed windows
+        let (mut skip, mut start) = (0, 0);
+        let pairs = input.zip(Pin::from(self.reference.stream()));
+        Box::new(pairs.filter_map(move |pair| {
+            let mut correlation = None;
+            if skip > 0 {
+                skip -= 1;
+            } else {
+                pending.push_back(pair);
+            }
+            if pending.len() == window {
+                let (input, reference): (Vec<f64>, Vec<f64>) = pending.iter().copied().unzip();
+                correlation = Some(cross_correlate(&input, &reference, max_lag, start));
+                start += hop;
+                skip = hop.saturating_sub(window);
+                pending.drain(..hop.min(window));
+            }
+            futures::future::ready(correlation)
+        }))
+    }
+}
+
+impl Pipe<f64, XCorr> for XCorrPipe {
+    input_connection!(f64);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn signal(len: usize) -> Vec<f64> {
+        (0..len).map(|i| ((i * 7919) % 23) as f64).collect()
+    }
+
+    #[test]
+    fn test_cross_correlate() {
+        let reference = signal(40);
+        // the input trails the reference by 3 samples
+        let input: Vec<f64> = (0..40)
+            .map(|i| if i < 3 { 0.0 } else { reference[i - 3] })
+            .collect();
+        let correlation = cross_correlate(&input, &reference, 5, 0);
+        assert_eq!(correlation.coefficients.len(), 11);
+        assert_eq!(correlation.best_lag, 3);
+        assert!((correlation.best - 1.0).abs() < 1e-12);
+        let leading = cross_correlate(&reference, &input, 5, 0);
+        assert_eq!(leading.best_lag, -3);
+        let flat = cross_correlate(&[1.0; 4], &[1.0, 2.0, 3.0, 4.0], 1, 0);
+        assert!(flat.coefficients.iter().all(|c| c.is_nan()));
+        assert_eq!(flat.best_lag, 0);
+    }
+
+    #[test]
+    fn test_xcorr_pipe() {
+        let reference = signal(50);
+        let input: Vec<f64> = (0..50).map(|i| -reference[(i + 48) % 50]).collect();
+        let mut pipe = XCorrPipe::new(Rc::new(MockSource::new().items(reference)), 20, 4)
+            .unwrap()
+            .with_hop(25)
+            .unwrap();
+        pipe.pipe(Rc::new(MockSource::new().items(input))).unwrap();
+        let correlations: Vec<XCorr> = block_on(Pin::from(pipe.stream()).collect());
+        let starts: Vec<usize> = correlations.iter().map(|c| c.start).collect();
+        assert_eq!(starts, vec![0, 25]);
+        // an inverted signal correlates negatively everywhere it aligns
+        assert!(correlations[1].coefficients[4 + 2] < -0.99);
+        assert!(XCorrPipe::new(Rc::new(MockSource::new().items(Vec::new())), 4, 4).is_err());
+        let squares: Vec<f64> = signal(10).iter().map(|v| v * v).collect();
+        let mut sliding = XCorrPipe::new(Rc::new(MockSource::new().items(squares.clone())), 4, 1)
+            .unwrap()
+            .with_hop(2)
+            .unwrap();
+        sliding
+            .pipe(Rc::new(MockSource::new().items(squares)))
+            .unwrap();
+        let correlations: Vec<XCorr> = block_on(Pin::from(sliding.stream()).collect());
+        assert_eq!(correlations.len(), 4);
+        assert!(correlations.iter().all(|c| c.best_lag == 0));
+    }
+}
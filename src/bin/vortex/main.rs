@@ -0,0 +1,194 @@
+//! vortex
+//!
+//! Running pipelines described in YAML or TOML files, without writing Rust
+//!
+//! ```text
+//! vortex [--quiet] [--dot] [--plugins <path>]... <description.yaml|description.toml>
+//! vortex [--plugins <path>]... --list
+//! ```
+//!
+//! plugins are the libraries given with `--plugins`, either directly or as the directories
+//! holding them, and those found in the directories of `VORTEX_PLUGIN_PATH`
+//!
+//! the exit status is 0 when every sink drained, 1 when the pipeline failed while running
+//! and 2 when the arguments or the description are invalid
+
+/// nodes
+/// Sub module for the kinds added by the command line
+mod nodes;
+
+use bitvortex::config::{NodeRegistry, PipelineConfig, Role};
+use bitvortex::plugin::{self, Plugin};
+use futures::executor::block_on;
+use nodes::{Checks, Progress};
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+const USAGE: &str = "usage: vortex [--quiet] [--dot] [--plugins <path>]... \
+                     <description.yaml|description.toml>\n       \
+                     vortex [--plugins <path>]... --list";
+
+/// the action of an invocation
+enum Command {
+    Run {
+        path: PathBuf,
+        quiet: bool,
+        dot: bool,
+        plugins: Vec<PathBuf>,
+    },
+    List {
+        plugins: Vec<PathBuf>,
+    },
+    Help,
+}
+
+fn parse_args<I: Iterator<Item = String>>(mut args: I) -> Result<Command, String> {
+    let (mut path, mut quiet, mut dot, mut list) = (None, false, false, false);
+    let mut plugins = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-q" | "--quiet" => quiet = true,
+            "--dot" => dot = true,
+            "-p" | "--plugins" => {
+                plugins.push(PathBuf::from(args.next().ok_or("No plugin path given")?));
+            }
+            "-l" | "--list" => list = true,
+            "-h" | "--help" => return Ok(Command::Help),
+            flag if flag.starts_with('-') => return Err(format!("Unknown option {}", flag)),
+            _ if path.is_some() => return Err("Only one description can be run".to_string()),
+            _ => path = Some(PathBuf::from(arg)),
+        }
+    }
+    if list {
+        return Ok(Command::List { plugins });
+    }
+    let path = path.ok_or("No description given")?;
+    Ok(Command::Run {
+        path,
+        quiet,
+        dot,
+        plugins,
+    })
+}
+
+/// the plugins of the given paths then of the `VORTEX_PLUGIN_PATH` directories
+fn plugins(paths: &[PathBuf]) -> Result<Vec<Plugin>, String> {
+    let mut paths = paths.to_vec();
+    if let Some(directories) = std::env::var_os("VORTEX_PLUGIN_PATH") {
+        paths.extend(std::env::split_paths(&directories).filter(|path| path.is_dir()));
+    }
+    let mut plugins = Vec::new();
+    for path in paths {
+        let loaded = match path.is_dir() {
+            true => plugin::discover(&path),
+            false => {
+                let plugin = Plugin::load(&path);
+                vec![(path, plugin)]
+            }
+        };
+        for (path, plugin) in loaded {
+            plugins.push(plugin.map_err(|error| format!("{}: {}", path.display(), error))?);
+        }
+    }
+    Ok(plugins)
+}
+
+fn registry(progress: &Progress, checks: &Checks, plugins: &[Plugin]) -> NodeRegistry {
+    let mut registry = NodeRegistry::with_builtins();
+    nodes::register(&mut registry, progress, checks);
+    for plugin in plugins {
+        registry.register_plugin(plugin);
+    }
+    registry
+}
+
+fn list(plugins: &[Plugin]) {
+    let registry = registry(&Progress::new(true), &Checks::default(), plugins);
+    for role in [Role::Source, Role::Pipe, Role::Sink] {
+        println!("{:?}s:", role);
+        for kind in registry.kinds().filter(|kind| kind.role == role) {
+            let items = format!(
+                "{} -> {}",
+                kind.input.unwrap_or(""),
+                kind.output.unwrap_or("")
+            );
+            println!("  {:<10}{:<18}{}", kind.name, items.trim(), kind.help);
+        }
+    }
+}
+
+fn run(path: PathBuf, quiet: bool, dot: bool, plugins: &[Plugin]) -> ExitCode {
+    let (progress, checks) = (Progress::new(quiet), Checks::default());
+    let registry = registry(&progress, &checks, plugins);
+    let built = PipelineConfig::load(&path)
+        .and_then(|config| config.graph(&registry))
+        .and_then(|graph| Ok(graph.build()?));
+    let run = match built {
+        Ok(run) => run,
+        Err(error) => {
+            eprintln!("vortex: {}: {}", path.display(), error);
+            return ExitCode::from(2);
+        }
+    };
+    if dot {
+        print!("{}", run.to_dot());
+        return ExitCode::SUCCESS;
+    }
+    let result = block_on(run.drain()).map_err(|error| format!("Pipeline failed: {}", error));
+    let result = result.and_then(|_| match checks.failure() {
+        Some((node, error)) => Err(format!("Node {} failed: {}", node, error)),
+        None => Ok(()),
+    });
+    let elapsed = progress.elapsed().as_secs_f64();
+    match result {
+        Ok(()) => {
+            if !quiet {
+                eprintln!(
+                    "vortex: done, {} items in {:.2}s",
+                    progress.items(),
+                    elapsed
+                );
+            }
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("vortex: {} after {} items", error, progress.items());
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match parse_args(std::env::args().skip(1)) {
+        Ok(Command::Run {
+            path,
+            quiet,
+            dot,
+            plugins: paths,
+        }) => match plugins(&paths) {
+            Ok(plugins) => run(path, quiet, dot, &plugins),
+            Err(error) => {
+                eprintln!("vortex: {}", error);
+                ExitCode::from(2)
+            }
+        },
+        Ok(Command::List { plugins: paths }) => match plugins(&paths) {
+            Ok(plugins) => {
+                list(&plugins);
+                ExitCode::SUCCESS
+            }
+            Err(error) => {
+                eprintln!("vortex: {}", error);
+                ExitCode::from(2)
+            }
+        },
+        Ok(Command::Help) => {
+            println!("{}", USAGE);
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("vortex: {}\n{}", error, USAGE);
+            ExitCode::from(2)
+        }
+    }
+}
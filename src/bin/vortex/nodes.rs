@@ -0,0 +1,215 @@
+//! nodes
+//!
+//! The kinds the command line adds to the built-in ones: sinks writing lines while counting
+//! progress, and a `lines` source whose read failures fail the run
+
+use bitvortex::config::NodeRegistry;
+use bitvortex::sources::TextSource;
+use bitvortex::{Sink, Source};
+use futures::future::LocalBoxFuture;
+use futures::StreamExt;
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// Progress
+/// Counts of the items reaching sinks, reported on the standard error at most every second
+#[derive(Clone)]
+pub struct Progress {
+    items: Rc<Cell<u64>>,
+    start: Instant,
+    reported: Rc<Cell<Instant>>,
+    quiet: bool,
+}
+
+impl Progress {
+    /// constructor starting the clock
+    pub fn new(quiet: bool) -> Self {
+        let start = Instant::now();
+        Self {
+            items: Rc::new(Cell::new(0)),
+            start,
+            reported: Rc::new(Cell::new(start)),
+            quiet,
+        }
+    }
+    /// the items counted so far
+    pub fn items(&self) -> u64 {
+        self.items.get()
+    }
+    /// the time since the start
+    pub fn elapsed(&self) -> Duration {
+        self.start.elapsed()
+    }
+    fn add(&self) {
+        self.items.set(self.items.get() + 1);
+        if !self.quiet && self.reported.get().elapsed() >= Duration::from_secs(1) {
+            self.reported.set(Instant::now());
+            let rate = self.items() as f64 / self.elapsed().as_secs_f64();
+            eprintln!("vortex: {} items ({:.0}/s)", self.items(), rate);
+        }
+    }
+}
+
+/// where a line sink writes
+enum Output {
+    Stdout,
+    File(PathBuf),
+    Discard,
+}
+
+/// LineSink
+/// A sink writing lines and counting them
+struct LineSink {
+    output: Rc<Output>,
+    progress: Progress,
+    input: Option<Rc<dyn Source<String>>>,
+}
+
+impl Sink<String> for LineSink {
+    fn pipe(&mut self, input: Rc<dyn Source<String>>) -> Result<(), &'static str> {
+        self.input = Some(input);
+        Ok(())
+    }
+    fn unpipe(&mut self) {
+        self.input = None;
+    }
+    fn get_input(&self) -> Option<Rc<dyn Source<String>>> {
+        self.input.clone()
+    }
+    fn drain(&self) -> LocalBoxFuture<'static, Result<(), &'static str>> {
+        let input = self.input.clone();
+        let (output, progress) = (self.output.clone(), self.progress.clone());
+        Box::pin(async move {
+            let mut stream = Pin::from(input.ok_or("Sink has no input")?.stream());
+            let mut writer: Box<dyn Write> = match &*output {
+                Output::Stdout => Box::new(BufWriter::new(std::io::stdout().lock())),
+                Output::File(path) => Box::new(BufWriter::new(
+                    File::create(path).map_err(|_| "Failure to create output file")?,
+                )),
+                Output::Discard => Box::new(std::io::sink()),
+            };
+            while let Some(line) = stream.next().await {
+                writeln!(writer, "{}", line).map_err(|_| "Failure to write line")?;
+                progress.add();
+            }
+            writer.flush().map_err(|_| "Failure to write line")
+        })
+    }
+}
+
+type Check = Box<dyn Fn() -> Option<&'static str>>;
+
+/// Checks
+/// The errors sources keep to themselves, looked at once the pipeline drained
+#[derive(Clone, Default)]
+pub struct Checks(Rc<RefCell<Vec<(String, Check)>>>);
+
+impl Checks {
+    /// the first error kept by a node, with the name of the node
+    pub fn failure(&self) -> Option<(String, &'static str)> {
+        self.0
+            .borrow()
+            .iter()
+            .find_map(|(node, check)| Some((node.clone(), check()?)))
+    }
+}
+
+/// register the kinds of the command line
+pub fn register(registry: &mut NodeRegistry, progress: &Progress, checks: &Checks) {
+    let checks = checks.clone();
+    registry.register_source(
+        "lines",
+        "the lines of the text file at path",
+        move |params| {
+            let path = params.required("path", params.text("path")?)?;
+            let source = Rc::new(TextSource::open(path));
+            let errors = source.clone();
+            let check: Check = Box::new(move || errors.error());
+            checks
+                .0
+                .borrow_mut()
+                .push((params.node().to_string(), check));
+            Ok(source as Rc<dyn Source<String>>)
+        },
+    );
+    let sinks = [
+        ("stdout", "writes lines to the standard output"),
+        ("file", "writes lines to the file at path, replacing it"),
+        ("discard", "counts lines without writing them"),
+    ];
+    for (kind, help) in sinks {
+        let progress = progress.clone();
+        registry.register_sink(kind, help, move |params| {
+            let output = match kind {
+                "stdout" => Output::Stdout,
+                "file" => Output::File(params.required("path", params.text("path")?)?.into()),
+                _ => Output::Discard,
+            };
+            Ok(LineSink {
+                output: Rc::new(output),
+                progress: progress.clone(),
+                input: None,
+            })
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitvortex::config::PipelineConfig;
+    use futures::executor::block_on;
+
+    fn run(description: &str) -> Result<u64, String> {
+        let (progress, checks) = (Progress::new(true), Checks::default());
+        let mut registry = NodeRegistry::with_builtins();
+        register(&mut registry, &progress, &checks);
+        let graph = PipelineConfig::from_toml(description)
+            .and_then(|config| config.graph(&registry))
+            .map_err(|error| error.to_string())?;
+        let run = graph.build().map_err(|error| error.to_string())?;
+        block_on(run.drain())?;
+        match checks.failure() {
+            Some((node, error)) => Err(format!("{}: {}", node, error)),
+            None => Ok(progress.items()),
+        }
+    }
+
+    #[test]
+    fn test_run() {
+        let dir = std::env::temp_dir().join(format!("vortex-nodes-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let output = dir.join("out.txt");
+        let description = format!(
+            "[nodes.ramp]\nkind = \"ramp\"\nstart = 1\nlen = 6\n\
+             [nodes.half]\nkind = \"scale\"\nfactor = 0.5\ninput = \"ramp\"\n\
+             [nodes.text]\nkind = \"format\"\nprecision = 1\ninput = \"half\"\n\
+             [nodes.odd]\nkind = \"grep\"\npattern = \"5$\"\ninput = \"text\"\n\
+             [nodes.out]\nkind = \"file\"\npath = {:?}\ninput = \"odd\"\n",
+            output.display().to_string()
+        );
+        assert_eq!(run(&description), Ok(3));
+        assert_eq!(std::fs::read_to_string(&output).unwrap(), "0.5\n1.5\n2.5\n");
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_failures() {
+        assert_eq!(
+            run("[nodes.a]\nkind = \"ramp\"\n[nodes.b]\nkind = \"stdout\"\ninput = \"a\"\n"),
+            Err("Node a produces items node b does not accept".to_string())
+        );
+        assert_eq!(
+            run(
+                "[nodes.a]\nkind = \"lines\"\npath = \"/nonexistent/vortex\"\n\
+                 [nodes.b]\nkind = \"discard\"\ninput = \"a\"\n"
+            ),
+            Err("a: Failure to open text file".to_string())
+        );
+    }
+}
@@ -0,0 +1,258 @@
+//! checkpoint
+//!
+//! Checkpoint barriers and their coordination across the sinks of a pipeline
+//!
+//! a checkpoint is requested from a `CheckpointCoordinator`, `BarrierPipe`s insert its
+//! barrier in band into their stream (like watermarks), every sink registered with the
+//! coordinator acknowledges the barrier once everything before it is durably staged and the
+//! checkpoint completes when all of them did, the coordinator is thread safe so it can be
+//! shared by the stages of a pipeline
+
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::collections::{BTreeMap, BTreeSet};
+use std::future::Future;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+
+/// the checkpoint acknowledged by sinks when their input ends
+pub const FINAL_CHECKPOINT: u64 = u64::MAX;
+
+/// Checkpointed
+/// The items of a stream carrying checkpoint barriers
+#[derive(Clone, Debug, PartialEq)]
+pub enum Checkpointed<T> {
+    Item(T),
+    Barrier(u64),
+}
+
+/// CheckpointStatus
+/// The progress of a checkpoint
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckpointStatus {
+    /// some sinks did not acknowledge the checkpoint yet
+    Pending,
+    /// every sink acknowledged the checkpoint
+    Completed,
+    /// a sink failed before acknowledging the checkpoint
+    Failed,
+}
+
+#[derive(Default)]
+struct CoordinatorState {
+    participants: usize,
+    next: u64,
+    requested: Option<u64>,
+    acks: BTreeMap<u64, usize>,
+    completed: BTreeSet<u64>,
+    failed: BTreeSet<u64>,
+    wakers: Vec<Waker>,
+}
+
+impl CoordinatorState {
+    fn wake(&mut self) {
+        self.wakers.drain(..).for_each(Waker::wake);
+    }
+}
+
+/// CheckpointCoordinator
+/// Hands out checkpoint ids and tracks their acknowledgement by the registered sinks,
+/// clones share the same coordination
+#[derive(Clone, Default)]
+pub struct CheckpointCoordinator {
+    state: Arc<Mutex<CoordinatorState>>,
+}
+
+impl CheckpointCoordinator {
+    /// constructor for a coordinator without participants
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// register a sink taking part in checkpoints
+    pub fn register(&self) {
+        self.state.lock().unwrap().participants += 1;
+    }
+    /// the number of registered sinks
+    pub fn participants(&self) -> usize {
+        self.state.lock().unwrap().participants
+    }
+    /// request a new checkpoint, returning its id
+    pub fn trigger(&self) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.next += 1;
+        state.requested = Some(state.next);
+        state.next
+    }
+    /// the latest requested checkpoint
+    pub fn requested(&self) -> Option<u64> {
+        self.state.lock().unwrap().requested
+    }
+    /// acknowledge a checkpoint on behalf of one sink
+    pub fn acknowledge(&self, checkpoint: u64) {
+        let mut state = self.state.lock().unwrap();
+        let participants = state.participants;
+        let acks = state.acks.entry(checkpoint).or_default();
+        *acks += 1;
+        if *acks >= participants {
+            state.acks.remove(&checkpoint);
+            if !state.failed.contains(&checkpoint) {
+                state.completed.insert(checkpoint);
+            }
+            state.wake();
+        }
+    }
+    /// mark a checkpoint as failed, every sink then aborts its transaction
+    pub fn fail(&self, checkpoint: u64) {
+        let mut state = self.state.lock().unwrap();
+        state.failed.insert(checkpoint);
+        state.wake();
+    }
+    /// the progress of a checkpoint
+    pub fn status(&self, checkpoint: u64) -> CheckpointStatus {
+        let state = self.state.lock().unwrap();
+        if state.failed.contains(&checkpoint) {
+            CheckpointStatus::Failed
+        } else if state.completed.contains(&checkpoint) {
+            CheckpointStatus::Completed
+        } else {
+            CheckpointStatus::Pending
+        }
+    }
+    /// the latest completed checkpoint
+    pub fn last_completed(&self) -> Option<u64> {
+        self.state.lock().unwrap().completed.last().copied()
+    }
+    /// wait until a checkpoint completes or fails
+    pub fn settled(&self, checkpoint: u64) -> impl Future<Output = CheckpointStatus> {
+        let coordinator = self.clone();
+        futures::future::poll_fn(move |cx| {
+            let status = coordinator.status(checkpoint);
+            if status != CheckpointStatus::Pending {
+                return Poll::Ready(status);
+            }
+            let mut state = coordinator.state.lock().unwrap();
+            state.wakers.push(cx.waker().clone());
+            drop(state);
+            match coordinator.status(checkpoint) {
+                CheckpointStatus::Pending => Poll::Pending,
+                status => Poll::Ready(status),
+            }
+        })
+    }
+}
+
+/// BarrierPipe
+/// A pipe wrapping its items into a checkpointed stream, inserting the barrier of every
+/// checkpoint requested from its coordinator before the next item (or right after the
+/// item completing an interval)
+pub struct BarrierPipe<T> {
+    coordinator: CheckpointCoordinator,
+    interval: Option<usize>,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T> BarrierPipe<T> {
+    /// constructor inserting the barriers requested from the coordinator
+    pub fn new(coordinator: CheckpointCoordinator) -> Self {
+        Self {
+            coordinator,
+            interval: None,
+            input: None,
+        }
+    }
+    /// also request a checkpoint every `interval` items
+    pub fn with_interval(mut self, interval: usize) -> Result<Self, &'static str> {
+        if interval == 0 {
+            return Err("Checkpoint interval must be strictly positive");
+        }
+        self.interval = Some(interval);
+        Ok(self)
+    }
+}
+
+impl<T: 'static> Source<Checkpointed<T>> for BarrierPipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = Checkpointed<T>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let coordinator = self.coordinator.clone();
+        let interval = self.interval;
+        let mut inserted = coordinator.requested();
+        let mut count = 0;
+        Box::new(input.flat_map(move |item| {
+            let mut items = Vec::with_capacity(2);
+            let requested = coordinator.requested();
+            if requested > inserted {
+                inserted = requested;
+                items.push(Checkpointed::Barrier(requested.unwrap()));
+            }
+            items.push(Checkpointed::Item(item));
+            count += 1;
+            if interval.is_some_and(|interval| count % interval == 0) {
+                let checkpoint = coordinator.trigger();
+                inserted = Some(checkpoint);
+                items.push(Checkpointed::Barrier(checkpoint));
+            }
+            stream::iter(items)
+        }))
+    }
+}
+
+impl<T: 'static> Pipe<T, Checkpointed<T>> for BarrierPipe<T> {
+    input_connection!(T);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_coordination() {
+        let coordinator = CheckpointCoordinator::new();
+        coordinator.register();
+        coordinator.register();
+        let first = coordinator.trigger();
+        let second = coordinator.trigger();
+        coordinator.acknowledge(first);
+        assert_eq!(coordinator.status(first), CheckpointStatus::Pending);
+        coordinator.acknowledge(first);
+        assert_eq!(coordinator.status(first), CheckpointStatus::Completed);
+        coordinator.fail(second);
+        coordinator.acknowledge(second);
+        coordinator.acknowledge(second);
+        assert_eq!(
+            block_on(coordinator.settled(second)),
+            CheckpointStatus::Failed
+        );
+        assert_eq!(coordinator.last_completed(), Some(first));
+    }
+
+    #[test]
+    fn test_barrier_insertion() {
+        let coordinator = CheckpointCoordinator::new();
+        let mut pipe = BarrierPipe::new(coordinator.clone())
+            .with_interval(2)
+            .unwrap();
+        pipe.pipe(Rc::new(MockSource::new().items(['a', 'b', 'c', 'd', 'e'])))
+            .unwrap();
+        let items: Vec<Checkpointed<char>> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(
+            items,
+            vec![
+                Checkpointed::Item('a'),
+                Checkpointed::Item('b'),
+                Checkpointed::Barrier(1),
+                Checkpointed::Item('c'),
+                Checkpointed::Item('d'),
+                Checkpointed::Barrier(2),
+                Checkpointed::Item('e'),
+            ]
+        );
+    }
+}
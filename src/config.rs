@@ -0,0 +1,350 @@
+//! config
+//!
+//! Describing pipelines in YAML or TOML documents so they can change without recompiling
+//!
+//! a document has a `nodes` table keyed by node names, every node names the constructor
+//! building it with `kind` and passes it the other keys as parameters. Connections are
+//! given by naming the node feeding a pipe or sink with `input`, or as `[from, to]` pairs
+//! in an `edges` list:
+//!
+//! ```yaml
+//! nodes:
+//!   readings:
+//!     kind: lines
+//!     path: readings.txt
+//!   values:
+//!     kind: parse
+//!     input: readings
+//!   out:
+//!     kind: log
+//! edges:
+//!   - [values, out]
+//! ```
+//!
+//! kinds are resolved by a `NodeRegistry`, which knows the built-in kinds and the ones
+//! registered by the application
+
+use crate::pipeline::graph::{GraphError, PipelineGraph};
+use std::fmt;
+use std::path::Path;
+
+/// builtins
+/// Sub module of the kinds known to every registry
+mod builtins;
+
+/// registry
+/// Sub module mapping kind names to node constructors
+pub mod registry;
+
+/// toml
+/// Sub module reading TOML documents
+mod toml;
+
+/// yaml
+/// Sub module reading a subset of YAML
+pub mod yaml;
+
+pub use registry::{KindInfo, NodeRegistry, Params, Role};
+
+/// ConfigValue
+/// A value of a document, tables keeping the order of their keys
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigValue {
+    Bool(bool),
+    Integer(i64),
+    Float(f64),
+    String(String),
+    List(Vec<ConfigValue>),
+    Table(Vec<(String, ConfigValue)>),
+}
+
+impl ConfigValue {
+    /// the value of a key of a table
+    pub fn get(&self, key: &str) -> Option<&ConfigValue> {
+        match self {
+            ConfigValue::Table(entries) => entries
+                .iter()
+                .find(|(name, _)| name == key)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+    /// the string held, if any
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            ConfigValue::String(text) => Some(text),
+            _ => None,
+        }
+    }
+}
+
+/// ConfigError
+/// The reasons a document fails to load or to build, naming the offending nodes
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConfigError {
+    /// the document is not valid YAML or TOML (lines start at 1)
+    Syntax { line: usize, message: String },
+    /// the document does not describe a pipeline
+    Invalid(String),
+    /// a node names a kind the registry does not know
+    UnknownKind { node: String, kind: String },
+    /// a parameter is missing, unexpected or of the wrong type
+    Parameter {
+        node: String,
+        key: String,
+        reason: &'static str,
+    },
+    /// a node has a parameter its kind does not know
+    UnknownParameter {
+        node: String,
+        key: String,
+        kind: String,
+    },
+    /// a constructor refused its parameters
+    Node { node: String, reason: &'static str },
+    /// the nodes do not form a valid graph
+    Graph(GraphError),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConfigError::Syntax { line, message } => write!(f, "Line {}: {}", line, message),
+            ConfigError::Invalid(reason) => write!(f, "{}", reason),
+            ConfigError::UnknownKind { node, kind } => {
+                write!(f, "Node {} has unknown kind {}", node, kind)
+            }
+            ConfigError::Parameter { node, key, reason } => {
+                write!(f, "Parameter {} of node {} {}", key, node, reason)
+            }
+            ConfigError::UnknownParameter { node, key, kind } => {
+                write!(
+                    f,
+                    "Parameter {} of node {} is not known by kind {}",
+                    key, node, kind
+                )
+            }
+            ConfigError::Node { node, reason } => write!(f, "Node {}: {}", node, reason),
+            ConfigError::Graph(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+impl From<GraphError> for ConfigError {
+    fn from(error: GraphError) -> Self {
+        ConfigError::Graph(error)
+    }
+}
+
+/// NodeConfig
+/// A node of a pipeline description
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeConfig {
+    pub name: String,
+    pub kind: String,
+    /// the other keys of the node, in document order
+    pub params: Vec<(String, ConfigValue)>,
+}
+
+/// PipelineConfig
+/// The nodes and connections of a pipeline description
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct PipelineConfig {
+    /// nodes in document order
+    pub nodes: Vec<NodeConfig>,
+    /// connections as (from, to) node names
+    pub edges: Vec<(String, String)>,
+}
+
+fn invalid<T>(reason: String) -> Result<T, ConfigError> {
+    Err(ConfigError::Invalid(reason))
+}
+
+impl PipelineConfig {
+    /// read a TOML document
+    pub fn from_toml(text: &str) -> Result<Self, ConfigError> {
+        Self::from_value(&toml::parse(text)?)
+    }
+    /// read a YAML document
+    pub fn from_yaml(text: &str) -> Result<Self, ConfigError> {
+        Self::from_value(&yaml::parse(text)?)
+    }
+    /// read a file, as TOML for a `.toml` extension and as YAML otherwise
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, ConfigError> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|error| ConfigError::Invalid(format!("{}: {}", path.display(), error)))?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some("toml") => Self::from_toml(&text),
+            _ => Self::from_yaml(&text),
+        }
+    }
+    /// read a document already parsed into values
+    pub fn from_value(document: &ConfigValue) -> Result<Self, ConfigError> {
+        let mut config = PipelineConfig::default();
+        let nodes = match document.get("nodes") {
+            Some(ConfigValue::Table(nodes)) => nodes,
+            Some(_) => return invalid("The nodes of the description must be a table".into()),
+            None => return invalid("The description has no nodes".into()),
+        };
+        for (name, node) in nodes {
+            let ConfigValue::Table(entries) = node else {
+                return invalid(format!("Node {} must be a table", name));
+            };
+            let (mut kind, mut params) = (None, Vec::new());
+            for (key, value) in entries {
+                match (key.as_str(), value) {
+                    ("kind", ConfigValue::String(text)) => kind = Some(text.clone()),
+                    ("input", ConfigValue::String(text)) => {
+                        config.edges.push((text.clone(), name.clone()))
+                    }
+                    ("kind" | "input", _) => {
+                        return invalid(format!("The {} of node {} must be a string", key, name))
+                    }
+                    _ => params.push((key.clone(), value.clone())),
+                }
+            }
+            let kind =
+                kind.ok_or_else(|| ConfigError::Invalid(format!("Node {} has no kind", name)))?;
+            config.nodes.push(NodeConfig {
+                name: name.clone(),
+                kind,
+                params,
+            });
+        }
+        match document.get("edges") {
+            None => {}
+            Some(ConfigValue::List(edges)) => {
+                for edge in edges {
+                    match edge {
+                        ConfigValue::List(pair) if pair.len() == 2 => {
+                            match (pair[0].as_str(), pair[1].as_str()) {
+                                (Some(from), Some(to)) => {
+                                    config.edges.push((from.to_string(), to.to_string()))
+                                }
+                                _ => return invalid("Edges must join node names".into()),
+                            }
+                        }
+                        _ => return invalid("Edges must be [from, to] pairs".into()),
+                    }
+                }
+            }
+            Some(_) => return invalid("The edges of the description must be a list".into()),
+        }
+        Ok(config)
+    }
+    /// construct the nodes with a registry and connect them
+    pub fn graph(&self, registry: &NodeRegistry) -> Result<PipelineGraph, ConfigError> {
+        let mut graph = PipelineGraph::new();
+        for node in self.nodes.iter() {
+            registry.add(&mut graph, node)?;
+        }
+        for (from, to) in self.edges.iter() {
+            graph.connect(from, to);
+        }
+        Ok(graph)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::CaptureSink;
+    use futures::executor::block_on;
+
+    const YAML: &str = "
+# numbers halved
+nodes:
+  ramp:
+    kind: ramp
+    start: 1
+    len: 4
+  half:
+    kind: scale
+    factor: 0.5
+    input: ramp
+  capture:
+    kind: capture
+edges:
+  - [half, capture]
+";
+
+    const TOML: &str = "
+edges = [['half', 'capture']]
+
+[nodes.ramp]
+kind = 'ramp'
+start = 1
+len = 4
+
+[nodes.half]
+kind = 'scale'
+factor = 0.5
+input = 'ramp'
+
+[nodes.capture]
+kind = 'capture'
+";
+
+    #[test]
+    fn test_yaml_and_toml_agree() {
+        let yaml = PipelineConfig::from_yaml(YAML).unwrap();
+        assert_eq!(PipelineConfig::from_toml(TOML), Ok(yaml.clone()));
+        assert_eq!(
+            yaml.edges,
+            vec![
+                ("ramp".to_string(), "half".to_string()),
+                ("half".to_string(), "capture".to_string())
+            ]
+        );
+        assert_eq!(
+            yaml.nodes[1].params,
+            vec![("factor".to_string(), ConfigValue::Float(0.5))]
+        );
+    }
+
+    #[test]
+    fn test_build_with_registry() {
+        let capture = CaptureSink::new();
+        let mut registry = NodeRegistry::with_builtins();
+        let captured = capture.clone();
+        registry.register_sink("capture", "keeps numbers", move |_| Ok(captured.clone()));
+        let graph = PipelineConfig::from_yaml(YAML)
+            .unwrap()
+            .graph(&registry)
+            .unwrap();
+        block_on(graph.build().unwrap().drain()).unwrap();
+        capture.assert_items(&[0.5, 1.0, 1.5, 2.0]);
+        let error = |text: &str| {
+            PipelineConfig::from_yaml(text)
+                .and_then(|config| config.graph(&registry))
+                .err()
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            error("nodes:\n  a:\n    kind: teleport\n"),
+            "Node a has unknown kind teleport"
+        );
+        assert_eq!(
+            error("nodes:\n  a:\n    kind: ramp\n    speed: 2\n"),
+            "Parameter speed of node a is not known by kind ramp"
+        );
+        assert_eq!(
+            error("nodes:\n  a:\n    kind: ramp\n    len: -1\n"),
+            "Parameter len of node a must be a non negative integer"
+        );
+        assert_eq!(
+            error("nodes:\n  a:\n    kind: scale\n"),
+            "Parameter factor of node a is missing"
+        );
+        assert_eq!(error("nodes:\n  a:\n    input: b\n"), "Node a has no kind");
+        assert_eq!(error("edges: []\n"), "The description has no nodes");
+        assert_eq!(
+            error("nodes:\n  a:\n    kind: ramp\nedges:\n  - [a]\n"),
+            "Edges must be [from, to] pairs"
+        );
+    }
+}
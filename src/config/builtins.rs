@@ -0,0 +1,182 @@
+//! builtins
+//!
+//! The kinds every registry starts with, streaming either text lines (String) or numbers
+//! (f64), `parse` and `format` converting between the two
+
+use super::{ConfigError, NodeRegistry, Params};
+use crate::ops::{ElementwiseOp, ElementwisePipe};
+use crate::pipes::{DistinctUntilChangedPipe, FlatMapPipe, SkipPipe, TakePipe};
+use crate::sinks::{LogFormat, LogSink};
+use crate::sources::{Distribution, RampSource, RandomSource, SineSource, TextSource};
+use crate::text::Regex;
+use crate::Source;
+use std::rc::Rc;
+
+fn distribution(params: &Params) -> Result<Distribution, ConfigError> {
+    Ok(match params.text("distribution")?.unwrap_or("uniform") {
+        "uniform" => Distribution::Uniform {
+            low: params.number("low")?.unwrap_or(0.0),
+            high: params.number("high")?.unwrap_or(1.0),
+        },
+        "normal" => Distribution::Normal {
+            mean: params.number("mean")?.unwrap_or(0.0),
+            std_dev: params.number("std_dev")?.unwrap_or(1.0),
+        },
+        "exponential" => Distribution::Exponential {
+            rate: params.number("rate")?.unwrap_or(1.0),
+        },
+        _ => return Err(params.refuse("Unknown distribution")),
+    })
+}
+
+fn sources(registry: &mut NodeRegistry) {
+    registry.register_source("lines", "the lines of the text file at path", |params| {
+        let path = params.required("path", params.text("path")?)?;
+        Ok(Rc::new(TextSource::open(path)) as Rc<dyn Source<String>>)
+    });
+    registry.register_source(
+        "ramp",
+        "numbers from start (0) by step (1), endless without len",
+        |params| {
+            let start = params.number("start")?.unwrap_or(0.0);
+            let ramp = RampSource::new(start, params.number("step")?.unwrap_or(1.0));
+            let ramp = match params.count("len")? {
+                Some(len) => ramp.with_len(len),
+                None => ramp,
+            };
+            Ok(Rc::new(ramp) as Rc<dyn Source<f64>>)
+        },
+    );
+    registry.register_source(
+        "sine",
+        "samples of a sine wave (frequency, sample_rate, amplitude, phase, offset), endless \
+         without len",
+        |params| {
+            let frequency = params.required("frequency", params.number("frequency")?)?;
+            let sample_rate = params.required("sample_rate", params.number("sample_rate")?)?;
+            let mut sine =
+                SineSource::new(frequency, sample_rate).map_err(|error| params.refuse(error))?;
+            if let Some(amplitude) = params.number("amplitude")? {
+                sine = sine.with_amplitude(amplitude);
+            }
+            if let Some(phase) = params.number("phase")? {
+                sine = sine.with_phase(phase);
+            }
+            if let Some(offset) = params.number("offset")? {
+                sine = sine.with_offset(offset);
+            }
+            if let Some(len) = params.count("len")? {
+                sine = sine.with_len(len);
+            }
+            Ok(Rc::new(sine) as Rc<dyn Source<f64>>)
+        },
+    );
+    registry.register_source(
+        "random",
+        "random numbers from a uniform (low, high), normal (mean, std_dev) or exponential \
+         (rate) distribution, endless without len",
+        |params| {
+            let mut random =
+                RandomSource::new(distribution(params)?).map_err(|error| params.refuse(error))?;
+            if let Some(seed) = params.count("seed")? {
+                random = random.with_seed(seed as u64);
+            }
+            if let Some(len) = params.count("len")? {
+                random = random.with_len(len);
+            }
+            Ok(Rc::new(random) as Rc<dyn Source<f64>>)
+        },
+    );
+}
+
+fn pipes(registry: &mut NodeRegistry) {
+    registry.register_pipe(
+        "parse",
+        "the numbers written on lines, dropping other lines",
+        |_| {
+            Ok(FlatMapPipe::new(|line: String| {
+                line.trim().parse::<f64>().ok()
+            }))
+        },
+    );
+    registry.register_pipe(
+        "format",
+        "numbers written as lines, with precision decimals if given",
+        |params| {
+            let precision = params.count("precision")?;
+            Ok(FlatMapPipe::new(move |value: f64| {
+                Some(match precision {
+                    Some(precision) => format!("{:.*}", precision, value),
+                    None => value.to_string(),
+                })
+            }))
+        },
+    );
+    registry.register_pipe("scale", "numbers multiplied by factor", |params| {
+        let factor = params.required("factor", params.number("factor")?)?;
+        Ok(ElementwisePipe::scale(factor))
+    });
+    registry.register_pipe("offset", "numbers with value added", |params| {
+        let value = params.required("value", params.number("value")?)?;
+        Ok(ElementwisePipe::with_scalar(ElementwiseOp::Add, value))
+    });
+    registry.register_pipe("take", "the first count lines", |params| {
+        let count = params.required("count", params.count("count")?)?;
+        Ok(TakePipe::<String>::new(count))
+    });
+    registry.register_pipe("skip", "the lines after the first count", |params| {
+        let count = params.required("count", params.count("count")?)?;
+        Ok(SkipPipe::<String>::new(count))
+    });
+    registry.register_pipe(
+        "grep",
+        "the lines matching the regular expression pattern",
+        |params| {
+            let pattern = params.required("pattern", params.text("pattern")?)?;
+            let regex = Regex::new(pattern).map_err(|error| params.refuse(error))?;
+            Ok(FlatMapPipe::new(move |line: String| {
+                regex.is_match(&line).then_some(line)
+            }))
+        },
+    );
+    registry.register_pipe(
+        "distinct",
+        "lines without the repetitions of the previous line",
+        |_| Ok(DistinctUntilChangedPipe::<String>::new()),
+    );
+}
+
+fn sinks(registry: &mut NodeRegistry) {
+    registry.register_sink(
+        "log",
+        "logs lines to stderr in a format (compact, pretty or json) at a level, keeping a \
+         sample fraction",
+        |params| {
+            let mut sink = LogSink::<String>::new(params.node());
+            sink = sink.format(match params.text("format")?.unwrap_or("compact") {
+                "compact" => LogFormat::Compact,
+                "pretty" => LogFormat::Pretty,
+                "json" => LogFormat::Json,
+                _ => return Err(params.refuse("Unknown log format")),
+            });
+            if let Some(level) = params.text("level")? {
+                sink = sink.level(
+                    level
+                        .parse()
+                        .map_err(|_| params.refuse("Unknown log level"))?,
+                );
+            }
+            if let Some(rate) = params.number("sample")? {
+                sink = sink.sample(rate);
+            }
+            Ok(sink)
+        },
+    );
+}
+
+/// register the built-in kinds
+pub(crate) fn register(registry: &mut NodeRegistry) {
+    sources(registry);
+    pipes(registry);
+    sinks(registry);
+}
@@ -0,0 +1,322 @@
+//! registry
+//!
+//! Mapping the kind names of descriptions to the constructors of nodes
+//!
+//! constructors read their parameters from `Params`, a parameter no constructor read is
+//! reported as unknown so misspelled keys do not go unnoticed. Registering a kind again
+//! replaces the previous constructor
+
+use super::{builtins, ConfigError, ConfigValue, NodeConfig};
+use crate::pipeline::graph::PipelineGraph;
+#[cfg(feature = "plugin")]
+use crate::plugin::{Plugin, PluginItem, PluginKind, BV_ITEMS_BYTES, BV_ITEMS_TEXT};
+use crate::{Pipe, Sink, Source};
+#[cfg(feature = "plugin")]
+use bytes::Bytes;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+/// Role
+/// Where the nodes of a kind sit in a graph
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    Source,
+    Pipe,
+    Sink,
+}
+
+/// KindInfo
+/// The description of a registered kind
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KindInfo {
+    pub name: String,
+    pub role: Role,
+    /// type of the items consumed (None for sources)
+    pub input: Option<&'static str>,
+    /// type of the items produced (None for sinks)
+    pub output: Option<&'static str>,
+    pub help: String,
+}
+
+/// Params
+/// The parameters of a node, read with the types its constructor expects
+pub struct Params {
+    node: String,
+    values: Vec<(String, ConfigValue)>,
+    read: RefCell<Vec<String>>,
+}
+
+impl Params {
+    /// constructor for the parameters of a node
+    pub fn new(node: &str, values: Vec<(String, ConfigValue)>) -> Self {
+        Self {
+            node: node.to_string(),
+            values,
+            read: RefCell::new(Vec::new()),
+        }
+    }
+    /// the name of the node
+    pub fn node(&self) -> &str {
+        &self.node
+    }
+    fn error(&self, key: &str, reason: &'static str) -> ConfigError {
+        ConfigError::Parameter {
+            node: self.node.clone(),
+            key: key.to_string(),
+            reason,
+        }
+    }
+    /// an optional parameter of any type
+    pub fn get(&self, key: &str) -> Option<&ConfigValue> {
+        self.read.borrow_mut().push(key.to_string());
+        self.values
+            .iter()
+            .find(|(name, _)| name == key)
+            .map(|(_, value)| value)
+    }
+    /// an optional string parameter
+    pub fn text(&self, key: &str) -> Result<Option<&str>, ConfigError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(ConfigValue::String(text)) => Ok(Some(text)),
+            Some(_) => Err(self.error(key, "must be a string")),
+        }
+    }
+    /// an optional numeric parameter (integer or float)
+    pub fn number(&self, key: &str) -> Result<Option<f64>, ConfigError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(ConfigValue::Float(value)) => Ok(Some(*value)),
+            Some(ConfigValue::Integer(value)) => Ok(Some(*value as f64)),
+            Some(_) => Err(self.error(key, "must be a number")),
+        }
+    }
+    /// an optional integer parameter
+    pub fn integer(&self, key: &str) -> Result<Option<i64>, ConfigError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(ConfigValue::Integer(value)) => Ok(Some(*value)),
+            Some(_) => Err(self.error(key, "must be an integer")),
+        }
+    }
+    /// an optional count parameter (non negative integer)
+    pub fn count(&self, key: &str) -> Result<Option<usize>, ConfigError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(ConfigValue::Integer(value)) if *value >= 0 => Ok(Some(*value as usize)),
+            Some(_) => Err(self.error(key, "must be a non negative integer")),
+        }
+    }
+    /// an optional boolean parameter
+    pub fn flag(&self, key: &str) -> Result<Option<bool>, ConfigError> {
+        match self.get(key) {
+            None => Ok(None),
+            Some(ConfigValue::Bool(value)) => Ok(Some(*value)),
+            Some(_) => Err(self.error(key, "must be true or false")),
+        }
+    }
+    /// every parameter written as text, for constructors taking parameters they do not know
+    /// in advance
+    pub fn scalars(&self) -> Result<Vec<(String, String)>, ConfigError> {
+        self.values
+            .iter()
+            .map(|(key, value)| {
+                self.read.borrow_mut().push(key.clone());
+                let text = match value {
+                    ConfigValue::Bool(value) => value.to_string(),
+                    ConfigValue::Integer(value) => value.to_string(),
+                    ConfigValue::Float(value) => value.to_string(),
+                    ConfigValue::String(value) => value.clone(),
+                    _ => return Err(self.error(key, "must be a string, number or boolean")),
+                };
+                Ok((key.clone(), text))
+            })
+            .collect()
+    }
+    /// fail for a missing parameter
+    pub fn required<T>(&self, key: &str, value: Option<T>) -> Result<T, ConfigError> {
+        value.ok_or_else(|| self.error(key, "is missing"))
+    }
+    /// an error for a constructor refusing its parameters
+    pub fn refuse(&self, reason: &'static str) -> ConfigError {
+        ConfigError::Node {
+            node: self.node.clone(),
+            reason,
+        }
+    }
+    /// the first parameter that was never read
+    fn unread(&self) -> Option<&str> {
+        let read = self.read.borrow();
+        self.values
+            .iter()
+            .map(|(name, _)| name.as_str())
+            .find(|name| !read.iter().any(|key| key == name))
+    }
+}
+
+type Constructor = Box<dyn Fn(&mut PipelineGraph, &Params) -> Result<(), ConfigError>>;
+
+/// NodeRegistry
+/// The kinds of nodes a description can name
+pub struct NodeRegistry {
+    kinds: BTreeMap<String, (KindInfo, Constructor)>,
+}
+
+/// the name of a type without its module path
+fn short_name<T>() -> &'static str {
+    let name = std::any::type_name::<T>();
+    match name.split('<').next().and_then(|path| path.rfind("::")) {
+        Some(end) => &name[end + 2..],
+        None => name,
+    }
+}
+
+impl NodeRegistry {
+    /// constructor without any kind
+    pub fn new() -> Self {
+        Self {
+            kinds: BTreeMap::new(),
+        }
+    }
+    /// constructor knowing the built-in kinds
+    pub fn with_builtins() -> Self {
+        let mut registry = Self::new();
+        builtins::register(&mut registry);
+        registry
+    }
+    fn insert(&mut self, info: KindInfo, constructor: Constructor) {
+        self.kinds.insert(info.name.clone(), (info, constructor));
+    }
+    /// register a kind of source
+    pub fn register_source<T, F>(&mut self, kind: &str, help: &str, constructor: F)
+    where
+        T: 'static,
+        F: Fn(&Params) -> Result<Rc<dyn Source<T>>, ConfigError> + 'static,
+    {
+        let info = KindInfo {
+            name: kind.to_string(),
+            role: Role::Source,
+            input: None,
+            output: Some(short_name::<T>()),
+            help: help.to_string(),
+        };
+        self.insert(
+            info,
+            Box::new(move |graph, params| {
+                let source = constructor(params)?;
+                Ok(graph.add_source(params.node(), source)?)
+            }),
+        );
+    }
+    /// register a kind of pipe
+    pub fn register_pipe<InT, OutT, P, F>(&mut self, kind: &str, help: &str, constructor: F)
+    where
+        InT: 'static,
+        OutT: 'static,
+        P: Pipe<InT, OutT> + 'static,
+        F: Fn(&Params) -> Result<P, ConfigError> + 'static,
+    {
+        let info = KindInfo {
+            name: kind.to_string(),
+            role: Role::Pipe,
+            input: Some(short_name::<InT>()),
+            output: Some(short_name::<OutT>()),
+            help: help.to_string(),
+        };
+        self.insert(
+            info,
+            Box::new(move |graph, params| {
+                let pipe = constructor(params)?;
+                Ok(graph.add_pipe(params.node(), pipe)?)
+            }),
+        );
+    }
+    /// register a kind of sink
+    pub fn register_sink<T, S, F>(&mut self, kind: &str, help: &str, constructor: F)
+    where
+        T: 'static,
+        S: Sink<T> + 'static,
+        F: Fn(&Params) -> Result<S, ConfigError> + 'static,
+    {
+        let info = KindInfo {
+            name: kind.to_string(),
+            role: Role::Sink,
+            input: Some(short_name::<T>()),
+            output: None,
+            help: help.to_string(),
+        };
+        self.insert(
+            info,
+            Box::new(move |graph, params| {
+                let sink = constructor(params)?;
+                Ok(graph.add_sink(params.node(), sink)?)
+            }),
+        );
+    }
+    /// register the pipes of a plugin, under the kind names the plugin gives them
+    #[cfg(feature = "plugin")]
+    pub fn register_plugin(&mut self, plugin: &Plugin) {
+        for kind in plugin.kinds() {
+            match (kind.input, kind.output) {
+                (BV_ITEMS_BYTES, BV_ITEMS_BYTES) => self.register_plugin_kind::<Bytes, Bytes>(kind),
+                (BV_ITEMS_BYTES, BV_ITEMS_TEXT) => self.register_plugin_kind::<Bytes, String>(kind),
+                (BV_ITEMS_BYTES, _) => self.register_plugin_kind::<Bytes, f64>(kind),
+                (BV_ITEMS_TEXT, BV_ITEMS_BYTES) => self.register_plugin_kind::<String, Bytes>(kind),
+                (BV_ITEMS_TEXT, BV_ITEMS_TEXT) => self.register_plugin_kind::<String, String>(kind),
+                (BV_ITEMS_TEXT, _) => self.register_plugin_kind::<String, f64>(kind),
+                (_, BV_ITEMS_BYTES) => self.register_plugin_kind::<f64, Bytes>(kind),
+                (_, BV_ITEMS_TEXT) => self.register_plugin_kind::<f64, String>(kind),
+                _ => self.register_plugin_kind::<f64, f64>(kind),
+            }
+        }
+    }
+    #[cfg(feature = "plugin")]
+    fn register_plugin_kind<InT: PluginItem, OutT: PluginItem>(&mut self, kind: &PluginKind) {
+        let plugin_kind = kind.clone();
+        self.register_pipe(&kind.kind, &kind.help, move |params| {
+            let values = params.scalars()?;
+            let values: Vec<(&str, &str)> = values
+                .iter()
+                .map(|(key, value)| (key.as_str(), value.as_str()))
+                .collect();
+            plugin_kind
+                .pipe::<InT, OutT>(&values)
+                .map_err(|error| params.refuse(error))
+        });
+    }
+    /// the registered kinds, by name
+    pub fn kinds(&self) -> impl Iterator<Item = &KindInfo> {
+        self.kinds.values().map(|(info, _)| info)
+    }
+    /// the description of a kind
+    pub fn kind(&self, name: &str) -> Option<&KindInfo> {
+        self.kinds.get(name).map(|(info, _)| info)
+    }
+    /// construct a node and add it to a graph
+    pub fn add(&self, graph: &mut PipelineGraph, node: &NodeConfig) -> Result<(), ConfigError> {
+        let (_, constructor) =
+            self.kinds
+                .get(&node.kind)
+                .ok_or_else(|| ConfigError::UnknownKind {
+                    node: node.name.clone(),
+                    kind: node.kind.clone(),
+                })?;
+        let params = Params::new(&node.name, node.params.clone());
+        constructor(graph, &params)?;
+        match params.unread() {
+            Some(key) => Err(ConfigError::UnknownParameter {
+                node: node.name.clone(),
+                key: key.to_string(),
+                kind: node.kind.clone(),
+            }),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Default for NodeRegistry {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,57 @@
+//! toml
+//!
+//! Reading TOML documents into values, datetimes kept as their text
+
+use super::{ConfigError, ConfigValue};
+use toml_edit::{DocumentMut, Item, Table, Value};
+
+fn value(entry: &Value) -> ConfigValue {
+    match entry {
+        Value::String(text) => ConfigValue::String(text.value().clone()),
+        Value::Integer(integer) => ConfigValue::Integer(*integer.value()),
+        Value::Float(float) => ConfigValue::Float(*float.value()),
+        Value::Boolean(flag) => ConfigValue::Bool(*flag.value()),
+        Value::Datetime(datetime) => ConfigValue::String(datetime.value().to_string()),
+        Value::Array(array) => ConfigValue::List(array.iter().map(value).collect()),
+        Value::InlineTable(table) => ConfigValue::Table(
+            table
+                .iter()
+                .map(|(key, entry)| (key.to_string(), value(entry)))
+                .collect(),
+        ),
+    }
+}
+
+fn table(table: &Table) -> ConfigValue {
+    ConfigValue::Table(
+        table
+            .iter()
+            .filter_map(|(key, entry)| Some((key.to_string(), item(entry)?)))
+            .collect(),
+    )
+}
+
+fn item(item: &Item) -> Option<ConfigValue> {
+    match item {
+        Item::None => None,
+        Item::Value(entry) => Some(value(entry)),
+        Item::Table(entry) => Some(table(entry)),
+        Item::ArrayOfTables(tables) => Some(ConfigValue::List(tables.iter().map(table).collect())),
+    }
+}
+
+/// parse a TOML document
+pub(crate) fn parse(text: &str) -> Result<ConfigValue, ConfigError> {
+    let document: DocumentMut = text.parse().map_err(|error: toml_edit::TomlError| {
+        let start = error.span().map_or(0, |span| span.start.min(text.len()));
+        ConfigError::Syntax {
+            line: text.as_bytes()[..start]
+                .iter()
+                .filter(|&&byte| byte == b'\n')
+                .count()
+                + 1,
+            message: error.message().to_string(),
+        }
+    })?;
+    Ok(table(document.as_table()))
+}
@@ -0,0 +1,514 @@
+//! yaml
+//!
+//! Reading the subset of YAML pipeline descriptions are written in
+//!
+//! supported are block mappings and sequences nested by indentation, flow sequences and
+//! mappings (`[a, b]`, `{a: 1}`), plain, single and double quoted scalars and comments.
+//! Plain scalars are booleans (`true`, `false`), integers, floats or strings. Anchors,
+//! tags, block scalars (`|`, `>`), null values and multiple documents are refused rather
+//! than misread
+
+use super::{ConfigError, ConfigValue};
+
+/// a line holding content, without its comment
+struct Line {
+    number: usize,
+    indent: usize,
+    text: String,
+}
+
+fn error<T>(line: usize, message: &str) -> Result<T, ConfigError> {
+    Err(ConfigError::Syntax {
+        line,
+        message: message.to_string(),
+    })
+}
+
+/// the line without a trailing comment, a `#` starting a comment at the start of the line
+/// or after a space outside quotes
+fn strip_comment(line: &str) -> &str {
+    let mut quote = None;
+    let mut previous = ' ';
+    for (index, character) in line.char_indices() {
+        match (quote, character) {
+            (None, '#') if previous == ' ' || previous == '\t' => return &line[..index],
+            (None, '"' | '\'') if previous == ' ' || index == 0 || "[{,:-".contains(previous) => {
+                quote = Some(character)
+            }
+            (Some(open), _) if character == open && !(open == '"' && previous == '\\') => {
+                quote = None
+            }
+            _ => {}
+        }
+        previous = character;
+    }
+    line
+}
+
+fn is_item(text: &str) -> bool {
+    text == "-" || text.starts_with("- ")
+}
+
+/// the end of a quoted scalar starting a text
+fn quoted_end(text: &str) -> Option<usize> {
+    let quote = text.chars().next()?;
+    let mut escaped = false;
+    let mut characters = text.char_indices().skip(1).peekable();
+    while let Some((index, character)) = characters.next() {
+        match character {
+            '\\' if quote == '"' && !escaped => {
+                escaped = true;
+                continue;
+            }
+            '\'' if quote == '\'' && characters.peek().map(|(_, next)| *next) == Some('\'') => {
+                characters.next();
+            }
+            _ if character == quote && !escaped => return Some(index + 1),
+            _ => {}
+        }
+        escaped = false;
+    }
+    None
+}
+
+/// split a `key: value` entry, the value being empty for a key opening a block
+fn split_key(text: &str) -> Option<(&str, &str)> {
+    if text.starts_with('[') || text.starts_with('{') {
+        return None;
+    }
+    let end = if text.starts_with('"') || text.starts_with('\'') {
+        let end = quoted_end(text)?;
+        text[end..].starts_with(':').then_some(end)?
+    } else {
+        text.char_indices()
+            .find(|&(index, character)| {
+                character == ':' && text[index + 1..].chars().next().is_none_or(|c| c == ' ')
+            })?
+            .0
+    };
+    Some((&text[..end], text[end + 1..].trim()))
+}
+
+/// the text of a quoted scalar, with its escapes resolved
+fn unquote(text: &str, line: usize) -> Result<String, ConfigError> {
+    if text.starts_with('\'') {
+        return Ok(text[1..text.len() - 1].replace("''", "'"));
+    }
+    let mut unquoted = String::new();
+    let mut characters = text[1..text.len() - 1].chars();
+    while let Some(character) = characters.next() {
+        if character != '\\' {
+            unquoted.push(character);
+            continue;
+        }
+        unquoted.push(match characters.next() {
+            Some('n') => '\n',
+            Some('t') => '\t',
+            Some('r') => '\r',
+            Some('0') => '\0',
+            Some(escaped @ ('"' | '\\' | '/' | ' ')) => escaped,
+            _ => return error(line, "Unsupported escape sequence"),
+        });
+    }
+    Ok(unquoted)
+}
+
+/// the value of a plain or quoted scalar
+fn scalar(text: &str, line: usize) -> Result<ConfigValue, ConfigError> {
+    if text.starts_with('"') || text.starts_with('\'') {
+        if quoted_end(text) != Some(text.len()) {
+            return error(line, "Unterminated or trailing text after a quoted string");
+        }
+        return Ok(ConfigValue::String(unquote(text, line)?));
+    }
+    if text.starts_with(['&', '*', '!', '|', '>', '%', '@', '`']) {
+        return error(line, "Anchors, tags and block scalars are not supported");
+    }
+    Ok(match text {
+        "" | "~" | "null" | "Null" | "NULL" => return error(line, "Null values are not supported"),
+        "true" | "True" | "TRUE" => ConfigValue::Bool(true),
+        "false" | "False" | "FALSE" => ConfigValue::Bool(false),
+        ".inf" | "+.inf" | ".Inf" | "+.Inf" => ConfigValue::Float(f64::INFINITY),
+        "-.inf" | "-.Inf" => ConfigValue::Float(f64::NEG_INFINITY),
+        ".nan" | ".NaN" => ConfigValue::Float(f64::NAN),
+        _ => {
+            let numeric = text.starts_with(|c: char| c.is_ascii_digit() || "+-.".contains(c))
+                && text.chars().any(|c| c.is_ascii_digit())
+                && text
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || "+-.eE".contains(c));
+            if let Some(hex) = text.strip_prefix("0x") {
+                match i64::from_str_radix(hex, 16) {
+                    Ok(integer) => ConfigValue::Integer(integer),
+                    Err(_) => ConfigValue::String(text.to_string()),
+                }
+            } else if let (true, Ok(integer)) = (numeric, text.parse::<i64>()) {
+                ConfigValue::Integer(integer)
+            } else if let (true, Ok(float)) = (numeric, text.parse::<f64>()) {
+                ConfigValue::Float(float)
+            } else {
+                ConfigValue::String(text.to_string())
+            }
+        }
+    })
+}
+
+/// Flow
+/// A reader of flow collections (`[a, b]`, `{a: 1}`) written on one line
+struct Flow<'a> {
+    text: &'a str,
+    position: usize,
+    line: usize,
+}
+
+impl Flow<'_> {
+    fn rest(&self) -> &str {
+        &self.text[self.position..]
+    }
+    fn skip_spaces(&mut self) {
+        self.position = self.text.len() - self.rest().trim_start().len();
+    }
+    fn next_is(&mut self, character: char) -> bool {
+        self.skip_spaces();
+        if self.rest().starts_with(character) {
+            self.position += character.len_utf8();
+            return true;
+        }
+        false
+    }
+    /// the text of a scalar ending at one of some characters
+    fn scalar_text(&mut self, ends: &str) -> Result<&str, ConfigError> {
+        self.skip_spaces();
+        let rest = self.rest();
+        let end = if rest.starts_with('"') || rest.starts_with('\'') {
+            match quoted_end(rest) {
+                Some(end) => end,
+                None => return error(self.line, "Unterminated quoted string"),
+            }
+        } else {
+            rest.find(|c: char| ends.contains(c)).unwrap_or(rest.len())
+        };
+        let start = self.position;
+        self.position += end;
+        Ok(self.text[start..self.position].trim_end())
+    }
+    fn value(&mut self) -> Result<ConfigValue, ConfigError> {
+        if self.next_is('[') {
+            let mut items = Vec::new();
+            if self.next_is(']') {
+                return Ok(ConfigValue::List(items));
+            }
+            loop {
+                items.push(self.value()?);
+                if self.next_is(']') {
+                    return Ok(ConfigValue::List(items));
+                }
+                if !self.next_is(',') {
+                    return error(self.line, "Expected , or ] in a flow sequence");
+                }
+            }
+        }
+        if self.next_is('{') {
+            let mut entries = Vec::new();
+            if self.next_is('}') {
+                return Ok(ConfigValue::Table(entries));
+            }
+            loop {
+                let line = self.line;
+                let key = key(self.scalar_text(":,}")?, line)?;
+                if !self.next_is(':') {
+                    return error(self.line, "Expected : after a key");
+                }
+                entries.push((key, self.value()?));
+                if self.next_is('}') {
+                    return Ok(ConfigValue::Table(entries));
+                }
+                if !self.next_is(',') {
+                    return error(self.line, "Expected , or } in a flow mapping");
+                }
+            }
+        }
+        let line = self.line;
+        scalar(self.scalar_text(",]}")?, line)
+    }
+}
+
+/// the value written after a key or a sequence dash
+fn inline(text: &str, line: usize) -> Result<ConfigValue, ConfigError> {
+    if !text.starts_with('[') && !text.starts_with('{') {
+        return scalar(text, line);
+    }
+    let mut flow = Flow {
+        text,
+        position: 0,
+        line,
+    };
+    let value = flow.value()?;
+    flow.skip_spaces();
+    if !flow.rest().is_empty() {
+        return error(line, "Trailing text after a flow collection");
+    }
+    Ok(value)
+}
+
+fn key(text: &str, line: usize) -> Result<String, ConfigError> {
+    match scalar(text, line)? {
+        ConfigValue::String(key) => Ok(key),
+        _ => Ok(text.to_string()),
+    }
+}
+
+/// Parser
+/// A reader of block collections nested by indentation
+struct Parser {
+    lines: Vec<Line>,
+    position: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Line> {
+        self.lines.get(self.position)
+    }
+    fn block(&mut self, indent: usize) -> Result<ConfigValue, ConfigError> {
+        match self.peek() {
+            Some(line) if is_item(&line.text) => self.sequence(indent),
+            _ => self.mapping(indent),
+        }
+    }
+    /// the end of a block, failing for lines indented deeper than it
+    fn end(&self, indent: usize) -> Result<(), ConfigError> {
+        match self.peek() {
+            Some(line) if line.indent > indent => error(line.number, "Unexpected indentation"),
+            _ => Ok(()),
+        }
+    }
+    /// the block nested under a key or dash, missing when the next line is not deeper
+    fn nested(&mut self, indent: usize, number: usize) -> Result<ConfigValue, ConfigError> {
+        match self.peek() {
+            Some(line) if line.indent > indent => self.block(line.indent),
+            _ => error(number, "Missing value"),
+        }
+    }
+    fn sequence(&mut self, indent: usize) -> Result<ConfigValue, ConfigError> {
+        let mut items = Vec::new();
+        while let Some(line) = self
+            .peek()
+            .filter(|line| line.indent == indent && is_item(&line.text))
+        {
+            let number = line.number;
+            let rest = line.text[1..].trim_start().to_string();
+            let offset = line.text.len() - rest.len();
+            if rest.is_empty() {
+                self.position += 1;
+                items.push(self.nested(indent, number)?);
+            } else if is_item(&rest) || split_key(&rest).is_some() {
+                // the item is a collection starting on the line of its dash
+                self.lines[self.position] = Line {
+                    number,
+                    indent: indent + offset,
+                    text: rest,
+                };
+                items.push(self.block(indent + offset)?);
+            } else {
+                self.position += 1;
+                items.push(inline(&rest, number)?);
+            }
+        }
+        self.end(indent)?;
+        Ok(ConfigValue::List(items))
+    }
+    fn mapping(&mut self, indent: usize) -> Result<ConfigValue, ConfigError> {
+        let mut entries: Vec<(String, ConfigValue)> = Vec::new();
+        while let Some(line) = self.peek().filter(|line| line.indent == indent) {
+            let number = line.number;
+            let (key_text, rest) = match split_key(&line.text) {
+                Some((key_text, rest)) if !is_item(&line.text) => (key_text, rest.to_string()),
+                _ => return error(number, "Expected a key"),
+            };
+            let key = key(key_text, number)?;
+            if entries.iter().any(|(name, _)| *name == key) {
+                return error(number, "Duplicate key");
+            }
+            self.position += 1;
+            let value = if !rest.is_empty() {
+                inline(&rest, number)?
+            } else {
+                match self.peek() {
+                    // sequences may sit at the indentation of their key
+                    Some(next) if next.indent == indent && is_item(&next.text) => {
+                        self.sequence(indent)?
+                    }
+                    _ => self.nested(indent, number)?,
+                }
+            };
+            entries.push((key, value));
+        }
+        self.end(indent)?;
+        Ok(ConfigValue::Table(entries))
+    }
+}
+
+/// parse a YAML document
+pub fn parse(text: &str) -> Result<ConfigValue, ConfigError> {
+    let mut lines = Vec::new();
+    for (index, raw) in text.lines().enumerate() {
+        let number = index + 1;
+        let content = strip_comment(raw).trim_end();
+        let trimmed = content.trim_start();
+        if trimmed.is_empty() {
+            continue;
+        }
+        if trimmed == "---" || trimmed == "..." {
+            if lines.is_empty() && trimmed == "---" {
+                continue;
+            }
+            return error(number, "Multiple documents are not supported");
+        }
+        if content[..content.len() - trimmed.len()].contains('\t') {
+            return error(number, "Tabs cannot indent YAML");
+        }
+        lines.push(Line {
+            number,
+            indent: content.len() - trimmed.len(),
+            text: trimmed.to_string(),
+        });
+    }
+    let indent = match lines.first() {
+        Some(line) => line.indent,
+        None => return Ok(ConfigValue::Table(Vec::new())),
+    };
+    let single_line = lines.len() == 1 && split_key(&lines[0].text).is_none();
+    if single_line && !is_item(&lines[0].text) {
+        return inline(&lines[0].text, lines[0].number);
+    }
+    let mut parser = Parser { lines, position: 0 };
+    let document = parser.block(indent)?;
+    match parser.peek() {
+        Some(line) if line.indent == indent => error(line.number, "Mixed sequence and mapping"),
+        Some(line) => error(line.number, "Unexpected indentation"),
+        None => Ok(document),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ConfigValue::*;
+
+    fn table(entries: Vec<(&str, ConfigValue)>) -> ConfigValue {
+        Table(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key.to_string(), value))
+                .collect(),
+        )
+    }
+
+    fn text(value: &str) -> ConfigValue {
+        String(value.to_string())
+    }
+
+    #[test]
+    fn test_nested_blocks() {
+        let document = parse(
+            "---\n# a pipeline\nnodes:\n  ramp:   # numbers\n    kind: ramp\n    step: 0.5\n\n\
+             \x20   len: 4\nedges:\n- [ramp, 'out']\n-\n  - a\n  - b\nsinks:\n  - kind: log\n    \
+             level: \"warn #1\"\n  - - 1\n    - true\n",
+        )
+        .unwrap();
+        assert_eq!(
+            document,
+            table(vec![
+                (
+                    "nodes",
+                    table(vec![(
+                        "ramp",
+                        table(vec![
+                            ("kind", text("ramp")),
+                            ("step", Float(0.5)),
+                            ("len", Integer(4))
+                        ])
+                    )])
+                ),
+                (
+                    "edges",
+                    List(vec![
+                        List(vec![text("ramp"), text("out")]),
+                        List(vec![text("a"), text("b")])
+                    ])
+                ),
+                (
+                    "sinks",
+                    List(vec![
+                        table(vec![("kind", text("log")), ("level", text("warn #1"))]),
+                        List(vec![Integer(1), Bool(true)])
+                    ])
+                )
+            ])
+        );
+    }
+
+    #[test]
+    fn test_scalars_and_flow() {
+        assert_eq!(
+            parse("a: -12\nb: 1e3\nc: 0x1F\nd: http://host:80/path\ne: 'it''s'\nf: \"a\\tb\"\n")
+                .unwrap(),
+            table(vec![
+                ("a", Integer(-12)),
+                ("b", Float(1000.0)),
+                ("c", Integer(31)),
+                ("d", text("http://host:80/path")),
+                ("e", text("it's")),
+                ("f", text("a\tb"))
+            ])
+        );
+        assert_eq!(
+            parse("{kind: ramp, len: 3, tags: [a, \"b, c\"], empty: {}}").unwrap(),
+            table(vec![
+                ("kind", text("ramp")),
+                ("len", Integer(3)),
+                ("tags", List(vec![text("a"), text("b, c")])),
+                ("empty", Table(vec![]))
+            ])
+        );
+        assert_eq!(parse("").unwrap(), Table(vec![]));
+        assert_eq!(
+            parse("- 1.5\n- .inf\n- 1.2.3\n").unwrap(),
+            List(vec![Float(1.5), Float(f64::INFINITY), text("1.2.3")])
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        let line = |text: &str| match parse(text) {
+            Err(ConfigError::Syntax { line, message }) => (line, message),
+            other => panic!("Expected a syntax error, got {:?}", other),
+        };
+        assert_eq!(
+            line("a: 1\n  b: 2\n"),
+            (2, "Unexpected indentation".to_string())
+        );
+        assert_eq!(line("a: 1\na: 2\n").1, "Duplicate key");
+        assert_eq!(line("a:\nb: 1\n"), (1, "Missing value".to_string()));
+        assert_eq!(line("a: [1, 2\n").1, "Expected , or ] in a flow sequence");
+        assert_eq!(line("a: ~\n").1, "Null values are not supported");
+        assert_eq!(
+            line("a: &anchor 1\n").1,
+            "Anchors, tags and block scalars are not supported"
+        );
+        assert_eq!(
+            line("a: |\n  text\n").1,
+            "Anchors, tags and block scalars are not supported"
+        );
+        assert_eq!(
+            line("a: 1\n---\nb: 2\n"),
+            (2, "Multiple documents are not supported".to_string())
+        );
+        assert_eq!(line("a:\n\t- 1\n").1, "Tabs cannot indent YAML");
+        assert_eq!(line("a: 1\n- 2\n"), (2, "Expected a key".to_string()));
+        assert_eq!(
+            line("a: 'open\n").1,
+            "Unterminated or trailing text after a quoted string"
+        );
+    }
+}
@@ -0,0 +1,319 @@
+//! control
+//!
+//! An HTTP API operating the pipelines of a long running service
+//!
+//! pipelines are registered under a name along with the handles they can be operated
+//! with, the server answers from a background thread with JSON (and DOT for topologies):
+//!
+//! - `GET /pipelines` lists the registered pipelines and their status
+//! - `GET /pipelines/{name}` gives the status of a pipeline
+//! - `GET /pipelines/{name}/metrics[?node={node}]` gives the metrics of a pipeline,
+//!   optionally only those labelled with the name of a node
+//! - `GET /pipelines/{name}/topology` gives the graph of a pipeline in the DOT language
+//! - `POST /pipelines/{name}/pause` and `POST /pipelines/{name}/resume`
+//! - `POST /pipelines/{name}/checkpoint` triggers a checkpoint and returns its id
+//!
+//! operations a pipeline was registered without answer `409 Conflict`
+
+use crate::checkpoint::CheckpointCoordinator;
+use crate::http;
+use crate::metrics::{MetricKey, MetricValue, MetricsRegistry};
+use crate::pipeline::PauseHandle;
+use crate::sinks::log::json_string;
+use std::collections::BTreeMap;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::sync::{Arc, Mutex};
+
+/// PipelineControl
+/// The handles a registered pipeline is operated and inspected with
+#[derive(Clone, Default)]
+pub struct PipelineControl {
+    pause: Option<PauseHandle>,
+    checkpoints: Option<CheckpointCoordinator>,
+    metrics: Option<MetricsRegistry>,
+    topology: Option<String>,
+}
+
+impl PipelineControl {
+    /// constructor for a pipeline without any operation
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// allow pausing and resuming the pipeline
+    pub fn with_pause(mut self, pause: &PauseHandle) -> Self {
+        self.pause = Some(pause.clone());
+        self
+    }
+    /// allow triggering checkpoints of the pipeline
+    pub fn with_checkpoints(mut self, checkpoints: &CheckpointCoordinator) -> Self {
+        self.checkpoints = Some(checkpoints.clone());
+        self
+    }
+    /// serve the metrics the pipeline reports to a registry
+    pub fn with_metrics(mut self, metrics: &MetricsRegistry) -> Self {
+        self.metrics = Some(metrics.clone());
+        self
+    }
+    /// serve the topology of the pipeline (such as `PipelineGraphRun::to_dot`)
+    pub fn with_topology(mut self, dot: &str) -> Self {
+        self.topology = Some(dot.to_string());
+        self
+    }
+}
+
+type Pipelines = Arc<Mutex<BTreeMap<String, PipelineControl>>>;
+
+/// ControlServer
+/// A background HTTP server operating registered pipelines, stopped when dropped
+pub struct ControlServer {
+    server: http::Server,
+    pipelines: Pipelines,
+}
+
+impl ControlServer {
+    /// start serving on an address (port 0 picks a free port)
+    pub fn bind<A: ToSocketAddrs>(address: A) -> Result<Self, &'static str> {
+        let pipelines: Pipelines = Arc::new(Mutex::new(BTreeMap::new()));
+        let served = pipelines.clone();
+        let server = http::Server::bind(
+            address,
+            "Failure to bind control server",
+            move |mut connection| {
+                if let Ok((method, path)) = http::read_request(&mut connection) {
+                    let (status, content_type, body) = route(&method, &path, &served);
+                    let _ = http::respond(&mut connection, status, content_type, &body);
+                }
+            },
+        )?;
+        Ok(Self { server, pipelines })
+    }
+    /// the address the server listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+    /// make a pipeline operable under a name
+    pub fn register(&self, name: &str, control: PipelineControl) -> Result<(), &'static str> {
+        let mut pipelines = self.pipelines.lock().unwrap();
+        if pipelines.contains_key(name) {
+            return Err("A pipeline with this name is already registered");
+        }
+        pipelines.insert(name.to_string(), control);
+        Ok(())
+    }
+    /// stop operating a pipeline, returning whether it was registered
+    pub fn unregister(&self, name: &str) -> bool {
+        self.pipelines.lock().unwrap().remove(name).is_some()
+    }
+}
+
+const JSON: &str = "application/json";
+
+type Reply = (&'static str, &'static str, String);
+
+fn error(status: &'static str, message: &str) -> Reply {
+    (
+        status,
+        JSON,
+        format!("{{\"error\":{}}}", json_string(message)),
+    )
+}
+
+fn optional(value: Option<impl ToString>) -> String {
+    value.map_or("null".to_string(), |value| value.to_string())
+}
+
+fn status(name: &str, control: &PipelineControl) -> String {
+    let checkpoints = control.checkpoints.as_ref();
+    format!(
+        "{{\"name\":{},\"paused\":{},\"requested_checkpoint\":{},\"completed_checkpoint\":{},\
+         \"metrics\":{},\"topology\":{}}}",
+        json_string(name),
+        optional(control.pause.as_ref().map(PauseHandle::is_paused)),
+        optional(checkpoints.and_then(CheckpointCoordinator::requested)),
+        optional(checkpoints.and_then(CheckpointCoordinator::last_completed)),
+        control.metrics.is_some(),
+        control.topology.is_some()
+    )
+}
+
+fn number(value: f64) -> String {
+    if value.is_finite() {
+        format!("{:?}", value)
+    } else {
+        "null".to_string()
+    }
+}
+
+fn metric(key: &MetricKey, value: &MetricValue) -> String {
+    let labels: Vec<String> = key
+        .labels
+        .iter()
+        .map(|(name, value)| format!("{}:{}", json_string(name), json_string(value)))
+        .collect();
+    let value = match value {
+        MetricValue::Counter(count) => format!("\"counter\":{}", count),
+        MetricValue::Gauge(gauge) => format!("\"gauge\":{}", number(*gauge)),
+        MetricValue::Histogram(histogram) => format!(
+            "\"histogram\":{{\"count\":{},\"sum\":{},\"min\":{},\"max\":{},\"mean\":{},\
+             \"p50\":{},\"p99\":{}}}",
+            histogram.count(),
+            histogram.sum(),
+            optional(histogram.min()),
+            optional(histogram.max()),
+            optional(histogram.mean().map(number)),
+            optional(histogram.value_at_quantile(0.5)),
+            optional(histogram.value_at_quantile(0.99))
+        ),
+    };
+    format!(
+        "{{\"name\":{},\"labels\":{{{}}},{}}}",
+        json_string(&key.name),
+        labels.join(","),
+        value
+    )
+}
+
+fn route(method: &str, target: &str, pipelines: &Pipelines) -> Reply {
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let segments: Vec<&str> = path.split('/').filter(|s| !s.is_empty()).collect();
+    let pipelines = pipelines.lock().unwrap();
+    let (name, action) = match segments.as_slice() {
+        ["pipelines"] if method == "GET" => {
+            let statuses: Vec<String> = pipelines
+                .iter()
+                .map(|(name, control)| status(name, control))
+                .collect();
+            return ("200 OK", JSON, format!("[{}]", statuses.join(",")));
+        }
+        ["pipelines", name] => (*name, ""),
+        ["pipelines", name, action] => (*name, *action),
+        _ => return error("404 Not Found", "Unknown resource"),
+    };
+    let Some(control) = pipelines.get(name) else {
+        return error("404 Not Found", "Unknown pipeline");
+    };
+    match (method, action) {
+        ("GET", "") => ("200 OK", JSON, status(name, control)),
+        ("GET", "metrics") => {
+            let Some(registry) = &control.metrics else {
+                return error("409 Conflict", "Pipeline reports no metrics");
+            };
+            let node = query
+                .split('&')
+                .find_map(|parameter| parameter.strip_prefix("node="));
+            let metrics: Vec<String> = registry
+                .snapshot()
+                .iter()
+                .filter(|(key, _)| {
+                    node.is_none_or(|node| key.labels.iter().any(|(_, value)| value == node))
+                })
+                .map(|(key, value)| metric(key, value))
+                .collect();
+            ("200 OK", JSON, format!("[{}]", metrics.join(",")))
+        }
+        ("GET", "topology") => match &control.topology {
+            Some(dot) => ("200 OK", "text/vnd.graphviz", dot.clone()),
+            None => error("409 Conflict", "Pipeline has no topology"),
+        },
+        ("POST", "pause") | ("POST", "resume") => {
+            let Some(pause) = &control.pause else {
+                return error("409 Conflict", "Pipeline cannot be paused");
+            };
+            if action == "pause" {
+                pause.pause();
+            } else {
+                pause.resume();
+            }
+            ("200 OK", JSON, status(name, control))
+        }
+        ("POST", "checkpoint") => match &control.checkpoints {
+            Some(checkpoints) => (
+                "202 Accepted",
+                JSON,
+                format!("{{\"checkpoint\":{}}}", checkpoints.trigger()),
+            ),
+            None => error("409 Conflict", "Pipeline takes no checkpoints"),
+        },
+        (_, "" | "metrics" | "topology" | "pause" | "resume" | "checkpoint") => {
+            error("405 Method Not Allowed", "Method not allowed")
+        }
+        _ => error("404 Not Found", "Unknown resource"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    fn request(server: &ControlServer, method: &str, path: &str) -> (String, String) {
+        let mut connection = TcpStream::connect(server.local_addr()).unwrap();
+        write!(connection, "{} {} HTTP/1.1\r\n\r\n", method, path).unwrap();
+        let mut response = String::new();
+        connection.read_to_string(&mut response).unwrap();
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        let status = head.lines().next().unwrap()[9..].to_string();
+        (status, body.to_string())
+    }
+
+    #[test]
+    fn test_operate_pipeline() {
+        let server = ControlServer::bind("127.0.0.1:0").unwrap();
+        let (pause, checkpoints) = (PauseHandle::new(), CheckpointCoordinator::new());
+        let registry = MetricsRegistry::new();
+        registry.increment("pipe_items_total", &[("pipe", "parse")], 4);
+        registry.increment("pipe_items_total", &[("pipe", "load")], 2);
+        let control = PipelineControl::new()
+            .with_pause(&pause)
+            .with_checkpoints(&checkpoints)
+            .with_metrics(&registry)
+            .with_topology("digraph pipeline {\n}\n");
+        server.register("ingest", control.clone()).unwrap();
+        assert!(server.register("ingest", control).is_err());
+        let (code, body) = request(&server, "GET", "/pipelines");
+        assert_eq!(code, "200 OK");
+        assert_eq!(
+            body,
+            "[{\"name\":\"ingest\",\"paused\":false,\"requested_checkpoint\":null,\
+             \"completed_checkpoint\":null,\"metrics\":true,\"topology\":true}]"
+        );
+        let (code, _) = request(&server, "POST", "/pipelines/ingest/pause");
+        assert_eq!((code.as_str(), pause.is_paused()), ("200 OK", true));
+        request(&server, "POST", "/pipelines/ingest/resume");
+        assert!(!pause.is_paused());
+        let (code, body) = request(&server, "POST", "/pipelines/ingest/checkpoint");
+        assert_eq!(
+            (code.as_str(), body.as_str()),
+            ("202 Accepted", "{\"checkpoint\":1}")
+        );
+        assert_eq!(checkpoints.requested(), Some(1));
+        let (_, body) = request(&server, "GET", "/pipelines/ingest/metrics?node=parse");
+        assert_eq!(
+            body,
+            "[{\"name\":\"pipe_items_total\",\"labels\":{\"pipe\":\"parse\"},\"counter\":4}]"
+        );
+        let (_, body) = request(&server, "GET", "/pipelines/ingest/topology");
+        assert_eq!(body, "digraph pipeline {\n}\n");
+    }
+
+    #[test]
+    fn test_errors() {
+        let server = ControlServer::bind("127.0.0.1:0").unwrap();
+        server.register("bare", PipelineControl::new()).unwrap();
+        assert_eq!(
+            request(&server, "GET", "/pipelines/other").0,
+            "404 Not Found"
+        );
+        assert_eq!(request(&server, "GET", "/elsewhere").0, "404 Not Found");
+        assert_eq!(
+            request(&server, "GET", "/pipelines/bare/pause").0,
+            "405 Method Not Allowed"
+        );
+        let (code, body) = request(&server, "POST", "/pipelines/bare/pause");
+        assert_eq!(code, "409 Conflict");
+        assert_eq!(body, "{\"error\":\"Pipeline cannot be paused\"}");
+        assert!(server.unregister("bare"));
+        assert_eq!(request(&server, "GET", "/pipelines").1, "[]");
+    }
+}
@@ -1,10 +1,110 @@
 use super::Source;
+use bytes::Bytes;
 use futures::stream;
 use futures::Stream;
+use num_complex::{Complex32, Complex64};
 use std::collections::HashMap;
+use std::sync::Arc;
+
+/// time
+/// Sub module for the time bases of timestamp blobs
+pub mod time;
+
+/// diff
+/// Sub module for structural comparisons between DataBuckets
+pub mod diff;
+
+/// rows
+/// Sub module for streaming buckets record by record
+pub mod rows;
+
+/// encoding
+/// Sub module for the binary encoding of blob elements
+pub mod encoding;
+
+/// compression
+/// Sub module for compressed blob storage
+pub mod compression;
+
+/// chunked
+/// Sub module for segmented blob storage
+pub mod chunked;
+
+/// schema
+/// Sub module for the declared schemas of blob and bucket streams
+pub mod schema;
+
+/// registry
+/// Sub module for named MetaData schemas
+pub mod registry;
+
+/// provenance
+/// Sub module for the derivation history of blobs
+pub mod provenance;
+
+/// units
+/// Sub module for the algebra of MetaData units
+pub mod units;
+
+/// conversion
+/// Sub module for the scale factors between units
+pub mod conversion;
+
+/// mask
+/// Sub module for the missing values of blobs
+pub mod mask;
+
+/// encryption
+/// Sub module for encrypted blob storage and bucket encodings
+#[cfg(feature = "encryption")]
+pub mod encryption;
+
+/// checksum
+/// Sub module for content hashes of blob data
+pub mod checksum;
+
+/// footprint
+/// Sub module for memory accounting of blobs and buckets
+pub mod footprint;
+
+/// spill
+/// Sub module for moving blob data exceeding a memory budget to disk
+pub mod spill;
+
+/// lazy
+/// Sub module for blobs loaded on first access
+pub mod lazy;
+
+/// wire
+/// Sub module for the binary encoding of whole blobs and buckets
+pub mod wire;
+
+/// half
+/// Sub module for the 16 bit floats of `Float16` and `BFloat16` blobs
+pub mod half;
+
+/// tensor
+/// Sub module for multi-dimensional blobs with named axes
+pub mod tensor;
+
+/// sort
+/// Sub module for reordering buckets by the units of a key blob
+pub mod sort;
+
+/// gather
+/// Sub module for following the handle links between blobs
+pub mod gather;
+
+use checksum::Checksum;
+use half::{BF16, F16};
+use lazy::DeferredBlob;
+use mask::ValidityMask;
+use provenance::ProvenanceEntry;
+use time::TimeBase;
 
 /// LinkType
 /// An enum for each type of relationship between two DataBlobs
+#[derive(Clone, Debug, PartialEq)]
 pub enum LinkType {
     /// Each primary unit of the data corresponds to one unit of the linked data
     OneToOne,
@@ -16,6 +116,7 @@ pub enum LinkType {
 
 /// Link
 /// Structure defining the relationship between two DataBlobs
+#[derive(Clone, Debug, PartialEq)]
 pub struct Link {
     /// nature of the link between the data
     pub nature: LinkType,
@@ -27,6 +128,7 @@ pub struct Link {
 
 /// MetaData
 /// A structure describing the data of a DataBlob
+#[derive(Clone, Debug, Default, PartialEq)]
 pub struct MetaData {
     /// name of the data array
     pub name: String,
@@ -38,15 +140,27 @@ pub struct MetaData {
     pub dimensions: Vec<usize>,
     /// dimensions of a unit size of the data
     pub unitary_dimensions: Vec<usize>,
+    /// names of the full dimensions when the data is a tensor, in the same order
+    pub axes: Vec<String>,
     /// links to other data in the same bucket
     pub links: Vec<Link>,
+    /// epoch and resolution of the data if it holds timestamps
+    pub time_base: Option<TimeBase>,
+    /// samples per second of data sampled at a fixed rate, one unit per sample
+    pub sample_rate: Option<f64>,
+    /// operations the data was derived through, oldest first
+    pub provenance: Vec<ProvenanceEntry>,
+    /// hash of the data when it was last checksummed
+    pub checksum: Option<Checksum>,
 }
 
 /// DataBlob
 /// A structure holding an array of data with its inherent meta-data
+#[derive(Clone)]
 pub struct DataBlob<T> {
     data: Vec<T>,
     meta: MetaData,
+    mask: Option<ValidityMask>,
 }
 
 impl<T> DataBlob<T> {
@@ -55,6 +169,7 @@ impl<T> DataBlob<T> {
         Self {
             data: new_data,
             meta: new_meta,
+            mask: None,
         }
     }
     /// get the underlying data immutably
@@ -73,6 +188,86 @@ impl<T> DataBlob<T> {
     pub fn get_mut_meta_data(&mut self) -> &mut MetaData {
         &mut self.meta
     }
+    /// number of elements in one unit of the data
+    pub fn unit_size(&self) -> usize {
+        self.meta
+            .unitary_dimensions
+            .iter()
+            .product::<usize>()
+            .max(1)
+    }
+    /// number of complete units held in the data
+    pub fn unit_count(&self) -> usize {
+        self.data.len() / self.unit_size()
+    }
+}
+
+impl<T: Clone> DataBlob<T> {
+    /// copy a range of units into a new blob with the same meta data
+    ///
+    /// the dimensions of the new blob are the unit count followed by the unitary
+    /// dimensions (collapsed to just the unit count for scalar units)
+    pub fn slice_units(&self, units: std::ops::Range<usize>) -> Option<Self> {
+        let size = self.unit_size();
+        let data = self
+            .data
+            .get(units.start * size..units.end * size)?
+            .to_vec();
+        let mut meta = self.meta.clone();
+        meta.dimensions = vec![units.len()];
+        if size > 1 {
+            meta.dimensions.extend(self.meta.unitary_dimensions.iter());
+        }
+        meta.record(
+            "slice_units",
+            &[
+                ("start", units.start.to_string()),
+                ("end", units.end.to_string()),
+            ],
+        );
+        let mask = self
+            .mask
+            .as_ref()
+            .map(|mask| mask.slice(units.start * size..units.end * size));
+        Some(Self { data, meta, mask })
+    }
+}
+
+impl DataBlob<i64> {
+    /// construct a timestamp blob by parsing RFC 3339 strings with the meta data's time base
+    pub fn parse_timestamps(texts: &[&str], new_meta: MetaData) -> Result<Self, &'static str> {
+        let base = new_meta.time_base.ok_or("Meta data has no time base")?;
+        let data = texts
+            .iter()
+            .map(|text| base.parse(text))
+            .collect::<Result<Vec<i64>, &'static str>>()?;
+        Ok(Self::new(data, new_meta))
+    }
+    /// format the underlying data as RFC 3339 strings using the meta data's time base
+    pub fn format_timestamps(&self) -> Result<Vec<String>, &'static str> {
+        let base = self.meta.time_base.ok_or("Meta data has no time base")?;
+        Ok(self.data.iter().map(|ticks| base.format(*ticks)).collect())
+    }
+}
+
+impl DataBlob<Bytes> {
+    /// size in bytes of one record as described by the unitary dimensions
+    /// (None when the unitary dimensions are empty and records have variable size)
+    pub fn record_size(&self) -> Option<usize> {
+        if self.meta.unitary_dimensions.is_empty() {
+            return None;
+        }
+        Some(self.meta.unitary_dimensions.iter().product())
+    }
+    /// check that every payload matches the record size of the blob
+    pub fn validate_records(&self) -> Result<(), &'static str> {
+        match self.record_size() {
+            Some(size) if self.data.iter().any(|record| record.len() != size) => {
+                Err("Record size does not match unitary dimensions")
+            }
+            _ => Ok(()),
+        }
+    }
 }
 
 impl<T: Clone + 'static> Source<T> for DataBlob<T> {
@@ -83,6 +278,7 @@ impl<T: Clone + 'static> Source<T> for DataBlob<T> {
 
 /// DataBucketBlobs
 /// An enum wrapping for all the different primitive typed DataBlobs
+#[derive(Clone)]
 pub enum DataBucketBlob {
     Bool(DataBlob<bool>),
     Char(DataBlob<char>),
@@ -100,9 +296,27 @@ pub enum DataBucketBlob {
     USize(DataBlob<usize>),
     Float32(DataBlob<f32>),
     Float64(DataBlob<f64>),
+    Float16(DataBlob<F16>),
+    BFloat16(DataBlob<BF16>),
     Str(DataBlob<String>),
+    Timestamp(DataBlob<i64>),
+    Complex32(DataBlob<Complex32>),
+    Complex64(DataBlob<Complex64>),
+    Bytes(DataBlob<Bytes>),
 }
 
+/// expand a macro with the list of every DataBucketBlob variant
+macro_rules! for_each_variant {
+    ($m:ident) => {
+        $m!(
+            Bool, Char, Int8, U8, Int16, U16, Int32, U32, Int64, U64, Int128, U128, ISize, USize,
+            Float32, Float64, Float16, BFloat16, Str, Timestamp, Complex32, Complex64, Bytes
+        );
+    };
+}
+
+pub(crate) use for_each_variant;
+
 macro_rules! meta_data_unwrap {
   ($($x:ident),*) => {
     pub fn get_meta_data(&self) -> &MetaData {
@@ -123,21 +337,101 @@ macro_rules! mut_meta_data_unwrap {
   }
 }
 
+macro_rules! unit_count_unwrap {
+  ($($x:ident),*) => {
+    /// number of complete units held in the blob
+    pub fn unit_count(&self) -> usize {
+      match self {
+        $( DataBucketBlob::$x(blob) => blob.unit_count(), )*
+      }
+    }
+  }
+}
+
+macro_rules! slice_units_unwrap {
+  ($($x:ident),*) => {
+    /// copy a range of units into a new blob of the same type
+    pub fn slice_units(&self, units: std::ops::Range<usize>) -> Option<DataBucketBlob> {
+      match self {
+        $( DataBucketBlob::$x(blob) => blob.slice_units(units).map(DataBucketBlob::$x), )*
+      }
+    }
+  }
+}
+
 impl DataBucketBlob {
-    meta_data_unwrap!(
-        Bool, Char, Int8, U8, Int16, U16, Int32, U32, Int64, U64, Int128, U128, ISize, USize,
-        Float32, Float64, Str
-    );
-    mut_meta_data_unwrap!(
-        Bool, Char, Int8, U8, Int16, U16, Int32, U32, Int64, U64, Int128, U128, ISize, USize,
-        Float32, Float64, Str
-    );
+    for_each_variant!(meta_data_unwrap);
+    for_each_variant!(mut_meta_data_unwrap);
+    for_each_variant!(unit_count_unwrap);
+    for_each_variant!(slice_units_unwrap);
+}
+
+/// BlobType
+/// A trait for the primitive types that can be held by a DataBucketBlob
+pub trait BlobType: Sized {
+    /// wrap a typed blob into its DataBucketBlob variant
+    fn wrap(blob: DataBlob<Self>) -> DataBucketBlob;
+    /// get the typed blob out of a DataBucketBlob if it holds this type
+    fn unwrap_ref(blob: &DataBucketBlob) -> Option<&DataBlob<Self>>;
+}
+
+macro_rules! blob_type {
+  ($($t:ty => $x:ident),*) => {
+    $( impl BlobType for $t {
+      fn wrap(blob: DataBlob<Self>) -> DataBucketBlob {
+        DataBucketBlob::$x(blob)
+      }
+      fn unwrap_ref(blob: &DataBucketBlob) -> Option<&DataBlob<Self>> {
+        match blob {
+          DataBucketBlob::$x(b) => Some(b),
+          _ => None,
+        }
+      }
+    } )*
+  }
+}
+
+blob_type!(
+    bool => Bool, char => Char, i8 => Int8, u8 => U8, i16 => Int16, u16 => U16,
+    i32 => Int32, u32 => U32, u64 => U64, i128 => Int128, u128 => U128,
+    isize => ISize, usize => USize, f32 => Float32, f64 => Float64, F16 => Float16,
+    BF16 => BFloat16, String => Str, Complex32 => Complex32, Complex64 => Complex64, Bytes => Bytes
+);
+
+/// i64 data wraps into `Int64` blobs but can be read back from `Timestamp` blobs as well
+impl BlobType for i64 {
+    fn wrap(blob: DataBlob<Self>) -> DataBucketBlob {
+        DataBucketBlob::Int64(blob)
+    }
+    fn unwrap_ref(blob: &DataBucketBlob) -> Option<&DataBlob<Self>> {
+        match blob {
+            DataBucketBlob::Int64(b) | DataBucketBlob::Timestamp(b) => Some(b),
+            _ => None,
+        }
+    }
 }
 
 /// DataBucket
 /// A flexible structure for holding heterogeneous data
+///
+/// blobs are stored behind reference counts so that snapshots share them,
+/// mutable access copies a blob first if a snapshot still refers to it
+///
+/// buckets can be nested into named groups, blobs and groups share the same namespace
+/// and nested blobs are addressed with `/` separated paths
 pub struct DataBucket {
-    data: HashMap<String, DataBucketBlob>,
+    data: HashMap<String, Arc<DataBucketBlob>>,
+    deferred: HashMap<String, DeferredBlob>,
+    groups: HashMap<String, DataBucket>,
+}
+
+/// separator between group names in a bucket path
+pub const PATH_SEPARATOR: char = '/';
+
+/// the blob of a fallible accessor for its infallible counterpart, logging a failure to load
+fn logged<T>(blob_name: &str, blob: Result<T, &'static str>) -> Option<T> {
+    blob.inspect_err(|error| log::warn!("Failure to load blob {}: {}", blob_name, error))
+        .ok()
 }
 
 impl DataBucket {
@@ -145,24 +439,344 @@ impl DataBucket {
     pub fn new() -> Self {
         Self {
             data: HashMap::new(),
+            deferred: HashMap::new(),
+            groups: HashMap::new(),
         }
     }
-    /// get a data blob
+    /// get a data blob (spilled and lazy blobs are loaded, a blob that fails to load is
+    /// logged and reported missing, see `try_get_blob`)
     pub fn get_blob(&self, blob_name: &String) -> Option<&DataBucketBlob> {
-        self.data.get(blob_name)
+        match self.data.get(blob_name) {
+            Some(blob) => Some(blob.as_ref()),
+            None => logged(blob_name, self.try_get_blob(blob_name))?,
+        }
+    }
+    /// get a data blob, returning the failure of a spilled or lazy blob that fails to load
+    pub fn try_get_blob(&self, blob_name: &str) -> Result<Option<&DataBucketBlob>, &'static str> {
+        Ok(self.try_get_arc_blob(blob_name)?.map(|blob| blob.as_ref()))
+    }
+    fn try_get_arc_blob(
+        &self,
+        blob_name: &str,
+    ) -> Result<Option<&Arc<DataBucketBlob>>, &'static str> {
+        match self.data.get(blob_name) {
+            Some(blob) => Ok(Some(blob)),
+            None => self
+                .deferred
+                .get(blob_name)
+                .map(|blob| blob.load())
+                .transpose(),
+        }
+    }
+    fn get_arc_blob(&self, blob_name: &str) -> Option<&Arc<DataBucketBlob>> {
+        logged(blob_name, self.try_get_arc_blob(blob_name))?
+    }
+    fn taken(&self, name: &str) -> bool {
+        self.data.contains_key(name)
+            || self.deferred.contains_key(name)
+            || self.groups.contains_key(name)
     }
     /// add a blob
     pub fn add_blob(&mut self, new_blob: DataBucketBlob) -> Option<DataBucketBlob> {
+        self.add_shared_blob(Arc::new(new_blob))
+            .map(|blob| Arc::try_unwrap(blob).unwrap_or_else(|shared| (*shared).clone()))
+    }
+    /// get a shared handle on a data blob
+    pub fn get_shared_blob(&self, blob_name: &str) -> Option<Arc<DataBucketBlob>> {
+        self.get_arc_blob(blob_name).cloned()
+    }
+    /// add a blob without copying it, shared with other holders of the handle
+    pub fn add_shared_blob(
+        &mut self,
+        new_blob: Arc<DataBucketBlob>,
+    ) -> Option<Arc<DataBucketBlob>> {
         let name = &new_blob.get_meta_data().name;
-        if self.data.contains_key(name) {
+        if self.taken(name) {
             return Some(new_blob);
         }
         self.data.insert(name.clone(), new_blob);
         None
     }
-    // remove a blob
+    // remove a blob (a spilled or lazy blob that fails to load stays in the bucket and is
+    // logged and reported missing, see `try_pop_blob`)
     pub fn pop_blob(&mut self, name: String) -> Option<DataBucketBlob> {
-      self.data.remove(&name)
+        logged(&name, self.try_pop_blob(&name))?
+    }
+    /// remove a blob, returning the failure of a spilled or lazy blob that fails to load
+    /// and leaving it in the bucket
+    pub fn try_pop_blob(&mut self, name: &str) -> Result<Option<DataBucketBlob>, &'static str> {
+        let blob = match self.data.remove(name) {
+            Some(blob) => blob,
+            None => match self.deferred.get(name) {
+                Some(deferred) => {
+                    let blob = deferred.load()?.clone();
+                    self.deferred.remove(name);
+                    blob
+                }
+                None => return Ok(None),
+            },
+        };
+        Ok(Some(
+            Arc::try_unwrap(blob).unwrap_or_else(|shared| (*shared).clone()),
+        ))
+    }
+    /// number of blobs in the bucket, counting the spilled and lazy blobs whether or not
+    /// they load
+    pub fn len(&self) -> usize {
+        self.data.len() + self.deferred.len()
+    }
+    /// whether the bucket holds no blobs
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// whether the bucket holds a blob with the given name, loaded or not (see
+    /// `try_get_blob` to tell whether it loads)
+    pub fn contains(&self, blob_name: &str) -> bool {
+        self.data.contains_key(blob_name) || self.deferred.contains_key(blob_name)
+    }
+    /// names of all the blobs in the bucket (in arbitrary order)
+    pub fn blob_names(&self) -> impl Iterator<Item = &String> {
+        self.data.keys().chain(self.deferred.keys())
+    }
+    /// iterate over (name, blob) pairs immutably (in arbitrary order, spilled and lazy
+    /// blobs are loaded, the blobs that fail to load are logged and skipped, see `try_iter`)
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &DataBucketBlob)> {
+        self.try_iter()
+            .filter_map(|(name, blob)| Some((name, logged(name, blob)?)))
+    }
+    /// iterate over every blob of the bucket with the failure of the spilled and lazy blobs
+    /// that fail to load (in arbitrary order)
+    pub fn try_iter(
+        &self,
+    ) -> impl Iterator<Item = (&String, Result<&DataBucketBlob, &'static str>)> {
+        self.data
+            .iter()
+            .map(|(name, blob)| (name, Ok(blob.as_ref())))
+            .chain(
+                self.deferred
+                    .iter()
+                    .map(|(name, blob)| (name, blob.load().map(|blob| blob.as_ref()))),
+            )
+    }
+    /// iterate over (name, blob) pairs mutably (in arbitrary order)
+    ///
+    /// renaming a blob through its meta data does not re-key it in the bucket, spilled
+    /// and lazy blobs are moved to memory first
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = (&String, &mut DataBucketBlob)> {
+        self.restore_deferred();
+        self.data
+            .iter_mut()
+            .map(|(name, blob)| (name, Arc::make_mut(blob)))
+    }
+    /// iterate over the blobs whose meta data satisfies a predicate (in arbitrary order,
+    /// only the matching spilled and lazy blobs are loaded, those that fail to load are
+    /// logged and skipped, see `try_find`)
+    pub fn find<'a, F>(&'a self, predicate: F) -> impl Iterator<Item = &'a DataBucketBlob> + 'a
+    where
+        F: Fn(&MetaData) -> bool + 'a,
+    {
+        self.try_find(predicate)
+            .filter_map(|(name, blob)| logged(name, blob))
+    }
+    /// iterate over the blobs whose meta data satisfies a predicate with their names and the
+    /// failure of the matching spilled and lazy blobs that fail to load (in arbitrary order)
+    pub fn try_find<'a, F>(
+        &'a self,
+        predicate: F,
+    ) -> impl Iterator<Item = (&'a String, Result<&'a DataBucketBlob, &'static str>)> + 'a
+    where
+        F: Fn(&MetaData) -> bool + 'a,
+    {
+        let predicate = std::rc::Rc::new(predicate);
+        let deferred_predicate = predicate.clone();
+        let deferred = self
+            .deferred
+            .iter()
+            .filter(move |(_, blob)| deferred_predicate(&blob.meta))
+            .map(|(name, blob)| (name, blob.load().map(|blob| blob.as_ref())));
+        self.data
+            .iter()
+            .filter(move |(_, blob)| predicate(blob.get_meta_data()))
+            .map(|(name, blob)| (name, Ok(blob.as_ref())))
+            .chain(deferred)
+    }
+    /// iterate over the blobs expressed in the given units
+    pub fn blobs_with_units<'a>(
+        &'a self,
+        units: &'a str,
+    ) -> impl Iterator<Item = &'a DataBucketBlob> + 'a {
+        self.find(move |meta| meta.units.as_deref() == Some(units))
+    }
+    /// iterate over the blobs holding a link to the named blob
+    pub fn blobs_linking_to<'a>(
+        &'a self,
+        linkee: &'a str,
+    ) -> impl Iterator<Item = &'a DataBucketBlob> + 'a {
+        self.find(move |meta| meta.links.iter().any(|link| link.linkee == linkee))
+    }
+    /// add a nested group (returns the group back if the name is already taken)
+    pub fn add_group(&mut self, name: &str, group: DataBucket) -> Option<DataBucket> {
+        if self.taken(name) {
+            return Some(group);
+        }
+        self.groups.insert(name.to_string(), group);
+        None
+    }
+    /// get a nested group immutably
+    pub fn get_group(&self, name: &str) -> Option<&DataBucket> {
+        self.groups.get(name)
+    }
+    /// get a nested group mutably
+    pub fn get_mut_group(&mut self, name: &str) -> Option<&mut DataBucket> {
+        self.groups.get_mut(name)
+    }
+    /// remove a nested group
+    pub fn pop_group(&mut self, name: &str) -> Option<DataBucket> {
+        self.groups.remove(name)
+    }
+    /// names of the nested groups of the bucket (in arbitrary order)
+    pub fn group_names(&self) -> impl Iterator<Item = &String> {
+        self.groups.keys()
+    }
+    /// iterate over (name, group) pairs immutably (in arbitrary order)
+    pub fn groups(&self) -> impl Iterator<Item = (&String, &DataBucket)> {
+        self.groups.iter()
+    }
+    /// get a group from a path of group names such as `sim/forces`
+    pub fn get_group_path(&self, path: &str) -> Option<&DataBucket> {
+        path.split(PATH_SEPARATOR)
+            .filter(|name| !name.is_empty())
+            .try_fold(self, |bucket, name| bucket.get_group(name))
+    }
+    /// get a blob from a path such as `sim/forces/x`
+    pub fn get_path(&self, path: &str) -> Option<&DataBucketBlob> {
+        match path.rsplit_once(PATH_SEPARATOR) {
+            Some((groups, name)) => self.get_group_path(groups)?.get_blob(&name.to_string()),
+            None => self.get_blob(&path.to_string()),
+        }
+    }
+    /// take a cheap immutable snapshot of the bucket sharing the blob data (spilled and
+    /// lazy blobs are loaded, those that fail to load are logged and left out, see
+    /// `try_snapshot`)
+    pub fn snapshot(&self) -> DataBucketSnapshot {
+        DataBucketSnapshot {
+            data: Arc::new(
+                self.blob_names()
+                    .filter_map(|name| Some((name.clone(), self.get_arc_blob(name)?.clone())))
+                    .collect(),
+            ),
+            groups: Arc::new(
+                self.groups
+                    .iter()
+                    .map(|(name, group)| (name.clone(), group.snapshot()))
+                    .collect(),
+            ),
+        }
+    }
+    /// take a snapshot of the bucket, returning the failure of the first spilled or lazy blob
+    /// that fails to load
+    pub fn try_snapshot(&self) -> Result<DataBucketSnapshot, &'static str> {
+        Ok(DataBucketSnapshot {
+            data: Arc::new(
+                self.blob_names()
+                    .filter_map(|name| {
+                        let blob = self.try_get_arc_blob(name).transpose()?;
+                        Some(blob.map(|blob| (name.clone(), blob.clone())))
+                    })
+                    .collect::<Result<_, _>>()?,
+            ),
+            groups: Arc::new(
+                self.groups
+                    .iter()
+                    .map(|(name, group)| Ok((name.clone(), group.try_snapshot()?)))
+                    .collect::<Result<_, &'static str>>()?,
+            ),
+        })
+    }
+}
+
+impl Clone for DataBucket {
+    /// deep copy of every blob in the bucket, spilled and lazy blobs share their files and
+    /// loaders
+    fn clone(&self) -> Self {
+        Self {
+            data: self
+                .data
+                .iter()
+                .map(|(name, blob)| (name.clone(), Arc::new(blob.as_ref().clone())))
+                .collect(),
+            deferred: self.deferred.clone(),
+            groups: self.groups.clone(),
+        }
+    }
+}
+
+impl Default for DataBucket {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// DataBucketSnapshot
+/// An immutable view of a DataBucket that can be cloned cheaply and shared across threads
+#[derive(Clone)]
+pub struct DataBucketSnapshot {
+    data: Arc<HashMap<String, Arc<DataBucketBlob>>>,
+    groups: Arc<HashMap<String, DataBucketSnapshot>>,
+}
+
+impl DataBucketSnapshot {
+    /// get a data blob
+    pub fn get_blob(&self, blob_name: &str) -> Option<&DataBucketBlob> {
+        self.data.get(blob_name).map(|blob| blob.as_ref())
+    }
+    /// number of blobs in the snapshot
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+    /// whether the snapshot holds no blobs
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+    /// whether the snapshot holds a blob with the given name
+    pub fn contains(&self, blob_name: &str) -> bool {
+        self.data.contains_key(blob_name)
+    }
+    /// names of all the blobs in the snapshot (in arbitrary order)
+    pub fn blob_names(&self) -> impl Iterator<Item = &String> {
+        self.data.keys()
+    }
+    /// iterate over (name, blob) pairs (in arbitrary order)
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &DataBucketBlob)> {
+        self.data.iter().map(|(name, blob)| (name, blob.as_ref()))
+    }
+    /// get a nested group
+    pub fn get_group(&self, name: &str) -> Option<&DataBucketSnapshot> {
+        self.groups.get(name)
+    }
+    /// get a blob from a path such as `sim/forces/x`
+    pub fn get_path(&self, path: &str) -> Option<&DataBucketBlob> {
+        let mut names: Vec<&str> = path
+            .split(PATH_SEPARATOR)
+            .filter(|name| !name.is_empty())
+            .collect();
+        let name = names.pop()?;
+        names
+            .into_iter()
+            .try_fold(self, |snapshot, group| snapshot.get_group(group))?
+            .get_blob(name)
+    }
+    /// turn the snapshot back into a bucket (blob data is only copied once mutated)
+    pub fn to_bucket(&self) -> DataBucket {
+        DataBucket {
+            data: self.data.as_ref().clone(),
+            deferred: HashMap::new(),
+            groups: self
+                .groups
+                .iter()
+                .map(|(name, group)| (name.clone(), group.to_bucket()))
+                .collect(),
+        }
     }
 }
 
@@ -178,7 +792,12 @@ mod tests {
             units: None,
             unitary_dimensions: vec![1],
             dimensions: vec![10],
+            axes: Vec::new(),
             links: Vec::new(),
+            time_base: None,
+            sample_rate: None,
+            provenance: Vec::new(),
+            checksum: None,
         };
         let data: Vec<i8> = (0..10).collect();
         DataBlob::new(data, meta)
@@ -200,7 +819,12 @@ mod tests {
             units: None,
             unitary_dimensions: vec![1],
             dimensions: vec![10],
+            axes: Vec::new(),
             links: Vec::new(),
+            time_base: None,
+            sample_rate: None,
+            provenance: Vec::new(),
+            checksum: None,
         };
         let data: Vec<i8> = (0..10).collect();
         let data: Vec<f64> = data.into_iter().map(|x| f64::from(x) / 10.0_f64).collect();
@@ -229,7 +853,7 @@ mod tests {
         let mut bucket = DataBucket::new();
         bucket.add_blob(DataBucketBlob::Int8(make_int_blob()));
         let blob = bucket.pop_blob("Test data".to_string());
-        assert!(!blob.is_none(), "Popped back None from data bucket");
+        assert!(blob.is_some(), "Popped back None from data bucket");
         let blob = blob.unwrap();
         let blob = match blob {
             DataBucketBlob::Int8(b) => b,
@@ -239,4 +863,230 @@ mod tests {
             assert_eq!(idx, *val, "Failure to pop data from bucket");
         }
     }
+
+    #[test]
+    fn test_timestamp_blob() {
+        let meta = MetaData {
+            name: "time".to_string(),
+            unitary_dimensions: vec![1],
+            dimensions: vec![2],
+            time_base: Some(TimeBase::unix(time::TimeResolution::Seconds)),
+            ..Default::default()
+        };
+        let texts = ["2022-01-01T00:00:00Z", "2022-01-01T00:00:10Z"];
+        let blob = DataBlob::parse_timestamps(&texts, meta).unwrap();
+        assert_eq!(blob.get_data()[1] - blob.get_data()[0], 10);
+        assert_eq!(blob.format_timestamps().unwrap(), texts);
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(DataBucketBlob::Timestamp(blob));
+        match bucket.get_blob(&"time".to_string()) {
+            Some(DataBucketBlob::Timestamp(b)) => assert!(b.get_meta_data().time_base.is_some()),
+            _ => panic!("Could not match timestamp blob"),
+        }
+    }
+
+    #[test]
+    fn test_complex_data_blob() {
+        let meta = MetaData {
+            name: "iq".to_string(),
+            unitary_dimensions: vec![1],
+            dimensions: vec![4],
+            ..Default::default()
+        };
+        let data: Vec<Complex64> = (0..4)
+            .map(|k| Complex64::new(k as f64, -k as f64))
+            .collect();
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(DataBucketBlob::Complex64(DataBlob::new(data, meta)));
+        let blob = match bucket.get_blob(&"iq".to_string()) {
+            Some(DataBucketBlob::Complex64(b)) => b,
+            _ => panic!("Could not match complex blob"),
+        };
+        for (idx, val) in zip(0..4, blob.get_data().iter()) {
+            assert_eq!(val.re, idx as f64, "Failure to get real part from blob");
+            assert_eq!(
+                val.im, -idx as f64,
+                "Failure to get imaginary part from blob"
+            );
+        }
+    }
+
+    #[test]
+    fn test_bytes_data_blob() {
+        let meta = MetaData {
+            name: "frames".to_string(),
+            unitary_dimensions: vec![2, 2],
+            dimensions: vec![2],
+            ..Default::default()
+        };
+        let data = vec![
+            Bytes::from_static(&[0, 1, 2, 3]),
+            Bytes::from_static(&[4, 5, 6, 7]),
+        ];
+        let mut blob = DataBlob::new(data, meta);
+        assert_eq!(blob.record_size(), Some(4));
+        assert!(blob.validate_records().is_ok(), "Rejected valid records");
+        blob.get_mut_data().push(Bytes::from_static(&[8]));
+        assert!(blob.validate_records().is_err(), "Accepted short record");
+        blob.get_mut_meta_data().unitary_dimensions.clear();
+        assert!(blob.validate_records().is_ok(), "Rejected variable records");
+    }
+
+    #[test]
+    fn test_iterate_data_bucket() {
+        let mut bucket = DataBucket::new();
+        assert!(bucket.is_empty(), "New bucket is not empty");
+        bucket.add_blob(DataBucketBlob::Int8(make_int_blob()));
+        let mut other = make_int_blob();
+        other.get_mut_meta_data().name = "Other data".to_string();
+        bucket.add_blob(DataBucketBlob::Int8(other));
+        assert_eq!(bucket.len(), 2);
+        assert!(
+            bucket.contains("Other data"),
+            "Failure to find blob by name"
+        );
+        assert!(
+            !bucket.contains("Missing"),
+            "Found a blob that was never added"
+        );
+        let mut names: Vec<&String> = bucket.blob_names().collect();
+        names.sort();
+        assert_eq!(names, ["Other data", "Test data"]);
+        for (name, blob) in bucket.iter_mut() {
+            assert_eq!(name, &blob.get_meta_data().name, "Blob keyed by wrong name");
+            blob.get_mut_meta_data().units = Some("m".to_string());
+        }
+        assert!(
+            bucket
+                .iter()
+                .all(|(_, blob)| blob.get_meta_data().units.as_deref() == Some("m")),
+            "Failure to mutate blobs while iterating"
+        );
+    }
+
+    #[test]
+    fn test_clone_data_bucket() {
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(DataBucketBlob::Int8(make_int_blob()));
+        let mut copy = bucket.clone();
+        for (_, blob) in copy.iter_mut() {
+            if let DataBucketBlob::Int8(b) = blob {
+                b.get_mut_data()[0] = 42;
+            }
+        }
+        match bucket.get_blob(&"Test data".to_string()) {
+            Some(DataBucketBlob::Int8(b)) => assert_eq!(b.get_data()[0], 0, "Clone is shallow"),
+            _ => panic!("Could not match blob"),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_data_bucket() {
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(DataBucketBlob::Int8(make_int_blob()));
+        let snapshot = bucket.snapshot();
+        let shared = snapshot.clone();
+        let handle = std::thread::spawn(move || shared.len());
+        assert_eq!(handle.join().unwrap(), 1, "Failure to share snapshot");
+        for (_, blob) in bucket.iter_mut() {
+            if let DataBucketBlob::Int8(b) = blob {
+                b.get_mut_data()[0] = 42;
+            }
+        }
+        match snapshot.get_blob("Test data") {
+            Some(DataBucketBlob::Int8(b)) => {
+                assert_eq!(b.get_data()[0], 0, "Snapshot changed with bucket")
+            }
+            _ => panic!("Could not match blob"),
+        }
+        let restored = snapshot.to_bucket();
+        assert!(restored.contains("Test data"), "Failure to restore bucket");
+    }
+
+    #[test]
+    fn test_find_data_bucket() {
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(DataBucketBlob::Int8(make_int_blob()));
+        let mut speed = make_int_blob();
+        speed.get_mut_meta_data().name = "speed".to_string();
+        speed.get_mut_meta_data().units = Some("m/s".to_string());
+        speed.get_mut_meta_data().links.push(Link {
+            nature: LinkType::OneToOne,
+            linker: "speed".to_string(),
+            linkee: "time".to_string(),
+        });
+        bucket.add_blob(DataBucketBlob::Int8(speed));
+        let found: Vec<&String> = bucket
+            .blobs_with_units("m/s")
+            .map(|blob| &blob.get_meta_data().name)
+            .collect();
+        assert_eq!(found, ["speed"], "Failure to find blobs by units");
+        assert_eq!(bucket.blobs_linking_to("time").count(), 1);
+        assert_eq!(bucket.blobs_linking_to("speed").count(), 0);
+        assert_eq!(bucket.find(|meta| meta.dimensions == [10]).count(), 2);
+    }
+
+    #[test]
+    fn test_nested_data_bucket() {
+        let mut forces = DataBucket::new();
+        let mut x = make_int_blob();
+        x.get_mut_meta_data().name = "x".to_string();
+        forces.add_blob(DataBucketBlob::Int8(x));
+        let mut sim = DataBucket::new();
+        sim.add_group("forces", forces);
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(DataBucketBlob::Int8(make_int_blob()));
+        assert!(
+            bucket.add_group("sim", sim).is_none(),
+            "Failure to add group"
+        );
+        assert!(
+            bucket.add_group("Test data", DataBucket::new()).is_some(),
+            "Group shadowed a blob"
+        );
+        assert!(
+            bucket.get_path("sim/forces/x").is_some(),
+            "Failure to get path"
+        );
+        assert!(
+            bucket.get_path("Test data").is_some(),
+            "Failure to get root blob"
+        );
+        assert!(
+            bucket.get_path("sim/torques/x").is_none(),
+            "Found missing path"
+        );
+        assert!(bucket.get_group_path("sim/forces").is_some());
+        assert_eq!(bucket.group_names().count(), 1);
+        let snapshot = bucket.clone().snapshot();
+        assert!(
+            snapshot.get_path("sim/forces/x").is_some(),
+            "Snapshot lost groups"
+        );
+        assert!(snapshot.to_bucket().get_path("sim/forces/x").is_some());
+        assert!(bucket.pop_group("sim").is_some(), "Failure to pop group");
+        assert!(
+            bucket.get_path("sim/forces/x").is_none(),
+            "Popped group still found"
+        );
+    }
+
+    #[test]
+    fn test_slice_units() {
+        let mut blob = make_int_blob();
+        let slice = blob.slice_units(2..4).unwrap();
+        assert_eq!(slice.get_data(), &vec![2, 3]);
+        assert_eq!(slice.get_meta_data().dimensions, [2]);
+        assert!(blob.slice_units(9..11).is_none(), "Sliced past the end");
+        blob.get_mut_meta_data().unitary_dimensions = vec![2];
+        assert_eq!(blob.unit_count(), 5);
+        let slice = DataBucketBlob::Int8(blob).slice_units(1..2).unwrap();
+        match slice {
+            DataBucketBlob::Int8(b) => {
+                assert_eq!(b.get_data(), &vec![2, 3]);
+                assert_eq!(b.get_meta_data().dimensions, [1, 2]);
+            }
+            _ => panic!("Could not match blob"),
+        }
+    }
 }
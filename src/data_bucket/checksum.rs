@@ -0,0 +1,98 @@
+//! checksum
+//!
+//! Content hashes of blob data kept in MetaData to detect corruption
+//!
+//! the hash covers the binary encoding of the data and the validity mask, recording an
+//! operation in the provenance of a blob clears its checksum since the derived data no
+//! longer matches it, decompression and decryption verify the checksum when one is present
+
+use super::encoding::BinaryElement;
+use super::DataBlob;
+
+/// Checksum
+/// A content hash of the data of a blob
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Checksum {
+    /// 64 bit XXH3 hash
+    XxHash3(u64),
+}
+
+impl<T: BinaryElement> DataBlob<T> {
+    /// hash the current data and mask of the blob
+    pub fn checksum(&self) -> Checksum {
+        let mut bytes = Vec::new();
+        T::write_bytes(&self.data, &mut bytes);
+        if let Some(mask) = &self.mask {
+            bytes.extend(mask.iter().map(u8::from));
+        }
+        Checksum::XxHash3(twox_hash::XxHash3_64::oneshot(&bytes))
+    }
+    /// store the checksum of the current data in the meta data
+    pub fn update_checksum(&mut self) {
+        self.meta.checksum = Some(self.checksum());
+    }
+    /// store the checksum of the current data in the meta data, consuming the blob
+    pub fn with_checksum(mut self) -> Self {
+        self.update_checksum();
+        self
+    }
+    /// check the data against the checksum stored in the meta data (returns an error if
+    /// there is none or it does not match)
+    pub fn verify(&self) -> Result<(), &'static str> {
+        match self.meta.checksum {
+            None => Err("Blob has no checksum"),
+            Some(checksum) if checksum == self.checksum() => Ok(()),
+            Some(_) => Err("Blob data does not match its checksum"),
+        }
+    }
+    /// verify the checksum only if the meta data holds one
+    pub(crate) fn verify_if_present(self) -> Result<Self, &'static str> {
+        if self.meta.checksum.is_some() {
+            self.verify()?;
+        }
+        Ok(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_bucket::compression::Codec;
+    #[cfg(feature = "encryption")]
+    use crate::data_bucket::encryption::EncryptionKey;
+    use crate::data_bucket::{DataBlob, MetaData};
+
+    #[test]
+    fn test_checksum_verification() {
+        let blob = DataBlob::from_options(vec![Some(1_i64), None, Some(3)], MetaData::default());
+        assert_eq!(blob.verify(), Err("Blob has no checksum"));
+        let mut blob = blob.with_checksum();
+        assert!(blob.verify().is_ok());
+        blob.get_mut_data()[0] = 2;
+        assert_eq!(blob.verify(), Err("Blob data does not match its checksum"));
+        blob.get_mut_data()[0] = 1;
+        blob.take_mask();
+        assert!(blob.verify().is_err(), "Mask not covered by the checksum");
+        let slice = blob.slice_units(0..1).unwrap();
+        assert_eq!(slice.get_meta_data().checksum, None);
+    }
+
+    #[test]
+    fn test_verified_on_decoding() {
+        let blob = DataBlob::new(vec![0.5_f32; 64], MetaData::default()).with_checksum();
+        assert!(blob.compress(Codec::Lz4).unwrap().decompress().is_ok());
+        let mut compressed = blob.clone().compress(Codec::Lz4).unwrap();
+        compressed.get_mut_meta_data().checksum = DataBlob::new(vec![1_u8], MetaData::default())
+            .with_checksum()
+            .get_meta_data()
+            .checksum;
+        assert_eq!(
+            compressed.decompress().err(),
+            Some("Blob data does not match its checksum")
+        );
+        #[cfg(feature = "encryption")]
+        {
+            let key = EncryptionKey::new([3; 32]);
+            assert!(blob.encrypt(&key, [0; 12]).unwrap().decrypt(&key).is_ok());
+        }
+    }
+}
@@ -0,0 +1,188 @@
+//! chunked
+//!
+//! Segmented blob storage for data that keeps growing while being streamed
+
+use super::mask::ValidityMask;
+use super::{DataBlob, MetaData};
+use crate::Source;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::sync::Arc;
+
+/// ChunkedBlob
+/// A blob holding its data in a list of fixed size segments
+///
+/// appending never reallocates the data already stored and streams started before an
+/// append keep seeing the segments as they were when the stream was created
+#[derive(Clone)]
+pub struct ChunkedBlob<T> {
+    chunks: Vec<Arc<Vec<T>>>,
+    chunk_size: usize,
+    meta: MetaData,
+    mask: Option<ValidityMask>,
+}
+
+impl<T: Clone> ChunkedBlob<T> {
+    /// empty constructor (returns an error for a chunk size of zero)
+    pub fn new(chunk_size: usize, new_meta: MetaData) -> Result<Self, &'static str> {
+        if chunk_size == 0 {
+            return Err("Chunk size must be strictly positive");
+        }
+        Ok(Self {
+            chunks: Vec::new(),
+            chunk_size,
+            meta: new_meta,
+            mask: None,
+        })
+    }
+    /// number of elements per full chunk
+    pub fn chunk_size(&self) -> usize {
+        self.chunk_size
+    }
+    /// number of chunks, the last one may be partially filled
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+    /// total number of elements
+    pub fn len(&self) -> usize {
+        self.chunks.iter().map(|chunk| chunk.len()).sum()
+    }
+    /// whether the blob holds no elements
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+    /// get an element by index
+    pub fn get(&self, index: usize) -> Option<&T> {
+        self.chunks
+            .get(index / self.chunk_size)?
+            .get(index % self.chunk_size)
+    }
+    /// get a chunk by index
+    pub fn get_chunk(&self, index: usize) -> Option<&[T]> {
+        self.chunks.get(index).map(|chunk| chunk.as_slice())
+    }
+    /// append one element
+    pub fn push(&mut self, value: T) {
+        if let Some(mask) = self.mask.as_mut() {
+            mask.push(true);
+        }
+        match self.chunks.last_mut() {
+            Some(chunk) if chunk.len() < self.chunk_size => Arc::make_mut(chunk).push(value),
+            _ => {
+                let mut chunk = Vec::with_capacity(self.chunk_size);
+                chunk.push(value);
+                self.chunks.push(Arc::new(chunk));
+            }
+        }
+    }
+    /// append every element of an iterator
+    pub fn extend<I: IntoIterator<Item = T>>(&mut self, values: I) {
+        for value in values {
+            self.push(value);
+        }
+    }
+    /// get the associated meta data immutably
+    pub fn get_meta_data(&self) -> &MetaData {
+        &self.meta
+    }
+    /// get the associated meta data mutably
+    pub fn get_mut_meta_data(&mut self) -> &mut MetaData {
+        &mut self.meta
+    }
+    /// copy the segments into a contiguous blob
+    pub fn to_blob(&self) -> DataBlob<T> {
+        let mut data = Vec::with_capacity(self.len());
+        for chunk in self.chunks.iter() {
+            data.extend_from_slice(chunk);
+        }
+        DataBlob {
+            data,
+            meta: self.meta.clone(),
+            mask: self.mask.clone(),
+        }
+    }
+    /// stream the data chunk by chunk for pipes able to process whole segments
+    pub fn stream_chunks(&self) -> Box<dyn Stream<Item = Arc<Vec<T>>>>
+    where
+        T: 'static,
+    {
+        Box::new(stream::iter(self.chunks.clone()))
+    }
+}
+
+impl<T: Clone> DataBlob<T> {
+    /// split the data into segments of `chunk_size` elements
+    pub fn to_chunked(&self, chunk_size: usize) -> Result<ChunkedBlob<T>, &'static str> {
+        let mut chunked = ChunkedBlob::new(chunk_size, self.meta.clone())?;
+        chunked.chunks = self
+            .data
+            .chunks(chunk_size)
+            .map(|chunk| Arc::new(chunk.to_vec()))
+            .collect();
+        chunked.mask = self.mask.clone();
+        Ok(chunked)
+    }
+}
+
+impl<T: Clone + 'static> Source<T> for ChunkedBlob<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        Box::new(
+            stream::iter(self.chunks.clone()).flat_map(|chunk| {
+                stream::iter((0..chunk.len()).map(move |idx| chunk[idx].clone()))
+            }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::pin::Pin;
+
+    fn make_meta() -> MetaData {
+        MetaData {
+            name: "samples".to_string(),
+            unitary_dimensions: vec![1],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_push_chunks() {
+        let mut blob = ChunkedBlob::new(4, make_meta()).unwrap();
+        blob.extend(0..10);
+        assert_eq!(blob.len(), 10);
+        assert_eq!(blob.chunk_count(), 3);
+        assert_eq!(blob.get_chunk(2), Some(&[8, 9][..]));
+        assert_eq!(blob.get(5), Some(&5));
+        assert_eq!(blob.get(10), None);
+        assert_eq!(blob.to_blob().get_data(), &(0..10).collect::<Vec<i32>>());
+        assert!(ChunkedBlob::<i32>::new(0, make_meta()).is_err());
+    }
+
+    #[test]
+    fn test_stream_while_appending() {
+        let mut blob = ChunkedBlob::new(4, make_meta()).unwrap();
+        blob.extend(0..6);
+        let before = Pin::from(blob.stream());
+        blob.extend(6..8);
+        let seen: Vec<i32> = block_on(before.collect());
+        assert_eq!(
+            seen,
+            (0..6).collect::<Vec<i32>>(),
+            "Stream saw later appends"
+        );
+        let chunks: Vec<Arc<Vec<i32>>> = block_on(Pin::from(blob.stream_chunks()).collect());
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(*chunks[1], vec![4, 5, 6, 7]);
+    }
+
+    #[test]
+    fn test_blob_to_chunked() {
+        let blob = DataBlob::new((0..5).collect::<Vec<u8>>(), make_meta());
+        let chunked = blob.to_chunked(2).unwrap();
+        assert_eq!(chunked.chunk_count(), 3);
+        assert_eq!(chunked.to_blob().get_data(), blob.get_data());
+    }
+}
@@ -0,0 +1,207 @@
+//! compression
+//!
+//! Compressed storage of blob data, decompressed on access or lazily while streaming
+
+use super::encoding::BinaryElement;
+use super::mask::ValidityMask;
+use super::{DataBlob, MetaData};
+use crate::Source;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::marker::PhantomData;
+
+/// Codec
+/// The compression algorithms available for blob data
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Codec {
+    /// fast lz4 block compression
+    Lz4,
+    /// zstd compression at the given level (requires the `zstd` feature)
+    #[cfg(feature = "zstd")]
+    Zstd(i32),
+}
+
+impl Codec {
+    /// the tag of the codec in wire encodings, 0 standing for uncompressed data
+    pub(super) fn wire_tag(&self) -> u8 {
+        match self {
+            Codec::Lz4 => 1,
+            #[cfg(feature = "zstd")]
+            Codec::Zstd(_) => 2,
+        }
+    }
+    /// the codec of a wire encoding tag, the level of zstd only mattering to compression
+    pub(super) fn from_wire_tag(tag: u8) -> Result<Self, &'static str> {
+        match tag {
+            1 => Ok(Codec::Lz4),
+            #[cfg(feature = "zstd")]
+            2 => Ok(Codec::Zstd(0)),
+            #[cfg(not(feature = "zstd"))]
+            2 => Err("Encoding compressed with zstd, which requires the zstd feature"),
+            _ => Err("Unknown compression codec in encoding"),
+        }
+    }
+    pub(super) fn compress(&self, bytes: &[u8]) -> Result<Vec<u8>, &'static str> {
+        match self {
+            Codec::Lz4 => Ok(lz4_flex::compress_prepend_size(bytes)),
+            #[cfg(feature = "zstd")]
+            Codec::Zstd(level) => {
+                zstd::bulk::compress(bytes, *level).map_err(|_| "Failure to zstd compress data")
+            }
+        }
+    }
+    pub(super) fn decompress(
+        &self,
+        bytes: &[u8],
+        capacity: usize,
+    ) -> Result<Vec<u8>, &'static str> {
+        match self {
+            Codec::Lz4 => {
+                lz4_flex::decompress_size_prepended(bytes).map_err(|_| "Corrupted lz4 data")
+            }
+            #[cfg(feature = "zstd")]
+            Codec::Zstd(_) => {
+                zstd::bulk::decompress(bytes, capacity).map_err(|_| "Corrupted zstd data")
+            }
+        }
+        .and_then(|out| {
+            if out.len() > capacity {
+                Err("Decompressed data larger than expected")
+            } else {
+                Ok(out)
+            }
+        })
+    }
+}
+
+/// CompressedBlob
+/// A DataBlob whose data is held compressed in memory
+#[derive(Clone)]
+pub struct CompressedBlob<T> {
+    codec: Codec,
+    compressed: Vec<u8>,
+    raw_size: usize,
+    meta: MetaData,
+    mask: Option<ValidityMask>,
+    phantom: PhantomData<T>,
+}
+
+impl<T: BinaryElement> CompressedBlob<T> {
+    /// the codec the data was compressed with
+    pub fn get_codec(&self) -> Codec {
+        self.codec
+    }
+    /// number of bytes held by the compressed data
+    pub fn compressed_size(&self) -> usize {
+        self.compressed.len()
+    }
+    /// number of bytes of the encoded data once decompressed
+    pub fn raw_size(&self) -> usize {
+        self.raw_size
+    }
+    /// get the associated meta data immutably
+    pub fn get_meta_data(&self) -> &MetaData {
+        &self.meta
+    }
+    /// get the associated meta data mutably
+    pub fn get_mut_meta_data(&mut self) -> &mut MetaData {
+        &mut self.meta
+    }
+    /// decompress only the data
+    pub fn decompress_data(&self) -> Result<Vec<T>, &'static str> {
+        let bytes = self.codec.decompress(&self.compressed, self.raw_size)?;
+        T::read_bytes(&bytes)
+    }
+    /// decompress back into a plain blob, verifying its checksum if it has one
+    pub fn decompress(&self) -> Result<DataBlob<T>, &'static str> {
+        let blob = DataBlob::new(self.decompress_data()?, self.meta.clone());
+        match &self.mask {
+            Some(mask) => blob.with_mask(mask.clone())?,
+            None => blob,
+        }
+        .verify_if_present()
+    }
+}
+
+impl<T: BinaryElement> DataBlob<T> {
+    /// compress the data of the blob with the given codec
+    pub fn compress(&self, codec: Codec) -> Result<CompressedBlob<T>, &'static str> {
+        let mut bytes = Vec::new();
+        T::write_bytes(&self.data, &mut bytes);
+        Ok(CompressedBlob {
+            codec,
+            compressed: codec.compress(&bytes)?,
+            raw_size: bytes.len(),
+            meta: self.meta.clone(),
+            mask: self.mask.clone(),
+            phantom: PhantomData,
+        })
+    }
+}
+
+/// the data is only decompressed once the stream is first polled (corrupted data yields
+/// an empty stream)
+impl<T: BinaryElement + Clone + 'static> Source<T> for CompressedBlob<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let blob = self.clone();
+        Box::new(
+            stream::once(async move { blob.decompress_data().unwrap_or_default() })
+                .flat_map(stream::iter),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::pin::Pin;
+
+    fn make_blob() -> DataBlob<f64> {
+        let meta = MetaData {
+            name: "zeros".to_string(),
+            unitary_dimensions: vec![1],
+            dimensions: vec![1000],
+            ..Default::default()
+        };
+        DataBlob::new(vec![0.25; 1000], meta)
+    }
+
+    #[test]
+    fn test_lz4_round_trip() {
+        let blob = make_blob();
+        let compressed = blob.compress(Codec::Lz4).unwrap();
+        assert!(
+            compressed.compressed_size() < compressed.raw_size(),
+            "Failure to compress repetitive data"
+        );
+        let restored = compressed.decompress().unwrap();
+        assert_eq!(restored.get_data(), blob.get_data());
+        assert_eq!(restored.get_meta_data(), blob.get_meta_data());
+    }
+
+    #[test]
+    fn test_stream_compressed_blob() {
+        let compressed = make_blob().compress(Codec::Lz4).unwrap();
+        let data: Vec<f64> = block_on(Pin::from(compressed.stream()).collect());
+        assert_eq!(data, vec![0.25; 1000], "Failure to stream compressed data");
+    }
+
+    #[test]
+    fn test_corrupted_data() {
+        let mut compressed = make_blob().compress(Codec::Lz4).unwrap();
+        compressed.compressed.truncate(3);
+        assert!(
+            compressed.decompress().is_err(),
+            "Decompressed corrupted data"
+        );
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_round_trip() {
+        let blob = make_blob();
+        let compressed = blob.compress(Codec::Zstd(3)).unwrap();
+        assert_eq!(compressed.decompress().unwrap().get_data(), blob.get_data());
+    }
+}
@@ -0,0 +1,191 @@
+//! conversion
+//!
+//! Tables of scale factors between units
+//!
+//! every symbol of a table is defined as a factor (and for temperatures an offset) times an
+//! expression of base units, `v_base = v * factor + offset`, expressions convert symbol by
+//! symbol so `km/h` converts to `m/s`, symbols missing from the table are base units of their
+//! own, units with an offset only convert on their own and not within a product
+
+use super::units::Units;
+use std::collections::HashMap;
+
+struct Definition {
+    factor: f64,
+    offset: f64,
+    base: Units,
+}
+
+/// UnitTable
+/// The definitions of unit symbols in terms of base units
+pub struct UnitTable {
+    definitions: HashMap<String, Definition>,
+}
+
+impl UnitTable {
+    /// constructor of a table without any definition
+    pub fn empty() -> Self {
+        Self {
+            definitions: HashMap::new(),
+        }
+    }
+    /// constructor of a table holding the built-in SI prefixed, imperial, time, temperature,
+    /// angle, pressure and energy units
+    pub fn new() -> Self {
+        let mut table = Self::empty();
+        let builtins: [(&str, f64, &str); 33] = [
+            ("km", 1e3, "m"),
+            ("cm", 1e-2, "m"),
+            ("mm", 1e-3, "m"),
+            ("um", 1e-6, "m"),
+            ("µm", 1e-6, "m"),
+            ("nm", 1e-9, "m"),
+            ("in", 0.0254, "m"),
+            ("ft", 0.3048, "m"),
+            ("yd", 0.9144, "m"),
+            ("mi", 1609.344, "m"),
+            ("ms", 1e-3, "s"),
+            ("us", 1e-6, "s"),
+            ("µs", 1e-6, "s"),
+            ("ns", 1e-9, "s"),
+            ("min", 60.0, "s"),
+            ("h", 3600.0, "s"),
+            ("d", 86400.0, "s"),
+            ("g", 1e-3, "kg"),
+            ("mg", 1e-6, "kg"),
+            ("t", 1e3, "kg"),
+            ("lb", 0.45359237, "kg"),
+            ("deg", std::f64::consts::PI / 180.0, "rad"),
+            ("N", 1.0, "kg*m/s^2"),
+            ("Pa", 1.0, "kg/m/s^2"),
+            ("kPa", 1e3, "kg/m/s^2"),
+            ("bar", 1e5, "kg/m/s^2"),
+            ("atm", 101325.0, "kg/m/s^2"),
+            ("J", 1.0, "kg*m^2/s^2"),
+            ("kJ", 1e3, "kg*m^2/s^2"),
+            ("cal", 4.184, "kg*m^2/s^2"),
+            ("kWh", 3.6e6, "kg*m^2/s^2"),
+            ("W", 1.0, "kg*m^2/s^3"),
+            ("Hz", 1.0, "1/s"),
+        ];
+        for (symbol, factor, base) in builtins {
+            table.register(symbol, factor, base).unwrap();
+        }
+        table.register_affine("°C", 1.0, 273.15, "K").unwrap();
+        table
+            .register_affine("°F", 5.0 / 9.0, 459.67 * 5.0 / 9.0, "K")
+            .unwrap();
+        table
+    }
+    /// define a symbol as a factor times an expression of base units, replacing any previous
+    /// definition (returns an error if the expression is invalid)
+    pub fn register(&mut self, symbol: &str, factor: f64, base: &str) -> Result<(), &'static str> {
+        self.register_affine(symbol, factor, 0.0, base)
+    }
+    /// define a symbol as a factor times an expression of base units plus an offset
+    pub fn register_affine(
+        &mut self,
+        symbol: &str,
+        factor: f64,
+        offset: f64,
+        base: &str,
+    ) -> Result<(), &'static str> {
+        if factor == 0.0 || !factor.is_finite() || !offset.is_finite() {
+            return Err("Conversion factors must be finite and non zero");
+        }
+        let base = Units::parse(base)?;
+        self.definitions.insert(
+            symbol.to_string(),
+            Definition {
+                factor,
+                offset,
+                base,
+            },
+        );
+        Ok(())
+    }
+    /// whether the table defines a symbol
+    pub fn contains(&self, symbol: &str) -> bool {
+        self.definitions.contains_key(symbol)
+    }
+    /// the base units, factor and offset of an expression
+    fn to_base(&self, units: &Units) -> Result<(Units, f64, f64), &'static str> {
+        let mut base = Units::dimensionless();
+        let mut factor = 1.0;
+        let mut offset = 0.0;
+        let single = units.iter().count() == 1;
+        for (symbol, exponent) in units.iter() {
+            match self.definitions.get(symbol) {
+                Some(definition) => {
+                    if definition.offset != 0.0 {
+                        if !single || *exponent != 1 {
+                            return Err("Units with an offset cannot be part of a product");
+                        }
+                        offset = definition.offset;
+                    }
+                    base = base.mul(&definition.base.powi(*exponent));
+                    factor *= definition.factor.powi(*exponent);
+                }
+                None => base = base.mul(&Units::parse(symbol)?.powi(*exponent)),
+            }
+        }
+        Ok((base, factor, offset))
+    }
+    /// the scale and offset converting values between two unit expressions,
+    /// `v_to = v_from * scale + offset` (returns an error if their dimensions differ)
+    pub fn conversion(&self, from: &str, to: &str) -> Result<(f64, f64), &'static str> {
+        let (from_base, from_factor, from_offset) = self.to_base(&Units::parse(from)?)?;
+        let (to_base, to_factor, to_offset) = self.to_base(&Units::parse(to)?)?;
+        if from_base != to_base {
+            return Err("Units have different dimensions");
+        }
+        Ok((
+            from_factor / to_factor,
+            (from_offset - to_offset) / to_factor,
+        ))
+    }
+}
+
+impl Default for UnitTable {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(table: &UnitTable, value: f64, from: &str, to: &str) -> f64 {
+        let (scale, offset) = table.conversion(from, to).unwrap();
+        value * scale + offset
+    }
+
+    #[test]
+    fn test_conversions() {
+        let table = UnitTable::new();
+        assert!((convert(&table, 1.5, "km", "m") - 1500.0).abs() < 1e-9);
+        assert!((convert(&table, 36.0, "km/h", "m/s") - 10.0).abs() < 1e-9);
+        assert!((convert(&table, 100.0, "°C", "K") - 373.15).abs() < 1e-9);
+        assert!((convert(&table, 212.0, "°F", "°C") - 100.0).abs() < 1e-9);
+        assert!((convert(&table, 1.0, "kWh", "kJ") - 3600.0).abs() < 1e-9);
+        assert!((convert(&table, 2.0, "m^2", "cm^2") - 2e4).abs() < 1e-6);
+        assert_eq!(
+            table.conversion("m", "s"),
+            Err("Units have different dimensions")
+        );
+        assert!(table.conversion("°C/s", "K/s").is_err());
+    }
+
+    #[test]
+    fn test_registered_factors() {
+        let mut table = UnitTable::empty();
+        assert!(table.conversion("furlong", "m").is_err());
+        table.register("furlong", 201.168, "m").unwrap();
+        table.register("fortnight", 1209600.0, "s").unwrap();
+        assert!(table.contains("furlong"));
+        let (scale, _) = table.conversion("furlong/fortnight", "m/s").unwrap();
+        assert!((scale - 201.168 / 1209600.0).abs() < 1e-15);
+        assert!(table.register("nothing", 0.0, "m").is_err());
+    }
+}
@@ -0,0 +1,352 @@
+//! diff
+//!
+//! Structural and element-wise comparison of DataBuckets, mostly aimed at regression testing
+
+use super::half::{BF16, F16};
+use super::{for_each_variant, DataBlob, DataBucket, DataBucketBlob, PATH_SEPARATOR};
+use bytes::Bytes;
+use num_complex::{Complex32, Complex64};
+
+/// DataChange
+/// The way the data of a blob differs between two buckets
+#[derive(Debug, PartialEq)]
+pub enum DataChange {
+    /// the blobs hold different primitive types
+    Variant,
+    /// the blobs hold a different number of elements (ours, theirs)
+    Length(usize, usize),
+    /// indices of the elements that differ
+    Elements(Vec<usize>),
+}
+
+/// BlobDiff
+/// The differences between two blobs sharing the same name
+#[derive(Debug, PartialEq)]
+pub struct BlobDiff {
+    /// name of the blob
+    pub name: String,
+    /// whether the meta data differs
+    pub meta_data_changed: bool,
+    /// how the data differs (None if the data is equal)
+    pub data: Option<DataChange>,
+}
+
+/// BucketDiff
+/// Report of the differences between two DataBuckets (names are sorted)
+#[derive(Debug, Default, PartialEq)]
+pub struct BucketDiff {
+    /// blobs only present in the other bucket
+    pub added: Vec<String>,
+    /// blobs only present in this bucket
+    pub removed: Vec<String>,
+    /// blobs present in both buckets but with different contents
+    pub changed: Vec<BlobDiff>,
+}
+
+impl BucketDiff {
+    /// whether the two buckets were found equal
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty() && self.changed.is_empty()
+    }
+}
+
+/// element comparison used for diffs, floating point types are compared with a tolerance
+trait ElementEq {
+    fn element_eq(&self, other: &Self, tolerance: f64) -> bool;
+}
+
+macro_rules! exact_element_eq {
+  ($($t:ty),*) => {
+    $( impl ElementEq for $t {
+      fn element_eq(&self, other: &Self, _tolerance: f64) -> bool {
+        self == other
+      }
+    } )*
+  }
+}
+
+exact_element_eq!(
+    bool, char, i8, u8, i16, u16, i32, u32, i64, u64, i128, u128, isize, usize, String, Bytes
+);
+
+macro_rules! float_element_eq {
+  ($($t:ty),*) => {
+    $( impl ElementEq for $t {
+      fn element_eq(&self, other: &Self, tolerance: f64) -> bool {
+        *self == *other
+          || (self.is_nan() && other.is_nan())
+          || ((*self - *other) as f64).abs() <= tolerance
+      }
+    } )*
+  }
+}
+
+float_element_eq!(f32, f64);
+
+macro_rules! half_element_eq {
+  ($($t:ty),*) => {
+    $( impl ElementEq for $t {
+      fn element_eq(&self, other: &Self, tolerance: f64) -> bool {
+        self.to_f64().element_eq(&other.to_f64(), tolerance)
+      }
+    } )*
+  }
+}
+
+half_element_eq!(F16, BF16);
+
+macro_rules! complex_element_eq {
+  ($($t:ty),*) => {
+    $( impl ElementEq for $t {
+      fn element_eq(&self, other: &Self, tolerance: f64) -> bool {
+        self.re.element_eq(&other.re, tolerance) && self.im.element_eq(&other.im, tolerance)
+      }
+    } )*
+  }
+}
+
+complex_element_eq!(Complex32, Complex64);
+
+/// the elements valid on one side only differ, the elements invalid on both sides do not
+fn element_diff<T: ElementEq>(
+    ours: &DataBlob<T>,
+    theirs: &DataBlob<T>,
+    tolerance: f64,
+) -> Option<DataChange> {
+    let (ours_data, theirs_data) = (ours.get_data(), theirs.get_data());
+    if ours_data.len() != theirs_data.len() {
+        return Some(DataChange::Length(ours_data.len(), theirs_data.len()));
+    }
+    let indices: Vec<usize> = ours_data
+        .iter()
+        .zip(theirs_data.iter())
+        .enumerate()
+        .filter(
+            |(idx, (a, b))| match (ours.is_valid(*idx), theirs.is_valid(*idx)) {
+                (true, true) => !a.element_eq(b, tolerance),
+                (valid, other_valid) => valid != other_valid,
+            },
+        )
+        .map(|(idx, _)| idx)
+        .collect();
+    if indices.is_empty() {
+        None
+    } else {
+        Some(DataChange::Elements(indices))
+    }
+}
+
+macro_rules! data_diff {
+  ($($x:ident),*) => {
+    fn data_diff(ours: &DataBucketBlob, theirs: &DataBucketBlob, tolerance: f64) -> Option<DataChange> {
+      match (ours, theirs) {
+        $( (DataBucketBlob::$x(a), DataBucketBlob::$x(b)) => {
+          element_diff(a, b, tolerance)
+        } )*
+        _ => Some(DataChange::Variant),
+      }
+    }
+  }
+}
+
+for_each_variant!(data_diff);
+
+impl DataBucket {
+    /// compare with another bucket, floating point elements must be exactly equal
+    pub fn diff(&self, other: &DataBucket) -> BucketDiff {
+        self.diff_with_tolerance(other, 0.0)
+    }
+    /// compare with another bucket, floating point elements may differ by up to `tolerance`
+    ///
+    /// nested groups are compared recursively and their blobs reported by path
+    pub fn diff_with_tolerance(&self, other: &DataBucket, tolerance: f64) -> BucketDiff {
+        let mut report = BucketDiff::default();
+        self.diff_into(other, tolerance, "", &mut report);
+        report.added.sort();
+        report.removed.sort();
+        report.changed.sort_by(|a, b| a.name.cmp(&b.name));
+        report
+    }
+    fn diff_into(&self, other: &DataBucket, tolerance: f64, prefix: &str, report: &mut BucketDiff) {
+        for (name, ours) in self.iter() {
+            match other.get_blob(name) {
+                None => report.removed.push(format!("{}{}", prefix, name)),
+                Some(theirs) => {
+                    let meta_data_changed = ours.get_meta_data() != theirs.get_meta_data();
+                    let data = data_diff(ours, theirs, tolerance);
+                    if meta_data_changed || data.is_some() {
+                        report.changed.push(BlobDiff {
+                            name: format!("{}{}", prefix, name),
+                            meta_data_changed,
+                            data,
+                        });
+                    }
+                }
+            }
+        }
+        report.added.extend(
+            other
+                .blob_names()
+                .filter(|name| !self.contains(name))
+                .map(|name| format!("{}{}", prefix, name)),
+        );
+        let empty = DataBucket::new();
+        for (name, ours) in self.groups() {
+            let group_prefix = format!("{}{}{}", prefix, name, PATH_SEPARATOR);
+            let theirs = other.get_group(name).unwrap_or(&empty);
+            ours.diff_into(theirs, tolerance, &group_prefix, report);
+        }
+        for (name, theirs) in other.groups() {
+            if self.get_group(name).is_none() {
+                let group_prefix = format!("{}{}{}", prefix, name, PATH_SEPARATOR);
+                empty.diff_into(theirs, tolerance, &group_prefix, report);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::mask::ValidityMask;
+    use super::super::MetaData;
+    use super::*;
+
+    fn make_float_blob(name: &str, data: Vec<f64>) -> DataBucketBlob {
+        let meta = MetaData {
+            name: name.to_string(),
+            unitary_dimensions: vec![1],
+            dimensions: vec![data.len()],
+            ..Default::default()
+        };
+        DataBucketBlob::Float64(DataBlob::new(data, meta))
+    }
+
+    #[test]
+    fn test_equal_buckets() {
+        let mut ours = DataBucket::new();
+        ours.add_blob(make_float_blob("x", vec![0.0, 1.0, f64::NAN]));
+        ours.add_blob(make_float_blob(
+            "inf",
+            vec![f64::INFINITY, f64::NEG_INFINITY],
+        ));
+        let theirs = ours.clone();
+        assert!(
+            ours.diff(&theirs).is_empty(),
+            "Equal buckets reported different"
+        );
+        assert!(ours.diff_with_tolerance(&theirs, 1e-6).is_empty());
+        let mut theirs = DataBucket::new();
+        theirs.add_blob(make_float_blob("x", vec![0.0, 1.0, f64::NAN]));
+        theirs.add_blob(make_float_blob(
+            "inf",
+            vec![f64::NEG_INFINITY, f64::NEG_INFINITY],
+        ));
+        let report = ours.diff_with_tolerance(&theirs, 1e-6);
+        assert_eq!(report.changed[0].data, Some(DataChange::Elements(vec![0])));
+    }
+
+    #[test]
+    fn test_masked_elements() {
+        let masked = |data: Vec<f64>, valid: [bool; 3]| {
+            let blob = match make_float_blob("x", data) {
+                DataBucketBlob::Float64(blob) => blob,
+                _ => unreachable!(),
+            };
+            let blob = blob.with_mask(ValidityMask::from_bools(valid)).unwrap();
+            let mut bucket = DataBucket::new();
+            bucket.add_blob(DataBucketBlob::Float64(blob));
+            bucket
+        };
+        let ours = masked(vec![0.0, 1.0, 2.0], [true, false, true]);
+        let theirs = masked(vec![0.0, 5.0, 2.0], [true, false, true]);
+        assert!(ours.diff(&theirs).is_empty(), "Invalid elements compared");
+        let theirs = masked(vec![0.0, 1.0, 2.0], [true, true, false]);
+        assert_eq!(
+            ours.diff(&theirs).changed[0].data,
+            Some(DataChange::Elements(vec![1, 2]))
+        );
+    }
+
+    #[test]
+    fn test_added_removed_blobs() {
+        let mut ours = DataBucket::new();
+        ours.add_blob(make_float_blob("x", vec![0.0]));
+        let mut theirs = DataBucket::new();
+        theirs.add_blob(make_float_blob("y", vec![0.0]));
+        let report = ours.diff(&theirs);
+        assert_eq!(report.added, ["y"]);
+        assert_eq!(report.removed, ["x"]);
+        assert!(report.changed.is_empty(), "Reported unexpected changes");
+    }
+
+    #[test]
+    fn test_changed_blobs() {
+        let mut ours = DataBucket::new();
+        ours.add_blob(make_float_blob("x", vec![0.0, 1.0, 2.0]));
+        ours.add_blob(make_float_blob("y", vec![0.0]));
+        let mut theirs = DataBucket::new();
+        theirs.add_blob(make_float_blob("x", vec![0.0, 1.0 + 1e-9, 2.5]));
+        let mut y = make_float_blob("y", vec![0.0]);
+        y.get_mut_meta_data().units = Some("m".to_string());
+        theirs.add_blob(y);
+        let report = ours.diff_with_tolerance(&theirs, 1e-6);
+        assert_eq!(
+            report.changed,
+            [
+                BlobDiff {
+                    name: "x".to_string(),
+                    meta_data_changed: false,
+                    data: Some(DataChange::Elements(vec![2])),
+                },
+                BlobDiff {
+                    name: "y".to_string(),
+                    meta_data_changed: true,
+                    data: None,
+                },
+            ]
+        );
+        let report = ours.diff(&theirs);
+        assert_eq!(
+            report.changed[0].data,
+            Some(DataChange::Elements(vec![1, 2]))
+        );
+    }
+
+    #[test]
+    fn test_variant_and_length_changes() {
+        let mut ours = DataBucket::new();
+        ours.add_blob(make_float_blob("x", vec![0.0]));
+        ours.add_blob(make_float_blob("y", vec![0.0]));
+        let mut theirs = DataBucket::new();
+        theirs.add_blob(make_float_blob("x", vec![0.0, 1.0]));
+        let meta = MetaData {
+            name: "y".to_string(),
+            unitary_dimensions: vec![1],
+            dimensions: vec![1],
+            ..Default::default()
+        };
+        theirs.add_blob(DataBucketBlob::Float32(DataBlob::new(vec![0.0], meta)));
+        let report = ours.diff(&theirs);
+        assert_eq!(report.changed[0].data, Some(DataChange::Length(1, 2)));
+        assert_eq!(report.changed[1].data, Some(DataChange::Variant));
+    }
+
+    #[test]
+    fn test_nested_group_diff() {
+        let mut group = DataBucket::new();
+        group.add_blob(make_float_blob("x", vec![0.0]));
+        let mut ours = DataBucket::new();
+        ours.add_group("sim", group);
+        let mut theirs = ours.clone();
+        theirs
+            .get_mut_group("sim")
+            .unwrap()
+            .add_blob(make_float_blob("y", vec![0.0]));
+        let mut extra = DataBucket::new();
+        extra.add_blob(make_float_blob("z", vec![0.0]));
+        theirs.add_group("extra", extra);
+        let report = ours.diff(&theirs);
+        assert_eq!(report.added, ["extra/z", "sim/y"]);
+        assert!(report.removed.is_empty() && report.changed.is_empty());
+    }
+}
@@ -0,0 +1,190 @@
+//! encoding
+//!
+//! Little endian binary encoding of the primitive types held by blobs
+
+use super::half::{BF16, F16};
+use bytes::Bytes;
+use num_complex::{Complex32, Complex64};
+
+/// BinaryElement
+/// A trait for element types that can be written to and read back from raw bytes
+pub trait BinaryElement: Sized {
+    /// append the encoding of a slice of elements to a byte buffer
+    fn write_bytes(data: &[Self], out: &mut Vec<u8>);
+    /// decode a byte buffer written by `write_bytes`
+    fn read_bytes(bytes: &[u8]) -> Result<Vec<Self>, &'static str>;
+}
+
+macro_rules! numeric_binary_element {
+  ($($t:ty),*) => {
+    $( impl BinaryElement for $t {
+      fn write_bytes(data: &[Self], out: &mut Vec<u8>) {
+        out.reserve(data.len() * std::mem::size_of::<$t>());
+        for value in data.iter() {
+          out.extend_from_slice(&value.to_le_bytes());
+        }
+      }
+      fn read_bytes(bytes: &[u8]) -> Result<Vec<Self>, &'static str> {
+        let size = std::mem::size_of::<$t>();
+        if bytes.len() % size != 0 {
+          return Err("Byte count is not a multiple of the element size");
+        }
+        Ok(bytes
+          .chunks_exact(size)
+          .map(|chunk| <$t>::from_le_bytes(chunk.try_into().unwrap()))
+          .collect())
+      }
+    } )*
+  }
+}
+
+numeric_binary_element!(i8, u8, i16, u16, i32, u32, i64, u64, i128, u128, isize, usize, f32, f64);
+
+macro_rules! half_binary_element {
+  ($($t:ty),*) => {
+    $( impl BinaryElement for $t {
+      fn write_bytes(data: &[Self], out: &mut Vec<u8>) {
+        let bits: Vec<u16> = data.iter().map(|value| value.to_bits()).collect();
+        u16::write_bytes(&bits, out);
+      }
+      fn read_bytes(bytes: &[u8]) -> Result<Vec<Self>, &'static str> {
+        Ok(u16::read_bytes(bytes)?.into_iter().map(<$t>::from_bits).collect())
+      }
+    } )*
+  }
+}
+
+half_binary_element!(F16, BF16);
+
+impl BinaryElement for bool {
+    fn write_bytes(data: &[Self], out: &mut Vec<u8>) {
+        out.extend(data.iter().map(|value| *value as u8));
+    }
+    fn read_bytes(bytes: &[u8]) -> Result<Vec<Self>, &'static str> {
+        bytes
+            .iter()
+            .map(|byte| match byte {
+                0 => Ok(false),
+                1 => Ok(true),
+                _ => Err("Invalid boolean byte"),
+            })
+            .collect()
+    }
+}
+
+impl BinaryElement for char {
+    fn write_bytes(data: &[Self], out: &mut Vec<u8>) {
+        let codes: Vec<u32> = data.iter().map(|value| *value as u32).collect();
+        u32::write_bytes(&codes, out);
+    }
+    fn read_bytes(bytes: &[u8]) -> Result<Vec<Self>, &'static str> {
+        u32::read_bytes(bytes)?
+            .into_iter()
+            .map(|code| char::from_u32(code).ok_or("Invalid character code"))
+            .collect()
+    }
+}
+
+/// split length prefixed byte records
+fn read_records(mut bytes: &[u8]) -> Result<Vec<&[u8]>, &'static str> {
+    let mut records = Vec::new();
+    while !bytes.is_empty() {
+        let (length, rest) = bytes.split_at_checked(8).ok_or("Truncated record length")?;
+        let length = u64::from_le_bytes(length.try_into().unwrap()) as usize;
+        let (record, rest) = rest.split_at_checked(length).ok_or("Truncated record")?;
+        records.push(record);
+        bytes = rest;
+    }
+    Ok(records)
+}
+
+fn write_record(record: &[u8], out: &mut Vec<u8>) {
+    out.extend_from_slice(&(record.len() as u64).to_le_bytes());
+    out.extend_from_slice(record);
+}
+
+impl BinaryElement for String {
+    fn write_bytes(data: &[Self], out: &mut Vec<u8>) {
+        for value in data.iter() {
+            write_record(value.as_bytes(), out);
+        }
+    }
+    fn read_bytes(bytes: &[u8]) -> Result<Vec<Self>, &'static str> {
+        read_records(bytes)?
+            .into_iter()
+            .map(|record| {
+                String::from_utf8(record.to_vec()).map_err(|_| "Invalid utf-8 string record")
+            })
+            .collect()
+    }
+}
+
+impl BinaryElement for Bytes {
+    fn write_bytes(data: &[Self], out: &mut Vec<u8>) {
+        for value in data.iter() {
+            write_record(value, out);
+        }
+    }
+    fn read_bytes(bytes: &[u8]) -> Result<Vec<Self>, &'static str> {
+        Ok(read_records(bytes)?
+            .into_iter()
+            .map(Bytes::copy_from_slice)
+            .collect())
+    }
+}
+
+macro_rules! complex_binary_element {
+  ($($t:ty => $f:ty),*) => {
+    $( impl BinaryElement for $t {
+      fn write_bytes(data: &[Self], out: &mut Vec<u8>) {
+        let parts: Vec<$f> = data.iter().flat_map(|value| [value.re, value.im]).collect();
+        <$f>::write_bytes(&parts, out);
+      }
+      fn read_bytes(bytes: &[u8]) -> Result<Vec<Self>, &'static str> {
+        let parts = <$f>::read_bytes(bytes)?;
+        if parts.len() % 2 != 0 {
+          return Err("Complex data holds an odd number of parts");
+        }
+        Ok(parts.chunks_exact(2).map(|pair| <$t>::new(pair[0], pair[1])).collect())
+      }
+    } )*
+  }
+}
+
+complex_binary_element!(Complex32 => f32, Complex64 => f64);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip<T: BinaryElement + PartialEq + std::fmt::Debug>(data: Vec<T>) {
+        let mut bytes = Vec::new();
+        T::write_bytes(&data, &mut bytes);
+        assert_eq!(
+            T::read_bytes(&bytes).unwrap(),
+            data,
+            "Failure to round trip"
+        );
+    }
+
+    #[test]
+    fn test_round_trips() {
+        round_trip(vec![-1_i32, 0, 7]);
+        round_trip(vec![0.5_f64, f64::MAX]);
+        round_trip(vec![true, false]);
+        round_trip(vec!['a', 'é']);
+        round_trip(vec!["".to_string(), "vortex".to_string()]);
+        round_trip(vec![Bytes::from_static(&[1, 2, 3])]);
+        round_trip(vec![Complex32::new(1.0, -1.0)]);
+    }
+
+    #[test]
+    fn test_malformed_bytes() {
+        assert!(
+            u32::read_bytes(&[0, 1, 2]).is_err(),
+            "Accepted partial element"
+        );
+        assert!(bool::read_bytes(&[2]).is_err(), "Accepted invalid boolean");
+        assert!(String::read_bytes(&[5, 0, 0, 0, 0, 0, 0, 0, b'a']).is_err());
+    }
+}
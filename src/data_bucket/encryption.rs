@@ -0,0 +1,401 @@
+//! encryption
+//!
+//! Authenticated encryption of blob data and encoded buckets with ChaCha20-Poly1305
+//!
+//! the AEAD construction of RFC 8439 comes from the `chacha20poly1305` crate. Key material
+//! and nonces are supplied by the caller and a nonce must never be reused with the same key
+//! (a `NonceSequence` hands out nonces that do not repeat), only the data is encrypted,
+//! the meta data and validity mask stay readable and the blob name is authenticated along
+//! with the data so ciphertexts cannot be swapped between blobs
+
+use super::encoding::BinaryElement;
+use super::mask::ValidityMask;
+use super::{DataBlob, DataBucket, MetaData};
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use std::marker::PhantomData;
+
+/// length in bytes of authentication tags
+pub const TAG_SIZE: usize = 16;
+
+/// EncryptionKey
+/// A 256 bit secret key
+#[derive(Clone)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    /// constructor from raw key material
+    pub fn new(key: [u8; 32]) -> Self {
+        Self(key)
+    }
+    /// constructor from a slice (returns an error unless it holds exactly 32 bytes)
+    pub fn from_slice(key: &[u8]) -> Result<Self, &'static str> {
+        Ok(Self(
+            key.try_into().map_err(|_| "Keys must be 32 bytes long")?,
+        ))
+    }
+}
+
+/// keys are never printed
+impl std::fmt::Debug for EncryptionKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("EncryptionKey(..)")
+    }
+}
+
+/// NonceSequence
+/// Nonces that never repeat, a fixed prefix followed by a 64 bit counter
+///
+/// a sequence hands each nonce out once, so sealing everything encrypted under a key with
+/// nonces of a single sequence (or of sequences with distinct prefixes) never reuses one
+#[derive(Debug)]
+pub struct NonceSequence {
+    prefix: [u8; 4],
+    counter: Option<u64>,
+}
+
+impl NonceSequence {
+    /// constructor starting at a counter of zero
+    pub fn new(prefix: [u8; 4]) -> Self {
+        Self {
+            prefix,
+            counter: Some(0),
+        }
+    }
+    /// the next nonce of the sequence (returns an error once all 2^64 nonces were used)
+    pub fn next_nonce(&mut self) -> Result<[u8; 12], &'static str> {
+        let counter = self.counter.ok_or("Nonce sequence exhausted")?;
+        self.counter = counter.checked_add(1);
+        let mut nonce = [0; 12];
+        nonce[..4].copy_from_slice(&self.prefix);
+        nonce[4..].copy_from_slice(&counter.to_be_bytes());
+        Ok(nonce)
+    }
+}
+
+fn cipher(key: &EncryptionKey) -> ChaCha20Poly1305 {
+    ChaCha20Poly1305::new(&Key::from(key.0))
+}
+
+/// encrypt and authenticate a plaintext along with associated data, returns the ciphertext
+/// followed by its tag (returns an error for plaintexts over 256 GiB)
+pub fn seal(
+    key: &EncryptionKey,
+    nonce: &[u8; 12],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Result<Vec<u8>, &'static str> {
+    cipher(key)
+        .encrypt(
+            &Nonce::from(*nonce),
+            Payload {
+                msg: plaintext,
+                aad,
+            },
+        )
+        .map_err(|_| "Data too long to encrypt under a single nonce")
+}
+
+/// authenticate and decrypt a ciphertext written by `seal` (returns an error if the key,
+/// nonce, associated data or ciphertext do not match)
+pub fn open(
+    key: &EncryptionKey,
+    nonce: &[u8; 12],
+    aad: &[u8],
+    sealed: &[u8],
+) -> Result<Vec<u8>, &'static str> {
+    if sealed.len() < TAG_SIZE {
+        return Err("Encrypted data shorter than its tag");
+    }
+    cipher(key)
+        .decrypt(&Nonce::from(*nonce), Payload { msg: sealed, aad })
+        .map_err(|_| "Failure to authenticate encrypted data")
+}
+
+/// EncryptedBlob
+/// A DataBlob whose data is held encrypted in memory
+#[derive(Clone)]
+pub struct EncryptedBlob<T> {
+    nonce: [u8; 12],
+    sealed: Vec<u8>,
+    meta: MetaData,
+    mask: Option<ValidityMask>,
+    phantom: PhantomData<T>,
+}
+
+impl<T: BinaryElement> EncryptedBlob<T> {
+    /// the nonce the data was encrypted with
+    pub fn get_nonce(&self) -> &[u8; 12] {
+        &self.nonce
+    }
+    /// the encrypted data followed by its tag
+    pub fn get_sealed(&self) -> &[u8] {
+        &self.sealed
+    }
+    /// get the associated meta data immutably
+    pub fn get_meta_data(&self) -> &MetaData {
+        &self.meta
+    }
+    /// decrypt back into a plain blob, verifying its checksum if it has one (returns an
+    /// error for a wrong key or tampered data)
+    pub fn decrypt(&self, key: &EncryptionKey) -> Result<DataBlob<T>, &'static str> {
+        let bytes = open(key, &self.nonce, self.meta.name.as_bytes(), &self.sealed)?;
+        let blob = DataBlob::new(T::read_bytes(&bytes)?, self.meta.clone());
+        match &self.mask {
+            Some(mask) => blob.with_mask(mask.clone())?,
+            None => blob,
+        }
+        .verify_if_present()
+    }
+}
+
+impl<T: BinaryElement> DataBlob<T> {
+    /// encrypt the data of the blob with a key and a nonce never used with that key before
+    ///
+    /// returns an error for data over 256 GiB
+    pub fn encrypt(
+        &self,
+        key: &EncryptionKey,
+        nonce: [u8; 12],
+    ) -> Result<EncryptedBlob<T>, &'static str> {
+        let mut bytes = Vec::new();
+        T::write_bytes(&self.data, &mut bytes);
+        Ok(EncryptedBlob {
+            nonce,
+            sealed: seal(key, &nonce, self.meta.name.as_bytes(), &bytes)?,
+            meta: self.meta.clone(),
+            mask: self.mask.clone(),
+            phantom: PhantomData,
+        })
+    }
+}
+
+/// the associated data of encrypted buckets, so they cannot pass for encrypted blob data
+const BUCKET_AAD: &[u8] = b"bitvortex bucket";
+
+/// EncryptedBucket
+/// The wire encoding of a DataBucket held encrypted, with the nonce it was encrypted with
+#[derive(Clone, Debug, PartialEq)]
+pub struct EncryptedBucket {
+    nonce: [u8; 12],
+    sealed: Vec<u8>,
+}
+
+impl EncryptedBucket {
+    /// the nonce the bucket was encrypted with
+    pub fn get_nonce(&self) -> &[u8; 12] {
+        &self.nonce
+    }
+    /// the encrypted encoding followed by its tag
+    pub fn get_sealed(&self) -> &[u8] {
+        &self.sealed
+    }
+    /// decrypt and decode back into a bucket (returns an error for a wrong key, tampered
+    /// data or an invalid encoding)
+    pub fn decrypt(&self, key: &EncryptionKey) -> Result<DataBucket, &'static str> {
+        DataBucket::from_bytes(&open(key, &self.nonce, BUCKET_AAD, &self.sealed)?)
+    }
+    /// encode as the nonce followed by the encrypted encoding and its tag
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [&self.nonce[..], &self.sealed].concat()
+    }
+    /// decode bytes written by `to_bytes` (returns an error if they are too short to hold
+    /// a nonce and a tag)
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        if bytes.len() < 12 + TAG_SIZE {
+            return Err("Encrypted bucket shorter than its nonce and tag");
+        }
+        let (nonce, sealed) = bytes.split_at(12);
+        Ok(Self {
+            nonce: nonce.try_into().unwrap(),
+            sealed: sealed.to_vec(),
+        })
+    }
+}
+
+impl DataBucket {
+    /// encrypt the wire encoding of the bucket with a key and a nonce never used with that
+    /// key before (returns an error for encodings over 256 GiB)
+    pub fn encrypt(
+        &self,
+        key: &EncryptionKey,
+        nonce: [u8; 12],
+    ) -> Result<EncryptedBucket, &'static str> {
+        Ok(EncryptedBucket {
+            nonce,
+            sealed: seal(key, &nonce, BUCKET_AAD, &self.to_bytes()?)?,
+        })
+    }
+    /// encode the bucket encrypted, readable back with `from_encrypted_bytes`
+    pub fn to_encrypted_bytes(
+        &self,
+        key: &EncryptionKey,
+        nonce: [u8; 12],
+    ) -> Result<Vec<u8>, &'static str> {
+        Ok(self.encrypt(key, nonce)?.to_bytes())
+    }
+    /// decrypt and decode a bucket written by `to_encrypted_bytes`
+    pub fn from_encrypted_bytes(bytes: &[u8], key: &EncryptionKey) -> Result<Self, &'static str> {
+        EncryptedBucket::from_bytes(bytes)?.decrypt(key)
+    }
+}
+
+/// serialized as the bytes of `to_bytes`
+#[cfg(feature = "serde")]
+impl serde::Serialize for EncryptedBucket {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_bytes(&self.to_bytes())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for EncryptedBucket {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let bytes = deserializer.deserialize_bytes(super::wire::BytesVisitor)?;
+        EncryptedBucket::from_bytes(&bytes).map_err(serde::de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(text: &str) -> Vec<u8> {
+        let digits: Vec<u8> = text.bytes().filter(u8::is_ascii_hexdigit).collect();
+        digits
+            .chunks(2)
+            .map(|pair| u8::from_str_radix(std::str::from_utf8(pair).unwrap(), 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_rfc8439_vector() {
+        // 2.8.2, the AEAD construction
+        let key = EncryptionKey::from_slice(&(0x80..=0x9f).collect::<Vec<u8>>()).unwrap();
+        let nonce: [u8; 12] = hex("070000004041424344454647").try_into().unwrap();
+        let aad = hex("50515253c0c1c2c3c4c5c6c7");
+        let plaintext = b"Ladies and Gentlemen of the class of '99: If I could offer you \
+only one tip for the future, sunscreen would be it.";
+        let sealed = seal(&key, &nonce, &aad, plaintext).unwrap();
+        let ciphertext = hex(
+            "d31a8d34648e60db7b86afbc53ef7ec2 a4aded51296e08fea9e2b5a736ee62d6
+             3dbea45e8ca9671282fafb69da92728b 1a71de0a9e060b2905d6a5b67ecd3b36
+             92ddbd7f2d778b8c9803aee328091b58 fab324e4fad675945585808b4831d7bc
+             3ff4def08e4b7a9de576d26586cec64b 6116",
+        );
+        let tag = hex("1ae10b594f09e26a7e902ecbd0600691");
+        assert_eq!(sealed, [ciphertext, tag].concat());
+        assert_eq!(
+            open(&key, &nonce, &aad, &sealed).unwrap(),
+            plaintext.to_vec()
+        );
+    }
+
+    #[test]
+    fn test_open_failures() {
+        let key = EncryptionKey::new([7; 32]);
+        let nonce = [1; 12];
+        let sealed = seal(&key, &nonce, b"aad", b"some secret data").unwrap();
+        let failure = Err("Failure to authenticate encrypted data");
+        for index in 0..sealed.len() {
+            let mut tampered = sealed.clone();
+            tampered[index] ^= 0x80;
+            assert_eq!(open(&key, &nonce, b"aad", &tampered), failure, "{index}");
+        }
+        assert_eq!(
+            open(&key, &nonce, b"aad", &sealed[..sealed.len() - 1]),
+            failure
+        );
+        assert_eq!(
+            open(&key, &nonce, b"aad", &sealed[..TAG_SIZE - 1]),
+            Err("Encrypted data shorter than its tag")
+        );
+        assert_eq!(open(&key, &nonce, b"aaa", &sealed), failure);
+        assert_eq!(open(&key, &[2; 12], b"aad", &sealed), failure);
+        assert_eq!(
+            open(&EncryptionKey::new([8; 32]), &nonce, b"aad", &sealed),
+            failure
+        );
+        assert_eq!(
+            open(&key, &nonce, b"aad", &sealed),
+            Ok(b"some secret data".to_vec())
+        );
+        assert!(EncryptionKey::from_slice(&[0; 16]).is_err());
+    }
+
+    #[test]
+    fn test_nonce_sequence() {
+        let key = EncryptionKey::new([7; 32]);
+        let mut nonces = NonceSequence::new([9; 4]);
+        let first = nonces.next_nonce().unwrap();
+        let second = nonces.next_nonce().unwrap();
+        assert_ne!(first, second);
+        assert_eq!(first, [9, 9, 9, 9, 0, 0, 0, 0, 0, 0, 0, 0]);
+        // the same plaintext under distinct nonces encrypts differently
+        let sealed = seal(&key, &first, b"", b"plaintext").unwrap();
+        assert_ne!(sealed, seal(&key, &second, b"", b"plaintext").unwrap());
+        assert!(open(&key, &second, b"", &sealed).is_err());
+        let mut last = NonceSequence {
+            prefix: [0; 4],
+            counter: Some(u64::MAX),
+        };
+        assert_eq!(last.next_nonce().unwrap()[4..], [0xff; 8]);
+        assert_eq!(last.next_nonce(), Err("Nonce sequence exhausted"));
+    }
+
+    #[test]
+    fn test_encrypted_blob() {
+        let meta = MetaData {
+            name: "secret".to_string(),
+            ..Default::default()
+        };
+        let blob = DataBlob::from_options(vec![Some(1.5_f64), None, Some(-3.0)], meta);
+        let key = EncryptionKey::new([7; 32]);
+        let encrypted = blob.encrypt(&key, [1; 12]).unwrap();
+        assert_eq!(encrypted.get_sealed().len(), 3 * 8 + TAG_SIZE);
+        let decrypted = encrypted.decrypt(&key).unwrap();
+        assert_eq!(decrypted.to_options(), blob.to_options());
+        assert!(encrypted.decrypt(&EncryptionKey::new([8; 32])).is_err());
+        let mut tampered = encrypted.clone();
+        tampered.sealed[0] ^= 1;
+        assert_eq!(
+            tampered.decrypt(&key).err(),
+            Some("Failure to authenticate encrypted data")
+        );
+        let mut renamed = encrypted;
+        renamed.meta.name = "public".to_string();
+        assert!(
+            renamed.decrypt(&key).is_err(),
+            "Blob name not authenticated"
+        );
+    }
+
+    #[test]
+    fn test_encrypted_bucket() {
+        let meta = MetaData {
+            name: "secret".to_string(),
+            ..Default::default()
+        };
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(super::super::DataBucketBlob::U8(DataBlob::new(
+            vec![1, 2, 3],
+            meta,
+        )));
+        let key = EncryptionKey::new([7; 32]);
+        let bytes = bucket.to_encrypted_bytes(&key, [2; 12]).unwrap();
+        assert_eq!(&bytes[..12], &[2; 12]);
+        assert!(!bytes.windows(6).any(|window| window == b"secret"));
+        let decoded = DataBucket::from_encrypted_bytes(&bytes, &key).unwrap();
+        assert_eq!(decoded.to_bytes(), bucket.to_bytes());
+        assert!(DataBucket::from_encrypted_bytes(&bytes, &EncryptionKey::new([8; 32])).is_err());
+        assert!(DataBucket::from_encrypted_bytes(&bytes[..20], &key).is_err());
+        #[cfg(feature = "serde")]
+        {
+            let encrypted = bucket.encrypt(&key, [3; 12]).unwrap();
+            let json = serde_json::to_string(&encrypted).unwrap();
+            let decoded: EncryptedBucket = serde_json::from_str(&json).unwrap();
+            assert_eq!(decoded, encrypted);
+            assert!(decoded.decrypt(&key).is_ok());
+        }
+    }
+}
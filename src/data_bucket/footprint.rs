@@ -0,0 +1,307 @@
+//! footprint
+//!
+//! Memory accounting of blobs and buckets
+//!
+//! sizes count the bytes of the elements held plus the heap bytes they own, the meta data
+//! of blobs and the bookkeeping of buckets, allocator overhead and spare vector capacity
+//! are not counted and blobs shared between buckets are counted in each of them
+
+use super::half::{BF16, F16};
+use super::mask::ValidityMask;
+use super::provenance::ProvenanceEntry;
+use super::{for_each_variant, DataBlob, DataBucket, DataBucketBlob, Link, MetaData};
+use bytes::Bytes;
+use num_complex::{Complex32, Complex64};
+use std::mem::size_of;
+use std::ops::Add;
+use std::sync::Arc;
+
+/// ByteSize
+/// A trait for the elements whose memory footprint can be measured
+pub trait ByteSize {
+    /// bytes held by the value including the heap memory it owns
+    fn byte_size(&self) -> usize;
+}
+
+macro_rules! fixed_byte_size {
+  ($($t:ty),*) => {
+    $( impl ByteSize for $t {
+      fn byte_size(&self) -> usize {
+        size_of::<$t>()
+      }
+    } )*
+  }
+}
+
+fixed_byte_size!(
+    bool, char, i8, u8, i16, u16, i32, u32, i64, u64, i128, u128, isize, usize, f32, f64, F16,
+    BF16, Complex32, Complex64
+);
+
+impl ByteSize for String {
+    fn byte_size(&self) -> usize {
+        size_of::<String>() + self.len()
+    }
+}
+
+impl ByteSize for Bytes {
+    fn byte_size(&self) -> usize {
+        size_of::<Bytes>() + self.len()
+    }
+}
+
+impl<T: ByteSize> ByteSize for Option<T> {
+    fn byte_size(&self) -> usize {
+        match self {
+            Some(value) => size_of::<Option<T>>() - size_of::<T>() + value.byte_size(),
+            None => size_of::<Option<T>>(),
+        }
+    }
+}
+
+/// Footprint
+/// A trait for the values whose total memory usage can be accounted for, such as the items
+/// queued in buffers and channels
+pub trait Footprint {
+    /// total bytes held by the value
+    fn footprint(&self) -> usize;
+}
+
+impl<T: ByteSize> Footprint for T {
+    fn footprint(&self) -> usize {
+        self.byte_size()
+    }
+}
+
+/// MemoryUsage
+/// The bytes held by the parts of a blob or bucket
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct MemoryUsage {
+    /// elements of the blobs
+    pub data: usize,
+    /// validity masks of the blobs
+    pub masks: usize,
+    /// meta data of the blobs except their links
+    pub meta_data: usize,
+    /// links between blobs
+    pub links: usize,
+    /// blob handles, names and groups of buckets
+    pub overhead: usize,
+    /// data of spilled blobs held on disk rather than in memory, not part of the total
+    pub spilled: usize,
+}
+
+impl MemoryUsage {
+    /// total number of bytes
+    pub fn total(&self) -> usize {
+        self.data + self.masks + self.meta_data + self.links + self.overhead
+    }
+}
+
+impl Add for MemoryUsage {
+    type Output = MemoryUsage;
+    fn add(self, other: MemoryUsage) -> MemoryUsage {
+        MemoryUsage {
+            data: self.data + other.data,
+            masks: self.masks + other.masks,
+            meta_data: self.meta_data + other.meta_data,
+            links: self.links + other.links,
+            overhead: self.overhead + other.overhead,
+            spilled: self.spilled + other.spilled,
+        }
+    }
+}
+
+impl ValidityMask {
+    /// bytes held by the bitmap
+    pub fn byte_size(&self) -> usize {
+        size_of::<ValidityMask>() + self.len().div_ceil(8)
+    }
+}
+
+fn option_string_size(text: &Option<String>) -> usize {
+    text.as_ref().map_or(0, String::len)
+}
+
+impl MetaData {
+    /// bytes held by the links
+    pub fn links_byte_size(&self) -> usize {
+        self.links
+            .iter()
+            .map(|link| size_of::<Link>() + link.linker.len() + link.linkee.len())
+            .sum()
+    }
+    /// bytes held by the meta data except the links
+    pub fn byte_size(&self) -> usize {
+        let provenance: usize = self
+            .provenance
+            .iter()
+            .map(|entry| {
+                let parameters: usize = entry
+                    .parameters
+                    .iter()
+                    .map(|(name, value)| size_of::<(String, String)>() + name.len() + value.len())
+                    .sum();
+                size_of::<ProvenanceEntry>() + entry.operation.len() + parameters
+            })
+            .sum();
+        size_of::<MetaData>()
+            + self.name.len()
+            + option_string_size(&self.units)
+            + option_string_size(&self.description)
+            + (self.dimensions.len() + self.unitary_dimensions.len()) * size_of::<usize>()
+            + self.axes.iter().map(String::byte_size).sum::<usize>()
+            + provenance
+    }
+}
+
+impl<T: ByteSize> DataBlob<T> {
+    /// bytes held by the elements of the blob
+    pub fn byte_size(&self) -> usize {
+        self.data.iter().map(ByteSize::byte_size).sum()
+    }
+    /// bytes held by the blob, its mask and meta data
+    pub fn memory_usage(&self) -> MemoryUsage {
+        MemoryUsage {
+            data: self.byte_size(),
+            masks: self.mask.as_ref().map_or(0, ValidityMask::byte_size),
+            meta_data: self.meta.byte_size(),
+            links: self.meta.links_byte_size(),
+            overhead: size_of::<DataBlob<T>>()
+                - size_of::<MetaData>()
+                - size_of::<Option<ValidityMask>>(),
+            spilled: 0,
+        }
+    }
+}
+
+macro_rules! memory_usage_unwrap {
+  ($($x:ident),*) => {
+    /// bytes held by the blob, its mask and meta data
+    pub fn memory_usage(&self) -> MemoryUsage {
+      match self {
+        $( DataBucketBlob::$x(blob) => blob.memory_usage(), )*
+      }
+    }
+  }
+}
+
+impl DataBucketBlob {
+    for_each_variant!(memory_usage_unwrap);
+    /// bytes held by the elements of the blob
+    pub fn byte_size(&self) -> usize {
+        self.memory_usage().data
+    }
+}
+
+impl DataBucket {
+    /// bytes held by the blobs and groups of the bucket and their bookkeeping, spilled and
+    /// lazy blobs only count their meta data and masks until loaded
+    pub fn memory_usage(&self) -> MemoryUsage {
+        let bookkeeping = MemoryUsage {
+            overhead: size_of::<DataBucket>()
+                + self
+                    .data
+                    .keys()
+                    .map(|name| size_of::<String>() + name.len() + size_of::<Arc<DataBucketBlob>>())
+                    .sum::<usize>()
+                + self
+                    .deferred
+                    .keys()
+                    .map(|name| size_of::<String>() + name.len())
+                    .sum::<usize>()
+                + self
+                    .groups
+                    .keys()
+                    .map(|name| size_of::<String>() + name.len())
+                    .sum::<usize>(),
+            ..Default::default()
+        };
+        let blobs = self
+            .data
+            .values()
+            .map(|blob| blob.memory_usage())
+            .fold(bookkeeping, Add::add);
+        let blobs = self
+            .deferred
+            .values()
+            .map(|blob| blob.memory_usage())
+            .fold(blobs, Add::add);
+        self.groups
+            .values()
+            .map(DataBucket::memory_usage)
+            .fold(blobs, Add::add)
+    }
+}
+
+impl<T: ByteSize> Footprint for DataBlob<T> {
+    fn footprint(&self) -> usize {
+        self.memory_usage().total()
+    }
+}
+
+impl Footprint for DataBucketBlob {
+    fn footprint(&self) -> usize {
+        self.memory_usage().total()
+    }
+}
+
+impl Footprint for DataBucket {
+    fn footprint(&self) -> usize {
+        self.memory_usage().total()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::LinkType;
+
+    #[test]
+    fn test_blob_sizes() {
+        let floats = DataBlob::new(vec![0.0_f64; 100], MetaData::default());
+        assert_eq!(floats.byte_size(), 800);
+        let texts = DataBlob::new(
+            vec!["ab".to_string(), "cde".to_string()],
+            MetaData::default(),
+        );
+        assert_eq!(texts.byte_size(), 2 * size_of::<String>() + 5);
+        let masked = DataBlob::from_options(vec![Some(1_u8), None], MetaData::default());
+        assert_eq!(masked.memory_usage().masks, size_of::<ValidityMask>() + 1);
+        let mut meta = MetaData {
+            name: "x".to_string(),
+            ..Default::default()
+        };
+        let base = meta.byte_size();
+        meta.record("op", &[("k", "v".to_string())]);
+        assert!(meta.byte_size() > base, "Provenance not counted");
+    }
+
+    #[test]
+    fn test_bucket_usage() {
+        let mut bucket = DataBucket::new();
+        let meta = MetaData {
+            name: "values".to_string(),
+            links: vec![Link {
+                nature: LinkType::OneToOne,
+                linker: "values".to_string(),
+                linkee: "index".to_string(),
+            }],
+            ..Default::default()
+        };
+        let blob = DataBlob::new(vec![1_i32; 10], meta);
+        let expected = blob.memory_usage();
+        bucket.add_blob(DataBucketBlob::Int32(blob));
+        let mut group = DataBucket::new();
+        group.add_blob(DataBucketBlob::U8(DataBlob::new(
+            vec![0; 3],
+            MetaData::default(),
+        )));
+        bucket.add_group("group", group);
+        let usage = bucket.memory_usage();
+        assert_eq!(usage.data, 43);
+        assert_eq!(usage.links, expected.links);
+        assert_eq!(expected.links, size_of::<Link>() + 11);
+        assert!(usage.total() > usage.data + usage.links + usage.meta_data);
+    }
+}
@@ -0,0 +1,485 @@
+//! gather
+//!
+//! Following the `Handle` links of a bucket, from a blob of indices to the units of the
+//! blob it links to
+//!
+//! gathering copies the units the handles point at into a new blob, one unit per handle,
+//! scattering writes the units of a blob holding one unit per handle back to the units
+//! the handles point at. Invalid handles point nowhere, they gather invalid units and
+//! scatter nothing
+
+use super::mask::ValidityMask;
+use super::schema::{handshake_blobs, BlobKind};
+use super::{DataBlob, DataBucket, DataBucketBlob, Link, LinkType};
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// the unit every element of a handle blob points at
+fn handle_units<T: Copy + TryInto<usize>>(
+    blob: &DataBlob<T>,
+) -> Result<Vec<Option<usize>>, &'static str> {
+    blob.data
+        .iter()
+        .enumerate()
+        .map(|(index, handle)| match blob.is_valid(index) {
+            true => (*handle)
+                .try_into()
+                .map(Some)
+                .map_err(|_| "Handle index out of range"),
+            false => Ok(None),
+        })
+        .collect()
+}
+
+macro_rules! handles_unwrap {
+  ($($x:ident),*) => {
+    /// the unit every element of a handle blob points at
+    fn handles(&self) -> Result<Vec<Option<usize>>, &'static str> {
+      match self {
+        $( DataBucketBlob::$x(blob) => handle_units(blob), )*
+        _ => Err("Handle blob does not hold integer indices"),
+      }
+    }
+  }
+}
+
+impl<T: Clone + Default> DataBlob<T> {
+    /// copy the units handles point at into a new blob, with an invalid placeholder unit
+    /// for every invalid handle
+    fn gather_units(&self, handles: &[Option<usize>]) -> Result<Self, &'static str> {
+        let (size, count) = (self.unit_size(), self.unit_count());
+        if handles.iter().flatten().any(|unit| *unit >= count) {
+            return Err("Handle index out of range");
+        }
+        let mut data = Vec::with_capacity(handles.len() * size);
+        let mut validity = Vec::with_capacity(handles.len() * size);
+        for handle in handles {
+            match handle {
+                Some(unit) => {
+                    data.extend_from_slice(&self.data[unit * size..(unit + 1) * size]);
+                    validity.extend((unit * size..(unit + 1) * size).map(|i| self.is_valid(i)));
+                }
+                None => {
+                    data.extend(std::iter::repeat_with(T::default).take(size));
+                    validity.extend(std::iter::repeat_n(false, size));
+                }
+            }
+        }
+        let mut meta = self.meta.clone();
+        meta.dimensions = vec![handles.len()];
+        if size > 1 {
+            meta.dimensions.extend(self.meta.unitary_dimensions.iter());
+        }
+        let mask = match validity.iter().all(|valid| *valid) {
+            true => None,
+            false => Some(ValidityMask::from_bools(validity)),
+        };
+        Ok(Self { data, meta, mask })
+    }
+    /// write the units of a blob to the units handles point at
+    fn scatter_units(
+        &mut self,
+        handles: &[Option<usize>],
+        values: &Self,
+    ) -> Result<(), &'static str> {
+        let (size, count) = (self.unit_size(), self.unit_count());
+        if values.unit_size() != size || values.unit_count() != handles.len() {
+            return Err("Scattered blob does not match its handles");
+        }
+        if handles.iter().flatten().any(|unit| *unit >= count) {
+            return Err("Handle index out of range");
+        }
+        if self.mask.is_none() && values.mask.is_some() {
+            self.mask = Some(ValidityMask::all_valid(self.data.len()));
+        }
+        for (at, handle) in handles.iter().enumerate() {
+            let Some(unit) = handle else { continue };
+            for offset in 0..size {
+                let (target, source) = (unit * size + offset, at * size + offset);
+                self.data[target] = values.data[source].clone();
+                if let Some(mask) = &mut self.mask {
+                    mask.set(target, values.is_valid(source));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+macro_rules! gather_unwrap {
+  ($($x:ident),*) => {
+    fn gather_units(&self, handles: &[Option<usize>]) -> Result<DataBucketBlob, &'static str> {
+      match self {
+        $( DataBucketBlob::$x(blob) => blob.gather_units(handles).map(DataBucketBlob::$x), )*
+      }
+    }
+  }
+}
+
+macro_rules! scatter_unwrap {
+  ($($x:ident),*) => {
+    fn scatter_units(
+      &mut self,
+      handles: &[Option<usize>],
+      values: &DataBucketBlob,
+    ) -> Result<(), &'static str> {
+      match (self, values) {
+        $( (DataBucketBlob::$x(blob), DataBucketBlob::$x(values)) => {
+          blob.scatter_units(handles, values)
+        } )*
+        _ => Err("Scattered blob type does not match its target"),
+      }
+    }
+  }
+}
+
+/// whether blobs of a type can hold handles
+fn holds_handles(kind: BlobKind) -> bool {
+    use BlobKind::*;
+    matches!(
+        kind,
+        Int8 | U8
+            | Int16
+            | U16
+            | Int32
+            | U32
+            | Int64
+            | U64
+            | Int128
+            | U128
+            | ISize
+            | USize
+            | Timestamp
+    )
+}
+
+impl DataBucketBlob {
+    handles_unwrap!(
+        Int8, U8, Int16, U16, Int32, U32, Int64, U64, Int128, U128, ISize, USize, Timestamp
+    );
+    super::for_each_variant!(gather_unwrap);
+    super::for_each_variant!(scatter_unwrap);
+}
+
+impl DataBucket {
+    /// the handles of a blob and the name of the blob they point at
+    fn handle_link(&self, handles: &str) -> Result<(Vec<Option<usize>>, String), &'static str> {
+        let blob = self
+            .get_blob(&handles.to_string())
+            .ok_or("Handle blob not found in bucket")?;
+        let link = blob
+            .get_meta_data()
+            .links
+            .iter()
+            .find(|link| matches!(link.nature, LinkType::Handle) && link.linker == handles)
+            .ok_or("Blob holds no handle link")?;
+        Ok((blob.handles()?, link.linkee.clone()))
+    }
+    /// a blob of the units the handles of a blob point at, named after the blob they point
+    /// at and linked `OneToOne` to the handles
+    pub fn gather(&self, handles: &str) -> Result<DataBucketBlob, &'static str> {
+        let (units, linkee) = self.handle_link(handles)?;
+        let target = self
+            .get_blob(&linkee)
+            .ok_or("Linked blob missing from bucket")?;
+        let mut gathered = target.gather_units(&units)?;
+        let meta = gathered.get_mut_meta_data();
+        meta.links = vec![Link {
+            nature: LinkType::OneToOne,
+            linker: linkee.clone(),
+            linkee: handles.to_string(),
+        }];
+        meta.record("gather", &[("handles", handles.to_string())]);
+        Ok(gathered)
+    }
+    /// write the units of a values blob, one per handle, to the units the handles of a blob
+    /// point at (returns an error, leaving the bucket untouched, when the values do not
+    /// match the handles or the blob they point at)
+    pub fn scatter(&mut self, handles: &str, values: &str) -> Result<(), &'static str> {
+        let (units, linkee) = self.handle_link(handles)?;
+        let values_blob = self
+            .get_shared_blob(values)
+            .ok_or("Scattered blob not found in bucket")?;
+        let mut target = self
+            .get_blob(&linkee)
+            .ok_or("Linked blob missing from bucket")?
+            .clone();
+        target.scatter_units(&units, &values_blob)?;
+        target.get_mut_meta_data().record(
+            "scatter",
+            &[
+                ("handles", handles.to_string()),
+                ("values", values.to_string()),
+            ],
+        );
+        self.deferred.remove(&linkee);
+        self.data.insert(linkee, Arc::new(target));
+        Ok(())
+    }
+}
+
+/// GatherPipe
+/// A pipe emitting the blob of the units the handles of a blob point at, for every bucket
+///
+/// the gathered blob is named after the blob the handles point at unless another name is
+/// set, a failure to gather ends the stream and is reported by `error`
+pub struct GatherPipe {
+    handles: String,
+    name: Option<String>,
+    error: Rc<Cell<Option<&'static str>>>,
+    input: Option<Rc<dyn Source<DataBucket>>>,
+}
+
+impl GatherPipe {
+    /// constructor following the handle link of the named blob
+    pub fn new(handles: &str) -> Self {
+        Self {
+            handles: handles.to_string(),
+            name: None,
+            error: Rc::new(Cell::new(None)),
+            input: None,
+        }
+    }
+    /// set the name of the gathered blobs
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = Some(name.to_string());
+        self
+    }
+    /// the error that ended the last stream, if any
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl Source<DataBucketBlob> for GatherPipe {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBucketBlob>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let (handles, name) = (self.handles.clone(), self.name.clone());
+        let error = self.error.clone();
+        error.set(None);
+        Box::new(
+            input
+                .map(move |bucket| match bucket.gather(&handles) {
+                    Ok(mut blob) => {
+                        if let Some(name) = &name {
+                            blob.get_mut_meta_data().name = name.clone();
+                            blob.get_mut_meta_data().links[0].linker = name.clone();
+                        }
+                        Some(blob)
+                    }
+                    Err(message) => {
+                        error.set(Some(message));
+                        None
+                    }
+                })
+                .take_while(|blob| futures::future::ready(blob.is_some()))
+                .filter_map(futures::future::ready),
+        )
+    }
+}
+
+/// the handle blob must be declared with an integer type by inputs declaring a bucket schema
+impl Pipe<DataBucket, DataBucketBlob> for GatherPipe {
+    fn pipe(&mut self, input: Rc<dyn Source<DataBucket>>) -> Result<(), &'static str> {
+        handshake_blobs(input.as_ref(), &[(&self.handles, holds_handles)])?;
+        self.input = Some(input);
+        Ok(())
+    }
+    fn unpipe(&mut self) {
+        self.input = None;
+    }
+    fn get_input(&self) -> Option<Rc<dyn Source<DataBucket>>> {
+        self.input.clone()
+    }
+}
+
+/// ScatterPipe
+/// A pipe writing the units of a values blob to the units the handles of a blob point at,
+/// for every bucket
+///
+/// a failure to scatter ends the stream and is reported by `error`
+pub struct ScatterPipe {
+    handles: String,
+    values: String,
+    error: Rc<Cell<Option<&'static str>>>,
+    input: Option<Rc<dyn Source<DataBucket>>>,
+}
+
+impl ScatterPipe {
+    /// constructor following the handle link of the named blob
+    pub fn new(handles: &str, values: &str) -> Self {
+        Self {
+            handles: handles.to_string(),
+            values: values.to_string(),
+            error: Rc::new(Cell::new(None)),
+            input: None,
+        }
+    }
+    /// the error that ended the last stream, if any
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl Source<DataBucket> for ScatterPipe {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBucket>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let (handles, values) = (self.handles.clone(), self.values.clone());
+        let error = self.error.clone();
+        error.set(None);
+        Box::new(
+            input
+                .map(move |mut bucket| match bucket.scatter(&handles, &values) {
+                    Ok(()) => Some(bucket),
+                    Err(message) => {
+                        error.set(Some(message));
+                        None
+                    }
+                })
+                .take_while(|bucket| futures::future::ready(bucket.is_some()))
+                .filter_map(futures::future::ready),
+        )
+    }
+}
+
+/// the handle blob must be declared with an integer type, and the values blob declared, by
+/// inputs declaring a bucket schema
+impl Pipe<DataBucket, DataBucket> for ScatterPipe {
+    fn pipe(&mut self, input: Rc<dyn Source<DataBucket>>) -> Result<(), &'static str> {
+        handshake_blobs(
+            input.as_ref(),
+            &[(&self.handles, holds_handles), (&self.values, |_| true)],
+        )?;
+        self.input = Some(input);
+        Ok(())
+    }
+    fn unpipe(&mut self) {
+        self.input = None;
+    }
+    fn get_input(&self) -> Option<Rc<dyn Source<DataBucket>>> {
+        self.input.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::schema::{DeclaredSource, StreamSchema};
+    use super::super::MetaData;
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn make_blob<T>(name: &str, data: Vec<T>, unit: usize, handle_to: Option<&str>) -> DataBlob<T> {
+        let meta = MetaData {
+            name: name.to_string(),
+            dimensions: vec![data.len() / unit],
+            unitary_dimensions: vec![unit],
+            links: handle_to
+                .map(|linkee| Link {
+                    nature: LinkType::Handle,
+                    linker: name.to_string(),
+                    linkee: linkee.to_string(),
+                })
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        DataBlob::new(data, meta)
+    }
+
+    fn make_bucket() -> DataBucket {
+        let mut bucket = DataBucket::new();
+        let nodes = vec![0.0, 0.0, 1.0, 0.0, 1.0, 1.0];
+        bucket.add_blob(DataBucketBlob::Float64(make_blob("nodes", nodes, 2, None)));
+        let corners = make_blob("corners", vec![2u32, 0, 9], 1, Some("nodes"))
+            .with_mask(ValidityMask::from_bools([true, true, false]))
+            .unwrap();
+        bucket.add_blob(DataBucketBlob::U32(corners));
+        bucket
+    }
+
+    #[test]
+    fn test_gather_pipe() {
+        let mut pipe = GatherPipe::new("corners").with_name("points");
+        pipe.pipe(Rc::new(MockSource::new().items([make_bucket()])))
+            .unwrap();
+        let blobs: Vec<DataBucketBlob> = block_on(Pin::from(pipe.stream()).collect());
+        match &blobs[..] {
+            [DataBucketBlob::Float64(points)] => {
+                assert_eq!(points.get_data()[..4], [1.0, 1.0, 0.0, 0.0]);
+                assert_eq!(points.null_count(), 2, "Invalid handle gathered a unit");
+                let meta = points.get_meta_data();
+                assert_eq!(
+                    (meta.name.as_str(), meta.dimensions.clone()),
+                    ("points", vec![3, 2])
+                );
+                assert_eq!(meta.links[0].linkee, "corners");
+            }
+            _ => panic!("Could not gather blob"),
+        }
+        let mut broken = make_bucket();
+        broken.pop_blob("nodes".to_string());
+        pipe.pipe(Rc::new(MockSource::new().items([broken])))
+            .unwrap();
+        assert_eq!(block_on(Pin::from(pipe.stream()).count()), 0);
+        assert_eq!(pipe.error(), Some("Linked blob missing from bucket"));
+        let declared = |schema| {
+            Rc::new(DeclaredSource::new(
+                Rc::new(MockSource::new().items([make_bucket()])),
+                schema,
+            ))
+        };
+        let float_handles = StreamSchema::bucket([("corners", BlobKind::Float64)]);
+        assert_eq!(
+            pipe.pipe(declared(float_handles)),
+            Err("Input declares a bucket blob of a different type than expected")
+        );
+        let missing = StreamSchema::bucket([("nodes", BlobKind::Float64)]);
+        assert!(ScatterPipe::new("corners", "points")
+            .pipe(declared(missing))
+            .is_err());
+        let handles = StreamSchema::bucket([("corners", BlobKind::U32)]);
+        pipe.pipe(declared(handles)).unwrap();
+        assert_eq!(block_on(Pin::from(pipe.stream()).count()), 1);
+    }
+
+    #[test]
+    fn test_scatter_pipe() {
+        let mut bucket = make_bucket();
+        let mut moved = bucket.gather("corners").unwrap();
+        if let DataBucketBlob::Float64(blob) = &mut moved {
+            blob.get_mut_data()[0] = 5.0;
+            blob.get_mut_meta_data().name = "moved".to_string();
+        }
+        bucket.add_blob(moved);
+        let mut pipe = ScatterPipe::new("corners", "moved");
+        pipe.pipe(Rc::new(MockSource::new().items([bucket])))
+            .unwrap();
+        let buckets: Vec<DataBucket> = block_on(Pin::from(pipe.stream()).collect());
+        match buckets[0].get_blob(&"nodes".to_string()) {
+            Some(DataBucketBlob::Float64(nodes)) => {
+                assert_eq!(nodes.get_data(), &vec![0.0, 0.0, 1.0, 0.0, 5.0, 1.0]);
+                assert_eq!(nodes.null_count(), 0, "Invalid handle scattered a unit");
+            }
+            _ => panic!("Could not match blob"),
+        }
+        let mut mismatched = make_bucket();
+        let values = make_blob("values", vec![1.0, 2.0, 3.0], 1, None);
+        mismatched.add_blob(DataBucketBlob::Float64(values));
+        assert_eq!(
+            mismatched.scatter("corners", "values"),
+            Err("Scattered blob does not match its handles")
+        );
+    }
+}
@@ -0,0 +1,224 @@
+//! half
+//!
+//! Reduced precision floats held by `Float16` and `BFloat16` blobs
+//!
+//! both are stored as their 16 bits and compared through f32, conversions from f32 round
+//! to the nearest value (ties to even), overflowing to infinity and keeping NaNs quiet.
+//! Conversions from f64 go through f32, which can round differently from a direct
+//! conversion for values lying exactly between two halves after the first rounding
+
+use std::cmp::Ordering;
+use std::fmt;
+
+/// the half precision bits of an f32
+fn f16_from_f32(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xff) as i32;
+    let mantissa = bits & 0x7f_ffff;
+    if exponent == 0xff {
+        let quiet = if mantissa != 0 { 0x0200 } else { 0 };
+        return sign | 0x7c00 | quiet;
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1f {
+        return sign | 0x7c00;
+    }
+    let round = |half: u32, rest: u32, halfway: u32| {
+        half + (rest > halfway || (rest == halfway && half & 1 == 1)) as u32
+    };
+    if exponent <= 0 {
+        // subnormal halves hold the mantissa with its implicit bit, shifted by the exponent
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        let half = round(
+            mantissa >> shift,
+            mantissa & ((1 << shift) - 1),
+            1 << (shift - 1),
+        );
+        return sign | half as u16;
+    }
+    // a carry out of the mantissa moves to the next exponent, up to infinity
+    let half = round(
+        ((exponent as u32) << 10) | (mantissa >> 13),
+        mantissa & 0x1fff,
+        0x1000,
+    );
+    sign | half as u16
+}
+
+/// the f32 of half precision bits
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = ((bits & 0x8000) as u32) << 16;
+    let exponent = ((bits >> 10) & 0x1f) as u32;
+    let mantissa = (bits & 0x3ff) as u32;
+    match (exponent, mantissa) {
+        (0, _) => {
+            let magnitude = mantissa as f32 / (1 << 24) as f32;
+            if sign != 0 {
+                -magnitude
+            } else {
+                magnitude
+            }
+        }
+        (0x1f, 0) => f32::from_bits(sign | 0x7f80_0000),
+        (0x1f, _) => f32::from_bits(sign | 0x7fc0_0000 | (mantissa << 13)),
+        _ => f32::from_bits(sign | ((exponent + 127 - 15) << 23) | (mantissa << 13)),
+    }
+}
+
+/// the bfloat16 bits of an f32
+fn bf16_from_f32(value: f32) -> u16 {
+    let bits = value.to_bits();
+    if value.is_nan() {
+        return ((bits >> 16) | 0x0040) as u16;
+    }
+    let rounding = 0x7fff + ((bits >> 16) & 1);
+    ((bits + rounding) >> 16) as u16
+}
+
+/// the f32 of bfloat16 bits
+fn bf16_to_f32(bits: u16) -> f32 {
+    f32::from_bits((bits as u32) << 16)
+}
+
+macro_rules! half_type {
+  ($($t:ident => $from:ident, $to:ident, $doc:literal),*) => {
+    $(
+      #[doc = stringify!($t)]
+      #[doc = $doc]
+      #[derive(Clone, Copy, Default)]
+      #[repr(transparent)]
+      pub struct $t(u16);
+
+      impl $t {
+        /// the value of raw bits
+        pub const fn from_bits(bits: u16) -> Self {
+          Self(bits)
+        }
+        /// the raw bits of the value
+        pub const fn to_bits(self) -> u16 {
+          self.0
+        }
+        /// the nearest value to an f32
+        pub fn from_f32(value: f32) -> Self {
+          Self($from(value))
+        }
+        /// the nearest value to an f64
+        pub fn from_f64(value: f64) -> Self {
+          Self::from_f32(value as f32)
+        }
+        /// the value as an f32 (exact)
+        pub fn to_f32(self) -> f32 {
+          $to(self.0)
+        }
+        /// the value as an f64 (exact)
+        pub fn to_f64(self) -> f64 {
+          self.to_f32() as f64
+        }
+        /// whether the value is not a number
+        pub fn is_nan(self) -> bool {
+          self.to_f32().is_nan()
+        }
+      }
+
+      impl PartialEq for $t {
+        fn eq(&self, other: &Self) -> bool {
+          self.to_f32() == other.to_f32()
+        }
+      }
+
+      impl PartialOrd for $t {
+        fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+          self.to_f32().partial_cmp(&other.to_f32())
+        }
+      }
+
+      impl fmt::Debug for $t {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+          fmt::Debug::fmt(&self.to_f32(), f)
+        }
+      }
+
+      impl fmt::Display for $t {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+          fmt::Display::fmt(&self.to_f32(), f)
+        }
+      }
+
+      impl From<$t> for f32 {
+        fn from(value: $t) -> f32 {
+          value.to_f32()
+        }
+      }
+
+      impl From<$t> for f64 {
+        fn from(value: $t) -> f64 {
+          value.to_f64()
+        }
+      }
+    )*
+  }
+}
+
+half_type!(
+    F16 => f16_from_f32, f16_to_f32,
+        "An IEEE 754 half precision float (5 exponent and 10 mantissa bits)",
+    BF16 => bf16_from_f32, bf16_to_f32,
+        "A bfloat16 float, the range of f32 with 7 mantissa bits"
+);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_f16_conversions() {
+        for value in [
+            0.0,
+            -0.0,
+            1.0,
+            -2.5,
+            0.1,
+            65504.0,
+            6.1035156e-5,
+            5.9604645e-8,
+        ] {
+            let half = F16::from_f32(value);
+            assert!(
+                (half.to_f32() - value).abs() <= value.abs() / 1024.0,
+                "{}",
+                value
+            );
+        }
+        assert_eq!(F16::from_f32(1.0).to_bits(), 0x3c00);
+        assert_eq!(F16::from_f32(-2.0).to_bits(), 0xc000);
+        assert_eq!(F16::from_f32(65504.0).to_bits(), 0x7bff);
+        assert_eq!(F16::from_f32(1e6).to_bits(), 0x7c00);
+        assert_eq!(F16::from_f32(5.9604645e-8).to_bits(), 0x0001);
+        assert_eq!(F16::from_f32(1e-9).to_bits(), 0x0000);
+        // 1 + 2^-11 lies halfway between 1 and the next half, ties go to the even mantissa
+        assert_eq!(F16::from_f32(1.0 + 2f32.powi(-11)).to_bits(), 0x3c00);
+        assert_eq!(F16::from_f32(1.0 + 3.0 * 2f32.powi(-11)).to_bits(), 0x3c02);
+        assert!(F16::from_f32(f32::NAN).is_nan());
+        assert_eq!(F16::from_f32(f32::NEG_INFINITY).to_f32(), f32::NEG_INFINITY);
+        assert_eq!(F16::from_bits(0x0001).to_f64(), 2f64.powi(-24));
+        assert!(F16::from_f32(1.0) < F16::from_f32(1.5));
+        assert_eq!(F16::from_f32(0.0), F16::from_f32(-0.0));
+    }
+
+    #[test]
+    fn test_bf16_conversions() {
+        assert_eq!(BF16::from_f32(1.0).to_bits(), 0x3f80);
+        assert!((BF16::from_f32(3.0e38).to_f32() / 3.0e38 - 1.0).abs() <= 1.0 / 256.0);
+        assert_eq!(BF16::from_f32(f32::MAX).to_f32(), f32::INFINITY);
+        assert_eq!(BF16::from_f32(1.0 + 2f32.powi(-8)).to_bits(), 0x3f80);
+        assert_eq!(BF16::from_f32(1.0 + 3.0 * 2f32.powi(-8)).to_bits(), 0x3f82);
+        assert!(BF16::from_f32(f32::NAN).is_nan());
+        assert_eq!(BF16::from_f64(-0.15625).to_f32(), -0.15625);
+        assert_eq!(format!("{}", BF16::from_f32(2.0)), "2");
+    }
+}
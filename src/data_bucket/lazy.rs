@@ -0,0 +1,353 @@
+//! lazy
+//!
+//! Blobs whose data is only loaded when first accessed
+//!
+//! a lazy blob holds its meta data and a loader closure, buckets can hold lazy blobs next
+//! to plain ones so that describing a huge dataset is cheap and only the blobs that are
+//! accessed get loaded, looking blobs up by their meta data does not load them
+
+use super::footprint::MemoryUsage;
+use super::mask::ValidityMask;
+use super::spill::SpillFile;
+use super::{BlobType, DataBlob, DataBucket, DataBucketBlob, MetaData};
+use crate::Source;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::mem::size_of;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// Loader
+/// A closure producing the data of a lazy blob
+pub type Loader<T> = Arc<dyn Fn() -> Result<Vec<T>, &'static str> + Send + Sync>;
+
+/// LazyBlob
+/// A DataBlob whose data is loaded by a closure on first access
+pub struct LazyBlob<T> {
+    meta: MetaData,
+    loader: Loader<T>,
+    data: OnceLock<Vec<T>>,
+    error: Arc<Mutex<Option<&'static str>>>,
+}
+
+impl<T> DataBlob<T> {
+    /// constructor of a blob whose data is only loaded when first accessed
+    pub fn lazy<F>(meta: MetaData, loader: F) -> LazyBlob<T>
+    where
+        F: Fn() -> Result<Vec<T>, &'static str> + Send + Sync + 'static,
+    {
+        LazyBlob {
+            meta,
+            loader: Arc::new(loader),
+            data: OnceLock::new(),
+            error: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+impl<T> LazyBlob<T> {
+    /// get the associated meta data immutably
+    pub fn get_meta_data(&self) -> &MetaData {
+        &self.meta
+    }
+    /// get the associated meta data mutably
+    pub fn get_mut_meta_data(&mut self) -> &mut MetaData {
+        &mut self.meta
+    }
+    /// whether the data has been loaded
+    pub fn is_loaded(&self) -> bool {
+        self.data.get().is_some()
+    }
+    /// get the data, loading it on first access
+    pub fn get_data(&self) -> Result<&Vec<T>, &'static str> {
+        if let Some(data) = self.data.get() {
+            return Ok(data);
+        }
+        let data = (self.loader)()?;
+        Ok(self.data.get_or_init(|| data))
+    }
+    /// the failure of the loader during the last stream, if any
+    pub fn error(&self) -> Option<&'static str> {
+        *self.error.lock().unwrap()
+    }
+    /// drop the loaded data so it is loaded again on the next access
+    pub fn unload(&mut self) {
+        self.data.take();
+    }
+    /// turn into a plain blob, loading the data if it has not been loaded yet
+    pub fn materialize(mut self) -> Result<DataBlob<T>, &'static str> {
+        let data = match self.data.take() {
+            Some(data) => data,
+            None => (self.loader)()?,
+        };
+        Ok(DataBlob::new(data, self.meta))
+    }
+}
+
+/// copies share the loader
+impl<T: Clone> Clone for LazyBlob<T> {
+    fn clone(&self) -> Self {
+        Self {
+            meta: self.meta.clone(),
+            loader: self.loader.clone(),
+            data: self.data.clone(),
+            error: Arc::new(Mutex::new(None)),
+        }
+    }
+}
+
+/// data that has not been loaded yet is loaded once the stream is first polled and
+/// streamed without being kept (a failing loader yields an empty stream and its failure is
+/// kept by `error`)
+impl<T: Clone + 'static> Source<T> for LazyBlob<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        *self.error.lock().unwrap() = None;
+        if let Some(data) = self.data.get() {
+            return Box::new(stream::iter(data.clone()));
+        }
+        let (loader, error) = (self.loader.clone(), self.error.clone());
+        let data = stream::once(async move {
+            loader().unwrap_or_else(|failure| {
+                *error.lock().unwrap() = Some(failure);
+                Vec::new()
+            })
+        });
+        Box::new(data.flat_map(stream::iter))
+    }
+}
+
+type BucketLoader = Arc<dyn Fn(MetaData) -> Result<DataBucketBlob, &'static str> + Send + Sync>;
+
+pub(crate) type Restore =
+    fn(&[u8], MetaData, Option<ValidityMask>) -> Result<DataBucketBlob, &'static str>;
+
+/// where the data of a deferred blob comes from
+#[derive(Clone)]
+pub(crate) enum Origin {
+    /// written to a spill file
+    Spilled {
+        file: Arc<SpillFile>,
+        bytes: usize,
+        restore: Restore,
+    },
+    /// produced by the loader of a lazy blob
+    Loader(BucketLoader),
+}
+
+/// a blob of a bucket whose data is not held in memory until accessed
+pub(crate) struct DeferredBlob {
+    pub(crate) origin: Origin,
+    pub(crate) meta: MetaData,
+    pub(crate) mask: Option<ValidityMask>,
+    pub(crate) resident: OnceLock<Arc<DataBucketBlob>>,
+}
+
+/// copies load the data on their own
+impl Clone for DeferredBlob {
+    fn clone(&self) -> Self {
+        Self {
+            origin: self.origin.clone(),
+            meta: self.meta.clone(),
+            mask: self.mask.clone(),
+            resident: OnceLock::new(),
+        }
+    }
+}
+
+impl DeferredBlob {
+    /// the blob, loading its data if it is not resident
+    pub(crate) fn load(&self) -> Result<&Arc<DataBucketBlob>, &'static str> {
+        if let Some(blob) = self.resident.get() {
+            return Ok(blob);
+        }
+        let blob = match &self.origin {
+            Origin::Spilled { file, restore, .. } => {
+                restore(&file.read()?, self.meta.clone(), self.mask.clone())?
+            }
+            Origin::Loader(loader) => loader(self.meta.clone())?,
+        };
+        Ok(self.resident.get_or_init(|| Arc::new(blob)))
+    }
+    /// whether the data is held in a spill file
+    pub(crate) fn is_spilled(&self) -> bool {
+        matches!(self.origin, Origin::Spilled { .. })
+    }
+    /// the bytes held in memory by the blob, the data only counts while resident
+    pub(crate) fn memory_usage(&self) -> MemoryUsage {
+        if let Some(blob) = self.resident.get() {
+            return blob.memory_usage();
+        }
+        let spilled = match &self.origin {
+            Origin::Spilled { bytes, .. } => *bytes,
+            Origin::Loader(_) => 0,
+        };
+        MemoryUsage {
+            masks: self.mask.as_ref().map_or(0, ValidityMask::byte_size),
+            meta_data: self.meta.byte_size(),
+            links: self.meta.links_byte_size(),
+            overhead: size_of::<DeferredBlob>() - size_of::<MetaData>(),
+            spilled,
+            ..Default::default()
+        }
+    }
+}
+
+impl DataBucket {
+    /// add a lazy blob whose data is only loaded once the blob is accessed (returns the
+    /// blob back if the name is already taken)
+    pub fn add_lazy_blob<T>(&mut self, blob: LazyBlob<T>) -> Option<LazyBlob<T>>
+    where
+        T: BlobType + Send + Sync + 'static,
+    {
+        if self.taken(&blob.meta.name) {
+            return Some(blob);
+        }
+        let LazyBlob {
+            meta, loader, data, ..
+        } = blob;
+        let resident = OnceLock::new();
+        if let Some(data) = data.into_inner() {
+            let _ = resident.set(Arc::new(T::wrap(DataBlob::new(data, meta.clone()))));
+        }
+        let loader: BucketLoader =
+            Arc::new(move |meta| Ok(T::wrap(DataBlob::new(loader()?, meta))));
+        self.deferred.insert(
+            meta.name.clone(),
+            DeferredBlob {
+                origin: Origin::Loader(loader),
+                meta,
+                mask: None,
+                resident,
+            },
+        );
+        None
+    }
+    /// whether the data of the named blob is held in memory
+    pub fn is_resident(&self, blob_name: &str) -> bool {
+        self.data.contains_key(blob_name)
+            || self
+                .deferred
+                .get(blob_name)
+                .is_some_and(|blob| blob.resident.get().is_some())
+    }
+    /// move a deferred blob to memory (returns an error, leaving it deferred, if it fails
+    /// to load)
+    pub(crate) fn restore_blob(&mut self, name: &str) -> Result<(), &'static str> {
+        if let Some(blob) = self.deferred.get(name) {
+            let resident = blob.load()?.clone();
+            self.deferred.remove(name);
+            self.data.insert(name.to_string(), resident);
+        }
+        Ok(())
+    }
+    /// move the deferred blobs of the bucket that can be loaded to memory
+    pub(crate) fn restore_deferred(&mut self) {
+        for (name, blob) in std::mem::take(&mut self.deferred) {
+            match blob.load() {
+                Ok(resident) => {
+                    let resident = resident.clone();
+                    self.data.insert(name, resident);
+                }
+                Err(_) => {
+                    self.deferred.insert(name, blob);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::pin::Pin;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn counted(name: &str, loads: &Arc<AtomicUsize>) -> LazyBlob<f64> {
+        let meta = MetaData {
+            name: name.to_string(),
+            units: Some("m".to_string()),
+            ..Default::default()
+        };
+        let loads = loads.clone();
+        DataBlob::lazy(meta, move || {
+            loads.fetch_add(1, Ordering::SeqCst);
+            Ok(vec![1.0, 2.0, 3.0])
+        })
+    }
+
+    #[test]
+    fn test_lazy_blob() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let mut blob = counted("x", &loads);
+        assert_eq!(loads.load(Ordering::SeqCst), 0, "Loaded on construction");
+        let streamed: Vec<f64> = block_on(Pin::from(blob.stream()).collect());
+        assert_eq!(streamed, vec![1.0, 2.0, 3.0]);
+        assert!(!blob.is_loaded(), "Streamed data was kept");
+        assert_eq!(blob.get_data().unwrap().len(), 3);
+        assert_eq!(blob.get_data().unwrap()[2], 3.0);
+        assert_eq!(loads.load(Ordering::SeqCst), 2);
+        assert_eq!(blob.clone().materialize().unwrap().get_data().len(), 3);
+        blob.unload();
+        assert_eq!(blob.materialize().unwrap().get_data(), &vec![1.0, 2.0, 3.0]);
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+        let failing: LazyBlob<u8> = DataBlob::lazy(MetaData::default(), || Err("Missing file"));
+        assert_eq!(failing.get_data().err(), Some("Missing file"));
+        assert!(block_on(Pin::from(failing.stream()).collect::<Vec<u8>>()).is_empty());
+        assert_eq!(failing.error(), Some("Missing file"));
+    }
+
+    #[test]
+    fn test_failed_loads_are_kept() {
+        let mut bucket = DataBucket::new();
+        let meta = MetaData {
+            name: "broken".to_string(),
+            ..Default::default()
+        };
+        bucket.add_lazy_blob(DataBlob::<u8>::lazy(meta, || Err("Missing file")));
+        let name = "broken".to_string();
+        assert_eq!(bucket.try_get_blob(&name).err(), Some("Missing file"));
+        assert_eq!(
+            bucket.try_iter().next().unwrap().1.err(),
+            Some("Missing file")
+        );
+        assert_eq!(bucket.try_pop_blob(&name).err(), Some("Missing file"));
+        assert!(bucket.pop_blob(name.clone()).is_none());
+        assert!(bucket.contains(&name), "Blob dropped by a failed load");
+        assert!(matches!(bucket.try_get_blob("missing"), Ok(None)));
+        let found: Vec<_> = bucket.try_find(|meta| meta.name == "broken").collect();
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].1.err(), Some("Missing file"));
+        assert_eq!(bucket.find(|_| true).count(), 0);
+        assert_eq!(bucket.try_snapshot().err(), Some("Missing file"));
+    }
+
+    #[test]
+    fn test_only_touched_blobs_load() {
+        let loads = Arc::new(AtomicUsize::new(0));
+        let mut bucket = DataBucket::new();
+        for name in ["a", "b", "c"] {
+            assert!(bucket.add_lazy_blob(counted(name, &loads)).is_none());
+        }
+        assert!(bucket.add_lazy_blob(counted("a", &loads)).is_some());
+        assert_eq!(bucket.len(), 3);
+        assert_eq!(bucket.blobs_with_units("s").count(), 0);
+        assert_eq!(
+            loads.load(Ordering::SeqCst),
+            0,
+            "Meta data lookups loaded blobs"
+        );
+        assert_eq!(bucket.get_blob(&"b".to_string()).unwrap().unit_count(), 3);
+        bucket.get_blob(&"b".to_string()).unwrap();
+        assert_eq!(loads.load(Ordering::SeqCst), 1);
+        assert!(bucket.is_resident("b") && !bucket.is_resident("a"));
+        let usage = bucket.memory_usage();
+        assert_eq!((usage.data, usage.spilled), (24, 0));
+        let popped = bucket.pop_blob("c".to_string()).unwrap();
+        assert_eq!(popped.get_meta_data().units.as_deref(), Some("m"));
+        bucket
+            .iter_mut()
+            .for_each(|(_, blob)| blob.get_mut_meta_data().units = None);
+        assert_eq!(loads.load(Ordering::SeqCst), 3);
+        assert!(bucket.is_resident("a"));
+    }
+}
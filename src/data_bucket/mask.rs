@@ -0,0 +1,234 @@
+//! mask
+//!
+//! Validity masks marking the missing values of a DataBlob
+//!
+//! masks are bitmaps holding one bit per element, least significant bit first, set for
+//! valid elements as in Arrow, the data of a blob keeps a placeholder value at every null
+//! position, a blob without a mask holds only valid elements
+
+use super::DataBlob;
+use crate::Source;
+use futures::stream;
+use futures::Stream;
+
+/// ValidityMask
+/// A bitmap of the valid elements of a blob
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ValidityMask {
+    bits: Vec<u8>,
+    len: usize,
+}
+
+impl ValidityMask {
+    /// constructor of a mask where every element is valid
+    pub fn all_valid(len: usize) -> Self {
+        let mut bits = vec![u8::MAX; len.div_ceil(8)];
+        if !len.is_multiple_of(8) {
+            if let Some(last) = bits.last_mut() {
+                *last = (1 << (len % 8)) - 1;
+            }
+        }
+        Self { bits, len }
+    }
+    /// constructor from the validity of every element
+    pub fn from_bools<I: IntoIterator<Item = bool>>(validity: I) -> Self {
+        let mut mask = Self::default();
+        for valid in validity {
+            mask.push(valid);
+        }
+        mask
+    }
+    /// number of elements covered by the mask
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    /// whether the mask covers no elements
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// whether an element is valid (false past the end)
+    pub fn is_valid(&self, index: usize) -> bool {
+        index < self.len && self.bits[index / 8] & (1 << (index % 8)) != 0
+    }
+    /// mark an element valid or null (ignored past the end)
+    pub fn set(&mut self, index: usize, valid: bool) {
+        if index >= self.len {
+            return;
+        }
+        if valid {
+            self.bits[index / 8] |= 1 << (index % 8);
+        } else {
+            self.bits[index / 8] &= !(1 << (index % 8));
+        }
+    }
+    /// append the validity of one element
+    pub fn push(&mut self, valid: bool) {
+        if self.len.is_multiple_of(8) {
+            self.bits.push(0);
+        }
+        self.len += 1;
+        self.set(self.len - 1, valid);
+    }
+    /// number of null elements
+    pub fn null_count(&self) -> usize {
+        self.len
+            - self
+                .bits
+                .iter()
+                .map(|byte| byte.count_ones() as usize)
+                .sum::<usize>()
+    }
+    /// the validity of every element in order
+    pub fn iter(&self) -> impl Iterator<Item = bool> + '_ {
+        (0..self.len).map(|index| self.is_valid(index))
+    }
+    /// the mask of a range of elements
+    pub fn slice(&self, range: std::ops::Range<usize>) -> Self {
+        Self::from_bools(range.map(|index| self.is_valid(index)))
+    }
+    /// the mask valid where both masks are valid
+    pub fn and(&self, other: &ValidityMask) -> Self {
+        Self::from_bools(
+            (0..self.len.min(other.len)).map(|i| self.is_valid(i) && other.is_valid(i)),
+        )
+    }
+}
+
+/// the mask of the first `len` elements of an operation between two blobs
+pub(crate) fn combine_masks(
+    lhs: Option<&ValidityMask>,
+    rhs: Option<&ValidityMask>,
+    len: usize,
+) -> Option<ValidityMask> {
+    match (lhs, rhs) {
+        (None, None) => None,
+        (Some(mask), None) | (None, Some(mask)) => Some(mask.slice(0..len)),
+        (Some(lhs), Some(rhs)) => Some(lhs.and(rhs).slice(0..len)),
+    }
+}
+
+impl<T> DataBlob<T> {
+    /// set the validity mask of the data (returns an error if it does not cover every element)
+    pub fn with_mask(mut self, mask: ValidityMask) -> Result<Self, &'static str> {
+        if mask.len() != self.data.len() {
+            return Err("Mask length does not match the data length");
+        }
+        self.mask = Some(mask);
+        Ok(self)
+    }
+    /// the validity mask of the data (None when every element is valid)
+    pub fn get_mask(&self) -> Option<&ValidityMask> {
+        self.mask.as_ref()
+    }
+    /// remove the validity mask, making every element valid
+    pub fn take_mask(&mut self) -> Option<ValidityMask> {
+        self.mask.take()
+    }
+    /// whether an element exists and is not null
+    pub fn is_valid(&self, index: usize) -> bool {
+        match &self.mask {
+            Some(mask) => mask.is_valid(index),
+            None => index < self.data.len(),
+        }
+    }
+    /// number of null elements
+    pub fn null_count(&self) -> usize {
+        self.mask.as_ref().map_or(0, ValidityMask::null_count)
+    }
+    /// iterate over the valid elements, skipping nulls
+    pub fn valid_data(&self) -> impl Iterator<Item = &T> + '_ {
+        self.data
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.is_valid(*index))
+            .map(|(_, value)| value)
+    }
+}
+
+impl<T: Clone> DataBlob<T> {
+    /// every element as None when null
+    pub fn to_options(&self) -> Vec<Option<T>> {
+        self.data
+            .iter()
+            .enumerate()
+            .map(|(index, value)| self.is_valid(index).then(|| value.clone()))
+            .collect()
+    }
+}
+
+impl<T: Default> DataBlob<T> {
+    /// constructor from optional values, nulls hold the default value in the data
+    pub fn from_options(values: Vec<Option<T>>, new_meta: super::MetaData) -> Self {
+        let mask = ValidityMask::from_bools(values.iter().map(Option::is_some));
+        let data = values.into_iter().map(Option::unwrap_or_default).collect();
+        let mut blob = Self::new(data, new_meta);
+        if mask.null_count() > 0 {
+            blob.mask = Some(mask);
+        }
+        blob
+    }
+}
+
+/// NullableSource
+/// A source streaming the elements of a blob as options, None for nulls
+pub struct NullableSource<T> {
+    blob: DataBlob<T>,
+}
+
+impl<T> NullableSource<T> {
+    /// constructor
+    pub fn new(blob: DataBlob<T>) -> Self {
+        Self { blob }
+    }
+}
+
+impl<T: Clone + 'static> Source<Option<T>> for NullableSource<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = Option<T>>> {
+        Box::new(stream::iter(self.blob.to_options()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::MetaData;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::pin::Pin;
+
+    #[test]
+    fn test_validity_mask() {
+        let mut mask = ValidityMask::all_valid(10);
+        assert_eq!(mask.null_count(), 0);
+        assert!(mask.is_valid(9) && !mask.is_valid(10));
+        mask.set(3, false);
+        mask.set(9, false);
+        assert_eq!(mask.null_count(), 2);
+        assert_eq!(
+            mask.slice(2..5),
+            ValidityMask::from_bools([true, false, true])
+        );
+        let other = ValidityMask::from_bools([false, true, true, true]);
+        assert_eq!(
+            mask.and(&other).iter().collect::<Vec<_>>(),
+            [false, true, true, false]
+        );
+    }
+
+    #[test]
+    fn test_nullable_blob() {
+        let blob = DataBlob::from_options(vec![Some(1.5), None, Some(-2.0)], MetaData::default());
+        assert_eq!(blob.get_data(), &vec![1.5, 0.0, -2.0]);
+        assert!(blob.is_valid(0) && !blob.is_valid(1) && !blob.is_valid(3));
+        assert_eq!(blob.null_count(), 1);
+        assert_eq!(blob.valid_data().sum::<f64>(), -0.5);
+        let streamed: Vec<Option<f64>> =
+            block_on(Pin::from(NullableSource::new(blob.clone()).stream()).collect());
+        assert_eq!(streamed, vec![Some(1.5), None, Some(-2.0)]);
+        let slice = blob.slice_units(1..3).unwrap();
+        assert_eq!(slice.to_options(), vec![None, Some(-2.0)]);
+        assert!(DataBlob::new(vec![1], MetaData::default())
+            .with_mask(ValidityMask::all_valid(2))
+            .is_err());
+    }
+}
@@ -0,0 +1,94 @@
+//! provenance
+//!
+//! The history of the operations a blob was derived through
+//!
+//! built-in sources, sinks and pipes producing or transforming blobs append an entry to the
+//! provenance of the meta data of their output, lossless storage changes such as
+//! compression or chunking are not recorded
+
+use super::MetaData;
+use std::time::SystemTime;
+
+/// ProvenanceEntry
+/// One operation in the derivation of a blob
+#[derive(Clone, Debug, PartialEq)]
+pub struct ProvenanceEntry {
+    /// name of the operation
+    pub operation: String,
+    /// (name, value) pairs of the parameters of the operation
+    pub parameters: Vec<(String, String)>,
+    /// when the operation was applied
+    pub timestamp: SystemTime,
+}
+
+impl ProvenanceEntry {
+    /// constructor timestamped now
+    pub fn new(operation: &str, parameters: &[(&str, String)]) -> Self {
+        Self {
+            operation: operation.to_string(),
+            parameters: parameters
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.clone()))
+                .collect(),
+            timestamp: SystemTime::now(),
+        }
+    }
+}
+
+impl std::fmt::Display for ProvenanceEntry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parameters: Vec<String> = self
+            .parameters
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        write!(f, "{}({})", self.operation, parameters.join(", "))
+    }
+}
+
+impl MetaData {
+    /// append an operation to the provenance of the data, clearing its checksum
+    pub fn record(&mut self, operation: &str, parameters: &[(&str, String)]) {
+        self.checksum = None;
+        self.provenance
+            .push(ProvenanceEntry::new(operation, parameters));
+    }
+    /// the operations the data was derived through, oldest first, one per line
+    pub fn explain(&self) -> String {
+        self.provenance
+            .iter()
+            .map(ProvenanceEntry::to_string)
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::data_bucket::{DataBlob, MetaData};
+    use crate::sources::{BatchedSource, RampSource};
+    use crate::Source;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::pin::Pin;
+    use std::rc::Rc;
+
+    #[test]
+    fn test_provenance_of_derived_blobs() {
+        let source = BatchedSource::new(
+            Rc::new(RampSource::new(0, 1).with_len(6)),
+            4,
+            MetaData::default(),
+        )
+        .unwrap();
+        let blobs: Vec<DataBlob<i32>> = block_on(Pin::from(source.stream()).collect());
+        let slice = blobs[0].slice_units(1..3).unwrap();
+        let history = &slice.get_meta_data().provenance;
+        assert_eq!(history.len(), 2);
+        assert!(history[0].timestamp <= history[1].timestamp);
+        assert_eq!(
+            slice.get_meta_data().explain(),
+            "batch(batch_size=4)\nslice_units(start=1, end=3)"
+        );
+    }
+}
@@ -0,0 +1,238 @@
+//! registry
+//!
+//! Named MetaData schemas that buckets are validated against
+//!
+//! a schema lists the meta data of the blobs a bucket must hold, a blob satisfies the meta
+//! data of its schema entry when its units match if the entry sets units, its dimensions and
+//! unitary dimensions match where the entry sets them and it holds every link of the entry,
+//! the schemas of a registry are referenced by name through `StreamSchema::Registered`
+
+use super::schema::StreamSchema;
+use super::{DataBucket, MetaData};
+use crate::Source;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// MetaDataSchema
+/// The meta data of the blobs a bucket must hold
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct MetaDataSchema {
+    blobs: Vec<MetaData>,
+}
+
+impl MetaDataSchema {
+    /// empty constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// require a blob described by the meta data
+    pub fn blob(mut self, meta: MetaData) -> Self {
+        self.blobs.push(meta);
+        self
+    }
+    /// the meta data of the required blobs
+    pub fn blobs(&self) -> &[MetaData] {
+        &self.blobs
+    }
+    /// check the meta data of a blob against the entry of the same name
+    pub fn validate_meta_data(&self, meta: &MetaData) -> Result<(), &'static str> {
+        let expected = self
+            .blobs
+            .iter()
+            .find(|expected| expected.name == meta.name)
+            .ok_or("Blob is not part of the schema")?;
+        if expected.units.is_some() && expected.units != meta.units {
+            return Err("Blob units do not match the schema");
+        }
+        if !expected.dimensions.is_empty() && expected.dimensions != meta.dimensions {
+            return Err("Blob dimensions do not match the schema");
+        }
+        if !expected.unitary_dimensions.is_empty()
+            && expected.unitary_dimensions != meta.unitary_dimensions
+        {
+            return Err("Blob unitary dimensions do not match the schema");
+        }
+        if !expected.links.iter().all(|link| meta.links.contains(link)) {
+            return Err("Blob lacks a link of the schema");
+        }
+        Ok(())
+    }
+    /// check that a bucket holds every blob of the schema with matching meta data
+    pub fn validate(&self, bucket: &DataBucket) -> Result<(), &'static str> {
+        for expected in self.blobs.iter() {
+            let blob = bucket
+                .get_blob(&expected.name)
+                .ok_or("Bucket lacks a blob of the schema")?;
+            self.validate_meta_data(blob.get_meta_data())?;
+        }
+        Ok(())
+    }
+}
+
+/// SchemaRegistry
+/// A set of named MetaData schemas
+#[derive(Clone, Debug, Default)]
+pub struct SchemaRegistry {
+    schemas: HashMap<String, MetaDataSchema>,
+}
+
+impl SchemaRegistry {
+    /// empty constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// register a schema under a name (returns an error if the name is taken)
+    pub fn register(&mut self, name: &str, schema: MetaDataSchema) -> Result<(), &'static str> {
+        if self.schemas.contains_key(name) {
+            return Err("A schema with this name is already registered");
+        }
+        self.schemas.insert(name.to_string(), schema);
+        Ok(())
+    }
+    /// get a registered schema
+    pub fn get(&self, name: &str) -> Option<&MetaDataSchema> {
+        self.schemas.get(name)
+    }
+    /// names of the registered schemas (in arbitrary order)
+    pub fn names(&self) -> impl Iterator<Item = &String> {
+        self.schemas.keys()
+    }
+    /// the stream schema referencing a registered schema
+    pub fn reference(&self, name: &str) -> Result<StreamSchema, &'static str> {
+        self.get(name).ok_or("Unknown schema")?;
+        Ok(StreamSchema::Registered(name.to_string()))
+    }
+    /// validate a bucket against a registered schema
+    pub fn validate(&self, name: &str, bucket: &DataBucket) -> Result<(), &'static str> {
+        self.get(name).ok_or("Unknown schema")?.validate(bucket)
+    }
+}
+
+/// ValidatingSource
+/// A source checking every bucket of its input against a registered schema and declaring
+/// that schema, the stream ends at the first invalid bucket
+pub struct ValidatingSource {
+    input: Rc<dyn Source<DataBucket>>,
+    name: String,
+    schema: MetaDataSchema,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl ValidatingSource {
+    /// constructor (returns an error if the schema is not registered)
+    pub fn new(
+        input: Rc<dyn Source<DataBucket>>,
+        registry: &SchemaRegistry,
+        name: &str,
+    ) -> Result<Self, &'static str> {
+        Ok(Self {
+            input,
+            name: name.to_string(),
+            schema: registry.get(name).ok_or("Unknown schema")?.clone(),
+            error: Rc::new(Cell::new(None)),
+        })
+    }
+    /// the validation failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl Source<DataBucket> for ValidatingSource {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBucket>> {
+        let schema = self.schema.clone();
+        let error = self.error.clone();
+        error.set(None);
+        Box::new(Pin::from(self.input.stream()).scan((), move |_, bucket| {
+            let valid = schema
+                .validate(&bucket)
+                .map_err(|failure| error.set(Some(failure)));
+            futures::future::ready(valid.ok().map(|_| bucket))
+        }))
+    }
+    fn schema(&self) -> Option<StreamSchema> {
+        Some(StreamSchema::Registered(self.name.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::schema::SchemaPipe;
+    use crate::data_bucket::{DataBlob, DataBucketBlob};
+    use crate::pipes::FlatMapPipe;
+    use crate::testing::MockSource;
+    use crate::Pipe;
+    use futures::executor::block_on;
+
+    fn speed(units: &str) -> MetaData {
+        MetaData {
+            name: "speed".to_string(),
+            units: Some(units.to_string()),
+            unitary_dimensions: vec![1],
+            ..Default::default()
+        }
+    }
+
+    fn bucket(units: &str) -> DataBucket {
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(DataBucketBlob::Float64(DataBlob::new(
+            vec![1.0, 2.0],
+            MetaData {
+                dimensions: vec![2],
+                ..speed(units)
+            },
+        )));
+        bucket
+    }
+
+    fn registry() -> SchemaRegistry {
+        let mut registry = SchemaRegistry::new();
+        registry
+            .register("kinematics", MetaDataSchema::new().blob(speed("m/s")))
+            .unwrap();
+        registry
+    }
+
+    #[test]
+    fn test_registry_validation() {
+        let mut registry = registry();
+        assert!(registry
+            .register("kinematics", MetaDataSchema::new())
+            .is_err());
+        assert!(registry.validate("kinematics", &bucket("m/s")).is_ok());
+        assert_eq!(
+            registry.validate("kinematics", &bucket("km/h")),
+            Err("Blob units do not match the schema")
+        );
+        assert_eq!(
+            registry.validate("kinematics", &DataBucket::new()),
+            Err("Bucket lacks a blob of the schema")
+        );
+        assert_eq!(
+            registry.validate("missing", &DataBucket::new()),
+            Err("Unknown schema")
+        );
+    }
+
+    #[test]
+    fn test_validating_source() {
+        let registry = registry();
+        let buckets = MockSource::new().items([bucket("m/s"), bucket("km/h"), bucket("m/s")]);
+        let source = ValidatingSource::new(Rc::new(buckets), &registry, "kinematics").unwrap();
+        let valid: Vec<DataBucket> = block_on(Pin::from(source.stream()).collect());
+        assert_eq!(valid.len(), 1, "Stream went on past an invalid bucket");
+        assert_eq!(source.error(), Some("Blob units do not match the schema"));
+        let identity = || FlatMapPipe::new(|bucket: DataBucket| Some(bucket));
+        let source: Rc<dyn Source<DataBucket>> = Rc::new(source);
+        let mut pipe =
+            SchemaPipe::new(identity()).expects(registry.reference("kinematics").unwrap());
+        assert!(pipe.pipe(source.clone()).is_ok());
+        let mut other =
+            SchemaPipe::new(identity()).expects(StreamSchema::Registered("other".to_string()));
+        assert!(other.pipe(source).is_err(), "Accepted a different schema");
+    }
+}
@@ -0,0 +1,140 @@
+//! rows
+//!
+//! Record oriented streaming of columnar DataBuckets
+
+use super::schema::StreamSchema;
+use super::{DataBucket, DataBucketBlob, LinkType};
+use crate::Source;
+use futures::stream;
+use futures::Stream;
+use std::sync::Arc;
+
+/// BucketRowSource
+/// A source yielding one small bucket per unit of a primary blob, holding the matching
+/// unit of the primary blob and of every blob linked to it `OneToOne`
+pub struct BucketRowSource {
+    blobs: Vec<Arc<DataBucketBlob>>,
+    records: usize,
+}
+
+impl BucketRowSource {
+    /// constructor (returns an error if the primary blob is missing or a linked blob
+    /// does not hold the same number of units)
+    pub fn new(bucket: &DataBucket, primary: &str) -> Result<Self, &'static str> {
+        let snapshot = bucket.snapshot();
+        let primary_blob = snapshot
+            .data
+            .get(primary)
+            .ok_or("Primary blob not found in bucket")?;
+        let records = primary_blob.unit_count();
+        let mut blobs = vec![primary_blob.clone()];
+        for (name, blob) in snapshot.data.iter() {
+            let linked = blob.get_meta_data().links.iter().any(|link| {
+                matches!(link.nature, LinkType::OneToOne)
+                    && link.linker == *name
+                    && link.linkee == primary
+            });
+            if linked {
+                if blob.unit_count() != records {
+                    return Err("Linked blob does not match the primary unit count");
+                }
+                blobs.push(blob.clone());
+            }
+        }
+        Ok(Self { blobs, records })
+    }
+    /// number of records the source yields
+    pub fn len(&self) -> usize {
+        self.records
+    }
+    /// whether the source yields no records
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+}
+
+impl Source<DataBucket> for BucketRowSource {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBucket>> {
+        let blobs = self.blobs.clone();
+        Box::new(stream::iter((0..self.records).map(move |idx| {
+            let mut record = DataBucket::new();
+            for blob in blobs.iter() {
+                if let Some(unit) = blob.slice_units(idx..idx + 1) {
+                    record.add_blob(unit);
+                }
+            }
+            record
+        })))
+    }
+    fn schema(&self) -> Option<StreamSchema> {
+        Some(StreamSchema::Bucket(
+            self.blobs
+                .iter()
+                .map(|blob| (blob.get_meta_data().name.clone(), blob.kind()))
+                .collect(),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{DataBlob, Link, MetaData};
+    use super::*;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::pin::Pin;
+
+    fn make_blob(name: &str, data: Vec<i32>, unit: usize, links: Vec<Link>) -> DataBucketBlob {
+        let meta = MetaData {
+            name: name.to_string(),
+            unitary_dimensions: vec![unit],
+            dimensions: vec![data.len() / unit, unit],
+            links,
+            ..Default::default()
+        };
+        DataBucketBlob::Int32(DataBlob::new(data, meta))
+    }
+
+    fn one_to_one(linker: &str) -> Vec<Link> {
+        vec![Link {
+            nature: LinkType::OneToOne,
+            linker: linker.to_string(),
+            linkee: "time".to_string(),
+        }]
+    }
+
+    #[test]
+    fn test_row_source() {
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(make_blob("time", vec![0, 1, 2], 1, Vec::new()));
+        bucket.add_blob(make_blob(
+            "pos",
+            vec![0, 0, 1, 1, 2, 2],
+            2,
+            one_to_one("pos"),
+        ));
+        bucket.add_blob(make_blob("other", vec![7], 1, Vec::new()));
+        let rows = BucketRowSource::new(&bucket, "time").unwrap();
+        assert_eq!(rows.len(), 3);
+        let records: Vec<DataBucket> = block_on(Pin::from(rows.stream()).collect());
+        assert_eq!(records.len(), 3, "Wrong number of records");
+        for (idx, record) in records.iter().enumerate() {
+            assert_eq!(record.len(), 2, "Record holds unlinked blobs");
+            match record.get_blob(&"pos".to_string()) {
+                Some(DataBucketBlob::Int32(b)) => {
+                    assert_eq!(b.get_data(), &vec![idx as i32, idx as i32])
+                }
+                _ => panic!("Could not match record blob"),
+            }
+        }
+    }
+
+    #[test]
+    fn test_row_source_mismatch() {
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(make_blob("time", vec![0, 1, 2], 1, Vec::new()));
+        bucket.add_blob(make_blob("pos", vec![0, 0], 2, one_to_one("pos")));
+        assert!(BucketRowSource::new(&bucket, "time").is_err());
+        assert!(BucketRowSource::new(&bucket, "missing").is_err());
+    }
+}
@@ -0,0 +1,328 @@
+//! schema
+//!
+//! Declared schemas of blob and bucket streams checked when pipes are connected
+//!
+//! sources may declare the schema of the items they stream, a pipe expecting a schema checks
+//! the declaration of its input in `pipe` so mismatched pipes fail when they are connected
+//! rather than mid-run, a source declaring nothing is accepted by any pipe
+
+use super::{for_each_variant, DataBucket, DataBucketBlob};
+use crate::{Pipe, Source};
+use futures::Stream;
+use std::collections::BTreeMap;
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+macro_rules! blob_kinds {
+  ($($x:ident),*) => {
+    /// BlobKind
+    /// The primitive type held by a DataBucketBlob, one per variant
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+    pub enum BlobKind {
+      $( $x, )*
+    }
+
+    impl BlobKind {
+      /// the name of the variant
+      pub fn name(&self) -> &'static str {
+        match self {
+          $( BlobKind::$x => stringify!($x), )*
+        }
+      }
+      /// the variant of a name written by `name`
+      pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+          $( stringify!($x) => Some(BlobKind::$x), )*
+          _ => None,
+        }
+      }
+    }
+
+    impl DataBucketBlob {
+      /// the primitive type held by the blob
+      pub fn kind(&self) -> BlobKind {
+        match self {
+          $( DataBucketBlob::$x(_) => BlobKind::$x, )*
+        }
+      }
+    }
+  }
+}
+
+for_each_variant!(blob_kinds);
+
+/// StreamSchema
+/// The declared shape of the items of a stream
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamSchema {
+    /// blobs holding a single primitive type
+    Blob(BlobKind),
+    /// buckets holding at least the named top level blobs with their types
+    Bucket(BTreeMap<String, BlobKind>),
+    /// buckets validated against a schema of a `SchemaRegistry`
+    Registered(String),
+}
+
+impl StreamSchema {
+    /// constructor of a bucket schema from (name, type) pairs
+    pub fn bucket<'a, I: IntoIterator<Item = (&'a str, BlobKind)>>(blobs: I) -> Self {
+        StreamSchema::Bucket(
+            blobs
+                .into_iter()
+                .map(|(name, kind)| (name.to_string(), kind))
+                .collect(),
+        )
+    }
+    /// the schema describing a blob
+    pub fn of_blob(blob: &DataBucketBlob) -> Self {
+        StreamSchema::Blob(blob.kind())
+    }
+    /// the schema describing the top level blobs of a bucket
+    pub fn of_bucket(bucket: &DataBucket) -> Self {
+        StreamSchema::Bucket(
+            bucket
+                .iter()
+                .map(|(name, blob)| (name.clone(), blob.kind()))
+                .collect(),
+        )
+    }
+    /// check that items of the produced schema satisfy this one
+    pub fn accepts(&self, produced: &StreamSchema) -> Result<(), &'static str> {
+        match (self, produced) {
+            (StreamSchema::Blob(expected), StreamSchema::Blob(kind)) if expected == kind => Ok(()),
+            (StreamSchema::Blob(_), StreamSchema::Blob(_)) => {
+                Err("Input declares blobs of a different type than expected")
+            }
+            (StreamSchema::Bucket(expected), StreamSchema::Bucket(blobs)) => {
+                for (name, kind) in expected.iter() {
+                    match blobs.get(name) {
+                        None => return Err("Input declares buckets missing an expected blob"),
+                        Some(other) if other != kind => {
+                            return Err(
+                                "Input declares a bucket blob of a different type than expected",
+                            )
+                        }
+                        _ => {}
+                    }
+                }
+                Ok(())
+            }
+            (StreamSchema::Registered(expected), StreamSchema::Registered(name))
+                if expected == name =>
+            {
+                Ok(())
+            }
+            (StreamSchema::Registered(_), StreamSchema::Registered(_)) => {
+                Err("Input declares a different registered schema than expected")
+            }
+            _ => Err("Input declares a schema of a different shape than expected"),
+        }
+    }
+}
+
+/// check the declared schema of an input against an expected one, inputs declaring nothing
+/// are accepted
+pub fn handshake<T>(
+    expected: Option<&StreamSchema>,
+    input: &dyn Source<T>,
+) -> Result<(), &'static str> {
+    match (expected, input.schema()) {
+        (Some(expected), Some(produced)) => expected.accepts(&produced),
+        _ => Ok(()),
+    }
+}
+
+/// a blob a pipe expects of its input buckets, with the types it accepts for it
+pub(crate) type ExpectedBlob<'a> = (&'a str, fn(BlobKind) -> bool);
+
+/// check that an input declaring a bucket schema declares each named blob with a type
+/// accepted for it, inputs declaring nothing or a registered schema are accepted
+pub(crate) fn handshake_blobs<T>(
+    input: &dyn Source<T>,
+    blobs: &[ExpectedBlob],
+) -> Result<(), &'static str> {
+    match input.schema() {
+        Some(StreamSchema::Bucket(declared)) => {
+            for (name, accepts) in blobs {
+                match declared.get(*name) {
+                    None => return Err("Input declares buckets missing an expected blob"),
+                    Some(kind) if !accepts(*kind) => {
+                        return Err(
+                            "Input declares a bucket blob of a different type than expected",
+                        )
+                    }
+                    _ => {}
+                }
+            }
+            Ok(())
+        }
+        Some(StreamSchema::Blob(_)) => {
+            Err("Input declares a schema of a different shape than expected")
+        }
+        _ => Ok(()),
+    }
+}
+
+/// DeclaredSource
+/// A source attaching a declared schema to another source
+pub struct DeclaredSource<T> {
+    input: Rc<dyn Source<T>>,
+    schema: StreamSchema,
+}
+
+impl<T> DeclaredSource<T> {
+    /// constructor
+    pub fn new(input: Rc<dyn Source<T>>, schema: StreamSchema) -> Self {
+        Self { input, schema }
+    }
+}
+
+impl<T> Source<T> for DeclaredSource<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        self.input.stream()
+    }
+    fn schema(&self) -> Option<StreamSchema> {
+        Some(self.schema.clone())
+    }
+}
+
+/// SchemaPipe
+/// A pipe checking the declared schema of its input before connecting the pipe it wraps
+/// and declaring the schema of its output
+pub struct SchemaPipe<InT, OutT, P> {
+    pipe: P,
+    expects: Option<StreamSchema>,
+    declares: Option<StreamSchema>,
+    types: PhantomData<(InT, OutT)>,
+}
+
+impl<InT, OutT, P: Pipe<InT, OutT>> SchemaPipe<InT, OutT, P> {
+    /// constructor expecting and declaring nothing
+    pub fn new(pipe: P) -> Self {
+        Self {
+            pipe,
+            expects: None,
+            declares: None,
+            types: PhantomData,
+        }
+    }
+    /// set the schema the input must declare (if it declares one)
+    pub fn expects(mut self, schema: StreamSchema) -> Self {
+        self.expects = Some(schema);
+        self
+    }
+    /// set the schema declared for the output
+    pub fn declares(mut self, schema: StreamSchema) -> Self {
+        self.declares = Some(schema);
+        self
+    }
+    /// the wrapped pipe
+    pub fn inner(&self) -> &P {
+        &self.pipe
+    }
+}
+
+impl<InT, OutT, P: Pipe<InT, OutT>> Source<OutT> for SchemaPipe<InT, OutT, P> {
+    fn stream(&self) -> Box<dyn Stream<Item = OutT>> {
+        self.pipe.stream()
+    }
+    fn schema(&self) -> Option<StreamSchema> {
+        self.declares.clone().or_else(|| self.pipe.schema())
+    }
+}
+
+impl<InT, OutT, P: Pipe<InT, OutT>> Pipe<InT, OutT> for SchemaPipe<InT, OutT, P> {
+    fn pipe(&mut self, input: Rc<dyn Source<InT>>) -> Result<(), &'static str> {
+        handshake(self.expects.as_ref(), input.as_ref())?;
+        self.pipe.pipe(input)
+    }
+    fn unpipe(&mut self) {
+        self.pipe.unpipe();
+    }
+    fn get_input(&self) -> Option<Rc<dyn Source<InT>>> {
+        self.pipe.get_input()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::rows::BucketRowSource;
+    use crate::data_bucket::{DataBlob, MetaData};
+    use crate::pipes::FlatMapPipe;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::pin::Pin;
+
+    fn blob<T: crate::data_bucket::BlobType>(name: &str, data: Vec<T>) -> DataBucketBlob {
+        let meta = MetaData {
+            name: name.to_string(),
+            unitary_dimensions: vec![1],
+            dimensions: vec![data.len()],
+            ..Default::default()
+        };
+        T::wrap(DataBlob::new(data, meta))
+    }
+
+    fn sum() -> SchemaPipe<DataBucketBlob, f64, FlatMapPipe<DataBucketBlob, f64>> {
+        SchemaPipe::new(FlatMapPipe::new(|blob: DataBucketBlob| match blob {
+            DataBucketBlob::Float64(blob) => Some(blob.get_data().iter().sum()),
+            _ => None,
+        }))
+        .expects(StreamSchema::Blob(BlobKind::Float64))
+    }
+
+    #[test]
+    fn test_blob_handshake() {
+        let text = blob("text", vec!["a".to_string()]);
+        assert_eq!(text.kind().name(), "Str");
+        let strings = DeclaredSource::new(
+            Rc::new(MockSource::new().items([text.clone()])),
+            StreamSchema::of_blob(&text),
+        );
+        let mut pipe = sum();
+        assert!(
+            pipe.pipe(Rc::new(strings)).is_err(),
+            "Connected a Str source to a Float64 pipe"
+        );
+        assert!(pipe.get_input().is_none());
+        let values = blob("values", vec![1.0, 2.5]);
+        let floats = DeclaredSource::new(
+            Rc::new(MockSource::new().items([values])),
+            StreamSchema::Blob(BlobKind::Float64),
+        );
+        pipe.pipe(Rc::new(floats)).unwrap();
+        assert_eq!(
+            block_on(Pin::from(pipe.stream()).collect::<Vec<_>>()),
+            vec![3.5]
+        );
+        pipe.pipe(Rc::new(MockSource::new().items([text])))
+            .expect("Rejected an undeclared source");
+    }
+
+    #[test]
+    fn test_bucket_handshake() {
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(blob("time", vec![0_i64, 1]));
+        bucket.add_blob(blob("label", vec!['a', 'b']));
+        let rows: Rc<dyn Source<DataBucket>> =
+            Rc::new(BucketRowSource::new(&bucket, "time").unwrap());
+        assert_eq!(
+            rows.schema(),
+            Some(StreamSchema::bucket([("time", BlobKind::Int64)]))
+        );
+        let identity = || FlatMapPipe::new(|bucket: DataBucket| Some(bucket));
+        let mut timed =
+            SchemaPipe::new(identity()).expects(StreamSchema::bucket([("time", BlobKind::Int64)]));
+        assert!(timed.pipe(rows.clone()).is_ok());
+        let mut labelled =
+            SchemaPipe::new(identity()).expects(StreamSchema::bucket([("label", BlobKind::Char)]));
+        assert_eq!(
+            labelled.pipe(rows.clone()),
+            Err("Input declares buckets missing an expected blob")
+        );
+        let mut float = SchemaPipe::new(identity()).expects(StreamSchema::Blob(BlobKind::Float64));
+        assert!(float.pipe(rows).is_err());
+    }
+}
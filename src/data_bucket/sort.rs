@@ -0,0 +1,326 @@
+//! sort
+//!
+//! Reordering the units of a bucket by the units of a key blob
+//!
+//! the blobs linked `OneToOne` to the key, either way and through one another, move along
+//! with it, and the indices of the blobs holding `Handle` links to any blob that moved are
+//! updated to keep pointing at the same units
+
+use super::mask::ValidityMask;
+use super::{for_each_variant, DataBlob, DataBucket, DataBucketBlob, LinkType};
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// the former position of every unit of a blob once sorted by a comparison of their
+/// elements, stable and with the units holding an invalid element last
+fn unit_order<T>(blob: &DataBlob<T>, compare: impl Fn(&T, &T) -> Ordering) -> Vec<usize> {
+    let size = blob.unit_size();
+    let valid = |unit: usize| (unit * size..(unit + 1) * size).all(|index| blob.is_valid(index));
+    let unit = |unit: usize| &blob.data[unit * size..(unit + 1) * size];
+    let mut order: Vec<usize> = (0..blob.unit_count()).collect();
+    order.sort_by(|&a, &b| {
+        valid(b).cmp(&valid(a)).then_with(|| {
+            let mut elements = unit(a).iter().zip(unit(b)).map(|(a, b)| compare(a, b));
+            elements
+                .find(|order| order.is_ne())
+                .unwrap_or(Ordering::Equal)
+        })
+    });
+    order
+}
+
+impl<T: Clone> DataBlob<T> {
+    /// move the units to the order of their former positions, trailing elements stay last
+    fn permute_units(&mut self, order: &[usize]) {
+        let size = self.unit_size();
+        let indices: Vec<usize> = order
+            .iter()
+            .flat_map(|unit| unit * size..(unit + 1) * size)
+            .chain(order.len() * size..self.data.len())
+            .collect();
+        self.data = indices
+            .iter()
+            .map(|index| self.data[*index].clone())
+            .collect();
+        if let Some(mask) = &self.mask {
+            self.mask = Some(ValidityMask::from_bools(
+                indices.iter().map(|index| mask.is_valid(*index)),
+            ));
+        }
+    }
+}
+
+/// the indices of a handle blob pointing at the new positions of the units
+fn remap<T>(blob: &DataBlob<T>, positions: &[usize]) -> Result<Vec<T>, &'static str>
+where
+    T: Copy + TryInto<usize> + TryFrom<usize>,
+{
+    let mut data = blob.data.clone();
+    for (index, handle) in data.iter_mut().enumerate() {
+        if !blob.is_valid(index) {
+            continue;
+        }
+        let unit: usize = (*handle)
+            .try_into()
+            .map_err(|_| "Handle index out of range")?;
+        let position = *positions.get(unit).ok_or("Handle index out of range")?;
+        *handle = T::try_from(position).map_err(|_| "Handle index does not fit its blob type")?;
+    }
+    Ok(data)
+}
+
+macro_rules! unit_order_unwrap {
+  ($($x:ident),*) => {
+    /// the sorted order of the units of the blob, floats in their total order
+    fn unit_order(&self) -> Result<Vec<usize>, &'static str> {
+      match self {
+        DataBucketBlob::Float32(blob) => Ok(unit_order(blob, f32::total_cmp)),
+        DataBucketBlob::Float64(blob) => Ok(unit_order(blob, f64::total_cmp)),
+        DataBucketBlob::Float16(blob) => {
+          Ok(unit_order(blob, |a, b| a.to_f32().total_cmp(&b.to_f32())))
+        }
+        DataBucketBlob::BFloat16(blob) => {
+          Ok(unit_order(blob, |a, b| a.to_f32().total_cmp(&b.to_f32())))
+        }
+        DataBucketBlob::Complex32(_) | DataBucketBlob::Complex64(_) => {
+          Err("Complex blobs cannot be sorted")
+        }
+        $( DataBucketBlob::$x(blob) => Ok(unit_order(blob, Ord::cmp)), )*
+      }
+    }
+  }
+}
+
+macro_rules! remap_unwrap {
+  ($($x:ident),*) => {
+    /// a copy of a handle blob pointing at the new positions of the units
+    fn remap_handles(&self, positions: &[usize]) -> Result<DataBucketBlob, &'static str> {
+      match self {
+        $( DataBucketBlob::$x(blob) => {
+          let data = remap(blob, positions)?;
+          Ok(DataBucketBlob::$x(DataBlob { data, ..blob.clone() }))
+        } )*
+        _ => Err("Handle blob does not hold integer indices"),
+      }
+    }
+  }
+}
+
+macro_rules! permute_units_unwrap {
+  ($($x:ident),*) => {
+    fn permute_units(&mut self, order: &[usize]) {
+      match self {
+        $( DataBucketBlob::$x(blob) => blob.permute_units(order), )*
+      }
+    }
+  }
+}
+
+impl DataBucketBlob {
+    unit_order_unwrap!(
+        Bool, Char, Int8, U8, Int16, U16, Int32, U32, Int64, U64, Int128, U128, ISize, USize, Str,
+        Timestamp, Bytes
+    );
+    remap_unwrap!(
+        Int8, U8, Int16, U16, Int32, U32, Int64, U64, Int128, U128, ISize, USize, Timestamp
+    );
+    for_each_variant!(permute_units_unwrap);
+}
+
+impl DataBucket {
+    /// sort the units of a key blob and of the blobs linked `OneToOne` to it, updating the
+    /// `Handle` blobs linking to them (returns an error, leaving the bucket untouched, when
+    /// the key cannot be sorted, a linked blob fails to load or holds another number of
+    /// units or a handle does not point at a unit)
+    ///
+    /// the sort is stable, units holding an invalid element of the key go last. Only the
+    /// spilled and lazy blobs of the key, the blobs moving with it and their handles are
+    /// loaded, and moved to memory
+    pub fn sort_by(&mut self, key_blob_name: &str) -> Result<(), &'static str> {
+        let order = self
+            .try_get_blob(key_blob_name)?
+            .ok_or("Key blob not found in bucket")?
+            .unit_order()?;
+        let links: Vec<_> = self
+            .data
+            .values()
+            .map(|blob| blob.get_meta_data())
+            .chain(self.deferred.values().map(|blob| &blob.meta))
+            .flat_map(|meta| meta.links.iter().cloned())
+            .filter(|link| self.contains(&link.linker))
+            .filter(|link| self.contains(&link.linkee))
+            .collect();
+        let mut moved = HashSet::from([key_blob_name.to_string()]);
+        loop {
+            let linked: Vec<String> = links
+                .iter()
+                .filter(|link| matches!(link.nature, LinkType::OneToOne))
+                .filter_map(|link| match moved.contains(&link.linker) {
+                    true => Some(link.linkee.clone()),
+                    false if moved.contains(&link.linkee) => Some(link.linker.clone()),
+                    false => None,
+                })
+                .filter(|name| !moved.contains(name))
+                .collect();
+            if linked.is_empty() {
+                break;
+            }
+            moved.extend(linked);
+        }
+        let handles: HashSet<String> = links
+            .iter()
+            .filter(|link| matches!(link.nature, LinkType::Handle))
+            .filter(|link| moved.contains(&link.linkee))
+            .map(|link| link.linker.clone())
+            .collect();
+        for name in moved.iter().chain(&handles) {
+            self.try_get_blob(name)?;
+        }
+        for name in moved.iter().chain(&handles) {
+            self.restore_blob(name)?;
+        }
+        if moved
+            .iter()
+            .any(|name| self.data[name].unit_count() != order.len())
+        {
+            return Err("Linked blob does not match the key unit count");
+        }
+        let mut positions = vec![0; order.len()];
+        for (position, unit) in order.iter().enumerate() {
+            positions[*unit] = position;
+        }
+        let remapped = handles
+            .into_iter()
+            .map(|name| {
+                let blob = self.data[&name].remap_handles(&positions)?;
+                Ok((name, blob))
+            })
+            .collect::<Result<Vec<(String, DataBucketBlob)>, &'static str>>()?;
+        let provenance = [("key", key_blob_name.to_string())];
+        for (name, mut blob) in remapped {
+            blob.get_mut_meta_data().record("sort_by", &provenance);
+            self.data.insert(name, Arc::new(blob));
+        }
+        for name in moved {
+            if let Some(blob) = self.data.get_mut(&name) {
+                let blob = Arc::make_mut(blob);
+                blob.permute_units(&order);
+                blob.get_mut_meta_data().record("sort_by", &provenance);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Link, MetaData};
+    use super::*;
+
+    fn make_blob<T>(name: &str, data: Vec<T>, links: Vec<(LinkType, &str)>) -> DataBlob<T> {
+        let meta = MetaData {
+            name: name.to_string(),
+            dimensions: vec![data.len()],
+            unitary_dimensions: vec![1],
+            links: links
+                .into_iter()
+                .map(|(nature, linkee)| Link {
+                    nature,
+                    linker: name.to_string(),
+                    linkee: linkee.to_string(),
+                })
+                .collect(),
+            ..Default::default()
+        };
+        DataBlob::new(data, meta)
+    }
+
+    fn data<'a>(bucket: &'a DataBucket, name: &str) -> &'a DataBucketBlob {
+        bucket.get_blob(&name.to_string()).unwrap()
+    }
+
+    #[test]
+    fn test_sort_by_linked_blob() {
+        let mut bucket = DataBucket::new();
+        let time = make_blob("time", vec![3i64, 1, 2, 0], vec![])
+            .with_mask(ValidityMask::from_bools([true, true, true, false]))
+            .unwrap();
+        bucket.add_blob(DataBucketBlob::Int64(time));
+        let speed = vec![30.0, 10.0, 20.0, 0.0];
+        let speed = make_blob("speed", speed, vec![(LinkType::OneToOne, "time")]);
+        bucket.add_blob(DataBucketBlob::Float64(speed));
+        let labels = ["c", "a", "b", "-"].map(String::from).to_vec();
+        let labels = make_blob("labels", labels, vec![(LinkType::OneToOne, "speed")]);
+        bucket.add_blob(DataBucketBlob::Str(labels));
+        let peaks = make_blob("peaks", vec![0u32, 2], vec![(LinkType::Handle, "time")]);
+        bucket.add_blob(DataBucketBlob::U32(peaks));
+        bucket.sort_by("time").unwrap();
+        match data(&bucket, "time") {
+            DataBucketBlob::Int64(time) => {
+                assert_eq!(time.get_data()[..3], [1, 2, 3]);
+                assert!(!time.is_valid(3), "Invalid key did not go last");
+            }
+            _ => panic!("Could not match blob"),
+        }
+        match (data(&bucket, "speed"), data(&bucket, "labels")) {
+            (DataBucketBlob::Float64(speed), DataBucketBlob::Str(labels)) => {
+                assert_eq!(speed.get_data(), &vec![10.0, 20.0, 30.0, 0.0]);
+                assert_eq!(labels.get_data(), &vec!["a", "b", "c", "-"]);
+            }
+            _ => panic!("Could not match blobs"),
+        }
+        match data(&bucket, "peaks") {
+            DataBucketBlob::U32(peaks) => assert_eq!(peaks.get_data(), &vec![2, 1]),
+            _ => panic!("Could not match blob"),
+        }
+        let entry = &data(&bucket, "labels").get_meta_data().provenance[0];
+        assert_eq!(entry.to_string(), "sort_by(key=time)");
+    }
+
+    #[test]
+    fn test_sort_by_failures() {
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(DataBucketBlob::U8(make_blob("key", vec![2u8, 1], vec![])));
+        let short = make_blob("short", vec![0u8], vec![(LinkType::OneToOne, "key")]);
+        bucket.add_blob(DataBucketBlob::U8(short));
+        assert!(bucket.sort_by("key").is_err());
+        bucket.pop_blob("short".to_string());
+        let handles = make_blob("handles", vec![0u8, 5], vec![(LinkType::Handle, "key")]);
+        bucket.add_blob(DataBucketBlob::U8(handles));
+        assert_eq!(bucket.sort_by("key"), Err("Handle index out of range"));
+        match data(&bucket, "key") {
+            DataBucketBlob::U8(key) => assert_eq!(key.get_data(), &vec![2, 1]),
+            _ => panic!("Could not match blob"),
+        }
+        assert!(bucket.sort_by("missing").is_err());
+    }
+
+    #[test]
+    fn test_sort_by_deferred_blobs() {
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(DataBucketBlob::U8(make_blob("key", vec![2u8, 1], vec![])));
+        let unrelated = make_blob("unrelated", Vec::<u8>::new(), vec![]);
+        let meta = unrelated.get_meta_data().clone();
+        bucket.add_lazy_blob(DataBlob::<u8>::lazy(meta, || Ok(vec![0])));
+        assert!(bucket.sort_by("unrelated").is_ok());
+        let meta = |name: &str| {
+            let blob = make_blob(name, vec![0u8], vec![(LinkType::OneToOne, "key")]);
+            blob.get_meta_data().clone()
+        };
+        bucket.add_lazy_blob(DataBlob::<u8>::lazy(meta("values"), || Ok(vec![7, 8])));
+        assert!(bucket.sort_by("missing").is_err());
+        assert!(!bucket.is_resident("values"), "Bad key loaded the bucket");
+        bucket.sort_by("key").unwrap();
+        match data(&bucket, "values") {
+            DataBucketBlob::U8(values) => assert_eq!(values.get_data(), &vec![8, 7]),
+            _ => panic!("Could not match blob"),
+        }
+        bucket.add_lazy_blob(DataBlob::<u8>::lazy(meta("broken"), || Err("Missing file")));
+        assert_eq!(bucket.sort_by("key"), Err("Missing file"));
+        match data(&bucket, "key") {
+            DataBucketBlob::U8(key) => assert_eq!(key.get_data(), &vec![1, 2]),
+            _ => panic!("Could not match blob"),
+        }
+    }
+}
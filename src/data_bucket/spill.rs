@@ -0,0 +1,377 @@
+//! spill
+//!
+//! Moving blob data exceeding a memory budget to temporary files
+//!
+//! only the data of a spilled blob is written to disk, its meta data and mask stay in
+//! memory, the data is read back the first time the blob is accessed and kept resident
+//! until the bucket is spilled again, files are removed once the last bucket referring to
+//! them is dropped
+
+use super::encoding::BinaryElement;
+use super::lazy::{DeferredBlob, Origin, Restore};
+use super::mask::ValidityMask;
+use super::{for_each_variant, DataBlob, DataBucket, DataBucketBlob, MetaData};
+use crate::memory::MemoryTracker;
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+/// SpillPolicy
+/// The memory budget of buckets and where the data exceeding it is spilled
+#[derive(Clone)]
+pub struct SpillPolicy {
+    budget: usize,
+    min_blob_size: usize,
+    directory: PathBuf,
+    tracker: Option<MemoryTracker>,
+}
+
+impl SpillPolicy {
+    /// constructor for a budget in bytes, spilling to the temporary directory
+    pub fn new(budget: usize) -> Self {
+        Self {
+            budget,
+            min_blob_size: 4096,
+            directory: std::env::temp_dir(),
+            tracker: None,
+        }
+    }
+    /// only spill blobs holding at least that many bytes of data (4 KiB by default)
+    pub fn min_blob_size(mut self, bytes: usize) -> Self {
+        self.min_blob_size = bytes;
+        self
+    }
+    /// spill to files in the given directory
+    pub fn in_directory<P: AsRef<Path>>(mut self, directory: P) -> Self {
+        self.directory = directory.as_ref().to_path_buf();
+        self
+    }
+    /// count the bytes live in a tracker (such as the one of an executor) against the
+    /// budget along with the bucket
+    pub fn tracked(mut self, tracker: &MemoryTracker) -> Self {
+        self.tracker = Some(tracker.clone());
+        self
+    }
+    /// the budget in bytes
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+}
+
+/// a spill file, removed when dropped
+pub(crate) struct SpillFile {
+    path: PathBuf,
+}
+
+impl SpillFile {
+    pub(crate) fn read(&self) -> Result<Vec<u8>, &'static str> {
+        fs::read(&self.path).map_err(|_| "Failure to read spill file")
+    }
+}
+
+impl Drop for SpillFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+fn restore<T: BinaryElement>(
+    bytes: &[u8],
+    meta: MetaData,
+    mask: Option<ValidityMask>,
+) -> Result<DataBlob<T>, &'static str> {
+    let blob = DataBlob::new(T::read_bytes(bytes)?, meta);
+    match mask {
+        Some(mask) => blob.with_mask(mask)?,
+        None => blob,
+    }
+    .verify_if_present()
+}
+
+macro_rules! spill_parts_unwrap {
+  ($($x:ident),*) => {
+    fn spill_parts(blob: &DataBucketBlob) -> (Vec<u8>, &MetaData, &Option<ValidityMask>, Restore) {
+      match blob {
+        $( DataBucketBlob::$x(blob) => {
+          let mut bytes = Vec::new();
+          BinaryElement::write_bytes(&blob.data, &mut bytes);
+          let restore: Restore = |bytes, meta, mask| restore(bytes, meta, mask).map(DataBucketBlob::$x);
+          (bytes, &blob.meta, &blob.mask, restore)
+        } )*
+      }
+    }
+  }
+}
+
+for_each_variant!(spill_parts_unwrap);
+
+static SPILL_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// write the data of a blob to a new file of a directory
+fn spill_blob(blob: &DataBucketBlob, directory: &Path) -> Result<DeferredBlob, &'static str> {
+    let (bytes, meta, mask, restore) = spill_parts(blob);
+    let path = directory.join(format!(
+        "bitvortex-spill-{}-{}.bin",
+        std::process::id(),
+        SPILL_COUNT.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&path, &bytes).map_err(|_| "Failure to write spill file")?;
+    Ok(DeferredBlob {
+        origin: Origin::Spilled {
+            file: Arc::new(SpillFile { path }),
+            bytes: bytes.len(),
+            restore,
+        },
+        meta: meta.clone(),
+        mask: mask.clone(),
+        resident: OnceLock::new(),
+    })
+}
+
+impl DataBucket {
+    /// spill the largest blobs of the bucket and its groups until the bytes it holds in
+    /// memory (plus those live in the tracker of the policy) fit the budget, returns the
+    /// number of bytes of data spilled
+    pub fn spill(&mut self, policy: &SpillPolicy) -> Result<usize, &'static str> {
+        self.evict_spilled();
+        let live = policy.tracker.as_ref().map_or(0, MemoryTracker::live);
+        let mut resident = self.memory_usage().total() + live;
+        let mut candidates = Vec::new();
+        self.spill_candidates(&mut Vec::new(), policy.min_blob_size, &mut candidates);
+        candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.2));
+        let mut spilled = 0;
+        for (path, name, size) in candidates {
+            if resident <= policy.budget {
+                break;
+            }
+            let group = path
+                .iter()
+                .try_fold(&mut *self, |bucket, group| bucket.groups.get_mut(group))
+                .unwrap();
+            let blob = spill_blob(&group.data[&name], &policy.directory)?;
+            group.data.remove(&name);
+            group.deferred.insert(name, blob);
+            resident = resident.saturating_sub(size);
+            spilled += size;
+        }
+        Ok(spilled)
+    }
+    fn spill_candidates(
+        &self,
+        path: &mut Vec<String>,
+        min_blob_size: usize,
+        candidates: &mut Vec<(Vec<String>, String, usize)>,
+    ) {
+        for (name, blob) in &self.data {
+            let size = blob.byte_size();
+            if size >= min_blob_size {
+                candidates.push((path.clone(), name.clone(), size));
+            }
+        }
+        for (name, group) in &self.groups {
+            path.push(name.clone());
+            group.spill_candidates(path, min_blob_size, candidates);
+            path.pop();
+        }
+    }
+    /// drop the data read back from spill files so it is read again on the next access
+    pub fn evict_spilled(&mut self) {
+        for blob in self.deferred.values_mut().filter(|blob| blob.is_spilled()) {
+            blob.resident.take();
+        }
+        for group in self.groups.values_mut() {
+            group.evict_spilled();
+        }
+    }
+    /// move every spilled blob of the bucket and its groups back to memory
+    pub fn unspill(&mut self) -> Result<(), &'static str> {
+        let names: Vec<String> = self.spilled_names().cloned().collect();
+        for name in names {
+            let resident = self.deferred[&name].load()?.clone();
+            self.deferred.remove(&name);
+            self.data.insert(name, resident);
+        }
+        self.groups.values_mut().try_for_each(DataBucket::unspill)
+    }
+    /// whether the data of the named blob is held in a spill file
+    pub fn is_spilled(&self, blob_name: &str) -> bool {
+        self.deferred
+            .get(blob_name)
+            .is_some_and(DeferredBlob::is_spilled)
+    }
+    /// names of the spilled blobs of the bucket (in arbitrary order)
+    pub fn spilled_names(&self) -> impl Iterator<Item = &String> {
+        self.deferred
+            .iter()
+            .filter(|(_, blob)| blob.is_spilled())
+            .map(|(name, _)| name)
+    }
+}
+
+/// SpillPipe
+/// A pipe spilling the buckets streamed through it to keep them within a memory budget
+///
+/// a failure to spill ends the stream and is reported by `error`
+pub struct SpillPipe {
+    policy: SpillPolicy,
+    error: Rc<Cell<Option<&'static str>>>,
+    input: Option<Rc<dyn Source<DataBucket>>>,
+}
+
+impl SpillPipe {
+    /// constructor
+    pub fn new(policy: SpillPolicy) -> Self {
+        Self {
+            policy,
+            error: Rc::new(Cell::new(None)),
+            input: None,
+        }
+    }
+    /// the error that ended the last stream, if any
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl Source<DataBucket> for SpillPipe {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBucket>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let policy = self.policy.clone();
+        let error = self.error.clone();
+        error.set(None);
+        Box::new(
+            input
+                .map(move |mut bucket| match bucket.spill(&policy) {
+                    Ok(_) => Some(bucket),
+                    Err(message) => {
+                        error.set(Some(message));
+                        None
+                    }
+                })
+                .take_while(|bucket| futures::future::ready(bucket.is_some()))
+                .filter_map(futures::future::ready),
+        )
+    }
+}
+
+impl Pipe<DataBucket, DataBucket> for SpillPipe {
+    input_connection!(DataBucket);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn make_bucket() -> DataBucket {
+        let meta = |name: &str| MetaData {
+            name: name.to_string(),
+            ..Default::default()
+        };
+        let mut bucket = DataBucket::new();
+        let large = DataBlob::new((0..4096).map(f64::from).collect(), meta("large"));
+        bucket.add_blob(DataBucketBlob::Float64(large.with_checksum()));
+        let texts = (0..1024).map(|n| n.to_string()).collect();
+        bucket.add_blob(DataBucketBlob::Str(DataBlob::new(texts, meta("texts"))));
+        bucket.add_blob(DataBucketBlob::U8(DataBlob::new(
+            vec![1; 16],
+            meta("small"),
+        )));
+        let mut group = DataBucket::new();
+        let nested = DataBlob::from_options([Some(7_i32), None].repeat(2048), meta("nested"));
+        group.add_blob(DataBucketBlob::Int32(nested));
+        bucket.add_group("group", group);
+        bucket
+    }
+
+    #[test]
+    fn test_spill_and_read_back() {
+        let mut bucket = make_bucket();
+        let original = bucket.clone();
+        let before = bucket.memory_usage().total();
+        let policy = SpillPolicy::new(before - 20000);
+        let spilled = bucket.spill(&policy).unwrap();
+        assert_eq!(spilled, 32768, "Largest blob not spilled first");
+        assert!(bucket.is_spilled("large"));
+        assert!(bucket.memory_usage().total() <= policy.budget());
+        assert_eq!(bucket.memory_usage().spilled, 32768);
+        assert_eq!(bucket.len(), 3);
+        assert!(
+            bucket.diff(&original).is_empty(),
+            "Spilled data not read back"
+        );
+        assert!(
+            bucket.memory_usage().spilled == 0,
+            "Read back data not resident"
+        );
+        let spilled = bucket
+            .spill(&SpillPolicy::new(0).min_blob_size(1024))
+            .unwrap();
+        assert!(spilled > 32768);
+        assert!(bucket.get_group("group").unwrap().is_spilled("nested"));
+        assert!(
+            !bucket.is_spilled("small"),
+            "Blob below minimum size spilled"
+        );
+        let snapshot = bucket.snapshot();
+        bucket.unspill().unwrap();
+        assert_eq!(bucket.spilled_names().count(), 0);
+        assert!(snapshot.to_bucket().diff(&original).is_empty());
+    }
+
+    #[test]
+    fn test_spill_files_removed() {
+        let directory =
+            std::env::temp_dir().join(format!("bitvortex-spill-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let mut bucket = make_bucket();
+        let tracker = MemoryTracker::new();
+        let _held = tracker.reserve("stage", usize::MAX / 2);
+        let policy = SpillPolicy::new(1 << 20)
+            .in_directory(&directory)
+            .tracked(&tracker);
+        bucket.spill(&policy).unwrap();
+        assert_eq!(
+            fs::read_dir(&directory).unwrap().count(),
+            3,
+            "Tracked bytes ignored"
+        );
+        let copy = bucket.clone();
+        drop(bucket);
+        assert_eq!(
+            fs::read_dir(&directory).unwrap().count(),
+            3,
+            "Shared file removed"
+        );
+        drop(copy);
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 0);
+        let mut failing = make_bucket();
+        let missing = SpillPolicy::new(0).in_directory(directory.join("missing"));
+        assert_eq!(failing.spill(&missing), Err("Failure to write spill file"));
+        fs::remove_dir(&directory).unwrap();
+    }
+
+    #[test]
+    fn test_spill_pipe() {
+        let mut pipe = SpillPipe::new(SpillPolicy::new(0));
+        pipe.pipe(Rc::new(
+            MockSource::new().items([make_bucket(), make_bucket()]),
+        ))
+        .unwrap();
+        let buckets: Vec<DataBucket> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(buckets.len(), 2);
+        assert!(buckets.iter().all(|bucket| bucket.is_spilled("large")));
+        assert_eq!(pipe.error(), None);
+    }
+}
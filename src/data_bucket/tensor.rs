@@ -0,0 +1,252 @@
+//! tensor
+//!
+//! Multi-dimensional blobs with named axes
+//!
+//! a tensor is a blob holding its elements in row major order (the last axis varying
+//! fastest) with the sizes of its axes in the `dimensions` and their names in the `axes` of
+//! its meta data, so any blob variant can hold one and every other part of the library
+//! handles it as an ordinary blob. A tensor without axes holds a single value
+
+use super::mask::ValidityMask;
+use super::{DataBlob, MetaData};
+use std::ops::Range;
+
+/// TensorBlob
+/// A view of a blob as a tensor with named axes
+#[derive(Clone)]
+pub struct TensorBlob<T> {
+    blob: DataBlob<T>,
+}
+
+/// the number of elements before, along and after an axis of a shape
+fn strides(shape: &[usize], axis: usize) -> (usize, usize, usize) {
+    let outer = shape[..axis].iter().product();
+    let inner = shape[axis + 1..].iter().product();
+    (outer, shape[axis], inner)
+}
+
+impl<T> TensorBlob<T> {
+    /// constructor from row major data and the names and sizes of the axes
+    pub fn new(data: Vec<T>, axes: &[(&str, usize)], meta: MetaData) -> Result<Self, &'static str> {
+        let mut meta = meta;
+        meta.axes = axes.iter().map(|(name, _)| name.to_string()).collect();
+        meta.dimensions = axes.iter().map(|(_, size)| *size).collect();
+        Self::from_blob(DataBlob::new(data, meta))
+    }
+    /// view a blob as a tensor (returns an error if its axes do not match its dimensions and
+    /// data)
+    pub fn from_blob(blob: DataBlob<T>) -> Result<Self, &'static str> {
+        let meta = blob.get_meta_data();
+        if meta.axes.len() != meta.dimensions.len() {
+            return Err("Tensor axes do not match its dimensions");
+        }
+        if meta.dimensions.iter().product::<usize>() != blob.get_data().len() {
+            return Err("Tensor dimensions do not match its data");
+        }
+        for (index, axis) in meta.axes.iter().enumerate() {
+            if meta.axes[..index].contains(axis) {
+                return Err("Tensor has duplicate axis names");
+            }
+        }
+        Ok(Self { blob })
+    }
+    /// get the underlying blob
+    pub fn as_blob(&self) -> &DataBlob<T> {
+        &self.blob
+    }
+    /// convert back into the underlying blob
+    pub fn into_blob(self) -> DataBlob<T> {
+        self.blob
+    }
+    /// names of the axes in order
+    pub fn axes(&self) -> &[String] {
+        &self.blob.get_meta_data().axes
+    }
+    /// sizes of the axes in order
+    pub fn shape(&self) -> &[usize] {
+        &self.blob.get_meta_data().dimensions
+    }
+    /// position of a named axis
+    pub fn axis(&self, name: &str) -> Option<usize> {
+        self.axes().iter().position(|axis| axis == name)
+    }
+    /// the element at an index holding a position along every axis
+    pub fn get(&self, index: &[usize]) -> Option<&T> {
+        if index.len() != self.shape().len() {
+            return None;
+        }
+        let mut offset = 0;
+        for (position, size) in index.iter().zip(self.shape()) {
+            if position >= size {
+                return None;
+            }
+            offset = offset * size + position;
+        }
+        self.blob.get_data().get(offset)
+    }
+    fn axis_index(&self, name: &str) -> Result<usize, &'static str> {
+        self.axis(name).ok_or("Tensor has no such axis")
+    }
+}
+
+impl<T: Clone> TensorBlob<T> {
+    /// copy the positions of a range along an axis into a new tensor
+    pub fn slice(&self, axis: &str, range: Range<usize>) -> Result<Self, &'static str> {
+        let index = self.axis_index(axis)?;
+        if range.start > range.end || range.end > self.shape()[index] {
+            return Err("Range is out of the bounds of the axis");
+        }
+        let mut meta = self.blob.get_meta_data().clone();
+        meta.dimensions[index] = range.len();
+        meta.record(
+            "slice_axis",
+            &[
+                ("axis", axis.to_string()),
+                ("start", range.start.to_string()),
+                ("end", range.end.to_string()),
+            ],
+        );
+        self.gather(index, range, meta)
+    }
+    /// copy the elements at one position along an axis into a new tensor without that axis
+    pub fn select(&self, axis: &str, position: usize) -> Result<Self, &'static str> {
+        let index = self.axis_index(axis)?;
+        if position >= self.shape()[index] {
+            return Err("Range is out of the bounds of the axis");
+        }
+        let mut meta = self.blob.get_meta_data().clone();
+        meta.dimensions.remove(index);
+        meta.axes.remove(index);
+        meta.record(
+            "select_axis",
+            &[("axis", axis.to_string()), ("index", position.to_string())],
+        );
+        self.gather(index, position..position + 1, meta)
+    }
+    /// reduce the valid values along an axis into a new tensor without that axis
+    ///
+    /// the reduction receives the values of each lane of the axis in order, an empty lane if
+    /// none of them are valid
+    pub fn reduce<F: Fn(&[T]) -> T>(&self, axis: &str, reduction: F) -> Result<Self, &'static str> {
+        let index = self.axis_index(axis)?;
+        let (outer, size, inner) = strides(self.shape(), index);
+        let data = self.blob.get_data();
+        let mut reduced = Vec::with_capacity(outer * inner);
+        let mut lane = Vec::with_capacity(size);
+        for before in 0..outer {
+            for after in 0..inner {
+                lane.clear();
+                for position in 0..size {
+                    let offset = (before * size + position) * inner + after;
+                    if self.blob.is_valid(offset) {
+                        lane.push(data[offset].clone());
+                    }
+                }
+                reduced.push(reduction(&lane));
+            }
+        }
+        let mut meta = self.blob.get_meta_data().clone();
+        meta.dimensions.remove(index);
+        meta.axes.remove(index);
+        meta.record("reduce_axis", &[("axis", axis.to_string())]);
+        Self::from_blob(DataBlob::new(reduced, meta))
+    }
+    fn gather(
+        &self,
+        index: usize,
+        positions: Range<usize>,
+        meta: MetaData,
+    ) -> Result<Self, &'static str> {
+        let (outer, size, inner) = strides(self.shape(), index);
+        let offsets = (0..outer).flat_map(|before| {
+            let positions = positions.clone();
+            positions.flat_map(move |position| {
+                (0..inner).map(move |after| (before * size + position) * inner + after)
+            })
+        });
+        let offsets: Vec<usize> = offsets.collect();
+        let data = self.blob.get_data();
+        let blob = DataBlob::new(offsets.iter().map(|&at| data[at].clone()).collect(), meta);
+        let blob = match self.blob.get_mask() {
+            Some(mask) => blob.with_mask(ValidityMask::from_bools(
+                offsets.iter().map(|&at| mask.is_valid(at)),
+            ))?,
+            None => blob,
+        };
+        Self::from_blob(blob)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// temperatures of 2 stations over 3 days at 2 heights
+    fn temperatures() -> TensorBlob<f64> {
+        let data = (0..12).map(|value| value as f64).collect();
+        let axes = [("station", 2), ("day", 3), ("height", 2)];
+        TensorBlob::new(data, &axes, MetaData::default()).unwrap()
+    }
+
+    #[test]
+    fn test_tensor_views() {
+        let tensor = temperatures();
+        assert_eq!(tensor.shape(), &[2, 3, 2]);
+        assert_eq!(tensor.axis("day"), Some(1));
+        assert_eq!(tensor.get(&[1, 2, 0]), Some(&10.0));
+        assert_eq!(tensor.get(&[1, 3, 0]), None);
+        let days = tensor.slice("day", 1..3).unwrap();
+        assert_eq!(days.shape(), &[2, 2, 2]);
+        assert_eq!(
+            days.as_blob().get_data(),
+            &vec![2.0, 3.0, 4.0, 5.0, 8.0, 9.0, 10.0, 11.0]
+        );
+        let ground = tensor.select("height", 0).unwrap();
+        assert_eq!(ground.axes(), &["station".to_string(), "day".to_string()]);
+        assert_eq!(
+            ground.as_blob().get_data(),
+            &vec![0.0, 2.0, 4.0, 6.0, 8.0, 10.0]
+        );
+        assert_eq!(
+            ground.as_blob().get_meta_data().explain(),
+            "select_axis(axis=height, index=0)"
+        );
+        assert_eq!(
+            tensor.slice("year", 0..1).err(),
+            Some("Tensor has no such axis")
+        );
+        assert!(tensor.select("day", 3).is_err());
+        let flat = DataBlob::new(vec![1, 2, 3], MetaData::default());
+        assert!(TensorBlob::from_blob(flat).is_err());
+        let twice = TensorBlob::new(vec![0; 4], &[("x", 2), ("x", 2)], MetaData::default());
+        assert_eq!(twice.err(), Some("Tensor has duplicate axis names"));
+    }
+
+    #[test]
+    fn test_tensor_reduce() {
+        let sum = |lane: &[f64]| lane.iter().sum();
+        let tensor = temperatures();
+        let daily = tensor.reduce("station", sum).unwrap();
+        assert_eq!(daily.shape(), &[3, 2]);
+        assert_eq!(
+            daily.as_blob().get_data(),
+            &vec![6.0, 8.0, 10.0, 12.0, 14.0, 16.0]
+        );
+        let total = daily
+            .reduce("day", sum)
+            .unwrap()
+            .reduce("height", sum)
+            .unwrap();
+        assert!(total.shape().is_empty());
+        assert_eq!(total.get(&[]), Some(&66.0));
+        let mask = ValidityMask::from_bools((0..12).map(|index| index != 1));
+        let blob = tensor.into_blob().with_mask(mask).unwrap();
+        let masked = TensorBlob::from_blob(blob).unwrap();
+        let heights = masked.reduce("height", |lane| lane.len() as f64).unwrap();
+        assert_eq!(heights.get(&[0, 0]), Some(&1.0));
+        assert_eq!(
+            masked.slice("height", 1..2).unwrap().as_blob().null_count(),
+            1
+        );
+    }
+}
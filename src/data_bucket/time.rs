@@ -0,0 +1,226 @@
+//! time
+//!
+//! Helpers for interpreting integer time columns stored in `Timestamp` blobs
+
+/// TimeResolution
+/// The duration represented by one tick of a timestamp
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TimeResolution {
+    Seconds,
+    Milliseconds,
+    Microseconds,
+    Nanoseconds,
+}
+
+impl TimeResolution {
+    /// number of ticks in one second
+    pub fn ticks_per_second(&self) -> i64 {
+        match self {
+            TimeResolution::Seconds => 1,
+            TimeResolution::Milliseconds => 1_000,
+            TimeResolution::Microseconds => 1_000_000,
+            TimeResolution::Nanoseconds => 1_000_000_000,
+        }
+    }
+    /// number of fractional digits needed to print one tick
+    fn digits(&self) -> usize {
+        match self {
+            TimeResolution::Seconds => 0,
+            TimeResolution::Milliseconds => 3,
+            TimeResolution::Microseconds => 6,
+            TimeResolution::Nanoseconds => 9,
+        }
+    }
+}
+
+/// TimeBase
+/// Describes how the integer values of a timestamp blob map onto UTC time
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TimeBase {
+    /// the instant of tick zero, in seconds since the unix epoch (1970-01-01T00:00:00Z)
+    pub epoch: i64,
+    /// the duration of one tick
+    pub resolution: TimeResolution,
+}
+
+impl TimeBase {
+    /// a time base counting from the unix epoch
+    pub fn unix(resolution: TimeResolution) -> Self {
+        Self {
+            epoch: 0,
+            resolution,
+        }
+    }
+    /// parse an RFC 3339 date-time (or a plain `YYYY-MM-DD` date) into ticks
+    pub fn parse(&self, text: &str) -> Result<i64, &'static str> {
+        let (seconds, nanos) = parse_rfc3339(text)?;
+        let per_second = self.resolution.ticks_per_second();
+        let ticks = (seconds - self.epoch) as i128 * per_second as i128
+            + nanos as i128 / (1_000_000_000 / per_second) as i128;
+        i64::try_from(ticks).map_err(|_| "Timestamp out of range for resolution")
+    }
+    /// format ticks as an RFC 3339 UTC date-time
+    pub fn format(&self, ticks: i64) -> String {
+        let per_second = self.resolution.ticks_per_second();
+        let seconds = ticks.div_euclid(per_second) + self.epoch;
+        let fraction = ticks.rem_euclid(per_second);
+        let days = seconds.div_euclid(86_400);
+        let day_seconds = seconds.rem_euclid(86_400);
+        let (year, month, day) = civil_from_days(days);
+        let mut out = format!(
+            "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}",
+            year,
+            month,
+            day,
+            day_seconds / 3600,
+            (day_seconds % 3600) / 60,
+            day_seconds % 60
+        );
+        if self.resolution.digits() > 0 {
+            out.push_str(&format!(
+                ".{:0width$}",
+                fraction,
+                width = self.resolution.digits()
+            ));
+        }
+        out.push('Z');
+        out
+    }
+}
+
+/// days since the unix epoch for a proleptic gregorian calendar date
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// proleptic gregorian calendar date for a number of days since the unix epoch
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn parse_number(text: &str, range: std::ops::Range<usize>) -> Result<i64, &'static str> {
+    let digits = text.get(range).ok_or("Truncated timestamp")?;
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err("Malformed timestamp");
+    }
+    digits.parse().map_err(|_| "Malformed timestamp")
+}
+
+/// parse an RFC 3339 string into (seconds since unix epoch, nanoseconds)
+fn parse_rfc3339(text: &str) -> Result<(i64, i64), &'static str> {
+    let text = text.trim();
+    if !text.is_ascii() {
+        return Err("Malformed timestamp");
+    }
+    let year = parse_number(text, 0..4)?;
+    let month = parse_number(text, 5..7)?;
+    let day = parse_number(text, 8..10)?;
+    if &text[4..5] != "-" || &text[7..8] != "-" {
+        return Err("Malformed timestamp");
+    }
+    if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+        return Err("Date out of range");
+    }
+    let days = days_from_civil(year, month, day);
+    if text.len() == 10 {
+        return Ok((days * 86_400, 0));
+    }
+    if text.len() < 19 {
+        return Err("Truncated timestamp");
+    }
+    if !matches!(&text[10..11], "T" | "t" | " ") || &text[13..14] != ":" {
+        return Err("Malformed timestamp");
+    }
+    let hours = parse_number(text, 11..13)?;
+    let minutes = parse_number(text, 14..16)?;
+    let seconds = parse_number(text, 17..19)?;
+    if &text[16..17] != ":" {
+        return Err("Malformed timestamp");
+    }
+    if hours > 23 || minutes > 59 || seconds > 60 {
+        return Err("Time out of range");
+    }
+    let mut rest = &text[19..];
+    let mut nanos = 0;
+    if let Some(fraction) = rest.strip_prefix('.') {
+        let length = fraction.bytes().take_while(|b| b.is_ascii_digit()).count();
+        if length == 0 {
+            return Err("Malformed timestamp");
+        }
+        for (idx, digit) in fraction[..length].bytes().take(9).enumerate() {
+            nanos += (digit - b'0') as i64 * 10_i64.pow(8 - idx as u32);
+        }
+        rest = &fraction[length..];
+    }
+    let offset = match rest {
+        "Z" | "z" => 0,
+        _ if rest.len() == 6 && &rest[3..4] == ":" => {
+            let sign = match &rest[0..1] {
+                "+" => 1,
+                "-" => -1,
+                _ => return Err("Malformed timezone offset"),
+            };
+            sign * (parse_number(rest, 1..3)? * 3600 + parse_number(rest, 4..6)? * 60)
+        }
+        _ => return Err("Malformed timezone offset"),
+    };
+    Ok((
+        days * 86_400 + hours * 3600 + minutes * 60 + seconds - offset,
+        nanos,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_format_round_trip() {
+        let base = TimeBase::unix(TimeResolution::Milliseconds);
+        let ticks = base.parse("2022-07-14T12:30:05.250Z").unwrap();
+        assert_eq!(ticks, 1_657_801_805_250, "Failure to parse timestamp");
+        assert_eq!(
+            base.format(ticks),
+            "2022-07-14T12:30:05.250Z",
+            "Failure to format timestamp"
+        );
+    }
+
+    #[test]
+    fn test_parse_offset_and_date() {
+        let base = TimeBase::unix(TimeResolution::Seconds);
+        assert_eq!(base.parse("1970-01-01T01:00:00+01:00").unwrap(), 0);
+        assert_eq!(base.parse("1969-12-31").unwrap(), -86_400);
+        assert!(base.parse("1970-13-01").is_err(), "Accepted invalid month");
+        assert!(base.parse("yesterday").is_err(), "Accepted garbage");
+    }
+
+    #[test]
+    fn test_custom_epoch() {
+        let base = TimeBase {
+            epoch: 946_684_800,
+            resolution: TimeResolution::Seconds,
+        };
+        assert_eq!(base.parse("2000-01-01T00:01:00Z").unwrap(), 60);
+        assert_eq!(base.format(-1), "1999-12-31T23:59:59Z");
+    }
+}
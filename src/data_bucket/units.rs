@@ -0,0 +1,193 @@
+//! units
+//!
+//! Parsing and combining the units of MetaData
+//!
+//! units are products of symbols raised to integer powers such as `kg*m/s^2`, `m s⁻¹` or
+//! `N·m`, factors are separated by `*`, `·` or spaces and a `/` puts the factor following it
+//! in the denominator, `1` is the unit of dimensionless data, symbols are compared as written
+//! so `km` and `m` are different units, blobs with no units are never checked
+
+use super::MetaData;
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Units
+/// The exponent of every symbol in a unit expression
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Units {
+    exponents: BTreeMap<String, i32>,
+}
+
+const SUPERSCRIPTS: [char; 10] = ['⁰', '¹', '²', '³', '⁴', '⁵', '⁶', '⁷', '⁸', '⁹'];
+
+/// parse the exponent following a symbol, written `^-2` or `⁻²` (1 if absent)
+fn parse_exponent(text: &str) -> Result<i32, &'static str> {
+    if text.is_empty() {
+        return Ok(1);
+    }
+    if let Some(power) = text.strip_prefix('^') {
+        return power.parse().map_err(|_| "Invalid unit exponent");
+    }
+    let (sign, digits) = match text.strip_prefix('⁻') {
+        Some(digits) => (-1, digits),
+        None => (1, text),
+    };
+    let mut power: i32 = 0;
+    for digit in digits.chars() {
+        let value = SUPERSCRIPTS
+            .iter()
+            .position(|superscript| *superscript == digit)
+            .ok_or("Invalid unit exponent")?;
+        power = power * 10 + value as i32;
+    }
+    if digits.is_empty() {
+        return Err("Invalid unit exponent");
+    }
+    Ok(sign * power)
+}
+
+impl Units {
+    /// the units of dimensionless data
+    pub fn dimensionless() -> Self {
+        Self::default()
+    }
+    /// parse a unit expression
+    pub fn parse(text: &str) -> Result<Self, &'static str> {
+        let mut units = Self::default();
+        let mut sign = 1;
+        let spaced = text.replace('/', " / ");
+        for token in spaced.split(|c: char| c == '*' || c == '·' || c.is_whitespace()) {
+            match token {
+                "" => continue,
+                "/" => {
+                    sign = -1;
+                    continue;
+                }
+                "1" => {}
+                _ => {
+                    let split = token
+                        .find(|c: char| c == '^' || c == '⁻' || SUPERSCRIPTS.contains(&c))
+                        .unwrap_or(token.len());
+                    let (symbol, exponent) = token.split_at(split);
+                    if symbol.is_empty() || !symbol.chars().all(|c| c.is_alphabetic() || c == '°')
+                    {
+                        return Err("Invalid unit symbol");
+                    }
+                    units.add(symbol, sign * parse_exponent(exponent)?);
+                }
+            }
+            sign = 1;
+        }
+        Ok(units)
+    }
+    fn add(&mut self, symbol: &str, exponent: i32) {
+        let total = self.exponents.get(symbol).copied().unwrap_or(0) + exponent;
+        match total {
+            0 => self.exponents.remove(symbol),
+            _ => self.exponents.insert(symbol.to_string(), total),
+        };
+    }
+    /// whether the data has no dimension
+    pub fn is_dimensionless(&self) -> bool {
+        self.exponents.is_empty()
+    }
+    /// the exponent of a symbol (0 if absent)
+    pub fn exponent(&self, symbol: &str) -> i32 {
+        self.exponents.get(symbol).copied().unwrap_or(0)
+    }
+    /// iterate over (symbol, exponent) pairs in symbol order
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &i32)> {
+        self.exponents.iter()
+    }
+    /// the units of a product
+    pub fn mul(&self, other: &Units) -> Units {
+        let mut units = self.clone();
+        for (symbol, exponent) in other.exponents.iter() {
+            units.add(symbol, *exponent);
+        }
+        units
+    }
+    /// the units of a quotient
+    pub fn div(&self, other: &Units) -> Units {
+        self.mul(&other.powi(-1))
+    }
+    /// the units raised to an integer power
+    pub fn powi(&self, power: i32) -> Units {
+        let mut units = Units::default();
+        for (symbol, exponent) in self.exponents.iter() {
+            units.add(symbol, exponent * power);
+        }
+        units
+    }
+}
+
+impl fmt::Display for Units {
+    /// symbols with positive exponents joined by `*` then a `/` before each negative one
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let factor = |symbol: &String, exponent: i32| match exponent {
+            1 => symbol.clone(),
+            _ => format!("{}^{}", symbol, exponent),
+        };
+        let numerator: Vec<String> = self
+            .exponents
+            .iter()
+            .filter(|(_, exponent)| **exponent > 0)
+            .map(|(symbol, exponent)| factor(symbol, *exponent))
+            .collect();
+        if numerator.is_empty() {
+            write!(f, "1")?;
+        } else {
+            write!(f, "{}", numerator.join("*"))?;
+        }
+        for (symbol, exponent) in self.exponents.iter().filter(|(_, e)| **e < 0) {
+            write!(f, "/{}", factor(symbol, -exponent))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::str::FromStr for Units {
+    type Err = &'static str;
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        Units::parse(text)
+    }
+}
+
+impl MetaData {
+    /// parse the units of the data (None if the data has no units)
+    pub fn parsed_units(&self) -> Result<Option<Units>, &'static str> {
+        self.units.as_deref().map(Units::parse).transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_units() {
+        let speed = Units::parse("m/s").unwrap();
+        assert_eq!(Units::parse("m*s^-1").unwrap(), speed);
+        assert_eq!(Units::parse("m s⁻¹").unwrap(), speed);
+        assert_eq!(speed.to_string(), "m/s");
+        let force = Units::parse("kg·m/s²").unwrap();
+        assert_eq!(force.exponent("s"), -2);
+        assert_eq!(force.to_string(), "kg*m/s^2");
+        assert!(Units::parse("1").unwrap().is_dimensionless());
+        assert!(Units::parse("m^x").is_err());
+        assert!(Units::parse("m^").is_err());
+        assert!(Units::parse("3m").is_err());
+    }
+
+    #[test]
+    fn test_combine_units() {
+        let metres = Units::parse("m").unwrap();
+        let seconds = Units::parse("s").unwrap();
+        let speed = metres.div(&seconds);
+        assert_eq!(speed.to_string(), "m/s");
+        assert_eq!(speed.mul(&seconds), metres);
+        assert_eq!(seconds.powi(-1).to_string(), "1/s");
+        assert!(speed.div(&speed).is_dimensionless());
+        assert_eq!(speed.powi(2).to_string(), "m^2/s^2");
+    }
+}
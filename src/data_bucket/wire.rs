@@ -0,0 +1,629 @@
+//! wire
+//!
+//! Self-describing binary encoding of whole blobs and buckets, with their meta data, masks
+//! and groups, for moving them between processes
+//!
+//! every integer is little endian and every string or byte array is prefixed with its
+//! length, blobs are written in name order so equal buckets encode to equal bytes. Decoding
+//! verifies the checksums the blobs carry. The data of every blob is tagged with the codec
+//! it is compressed with, if any, and decoding decompresses it whatever the codec. With the
+//! `serde` feature blobs and buckets serialize as their encoding (compressed through
+//! `Compressed`), the `encryption` feature holds the encrypted encodings
+
+use super::checksum::Checksum;
+use super::compression::Codec;
+use super::encoding::BinaryElement;
+use super::mask::ValidityMask;
+use super::provenance::ProvenanceEntry;
+use super::schema::BlobKind;
+use super::time::{TimeBase, TimeResolution};
+use super::{for_each_variant, DataBlob, DataBucket, DataBucketBlob, Link, LinkType, MetaData};
+use std::time::{Duration, SystemTime};
+
+/// appends the encoding of values to a buffer
+struct Writer<'a>(&'a mut Vec<u8>);
+
+impl Writer<'_> {
+    fn u8(&mut self, value: u8) {
+        self.0.push(value);
+    }
+    fn u64(&mut self, value: u64) {
+        self.0.extend_from_slice(&value.to_le_bytes());
+    }
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.u64(bytes.len() as u64);
+        self.0.extend_from_slice(bytes);
+    }
+    fn text(&mut self, text: &str) {
+        self.bytes(text.as_bytes());
+    }
+    fn optional_text(&mut self, text: &Option<String>) {
+        match text {
+            Some(text) => {
+                self.u8(1);
+                self.text(text);
+            }
+            None => self.u8(0),
+        }
+    }
+    fn sizes(&mut self, sizes: &[usize]) {
+        self.u64(sizes.len() as u64);
+        for size in sizes {
+            self.u64(*size as u64);
+        }
+    }
+}
+
+/// reads values back from an encoding
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, len: usize) -> Result<&'a [u8], &'static str> {
+        let (taken, rest) = self.0.split_at_checked(len).ok_or("Truncated encoding")?;
+        self.0 = rest;
+        Ok(taken)
+    }
+    fn u8(&mut self) -> Result<u8, &'static str> {
+        Ok(self.take(1)?[0])
+    }
+    fn u64(&mut self) -> Result<u64, &'static str> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+    }
+    fn len(&mut self) -> Result<usize, &'static str> {
+        let len = self.u64()? as usize;
+        // a length beyond the remaining bytes can only come from corrupted data
+        match len <= self.0.len() {
+            true => Ok(len),
+            false => Err("Truncated encoding"),
+        }
+    }
+    fn bytes(&mut self) -> Result<&'a [u8], &'static str> {
+        let len = self.len()?;
+        self.take(len)
+    }
+    fn text(&mut self) -> Result<String, &'static str> {
+        String::from_utf8(self.bytes()?.to_vec()).map_err(|_| "Invalid utf-8 string in encoding")
+    }
+    fn flag(&mut self) -> Result<bool, &'static str> {
+        match self.u8()? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err("Invalid flag in encoding"),
+        }
+    }
+    fn optional_text(&mut self) -> Result<Option<String>, &'static str> {
+        Ok(match self.flag()? {
+            true => Some(self.text()?),
+            false => None,
+        })
+    }
+    fn sizes(&mut self) -> Result<Vec<usize>, &'static str> {
+        (0..self.len()?).map(|_| Ok(self.u64()? as usize)).collect()
+    }
+    fn finish(&self) -> Result<(), &'static str> {
+        match self.0.is_empty() {
+            true => Ok(()),
+            false => Err("Trailing bytes after encoding"),
+        }
+    }
+}
+
+fn write_meta(meta: &MetaData, out: &mut Writer) -> Result<(), &'static str> {
+    out.text(&meta.name);
+    out.optional_text(&meta.units);
+    out.optional_text(&meta.description);
+    out.sizes(&meta.dimensions);
+    out.sizes(&meta.unitary_dimensions);
+    out.u64(meta.axes.len() as u64);
+    for axis in meta.axes.iter() {
+        out.text(axis);
+    }
+    out.u64(meta.links.len() as u64);
+    for link in meta.links.iter() {
+        out.u8(match link.nature {
+            LinkType::OneToOne => 0,
+            LinkType::Handle => 1,
+            LinkType::Reduced => 2,
+        });
+        out.text(&link.linker);
+        out.text(&link.linkee);
+    }
+    match &meta.time_base {
+        Some(base) => {
+            out.u8(1);
+            out.u64(base.epoch as u64);
+            out.u8(match base.resolution {
+                TimeResolution::Seconds => 0,
+                TimeResolution::Milliseconds => 1,
+                TimeResolution::Microseconds => 2,
+                TimeResolution::Nanoseconds => 3,
+            });
+        }
+        None => out.u8(0),
+    }
+    match meta.sample_rate {
+        Some(rate) => {
+            out.u8(1);
+            out.u64(rate.to_bits());
+        }
+        None => out.u8(0),
+    }
+    out.u64(meta.provenance.len() as u64);
+    for entry in meta.provenance.iter() {
+        out.text(&entry.operation);
+        out.u64(entry.parameters.len() as u64);
+        for (name, value) in entry.parameters.iter() {
+            out.text(name);
+            out.text(value);
+        }
+        let since = entry
+            .timestamp
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map_err(|_| "Provenance timestamp before the unix epoch")?;
+        out.u64(since.as_secs());
+        out.u64(since.subsec_nanos() as u64);
+    }
+    match meta.checksum {
+        Some(Checksum::XxHash3(hash)) => {
+            out.u8(1);
+            out.u64(hash);
+        }
+        None => out.u8(0),
+    }
+    Ok(())
+}
+
+fn read_meta(input: &mut Reader) -> Result<MetaData, &'static str> {
+    let mut meta = MetaData {
+        name: input.text()?,
+        units: input.optional_text()?,
+        description: input.optional_text()?,
+        dimensions: input.sizes()?,
+        unitary_dimensions: input.sizes()?,
+        ..Default::default()
+    };
+    for _ in 0..input.len()? {
+        meta.axes.push(input.text()?);
+    }
+    for _ in 0..input.len()? {
+        let nature = match input.u8()? {
+            0 => LinkType::OneToOne,
+            1 => LinkType::Handle,
+            2 => LinkType::Reduced,
+            _ => return Err("Invalid link type in encoding"),
+        };
+        meta.links.push(Link {
+            nature,
+            linker: input.text()?,
+            linkee: input.text()?,
+        });
+    }
+    if input.flag()? {
+        let epoch = input.u64()? as i64;
+        let resolution = match input.u8()? {
+            0 => TimeResolution::Seconds,
+            1 => TimeResolution::Milliseconds,
+            2 => TimeResolution::Microseconds,
+            3 => TimeResolution::Nanoseconds,
+            _ => return Err("Invalid time resolution in encoding"),
+        };
+        meta.time_base = Some(TimeBase { epoch, resolution });
+    }
+    if input.flag()? {
+        meta.sample_rate = Some(f64::from_bits(input.u64()?));
+    }
+    for _ in 0..input.len()? {
+        let operation = input.text()?;
+        let parameters = (0..input.len()?)
+            .map(|_| Ok((input.text()?, input.text()?)))
+            .collect::<Result<_, &'static str>>()?;
+        let (seconds, nanos) = (input.u64()?, input.u64()?);
+        let timestamp = (nanos < 1_000_000_000)
+            .then(|| SystemTime::UNIX_EPOCH.checked_add(Duration::new(seconds, nanos as u32)))
+            .flatten()
+            .ok_or("Invalid provenance timestamp in encoding")?;
+        meta.provenance.push(ProvenanceEntry {
+            operation,
+            parameters,
+            timestamp,
+        });
+    }
+    if input.flag()? {
+        meta.checksum = Some(Checksum::XxHash3(input.u64()?));
+    }
+    Ok(meta)
+}
+
+fn restore<T: BinaryElement>(
+    bytes: &[u8],
+    meta: MetaData,
+    mask: Option<ValidityMask>,
+) -> Result<DataBlob<T>, &'static str> {
+    let blob = DataBlob::new(T::read_bytes(bytes)?, meta);
+    match mask {
+        Some(mask) => blob.with_mask(mask)?,
+        None => blob,
+    }
+    .verify_if_present()
+}
+
+macro_rules! wire_unwrap {
+  ($($x:ident),*) => {
+    fn write_parts<'a>(blob: &'a DataBucketBlob, out: &mut Vec<u8>) -> (&'a MetaData, Option<&'a ValidityMask>) {
+      match blob {
+        $( DataBucketBlob::$x(blob) => {
+          BinaryElement::write_bytes(blob.get_data(), out);
+          (blob.get_meta_data(), blob.get_mask())
+        } )*
+      }
+    }
+
+    fn read_parts(
+      kind: BlobKind,
+      bytes: &[u8],
+      meta: MetaData,
+      mask: Option<ValidityMask>,
+    ) -> Result<DataBucketBlob, &'static str> {
+      match kind {
+        $( BlobKind::$x => restore(bytes, meta, mask).map(DataBucketBlob::$x), )*
+      }
+    }
+  }
+}
+
+for_each_variant!(wire_unwrap);
+
+fn write_blob(
+    blob: &DataBucketBlob,
+    codec: Option<Codec>,
+    out: &mut Writer,
+) -> Result<(), &'static str> {
+    let mut data = Vec::new();
+    let (meta, mask) = write_parts(blob, &mut data);
+    out.text(blob.kind().name());
+    write_meta(meta, out)?;
+    match mask {
+        Some(mask) => {
+            out.u8(1);
+            let validity: Vec<bool> = mask.iter().collect();
+            let mut bytes = Vec::new();
+            bool::write_bytes(&validity, &mut bytes);
+            out.bytes(&bytes);
+        }
+        None => out.u8(0),
+    }
+    match codec {
+        Some(codec) => {
+            out.u8(codec.wire_tag());
+            out.u64(data.len() as u64);
+            out.bytes(&codec.compress(&data)?);
+        }
+        None => {
+            out.u8(0);
+            out.bytes(&data);
+        }
+    }
+    Ok(())
+}
+
+fn read_blob(input: &mut Reader) -> Result<DataBucketBlob, &'static str> {
+    let kind = BlobKind::from_name(&input.text()?).ok_or("Unknown blob type in encoding")?;
+    let meta = read_meta(input)?;
+    let mask = match input.flag()? {
+        true => Some(ValidityMask::from_bools(bool::read_bytes(input.bytes()?)?)),
+        false => None,
+    };
+    match input.u8()? {
+        0 => read_parts(kind, input.bytes()?, meta, mask),
+        tag => {
+            let codec = Codec::from_wire_tag(tag)?;
+            let size = input.u64()? as usize;
+            let data = codec.decompress(input.bytes()?, size)?;
+            if data.len() != size {
+                return Err("Decompressed data smaller than expected");
+            }
+            read_parts(kind, &data, meta, mask)
+        }
+    }
+}
+
+fn write_bucket(
+    bucket: &DataBucket,
+    codec: Option<Codec>,
+    out: &mut Writer,
+) -> Result<(), &'static str> {
+    let mut names: Vec<&String> = bucket.blob_names().collect();
+    names.sort();
+    out.u64(names.len() as u64);
+    for name in names {
+        let blob = bucket
+            .get_blob(name)
+            .ok_or("Failure to load a blob to encode")?;
+        write_blob(blob, codec, out)?;
+    }
+    let mut groups: Vec<(&String, &DataBucket)> = bucket.groups().collect();
+    groups.sort_by_key(|(name, _)| *name);
+    out.u64(groups.len() as u64);
+    for (name, group) in groups {
+        out.text(name);
+        write_bucket(group, codec, out)?;
+    }
+    Ok(())
+}
+
+fn read_bucket(input: &mut Reader) -> Result<DataBucket, &'static str> {
+    let mut bucket = DataBucket::new();
+    for _ in 0..input.len()? {
+        if bucket.add_blob(read_blob(input)?).is_some() {
+            return Err("Duplicate blob in encoding");
+        }
+    }
+    for _ in 0..input.len()? {
+        let name = input.text()?;
+        if bucket.add_group(&name, read_bucket(input)?).is_some() {
+            return Err("Duplicate group in encoding");
+        }
+    }
+    Ok(bucket)
+}
+
+impl DataBucketBlob {
+    /// the encoding of the blob with its meta data and mask
+    pub fn to_bytes(&self) -> Result<Vec<u8>, &'static str> {
+        let mut bytes = Vec::new();
+        write_blob(self, None, &mut Writer(&mut bytes))?;
+        Ok(bytes)
+    }
+    /// the encoding of the blob with its data compressed by a codec
+    pub fn to_compressed_bytes(&self, codec: Codec) -> Result<Vec<u8>, &'static str> {
+        let mut bytes = Vec::new();
+        write_blob(self, Some(codec), &mut Writer(&mut bytes))?;
+        Ok(bytes)
+    }
+    /// decode a blob written by `to_bytes` or `to_compressed_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        let mut input = Reader(bytes);
+        let blob = read_blob(&mut input)?;
+        input.finish()?;
+        Ok(blob)
+    }
+}
+
+impl DataBucket {
+    /// the encoding of the bucket with its blobs and groups (spilled and lazy blobs are
+    /// loaded)
+    pub fn to_bytes(&self) -> Result<Vec<u8>, &'static str> {
+        let mut bytes = Vec::new();
+        write_bucket(self, None, &mut Writer(&mut bytes))?;
+        Ok(bytes)
+    }
+    /// the encoding of the bucket with the data of every blob compressed by a codec
+    pub fn to_compressed_bytes(&self, codec: Codec) -> Result<Vec<u8>, &'static str> {
+        let mut bytes = Vec::new();
+        write_bucket(self, Some(codec), &mut Writer(&mut bytes))?;
+        Ok(bytes)
+    }
+    /// decode a bucket written by `to_bytes` or `to_compressed_bytes`
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        let mut input = Reader(bytes);
+        let bucket = read_bucket(&mut input)?;
+        input.finish()?;
+        Ok(bucket)
+    }
+}
+
+/// the visitor of the byte arrays blobs and buckets are serialized as, either as bytes or
+/// as a sequence for formats without them
+#[cfg(feature = "serde")]
+pub(crate) struct BytesVisitor;
+
+#[cfg(feature = "serde")]
+impl<'de> serde::de::Visitor<'de> for BytesVisitor {
+    type Value = Vec<u8>;
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("a byte array")
+    }
+    fn visit_bytes<E: serde::de::Error>(self, bytes: &[u8]) -> Result<Vec<u8>, E> {
+        Ok(bytes.to_vec())
+    }
+    fn visit_byte_buf<E: serde::de::Error>(self, bytes: Vec<u8>) -> Result<Vec<u8>, E> {
+        Ok(bytes)
+    }
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Vec<u8>, A::Error> {
+        let mut bytes = Vec::with_capacity(seq.size_hint().unwrap_or(0));
+        while let Some(byte) = seq.next_element()? {
+            bytes.push(byte);
+        }
+        Ok(bytes)
+    }
+}
+
+macro_rules! serde_as_bytes {
+  ($($x:ident),*) => {
+    $(
+      /// serialized as its wire encoding
+      #[cfg(feature = "serde")]
+      impl serde::Serialize for $x {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+          let bytes = self.to_bytes().map_err(serde::ser::Error::custom)?;
+          serializer.serialize_bytes(&bytes)
+        }
+      }
+
+      #[cfg(feature = "serde")]
+      impl<'de> serde::Deserialize<'de> for $x {
+        fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+          let bytes = deserializer.deserialize_bytes(BytesVisitor)?;
+          $x::from_bytes(&bytes).map_err(serde::de::Error::custom)
+        }
+      }
+    )*
+  }
+}
+
+serde_as_bytes!(DataBucketBlob, DataBucket);
+
+/// Compressed
+/// A blob or bucket serialized as its encoding with the data compressed by a codec, read
+/// back by deserializing the blob or bucket itself
+#[cfg(feature = "serde")]
+pub struct Compressed<'a, T> {
+    value: &'a T,
+    codec: Codec,
+}
+
+#[cfg(feature = "serde")]
+impl<'a, T> Compressed<'a, T> {
+    /// constructor
+    pub fn new(value: &'a T, codec: Codec) -> Self {
+        Self { value, codec }
+    }
+}
+
+macro_rules! serde_compressed {
+  ($($x:ident),*) => {
+    $(
+      #[cfg(feature = "serde")]
+      impl serde::Serialize for Compressed<'_, $x> {
+        fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+          let bytes = self
+            .value
+            .to_compressed_bytes(self.codec)
+            .map_err(serde::ser::Error::custom)?;
+          serializer.serialize_bytes(&bytes)
+        }
+      }
+    )*
+  }
+}
+
+serde_compressed!(DataBucketBlob, DataBucket);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn make_bucket() -> DataBucket {
+        let meta = MetaData {
+            name: "temperature".to_string(),
+            units: Some("K".to_string()),
+            dimensions: vec![3],
+            axes: vec!["time".to_string()],
+            unitary_dimensions: vec![1],
+            ..Default::default()
+        };
+        let temperature = DataBlob::from_options(vec![Some(280.5), None, Some(291.0)], meta);
+        let names = DataBlob::new(
+            vec!["a".to_string(), "b".to_string()],
+            MetaData {
+                name: "names".to_string(),
+                links: vec![Link {
+                    nature: LinkType::Handle,
+                    linker: "names".to_string(),
+                    linkee: "temperature".to_string(),
+                }],
+                ..Default::default()
+            },
+        );
+        let mut group = DataBucket::new();
+        group.add_blob(DataBucketBlob::Int64(DataBlob::new(
+            vec![-1, 2],
+            MetaData {
+                name: "counts".to_string(),
+                ..Default::default()
+            },
+        )));
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(DataBucketBlob::Float64(temperature.with_checksum()));
+        bucket.add_blob(DataBucketBlob::Str(names));
+        bucket.add_group("station", group);
+        bucket
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bucket = make_bucket();
+        let bytes = bucket.to_bytes().unwrap();
+        assert_eq!(bucket.to_bytes().unwrap(), bytes);
+        let decoded = DataBucket::from_bytes(&bytes).unwrap();
+        assert!(bucket.diff(&decoded).is_empty());
+        let name = "temperature".to_string();
+        let blob = decoded.get_blob(&name).unwrap();
+        assert_eq!(
+            blob.get_meta_data(),
+            bucket.get_blob(&name).unwrap().get_meta_data()
+        );
+        match blob {
+            DataBucketBlob::Float64(blob) => assert_eq!(blob.null_count(), 1),
+            _ => panic!("Decoded blob of another type"),
+        }
+        assert_eq!(decoded.get_path("station/counts").unwrap().unit_count(), 2);
+        let blob = bucket.get_blob(&name).unwrap();
+        let decoded = DataBucketBlob::from_bytes(&blob.to_bytes().unwrap()).unwrap();
+        assert_eq!(decoded.get_meta_data(), blob.get_meta_data());
+    }
+
+    #[test]
+    fn test_corrupted_encodings() {
+        let bytes = make_bucket().to_bytes().unwrap();
+        assert!(DataBucket::from_bytes(&bytes[..bytes.len() - 1]).is_err());
+        let mut extended = bytes.clone();
+        extended.push(0);
+        assert_eq!(
+            DataBucket::from_bytes(&extended).err(),
+            Some("Trailing bytes after encoding")
+        );
+        // flipping a data byte of the checksummed blob fails its verification
+        let blob = make_bucket().pop_blob("temperature".to_string()).unwrap();
+        let mut bytes = blob.to_bytes().unwrap();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(DataBucketBlob::from_bytes(&bytes).is_err());
+    }
+
+    #[test]
+    fn test_compressed_round_trip() {
+        let mut bucket = make_bucket();
+        bucket.add_blob(DataBucketBlob::U64(DataBlob::new(
+            vec![7; 4096],
+            MetaData {
+                name: "repeated".to_string(),
+                ..Default::default()
+            },
+        )));
+        let plain = bucket.to_bytes().unwrap();
+        let codecs = [
+            Codec::Lz4,
+            #[cfg(feature = "zstd")]
+            Codec::Zstd(3),
+        ];
+        for codec in codecs {
+            let bytes = bucket.to_compressed_bytes(codec).unwrap();
+            assert!(bytes.len() < plain.len() / 4, "Data not compressed");
+            let decoded = DataBucket::from_bytes(&bytes).unwrap();
+            assert!(bucket.diff(&decoded).is_empty());
+            assert_eq!(decoded.to_bytes().unwrap(), plain);
+            let blob = bucket.get_blob(&"temperature".to_string()).unwrap();
+            let decoded = DataBucketBlob::from_bytes(&blob.to_compressed_bytes(codec).unwrap());
+            assert_eq!(decoded.unwrap().get_meta_data(), blob.get_meta_data());
+        }
+        let mut bytes = bucket.to_compressed_bytes(Codec::Lz4).unwrap();
+        let last = bytes.len() - 8;
+        bytes[last] ^= 0xff;
+        assert!(DataBucket::from_bytes(&bytes).is_err());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serde() {
+        let bucket = make_bucket();
+        let json = serde_json::to_string(&bucket).unwrap();
+        let decoded: DataBucket = serde_json::from_str(&json).unwrap();
+        assert!(bucket.diff(&decoded).is_empty());
+        assert!(serde_json::from_str::<DataBucket>("[0, 1]").is_err());
+        let compressed = serde_json::to_string(&Compressed::new(&bucket, Codec::Lz4)).unwrap();
+        assert_ne!(compressed, json);
+        let decoded: DataBucket = serde_json::from_str(&compressed).unwrap();
+        assert!(bucket.diff(&decoded).is_empty());
+    }
+}
@@ -0,0 +1,256 @@
+//! event
+//!
+//! Event time: items stamped with the instant they happened and watermarks tracking the
+//! progress of that time through a pipeline
+//!
+//! timestamps are integer ticks as held by `Timestamp` blobs (see `data_bucket::time`), a
+//! watermark `w` asserts that no event with a timestamp below `w` will follow it on the
+//! same stream, watermarks travel in band with the events as `Timed` items so they cross
+//! channels and executor stages like any other item
+
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Event
+/// A value stamped with its event time
+#[derive(Clone, Debug, PartialEq)]
+pub struct Event<T> {
+    pub timestamp: i64,
+    pub value: T,
+}
+
+impl<T> Event<T> {
+    /// constructor
+    pub fn new(timestamp: i64, value: T) -> Self {
+        Self { timestamp, value }
+    }
+    /// transform the value keeping the timestamp
+    pub fn map<U, F: FnOnce(T) -> U>(self, function: F) -> Event<U> {
+        Event {
+            timestamp: self.timestamp,
+            value: function(self.value),
+        }
+    }
+}
+
+/// Timed
+/// The items of an event time stream: events interleaved with watermarks
+#[derive(Clone, Debug, PartialEq)]
+pub enum Timed<T> {
+    Event(Event<T>),
+    Watermark(i64),
+}
+
+impl<T> Timed<T> {
+    /// transform the value of an event, watermarks pass through untouched
+    pub fn map<U, F: FnOnce(T) -> U>(self, function: F) -> Timed<U> {
+        match self {
+            Timed::Event(event) => Timed::Event(event.map(function)),
+            Timed::Watermark(watermark) => Timed::Watermark(watermark),
+        }
+    }
+    /// the event if the item is one
+    pub fn event(&self) -> Option<&Event<T>> {
+        match self {
+            Timed::Event(event) => Some(event),
+            Timed::Watermark(_) => None,
+        }
+    }
+}
+
+/// WatermarkPipe
+/// A pipe turning a stream of events into a timed stream, emitting a watermark whenever
+/// the largest timestamp seen minus the tolerated out of orderness advances
+///
+/// a final watermark of `i64::MAX` is emitted when the input ends so every pending window
+/// downstream closes
+pub struct WatermarkPipe<T> {
+    max_out_of_orderness: i64,
+    input: Option<Rc<dyn Source<Event<T>>>>,
+}
+
+impl<T> WatermarkPipe<T> {
+    /// constructor for events arriving at most `max_out_of_orderness` ticks late
+    pub fn new(max_out_of_orderness: i64) -> Result<Self, &'static str> {
+        if max_out_of_orderness < 0 {
+            return Err("Out of orderness must be positive");
+        }
+        Ok(Self {
+            max_out_of_orderness,
+            input: None,
+        })
+    }
+}
+
+impl<T: 'static> Source<Timed<T>> for WatermarkPipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = Timed<T>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let delay = self.max_out_of_orderness;
+        let mut watermark = i64::MIN;
+        let timed = input.flat_map(move |event| {
+            let candidate = event.timestamp.saturating_sub(delay);
+            let mut items = vec![Timed::Event(event)];
+            if candidate > watermark {
+                watermark = candidate;
+                items.push(Timed::Watermark(candidate));
+            }
+            stream::iter(items)
+        });
+        Box::new(timed.chain(stream::once(async { Timed::Watermark(i64::MAX) })))
+    }
+}
+
+impl<T: 'static> Pipe<Event<T>, Timed<T>> for WatermarkPipe<T> {
+    input_connection!(Event<T>);
+}
+
+enum Tagged<T> {
+    Item(usize, Timed<T>),
+    End(usize),
+}
+
+/// WatermarkMergePipe
+/// Interleaves several timed streams as their items arrive, forwarding a watermark only
+/// once every input has passed it (the minimum of the input watermarks)
+pub struct WatermarkMergePipe<T> {
+    inputs: Vec<Rc<dyn Source<Timed<T>>>>,
+}
+
+impl<T> WatermarkMergePipe<T> {
+    /// empty constructor
+    pub fn new() -> Self {
+        Self { inputs: Vec::new() }
+    }
+    /// add an input to the merge
+    pub fn add_input(&mut self, input: Rc<dyn Source<Timed<T>>>) {
+        self.inputs.push(input);
+    }
+    /// the merged inputs
+    pub fn inputs(&self) -> &[Rc<dyn Source<Timed<T>>>] {
+        &self.inputs
+    }
+}
+
+impl<T> Default for WatermarkMergePipe<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: 'static> Source<Timed<T>> for WatermarkMergePipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = Timed<T>>> {
+        let tagged = self.inputs.iter().enumerate().map(|(idx, input)| {
+            Pin::from(input.stream())
+                .map(move |item| Tagged::Item(idx, item))
+                .chain(stream::once(async move { Tagged::End(idx) }))
+                .boxed_local()
+        });
+        let marks = Rc::new(RefCell::new(vec![i64::MIN; self.inputs.len()]));
+        let mut emitted = i64::MIN;
+        Box::new(stream::select_all(tagged).filter_map(move |tagged| {
+            let mut marks = marks.borrow_mut();
+            let output = match tagged {
+                Tagged::Item(_, Timed::Event(event)) => Some(Timed::Event(event)),
+                Tagged::Item(idx, Timed::Watermark(watermark)) => {
+                    marks[idx] = marks[idx].max(watermark);
+                    None
+                }
+                Tagged::End(idx) => {
+                    marks[idx] = i64::MAX;
+                    None
+                }
+            };
+            let output = output.or_else(|| {
+                let low = marks.iter().copied().min().unwrap_or(i64::MAX);
+                if low > emitted {
+                    emitted = low;
+                    Some(Timed::Watermark(low))
+                } else {
+                    None
+                }
+            });
+            async move { output }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{collect, MockSource};
+
+    #[test]
+    fn test_watermark_generation() {
+        let mut pipe = WatermarkPipe::new(2).unwrap();
+        pipe.pipe(Rc::new(MockSource::new().items([
+            Event::new(10, 'a'),
+            Event::new(9, 'b'),
+            Event::new(13, 'c'),
+        ])))
+        .unwrap();
+        assert_eq!(
+            collect(&pipe),
+            vec![
+                Timed::Event(Event::new(10, 'a')),
+                Timed::Watermark(8),
+                Timed::Event(Event::new(9, 'b')),
+                Timed::Event(Event::new(13, 'c')),
+                Timed::Watermark(11),
+                Timed::Watermark(i64::MAX),
+            ]
+        );
+        assert!(WatermarkPipe::<char>::new(-1).is_err());
+    }
+
+    #[test]
+    fn test_watermark_merge_takes_minimum() {
+        let mut merge = WatermarkMergePipe::new();
+        merge.add_input(Rc::new(MockSource::new().items([
+            Timed::Event(Event::new(5, 1)),
+            Timed::Watermark(5),
+            Timed::Watermark(20),
+        ])));
+        merge.add_input(Rc::new(
+            MockSource::new().items([Timed::Event(Event::new(7, 2)), Timed::Watermark(7)]),
+        ));
+        let items = collect(&merge);
+        let watermarks: Vec<i64> = items
+            .iter()
+            .filter_map(|item| match item {
+                Timed::Watermark(watermark) => Some(*watermark),
+                Timed::Event(_) => None,
+            })
+            .collect();
+        assert_eq!(
+            items.iter().filter(|item| item.event().is_some()).count(),
+            2
+        );
+        assert!(
+            watermarks.windows(2).all(|pair| pair[0] < pair[1]),
+            "Watermark regressed"
+        );
+        assert!(watermarks
+            .iter()
+            .all(|mark| [5, 7, 20, i64::MAX].contains(mark)));
+        assert_eq!(
+            watermarks.last(),
+            Some(&i64::MAX),
+            "Finished inputs hold back time"
+        );
+        assert_eq!(
+            Timed::Event(Event::new(1, 2))
+                .map(|v| v * 2)
+                .event()
+                .unwrap()
+                .value,
+            4
+        );
+    }
+}
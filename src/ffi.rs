@@ -0,0 +1,405 @@
+//! ffi
+//!
+//! A C interface to buckets and prebuilt pipelines (requires the `capi` feature)
+//!
+//! every object is handed out as an opaque pointer that must be released with the
+//! matching `_free` function, functions returning an `int` report `BV_OK` on success
+
+use crate::data_bucket::{BlobType, DataBlob, DataBucket, DataBucketBlob, MetaData};
+use crate::ops::ElementwisePipe;
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::task::noop_waker_ref;
+use futures::Stream;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::ffi::{c_char, c_int, CStr};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll};
+
+/// the call succeeded
+pub const BV_OK: c_int = 0;
+/// an argument was null or invalid
+pub const BV_INVALID: c_int = -1;
+/// the named blob does not exist or holds another type
+pub const BV_NOT_FOUND: c_int = -2;
+/// the name is already taken
+pub const BV_EXISTS: c_int = -3;
+/// the pipeline has no output available until more input is pushed
+pub const BV_PENDING: c_int = 1;
+/// the pipeline is closed and fully drained
+pub const BV_FINISHED: c_int = 2;
+
+unsafe fn read_name<'a>(name: *const c_char) -> Option<&'a str> {
+    if name.is_null() {
+        return None;
+    }
+    CStr::from_ptr(name).to_str().ok()
+}
+
+/// create an empty bucket
+#[no_mangle]
+pub extern "C" fn bv_bucket_new() -> *mut DataBucket {
+    Box::into_raw(Box::new(DataBucket::new()))
+}
+
+/// release a bucket and every blob it holds
+///
+/// # Safety
+/// `bucket` must be null or a pointer returned by `bv_bucket_new` not yet released
+#[no_mangle]
+pub unsafe extern "C" fn bv_bucket_free(bucket: *mut DataBucket) {
+    if !bucket.is_null() {
+        drop(Box::from_raw(bucket));
+    }
+}
+
+/// number of blobs in a bucket
+///
+/// # Safety
+/// `bucket` must be a valid bucket pointer
+#[no_mangle]
+pub unsafe extern "C" fn bv_bucket_len(bucket: *const DataBucket) -> usize {
+    bucket.as_ref().map_or(0, |bucket| bucket.len())
+}
+
+/// create a float64 blob by copying `len` values
+///
+/// # Safety
+/// `name` must be a nul terminated string and `data` must point to `len` values
+#[no_mangle]
+pub unsafe extern "C" fn bv_blob_new_f64(
+    name: *const c_char,
+    data: *const f64,
+    len: usize,
+) -> *mut DataBucketBlob {
+    new_blob(name, data, len)
+}
+
+/// create an int64 blob by copying `len` values
+///
+/// # Safety
+/// `name` must be a nul terminated string and `data` must point to `len` values
+#[no_mangle]
+pub unsafe extern "C" fn bv_blob_new_i64(
+    name: *const c_char,
+    data: *const i64,
+    len: usize,
+) -> *mut DataBucketBlob {
+    new_blob(name, data, len)
+}
+
+unsafe fn new_blob<T: BlobType + Copy>(
+    name: *const c_char,
+    data: *const T,
+    len: usize,
+) -> *mut DataBucketBlob {
+    let name = match read_name(name) {
+        Some(name) => name,
+        None => return std::ptr::null_mut(),
+    };
+    if data.is_null() && len > 0 {
+        return std::ptr::null_mut();
+    }
+    let values = if len == 0 {
+        Vec::new()
+    } else {
+        std::slice::from_raw_parts(data, len).to_vec()
+    };
+    let meta = MetaData {
+        name: name.to_string(),
+        dimensions: vec![len],
+        unitary_dimensions: vec![1],
+        ..Default::default()
+    };
+    Box::into_raw(Box::new(T::wrap(DataBlob::new(values, meta))))
+}
+
+/// release a blob that was not added to a bucket
+///
+/// # Safety
+/// `blob` must be null or a pointer returned by a `bv_blob_new_*` function not yet
+/// released nor added to a bucket
+#[no_mangle]
+pub unsafe extern "C" fn bv_blob_free(blob: *mut DataBucketBlob) {
+    if !blob.is_null() {
+        drop(Box::from_raw(blob));
+    }
+}
+
+/// move a blob into a bucket, the bucket takes ownership of the blob even on failure
+///
+/// # Safety
+/// `bucket` must be a valid bucket pointer and `blob` a blob pointer not yet released
+#[no_mangle]
+pub unsafe extern "C" fn bv_bucket_add_blob(
+    bucket: *mut DataBucket,
+    blob: *mut DataBucketBlob,
+) -> c_int {
+    if blob.is_null() {
+        return BV_INVALID;
+    }
+    let blob = Box::from_raw(blob);
+    match bucket.as_mut() {
+        None => BV_INVALID,
+        Some(bucket) => match bucket.add_blob(*blob) {
+            None => BV_OK,
+            Some(_) => BV_EXISTS,
+        },
+    }
+}
+
+/// borrow the data of a float64 blob, valid until the bucket is modified or released
+///
+/// # Safety
+/// `bucket` must be a valid bucket pointer, `name` a nul terminated string, and `data`
+/// and `len` valid pointers to write to
+#[no_mangle]
+pub unsafe extern "C" fn bv_bucket_get_f64(
+    bucket: *const DataBucket,
+    name: *const c_char,
+    data: *mut *const f64,
+    len: *mut usize,
+) -> c_int {
+    get_blob(bucket, name, data, len)
+}
+
+/// borrow the data of an int64 blob, valid until the bucket is modified or released
+///
+/// # Safety
+/// `bucket` must be a valid bucket pointer, `name` a nul terminated string, and `data`
+/// and `len` valid pointers to write to
+#[no_mangle]
+pub unsafe extern "C" fn bv_bucket_get_i64(
+    bucket: *const DataBucket,
+    name: *const c_char,
+    data: *mut *const i64,
+    len: *mut usize,
+) -> c_int {
+    get_blob(bucket, name, data, len)
+}
+
+unsafe fn get_blob<T: BlobType>(
+    bucket: *const DataBucket,
+    name: *const c_char,
+    data: *mut *const T,
+    len: *mut usize,
+) -> c_int {
+    let (bucket, name) = match (bucket.as_ref(), read_name(name)) {
+        (Some(bucket), Some(name)) if !data.is_null() && !len.is_null() => (bucket, name),
+        _ => return BV_INVALID,
+    };
+    match bucket.get_blob(&name.to_string()).and_then(T::unwrap_ref) {
+        None => BV_NOT_FOUND,
+        Some(blob) => {
+            *data = blob.get_data().as_ptr();
+            *len = blob.get_data().len();
+            BV_OK
+        }
+    }
+}
+
+/// remove and release a blob from a bucket
+///
+/// # Safety
+/// `bucket` must be a valid bucket pointer and `name` a nul terminated string
+#[no_mangle]
+pub unsafe extern "C" fn bv_bucket_remove(bucket: *mut DataBucket, name: *const c_char) -> c_int {
+    match (bucket.as_mut(), read_name(name)) {
+        (Some(bucket), Some(name)) => match bucket.pop_blob(name.to_string()) {
+            Some(_) => BV_OK,
+            None => BV_NOT_FOUND,
+        },
+        _ => BV_INVALID,
+    }
+}
+
+#[derive(Default)]
+struct Queue {
+    items: VecDeque<f64>,
+    closed: bool,
+}
+
+/// a source yielding the values pushed from C, pending while the queue is empty
+struct QueueSource {
+    queue: Rc<RefCell<Queue>>,
+}
+
+impl Source<f64> for QueueSource {
+    fn stream(&self) -> Box<dyn Stream<Item = f64>> {
+        let queue = self.queue.clone();
+        Box::new(stream::poll_fn(move |_| {
+            let mut queue = queue.borrow_mut();
+            match queue.items.pop_front() {
+                Some(item) => Poll::Ready(Some(item)),
+                None if queue.closed => Poll::Ready(None),
+                None => Poll::Pending,
+            }
+        }))
+    }
+}
+
+/// BvPipeline
+/// A prebuilt pipeline fed and drained from C
+pub struct BvPipeline {
+    queue: Rc<RefCell<Queue>>,
+    output: Pin<Box<dyn Stream<Item = f64>>>,
+}
+
+fn new_pipeline(mut pipe: ElementwisePipe<f64>) -> *mut BvPipeline {
+    let queue = Rc::new(RefCell::new(Queue::default()));
+    let source = Rc::new(QueueSource {
+        queue: queue.clone(),
+    });
+    pipe.pipe(source)
+        .expect("Failure to connect pipeline input");
+    Box::into_raw(Box::new(BvPipeline {
+        queue,
+        output: Pin::from(pipe.stream()),
+    }))
+}
+
+/// create a pipeline multiplying every value by a factor
+#[no_mangle]
+pub extern "C" fn bv_pipeline_scale(factor: f64) -> *mut BvPipeline {
+    new_pipeline(ElementwisePipe::scale(factor))
+}
+
+/// create a pipeline adding an offset to every value
+#[no_mangle]
+pub extern "C" fn bv_pipeline_offset(offset: f64) -> *mut BvPipeline {
+    new_pipeline(ElementwisePipe::with_scalar(
+        crate::ops::ElementwiseOp::Add,
+        offset,
+    ))
+}
+
+/// push `len` values into a pipeline
+///
+/// # Safety
+/// `pipeline` must be a valid pipeline pointer and `data` must point to `len` values
+#[no_mangle]
+pub unsafe extern "C" fn bv_pipeline_push(
+    pipeline: *mut BvPipeline,
+    data: *const f64,
+    len: usize,
+) -> c_int {
+    let pipeline = match pipeline.as_mut() {
+        Some(pipeline) if !data.is_null() || len == 0 => pipeline,
+        _ => return BV_INVALID,
+    };
+    let mut queue = pipeline.queue.borrow_mut();
+    if queue.closed {
+        return BV_INVALID;
+    }
+    if len > 0 {
+        queue
+            .items
+            .extend(std::slice::from_raw_parts(data, len).iter());
+    }
+    BV_OK
+}
+
+/// signal that no more values will be pushed
+///
+/// # Safety
+/// `pipeline` must be a valid pipeline pointer
+#[no_mangle]
+pub unsafe extern "C" fn bv_pipeline_close(pipeline: *mut BvPipeline) -> c_int {
+    match pipeline.as_mut() {
+        Some(pipeline) => {
+            pipeline.queue.borrow_mut().closed = true;
+            BV_OK
+        }
+        None => BV_INVALID,
+    }
+}
+
+/// pull the next output value, returns `BV_OK` when a value was written, `BV_PENDING`
+/// when more input is needed and `BV_FINISHED` once the closed pipeline is drained
+///
+/// # Safety
+/// `pipeline` must be a valid pipeline pointer and `value` a valid pointer to write to
+#[no_mangle]
+pub unsafe extern "C" fn bv_pipeline_pull(pipeline: *mut BvPipeline, value: *mut f64) -> c_int {
+    let pipeline = match pipeline.as_mut() {
+        Some(pipeline) if !value.is_null() => pipeline,
+        _ => return BV_INVALID,
+    };
+    let mut context = Context::from_waker(noop_waker_ref());
+    match pipeline.output.as_mut().poll_next(&mut context) {
+        Poll::Ready(Some(item)) => {
+            *value = item;
+            BV_OK
+        }
+        Poll::Ready(None) => BV_FINISHED,
+        Poll::Pending => BV_PENDING,
+    }
+}
+
+/// release a pipeline
+///
+/// # Safety
+/// `pipeline` must be null or a pipeline pointer not yet released
+#[no_mangle]
+pub unsafe extern "C" fn bv_pipeline_free(pipeline: *mut BvPipeline) {
+    if !pipeline.is_null() {
+        drop(Box::from_raw(pipeline));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::ffi::CString;
+
+    #[test]
+    fn test_bucket_handles() {
+        unsafe {
+            let bucket = bv_bucket_new();
+            let name = CString::new("x").unwrap();
+            let values = [1.0, 2.0, 3.0];
+            let blob = bv_blob_new_f64(name.as_ptr(), values.as_ptr(), values.len());
+            assert_eq!(bv_bucket_add_blob(bucket, blob), BV_OK);
+            let duplicate = bv_blob_new_f64(name.as_ptr(), values.as_ptr(), 1);
+            assert_eq!(bv_bucket_add_blob(bucket, duplicate), BV_EXISTS);
+            assert_eq!(bv_bucket_len(bucket), 1);
+            let mut data = std::ptr::null();
+            let mut len = 0;
+            assert_eq!(
+                bv_bucket_get_f64(bucket, name.as_ptr(), &mut data, &mut len),
+                BV_OK
+            );
+            assert_eq!(std::slice::from_raw_parts(data, len), values);
+            let mut ints = std::ptr::null();
+            assert_eq!(
+                bv_bucket_get_i64(bucket, name.as_ptr(), &mut ints, &mut len),
+                BV_NOT_FOUND
+            );
+            assert_eq!(bv_bucket_remove(bucket, name.as_ptr()), BV_OK);
+            assert_eq!(bv_bucket_len(bucket), 0);
+            bv_bucket_free(bucket);
+        }
+    }
+
+    #[test]
+    fn test_pipeline_push_pull() {
+        unsafe {
+            let pipeline = bv_pipeline_scale(2.0);
+            let mut value = 0.0;
+            assert_eq!(bv_pipeline_pull(pipeline, &mut value), BV_PENDING);
+            let values = [1.0, 2.0];
+            assert_eq!(bv_pipeline_push(pipeline, values.as_ptr(), 2), BV_OK);
+            let mut out = Vec::new();
+            while bv_pipeline_pull(pipeline, &mut value) == BV_OK {
+                out.push(value);
+            }
+            assert_eq!(out, [2.0, 4.0]);
+            bv_pipeline_close(pipeline);
+            assert_eq!(bv_pipeline_pull(pipeline, &mut value), BV_FINISHED);
+            assert_eq!(bv_pipeline_push(pipeline, values.as_ptr(), 2), BV_INVALID);
+            bv_pipeline_free(pipeline);
+        }
+    }
+}
@@ -0,0 +1,341 @@
+//! http
+//!
+//! A minimal blocking HTTP/1.1 client for plain `http://` endpoints, and the background
+//! server the exporters and sinks answering requests are built on
+//!
+//! every request opens its own connection and asks the server to close it, responses are
+//! read whole, with or without chunked transfer encoding
+
+use std::io::{Read, Write};
+use std::net::{Ipv4Addr, SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+/// Url
+/// The parts of a plain http URL
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Url {
+    pub host: String,
+    pub port: u16,
+    /// path and query, starting with a slash
+    pub path: String,
+}
+
+impl Url {
+    /// parse an `http://host[:port][/path]` URL
+    pub fn parse(url: &str) -> Result<Self, &'static str> {
+        let rest = url
+            .strip_prefix("http://")
+            .ok_or("Only plain http URLs are supported")?;
+        let (authority, path) = match rest.find('/') {
+            Some(slash) => (&rest[..slash], &rest[slash..]),
+            None => (rest, "/"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| "Invalid URL port")?),
+            None => (authority, 80),
+        };
+        if host.is_empty() {
+            return Err("Invalid URL host");
+        }
+        Ok(Self {
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+}
+
+/// Response
+/// The status, headers and body of a response
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Response {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: Vec<u8>,
+}
+
+impl Response {
+    /// the value of a header, whatever its case
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(header, _)| header.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+    /// whether the status is a 2xx success
+    pub fn is_success(&self) -> bool {
+        (200..300).contains(&self.status)
+    }
+}
+
+/// send a request and wait for its whole response
+pub(crate) fn send(
+    method: &str,
+    url: &Url,
+    headers: &[(&str, &str)],
+    body: &[u8],
+    timeout: Duration,
+) -> Result<Response, &'static str> {
+    let address = (url.host.as_str(), url.port)
+        .to_socket_addrs()
+        .map_err(|_| "Failure to resolve host")?
+        .next()
+        .ok_or("Failure to resolve host")?;
+    let mut connection =
+        TcpStream::connect_timeout(&address, timeout).map_err(|_| "Failure to connect")?;
+    connection
+        .set_read_timeout(Some(timeout))
+        .and_then(|_| connection.set_write_timeout(Some(timeout)))
+        .map_err(|_| "Failure to connect")?;
+    let mut request = format!(
+        "{} {} HTTP/1.1\r\nHost: {}:{}\r\nConnection: close\r\nContent-Length: {}\r\n",
+        method,
+        url.path,
+        url.host,
+        url.port,
+        body.len()
+    );
+    for (name, value) in headers {
+        request.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    request.push_str("\r\n");
+    connection
+        .write_all(request.as_bytes())
+        .and_then(|_| connection.write_all(body))
+        .map_err(|_| "Failure to send request")?;
+    let mut raw = Vec::new();
+    connection
+        .read_to_end(&mut raw)
+        .map_err(|_| "Failure to read response")?;
+    parse(&raw)
+}
+
+/// Server
+/// A background thread handing every connection accepted on a listener to a handler,
+/// stopped when dropped
+pub(crate) struct Server {
+    address: SocketAddr,
+    running: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Server {
+    /// start accepting connections on an address (port 0 picks a free port), returns the
+    /// given error if the address cannot be bound
+    pub fn bind<A, F>(address: A, error: &'static str, mut handle: F) -> Result<Self, &'static str>
+    where
+        A: ToSocketAddrs,
+        F: FnMut(TcpStream) + Send + 'static,
+    {
+        let listener = TcpListener::bind(address).map_err(|_| error)?;
+        let address = listener.local_addr().map_err(|_| error)?;
+        let running = Arc::new(AtomicBool::new(true));
+        let serving = running.clone();
+        let thread = std::thread::spawn(move || {
+            for connection in listener.incoming() {
+                if !serving.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(connection) = connection {
+                    handle(connection);
+                }
+            }
+        });
+        Ok(Self {
+            address,
+            running,
+            thread: Some(thread),
+        })
+    }
+    /// the address the server listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+/// connecting to the listener wakes the thread blocked accepting so it sees it must stop
+impl Drop for Server {
+    fn drop(&mut self) {
+        self.running.store(false, Ordering::SeqCst);
+        let mut wake = self.address;
+        if wake.ip().is_unspecified() {
+            wake.set_ip(Ipv4Addr::LOCALHOST.into());
+        }
+        let _ = TcpStream::connect_timeout(&wake, Duration::from_secs(1));
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// read the head of a request sent to a server and return its method and path
+pub(crate) fn read_request(connection: &mut TcpStream) -> std::io::Result<(String, String)> {
+    connection.set_read_timeout(Some(Duration::from_secs(5)))?;
+    let mut request = Vec::new();
+    let mut chunk = [0_u8; 1024];
+    while !request.windows(4).any(|end| end == b"\r\n\r\n") && request.len() < 8192 {
+        let read = connection.read(&mut chunk)?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&chunk[..read]);
+    }
+    let request = String::from_utf8_lossy(&request);
+    let mut line = request.lines().next().unwrap_or("").split_whitespace();
+    let method = line.next().unwrap_or("").to_string();
+    let path = line.next().unwrap_or("").to_string();
+    Ok((method, path))
+}
+
+/// write a whole response to a request and close the connection
+pub(crate) fn respond(
+    connection: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> std::io::Result<()> {
+    write!(
+        connection,
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    )?;
+    connection.flush()
+}
+
+fn parse(raw: &[u8]) -> Result<Response, &'static str> {
+    let end = raw
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or("Malformed HTTP response")?;
+    let head = std::str::from_utf8(&raw[..end]).map_err(|_| "Malformed HTTP response")?;
+    let mut lines = head.split("\r\n");
+    let status = lines
+        .next()
+        .and_then(|line| line.split_whitespace().nth(1))
+        .and_then(|code| code.parse().ok())
+        .ok_or("Malformed HTTP response")?;
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|line| line.split_once(':'))
+        .map(|(name, value)| (name.trim().to_string(), value.trim().to_string()))
+        .collect();
+    let mut response = Response {
+        status,
+        headers,
+        body: raw[end + 4..].to_vec(),
+    };
+    if response
+        .header("Transfer-Encoding")
+        .is_some_and(|encoding| encoding.eq_ignore_ascii_case("chunked"))
+    {
+        response.body = dechunk(&response.body)?;
+    } else if let Some(length) = response.header("Content-Length") {
+        let length: usize = length.parse().map_err(|_| "Malformed HTTP response")?;
+        if response.body.len() < length {
+            return Err("Truncated HTTP response");
+        }
+        response.body.truncate(length);
+    }
+    Ok(response)
+}
+
+fn dechunk(mut chunked: &[u8]) -> Result<Vec<u8>, &'static str> {
+    let mut body = Vec::new();
+    loop {
+        let line = chunked
+            .windows(2)
+            .position(|window| window == b"\r\n")
+            .ok_or("Malformed chunked body")?;
+        let size = std::str::from_utf8(&chunked[..line])
+            .ok()
+            .and_then(|size| usize::from_str_radix(size.split(';').next()?.trim(), 16).ok())
+            .ok_or("Malformed chunked body")?;
+        chunked = &chunked[line + 2..];
+        if size == 0 {
+            return Ok(body);
+        }
+        if chunked.len() < size + 2 {
+            return Err("Truncated HTTP response");
+        }
+        body.extend_from_slice(&chunked[..size]);
+        chunked = &chunked[size + 2..];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_url() {
+        let url = Url::parse("http://localhost:4318/v1/metrics?a=b").unwrap();
+        assert_eq!((url.host.as_str(), url.port), ("localhost", 4318));
+        assert_eq!(url.path, "/v1/metrics?a=b");
+        assert_eq!(Url::parse("http://example.com").unwrap().port, 80);
+        assert!(Url::parse("https://example.com").is_err());
+        assert!(Url::parse("http://:80/").is_err());
+    }
+
+    #[test]
+    fn test_chunked_response() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = Url::parse(&format!("http://{}/data", listener.local_addr().unwrap())).unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut connection, _) = listener.accept().unwrap();
+            let mut request = [0_u8; 1024];
+            let read = connection.read(&mut request).unwrap();
+            connection
+                .write_all(
+                    b"HTTP/1.1 200 OK\r\netag: \"v1\"\r\nTransfer-Encoding: chunked\r\n\r\n\
+                      4\r\nbitv\r\n5\r\nortex\r\n0\r\n\r\n",
+                )
+                .unwrap();
+            String::from_utf8_lossy(&request[..read]).to_string()
+        });
+        let response = send(
+            "GET",
+            &url,
+            &[("Accept", "*/*")],
+            b"",
+            Duration::from_secs(5),
+        );
+        let request = server.join().unwrap();
+        assert!(request.starts_with("GET /data HTTP/1.1\r\n"));
+        assert!(request.contains("Accept: */*\r\n"));
+        let response = response.unwrap();
+        assert!(response.is_success());
+        assert_eq!(response.header("ETag"), Some("\"v1\""));
+        assert_eq!(response.body, b"bitvortex");
+        assert_eq!(
+            parse(b"HTTP/1.1 204 No Content\r\n\r\n").unwrap().status,
+            204
+        );
+        assert!(parse(b"garbage").is_err());
+    }
+
+    #[test]
+    fn test_server() {
+        let server = Server::bind("0.0.0.0:0", "Failure to bind", |mut connection| {
+            let (method, path) = read_request(&mut connection).unwrap();
+            let _ = respond(&mut connection, "200 OK", "text/plain", &(method + &path));
+        })
+        .unwrap();
+        let url = Url::parse(&format!(
+            "http://127.0.0.1:{}/ping",
+            server.local_addr().port()
+        ));
+        let response = send("GET", &url.unwrap(), &[], b"", Duration::from_secs(5)).unwrap();
+        assert_eq!(response.body, b"GET/ping");
+        let address = server.local_addr();
+        assert!(Server::bind(address, "Failure to bind", |_| {}).is_err());
+        // dropping stops the thread even when listening on every interface
+        drop(server);
+        assert!(Server::bind(address, "Failure to bind", |_| {}).is_ok());
+    }
+}
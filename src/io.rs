@@ -0,0 +1,30 @@
+//! io
+//!
+//! Elements bridging pipelines with other services over the network
+//! or through files
+
+/// audio
+/// Sub module for reading and writing WAV audio
+pub mod audio;
+
+/// grpc
+/// Sub module for the gRPC message framing of streams, without a transport
+pub mod grpc;
+
+/// poll
+/// Sub module for polling HTTP endpoints
+pub mod poll;
+
+/// remote
+/// Sub module for sending streams between pipelines over TCP
+pub mod remote;
+
+/// shard
+/// Sub module for distributing streams across worker processes
+pub mod shard;
+
+pub use audio::{WavEncoding, WavSink, WavSource};
+pub use grpc::{FrameDecoder, GrpcDecodePipe, GrpcEncodePipe, GrpcMessage};
+pub use poll::HttpPollSource;
+pub use remote::{RemoteSink, RemoteSource, WireItem};
+pub use shard::{ShardCollector, ShardRouter};
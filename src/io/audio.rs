@@ -0,0 +1,515 @@
+//! audio
+//!
+//! Reading and writing the samples of WAV files
+//!
+//! samples stream as blobs of interleaved frames scaled to [-1, 1] with the dimensions
+//! `[frames, channels]` and the axes `time` and `channel` (a tensor), one unit per frame and
+//! the sample rate in their meta data. PCM of 8, 16, 24 or 32 bits and floats of 32 or 64
+//! bits are read, extensible headers included, PCM of 16 or 24 bits or 32 bit floats are
+//! written
+
+use crate::data_bucket::{DataBlob, MetaData};
+use crate::{Sink, Source};
+use bytes::Bytes;
+use futures::future::LocalBoxFuture;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::rc::Rc;
+
+const FORMAT_PCM: u16 = 1;
+const FORMAT_FLOAT: u16 = 3;
+const FORMAT_EXTENSIBLE: u16 = 0xfffe;
+
+/// WavEncoding
+/// The sample encodings WAV files are written with
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WavEncoding {
+    /// 16 bit integers
+    #[default]
+    Pcm16,
+    /// 24 bit integers
+    Pcm24,
+    /// 32 bit floats
+    Float32,
+}
+
+impl WavEncoding {
+    fn format(self) -> (u16, u16) {
+        match self {
+            WavEncoding::Pcm16 => (FORMAT_PCM, 16),
+            WavEncoding::Pcm24 => (FORMAT_PCM, 24),
+            WavEncoding::Float32 => (FORMAT_FLOAT, 32),
+        }
+    }
+}
+
+/// the layout of the samples of a WAV file
+#[derive(Clone, Copy)]
+struct WavFormat {
+    channels: usize,
+    sample_rate: u32,
+    float: bool,
+    bits: u16,
+}
+
+impl WavFormat {
+    fn frame_bytes(&self) -> usize {
+        self.channels * self.bits as usize / 8
+    }
+    fn decode(&self, bytes: &[u8]) -> Vec<f32> {
+        let size = self.bits as usize / 8;
+        bytes
+            .chunks_exact(size)
+            .map(|sample| match (self.float, self.bits) {
+                (true, 32) => f32::from_le_bytes(sample.try_into().unwrap()),
+                (true, _) => f64::from_le_bytes(sample.try_into().unwrap()) as f32,
+                (false, 8) => (sample[0] as f32 - 128.0) / 128.0,
+                (false, 16) => i16::from_le_bytes([sample[0], sample[1]]) as f32 / 32768.0,
+                (false, 24) => {
+                    let value = i32::from_le_bytes([0, sample[0], sample[1], sample[2]]) >> 8;
+                    value as f32 / 8_388_608.0
+                }
+                (false, _) => {
+                    i32::from_le_bytes(sample.try_into().unwrap()) as f32 / 2_147_483_648.0
+                }
+            })
+            .collect()
+    }
+}
+
+fn read_u32(reader: &mut dyn Read) -> Result<u32, &'static str> {
+    let mut bytes = [0; 4];
+    reader
+        .read_exact(&mut bytes)
+        .map_err(|_| "Truncated WAV file")?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// read the header of a WAV file up to its samples, returning their format and byte length
+fn read_header(reader: &mut dyn Read) -> Result<(WavFormat, u64), &'static str> {
+    let mut magic = [0; 12];
+    reader
+        .read_exact(&mut magic)
+        .map_err(|_| "Not a WAV file")?;
+    if &magic[0..4] != b"RIFF" || &magic[8..12] != b"WAVE" {
+        return Err("Not a WAV file");
+    }
+    let mut format = None;
+    loop {
+        let mut kind = [0; 4];
+        reader
+            .read_exact(&mut kind)
+            .map_err(|_| "WAV file has no samples")?;
+        let len = read_u32(reader)? as u64;
+        match &kind {
+            b"fmt " => {
+                let mut chunk = Vec::new();
+                reader
+                    .take(len)
+                    .read_to_end(&mut chunk)
+                    .map_err(|_| "Truncated WAV file")?;
+                if len < 16 || chunk.len() as u64 != len {
+                    return Err("Invalid WAV format");
+                }
+                let field = |at: usize| u16::from_le_bytes([chunk[at], chunk[at + 1]]);
+                let mut tag = field(0);
+                if tag == FORMAT_EXTENSIBLE && chunk.len() >= 26 {
+                    // the format of extensible headers starts their sub format identifier
+                    tag = field(24);
+                }
+                let (channels, bits) = (field(2) as usize, field(14));
+                let float = match (tag, bits) {
+                    (FORMAT_PCM, 8 | 16 | 24 | 32) => false,
+                    (FORMAT_FLOAT, 32 | 64) => true,
+                    _ => return Err("Unsupported WAV sample encoding"),
+                };
+                if channels == 0 {
+                    return Err("Invalid WAV format");
+                }
+                let sample_rate = u32::from_le_bytes(chunk[4..8].try_into().unwrap());
+                format = Some(WavFormat {
+                    channels,
+                    sample_rate,
+                    float,
+                    bits,
+                });
+                if len % 2 == 1 {
+                    reader
+                        .read_exact(&mut [0])
+                        .map_err(|_| "Truncated WAV file")?;
+                }
+            }
+            b"data" => return Ok((format.ok_or("WAV samples before their format")?, len)),
+            _ => {
+                // chunks are padded to an even length
+                let skipped = std::io::copy(&mut reader.take(len + len % 2), &mut std::io::sink());
+                if skipped.map_err(|_| "Truncated WAV file")? != len + len % 2 {
+                    return Err("Truncated WAV file");
+                }
+            }
+        }
+    }
+}
+
+/// the input of a WAV source
+enum WavInput {
+    File(PathBuf),
+    Bytes(Bytes),
+}
+
+/// WavSource
+/// A source streaming the samples of a WAV file in blobs of a number of frames, the stream
+/// ends at the first read failure
+pub struct WavSource {
+    input: WavInput,
+    frames: usize,
+    name: String,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl WavSource {
+    fn with_input(input: WavInput) -> Self {
+        Self {
+            input,
+            frames: 4096,
+            name: "samples".to_string(),
+            error: Rc::new(Cell::new(None)),
+        }
+    }
+    /// constructor reading a file each time the source is streamed
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        Self::with_input(WavInput::File(path.as_ref().to_path_buf()))
+    }
+    /// constructor reading a WAV file held in memory
+    pub fn from_bytes<B: Into<Bytes>>(bytes: B) -> Self {
+        Self::with_input(WavInput::Bytes(bytes.into()))
+    }
+    /// set the number of frames of each blob, at least 1 (4096 by default, the last blob
+    /// may be shorter)
+    pub fn with_frames(mut self, frames: usize) -> Self {
+        self.frames = frames.max(1);
+        self
+    }
+    /// set the name of the blobs ("samples" by default)
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+    /// the read failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl Source<DataBlob<f32>> for WavSource {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBlob<f32>>> {
+        let error = self.error.clone();
+        error.set(None);
+        let mut reader: Box<dyn Read> = match &self.input {
+            WavInput::Bytes(bytes) => Box::new(std::io::Cursor::new(bytes.clone())),
+            WavInput::File(path) => match File::open(path) {
+                Ok(file) => Box::new(BufReader::new(file)),
+                Err(_) => {
+                    error.set(Some("Failure to open WAV file"));
+                    return Box::new(stream::empty());
+                }
+            },
+        };
+        let (format, len) = match read_header(reader.as_mut()) {
+            Ok(header) => header,
+            Err(failure) => {
+                error.set(Some(failure));
+                return Box::new(stream::empty());
+            }
+        };
+        let meta = MetaData {
+            name: self.name.clone(),
+            unitary_dimensions: vec![format.channels],
+            axes: vec!["time".to_string(), "channel".to_string()],
+            sample_rate: Some(format.sample_rate as f64),
+            ..Default::default()
+        };
+        let chunk = (self.frames * format.frame_bytes()) as u64;
+        // streamed files may not know their length and leave it at its maximum
+        let samples = reader.take(len);
+        Box::new(stream::unfold(Some(samples), move |samples| {
+            let (error, meta) = (error.clone(), meta.clone());
+            async move {
+                let mut samples = samples?;
+                let mut bytes = Vec::new();
+                if samples
+                    .by_ref()
+                    .take(chunk)
+                    .read_to_end(&mut bytes)
+                    .is_err()
+                {
+                    error.set(Some("Failure to read WAV file"));
+                    return None;
+                }
+                let frames = bytes.len() / format.frame_bytes();
+                if frames == 0 {
+                    return None;
+                }
+                bytes.truncate(frames * format.frame_bytes());
+                let mut meta = meta;
+                meta.dimensions = vec![frames, format.channels];
+                Some((DataBlob::new(format.decode(&bytes), meta), Some(samples)))
+            }
+        }))
+    }
+}
+
+/// WavSink
+/// A sink writing the sample blobs it drains to a WAV file
+///
+/// the first blob sets the sample rate and the number of channels (its unit size) of the
+/// file and every other blob must match them, samples are clamped to [-1, 1] when written
+/// as integers and no file is written for an empty stream
+pub struct WavSink {
+    path: PathBuf,
+    encoding: WavEncoding,
+    input: Option<Rc<dyn Source<DataBlob<f32>>>>,
+}
+
+impl WavSink {
+    /// constructor writing (or truncating) a file when drained
+    pub fn create<P: AsRef<Path>>(path: P) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            encoding: WavEncoding::default(),
+            input: None,
+        }
+    }
+    /// set the encoding of the samples (16 bit PCM by default)
+    pub fn with_encoding(mut self, encoding: WavEncoding) -> Self {
+        self.encoding = encoding;
+        self
+    }
+}
+
+/// the format of the file a blob starts
+fn blob_format(blob: &DataBlob<f32>, encoding: WavEncoding) -> Result<WavFormat, &'static str> {
+    let rate = blob
+        .get_meta_data()
+        .sample_rate
+        .ok_or("Blob has no sample rate")?;
+    if !(rate >= 1.0 && rate <= u32::MAX as f64 && rate.fract() == 0.0) {
+        return Err("WAV sample rates must be positive integers");
+    }
+    let (tag, bits) = encoding.format();
+    Ok(WavFormat {
+        channels: blob.unit_size(),
+        sample_rate: rate as u32,
+        float: tag == FORMAT_FLOAT,
+        bits,
+    })
+}
+
+fn write_header(out: &mut dyn Write, format: &WavFormat, len: u32) -> std::io::Result<()> {
+    let tag = if format.float {
+        FORMAT_FLOAT
+    } else {
+        FORMAT_PCM
+    };
+    let frame = format.frame_bytes() as u32;
+    out.write_all(b"RIFF")?;
+    out.write_all(&(36u32.wrapping_add(len)).to_le_bytes())?;
+    out.write_all(b"WAVEfmt ")?;
+    out.write_all(&16u32.to_le_bytes())?;
+    out.write_all(&tag.to_le_bytes())?;
+    out.write_all(&(format.channels as u16).to_le_bytes())?;
+    out.write_all(&format.sample_rate.to_le_bytes())?;
+    out.write_all(&(format.sample_rate.wrapping_mul(frame)).to_le_bytes())?;
+    out.write_all(&(frame as u16).to_le_bytes())?;
+    out.write_all(&format.bits.to_le_bytes())?;
+    out.write_all(b"data")?;
+    out.write_all(&len.to_le_bytes())
+}
+
+fn encode(samples: &[f32], encoding: WavEncoding, out: &mut Vec<u8>) {
+    for sample in samples {
+        match encoding {
+            WavEncoding::Float32 => out.extend_from_slice(&sample.to_le_bytes()),
+            WavEncoding::Pcm16 => {
+                let value = (sample.clamp(-1.0, 1.0) * 32767.0).round() as i16;
+                out.extend_from_slice(&value.to_le_bytes());
+            }
+            WavEncoding::Pcm24 => {
+                let value = (sample.clamp(-1.0, 1.0) * 8_388_607.0).round() as i32;
+                out.extend_from_slice(&value.to_le_bytes()[..3]);
+            }
+        }
+    }
+}
+
+impl Sink<DataBlob<f32>> for WavSink {
+    input_connection!(DataBlob<f32>);
+    fn drain(&self) -> LocalBoxFuture<'static, Result<(), &'static str>> {
+        let input = self.input.clone();
+        let (path, encoding) = (self.path.clone(), self.encoding);
+        Box::pin(async move {
+            let input = input.ok_or("Sink has no input")?;
+            let mut blobs = Pin::from(input.stream());
+            let first = match blobs.next().await {
+                Some(blob) => blob,
+                None => return Ok(()),
+            };
+            let format = blob_format(&first, encoding)?;
+            if format.channels > u16::MAX as usize {
+                return Err("Too many channels for a WAV file");
+            }
+            let file = File::create(&path).map_err(|_| "Failure to create WAV file")?;
+            let mut out = BufWriter::new(file);
+            let failure = "Failure to write WAV file";
+            write_header(&mut out, &format, 0).map_err(|_| failure)?;
+            let (mut len, mut bytes) = (0u64, Vec::new());
+            let mut blob = Some(first);
+            while let Some(samples) = blob {
+                let matches = blob_format(&samples, encoding).is_ok_and(|other| {
+                    (other.channels, other.sample_rate) == (format.channels, format.sample_rate)
+                });
+                if !matches {
+                    return Err("Blob does not match the format of the WAV file");
+                }
+                bytes.clear();
+                encode(samples.get_data(), encoding, &mut bytes);
+                len += bytes.len() as u64;
+                out.write_all(&bytes).map_err(|_| failure)?;
+                blob = blobs.next().await;
+            }
+            let len = u32::try_from(len)
+                .ok()
+                .filter(|len| *len <= u32::MAX - 36)
+                .ok_or("WAV files cannot exceed 4 GiB")?;
+            if len % 2 == 1 {
+                out.write_all(&[0]).map_err(|_| failure)?;
+            }
+            out.seek(SeekFrom::Start(0)).map_err(|_| failure)?;
+            write_header(&mut out, &format, len).map_err(|_| failure)?;
+            out.flush().map_err(|_| failure)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::tensor::TensorBlob;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn collect(source: &WavSource) -> Vec<DataBlob<f32>> {
+        block_on(Pin::from(source.stream()).collect())
+    }
+
+    /// a WAV file with an extensible header and an odd sized chunk before its samples
+    fn extensible(bits: u16, samples: &[u8]) -> Vec<u8> {
+        let mut wav = b"RIFF\0\0\0\0WAVE".to_vec();
+        wav.extend(b"LIST");
+        wav.extend(3u32.to_le_bytes());
+        wav.extend([1, 2, 3, 0]);
+        let mut format = vec![0xfe, 0xff, 2, 0];
+        format.extend(8000u32.to_le_bytes());
+        format.extend((8000 * 2 * bits as u32 / 8).to_le_bytes());
+        format.extend((2 * bits / 8).to_le_bytes());
+        format.extend(bits.to_le_bytes());
+        format.extend([22, 0, 0, 0, 0, 0, 0, 0, 1, 0]);
+        format.extend([0; 14]);
+        wav.extend(b"fmt ");
+        wav.extend((format.len() as u32).to_le_bytes());
+        wav.extend(format);
+        wav.extend(b"data");
+        wav.extend(u32::MAX.to_le_bytes());
+        wav.extend_from_slice(samples);
+        wav
+    }
+
+    #[test]
+    fn test_wav_source() {
+        // 3 stereo frames of 24 bit samples and a partial frame
+        let samples = [
+            0, 0, 0x40, 0, 0, 0xc0, //
+            0xff, 0xff, 0x7f, 0, 0, 0x80, //
+            0, 0, 0, 0, 0, 0, //
+            1, 2,
+        ];
+        let source = WavSource::from_bytes(extensible(24, &samples)).with_frames(2);
+        let blobs = collect(&source);
+        assert_eq!(source.error(), None);
+        assert_eq!(blobs.len(), 2);
+        assert_eq!(
+            blobs[0].get_data(),
+            &vec![0.5, -0.5, 8_388_607.0 / 8_388_608.0, -1.0]
+        );
+        assert_eq!(blobs[1].get_data(), &vec![0.0, 0.0]);
+        let meta = blobs[0].get_meta_data();
+        assert_eq!(meta.sample_rate, Some(8000.0));
+        assert_eq!(blobs[0].unit_count(), 2);
+        let tensor = TensorBlob::from_blob(blobs[0].clone()).unwrap();
+        assert_eq!(tensor.get(&[1, 1]), Some(&-1.0));
+        let invalid = WavSource::from_bytes(&b"RIFF\0\0\0\0AVI LIST"[..]);
+        assert!(collect(&invalid).is_empty());
+        assert_eq!(invalid.error(), Some("Not a WAV file"));
+        let odd = WavSource::from_bytes(extensible(12, &samples));
+        assert!(collect(&odd).is_empty());
+        assert_eq!(odd.error(), Some("Unsupported WAV sample encoding"));
+    }
+
+    #[test]
+    fn test_wav_round_trip() {
+        let path = std::env::temp_dir().join(format!("bitvortex-audio-{}.wav", std::process::id()));
+        let meta = MetaData {
+            name: "voice".to_string(),
+            unitary_dimensions: vec![2],
+            sample_rate: Some(44100.0),
+            ..Default::default()
+        };
+        let blobs = [
+            DataBlob::new(vec![0.25, -0.25, 1.5, 0.0], meta.clone()),
+            DataBlob::new(vec![-1.0, 0.125], meta.clone()),
+        ];
+        for (encoding, tolerance) in [(WavEncoding::Pcm16, 1e-4), (WavEncoding::Pcm24, 1e-6)] {
+            let mut sink = WavSink::create(&path).with_encoding(encoding);
+            sink.pipe(Rc::new(MockSource::new().items(blobs.clone())))
+                .unwrap();
+            block_on(sink.drain()).unwrap();
+            let read = collect(&WavSource::open(&path));
+            let expected = [0.25, -0.25, 1.0, 0.0, -1.0, 0.125];
+            assert_eq!(read.len(), 1);
+            for (sample, expected) in read[0].get_data().iter().zip(expected) {
+                assert!((sample - expected).abs() < tolerance, "{:?}", encoding);
+            }
+        }
+        let mut sink = WavSink::create(&path).with_encoding(WavEncoding::Float32);
+        sink.pipe(Rc::new(MockSource::new().items(blobs.clone())))
+            .unwrap();
+        block_on(sink.drain()).unwrap();
+        let read = collect(&WavSource::open(&path).with_frames(1));
+        assert_eq!(read.len(), 3);
+        assert_eq!(read[1].get_data(), &vec![1.5, 0.0]);
+        assert_eq!(read[2].get_meta_data().sample_rate, Some(44100.0));
+        let mono = MetaData {
+            unitary_dimensions: vec![1],
+            ..meta.clone()
+        };
+        let mono = DataBlob::new(vec![0.0], mono);
+        let mut sink = WavSink::create(&path);
+        sink.pipe(Rc::new(MockSource::new().items([blobs[0].clone(), mono])))
+            .unwrap();
+        assert_eq!(
+            block_on(sink.drain()),
+            Err("Blob does not match the format of the WAV file")
+        );
+        std::fs::remove_file(&path).unwrap();
+        let mut sink = WavSink::create(&path);
+        let unrated = DataBlob::new(vec![0.0], MetaData::default());
+        sink.pipe(Rc::new(MockSource::new().items([unrated])))
+            .unwrap();
+        assert_eq!(block_on(sink.drain()), Err("Blob has no sample rate"));
+    }
+}
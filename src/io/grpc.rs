@@ -0,0 +1,271 @@
+//! grpc
+//!
+//! gRPC message framing, turning streams of messages into the length prefixed byte
+//! streams of streaming gRPC calls and back
+//!
+//! gRPC prefixes every message with a compression flag and its big endian length, the
+//! encoding pipe produces that framing for the body of a client streaming call and the
+//! decoding pipe reassembles messages out of the arbitrarily split chunks of a server
+//! streaming response. Only the framing is covered: the HTTP/2 transport, the calls
+//! themselves and the protobuf mapping are left to the caller (no gRPC or protobuf crate
+//! is a dependency), messages are mapped to bytes with `GrpcMessage`, implemented by hand
+//! or by wrapping generated types. Compressed messages are not supported
+
+use crate::{Pipe, Source};
+use bytes::Bytes;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// bytes of the prefix of every message
+const PREFIX: usize = 5;
+
+/// GrpcMessage
+/// A message with a binary encoding (usually protobuf) carried by gRPC calls
+pub trait GrpcMessage: Sized {
+    /// the encoding of the message
+    fn encode(&self) -> Vec<u8>;
+    /// decode bytes written by `encode`
+    fn decode(bytes: &[u8]) -> Result<Self, &'static str>;
+}
+
+impl GrpcMessage for Bytes {
+    fn encode(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+    fn decode(bytes: &[u8]) -> Result<Self, &'static str> {
+        Ok(Bytes::copy_from_slice(bytes))
+    }
+}
+
+impl GrpcMessage for Vec<u8> {
+    fn encode(&self) -> Vec<u8> {
+        self.clone()
+    }
+    fn decode(bytes: &[u8]) -> Result<Self, &'static str> {
+        Ok(bytes.to_vec())
+    }
+}
+
+/// the framing of an uncompressed message
+pub fn frame(message: &[u8]) -> Vec<u8> {
+    let mut framed = Vec::with_capacity(PREFIX + message.len());
+    framed.push(0);
+    framed.extend_from_slice(&(message.len() as u32).to_be_bytes());
+    framed.extend_from_slice(message);
+    framed
+}
+
+/// FrameDecoder
+/// Reassembles framed messages out of chunks of bytes
+#[derive(Default)]
+pub struct FrameDecoder {
+    buffer: Vec<u8>,
+}
+
+impl FrameDecoder {
+    /// constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// add a chunk and return the messages it completes
+    pub fn push(&mut self, chunk: &[u8]) -> Result<Vec<Vec<u8>>, &'static str> {
+        self.buffer.extend_from_slice(chunk);
+        let mut messages = Vec::new();
+        let mut start = 0;
+        while self.buffer.len() - start >= PREFIX {
+            if self.buffer[start] != 0 {
+                return Err("Compressed gRPC messages are not supported");
+            }
+            let length =
+                u32::from_be_bytes(self.buffer[start + 1..start + PREFIX].try_into().unwrap());
+            let end = start + PREFIX + length as usize;
+            if self.buffer.len() < end {
+                break;
+            }
+            messages.push(self.buffer[start + PREFIX..end].to_vec());
+            start = end;
+        }
+        self.buffer.drain(..start);
+        Ok(messages)
+    }
+    /// whether bytes of an incomplete message are waiting
+    pub fn is_pending(&self) -> bool {
+        !self.buffer.is_empty()
+    }
+}
+
+/// GrpcEncodePipe
+/// A pipe framing messages into the chunks of a gRPC request body
+pub struct GrpcEncodePipe<M> {
+    input: Option<Rc<dyn Source<M>>>,
+}
+
+impl<M> GrpcEncodePipe<M> {
+    /// constructor
+    pub fn new() -> Self {
+        Self { input: None }
+    }
+}
+
+impl<M> Default for GrpcEncodePipe<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: GrpcMessage + 'static> Source<Bytes> for GrpcEncodePipe<M> {
+    fn stream(&self) -> Box<dyn Stream<Item = Bytes>> {
+        match &self.input {
+            Some(input) => Box::new(
+                Pin::from(input.stream()).map(|message| Bytes::from(frame(&message.encode()))),
+            ),
+            None => Box::new(stream::empty()),
+        }
+    }
+}
+
+impl<M: GrpcMessage + 'static> Pipe<M, Bytes> for GrpcEncodePipe<M> {
+    input_connection!(M);
+}
+
+/// GrpcDecodePipe
+/// A pipe decoding the messages of the chunks of a gRPC response body
+///
+/// the stream ends at the first framing or decoding error, kept by `error`
+pub struct GrpcDecodePipe<M> {
+    error: Rc<Cell<Option<&'static str>>>,
+    input: Option<Rc<dyn Source<Bytes>>>,
+    message: PhantomData<M>,
+}
+
+impl<M> GrpcDecodePipe<M> {
+    /// constructor
+    pub fn new() -> Self {
+        Self {
+            error: Rc::new(Cell::new(None)),
+            input: None,
+            message: PhantomData,
+        }
+    }
+    /// the error that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl<M> Default for GrpcDecodePipe<M> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<M: GrpcMessage + 'static> Source<M> for GrpcDecodePipe<M> {
+    fn stream(&self) -> Box<dyn Stream<Item = M>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        self.error.set(None);
+        let decoder = Rc::new(RefCell::new(FrameDecoder::new()));
+        let (error, decoding) = (self.error.clone(), decoder.clone());
+        let messages = input
+            .flat_map(move |chunk| {
+                let messages = decoding.borrow_mut().push(&chunk).and_then(|messages| {
+                    messages
+                        .iter()
+                        .map(|message| M::decode(message))
+                        .collect::<Result<Vec<M>, _>>()
+                });
+                stream::iter(match messages {
+                    Ok(messages) => messages.into_iter().map(Ok).collect(),
+                    Err(message) => vec![Err(message)],
+                })
+            })
+            .take_while(move |message| {
+                if let Err(message) = message {
+                    error.set(Some(message));
+                }
+                futures::future::ready(message.is_ok())
+            })
+            .filter_map(|message| async move { message.ok() });
+        let error = self.error.clone();
+        let end = stream::once(async move {
+            if error.get().is_none() && decoder.borrow().is_pending() {
+                error.set(Some("Truncated gRPC message"));
+            }
+        })
+        .filter_map(|_| async { None });
+        Box::new(messages.chain(end))
+    }
+}
+
+impl<M: GrpcMessage + 'static> Pipe<Bytes, M> for GrpcDecodePipe<M> {
+    input_connection!(Bytes);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct Reading(u32);
+
+    impl GrpcMessage for Reading {
+        fn encode(&self) -> Vec<u8> {
+            self.0.to_le_bytes().to_vec()
+        }
+        fn decode(bytes: &[u8]) -> Result<Self, &'static str> {
+            let bytes = bytes.try_into().map_err(|_| "Invalid reading")?;
+            Ok(Reading(u32::from_le_bytes(bytes)))
+        }
+    }
+
+    #[test]
+    fn test_round_trip_across_chunks() {
+        let mut encode = GrpcEncodePipe::new();
+        encode
+            .pipe(Rc::new(MockSource::new().items((0..4).map(Reading))))
+            .unwrap();
+        let body: Vec<u8> = block_on(Pin::from(encode.stream()).collect::<Vec<_>>()).concat();
+        assert_eq!(&body[..PREFIX + 4], &[0, 0, 0, 0, 4, 0, 0, 0, 0]);
+        let chunks: Vec<Bytes> = body.chunks(3).map(Bytes::copy_from_slice).collect();
+        let mut decode = GrpcDecodePipe::new();
+        decode
+            .pipe(Rc::new(MockSource::new().items(chunks)))
+            .unwrap();
+        let readings: Vec<Reading> = block_on(Pin::from(decode.stream()).collect());
+        assert_eq!(readings, (0..4).map(Reading).collect::<Vec<_>>());
+        assert_eq!(decode.error(), None);
+    }
+
+    #[test]
+    fn test_decoding_errors() {
+        let decoded = |chunks: Vec<Vec<u8>>| {
+            let mut decode = GrpcDecodePipe::<Reading>::new();
+            let chunks = chunks.into_iter().map(Bytes::from);
+            decode
+                .pipe(Rc::new(MockSource::new().items(chunks)))
+                .unwrap();
+            let readings: Vec<Reading> = block_on(Pin::from(decode.stream()).collect());
+            (readings.len(), decode.error())
+        };
+        let truncated = frame(&[1, 0, 0, 0])[..7].to_vec();
+        assert_eq!(
+            decoded(vec![frame(&[1, 0, 0, 0]), truncated]),
+            (1, Some("Truncated gRPC message"))
+        );
+        assert_eq!(decoded(vec![frame(&[1, 0])]), (0, Some("Invalid reading")));
+        let mut compressed = frame(&[1, 0, 0, 0]);
+        compressed[0] = 1;
+        assert_eq!(
+            decoded(vec![compressed]),
+            (0, Some("Compressed gRPC messages are not supported"))
+        );
+    }
+}
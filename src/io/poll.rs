@@ -0,0 +1,250 @@
+//! poll
+//!
+//! Ingesting HTTP endpoints that have no push interface by fetching them periodically
+//!
+//! conditional requests (`If-None-Match` with the last `ETag`, `If-Modified-Since` with the
+//! last `Last-Modified`) keep unchanged resources from being downloaded and emitted again.
+//! Requests run on their own thread so waiting for a slow server does not block the
+//! executor
+
+use crate::http::{self, Response, Url};
+use crate::runtime::thread_sleep;
+use crate::Source;
+use bytes::Bytes;
+use futures::channel::oneshot;
+use futures::stream;
+use futures::Stream;
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+type Parse<T> = Rc<dyn Fn(&[u8]) -> Result<T, &'static str>>;
+
+/// HttpPollSource
+/// A source fetching a URL on an interval and emitting the bodies that changed, as bytes
+/// or parsed into items such as DataBuckets
+///
+/// failed requests and bodies that fail to parse emit nothing and are kept by `error`
+/// while polling continues
+pub struct HttpPollSource<T = Bytes> {
+    url: Url,
+    interval: Duration,
+    timeout: Duration,
+    headers: Vec<(String, String)>,
+    polls: Option<usize>,
+    parse: Parse<T>,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl HttpPollSource<Bytes> {
+    /// constructor for a plain http URL fetched once per interval, forever
+    pub fn new(url: &str, interval: Duration) -> Result<Self, &'static str> {
+        Ok(Self {
+            url: Url::parse(url)?,
+            interval,
+            timeout: Duration::from_secs(30),
+            headers: Vec::new(),
+            polls: None,
+            parse: Rc::new(|body: &[u8]| Ok(Bytes::copy_from_slice(body))),
+            error: Rc::new(Cell::new(None)),
+        })
+    }
+}
+
+impl<T> HttpPollSource<T> {
+    /// parse bodies into items
+    pub fn parsed<U, F>(self, parse: F) -> HttpPollSource<U>
+    where
+        F: Fn(&[u8]) -> Result<U, &'static str> + 'static,
+    {
+        HttpPollSource {
+            url: self.url,
+            interval: self.interval,
+            timeout: self.timeout,
+            headers: self.headers,
+            polls: self.polls,
+            parse: Rc::new(parse),
+            error: self.error,
+        }
+    }
+    /// send a header with every request (such as an authorization)
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+    /// set how long to wait for the server (30 seconds by default)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// end streams after a number of requests
+    pub fn polls(mut self, polls: usize) -> Self {
+        self.polls = Some(polls);
+        self
+    }
+    /// the last error met while polling
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+struct Poller {
+    url: Url,
+    timeout: Duration,
+    headers: Vec<(String, String)>,
+    etag: Option<String>,
+    modified: Option<String>,
+}
+
+impl Poller {
+    async fn fetch(&self) -> Result<Response, &'static str> {
+        let mut headers = self.headers.clone();
+        if let Some(etag) = &self.etag {
+            headers.push(("If-None-Match".to_string(), etag.clone()));
+        }
+        if let Some(modified) = &self.modified {
+            headers.push(("If-Modified-Since".to_string(), modified.clone()));
+        }
+        let (url, timeout) = (self.url.clone(), self.timeout);
+        let (sender, receiver) = oneshot::channel();
+        std::thread::spawn(move || {
+            let headers: Vec<(&str, &str)> = headers
+                .iter()
+                .map(|(name, value)| (name.as_str(), value.as_str()))
+                .collect();
+            let _ = sender.send(http::send("GET", &url, &headers, b"", timeout));
+        });
+        receiver.await.map_err(|_| "Failure to read response")?
+    }
+    /// the body of the resource if it changed since the last poll
+    async fn poll(&mut self) -> Result<Option<Vec<u8>>, &'static str> {
+        let response = self.fetch().await?;
+        if response.status == 304 {
+            return Ok(None);
+        }
+        if !response.is_success() {
+            return Err("Unexpected HTTP status");
+        }
+        self.etag = response.header("ETag").map(str::to_string);
+        self.modified = response.header("Last-Modified").map(str::to_string);
+        Ok(Some(response.body))
+    }
+}
+
+impl<T: 'static> Source<T> for HttpPollSource<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let poller = Poller {
+            url: self.url.clone(),
+            timeout: self.timeout,
+            headers: self.headers.clone(),
+            etag: None,
+            modified: None,
+        };
+        let (interval, polls) = (self.interval, self.polls);
+        let (parse, error) = (self.parse.clone(), self.error.clone());
+        let state = (poller, 0_usize, None::<Instant>);
+        Box::new(stream::unfold(
+            state,
+            move |(mut poller, mut done, mut last)| {
+                let (parse, error) = (parse.clone(), error.clone());
+                async move {
+                    while polls.is_none_or(|polls| done < polls) {
+                        if let Some(last) = last {
+                            let remaining =
+                                (last + interval).saturating_duration_since(Instant::now());
+                            if !remaining.is_zero() {
+                                thread_sleep(remaining).await;
+                            }
+                        }
+                        last = Some(Instant::now());
+                        done += 1;
+                        let item = poller.poll().await.and_then(|body| match body {
+                            Some(body) => parse(&body).map(Some),
+                            None => Ok(None),
+                        });
+                        match item {
+                            Ok(Some(item)) => return Some((item, (poller, done, last))),
+                            Ok(None) => {}
+                            Err(message) => error.set(Some(message)),
+                        }
+                    }
+                    None
+                }
+            },
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+    use std::pin::Pin;
+
+    /// a server answering requests in turn and returning the requests it received
+    fn serve(responses: Vec<&'static str>) -> (String, std::thread::JoinHandle<Vec<String>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/readings", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for response in responses {
+                let (mut connection, _) = listener.accept().unwrap();
+                let mut request = [0_u8; 2048];
+                let read = connection.read(&mut request).unwrap();
+                requests.push(String::from_utf8_lossy(&request[..read]).to_string());
+                connection.write_all(response.as_bytes()).unwrap();
+            }
+            requests
+        });
+        (url, server)
+    }
+
+    #[test]
+    fn test_conditional_polling() {
+        let (url, server) = serve(vec![
+            "HTTP/1.1 200 OK\r\nETag: \"a\"\r\nContent-Length: 2\r\n\r\n12",
+            "HTTP/1.1 304 Not Modified\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nETag: \"b\"\r\nContent-Length: 2\r\n\r\n34",
+        ]);
+        let source = HttpPollSource::new(&url, Duration::ZERO)
+            .unwrap()
+            .with_header("Accept", "text/plain")
+            .polls(3)
+            .parsed(|body| {
+                let text = std::str::from_utf8(body).map_err(|_| "Invalid text")?;
+                text.parse::<u32>().map_err(|_| "Invalid reading")
+            });
+        let readings: Vec<u32> = block_on(Pin::from(source.stream()).collect());
+        assert_eq!(readings, vec![12, 34]);
+        assert_eq!(source.error(), None);
+        let requests = server.join().unwrap();
+        assert!(requests[0].contains("Accept: text/plain\r\n"));
+        assert!(!requests[0].contains("If-None-Match"));
+        assert!(requests[1].contains("If-None-Match: \"a\"\r\n"));
+        assert!(requests[2].contains("If-None-Match: \"a\"\r\n"));
+    }
+
+    #[test]
+    fn test_polling_errors() {
+        let (url, server) = serve(vec![
+            "HTTP/1.1 500 Internal Server Error\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nLast-Modified: Tue, 13 Oct 2026 10:00:00 GMT\r\n\r\nok",
+        ]);
+        let source = HttpPollSource::new(&url, Duration::from_millis(20))
+            .unwrap()
+            .polls(2);
+        let start = Instant::now();
+        let bodies: Vec<Bytes> = block_on(Pin::from(source.stream()).collect());
+        assert!(
+            start.elapsed() >= Duration::from_millis(20),
+            "Interval not kept"
+        );
+        assert_eq!(bodies, vec![Bytes::from_static(b"ok")]);
+        assert_eq!(source.error(), Some("Unexpected HTTP status"));
+        server.join().unwrap();
+        assert!(HttpPollSource::new("ftp://host/", Duration::ZERO).is_err());
+    }
+}
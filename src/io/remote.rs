@@ -0,0 +1,545 @@
+//! remote
+//!
+//! Splitting a pipeline across processes or machines, a sink of one pipeline sending its
+//! items over TCP to a source of another
+//!
+//! the sink connects to the source and opens with a handshake naming the type of its items
+//! and the schema its input declares, the source refuses items of another type or schemas
+//! it does not accept. Every item then travels as a length prefixed frame, and a closing
+//! frame tells a finished stream from a lost connection. Items are encoded with
+//! `WireItem`, implemented for the primitive types, blobs and buckets
+
+use crate::data_bucket::half::{BF16, F16};
+use crate::data_bucket::schema::{BlobKind, StreamSchema};
+use crate::data_bucket::{encoding::BinaryElement, DataBucket, DataBucketBlob};
+use crate::runtime::thread_sleep;
+use crate::{Sink, Source};
+use bytes::Bytes;
+use futures::channel::{mpsc, oneshot};
+use futures::future::LocalBoxFuture;
+use futures::{SinkExt, Stream, StreamExt};
+use num_complex::{Complex32, Complex64};
+use std::cell::Cell;
+use std::io::{BufWriter, Read, Write};
+use std::marker::PhantomData;
+use std::net::{SocketAddr, TcpListener, TcpStream, ToSocketAddrs};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// opening bytes of a connection, ending with the protocol version
+const MAGIC: &[u8; 4] = b"BVR\x01";
+
+/// frame tags
+const END: u8 = 0;
+const ITEM: u8 = 1;
+
+/// handshake answers
+const ACCEPTED: u8 = 0;
+const OTHER_TYPE: u8 = 1;
+const OTHER_SCHEMA: u8 = 2;
+
+/// frames a sink encodes ahead of the connection
+const FRAMES_IN_FLIGHT: usize = 64;
+
+/// longest text of a handshake
+const MAX_TEXT: u32 = 1 << 16;
+
+/// how often a source waiting for its sink checks whether the stream was dropped
+const ACCEPT_POLL: Duration = Duration::from_millis(20);
+
+/// WireItem
+/// An item type with a binary encoding for crossing process boundaries
+pub trait WireItem: Sized {
+    /// the name of the type, both ends of a connection must agree on it
+    const TYPE: &'static str;
+    /// the encoding of the item
+    fn encode(&self) -> Result<Vec<u8>, &'static str>;
+    /// decode bytes written by `encode`
+    fn decode(bytes: &[u8]) -> Result<Self, &'static str>;
+}
+
+macro_rules! element_wire_item {
+  ($($t:ty => $name:literal),*) => {
+    $( impl WireItem for $t {
+      const TYPE: &'static str = $name;
+      fn encode(&self) -> Result<Vec<u8>, &'static str> {
+        let mut bytes = Vec::new();
+        <$t>::write_bytes(std::slice::from_ref(self), &mut bytes);
+        Ok(bytes)
+      }
+      fn decode(bytes: &[u8]) -> Result<Self, &'static str> {
+        let mut items = <$t>::read_bytes(bytes)?;
+        match items.len() {
+          1 => Ok(items.remove(0)),
+          _ => Err("Encoding does not hold exactly one item"),
+        }
+      }
+    } )*
+  }
+}
+
+element_wire_item!(
+    bool => "bool", char => "char", i8 => "i8", u8 => "u8", i16 => "i16", u16 => "u16",
+    i32 => "i32", u32 => "u32", i64 => "i64", u64 => "u64", i128 => "i128", u128 => "u128",
+    f32 => "f32", f64 => "f64", F16 => "F16", BF16 => "BF16", String => "String", Bytes => "Bytes",
+    Complex32 => "Complex32", Complex64 => "Complex64"
+);
+
+impl WireItem for DataBucketBlob {
+    const TYPE: &'static str = "DataBucketBlob";
+    fn encode(&self) -> Result<Vec<u8>, &'static str> {
+        self.to_bytes()
+    }
+    fn decode(bytes: &[u8]) -> Result<Self, &'static str> {
+        DataBucketBlob::from_bytes(bytes)
+    }
+}
+
+impl WireItem for DataBucket {
+    const TYPE: &'static str = "DataBucket";
+    fn encode(&self) -> Result<Vec<u8>, &'static str> {
+        self.to_bytes()
+    }
+    fn decode(bytes: &[u8]) -> Result<Self, &'static str> {
+        DataBucket::from_bytes(bytes)
+    }
+}
+
+fn write_text(text: &str, out: &mut Vec<u8>) {
+    out.extend_from_slice(&(text.len() as u32).to_le_bytes());
+    out.extend_from_slice(text.as_bytes());
+}
+
+fn read_u32(connection: &mut impl Read) -> std::io::Result<u32> {
+    let mut bytes = [0; 4];
+    connection.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_text(connection: &mut impl Read) -> Result<String, &'static str> {
+    let len = read_u32(connection).map_err(|_| "Remote connection lost")?;
+    if len > MAX_TEXT {
+        return Err("Invalid remote handshake");
+    }
+    let mut bytes = vec![0; len as usize];
+    connection
+        .read_exact(&mut bytes)
+        .map_err(|_| "Remote connection lost")?;
+    String::from_utf8(bytes).map_err(|_| "Invalid remote handshake")
+}
+
+/// the opening of a connection: magic, item type and declared schema
+fn hello(item_type: &str, schema: Option<&StreamSchema>) -> Vec<u8> {
+    let mut bytes = MAGIC.to_vec();
+    write_text(item_type, &mut bytes);
+    match schema {
+        None => bytes.push(0),
+        Some(StreamSchema::Blob(kind)) => {
+            bytes.push(1);
+            write_text(kind.name(), &mut bytes);
+        }
+        Some(StreamSchema::Bucket(blobs)) => {
+            bytes.push(2);
+            bytes.extend_from_slice(&(blobs.len() as u32).to_le_bytes());
+            for (name, kind) in blobs {
+                write_text(name, &mut bytes);
+                write_text(kind.name(), &mut bytes);
+            }
+        }
+        Some(StreamSchema::Registered(name)) => {
+            bytes.push(3);
+            write_text(name, &mut bytes);
+        }
+    }
+    bytes
+}
+
+fn read_kind(connection: &mut impl Read) -> Result<BlobKind, &'static str> {
+    BlobKind::from_name(&read_text(connection)?).ok_or("Invalid remote handshake")
+}
+
+/// read the opening of a connection
+fn read_hello(connection: &mut impl Read) -> Result<(String, Option<StreamSchema>), &'static str> {
+    let mut magic = [0; 4];
+    connection
+        .read_exact(&mut magic)
+        .map_err(|_| "Remote connection lost")?;
+    if &magic != MAGIC {
+        return Err("Invalid remote handshake");
+    }
+    let item_type = read_text(connection)?;
+    let mut shape = [0];
+    connection
+        .read_exact(&mut shape)
+        .map_err(|_| "Remote connection lost")?;
+    let schema = match shape[0] {
+        0 => None,
+        1 => Some(StreamSchema::Blob(read_kind(connection)?)),
+        2 => {
+            let count = read_u32(connection).map_err(|_| "Remote connection lost")?;
+            let mut blobs = Vec::new();
+            for _ in 0..count {
+                blobs.push((read_text(connection)?, read_kind(connection)?));
+            }
+            Some(StreamSchema::Bucket(blobs.into_iter().collect()))
+        }
+        3 => Some(StreamSchema::Registered(read_text(connection)?)),
+        _ => return Err("Invalid remote handshake"),
+    };
+    Ok((item_type, schema))
+}
+
+type Frames = mpsc::Sender<Result<Vec<u8>, &'static str>>;
+
+/// wait for a sink, answer its handshake and forward its frames until it closes the stream
+fn receive(listener: TcpListener, item_type: &str, expected: Option<StreamSchema>, frames: Frames) {
+    let mut frames = frames;
+    let mut connection = loop {
+        match listener.accept() {
+            Ok((connection, _)) => break connection,
+            Err(_) if !frames.is_closed() => std::thread::sleep(ACCEPT_POLL),
+            Err(_) => return,
+        }
+    };
+    let mut forward = |frame| futures::executor::block_on(frames.send(frame)).is_ok();
+    if connection.set_nonblocking(false).is_err() {
+        forward(Err("Remote connection lost"));
+        return;
+    }
+    let (answer, refusal) = match read_hello(&mut connection) {
+        Err(error) => {
+            forward(Err(error));
+            return;
+        }
+        Ok((other, _)) if other != item_type => (OTHER_TYPE, "Remote sink sends another item type"),
+        Ok((_, produced)) => match (&expected, &produced) {
+            (Some(expected), Some(produced)) if expected.accepts(produced).is_err() => (
+                OTHER_SCHEMA,
+                "Remote sink declares a schema that is not accepted",
+            ),
+            _ => (ACCEPTED, ""),
+        },
+    };
+    if connection.write_all(&[answer]).is_err() {
+        forward(Err("Remote connection lost"));
+        return;
+    }
+    if answer != ACCEPTED {
+        forward(Err(refusal));
+        return;
+    }
+    let mut connection = std::io::BufReader::new(connection);
+    loop {
+        let mut tag = [0];
+        if connection.read_exact(&mut tag).is_err() {
+            forward(Err("Remote connection lost before the end of the stream"));
+            return;
+        }
+        match tag[0] {
+            END => return,
+            ITEM => {}
+            _ => {
+                forward(Err("Invalid remote frame"));
+                return;
+            }
+        }
+        // reading through take grows the buffer with the bytes received rather than
+        // trusting the announced length
+        let frame = read_u32(&mut connection).and_then(|len| {
+            let mut bytes = Vec::new();
+            match (&mut connection).take(len as u64).read_to_end(&mut bytes)? == len as usize {
+                true => Ok(bytes),
+                false => Err(std::io::ErrorKind::UnexpectedEof.into()),
+            }
+        });
+        let forwarded = match frame {
+            Ok(bytes) => forward(Ok(bytes)),
+            Err(_) => {
+                forward(Err("Remote connection lost before the end of the stream"));
+                return;
+            }
+        };
+        if !forwarded {
+            return;
+        }
+    }
+}
+
+/// RemoteSource
+/// A source streaming the items a `RemoteSink` of another pipeline sends to its address
+///
+/// every stream waits for one sink to connect, streams end when the sink finished and
+/// failures (refused handshakes, lost connections, undecodable items) end them early and
+/// are kept by `error`. Connections are not encrypted, keep them on trusted networks
+pub struct RemoteSource<T> {
+    listener: TcpListener,
+    address: SocketAddr,
+    expected: Option<StreamSchema>,
+    capacity: usize,
+    pub(super) error: Rc<Cell<Option<&'static str>>>,
+    items: PhantomData<T>,
+}
+
+impl<T> RemoteSource<T> {
+    /// start listening on an address (port 0 picks a free port)
+    pub fn bind<A: ToSocketAddrs>(address: A) -> Result<Self, &'static str> {
+        let listener = TcpListener::bind(address).map_err(|_| "Failure to bind remote source")?;
+        let address = listener
+            .local_addr()
+            .map_err(|_| "Failure to bind remote source")?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|_| "Failure to bind remote source")?;
+        Ok(Self {
+            listener,
+            address,
+            expected: None,
+            capacity: 64,
+            error: Rc::new(Cell::new(None)),
+            items: PhantomData,
+        })
+    }
+    /// refuse sinks whose input declares a schema this one does not accept, the schema is
+    /// also declared to the pipes downstream
+    pub fn expects(mut self, schema: StreamSchema) -> Self {
+        self.expected = Some(schema);
+        self
+    }
+    /// set how many received items may wait for the pipeline (64 by default), a full
+    /// buffer stops reading from the connection
+    pub fn with_capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity.max(1);
+        self
+    }
+    /// the address the source listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.address
+    }
+    /// the failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl<T: WireItem + 'static> Source<T> for RemoteSource<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        self.error.set(None);
+        let listener = match self.listener.try_clone() {
+            Ok(listener) => listener,
+            Err(_) => {
+                self.error.set(Some("Failure to bind remote source"));
+                return Box::new(futures::stream::empty());
+            }
+        };
+        let (sender, receiver) = mpsc::channel(self.capacity);
+        let expected = self.expected.clone();
+        std::thread::spawn(move || receive(listener, T::TYPE, expected, sender));
+        let error = self.error.clone();
+        Box::new(
+            receiver
+                .map(|frame| frame.and_then(|bytes| T::decode(&bytes)))
+                .take_while(move |item| {
+                    if let Err(message) = item {
+                        error.set(Some(message));
+                    }
+                    futures::future::ready(item.is_ok())
+                })
+                .filter_map(|item| futures::future::ready(item.ok())),
+        )
+    }
+    fn schema(&self) -> Option<StreamSchema> {
+        self.expected.clone()
+    }
+}
+
+/// RemoteSink
+/// A sink sending the items it drains to the `RemoteSource` of another pipeline
+///
+/// draining connects to the source, retrying until the connection timeout while the source
+/// is not listening yet, and completes once the source read the whole stream
+pub struct RemoteSink<T> {
+    address: SocketAddr,
+    timeout: Duration,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T> RemoteSink<T> {
+    /// constructor for the address of a source
+    pub fn connect<A: ToSocketAddrs>(address: A) -> Result<Self, &'static str> {
+        let address = address
+            .to_socket_addrs()
+            .ok()
+            .and_then(|mut addresses| addresses.next())
+            .ok_or("Invalid remote address")?;
+        Ok(Self {
+            address,
+            timeout: Duration::from_secs(10),
+            input: None,
+        })
+    }
+    /// set how long to keep trying to reach the source (10 seconds by default)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+/// connect to a source, retrying until the deadline
+async fn open(address: SocketAddr, timeout: Duration) -> Result<TcpStream, &'static str> {
+    let deadline = Instant::now() + timeout;
+    loop {
+        let left = deadline.saturating_duration_since(Instant::now());
+        match TcpStream::connect_timeout(&address, left.max(Duration::from_millis(1))) {
+            Ok(connection) => return Ok(connection),
+            Err(_) if Instant::now() < deadline => thread_sleep(Duration::from_millis(50)).await,
+            Err(_) => return Err("Failure to connect to remote source"),
+        }
+    }
+}
+
+impl<T: WireItem + 'static> Sink<T> for RemoteSink<T> {
+    input_connection!(T);
+    fn drain(&self) -> LocalBoxFuture<'static, Result<(), &'static str>> {
+        let input = self.input.clone();
+        let (address, timeout) = (self.address, self.timeout);
+        Box::pin(async move {
+            let input = input.ok_or("Sink has no input")?;
+            let mut connection = open(address, timeout).await?;
+            let lost = |_| "Remote connection lost";
+            connection
+                .write_all(&hello(T::TYPE, input.schema().as_ref()))
+                .map_err(lost)?;
+            let mut answer = [0];
+            connection.read_exact(&mut answer).map_err(lost)?;
+            match answer[0] {
+                ACCEPTED => {}
+                OTHER_TYPE => return Err("Remote source expects another item type"),
+                OTHER_SCHEMA => return Err("Remote source does not accept the schema"),
+                _ => return Err("Invalid remote handshake"),
+            }
+            // a thread writes the frames so a slow connection suspends the drain instead of
+            // blocking the executor
+            let (mut frames, pending) = mpsc::channel(FRAMES_IN_FLIGHT);
+            let (done, finished) = oneshot::channel();
+            std::thread::spawn(move || done.send(send(connection, pending)));
+            let mut stream = Pin::from(input.stream());
+            while let Some(item) = stream.next().await {
+                let bytes = item.encode()?;
+                let len = u32::try_from(bytes.len()).map_err(|_| "Item too large to send")?;
+                let mut frame = Vec::with_capacity(bytes.len() + 5);
+                frame.push(ITEM);
+                frame.extend_from_slice(&len.to_le_bytes());
+                frame.extend_from_slice(&bytes);
+                if frames.send(frame).await.is_err() {
+                    break;
+                }
+            }
+            drop(frames);
+            finished.await.unwrap_or(Err("Remote connection lost"))
+        })
+    }
+}
+
+/// write frames to a source, then the end of the stream
+fn send(connection: TcpStream, frames: mpsc::Receiver<Vec<u8>>) -> Result<(), &'static str> {
+    let lost = |_| "Remote connection lost";
+    let mut writer = BufWriter::new(connection.try_clone().map_err(lost)?);
+    for frame in futures::executor::block_on_stream(frames) {
+        writer.write_all(&frame).map_err(lost)?;
+    }
+    writer.write_all(&[END]).map_err(lost)?;
+    writer.flush().map_err(lost)?;
+    // the source closes the connection once it read the end of the stream
+    let _ = (&connection).read(&mut [0]);
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::{DataBlob, MetaData};
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn transfer<T: WireItem + Clone + 'static>(
+        source: RemoteSource<T>,
+        items: Vec<T>,
+    ) -> (Vec<T>, Result<(), &'static str>, Option<&'static str>) {
+        let mut sink = RemoteSink::connect(source.local_addr()).unwrap();
+        sink.pipe(Rc::new(MockSource::new().items(items))).unwrap();
+        let received = Pin::from(source.stream()).collect::<Vec<T>>();
+        let (received, drained) = block_on(futures::future::join(received, sink.drain()));
+        (received, drained, source.error())
+    }
+
+    #[test]
+    fn test_transfer() {
+        let source = RemoteSource::<f64>::bind("127.0.0.1:0").unwrap();
+        let (received, drained, error) = transfer(source, vec![1.0, -2.5, 3.0]);
+        assert_eq!(received, [1.0, -2.5, 3.0]);
+        assert_eq!((drained, error), (Ok(()), None));
+        let mut bucket = DataBucket::new();
+        bucket.add_blob(DataBucketBlob::Float64(DataBlob::new(
+            vec![1.0, 2.0],
+            MetaData {
+                name: "speed".to_string(),
+                ..Default::default()
+            },
+        )));
+        let source = RemoteSource::<DataBucket>::bind("127.0.0.1:0").unwrap();
+        let (received, drained, _) = transfer(source, vec![bucket.clone(), DataBucket::new()]);
+        assert_eq!(drained, Ok(()));
+        assert_eq!(received.len(), 2);
+        assert!(received[0].diff(&bucket).is_empty());
+    }
+
+    #[test]
+    fn test_handshake_refusals() {
+        let source = RemoteSource::<String>::bind("127.0.0.1:0").unwrap();
+        let mut sink = RemoteSink::<f64>::connect(source.local_addr()).unwrap();
+        sink.pipe(Rc::new(MockSource::new().items(vec![1.0])))
+            .unwrap();
+        let received = Pin::from(source.stream()).collect::<Vec<String>>();
+        let (received, drained) = block_on(futures::future::join(received, sink.drain()));
+        assert!(received.is_empty());
+        assert_eq!(drained, Err("Remote source expects another item type"));
+        assert_eq!(source.error(), Some("Remote sink sends another item type"));
+        let expected = StreamSchema::bucket([("speed", BlobKind::Float64)]);
+        let source = RemoteSource::<DataBucket>::bind("127.0.0.1:0")
+            .unwrap()
+            .expects(expected.clone());
+        assert_eq!(source.schema(), Some(expected));
+        let declared = crate::data_bucket::schema::DeclaredSource::new(
+            Rc::new(MockSource::new().items(vec![DataBucket::new()])),
+            StreamSchema::bucket([("speed", BlobKind::Int64)]),
+        );
+        let mut sink = RemoteSink::connect(source.local_addr()).unwrap();
+        sink.pipe(Rc::new(declared)).unwrap();
+        let received = Pin::from(source.stream()).collect::<Vec<DataBucket>>();
+        let (_, drained) = block_on(futures::future::join(received, sink.drain()));
+        assert_eq!(drained, Err("Remote source does not accept the schema"));
+    }
+
+    #[test]
+    fn test_lost_connection() {
+        let source = RemoteSource::<u32>::bind("127.0.0.1:0").unwrap();
+        let received = Pin::from(source.stream()).collect::<Vec<u32>>();
+        {
+            // the connection closes at the end of the block
+            let mut client = TcpStream::connect(source.local_addr()).unwrap();
+            client.write_all(&hello("u32", None)).unwrap();
+            client.write_all(&[ITEM, 4, 0, 0, 0, 7, 0, 0, 0]).unwrap();
+        }
+        assert_eq!(block_on(received), [7]);
+        assert_eq!(
+            source.error(),
+            Some("Remote connection lost before the end of the stream")
+        );
+        let sink = RemoteSink::<u32>::connect(source.local_addr())
+            .unwrap()
+            .with_timeout(Duration::from_millis(10));
+        assert_eq!(block_on(sink.drain()), Err("Sink has no input"));
+        assert!(RemoteSink::<u32>::connect("not an address").is_err());
+    }
+}
@@ -0,0 +1,268 @@
+//! shard
+//!
+//! Distributing the items of a pipeline across worker processes and collecting their
+//! results
+//!
+//! a `ShardRouter` sends every item to the worker chosen by hashing its key, so items with
+//! equal keys are always handled by the same worker, and a `ShardCollector` merges what the
+//! workers send back. A worker is an ordinary pipeline reading a `RemoteSource` and draining
+//! into a `RemoteSink` that targets its address of the collector
+
+use super::remote::{RemoteSink, RemoteSource, WireItem};
+use crate::data_bucket::schema::StreamSchema;
+use crate::data_bucket::DataBucket;
+use crate::pipes::split::{SplitOutput, Splitter};
+use crate::{Sink, Source};
+use futures::future::LocalBoxFuture;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// ShardRouter
+/// A sink sending each item to one of several remote workers by the hash of its key
+///
+/// items keep their order within a worker, draining fails with the first worker that
+/// cannot be reached or refuses the stream
+pub struct ShardRouter<T = DataBucket> {
+    workers: Vec<SocketAddr>,
+    key: Rc<dyn Fn(&T) -> u64>,
+    timeout: Duration,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T: 'static> ShardRouter<T> {
+    /// constructor for the addresses of the workers, in shard order, and a key function
+    pub fn new<A, K, F>(workers: &[A], key: F) -> Result<Self, &'static str>
+    where
+        A: ToSocketAddrs,
+        K: Hash,
+        F: Fn(&T) -> K + 'static,
+    {
+        if workers.is_empty() {
+            return Err("Number of workers must be strictly positive");
+        }
+        let workers = workers
+            .iter()
+            .map(|worker| {
+                worker
+                    .to_socket_addrs()
+                    .ok()
+                    .and_then(|mut addresses| addresses.next())
+                    .ok_or("Invalid remote address")
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            workers,
+            key: Rc::new(move |item| {
+                let mut hasher = DefaultHasher::new();
+                key(item).hash(&mut hasher);
+                hasher.finish()
+            }),
+            timeout: Duration::from_secs(10),
+            input: None,
+        })
+    }
+    /// set how long to keep trying to reach each worker (10 seconds by default)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    /// number of workers
+    pub fn workers(&self) -> usize {
+        self.workers.len()
+    }
+    /// the worker an item is sent to
+    pub fn shard(&self, item: &T) -> usize {
+        ((self.key)(item) % self.workers.len() as u64) as usize
+    }
+}
+
+impl<T: WireItem + 'static> Sink<T> for ShardRouter<T> {
+    input_connection!(T);
+    fn drain(&self) -> LocalBoxFuture<'static, Result<(), &'static str>> {
+        let input = self.input.clone();
+        let (workers, key, timeout) = (self.workers.clone(), self.key.clone(), self.timeout);
+        Box::pin(async move {
+            let input = input.ok_or("Sink has no input")?;
+            let shards = workers.len() as u64;
+            let route = move |item: &T| (key(item) % shards) as usize;
+            let splitter = Rc::new(Splitter::new(workers.len(), Box::new(route)));
+            splitter.set_input(Some(input));
+            let mut sinks = Vec::new();
+            for (index, worker) in workers.into_iter().enumerate() {
+                let mut sink = RemoteSink::connect(worker)?.with_timeout(timeout);
+                sink.pipe(Rc::new(SplitOutput {
+                    splitter: splitter.clone(),
+                    index,
+                }))?;
+                sinks.push(sink);
+            }
+            let drains = sinks.iter().map(|sink| sink.drain());
+            futures::future::join_all(drains)
+                .await
+                .into_iter()
+                .collect::<Result<Vec<()>, _>>()?;
+            Ok(())
+        })
+    }
+}
+
+/// ShardCollector
+/// A source merging the items remote workers send back, each worker to its own address
+///
+/// items of a worker keep their order while those of different workers are interleaved as
+/// they arrive, failures of any worker end the stream and are kept by `error`
+pub struct ShardCollector<T = DataBucket> {
+    sources: Vec<RemoteSource<T>>,
+    expected: Option<StreamSchema>,
+}
+
+impl<T> ShardCollector<T> {
+    /// start listening for workers on addresses (port 0 picks a free port)
+    pub fn bind<A: ToSocketAddrs>(addresses: &[A]) -> Result<Self, &'static str> {
+        if addresses.is_empty() {
+            return Err("Number of workers must be strictly positive");
+        }
+        Ok(Self {
+            sources: addresses
+                .iter()
+                .map(RemoteSource::bind)
+                .collect::<Result<_, _>>()?,
+            expected: None,
+        })
+    }
+    /// refuse workers whose results declare a schema this one does not accept
+    pub fn expects(mut self, schema: StreamSchema) -> Self {
+        self.sources = self
+            .sources
+            .into_iter()
+            .map(|source| source.expects(schema.clone()))
+            .collect();
+        self.expected = Some(schema);
+        self
+    }
+    /// the addresses workers send their results to, in shard order
+    pub fn local_addrs(&self) -> Vec<SocketAddr> {
+        self.sources.iter().map(RemoteSource::local_addr).collect()
+    }
+    /// the failure of the first worker whose last stream failed
+    pub fn error(&self) -> Option<&'static str> {
+        self.sources.iter().find_map(RemoteSource::error)
+    }
+}
+
+impl<T: WireItem + 'static> Source<T> for ShardCollector<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let streams = self.sources.iter().map(|source| Pin::from(source.stream()));
+        let sources = self.sources.iter().map(|source| source.error.clone());
+        let errors: Vec<_> = sources.collect();
+        // a failed worker ends the whole stream rather than silently losing its shard
+        Box::new(stream::select_all(streams).take_while(move |_| {
+            futures::future::ready(errors.iter().all(|error| error.get().is_none()))
+        }))
+    }
+    fn schema(&self) -> Option<StreamSchema> {
+        self.expected.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::{DataBlob, DataBucketBlob, MetaData};
+    use crate::pipes::FlatMapPipe;
+    use crate::testing::MockSource;
+    use crate::Pipe;
+    use futures::executor::block_on;
+
+    fn blob(name: &str, value: i64) -> DataBucketBlob {
+        DataBucketBlob::Int64(DataBlob::new(
+            vec![value],
+            MetaData {
+                name: name.to_string(),
+                ..Default::default()
+            },
+        ))
+    }
+
+    fn value(bucket: &DataBucket, name: &str) -> i64 {
+        match bucket.get_blob(&name.to_string()) {
+            Some(DataBucketBlob::Int64(blob)) => blob.get_data()[0],
+            _ => panic!("Bucket without {}", name),
+        }
+    }
+
+    /// a worker process, tagging the buckets it handles with its index
+    fn worker(index: i64, results: SocketAddr) -> SocketAddr {
+        let (address, bound) = std::sync::mpsc::channel();
+        std::thread::spawn(move || {
+            let source = Rc::new(RemoteSource::<DataBucket>::bind("127.0.0.1:0").unwrap());
+            address.send(source.local_addr()).unwrap();
+            let mut tag = FlatMapPipe::new(move |mut bucket: DataBucket| {
+                bucket.add_blob(blob("worker", index));
+                Some(bucket)
+            });
+            tag.pipe(source).unwrap();
+            let mut sink = RemoteSink::connect(results).unwrap();
+            sink.pipe(Rc::new(tag)).unwrap();
+            block_on(sink.drain()).unwrap();
+        });
+        bound.recv().unwrap()
+    }
+
+    #[test]
+    fn test_distributed_run() {
+        let collector = ShardCollector::<DataBucket>::bind(&["127.0.0.1:0"; 3]).unwrap();
+        let workers: Vec<SocketAddr> = collector
+            .local_addrs()
+            .into_iter()
+            .enumerate()
+            .map(|(index, results)| worker(index as i64, results))
+            .collect();
+        let key = |bucket: &DataBucket| value(bucket, "key");
+        let mut router = ShardRouter::new(&workers, key).unwrap();
+        let buckets: Vec<DataBucket> = (0..300)
+            .map(|index| {
+                let mut bucket = DataBucket::new();
+                bucket.add_blob(blob("key", index % 5));
+                bucket
+            })
+            .collect();
+        let expected: Vec<usize> = buckets.iter().map(|bucket| router.shard(bucket)).collect();
+        assert!(expected.iter().all(|shard| *shard < router.workers()));
+        router
+            .pipe(Rc::new(MockSource::new().items(buckets)))
+            .unwrap();
+        let results = Pin::from(collector.stream()).collect::<Vec<DataBucket>>();
+        let (results, drained) = block_on(futures::future::join(results, router.drain()));
+        assert_eq!(drained, Ok(()));
+        assert_eq!(collector.error(), None);
+        assert_eq!(results.len(), 300);
+        for bucket in results.iter() {
+            let key = value(bucket, "key");
+            assert_eq!(value(bucket, "worker") as usize, expected[key as usize]);
+        }
+    }
+
+    #[test]
+    fn test_invalid_shards() {
+        let none: [&str; 0] = [];
+        assert!(ShardRouter::new(&none, |item: &DataBucket| item.len()).is_err());
+        assert!(ShardCollector::<DataBucket>::bind(&none).is_err());
+        let mut router = ShardRouter::new(&["127.0.0.1:1"], |item: &DataBucket| item.len())
+            .unwrap()
+            .with_timeout(Duration::from_millis(10));
+        router
+            .pipe(Rc::new(MockSource::new().items(vec![DataBucket::new()])))
+            .unwrap();
+        assert_eq!(
+            block_on(router.drain()),
+            Err("Failure to connect to remote source")
+        );
+    }
+}
@@ -1,6 +1,7 @@
 //! bitvortex
 //!
 //! A library for processing and analysing data concurrently, asynchronously and in parallel
+use futures::future::LocalBoxFuture;
 use futures::Stream;
 use std::rc::Rc;
 
@@ -8,6 +9,11 @@ use std::rc::Rc;
 /// A trait for a struct that can provide an output data stream
 pub trait Source<T> {
     fn stream(&self) -> Box<dyn Stream<Item = T>>;
+    /// the declared schema of the streamed items, checked by pipes expecting one when they
+    /// are connected (None when undeclared)
+    fn schema(&self) -> Option<data_bucket::schema::StreamSchema> {
+        None
+    }
 }
 
 /// Pipe
@@ -21,6 +27,144 @@ pub trait Pipe<InT, OutT>: Source<OutT> {
     fn get_input(&self) -> Option<Rc<dyn Source<InT>>>;
 }
 
+/// Sink
+/// Trait for the terminal elements consuming data streams
+pub trait Sink<T> {
+    /// connect a source to the sink (returns an error if unsuccessful)
+    fn pipe(&mut self, input: Rc<dyn Source<T>>) -> Result<(), &'static str>;
+    /// sever input connection to the sink
+    fn unpipe(&mut self);
+    /// return a reference to the input source
+    fn get_input(&self) -> Option<Rc<dyn Source<T>>>;
+    /// consume the input stream until it ends (returns an error if the sink fails)
+    fn drain(&self) -> LocalBoxFuture<'static, Result<(), &'static str>>;
+}
+
+/// implement the input connection methods of a Pipe or Sink for a struct holding its input
+/// source in an `input: Option<Rc<dyn Source<_>>>` field
+macro_rules! input_connection {
+    ($in:ty) => {
+        fn pipe(&mut self, input: Rc<dyn Source<$in>>) -> Result<(), &'static str> {
+            self.input = Some(input);
+            Ok(())
+        }
+        fn unpipe(&mut self) {
+            self.input = None;
+        }
+        fn get_input(&self) -> Option<Rc<dyn Source<$in>>> {
+            self.input.clone()
+        }
+    };
+}
+
 /// data_bucket
 /// Sub module holding the definitions of the data model for the library
 pub mod data_bucket;
+
+/// sources
+/// Sub module holding the initial elements of pipelines
+pub mod sources;
+
+/// sinks
+/// Sub module holding the terminal elements of pipelines
+pub mod sinks;
+
+/// memory
+/// Sub module for reusable allocations
+pub mod memory;
+
+/// ops
+/// Sub module for numeric operations on data streams
+pub mod ops;
+
+/// analysis
+/// Sub module for statistical analyses of data streams
+pub mod analysis;
+
+/// pipes
+/// Sub module holding general purpose pipes
+pub mod pipes;
+
+/// text
+/// Sub module for structuring text streams
+pub mod text;
+
+/// event
+/// Sub module for event time and watermarks
+pub mod event;
+
+/// window
+/// Sub module for event time windows
+pub mod window;
+
+/// ack
+/// Sub module for acknowledging processed items to their source
+pub mod ack;
+
+/// checkpoint
+/// Sub module for checkpoint barriers and their coordination
+pub mod checkpoint;
+
+/// state
+/// Sub module for the keyed state of stateful pipes
+pub mod state;
+
+/// runtime
+/// Sub module for running pipelines on threads and runtimes
+pub mod runtime;
+
+/// metrics
+/// Sub module for measurements reported by pipelines
+pub mod metrics;
+
+/// io
+/// Sub module for exchanging streams with other services
+pub mod io;
+
+/// http
+/// Sub module of a minimal HTTP client
+mod http;
+
+/// pipeline
+/// Sub module for assembling and running pipelines
+pub mod pipeline;
+
+/// testing
+/// Sub module of helpers for testing custom pipes
+pub mod testing;
+
+/// control
+/// Sub module of an HTTP API operating running pipelines
+#[cfg(feature = "control")]
+pub mod control;
+
+/// config
+/// Sub module for describing pipelines in YAML or TOML documents
+#[cfg(feature = "config")]
+pub mod config;
+
+/// plugin
+/// Sub module loading pipes from separately compiled libraries
+#[cfg(feature = "plugin")]
+pub mod plugin;
+
+/// mpi
+/// Sub module exchanging blobs between the ranks of MPI style jobs through a communicator
+/// abstraction
+#[cfg(feature = "mpi")]
+pub mod mpi;
+
+/// wasm
+/// Sub module adapting browser streams into sources
+#[cfg(all(feature = "wasm", target_arch = "wasm32"))]
+pub mod wasm;
+
+/// ffi
+/// Sub module exposing a C interface
+#[cfg(feature = "capi")]
+pub mod ffi;
+
+/// python
+/// Sub module exposing python bindings
+#[cfg(feature = "python")]
+pub mod python;
@@ -0,0 +1,289 @@
+//! memory
+//!
+//! Reusable allocations for high throughput pipelines and accounting of the memory they
+//! hold
+
+use std::collections::HashMap;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex};
+
+struct PoolInner<T> {
+    free: Mutex<Vec<Vec<T>>>,
+    buffer_capacity: usize,
+    max_pooled: usize,
+}
+
+/// BufferPool
+/// A thread safe pool of vectors that can be borrowed and handed back instead of being
+/// reallocated for every batch (clones share the same pool)
+pub struct BufferPool<T> {
+    inner: Arc<PoolInner<T>>,
+}
+
+/// BytePool
+/// A pool of byte buffers
+pub type BytePool = BufferPool<u8>;
+
+impl<T> BufferPool<T> {
+    /// constructor, new buffers are allocated with `buffer_capacity` elements and at most
+    /// `max_pooled` idle buffers are retained
+    pub fn new(buffer_capacity: usize, max_pooled: usize) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                free: Mutex::new(Vec::new()),
+                buffer_capacity,
+                max_pooled,
+            }),
+        }
+    }
+    /// borrow an empty buffer from the pool (allocating one if none are idle)
+    pub fn acquire(&self) -> PooledBuffer<T> {
+        let buffer = self
+            .inner
+            .free
+            .lock()
+            .unwrap()
+            .pop()
+            .unwrap_or_else(|| Vec::with_capacity(self.inner.buffer_capacity));
+        PooledBuffer {
+            buffer: Some(buffer),
+            pool: self.inner.clone(),
+        }
+    }
+    /// hand a vector over to the pool so it can be reused
+    pub fn recycle(&self, buffer: Vec<T>) {
+        self.inner.recycle(buffer);
+    }
+    /// number of idle buffers currently held by the pool
+    pub fn idle(&self) -> usize {
+        self.inner.free.lock().unwrap().len()
+    }
+}
+
+impl<T> PoolInner<T> {
+    fn recycle(&self, mut buffer: Vec<T>) {
+        buffer.clear();
+        let mut free = self.free.lock().unwrap();
+        if free.len() < self.max_pooled {
+            free.push(buffer);
+        }
+    }
+}
+
+impl<T> Clone for BufferPool<T> {
+    fn clone(&self) -> Self {
+        Self {
+            inner: self.inner.clone(),
+        }
+    }
+}
+
+/// PooledBuffer
+/// A vector borrowed from a BufferPool, returned to the pool when dropped
+pub struct PooledBuffer<T> {
+    buffer: Option<Vec<T>>,
+    pool: Arc<PoolInner<T>>,
+}
+
+impl<T> PooledBuffer<T> {
+    /// keep the vector instead of returning it to the pool
+    pub fn into_inner(mut self) -> Vec<T> {
+        self.buffer.take().unwrap()
+    }
+}
+
+impl<T> Deref for PooledBuffer<T> {
+    type Target = Vec<T>;
+    fn deref(&self) -> &Vec<T> {
+        self.buffer.as_ref().unwrap()
+    }
+}
+
+impl<T> DerefMut for PooledBuffer<T> {
+    fn deref_mut(&mut self) -> &mut Vec<T> {
+        self.buffer.as_mut().unwrap()
+    }
+}
+
+impl<T> Drop for PooledBuffer<T> {
+    fn drop(&mut self) {
+        if let Some(buffer) = self.buffer.take() {
+            self.pool.recycle(buffer);
+        }
+    }
+}
+
+/// StageMemory
+/// The bytes held by the buffers of a stage
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StageMemory {
+    /// bytes currently reserved
+    pub live: usize,
+    /// highest number of bytes reserved at once
+    pub peak: usize,
+}
+
+impl StageMemory {
+    fn reserve(&mut self, bytes: usize) {
+        self.live += bytes;
+        self.peak = self.peak.max(self.live);
+    }
+}
+
+#[derive(Default)]
+struct TrackerState {
+    total: StageMemory,
+    stages: HashMap<String, StageMemory>,
+}
+
+/// MemoryTracker
+/// A thread safe account of the bytes held by the buffers of every stage of a pipeline
+/// (clones share the same account)
+#[derive(Clone, Default)]
+pub struct MemoryTracker {
+    state: Arc<Mutex<TrackerState>>,
+}
+
+impl MemoryTracker {
+    /// constructor of an empty account
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// account for bytes held by a stage until the returned reservation is dropped
+    pub fn reserve(&self, stage: &str, bytes: usize) -> MemoryReservation {
+        let mut state = self.state.lock().unwrap();
+        state.total.reserve(bytes);
+        state
+            .stages
+            .entry(stage.to_string())
+            .or_default()
+            .reserve(bytes);
+        MemoryReservation {
+            tracker: self.clone(),
+            stage: stage.to_string(),
+            bytes,
+        }
+    }
+    /// bytes currently held by every stage
+    pub fn live(&self) -> usize {
+        self.state.lock().unwrap().total.live
+    }
+    /// highest number of bytes held at once by every stage together
+    pub fn peak(&self) -> usize {
+        self.state.lock().unwrap().total.peak
+    }
+    /// bytes held by a stage (zero for unknown stages)
+    pub fn stage(&self, stage: &str) -> StageMemory {
+        let state = self.state.lock().unwrap();
+        state.stages.get(stage).copied().unwrap_or_default()
+    }
+    /// bytes held by every stage that reserved some, sorted by stage name
+    pub fn stages(&self) -> Vec<(String, StageMemory)> {
+        let state = self.state.lock().unwrap();
+        let mut stages: Vec<(String, StageMemory)> = state
+            .stages
+            .iter()
+            .map(|(name, memory)| (name.clone(), *memory))
+            .collect();
+        stages.sort_by(|a, b| a.0.cmp(&b.0));
+        stages
+    }
+}
+
+/// MemoryReservation
+/// Bytes accounted for in a MemoryTracker, released when dropped
+pub struct MemoryReservation {
+    tracker: MemoryTracker,
+    stage: String,
+    bytes: usize,
+}
+
+impl MemoryReservation {
+    /// number of reserved bytes
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
+
+impl Drop for MemoryReservation {
+    fn drop(&mut self) {
+        let mut state = self.tracker.state.lock().unwrap();
+        state.total.live -= self.bytes;
+        if let Some(stage) = state.stages.get_mut(&self.stage) {
+            stage.live -= self.bytes;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reuse_buffer() {
+        let pool: BufferPool<f64> = BufferPool::new(16, 4);
+        let mut buffer = pool.acquire();
+        buffer.extend([1.0, 2.0]);
+        let address = buffer.as_ptr();
+        drop(buffer);
+        assert_eq!(pool.idle(), 1, "Buffer not returned to pool");
+        let buffer = pool.acquire();
+        assert!(buffer.is_empty(), "Reused buffer was not cleared");
+        assert_eq!(buffer.as_ptr(), address, "Buffer was reallocated");
+        assert!(buffer.capacity() >= 16);
+    }
+
+    #[test]
+    fn test_max_pooled() {
+        let pool: BytePool = BufferPool::new(8, 1);
+        let first = pool.acquire();
+        let second = pool.acquire();
+        drop(first);
+        drop(second);
+        assert_eq!(pool.idle(), 1, "Pool retained too many buffers");
+        let kept = pool.acquire().into_inner();
+        assert_eq!(pool.idle(), 0, "Detached buffer returned to pool");
+        pool.recycle(kept);
+        assert_eq!(pool.idle(), 1);
+    }
+
+    #[test]
+    fn test_shared_across_threads() {
+        let pool: BufferPool<u32> = BufferPool::new(4, 8);
+        let handles: Vec<_> = (0..4)
+            .map(|idx| {
+                let pool = pool.clone();
+                std::thread::spawn(move || pool.acquire().push(idx))
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(pool.idle() >= 1, "Buffers lost across threads");
+    }
+
+    #[test]
+    fn test_memory_tracker() {
+        let tracker = MemoryTracker::new();
+        let first = tracker.reserve("decode", 100);
+        let second = tracker.clone().reserve("decode", 50);
+        let third = tracker.reserve("encode", 25);
+        assert_eq!((tracker.live(), tracker.peak()), (175, 175));
+        drop(first);
+        drop(third);
+        assert_eq!(tracker.live(), 50);
+        assert_eq!(
+            tracker.stage("decode"),
+            StageMemory {
+                live: 50,
+                peak: 150
+            }
+        );
+        let _fourth = tracker.reserve("encode", 10);
+        assert_eq!(tracker.peak(), 175);
+        assert_eq!(second.bytes(), 50);
+        let names: Vec<String> = tracker.stages().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["decode", "encode"]);
+        assert_eq!(tracker.stage("unknown"), StageMemory::default());
+    }
+}
@@ -0,0 +1,228 @@
+//! metrics
+//!
+//! A registry of named and labelled measurements reported by the elements of pipelines
+//!
+//! metrics are identified by a name and a set of labels (such as the name of the pipe
+//! reporting them), the registry is thread safe so stages running on different threads
+//! report to the same registry
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, OnceLock};
+
+/// histogram
+/// Sub module for high dynamic range histograms
+pub mod histogram;
+
+/// latency
+/// Sub module for measuring the latency of items across stages
+pub mod latency;
+
+/// otel
+/// Sub module for exporting metrics and spans to OpenTelemetry collectors
+#[cfg(feature = "otel")]
+pub mod otel;
+
+/// prometheus
+/// Sub module for exporting metrics in the Prometheus text format
+#[cfg(feature = "prometheus")]
+pub mod prometheus;
+
+/// throughput
+/// Sub module for measuring item and byte rates
+pub mod throughput;
+
+/// trace
+/// Sub module for recording spans of pipeline stages
+pub mod trace;
+
+pub use histogram::HdrHistogram;
+pub use latency::{LatencyPipe, LatencySink, StampPipe, Stamped};
+pub use throughput::{ThroughputPipe, ThroughputReport};
+pub use trace::{Span, SpanRecord, SpanRecorder, TracePipe};
+
+/// MetricKey
+/// The name and labels identifying a metric
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct MetricKey {
+    pub name: String,
+    /// (name, value) pairs sorted by name
+    pub labels: Vec<(String, String)>,
+}
+
+impl MetricKey {
+    /// constructor sorting the labels
+    pub fn new(name: &str, labels: &[(&str, &str)]) -> Self {
+        let mut labels: Vec<(String, String)> = labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        labels.sort();
+        Self {
+            name: name.to_string(),
+            labels,
+        }
+    }
+    /// the value of a label
+    pub fn label(&self, name: &str) -> Option<&str> {
+        self.labels
+            .iter()
+            .find(|(label, _)| label == name)
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// MetricValue
+/// The current value of a metric
+#[derive(Clone, Debug, PartialEq)]
+pub enum MetricValue {
+    /// a monotonically increasing count
+    Counter(u64),
+    /// a value that can go up and down
+    Gauge(f64),
+    /// a distribution of values
+    Histogram(HdrHistogram),
+}
+
+/// significant digits of the histograms created by the registry
+pub const HISTOGRAM_DIGITS: u32 = 3;
+
+/// upper bounds of the histogram buckets handed to exporters (powers of ten up to a trillion)
+pub const DEFAULT_BUCKETS: [u64; 13] = [
+    1,
+    10,
+    100,
+    1_000,
+    10_000,
+    100_000,
+    1_000_000,
+    10_000_000,
+    100_000_000,
+    1_000_000_000,
+    10_000_000_000,
+    100_000_000_000,
+    1_000_000_000_000,
+];
+
+/// MetricsRegistry
+/// A thread safe store of metrics (clones share the same store)
+#[derive(Clone, Default)]
+pub struct MetricsRegistry {
+    metrics: Arc<Mutex<BTreeMap<MetricKey, MetricValue>>>,
+}
+
+impl MetricsRegistry {
+    /// constructor of an empty registry
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// the registry shared by the whole process
+    pub fn global() -> MetricsRegistry {
+        static GLOBAL: OnceLock<MetricsRegistry> = OnceLock::new();
+        GLOBAL.get_or_init(MetricsRegistry::new).clone()
+    }
+    /// add to a counter, created at zero (a gauge or other metric of the same key is
+    /// replaced)
+    pub fn increment(&self, name: &str, labels: &[(&str, &str)], by: u64) {
+        let mut metrics = self.metrics.lock().unwrap();
+        let value = metrics
+            .entry(MetricKey::new(name, labels))
+            .or_insert(MetricValue::Counter(0));
+        match value {
+            MetricValue::Counter(count) => *count += by,
+            other => *other = MetricValue::Counter(by),
+        }
+    }
+    /// set a gauge
+    pub fn set_gauge(&self, name: &str, labels: &[(&str, &str)], value: f64) {
+        let mut metrics = self.metrics.lock().unwrap();
+        metrics.insert(MetricKey::new(name, labels), MetricValue::Gauge(value));
+    }
+    /// count a value in a histogram, created keeping `HISTOGRAM_DIGITS` significant digits
+    /// (a counter or other metric of the same key is replaced)
+    pub fn record(&self, name: &str, labels: &[(&str, &str)], value: u64) {
+        let mut metrics = self.metrics.lock().unwrap();
+        let entry = metrics
+            .entry(MetricKey::new(name, labels))
+            .or_insert_with(|| {
+                MetricValue::Histogram(HdrHistogram::new(HISTOGRAM_DIGITS).unwrap())
+            });
+        if !matches!(entry, MetricValue::Histogram(_)) {
+            *entry = MetricValue::Histogram(HdrHistogram::new(HISTOGRAM_DIGITS).unwrap());
+        }
+        if let MetricValue::Histogram(histogram) = entry {
+            histogram.record(value);
+        }
+    }
+    /// the value of a metric
+    pub fn get(&self, name: &str, labels: &[(&str, &str)]) -> Option<MetricValue> {
+        let metrics = self.metrics.lock().unwrap();
+        metrics.get(&MetricKey::new(name, labels)).cloned()
+    }
+    /// the value of a counter
+    pub fn counter(&self, name: &str, labels: &[(&str, &str)]) -> Option<u64> {
+        match self.get(name, labels)? {
+            MetricValue::Counter(count) => Some(count),
+            _ => None,
+        }
+    }
+    /// the value of a gauge
+    pub fn gauge(&self, name: &str, labels: &[(&str, &str)]) -> Option<f64> {
+        match self.get(name, labels)? {
+            MetricValue::Gauge(value) => Some(value),
+            _ => None,
+        }
+    }
+    /// a copy of a histogram
+    pub fn histogram(&self, name: &str, labels: &[(&str, &str)]) -> Option<HdrHistogram> {
+        match self.get(name, labels)? {
+            MetricValue::Histogram(histogram) => Some(histogram),
+            _ => None,
+        }
+    }
+    /// remove a metric
+    pub fn remove(&self, name: &str, labels: &[(&str, &str)]) -> Option<MetricValue> {
+        let mut metrics = self.metrics.lock().unwrap();
+        metrics.remove(&MetricKey::new(name, labels))
+    }
+    /// every metric sorted by name then labels
+    pub fn snapshot(&self) -> Vec<(MetricKey, MetricValue)> {
+        let metrics = self.metrics.lock().unwrap();
+        metrics
+            .iter()
+            .map(|(key, value)| (key.clone(), value.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_registry() {
+        let registry = MetricsRegistry::new();
+        let shared = registry.clone();
+        registry.increment("items", &[("pipe", "parse"), ("stage", "a")], 2);
+        shared.increment("items", &[("stage", "a"), ("pipe", "parse")], 3);
+        assert_eq!(
+            registry.counter("items", &[("pipe", "parse"), ("stage", "a")]),
+            Some(5)
+        );
+        registry.set_gauge("rate", &[], 1.5);
+        assert_eq!(registry.gauge("rate", &[]), Some(1.5));
+        assert_eq!(registry.counter("rate", &[]), None);
+        let snapshot = registry.snapshot();
+        assert_eq!(snapshot[0].0.name, "items");
+        assert_eq!(snapshot[0].0.label("stage"), Some("a"));
+        assert_eq!(registry.remove("rate", &[]), Some(MetricValue::Gauge(1.5)));
+        assert_eq!(registry.snapshot().len(), 1);
+        registry.record("latency", &[], 10);
+        registry.record("latency", &[], 30);
+        let histogram = registry.histogram("latency", &[]).unwrap();
+        assert_eq!((histogram.count(), histogram.max()), (2, Some(30)));
+        MetricsRegistry::global().increment("test_registry", &[], 1);
+        assert!(MetricsRegistry::global()
+            .counter("test_registry", &[])
+            .is_some());
+    }
+}
@@ -0,0 +1,215 @@
+//! histogram
+//!
+//! High dynamic range histograms of integer values such as latencies in nanoseconds
+//!
+//! values are counted in buckets whose width doubles with every power of two while each
+//! power of two is split into enough sub buckets to keep the given number of significant
+//! decimal digits, so quantiles keep the same relative precision from nanoseconds to hours
+
+/// HdrHistogram
+/// A histogram of u64 values with a bounded relative error
+#[derive(Clone, Debug, PartialEq)]
+pub struct HdrHistogram {
+    significant_digits: u32,
+    sub_bucket_bits: u32,
+    counts: Vec<u64>,
+    count: u64,
+    sum: u128,
+    min: u64,
+    max: u64,
+}
+
+impl HdrHistogram {
+    /// constructor keeping between 1 and 5 significant decimal digits
+    pub fn new(significant_digits: u32) -> Result<Self, &'static str> {
+        if !(1..=5).contains(&significant_digits) {
+            return Err("Significant digits must be between 1 and 5");
+        }
+        let sub_buckets = 2 * 10_u64.pow(significant_digits);
+        Ok(Self {
+            significant_digits,
+            sub_bucket_bits: 64 - (sub_buckets - 1).leading_zeros(),
+            counts: Vec::new(),
+            count: 0,
+            sum: 0,
+            min: u64::MAX,
+            max: 0,
+        })
+    }
+    /// number of significant decimal digits kept
+    pub fn significant_digits(&self) -> u32 {
+        self.significant_digits
+    }
+    fn index_of(&self, value: u64) -> usize {
+        let sub_buckets = 1_u64 << self.sub_bucket_bits;
+        if value < sub_buckets {
+            return value as usize;
+        }
+        let shift = 64 - value.leading_zeros() - self.sub_bucket_bits;
+        let half = sub_buckets / 2;
+        (sub_buckets + (shift as u64 - 1) * half + ((value >> shift) - half)) as usize
+    }
+    /// the (lowest, highest) values counted by a bucket
+    fn range_of(&self, index: usize) -> (u64, u64) {
+        let sub_buckets = 1_usize << self.sub_bucket_bits;
+        if index < sub_buckets {
+            return (index as u64, index as u64);
+        }
+        let half = sub_buckets / 2;
+        let shift = (index - sub_buckets) / half + 1;
+        let sub = ((index - sub_buckets) % half + half) as u64;
+        let lowest = sub << shift;
+        (lowest, lowest + ((1_u64 << shift) - 1))
+    }
+    /// count a value
+    pub fn record(&mut self, value: u64) {
+        self.record_n(value, 1);
+    }
+    /// count a value several times
+    pub fn record_n(&mut self, value: u64, times: u64) {
+        if times == 0 {
+            return;
+        }
+        let index = self.index_of(value);
+        if index >= self.counts.len() {
+            self.counts.resize(index + 1, 0);
+        }
+        self.counts[index] += times;
+        self.count += times;
+        self.sum += value as u128 * times as u128;
+        self.min = self.min.min(value);
+        self.max = self.max.max(value);
+    }
+    /// add the counts of another histogram keeping as many digits
+    pub fn merge(&mut self, other: &HdrHistogram) -> Result<(), &'static str> {
+        if other.significant_digits != self.significant_digits {
+            return Err("Histograms keep different significant digits");
+        }
+        if other.counts.len() > self.counts.len() {
+            self.counts.resize(other.counts.len(), 0);
+        }
+        for (count, other) in self.counts.iter_mut().zip(&other.counts) {
+            *count += other;
+        }
+        self.count += other.count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        Ok(())
+    }
+    /// forget every value
+    pub fn reset(&mut self) {
+        self.counts.clear();
+        self.count = 0;
+        self.sum = 0;
+        self.min = u64::MAX;
+        self.max = 0;
+    }
+    /// number of values counted
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+    /// exact sum of the values counted
+    pub fn sum(&self) -> u128 {
+        self.sum
+    }
+    /// smallest value counted
+    pub fn min(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.min)
+    }
+    /// largest value counted
+    pub fn max(&self) -> Option<u64> {
+        (self.count > 0).then_some(self.max)
+    }
+    /// exact mean of the values counted
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.sum as f64 / self.count as f64)
+    }
+    /// the value below which a fraction of the values fall (within the precision of the
+    /// histogram, None when empty or the quantile is outside [0, 1])
+    pub fn value_at_quantile(&self, quantile: f64) -> Option<u64> {
+        if self.count == 0 || !(0.0..=1.0).contains(&quantile) {
+            return None;
+        }
+        let rank = ((quantile * self.count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (index, count) in self.counts.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                return Some(self.range_of(index).1.clamp(self.min, self.max));
+            }
+        }
+        Some(self.max)
+    }
+    /// number of values counted at or below a value (within the precision of the histogram)
+    pub fn count_at_or_below(&self, value: u64) -> u64 {
+        if value >= self.max {
+            return self.count;
+        }
+        self.buckets()
+            .take_while(|(highest, _)| *highest <= value)
+            .map(|(_, count)| count)
+            .sum()
+    }
+    /// (highest value, count) of every non empty bucket in increasing order
+    pub fn buckets(&self) -> impl Iterator<Item = (u64, u64)> + '_ {
+        self.counts
+            .iter()
+            .enumerate()
+            .filter(|(_, count)| **count > 0)
+            .map(|(index, count)| (self.range_of(index).1, *count))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_quantiles_within_precision() {
+        let mut histogram = HdrHistogram::new(3).unwrap();
+        for value in 1..=100_000_u64 {
+            histogram.record(value * 1000);
+        }
+        assert_eq!(histogram.count(), 100_000);
+        assert_eq!(histogram.min(), Some(1000));
+        assert_eq!(histogram.max(), Some(100_000_000));
+        for (quantile, expected) in [
+            (0.5, 50_000_000.0),
+            (0.99, 99_000_000.0),
+            (0.001, 100_000.0),
+        ] {
+            let value = histogram.value_at_quantile(quantile).unwrap() as f64;
+            assert!(
+                (value - expected).abs() / expected < 1e-3,
+                "{} at {}",
+                value,
+                quantile
+            );
+        }
+        assert_eq!(histogram.value_at_quantile(1.0), Some(100_000_000));
+        assert_eq!(histogram.value_at_quantile(1.5), None);
+        assert!((histogram.mean().unwrap() - 50_000_500.0).abs() < 1e-6);
+        let total: u64 = histogram.buckets().map(|(_, count)| count).sum();
+        assert_eq!(total, 100_000);
+        assert_eq!(histogram.count_at_or_below(999), 0);
+        let below = histogram.count_at_or_below(10_000_000) as f64;
+        assert!((below - 10_000.0).abs() / 10_000.0 < 1e-3);
+        assert_eq!(histogram.count_at_or_below(u64::MAX), 100_000);
+    }
+
+    #[test]
+    fn test_merge_and_reset() {
+        let mut first = HdrHistogram::new(2).unwrap();
+        first.record_n(5, 3);
+        let mut second = HdrHistogram::new(2).unwrap();
+        second.record(u64::MAX);
+        first.merge(&second).unwrap();
+        assert_eq!((first.count(), first.max()), (4, Some(u64::MAX)));
+        assert_eq!(first.value_at_quantile(0.5), Some(5));
+        assert!(first.merge(&HdrHistogram::new(3).unwrap()).is_err());
+        first.reset();
+        assert_eq!((first.count(), first.min()), (0, None));
+        assert!(HdrHistogram::new(0).is_err());
+    }
+}
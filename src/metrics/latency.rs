@@ -0,0 +1,210 @@
+//! latency
+//!
+//! Measuring how long items take to flow through the stages of a pipeline
+//!
+//! items are stamped with the time they left their source, every latency pipe they go
+//! through records the time elapsed since the previous checkpoint (the stage latency) and
+//! since the stamp (the end to end latency) in nanoseconds to the histograms of a metrics
+//! registry, labelled with the name of the stage
+
+use super::MetricsRegistry;
+use crate::{Pipe, Sink, Source};
+use futures::future::LocalBoxFuture;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// name of the histograms of the latencies since the previous checkpoint
+pub const STAGE_LATENCY: &str = "stage_latency_nanoseconds";
+
+/// name of the histograms of the latencies since items were stamped
+pub const END_TO_END_LATENCY: &str = "end_to_end_latency_nanoseconds";
+
+/// Stamped
+/// An item along with the times it was stamped and last measured
+#[derive(Clone, Debug)]
+pub struct Stamped<T> {
+    pub item: T,
+    origin: Instant,
+    checkpoint: Instant,
+}
+
+impl<T> Stamped<T> {
+    /// stamp an item with the current time
+    pub fn new(item: T) -> Self {
+        let now = Instant::now();
+        Self {
+            item,
+            origin: now,
+            checkpoint: now,
+        }
+    }
+    /// time elapsed since the item was stamped
+    pub fn age(&self) -> Duration {
+        self.origin.elapsed()
+    }
+    /// the item without its stamp
+    pub fn into_inner(self) -> T {
+        self.item
+    }
+    /// record the latencies of the item at a stage and move its checkpoint
+    fn measure(&mut self, registry: &MetricsRegistry, stage: &str) {
+        let now = Instant::now();
+        let labels = [("stage", stage)];
+        let nanoseconds = |since: Instant| now.duration_since(since).as_nanos() as u64;
+        registry.record(STAGE_LATENCY, &labels, nanoseconds(self.checkpoint));
+        registry.record(END_TO_END_LATENCY, &labels, nanoseconds(self.origin));
+        self.checkpoint = now;
+    }
+}
+
+/// StampPipe
+/// A pipe stamping the items of its input, usually placed right after a source
+pub struct StampPipe<T> {
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T> StampPipe<T> {
+    /// constructor
+    pub fn new() -> Self {
+        Self { input: None }
+    }
+}
+
+impl<T> Default for StampPipe<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: 'static> Source<Stamped<T>> for StampPipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = Stamped<T>>> {
+        match &self.input {
+            Some(input) => Box::new(Pin::from(input.stream()).map(Stamped::new)),
+            None => Box::new(stream::empty()),
+        }
+    }
+}
+
+impl<T: 'static> Pipe<T, Stamped<T>> for StampPipe<T> {
+    input_connection!(T);
+}
+
+/// LatencyPipe
+/// A pipe recording the latencies of stamped items at a stage
+pub struct LatencyPipe<T> {
+    stage: String,
+    registry: MetricsRegistry,
+    input: Option<Rc<dyn Source<Stamped<T>>>>,
+}
+
+impl<T> LatencyPipe<T> {
+    /// constructor recording under a stage name to a registry
+    pub fn new(stage: &str, registry: &MetricsRegistry) -> Self {
+        Self {
+            stage: stage.to_string(),
+            registry: registry.clone(),
+            input: None,
+        }
+    }
+}
+
+impl<T: 'static> Source<Stamped<T>> for LatencyPipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = Stamped<T>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let (stage, registry) = (self.stage.clone(), self.registry.clone());
+        Box::new(input.map(move |mut item| {
+            item.measure(&registry, &stage);
+            item
+        }))
+    }
+}
+
+impl<T: 'static> Pipe<Stamped<T>, Stamped<T>> for LatencyPipe<T> {
+    input_connection!(Stamped<T>);
+}
+
+/// LatencySink
+/// A sink recording the latencies of the stamped items reaching the end of a pipeline
+pub struct LatencySink<T> {
+    stage: String,
+    registry: MetricsRegistry,
+    input: Option<Rc<dyn Source<Stamped<T>>>>,
+}
+
+impl<T> LatencySink<T> {
+    /// constructor recording under a stage name to a registry
+    pub fn new(stage: &str, registry: &MetricsRegistry) -> Self {
+        Self {
+            stage: stage.to_string(),
+            registry: registry.clone(),
+            input: None,
+        }
+    }
+}
+
+impl<T: 'static> Sink<Stamped<T>> for LatencySink<T> {
+    input_connection!(Stamped<T>);
+    fn drain(&self) -> LocalBoxFuture<'static, Result<(), &'static str>> {
+        let input = self.input.clone();
+        let (stage, registry) = (self.stage.clone(), self.registry.clone());
+        Box::pin(async move {
+            let mut stream = Pin::from(input.ok_or("Sink has no input")?.stream());
+            while let Some(mut item) = stream.next().await {
+                item.measure(&registry, &stage);
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipes::InspectPipe;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_stage_and_end_to_end_latencies() {
+        let registry = MetricsRegistry::new();
+        let mut stamp = StampPipe::new();
+        stamp.pipe(Rc::new(MockSource::new().items(0..20))).unwrap();
+        let mut slow = InspectPipe::new(|_: &Stamped<i32>| {
+            std::thread::sleep(Duration::from_micros(200));
+        });
+        slow.pipe(Rc::new(stamp)).unwrap();
+        let mut decode = LatencyPipe::new("decode", &registry);
+        decode.pipe(Rc::new(slow)).unwrap();
+        let mut sink = LatencySink::new("sink", &registry);
+        sink.pipe(Rc::new(decode)).unwrap();
+        block_on(sink.drain()).unwrap();
+        let decode = registry
+            .histogram(STAGE_LATENCY, &[("stage", "decode")])
+            .unwrap();
+        assert_eq!(decode.count(), 20);
+        assert!(decode.min().unwrap() >= 200_000, "Stage latency too low");
+        let sink_stage = registry
+            .histogram(STAGE_LATENCY, &[("stage", "sink")])
+            .unwrap();
+        let end_to_end = registry
+            .histogram(END_TO_END_LATENCY, &[("stage", "sink")])
+            .unwrap();
+        assert!(end_to_end.value_at_quantile(0.5) > sink_stage.value_at_quantile(0.5));
+        assert!(end_to_end.min().unwrap() >= decode.min().unwrap());
+    }
+
+    #[test]
+    fn test_stamped_item() {
+        let stamped = Stamped::new("reading");
+        assert!(stamped.age() < Duration::from_secs(60));
+        assert_eq!(stamped.into_inner(), "reading");
+        let unconnected = LatencySink::<u8>::new("sink", &MetricsRegistry::new());
+        assert_eq!(block_on(unconnected.drain()), Err("Sink has no input"));
+    }
+}
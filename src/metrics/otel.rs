@@ -0,0 +1,269 @@
+//! otel
+//!
+//! Exporting the metrics registry and the recorded spans to an OpenTelemetry collector
+//!
+//! metrics and spans are encoded as OTLP JSON and posted to the `/v1/metrics` and
+//! `/v1/traces` endpoints of a collector over plain HTTP (the OTLP/HTTP protocol), the
+//! metrics are cumulative and labels become data point attributes
+
+use super::trace::{SpanRecord, SpanRecorder};
+use super::{MetricKey, MetricValue, MetricsRegistry, DEFAULT_BUCKETS};
+use crate::http::{self, Url};
+use crate::sinks::log::json_string;
+use std::time::{Duration, SystemTime};
+
+const CUMULATIVE: u32 = 2;
+const INTERNAL: u32 = 1;
+const SCOPE: &str = "\"scope\":{\"name\":\"bitvortex\"}";
+
+fn nanoseconds(time: SystemTime) -> String {
+    let since = time.duration_since(SystemTime::UNIX_EPOCH);
+    since.map(|since| since.as_nanos()).unwrap_or(0).to_string()
+}
+
+fn double(value: f64) -> String {
+    if value.is_nan() {
+        "\"NaN\"".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 {
+            "\"Infinity\""
+        } else {
+            "\"-Infinity\""
+        }
+        .to_string()
+    } else {
+        format!("{:?}", value)
+    }
+}
+
+fn attribute(key: &str, value: &str) -> String {
+    format!(
+        "{{\"key\":{},\"value\":{{\"stringValue\":{}}}}}",
+        json_string(key),
+        json_string(value)
+    )
+}
+
+fn resource(service: &str) -> String {
+    format!(
+        "\"resource\":{{\"attributes\":[{}]}}",
+        attribute("service.name", service)
+    )
+}
+
+fn data_point(key: &MetricKey, time: &str, value: String) -> String {
+    let attributes: Vec<String> = key
+        .labels
+        .iter()
+        .map(|(name, value)| attribute(name, value))
+        .collect();
+    format!(
+        "{{\"attributes\":[{}],\"timeUnixNano\":\"{}\",{}}}",
+        attributes.join(","),
+        time,
+        value
+    )
+}
+
+fn histogram_value(histogram: &super::HdrHistogram) -> String {
+    let mut below = 0;
+    let mut counts: Vec<String> = DEFAULT_BUCKETS
+        .iter()
+        .map(|bound| {
+            let at_or_below = histogram.count_at_or_below(*bound);
+            let count = at_or_below - below;
+            below = at_or_below;
+            format!("\"{}\"", count)
+        })
+        .collect();
+    counts.push(format!("\"{}\"", histogram.count() - below));
+    let bounds: Vec<String> = DEFAULT_BUCKETS.iter().map(u64::to_string).collect();
+    let mut value = format!(
+        "\"count\":\"{}\",\"sum\":{},\"bucketCounts\":[{}],\"explicitBounds\":[{}]",
+        histogram.count(),
+        histogram.sum(),
+        counts.join(","),
+        bounds.join(",")
+    );
+    if let (Some(min), Some(max)) = (histogram.min(), histogram.max()) {
+        value.push_str(&format!(",\"min\":{},\"max\":{}", min, max));
+    }
+    value
+}
+
+/// the metrics of a registry as an OTLP JSON export request
+pub fn metrics_json(registry: &MetricsRegistry, service: &str) -> String {
+    let time = nanoseconds(SystemTime::now());
+    let mut metrics: Vec<String> = Vec::new();
+    let mut points: Vec<String> = Vec::new();
+    let snapshot = registry.snapshot();
+    for (index, (key, value)) in snapshot.iter().enumerate() {
+        points.push(match value {
+            MetricValue::Counter(count) => {
+                data_point(key, &time, format!("\"asInt\":\"{}\"", count))
+            }
+            MetricValue::Gauge(gauge) => {
+                data_point(key, &time, format!("\"asDouble\":{}", double(*gauge)))
+            }
+            MetricValue::Histogram(histogram) => data_point(key, &time, histogram_value(histogram)),
+        });
+        // the snapshot is sorted by name, a metric ends where the next name starts
+        let last = snapshot
+            .get(index + 1)
+            .is_none_or(|(next, _)| next.name != key.name);
+        if last {
+            let points = std::mem::take(&mut points).join(",");
+            let data = match value {
+                MetricValue::Counter(_) => format!(
+                    "\"sum\":{{\"dataPoints\":[{}],\"aggregationTemporality\":{},\"isMonotonic\":true}}",
+                    points, CUMULATIVE
+                ),
+                MetricValue::Gauge(_) => format!("\"gauge\":{{\"dataPoints\":[{}]}}", points),
+                MetricValue::Histogram(_) => format!(
+                    "\"histogram\":{{\"dataPoints\":[{}],\"aggregationTemporality\":{}}}",
+                    points, CUMULATIVE
+                ),
+            };
+            metrics.push(format!("{{\"name\":{},{}}}", json_string(&key.name), data));
+        }
+    }
+    format!(
+        "{{\"resourceMetrics\":[{{{},\"scopeMetrics\":[{{{},\"metrics\":[{}]}}]}}]}}",
+        resource(service),
+        SCOPE,
+        metrics.join(",")
+    )
+}
+
+/// spans as an OTLP JSON export request
+pub fn traces_json(spans: &[SpanRecord], service: &str) -> String {
+    let spans: Vec<String> = spans
+        .iter()
+        .map(|span| {
+            format!(
+                "{{\"traceId\":\"{:032x}\",\"spanId\":\"{:016x}\",\"name\":{},\"kind\":{},\
+                 \"startTimeUnixNano\":\"{}\",\"endTimeUnixNano\":\"{}\",\
+                 \"attributes\":[{{\"key\":\"items\",\"value\":{{\"intValue\":\"{}\"}}}}]}}",
+                span.trace_id,
+                span.span_id,
+                json_string(&span.name),
+                INTERNAL,
+                nanoseconds(span.start),
+                nanoseconds(span.end),
+                span.items
+            )
+        })
+        .collect();
+    format!(
+        "{{\"resourceSpans\":[{{{},\"scopeSpans\":[{{{},\"spans\":[{}]}}]}}]}}",
+        resource(service),
+        SCOPE,
+        spans.join(",")
+    )
+}
+
+/// OtlpExporter
+/// A client posting metrics and spans to an OpenTelemetry collector
+pub struct OtlpExporter {
+    endpoint: Url,
+    service: String,
+    timeout: Duration,
+}
+
+impl OtlpExporter {
+    /// constructor for a collector base URL (such as `http://localhost:4318`) and the
+    /// service name attached to everything exported
+    pub fn new(endpoint: &str, service: &str) -> Result<Self, &'static str> {
+        Ok(Self {
+            endpoint: Url::parse(endpoint)?,
+            service: service.to_string(),
+            timeout: Duration::from_secs(10),
+        })
+    }
+    /// set how long to wait for the collector (10 seconds by default)
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+    fn post(&self, signal: &str, body: String) -> Result<(), &'static str> {
+        let path = format!("{}/v1/{}", self.endpoint.path.trim_end_matches('/'), signal);
+        let headers = [("Content-Type", "application/json")];
+        let url = Url {
+            path,
+            ..self.endpoint.clone()
+        };
+        let response = http::send("POST", &url, &headers, body.as_bytes(), self.timeout)?;
+        if !response.is_success() {
+            return Err("Collector rejected the export");
+        }
+        Ok(())
+    }
+    /// export the current state of every metric of a registry
+    pub fn export_metrics(&self, registry: &MetricsRegistry) -> Result<(), &'static str> {
+        self.post("metrics", metrics_json(registry, &self.service))
+    }
+    /// export and forget the spans recorded so far (lost if the export fails)
+    pub fn export_spans(&self, recorder: &SpanRecorder) -> Result<(), &'static str> {
+        let spans = recorder.take();
+        if spans.is_empty() {
+            return Ok(());
+        }
+        self.post("traces", traces_json(&spans, &self.service))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    #[test]
+    fn test_metrics_json() {
+        let registry = MetricsRegistry::new();
+        registry.increment("items", &[("pipe", "a")], 2);
+        registry.increment("items", &[("pipe", "b")], 3);
+        registry.set_gauge("rate", &[], 0.5);
+        registry.record("latency", &[], 50);
+        let json = metrics_json(&registry, "ingest");
+        assert!(json.starts_with(
+            "{\"resourceMetrics\":[{\"resource\":{\"attributes\":[{\"key\":\"service.name\",\
+             \"value\":{\"stringValue\":\"ingest\"}}]},\"scopeMetrics\":[{\"scope\":\
+             {\"name\":\"bitvortex\"},\"metrics\":[{\"name\":\"items\",\"sum\":{\"dataPoints\":[{"
+        ));
+        assert_eq!(json.matches("\"name\":\"items\"").count(), 1);
+        assert_eq!(json.matches("\"asInt\"").count(), 2);
+        assert!(json.contains("\"asDouble\":0.5"));
+        assert!(json.contains("\"bucketCounts\":[\"0\",\"0\",\"1\","));
+        assert!(json.contains("\"min\":50,\"max\":50"));
+    }
+
+    #[test]
+    fn test_export_spans() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let collector = std::thread::spawn(move || {
+            let (mut connection, _) = listener.accept().unwrap();
+            let mut request = Vec::new();
+            let mut chunk = [0_u8; 4096];
+            while !String::from_utf8_lossy(&request).ends_with("}]}]}]}") {
+                let read = connection.read(&mut chunk).unwrap();
+                request.extend_from_slice(&chunk[..read]);
+            }
+            connection
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 2\r\n\r\n{}")
+                .unwrap();
+            String::from_utf8(request).unwrap()
+        });
+        let recorder = SpanRecorder::new();
+        recorder.start("parse").add_items(3);
+        let exporter = OtlpExporter::new(&format!("http://{}", address), "ingest").unwrap();
+        exporter.export_spans(&recorder).unwrap();
+        let request = collector.join().unwrap();
+        assert!(request.starts_with("POST /v1/traces HTTP/1.1\r\n"));
+        assert!(request.contains(&format!("\"traceId\":\"{:032x}\"", recorder.trace_id())));
+        assert!(request.contains("\"intValue\":\"3\""));
+        assert!(recorder.spans().is_empty());
+        assert_eq!(exporter.export_spans(&recorder), Ok(()));
+    }
+}
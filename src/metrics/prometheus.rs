@@ -0,0 +1,204 @@
+//! prometheus
+//!
+//! Exporting a metrics registry in the Prometheus text format
+//!
+//! counters and gauges are exported as they are, histograms as cumulative buckets along
+//! with their sum and count, labels (such as the name of the reporting pipe) are kept on
+//! every sample. The exporter serves the current state of a registry at `/metrics` over
+//! plain HTTP from a background thread so monitoring can scrape running pipelines
+
+pub use super::DEFAULT_BUCKETS;
+use super::{MetricKey, MetricValue, MetricsRegistry};
+use crate::http;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+
+/// the metrics of a registry in the Prometheus text format with the default buckets
+pub fn encode(registry: &MetricsRegistry) -> String {
+    encode_with_buckets(registry, &DEFAULT_BUCKETS)
+}
+
+/// the metrics of a registry in the Prometheus text format, histograms reporting the
+/// values at or below each of the given increasing bounds
+pub fn encode_with_buckets(registry: &MetricsRegistry, bounds: &[u64]) -> String {
+    let mut text = String::new();
+    let mut previous: Option<String> = None;
+    let mut metrics: Vec<(String, MetricKey, MetricValue)> = registry
+        .snapshot()
+        .into_iter()
+        .map(|(key, value)| (sanitize(&key.name), key, value))
+        .collect();
+    // names sharing a sanitized form must be contiguous to share their type line
+    metrics.sort_by(|a, b| (&a.0, &a.1.labels).cmp(&(&b.0, &b.1.labels)));
+    for (name, key, value) in metrics {
+        if previous.as_ref() != Some(&name) {
+            let kind = match value {
+                MetricValue::Counter(_) => "counter",
+                MetricValue::Gauge(_) => "gauge",
+                MetricValue::Histogram(_) => "histogram",
+            };
+            text.push_str(&format!("# TYPE {} {}\n", name, kind));
+            previous = Some(name.clone());
+        }
+        match value {
+            MetricValue::Counter(count) => {
+                text.push_str(&format!("{}{} {}\n", name, labels(&key, None), count))
+            }
+            MetricValue::Gauge(gauge) => text.push_str(&format!(
+                "{}{} {}\n",
+                name,
+                labels(&key, None),
+                number(gauge)
+            )),
+            MetricValue::Histogram(histogram) => {
+                for bound in bounds {
+                    let le = bound.to_string();
+                    let count = histogram.count_at_or_below(*bound);
+                    let labels = labels(&key, Some(&le));
+                    text.push_str(&format!("{}_bucket{} {}\n", name, labels, count));
+                }
+                let (all, count) = (labels(&key, Some("+Inf")), histogram.count());
+                text.push_str(&format!("{}_bucket{} {}\n", name, all, count));
+                let plain = labels(&key, None);
+                text.push_str(&format!("{}_sum{} {}\n", name, plain, histogram.sum()));
+                text.push_str(&format!("{}_count{} {}\n", name, plain, count));
+            }
+        }
+    }
+    text
+}
+
+fn sanitize(name: &str) -> String {
+    let mut clean: String = name
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '_' | ':' => c,
+            _ => '_',
+        })
+        .collect();
+    if clean.is_empty() || clean.starts_with(|c: char| c.is_ascii_digit()) {
+        clean.insert(0, '_');
+    }
+    clean
+}
+
+fn labels(key: &MetricKey, le: Option<&str>) -> String {
+    let mut pairs: Vec<String> = key
+        .labels
+        .iter()
+        .map(|(name, value)| format!("{}=\"{}\"", sanitize(name), escape(value)))
+        .collect();
+    if let Some(le) = le {
+        pairs.push(format!("le=\"{}\"", le));
+    }
+    if pairs.is_empty() {
+        String::new()
+    } else {
+        format!("{{{}}}", pairs.join(","))
+    }
+}
+
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+fn number(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// PrometheusExporter
+/// A background HTTP endpoint serving the metrics of a registry at `/metrics`, stopped when
+/// dropped
+pub struct PrometheusExporter {
+    server: http::Server,
+}
+
+impl PrometheusExporter {
+    /// start serving a registry on an address (port 0 picks a free port)
+    pub fn bind<A: ToSocketAddrs>(
+        address: A,
+        registry: &MetricsRegistry,
+    ) -> Result<Self, &'static str> {
+        let registry = registry.clone();
+        let server = http::Server::bind(address, "Failure to bind exporter", move |connection| {
+            let _ = respond(connection, &registry);
+        })?;
+        Ok(Self { server })
+    }
+    /// the address the exporter listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+}
+
+fn respond(mut connection: TcpStream, registry: &MetricsRegistry) -> std::io::Result<()> {
+    let (method, path) = http::read_request(&mut connection)?;
+    let (status, body) = match (method.as_str(), path.as_str()) {
+        ("GET", "/metrics") => ("200 OK", encode(registry)),
+        ("GET", _) => ("404 Not Found", String::new()),
+        _ => ("405 Method Not Allowed", String::new()),
+    };
+    http::respond(&mut connection, status, "text/plain; version=0.0.4", &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+
+    #[test]
+    fn test_encode() {
+        let registry = MetricsRegistry::new();
+        registry.increment("pipe_items_total", &[("pipe", "parse")], 3);
+        registry.increment("pipe_items_total", &[("pipe", "say \"hi\"")], 1);
+        registry.set_gauge("pipe.rate", &[], f64::INFINITY);
+        registry.record("latency", &[("stage", "sink")], 5);
+        registry.record("latency", &[("stage", "sink")], 500);
+        let text = encode_with_buckets(&registry, &[10, 1000]);
+        let expected = "# TYPE latency histogram\n\
+            latency_bucket{stage=\"sink\",le=\"10\"} 1\n\
+            latency_bucket{stage=\"sink\",le=\"1000\"} 2\n\
+            latency_bucket{stage=\"sink\",le=\"+Inf\"} 2\n\
+            latency_sum{stage=\"sink\"} 505\n\
+            latency_count{stage=\"sink\"} 2\n\
+            # TYPE pipe_items_total counter\n\
+            pipe_items_total{pipe=\"parse\"} 3\n\
+            pipe_items_total{pipe=\"say \\\"hi\\\"\"} 1\n\
+            # TYPE pipe_rate gauge\n\
+            pipe_rate +Inf\n";
+        assert_eq!(text, expected);
+    }
+
+    fn get(exporter: &PrometheusExporter, path: &str) -> String {
+        let mut connection = TcpStream::connect(exporter.local_addr()).unwrap();
+        write!(
+            connection,
+            "GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n",
+            path
+        )
+        .unwrap();
+        let mut response = String::new();
+        connection.read_to_string(&mut response).unwrap();
+        response
+    }
+
+    #[test]
+    fn test_exporter() {
+        let registry = MetricsRegistry::new();
+        let exporter = PrometheusExporter::bind("127.0.0.1:0", &registry).unwrap();
+        registry.increment("items", &[("pipe", "parse")], 7);
+        let response = get(&exporter, "/metrics");
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"), "{}", response);
+        assert!(response.ends_with("\r\n\r\n# TYPE items counter\nitems{pipe=\"parse\"} 7\n"));
+        assert!(get(&exporter, "/").starts_with("HTTP/1.1 404"));
+        drop(exporter);
+    }
+}
@@ -0,0 +1,248 @@
+//! throughput
+//!
+//! Measuring the rate of items and bytes flowing through a pipeline stage
+//!
+//! rates are measured over consecutive intervals of wall clock time, a report is produced
+//! by the first item arriving after an interval elapsed and a last one covering the rest
+//! of the stream when it ends
+
+use super::MetricsRegistry;
+use crate::data_bucket::footprint::Footprint;
+use crate::window::{SideOutput, SideOutputState};
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// ThroughputReport
+/// The items and bytes that went through a pipe over an interval
+#[derive(Clone, Debug, PartialEq)]
+pub struct ThroughputReport {
+    /// name of the measuring pipe
+    pub pipe: String,
+    /// items over the interval
+    pub items: u64,
+    /// bytes over the interval, if the pipe knows the size of items
+    pub bytes: Option<u64>,
+    /// length of the interval
+    pub elapsed: Duration,
+    /// items since the stream started
+    pub total_items: u64,
+}
+
+impl ThroughputReport {
+    fn rate(&self, count: u64) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 {
+            count as f64 / seconds
+        } else {
+            0.0
+        }
+    }
+    /// items per second over the interval (zero for an empty interval)
+    pub fn items_per_second(&self) -> f64 {
+        self.rate(self.items)
+    }
+    /// bytes per second over the interval
+    pub fn bytes_per_second(&self) -> Option<f64> {
+        self.bytes.map(|bytes| self.rate(bytes))
+    }
+}
+
+type Size<T> = Rc<dyn Fn(&T) -> usize>;
+
+struct Interval {
+    start: Instant,
+    items: u64,
+    bytes: u64,
+    total_items: u64,
+}
+
+/// ThroughputPipe
+/// A pipe passing items through while reporting their rate on its reports output and to a
+/// metrics registry
+///
+/// the registry receives the `pipe_items_total` and `pipe_bytes_total` counters and the
+/// `pipe_items_per_second` and `pipe_bytes_per_second` gauges labelled with the name of
+/// the pipe, the reports output ends with the stream of the pipe and only makes progress
+/// while that stream is polled
+pub struct ThroughputPipe<T> {
+    name: String,
+    interval: Duration,
+    size: Option<Size<T>>,
+    registry: Option<MetricsRegistry>,
+    reports: Rc<SideOutputState<ThroughputReport>>,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T: 'static> ThroughputPipe<T> {
+    /// constructor for a pipe reporting under a name once per interval
+    pub fn new(name: &str, interval: Duration) -> Self {
+        Self {
+            name: name.to_string(),
+            interval,
+            size: None,
+            registry: None,
+            reports: Rc::new(SideOutputState::new()),
+            input: None,
+        }
+    }
+    /// measure bytes with a function giving the size of an item
+    pub fn with_size<F: Fn(&T) -> usize + 'static>(mut self, size: F) -> Self {
+        self.size = Some(Rc::new(size));
+        self
+    }
+    /// report to a metrics registry
+    pub fn with_registry(mut self, registry: &MetricsRegistry) -> Self {
+        self.registry = Some(registry.clone());
+        self
+    }
+    /// the source of the rate reports
+    pub fn reports(&self) -> SideOutput<ThroughputReport> {
+        SideOutput::new(self.reports.clone())
+    }
+}
+
+impl<T: Footprint + 'static> ThroughputPipe<T> {
+    /// measure bytes with the memory footprint of items
+    pub fn measured(self) -> Self {
+        self.with_size(T::footprint)
+    }
+}
+
+struct Reporter {
+    name: String,
+    measures_bytes: bool,
+    registry: Option<MetricsRegistry>,
+    reports: Rc<SideOutputState<ThroughputReport>>,
+}
+
+impl Reporter {
+    fn report(&self, interval: &mut Interval, now: Instant) {
+        let report = ThroughputReport {
+            pipe: self.name.clone(),
+            items: interval.items,
+            bytes: self.measures_bytes.then_some(interval.bytes),
+            elapsed: now.duration_since(interval.start),
+            total_items: interval.total_items,
+        };
+        if let Some(registry) = &self.registry {
+            let labels = [("pipe", self.name.as_str())];
+            registry.increment("pipe_items_total", &labels, report.items);
+            registry.set_gauge("pipe_items_per_second", &labels, report.items_per_second());
+            if let (Some(bytes), Some(rate)) = (report.bytes, report.bytes_per_second()) {
+                registry.increment("pipe_bytes_total", &labels, bytes);
+                registry.set_gauge("pipe_bytes_per_second", &labels, rate);
+            }
+        }
+        self.reports.push(report);
+        interval.start = now;
+        interval.items = 0;
+        interval.bytes = 0;
+    }
+}
+
+impl<T: 'static> Source<T> for ThroughputPipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        self.reports.set_open(true);
+        let reporter = Rc::new(Reporter {
+            name: self.name.clone(),
+            measures_bytes: self.size.is_some(),
+            registry: self.registry.clone(),
+            reports: self.reports.clone(),
+        });
+        let interval = Rc::new(RefCell::new(Interval {
+            start: Instant::now(),
+            items: 0,
+            bytes: 0,
+            total_items: 0,
+        }));
+        let (size, length) = (self.size.clone(), self.interval);
+        let (measure, measured) = (reporter.clone(), interval.clone());
+        let items = input.inspect(move |item| {
+            let mut interval = measured.borrow_mut();
+            interval.items += 1;
+            interval.total_items += 1;
+            if let Some(size) = &size {
+                interval.bytes += size(item) as u64;
+            }
+            let now = Instant::now();
+            if now.duration_since(interval.start) >= length {
+                measure.report(&mut interval, now);
+            }
+        });
+        let close = stream::once(async move {
+            let mut interval = interval.borrow_mut();
+            if interval.items > 0 || interval.total_items == 0 {
+                reporter.report(&mut interval, Instant::now());
+            }
+            reporter.reports.set_open(false);
+        })
+        .filter_map(|_| async { None });
+        Box::new(items.chain(close))
+    }
+}
+
+impl<T: 'static> Pipe<T, T> for ThroughputPipe<T> {
+    input_connection!(T);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::{DataBlob, MetaData};
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn run<T: 'static>(pipe: &ThroughputPipe<T>) -> (Vec<T>, Vec<ThroughputReport>) {
+        let reports = pipe.reports();
+        block_on(async {
+            futures::join!(
+                Pin::from(pipe.stream()).collect::<Vec<_>>(),
+                Pin::from(reports.stream()).collect::<Vec<_>>()
+            )
+        })
+    }
+
+    #[test]
+    fn test_reports_per_interval() {
+        let registry = MetricsRegistry::new();
+        let mut pipe = ThroughputPipe::new("parse", Duration::ZERO)
+            .with_size(|text: &String| text.len())
+            .with_registry(&registry);
+        pipe.pipe(Rc::new(
+            MockSource::new().items(["ab", "cde", "f"].map(str::to_string)),
+        ))
+        .unwrap();
+        let (items, reports) = run(&pipe);
+        assert_eq!(items.len(), 3, "Items not passed through");
+        assert_eq!(reports.len(), 3, "Expected a report per item");
+        assert_eq!(reports[1].bytes, Some(3));
+        assert_eq!(reports[2].total_items, 3);
+        let labels = [("pipe", "parse")];
+        assert_eq!(registry.counter("pipe_items_total", &labels), Some(3));
+        assert_eq!(registry.counter("pipe_bytes_total", &labels), Some(6));
+        assert!(registry.gauge("pipe_items_per_second", &labels).is_some());
+    }
+
+    #[test]
+    fn test_final_report() {
+        let mut pipe = ThroughputPipe::new("blobs", Duration::from_secs(3600)).measured();
+        let blobs = (0..4).map(|_| DataBlob::new(vec![0_u32; 8], MetaData::default()));
+        pipe.pipe(Rc::new(MockSource::new().items(blobs))).unwrap();
+        let (_, reports) = run(&pipe);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].items, 4);
+        assert!(reports[0].bytes.unwrap() > 4 * 32);
+        assert!(reports[0].items_per_second() > 0.0);
+        let empty = ThroughputPipe::<u8>::new("empty", Duration::ZERO);
+        assert!(run(&empty).1.is_empty(), "Unconnected pipe reported");
+    }
+}
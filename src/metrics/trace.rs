@@ -0,0 +1,182 @@
+//! trace
+//!
+//! Recording spans of time spent by the stages of a pipeline
+//!
+//! every recorder stands for one trace, usually one run of a pipeline, the spans it records
+//! belong to that trace so the stages of a run show up together when exported
+
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::collections::hash_map::RandomState;
+use std::hash::{BuildHasher, Hasher};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+fn random_id() -> u64 {
+    let mut hasher = RandomState::new().build_hasher();
+    if let Ok(now) = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+        hasher.write_u128(now.as_nanos());
+    }
+    hasher.finish().max(1)
+}
+
+/// SpanRecord
+/// A finished span
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SpanRecord {
+    pub trace_id: u128,
+    pub span_id: u64,
+    pub name: String,
+    pub start: SystemTime,
+    pub end: SystemTime,
+    /// items that went through the span
+    pub items: u64,
+}
+
+/// SpanRecorder
+/// A thread safe store of the spans of a trace (clones share the same store)
+#[derive(Clone)]
+pub struct SpanRecorder {
+    trace_id: u128,
+    spans: Arc<Mutex<Vec<SpanRecord>>>,
+}
+
+impl SpanRecorder {
+    /// constructor for a new trace
+    pub fn new() -> Self {
+        Self {
+            trace_id: (random_id() as u128) << 64 | random_id() as u128,
+            spans: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+    /// identifier of the trace
+    pub fn trace_id(&self) -> u128 {
+        self.trace_id
+    }
+    /// start a span, recorded when dropped
+    pub fn start(&self, name: &str) -> Span {
+        Span {
+            recorder: self.clone(),
+            name: name.to_string(),
+            start: SystemTime::now(),
+            items: Cell::new(0),
+        }
+    }
+    /// copy of the spans recorded so far
+    pub fn spans(&self) -> Vec<SpanRecord> {
+        self.spans.lock().unwrap().clone()
+    }
+    /// remove and return the spans recorded so far
+    pub fn take(&self) -> Vec<SpanRecord> {
+        std::mem::take(&mut *self.spans.lock().unwrap())
+    }
+}
+
+impl Default for SpanRecorder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Span
+/// A running span, ending when dropped
+pub struct Span {
+    recorder: SpanRecorder,
+    name: String,
+    start: SystemTime,
+    items: Cell<u64>,
+}
+
+impl Span {
+    /// count items that went through the span
+    pub fn add_items(&self, items: u64) {
+        self.items.set(self.items.get() + items);
+    }
+}
+
+impl Drop for Span {
+    fn drop(&mut self) {
+        let record = SpanRecord {
+            trace_id: self.recorder.trace_id,
+            span_id: random_id(),
+            name: std::mem::take(&mut self.name),
+            start: self.start,
+            end: SystemTime::now(),
+            items: self.items.get(),
+        };
+        self.recorder.spans.lock().unwrap().push(record);
+    }
+}
+
+/// TracePipe
+/// A pipe passing items through while recording a span from the start to the end (or the
+/// drop) of every stream
+pub struct TracePipe<T> {
+    name: String,
+    recorder: SpanRecorder,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T> TracePipe<T> {
+    /// constructor recording spans under a name to a recorder
+    pub fn new(name: &str, recorder: &SpanRecorder) -> Self {
+        Self {
+            name: name.to_string(),
+            recorder: recorder.clone(),
+            input: None,
+        }
+    }
+}
+
+impl<T: 'static> Source<T> for TracePipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let span = Rc::new(self.recorder.start(&self.name));
+        let counted = span.clone();
+        let items = input.inspect(move |_| counted.add_items(1));
+        let close = stream::once(async move { drop(span) }).filter_map(|_| async { None });
+        Box::new(items.chain(close))
+    }
+}
+
+impl<T: 'static> Pipe<T, T> for TracePipe<T> {
+    input_connection!(T);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_trace_pipe() {
+        let recorder = SpanRecorder::new();
+        let mut pipe = TracePipe::new("parse", &recorder);
+        pipe.pipe(Rc::new(MockSource::new().items(0..5))).unwrap();
+        let mut stream = Pin::from(pipe.stream());
+        block_on(stream.next());
+        assert!(recorder.spans().is_empty(), "Span ended early");
+        assert_eq!(block_on(stream.count()), 4);
+        {
+            let _partial = Pin::from(pipe.stream());
+        }
+        let spans = recorder.take();
+        assert_eq!(spans.len(), 2);
+        assert_eq!((spans[0].name.as_str(), spans[0].items), ("parse", 5));
+        assert_eq!(spans[1].items, 0);
+        assert!(spans
+            .iter()
+            .all(|span| span.trace_id == recorder.trace_id()));
+        assert_ne!(spans[0].span_id, spans[1].span_id);
+        assert!(spans[0].end >= spans[0].start);
+        assert!(recorder.spans().is_empty());
+    }
+}
@@ -0,0 +1,569 @@
+//! mpi
+//!
+//! Running one pipeline per rank of an MPI style job, the ranks exchanging blobs with point
+//! to point messages and collectives (requires the `mpi` feature)
+//!
+//! ranks talk through the `Communicator` trait, the only implementation being
+//! `LocalCommunicator` for ranks running as threads of one process, no MPI binding is
+//! wrapped here. A binding implements the point to point operations and may map the
+//! collectives to native ones. Every rank runs the same pipeline: a `ScatterUnitsPipe`
+//! splits the blobs of the root into one range of units per rank and a `GatherRanksPipe`
+//! brings the results of every rank back to the root. Like MPI calls the exchanges block
+//! until the other ranks take part
+
+use crate::data_bucket::mask::ValidityMask;
+use crate::data_bucket::{for_each_variant, DataBlob, DataBucketBlob};
+use crate::io::remote::WireItem;
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::{Cell, RefCell};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::mpsc;
+
+/// tags of the messages exchanged by the pipes, a rank running several of them gives each
+/// its own tag
+pub const SCATTER_TAG: u32 = 1;
+pub const GATHER_TAG: u32 = 2;
+
+/// Communicator
+/// The ranks of a job and the messages they exchange
+///
+/// only the point to point operations are required, the collectives are built from them
+/// unless an implementation maps them to native collectives
+pub trait Communicator {
+    /// the index of this rank
+    fn rank(&self) -> usize;
+    /// the number of ranks
+    fn size(&self) -> usize;
+    /// send a message to a rank
+    fn send(&self, to: usize, tag: u32, message: &[u8]) -> Result<(), &'static str>;
+    /// wait for the next message of a rank with a tag
+    fn receive(&self, from: usize, tag: u32) -> Result<Vec<u8>, &'static str>;
+    /// the message of the root on every rank, `message` is only read on the root
+    fn broadcast(
+        &self,
+        root: usize,
+        tag: u32,
+        message: Option<Vec<u8>>,
+    ) -> Result<Vec<u8>, &'static str> {
+        if self.rank() != root {
+            return self.receive(root, tag);
+        }
+        let message = message.ok_or("Root rank has nothing to broadcast")?;
+        for rank in (0..self.size()).filter(|rank| *rank != root) {
+            self.send(rank, tag, &message)?;
+        }
+        Ok(message)
+    }
+    /// the part of every rank out of the parts of the root, one per rank in rank order
+    fn scatter(
+        &self,
+        root: usize,
+        tag: u32,
+        parts: Option<Vec<Vec<u8>>>,
+    ) -> Result<Vec<u8>, &'static str> {
+        if self.rank() != root {
+            return self.receive(root, tag);
+        }
+        let mut parts = parts.ok_or("Root rank has nothing to scatter")?;
+        if parts.len() != self.size() {
+            return Err("Scatter needs one part per rank");
+        }
+        for (rank, part) in parts.iter().enumerate().filter(|(rank, _)| *rank != root) {
+            self.send(rank, tag, part)?;
+        }
+        Ok(parts.swap_remove(root))
+    }
+    /// the parts of every rank in rank order on the root, None on the other ranks
+    fn gather(
+        &self,
+        root: usize,
+        tag: u32,
+        part: Vec<u8>,
+    ) -> Result<Option<Vec<Vec<u8>>>, &'static str> {
+        if self.rank() != root {
+            self.send(root, tag, &part)?;
+            return Ok(None);
+        }
+        let mut parts = Vec::with_capacity(self.size());
+        for rank in 0..self.size() {
+            parts.push(match rank == root {
+                true => Vec::new(),
+                false => self.receive(rank, tag)?,
+            });
+        }
+        parts[root] = part;
+        Ok(Some(parts))
+    }
+}
+
+type Message = (usize, u32, Vec<u8>);
+
+/// LocalCommunicator
+/// A rank of a job whose ranks are threads of the current process, sent to the thread
+/// running the rank
+pub struct LocalCommunicator {
+    rank: usize,
+    ranks: Vec<mpsc::Sender<Message>>,
+    inbox: mpsc::Receiver<Message>,
+    unexpected: RefCell<Vec<Message>>,
+}
+
+impl LocalCommunicator {
+    /// the ranks of a new job
+    pub fn world(size: usize) -> Result<Vec<Self>, &'static str> {
+        if size == 0 {
+            return Err("Number of ranks must be strictly positive");
+        }
+        let (ranks, inboxes): (Vec<_>, Vec<_>) = (0..size).map(|_| mpsc::channel()).unzip();
+        Ok(inboxes
+            .into_iter()
+            .enumerate()
+            .map(|(rank, inbox)| Self {
+                rank,
+                ranks: ranks.clone(),
+                inbox,
+                unexpected: RefCell::new(Vec::new()),
+            })
+            .collect())
+    }
+}
+
+impl Communicator for LocalCommunicator {
+    fn rank(&self) -> usize {
+        self.rank
+    }
+    fn size(&self) -> usize {
+        self.ranks.len()
+    }
+    fn send(&self, to: usize, tag: u32, message: &[u8]) -> Result<(), &'static str> {
+        self.ranks
+            .get(to)
+            .ok_or("Rank out of the communicator")?
+            .send((self.rank, tag, message.to_vec()))
+            .map_err(|_| "Rank left the communicator")
+    }
+    fn receive(&self, from: usize, tag: u32) -> Result<Vec<u8>, &'static str> {
+        if from >= self.size() {
+            return Err("Rank out of the communicator");
+        }
+        let mut unexpected = self.unexpected.borrow_mut();
+        let waiting = unexpected
+            .iter()
+            .position(|(sender, kind, _)| *sender == from && *kind == tag);
+        if let Some(index) = waiting {
+            return Ok(unexpected.remove(index).2);
+        }
+        // messages from other ranks or for other tags wait for their own receive
+        loop {
+            let message = self
+                .inbox
+                .recv()
+                .map_err(|_| "Rank left the communicator")?;
+            if message.0 == from && message.1 == tag {
+                return Ok(message.2);
+            }
+            unexpected.push(message);
+        }
+    }
+}
+
+macro_rules! concat_units_unwrap {
+  ($($x:ident),*) => {
+    fn concat_parts(first: &DataBucketBlob, parts: &[DataBucketBlob]) -> Result<DataBucketBlob, &'static str> {
+      match first {
+        $( DataBucketBlob::$x(first) => {
+          let mut blobs = Vec::with_capacity(parts.len());
+          for part in parts {
+            match part {
+              DataBucketBlob::$x(blob) => blobs.push(blob),
+              _ => return Err("Blobs to concatenate hold different types"),
+            }
+          }
+          concat_blobs(first, &blobs).map(DataBucketBlob::$x)
+        } )*
+      }
+    }
+  }
+}
+
+for_each_variant!(concat_units_unwrap);
+
+fn concat_blobs<T: Clone>(
+    first: &DataBlob<T>,
+    blobs: &[&DataBlob<T>],
+) -> Result<DataBlob<T>, &'static str> {
+    let size = first.unit_size();
+    if blobs.iter().any(|blob| blob.unit_size() != size) {
+        return Err("Blobs to concatenate have different unit sizes");
+    }
+    let data: Vec<T> = blobs
+        .iter()
+        .flat_map(|blob| blob.get_data().iter().cloned())
+        .collect();
+    let mut meta = first.get_meta_data().clone();
+    meta.dimensions = vec![data.len() / size];
+    if size > 1 {
+        meta.dimensions
+            .extend(first.get_meta_data().unitary_dimensions.iter());
+    }
+    meta.record("concat_units", &[("parts", blobs.len().to_string())]);
+    let masked = blobs.iter().any(|blob| blob.get_mask().is_some());
+    let blob = DataBlob::new(data, meta);
+    match masked {
+        true => blob.with_mask(ValidityMask::from_bools(blobs.iter().flat_map(|blob| {
+            let len = blob.get_data().len();
+            (0..len).map(|index| blob.is_valid(index))
+        }))),
+        false => Ok(blob),
+    }
+}
+
+/// join blobs of the same type and unit size into one, unit after unit (such as the parts
+/// gathered from every rank)
+pub fn concat_units(parts: &[DataBucketBlob]) -> Result<DataBucketBlob, &'static str> {
+    let first = parts.first().ok_or("No blobs to concatenate")?;
+    concat_parts(first, parts)
+}
+
+/// the range of units of a rank when a blob is split as evenly as possible
+fn units_of(rank: usize, size: usize, units: usize) -> std::ops::Range<usize> {
+    rank * units / size..(rank + 1) * units / size
+}
+
+/// prefix of the parts carrying an item, those without it end the stream
+const MORE: u8 = 1;
+const DONE: u8 = 0;
+
+/// ScatterUnitsPipe
+/// Splits each blob of the root rank into one contiguous range of units per rank, every
+/// rank streaming its own range
+///
+/// only the input of the root is read, the other ranks stream what the root sends them
+/// until its input ends. Failures end the stream and are kept by `error`
+pub struct ScatterUnitsPipe<C> {
+    communicator: Rc<C>,
+    root: usize,
+    tag: u32,
+    error: Rc<Cell<Option<&'static str>>>,
+    input: Option<Rc<dyn Source<DataBucketBlob>>>,
+}
+
+impl<C: Communicator> ScatterUnitsPipe<C> {
+    /// constructor for blobs scattered from a root rank
+    pub fn new(communicator: Rc<C>, root: usize) -> Result<Self, &'static str> {
+        if root >= communicator.size() {
+            return Err("Rank out of the communicator");
+        }
+        Ok(Self {
+            communicator,
+            root,
+            tag: SCATTER_TAG,
+            error: Rc::new(Cell::new(None)),
+            input: None,
+        })
+    }
+    /// exchange the blobs with another tag
+    pub fn with_tag(mut self, tag: u32) -> Self {
+        self.tag = tag;
+        self
+    }
+    /// the failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+/// the parts of a blob, one per rank
+fn split(blob: Option<DataBucketBlob>, size: usize) -> Result<Vec<Vec<u8>>, &'static str> {
+    let blob = match blob {
+        Some(blob) => blob,
+        None => return Ok(vec![vec![DONE]; size]),
+    };
+    let units = blob.unit_count();
+    (0..size)
+        .map(|rank| {
+            let part = blob
+                .slice_units(units_of(rank, size, units))
+                .ok_or("Failure to slice blob units")?;
+            let mut bytes = vec![MORE];
+            bytes.extend(part.to_bytes()?);
+            Ok(bytes)
+        })
+        .collect()
+}
+
+/// the item of a part, None at the end of the stream
+fn decode_part<T: WireItem>(part: &[u8]) -> Result<Option<T>, &'static str> {
+    match part.split_first() {
+        Some((&MORE, bytes)) => T::decode(bytes).map(Some),
+        Some((&DONE, _)) => Ok(None),
+        _ => Err("Invalid message between ranks"),
+    }
+}
+
+impl<C: Communicator + 'static> Source<DataBucketBlob> for ScatterUnitsPipe<C> {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBucketBlob>> {
+        self.error.set(None);
+        let is_root = self.communicator.rank() == self.root;
+        let input = match (&self.input, is_root) {
+            (Some(input), true) => Some(Pin::from(input.stream())),
+            _ => None,
+        };
+        let (communicator, root, tag) = (self.communicator.clone(), self.root, self.tag);
+        let error = self.error.clone();
+        Box::new(stream::unfold(Some(input), move |input| {
+            let (communicator, error) = (communicator.clone(), error.clone());
+            async move {
+                let mut input = input?;
+                let parts = match (is_root, &mut input) {
+                    (true, Some(input)) => Some(split(input.next().await, communicator.size())),
+                    (true, None) => Some(split(None, communicator.size())),
+                    (false, _) => None,
+                };
+                let part = match parts.transpose() {
+                    Ok(parts) => communicator.scatter(root, tag, parts),
+                    Err(message) => Err(message),
+                };
+                match part.and_then(|part| decode_part::<DataBucketBlob>(&part)) {
+                    Ok(Some(blob)) => Some((blob, Some(input))),
+                    Ok(None) => None,
+                    Err(message) => {
+                        error.set(Some(message));
+                        None
+                    }
+                }
+            }
+        }))
+    }
+}
+
+impl<C: Communicator + 'static> Pipe<DataBucketBlob, DataBucketBlob> for ScatterUnitsPipe<C> {
+    input_connection!(DataBucketBlob);
+}
+
+/// GatherRanksPipe
+/// Collects the items of every rank on the root rank, emitting the items of one round in
+/// rank order
+///
+/// every rank streams its input one item per round, the root emits the items of the ranks
+/// that still had one and the other ranks emit nothing, the streams end once every input
+/// ended. Failures end the stream and are kept by `error`
+pub struct GatherRanksPipe<T, C> {
+    communicator: Rc<C>,
+    root: usize,
+    tag: u32,
+    error: Rc<Cell<Option<&'static str>>>,
+    input: Option<Rc<dyn Source<T>>>,
+    items: PhantomData<T>,
+}
+
+impl<T, C: Communicator> GatherRanksPipe<T, C> {
+    /// constructor for items gathered on a root rank
+    pub fn new(communicator: Rc<C>, root: usize) -> Result<Self, &'static str> {
+        if root >= communicator.size() {
+            return Err("Rank out of the communicator");
+        }
+        Ok(Self {
+            communicator,
+            root,
+            tag: GATHER_TAG,
+            error: Rc::new(Cell::new(None)),
+            input: None,
+            items: PhantomData,
+        })
+    }
+    /// exchange the items with another tag
+    pub fn with_tag(mut self, tag: u32) -> Self {
+        self.tag = tag;
+        self
+    }
+    /// the failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+/// one round of a gather: the items of the root, or None once every rank ended
+fn gather_round<T: WireItem, C: Communicator>(
+    communicator: &C,
+    root: usize,
+    tag: u32,
+    item: Option<T>,
+) -> Result<Option<Vec<T>>, &'static str> {
+    let part = match item {
+        Some(item) => {
+            let mut bytes = vec![MORE];
+            bytes.extend(item.encode()?);
+            bytes
+        }
+        None => vec![DONE],
+    };
+    let items = match communicator.gather(root, tag, part)? {
+        Some(parts) => Some(
+            parts
+                .iter()
+                .filter_map(|part| decode_part::<T>(part).transpose())
+                .collect::<Result<Vec<T>, _>>()?,
+        ),
+        None => None,
+    };
+    // the root tells every rank whether another round follows
+    let more = items.as_ref().map(|items| vec![!items.is_empty() as u8]);
+    match communicator.broadcast(root, tag, more)?.as_slice() {
+        [0] => Ok(None),
+        [_] => Ok(Some(items.unwrap_or_default())),
+        _ => Err("Invalid message between ranks"),
+    }
+}
+
+impl<T: WireItem + 'static, C: Communicator + 'static> Source<Vec<T>> for GatherRanksPipe<T, C> {
+    fn stream(&self) -> Box<dyn Stream<Item = Vec<T>>> {
+        self.error.set(None);
+        let input = self.input.as_ref().map(|input| Pin::from(input.stream()));
+        let (communicator, root, tag) = (self.communicator.clone(), self.root, self.tag);
+        let is_root = communicator.rank() == root;
+        let error = self.error.clone();
+        Box::new(stream::unfold(Some(input), move |input| {
+            let (communicator, error) = (communicator.clone(), error.clone());
+            async move {
+                let mut input = input?;
+                loop {
+                    let item = match &mut input {
+                        Some(stream) => stream.next().await,
+                        None => None,
+                    };
+                    if item.is_none() {
+                        input = None;
+                    }
+                    match gather_round(communicator.as_ref(), root, tag, item) {
+                        Ok(Some(items)) if is_root => return Some((items, Some(input))),
+                        Ok(Some(_)) => continue,
+                        Ok(None) => return None,
+                        Err(message) => {
+                            error.set(Some(message));
+                            return None;
+                        }
+                    }
+                }
+            }
+        }))
+    }
+}
+
+impl<T: WireItem + 'static, C: Communicator + 'static> Pipe<T, Vec<T>> for GatherRanksPipe<T, C> {
+    input_connection!(T);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::MetaData;
+    use crate::pipes::FlatMapPipe;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn blob(data: Vec<f64>) -> DataBucketBlob {
+        DataBucketBlob::Float64(DataBlob::new(
+            data,
+            MetaData {
+                name: "samples".to_string(),
+                ..Default::default()
+            },
+        ))
+    }
+
+    fn data(blob: &DataBucketBlob) -> Vec<f64> {
+        match blob {
+            DataBucketBlob::Float64(blob) => blob.get_data().clone(),
+            _ => panic!("Blob of another type"),
+        }
+    }
+
+    /// the pipeline of a rank: scatter from rank 0, scale by the rank plus one, gather
+    fn run(communicator: LocalCommunicator, blobs: Vec<DataBucketBlob>) -> Vec<Vec<f64>> {
+        let communicator = Rc::new(communicator);
+        let factor = communicator.rank() as f64 + 1.0;
+        let mut scatter = ScatterUnitsPipe::new(communicator.clone(), 0).unwrap();
+        if communicator.rank() == 0 {
+            scatter
+                .pipe(Rc::new(MockSource::new().items(blobs)))
+                .unwrap();
+        }
+        let mut scale = FlatMapPipe::new(move |part: DataBucketBlob| {
+            Some(blob(
+                data(&part).iter().map(|value| value * factor).collect(),
+            ))
+        });
+        scale.pipe(Rc::new(scatter)).unwrap();
+        let mut gather = GatherRanksPipe::new(communicator, 0).unwrap();
+        gather.pipe(Rc::new(scale)).unwrap();
+        let rounds: Vec<Vec<DataBucketBlob>> = block_on(Pin::from(gather.stream()).collect());
+        assert_eq!(gather.error(), None);
+        rounds
+            .iter()
+            .map(|parts| data(&concat_units(parts).unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_scatter_and_gather() {
+        let ranks = LocalCommunicator::world(3).unwrap();
+        let threads: Vec<_> = ranks
+            .into_iter()
+            .map(|communicator| {
+                std::thread::spawn(move || {
+                    let blobs = vec![blob((0..7).map(f64::from).collect()), blob(vec![1.0; 3])];
+                    run(communicator, blobs)
+                })
+            })
+            .collect();
+        let results: Vec<Vec<Vec<f64>>> = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+        assert_eq!(
+            results[0],
+            [
+                vec![0.0, 1.0, 4.0, 6.0, 12.0, 15.0, 18.0],
+                vec![1.0, 2.0, 3.0]
+            ]
+        );
+        assert!(results[1].is_empty() && results[2].is_empty());
+    }
+
+    #[test]
+    fn test_uneven_gathers() {
+        let ranks = LocalCommunicator::world(2).unwrap();
+        let threads: Vec<_> = ranks
+            .into_iter()
+            .map(|communicator| {
+                std::thread::spawn(move || {
+                    let communicator = Rc::new(communicator);
+                    let count = communicator.rank() as u32 * 2 + 1;
+                    let mut gather = GatherRanksPipe::new(communicator, 1).unwrap();
+                    gather
+                        .pipe(Rc::new(MockSource::new().items(0..count)))
+                        .unwrap();
+                    block_on(Pin::from(gather.stream()).collect::<Vec<Vec<u32>>>())
+                })
+            })
+            .collect();
+        let results: Vec<_> = threads
+            .into_iter()
+            .map(|thread| thread.join().unwrap())
+            .collect();
+        assert!(results[0].is_empty());
+        assert_eq!(results[1], [vec![0, 0], vec![1], vec![2]]);
+        assert!(LocalCommunicator::world(0).is_err());
+        let single = Rc::new(LocalCommunicator::world(1).unwrap().remove(0));
+        assert!(GatherRanksPipe::<u32, _>::new(single.clone(), 1).is_err());
+        assert_eq!(single.broadcast(0, 7, Some(vec![3])), Ok(vec![3]));
+        let joined = concat_units(&[blob(vec![1.0]), blob(vec![2.0, 3.0])]).unwrap();
+        assert_eq!(joined.get_meta_data().dimensions, [3]);
+        assert!(concat_units(&[]).is_err());
+    }
+}
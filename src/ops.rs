@@ -0,0 +1,49 @@
+//! ops
+//!
+//! Pipes performing numeric operations on data streams
+
+/// elementwise
+/// Sub module for elementwise arithmetic
+pub mod elementwise;
+
+/// blob
+/// Sub module for unit aware arithmetic on blobs
+pub mod blob;
+
+/// convert
+/// Sub module for unit conversions of blobs
+pub mod convert;
+
+/// stats
+/// Sub module for statistics of blobs with missing values
+pub mod stats;
+
+/// cast
+/// Sub module for conversions of blobs between element types
+pub mod cast;
+
+/// tensor
+/// Sub module for slicing and reducing tensors along named axes
+pub mod tensor;
+
+/// series
+/// Sub module for downsampling and interpolating time series
+pub mod series;
+
+/// convolve
+/// Sub module for convolving blobs with kernels
+pub mod convolve;
+
+/// linalg
+/// Sub module for linear algebra on matrix blobs
+pub mod linalg;
+
+pub use blob::{BlobElementwisePipe, BlobOperand};
+pub use cast::{CastPipe, Castable};
+pub use convert::{ConvertUnitsPipe, Convertible};
+pub use convolve::{Boundary, ConvolvePipe, Kernel};
+pub use elementwise::{Elementwise, ElementwiseOp, ElementwisePipe, Operand};
+pub use linalg::{DecomposePipe, Decomposition, MatMulPipe, MatrixOperand, SolvePipe};
+pub use series::{DownsamplePipe, Downsampling, InterpolatePipe, Interpolation};
+pub use stats::{Histogram, HistogramPipe, MissingPolicy, NormalizePipe, Stats, StatsPipe};
+pub use tensor::{TensorReducePipe, TensorSlicePipe};
@@ -0,0 +1,254 @@
+//! blob
+//!
+//! Unit aware elementwise arithmetic on streams of blobs
+//!
+//! the units of both operands are checked before computing: sums and differences need
+//! operands of the same units and keep them, products and quotients combine them (`m` over
+//! `s` gives `m/s`), operands without units are not checked and make products and quotients
+//! lose their units, scalars are dimensionless unless given units
+
+use super::elementwise::{Elementwise, ElementwiseOp};
+use crate::data_bucket::mask::combine_masks;
+use crate::data_bucket::units::Units;
+use crate::data_bucket::{DataBlob, MetaData};
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// the units of the result of an operation between operands with the given units
+pub fn combine_units(
+    op: ElementwiseOp,
+    lhs: Option<&str>,
+    rhs: Option<&str>,
+) -> Result<Option<String>, &'static str> {
+    let (lhs, rhs) = match (lhs, rhs) {
+        (Some(lhs), Some(rhs)) => (Units::parse(lhs)?, Units::parse(rhs)?),
+        _ => {
+            return Ok(match op {
+                ElementwiseOp::Add | ElementwiseOp::Sub => lhs.or(rhs).map(str::to_string),
+                ElementwiseOp::Mul | ElementwiseOp::Div => None,
+            })
+        }
+    };
+    let units = match op {
+        ElementwiseOp::Add | ElementwiseOp::Sub if lhs != rhs => {
+            return Err("Cannot add or subtract data of different units")
+        }
+        ElementwiseOp::Add | ElementwiseOp::Sub => lhs,
+        ElementwiseOp::Mul => lhs.mul(&rhs),
+        ElementwiseOp::Div => lhs.div(&rhs),
+    };
+    Ok(Some(units.to_string()))
+}
+
+/// the meta data of the result of an operation on a blob
+fn derive_meta(
+    op: ElementwiseOp,
+    lhs: &MetaData,
+    rhs: Option<&str>,
+    operand: String,
+) -> Result<MetaData, &'static str> {
+    let mut meta = lhs.clone();
+    meta.units = combine_units(op, lhs.units.as_deref(), rhs)?;
+    meta.record(
+        "elementwise",
+        &[("op", format!("{:?}", op)), ("operand", operand)],
+    );
+    Ok(meta)
+}
+
+impl<T: Elementwise> DataBlob<T> {
+    /// apply an operation pairwise with another blob on their common length (returns an
+    /// error if their units are incompatible)
+    pub fn elementwise(&self, op: ElementwiseOp, rhs: &DataBlob<T>) -> Result<Self, &'static str> {
+        let rhs_meta = rhs.get_meta_data();
+        let meta = derive_meta(
+            op,
+            self.get_meta_data(),
+            rhs_meta.units.as_deref(),
+            rhs_meta.name.clone(),
+        )?;
+        let mut data = Vec::with_capacity(self.get_data().len());
+        T::apply(op, self.get_data(), rhs.get_data(), &mut data);
+        let mask = combine_masks(self.get_mask(), rhs.get_mask(), data.len());
+        let blob = Self::new(data, meta);
+        Ok(match mask {
+            Some(mask) => blob.with_mask(mask)?,
+            None => blob,
+        })
+    }
+    /// apply an operation between every element and a scalar with the given units (returns
+    /// an error if the units are incompatible with those of the blob)
+    pub fn elementwise_scalar(
+        &self,
+        op: ElementwiseOp,
+        rhs: T,
+        units: &str,
+    ) -> Result<Self, &'static str>
+    where
+        T: std::fmt::Debug,
+    {
+        let meta = derive_meta(op, self.get_meta_data(), Some(units), format!("{:?}", rhs))?;
+        let mut data = Vec::with_capacity(self.get_data().len());
+        T::apply_scalar(op, self.get_data(), rhs, &mut data);
+        let blob = Self::new(data, meta);
+        Ok(match self.get_mask() {
+            Some(mask) => blob.with_mask(mask.clone())?,
+            None => blob,
+        })
+    }
+}
+
+/// BlobOperand
+/// The right hand side of a unit aware elementwise operation
+pub enum BlobOperand<T> {
+    /// the same value with the given units for every element
+    Scalar(T, String),
+    /// the blob at the same position in another stream
+    Source(Rc<dyn Source<DataBlob<T>>>),
+}
+
+type BlobResults<T> = Pin<Box<dyn Stream<Item = Result<DataBlob<T>, &'static str>>>>;
+
+/// BlobElementwisePipe
+/// A pipe applying a unit aware arithmetic operation between the blobs of its input and an
+/// operand, the stream ends at the first pair of blobs with incompatible units
+pub struct BlobElementwisePipe<T> {
+    op: ElementwiseOp,
+    rhs: Rc<BlobOperand<T>>,
+    input: Option<Rc<dyn Source<DataBlob<T>>>>,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl<T: Elementwise + std::fmt::Debug> BlobElementwisePipe<T> {
+    /// constructor
+    pub fn new(op: ElementwiseOp, rhs: BlobOperand<T>) -> Self {
+        Self {
+            op,
+            rhs: Rc::new(rhs),
+            input: None,
+            error: Rc::new(Cell::new(None)),
+        }
+    }
+    /// operation against a dimensionless scalar
+    pub fn with_scalar(op: ElementwiseOp, rhs: T) -> Self {
+        Self::with_scalar_units(op, rhs, "1")
+    }
+    /// operation against a scalar with units
+    pub fn with_scalar_units(op: ElementwiseOp, rhs: T, units: &str) -> Self {
+        Self::new(op, BlobOperand::Scalar(rhs, units.to_string()))
+    }
+    /// operation against the blobs of another source
+    pub fn with_source(op: ElementwiseOp, rhs: Rc<dyn Source<DataBlob<T>>>) -> Self {
+        Self::new(op, BlobOperand::Source(rhs))
+    }
+    /// the units failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl<T: Elementwise + std::fmt::Debug> Source<DataBlob<T>> for BlobElementwisePipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBlob<T>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let op = self.op;
+        let error = self.error.clone();
+        error.set(None);
+        let results: BlobResults<T> = match self.rhs.as_ref() {
+            BlobOperand::Scalar(rhs, units) => {
+                let (rhs, units) = (*rhs, units.clone());
+                Box::pin(input.map(move |lhs| lhs.elementwise_scalar(op, rhs, &units)))
+            }
+            BlobOperand::Source(rhs) => Box::pin(
+                input
+                    .zip(Pin::from(rhs.stream()))
+                    .map(move |(lhs, rhs)| lhs.elementwise(op, &rhs)),
+            ),
+        };
+        Box::new(results.scan((), move |_, result| {
+            futures::future::ready(result.map_err(|failure| error.set(Some(failure))).ok())
+        }))
+    }
+}
+
+impl<T: Elementwise + std::fmt::Debug> Pipe<DataBlob<T>, DataBlob<T>> for BlobElementwisePipe<T> {
+    input_connection!(DataBlob<T>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn blob(name: &str, data: Vec<f64>, units: &str) -> DataBlob<f64> {
+        let meta = MetaData {
+            name: name.to_string(),
+            units: Some(units.to_string()),
+            unitary_dimensions: vec![1],
+            dimensions: vec![data.len()],
+            ..Default::default()
+        };
+        DataBlob::new(data, meta)
+    }
+
+    #[test]
+    fn test_combine_units() {
+        let speed = combine_units(ElementwiseOp::Div, Some("m"), Some("s")).unwrap();
+        assert_eq!(speed.as_deref(), Some("m/s"));
+        assert_eq!(
+            combine_units(ElementwiseOp::Mul, Some("m/s"), Some("s")).unwrap(),
+            Some("m".to_string())
+        );
+        assert!(combine_units(ElementwiseOp::Add, Some("m"), Some("s")).is_err());
+        assert_eq!(
+            combine_units(ElementwiseOp::Add, Some("m*s^-1"), Some("m/s")).unwrap(),
+            Some("m/s".to_string())
+        );
+        assert_eq!(
+            combine_units(ElementwiseOp::Sub, None, Some("K")).unwrap(),
+            Some("K".to_string())
+        );
+        assert_eq!(
+            combine_units(ElementwiseOp::Mul, None, Some("K")).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn test_unit_aware_pipe() {
+        let times =
+            MockSource::new().items([blob("t", vec![2.0, 4.0], "s"), blob("t", vec![1.0], "m")]);
+        let mut pipe = BlobElementwisePipe::with_source(ElementwiseOp::Div, Rc::new(times));
+        let distances =
+            MockSource::new().items([blob("x", vec![10.0, 20.0], "m"), blob("x", vec![5.0], "m")]);
+        pipe.pipe(Rc::new(distances)).unwrap();
+        let speeds: Vec<DataBlob<f64>> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(speeds[0].get_data(), &vec![5.0, 5.0]);
+        assert_eq!(speeds[0].get_meta_data().units.as_deref(), Some("m/s"));
+        assert_eq!(speeds[1].get_meta_data().units.as_deref(), Some("1"));
+        assert_eq!(
+            speeds[0].get_meta_data().explain(),
+            "elementwise(op=Div, operand=t)"
+        );
+        let mut offset = BlobElementwisePipe::with_scalar_units(ElementwiseOp::Add, 1.0, "s");
+        offset
+            .pipe(Rc::new(MockSource::new().items([
+                blob("t", vec![0.0], "s"),
+                blob("x", vec![0.0], "m"),
+            ])))
+            .unwrap();
+        let shifted: Vec<DataBlob<f64>> = block_on(Pin::from(offset.stream()).collect());
+        assert_eq!(shifted.len(), 1, "Added seconds to metres");
+        assert_eq!(
+            offset.error(),
+            Some("Cannot add or subtract data of different units")
+        );
+    }
+}
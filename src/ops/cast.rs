@@ -0,0 +1,157 @@
+//! cast
+//!
+//! Converting blobs between numeric element types
+//!
+//! values go through f64, integers saturate at the bounds of their type and take NaN as 0,
+//! floats round to the nearest value they can hold
+
+use crate::data_bucket::half::{BF16, F16};
+use crate::data_bucket::DataBlob;
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Castable
+/// A trait for the numeric element types blobs can be cast between
+pub trait Castable: Copy + 'static {
+    /// name of the type recorded in the provenance of cast blobs
+    const NAME: &'static str;
+    /// the value as an f64
+    fn to_f64(self) -> f64;
+    /// the nearest value to an f64
+    fn from_f64(value: f64) -> Self;
+}
+
+macro_rules! primitive_castable {
+  ($($t:ty),*) => {
+    $( impl Castable for $t {
+      const NAME: &'static str = stringify!($t);
+      fn to_f64(self) -> f64 {
+        self as f64
+      }
+      fn from_f64(value: f64) -> Self {
+        value as $t
+      }
+    } )*
+  }
+}
+
+primitive_castable!(i8, u8, i16, u16, i32, u32, i64, u64, isize, usize, f32, f64);
+
+macro_rules! half_castable {
+  ($($t:ident),*) => {
+    $( impl Castable for $t {
+      const NAME: &'static str = stringify!($t);
+      fn to_f64(self) -> f64 {
+        $t::to_f64(self)
+      }
+      fn from_f64(value: f64) -> Self {
+        $t::from_f64(value)
+      }
+    } )*
+  }
+}
+
+half_castable!(F16, BF16);
+
+impl<T: Castable> DataBlob<T> {
+    /// convert the data to another element type, keeping the meta data and mask
+    pub fn cast<U: Castable>(&self) -> DataBlob<U> {
+        let mut meta = self.get_meta_data().clone();
+        let data = self
+            .get_data()
+            .iter()
+            .map(|value| U::from_f64(value.to_f64()))
+            .collect();
+        meta.record(
+            "cast",
+            &[("from", T::NAME.to_string()), ("to", U::NAME.to_string())],
+        );
+        let blob = DataBlob::new(data, meta);
+        match self.get_mask() {
+            // the data keeps its length so the mask always fits
+            Some(mask) => blob.with_mask(mask.clone()).unwrap(),
+            None => blob,
+        }
+    }
+}
+
+/// CastPipe
+/// A pipe casting the blobs of its input to another element type
+pub struct CastPipe<From, To> {
+    input: Option<Rc<dyn Source<DataBlob<From>>>>,
+    output: PhantomData<To>,
+}
+
+impl<From: Castable, To: Castable> CastPipe<From, To> {
+    /// constructor
+    pub fn new() -> Self {
+        Self {
+            input: None,
+            output: PhantomData,
+        }
+    }
+}
+
+impl<From: Castable, To: Castable> Default for CastPipe<From, To> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<From: Castable, To: Castable> Source<DataBlob<To>> for CastPipe<From, To> {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBlob<To>>> {
+        match &self.input {
+            Some(input) => Box::new(Pin::from(input.stream()).map(|blob| blob.cast())),
+            None => Box::new(stream::empty()),
+        }
+    }
+}
+
+impl<From: Castable, To: Castable> Pipe<DataBlob<From>, DataBlob<To>> for CastPipe<From, To> {
+    input_connection!(DataBlob<From>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::mask::ValidityMask;
+    use crate::data_bucket::MetaData;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_cast_pipe() {
+        let meta = MetaData {
+            name: "weights".to_string(),
+            ..Default::default()
+        };
+        let weights = DataBlob::new(vec![0.5_f32, -1.25, 70000.0, f32::NAN], meta)
+            .with_mask(ValidityMask::from_bools([true, true, true, false]))
+            .unwrap();
+        let mut pipe = CastPipe::<f32, F16>::new();
+        pipe.pipe(Rc::new(MockSource::new().items([weights])))
+            .unwrap();
+        let blobs: Vec<DataBlob<F16>> = block_on(Pin::from(pipe.stream()).collect());
+        let data = blobs[0].get_data();
+        assert_eq!(data[0].to_f32(), 0.5);
+        assert_eq!(data[1].to_f32(), -1.25);
+        assert_eq!(data[2].to_f32(), f32::INFINITY);
+        assert!(data[3].is_nan());
+        assert_eq!(blobs[0].null_count(), 1);
+        assert_eq!(blobs[0].get_meta_data().explain(), "cast(from=f32, to=F16)");
+    }
+
+    #[test]
+    fn test_casts() {
+        let blob = DataBlob::new(vec![-1.5_f64, 300.0, f64::NAN], MetaData::default());
+        assert_eq!(blob.cast::<u8>().get_data(), &vec![0, 255, 0]);
+        assert_eq!(blob.cast::<i16>().get_data(), &vec![-1, 300, 0]);
+        let brain = blob.cast::<BF16>();
+        assert_eq!(brain.get_data()[1].to_f64(), 300.0);
+        assert_eq!(brain.cast::<f64>().get_data()[0], -1.5);
+    }
+}
@@ -0,0 +1,165 @@
+//! convert
+//!
+//! Rescaling blobs of floating point data between units
+
+use crate::data_bucket::conversion::UnitTable;
+use crate::data_bucket::DataBlob;
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Convertible
+/// A trait for the element types that can be rescaled between units
+pub trait Convertible: Copy + 'static {
+    /// the value times a scale plus an offset
+    fn rescale(self, scale: f64, offset: f64) -> Self;
+}
+
+impl Convertible for f64 {
+    fn rescale(self, scale: f64, offset: f64) -> Self {
+        self * scale + offset
+    }
+}
+
+impl Convertible for f32 {
+    fn rescale(self, scale: f64, offset: f64) -> Self {
+        (self as f64 * scale + offset) as f32
+    }
+}
+
+impl<T: Convertible> DataBlob<T> {
+    /// convert the data to other units with the conversions of a table (returns an error if
+    /// the blob has no units or they cannot be converted)
+    pub fn convert_units(&self, units: &str, table: &UnitTable) -> Result<Self, &'static str> {
+        let mut meta = self.get_meta_data().clone();
+        let from = meta.units.clone().ok_or("Blob has no units")?;
+        let (scale, offset) = table.conversion(&from, units)?;
+        let data = self
+            .get_data()
+            .iter()
+            .map(|value| value.rescale(scale, offset))
+            .collect();
+        meta.units = Some(units.to_string());
+        meta.record(
+            "convert_units",
+            &[("from", from), ("to", units.to_string())],
+        );
+        let blob = Self::new(data, meta);
+        Ok(match self.get_mask() {
+            Some(mask) => blob.with_mask(mask.clone())?,
+            None => blob,
+        })
+    }
+}
+
+/// ConvertUnitsPipe
+/// A pipe converting the blobs of its input to the given units, the stream ends at the first
+/// blob that cannot be converted
+pub struct ConvertUnitsPipe<T> {
+    units: String,
+    table: Rc<UnitTable>,
+    input: Option<Rc<dyn Source<DataBlob<T>>>>,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl<T: Convertible> ConvertUnitsPipe<T> {
+    /// constructor converting with the built-in table
+    pub fn new(units: &str) -> Self {
+        Self::with_table(units, UnitTable::new())
+    }
+    /// constructor converting with a custom table
+    pub fn with_table(units: &str, table: UnitTable) -> Self {
+        Self {
+            units: units.to_string(),
+            table: Rc::new(table),
+            input: None,
+            error: Rc::new(Cell::new(None)),
+        }
+    }
+    /// the conversion failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl<T: Convertible> Source<DataBlob<T>> for ConvertUnitsPipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBlob<T>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let units = self.units.clone();
+        let table = self.table.clone();
+        let error = self.error.clone();
+        error.set(None);
+        Box::new(input.scan((), move |_, blob| {
+            let converted = blob
+                .convert_units(&units, &table)
+                .map_err(|failure| error.set(Some(failure)));
+            futures::future::ready(converted.ok())
+        }))
+    }
+}
+
+impl<T: Convertible> Pipe<DataBlob<T>, DataBlob<T>> for ConvertUnitsPipe<T> {
+    input_connection!(DataBlob<T>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::MetaData;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn blob(data: Vec<f64>, units: Option<&str>) -> DataBlob<f64> {
+        let meta = MetaData {
+            name: "temperature".to_string(),
+            units: units.map(str::to_string),
+            ..Default::default()
+        };
+        DataBlob::new(data, meta)
+    }
+
+    #[test]
+    fn test_convert_pipe() {
+        let mut pipe = ConvertUnitsPipe::new("K");
+        pipe.pipe(Rc::new(MockSource::new().items([
+            blob(vec![0.0, 100.0], Some("°C")),
+            blob(vec![32.0], Some("°F")),
+            blob(vec![1.0], None),
+            blob(vec![2.0], Some("K")),
+        ])))
+        .unwrap();
+        let blobs: Vec<DataBlob<f64>> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(blobs.len(), 2, "Converted a blob without units");
+        assert_eq!(blobs[0].get_data(), &vec![273.15, 373.15]);
+        assert!((blobs[1].get_data()[0] - 273.15).abs() < 1e-9);
+        assert_eq!(blobs[1].get_meta_data().units.as_deref(), Some("K"));
+        assert_eq!(
+            blobs[0].get_meta_data().explain(),
+            "convert_units(from=°C, to=K)"
+        );
+        assert_eq!(pipe.error(), Some("Blob has no units"));
+    }
+
+    #[test]
+    fn test_custom_table() {
+        let mut table = UnitTable::new();
+        table.register("ly", 9.4607e15, "m").unwrap();
+        let mut pipe = ConvertUnitsPipe::<f32>::with_table("km", table);
+        let meta = MetaData {
+            units: Some("ly".to_string()),
+            ..Default::default()
+        };
+        pipe.pipe(Rc::new(
+            MockSource::new().items([DataBlob::new(vec![1.0_f32], meta)]),
+        ))
+        .unwrap();
+        let blobs: Vec<DataBlob<f32>> = block_on(Pin::from(pipe.stream()).collect());
+        assert!((blobs[0].get_data()[0] / 9.4607e12 - 1.0).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,329 @@
+//! convolve
+//!
+//! Convolving the blobs of a stream with kernels
+//!
+//! a kernel of one dimension runs along the first dimension of a blob (its units when it
+//! declares no dimensions) and one of two dimensions along its first two, any further
+//! dimensions are channels convolved separately, as the interleaved samples of an image.
+//! Values go through f64 and an output is invalid in the mask when any input it reads is too
+
+use crate::data_bucket::mask::ValidityMask;
+use crate::data_bucket::DataBlob;
+use crate::ops::Castable;
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Kernel
+/// The weights of a convolution, row after row
+#[derive(Clone, Debug, PartialEq)]
+pub struct Kernel {
+    weights: Vec<f64>,
+    rows: usize,
+    cols: usize,
+    planar: bool,
+}
+
+impl Kernel {
+    /// constructor of a kernel of one dimension
+    pub fn new(weights: Vec<f64>) -> Result<Self, &'static str> {
+        if weights.is_empty() {
+            return Err("Kernels cannot be empty");
+        }
+        Ok(Self {
+            rows: weights.len(),
+            weights,
+            cols: 1,
+            planar: false,
+        })
+    }
+    /// constructor of a kernel of two dimensions
+    pub fn new_2d(weights: Vec<f64>, rows: usize, cols: usize) -> Result<Self, &'static str> {
+        if rows == 0 || cols == 0 {
+            return Err("Kernels cannot be empty");
+        }
+        if weights.len() != rows * cols {
+            return Err("Kernel weights do not match its dimensions");
+        }
+        Ok(Self {
+            weights,
+            rows,
+            cols,
+            planar: true,
+        })
+    }
+    /// a kernel of one dimension averaging `len` values
+    pub fn moving_average(len: usize) -> Result<Self, &'static str> {
+        Self::new(vec![1.0 / len as f64; len])
+    }
+    /// the dimensions of the kernel
+    pub fn shape(&self) -> Vec<usize> {
+        match self.planar {
+            true => vec![self.rows, self.cols],
+            false => vec![self.rows],
+        }
+    }
+}
+
+/// Boundary
+/// How positions beyond the edges of a blob are read
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Default)]
+pub enum Boundary {
+    /// as zeros
+    #[default]
+    Zero,
+    /// as the value at the closest edge
+    Clamp,
+    /// as the values mirrored about the edge, the edge included (`c b a | a b c`)
+    Reflect,
+    /// as the values from the opposite edge
+    Wrap,
+    /// never, the output only covers the positions the kernel fits in entirely
+    Valid,
+}
+
+impl Boundary {
+    fn name(self) -> &'static str {
+        match self {
+            Boundary::Zero => "zero",
+            Boundary::Clamp => "clamp",
+            Boundary::Reflect => "reflect",
+            Boundary::Wrap => "wrap",
+            Boundary::Valid => "valid",
+        }
+    }
+    /// the position read for a position along a dimension of a length, none for zeros
+    fn read(self, position: isize, len: usize) -> Option<usize> {
+        let len = len as isize;
+        if (0..len).contains(&position) {
+            return Some(position as usize);
+        }
+        match self {
+            Boundary::Zero | Boundary::Valid => None,
+            Boundary::Clamp => Some(position.clamp(0, len - 1) as usize),
+            Boundary::Reflect => {
+                let folded = position.rem_euclid(2 * len);
+                Some(match folded < len {
+                    true => folded,
+                    false => 2 * len - 1 - folded,
+                } as usize)
+            }
+            Boundary::Wrap => Some(position.rem_euclid(len) as usize),
+        }
+    }
+}
+
+/// convolve values laid out as `[rows, cols, channels]`, returning the values, their
+/// validity and the rows and columns of the output
+fn convolve(
+    values: &[f64],
+    valid: &dyn Fn(usize) -> bool,
+    (rows, cols, channels): (usize, usize, usize),
+    kernel: &Kernel,
+    boundary: Boundary,
+) -> (Vec<f64>, Vec<bool>, usize, usize) {
+    let (out_rows, out_cols, offsets) = match boundary {
+        Boundary::Valid => (
+            (rows + 1).saturating_sub(kernel.rows),
+            (cols + 1).saturating_sub(kernel.cols),
+            (kernel.rows - 1, kernel.cols - 1),
+        ),
+        _ => (rows, cols, (kernel.rows / 2, kernel.cols / 2)),
+    };
+    let len = out_rows * out_cols * channels;
+    let (mut out, mut validity) = (Vec::with_capacity(len), Vec::with_capacity(len));
+    for row in 0..out_rows {
+        for col in 0..out_cols {
+            for channel in 0..channels {
+                let (mut sum, mut all_valid) = (0.0, true);
+                for (at, weight) in kernel.weights.iter().enumerate() {
+                    let (a, b) = (at / kernel.cols, at % kernel.cols);
+                    let y = (row + offsets.0) as isize - a as isize;
+                    let x = (col + offsets.1) as isize - b as isize;
+                    if let (Some(y), Some(x)) = (boundary.read(y, rows), boundary.read(x, cols)) {
+                        let index = (y * cols + x) * channels + channel;
+                        sum += weight * values[index];
+                        all_valid &= valid(index);
+                    }
+                }
+                out.push(sum);
+                validity.push(all_valid);
+            }
+        }
+    }
+    (out, validity, out_rows, out_cols)
+}
+
+impl<T: Castable> DataBlob<T> {
+    /// convolve the blob with a kernel reading beyond its edges as the boundary says
+    pub fn convolve(&self, kernel: &Kernel, boundary: Boundary) -> Result<Self, &'static str> {
+        let meta = self.get_meta_data();
+        let mut dimensions = match meta.dimensions.is_empty() {
+            true => vec![self.unit_count(), self.unit_size()],
+            false => meta.dimensions.clone(),
+        };
+        if dimensions.iter().product::<usize>() != self.get_data().len() {
+            return Err("Blob dimensions do not match its data");
+        }
+        if kernel.planar && meta.dimensions.len() < 2 {
+            return Err("Kernels of two dimensions need blobs of at least two dimensions");
+        }
+        let (rows, cols) = match kernel.planar {
+            true => (dimensions[0], dimensions[1]),
+            false => (dimensions[0], 1),
+        };
+        let channels = match rows * cols {
+            0 => 0,
+            cells => self.get_data().len() / cells,
+        };
+        let values: Vec<f64> = self.get_data().iter().map(|value| value.to_f64()).collect();
+        let valid = |index: usize| self.is_valid(index);
+        let (out, validity, out_rows, out_cols) =
+            convolve(&values, &valid, (rows, cols, channels), kernel, boundary);
+        dimensions[0] = out_rows;
+        if kernel.planar {
+            dimensions[1] = out_cols;
+        }
+        let mut meta = meta.clone();
+        if !meta.dimensions.is_empty() {
+            meta.dimensions = dimensions;
+        }
+        let shape: Vec<String> = kernel.shape().iter().map(usize::to_string).collect();
+        meta.record(
+            "convolve",
+            &[
+                ("kernel", shape.join("x")),
+                ("boundary", boundary.name().to_string()),
+            ],
+        );
+        let blob = DataBlob::new(out.into_iter().map(T::from_f64).collect(), meta);
+        match self.get_mask().is_some() && validity.contains(&false) {
+            true => blob.with_mask(ValidityMask::from_bools(validity)),
+            false => Ok(blob),
+        }
+    }
+}
+
+/// ConvolvePipe
+/// A pipe convolving every blob of its input with a kernel, the stream ends at the first
+/// blob the kernel does not fit
+pub struct ConvolvePipe<T> {
+    kernel: Rc<Kernel>,
+    boundary: Boundary,
+    input: Option<Rc<dyn Source<DataBlob<T>>>>,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl<T: Castable> ConvolvePipe<T> {
+    /// constructor from the kernel and the handling of the edges of blobs
+    pub fn new(kernel: Kernel, boundary: Boundary) -> Self {
+        Self {
+            kernel: Rc::new(kernel),
+            boundary,
+            input: None,
+            error: Rc::new(Cell::new(None)),
+        }
+    }
+    /// the failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl<T: Castable> Source<DataBlob<T>> for ConvolvePipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBlob<T>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let error = self.error.clone();
+        error.set(None);
+        let (kernel, boundary) = (self.kernel.clone(), self.boundary);
+        Box::new(input.scan((), move |_, blob| {
+            let result = blob
+                .convolve(&kernel, boundary)
+                .map_err(|failure| error.set(Some(failure)));
+            futures::future::ready(result.ok())
+        }))
+    }
+}
+
+impl<T: Castable> Pipe<DataBlob<T>, DataBlob<T>> for ConvolvePipe<T> {
+    input_connection!(DataBlob<T>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::MetaData;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_convolve_1d() {
+        let signal = DataBlob::new(vec![1.0, 2.0, 3.0, 4.0], MetaData::default());
+        let derivative = Kernel::new(vec![1.0, 0.0, -1.0]).unwrap();
+        let expected = [
+            (Boundary::Zero, vec![2.0, 2.0, 2.0, -3.0]),
+            (Boundary::Clamp, vec![1.0, 2.0, 2.0, 1.0]),
+            (Boundary::Reflect, vec![1.0, 2.0, 2.0, 1.0]),
+            (Boundary::Wrap, vec![-2.0, 2.0, 2.0, -2.0]),
+            (Boundary::Valid, vec![2.0, 2.0]),
+        ];
+        for (boundary, values) in expected {
+            let out = signal.convolve(&derivative, boundary).unwrap();
+            assert_eq!(out.get_data(), &values, "{:?}", boundary);
+        }
+        // stereo frames smooth each channel apart, an invalid sample taints its neighbours
+        let meta = MetaData {
+            unitary_dimensions: vec![2],
+            ..Default::default()
+        };
+        let stereo = DataBlob::new(vec![0.0f32, 4.0, 3.0, 4.0, 6.0, 4.0], meta)
+            .with_mask(ValidityMask::from_bools([
+                true, true, true, true, false, true,
+            ]))
+            .unwrap();
+        let smooth = stereo
+            .convolve(&Kernel::moving_average(2).unwrap(), Boundary::Clamp)
+            .unwrap();
+        assert_eq!(smooth.get_data(), &vec![1.5, 4.0, 4.5, 4.0, 6.0, 4.0]);
+        let validity: Vec<bool> = (0..6).map(|at| smooth.is_valid(at)).collect();
+        assert_eq!(validity, vec![true, true, false, true, false, true]);
+        assert_eq!(
+            smooth.get_meta_data().provenance[0].to_string(),
+            "convolve(kernel=2, boundary=clamp)"
+        );
+    }
+
+    #[test]
+    fn test_convolve_pipe() {
+        let meta = MetaData {
+            dimensions: vec![3, 3],
+            ..Default::default()
+        };
+        let image = DataBlob::new(vec![0u8, 0, 9, 0, 0, 9, 0, 0, 9], meta);
+        let sobel =
+            Kernel::new_2d(vec![1.0, 0.0, -1.0, 2.0, 0.0, -2.0, 1.0, 0.0, -1.0], 3, 3).unwrap();
+        let mut pipe = ConvolvePipe::new(sobel, Boundary::Valid);
+        let line = DataBlob::new(vec![1u8, 2], MetaData::default());
+        pipe.pipe(Rc::new(MockSource::new().items([
+            image.clone(),
+            line,
+            image,
+        ])))
+        .unwrap();
+        let blobs: Vec<DataBlob<u8>> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(blobs.len(), 1);
+        assert_eq!(blobs[0].get_data(), &vec![36]);
+        assert_eq!(blobs[0].get_meta_data().dimensions, vec![1, 1]);
+        assert_eq!(
+            pipe.error(),
+            Some("Kernels of two dimensions need blobs of at least two dimensions")
+        );
+        assert!(Kernel::new_2d(vec![1.0], 1, 2).is_err());
+    }
+}
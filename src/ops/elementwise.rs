@@ -0,0 +1,328 @@
+//! elementwise
+//!
+//! Elementwise arithmetic between streams or against scalars with SIMD fast paths
+
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::rc::Rc;
+use wide::{f32x8, f64x4, i32x8};
+
+/// ElementwiseOp
+/// The arithmetic operations supported by the ElementwisePipe
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ElementwiseOp {
+    Add,
+    Sub,
+    Mul,
+    /// integer division by zero yields zero
+    Div,
+}
+
+/// Elementwise
+/// A trait for the element types the ElementwisePipe can operate on
+pub trait Elementwise: Copy + 'static {
+    /// apply the operation pairwise on the common length of two slices
+    fn apply(op: ElementwiseOp, lhs: &[Self], rhs: &[Self], out: &mut Vec<Self>);
+    /// apply the operation between every element of a slice and a scalar
+    fn apply_scalar(op: ElementwiseOp, lhs: &[Self], rhs: Self, out: &mut Vec<Self>);
+}
+
+macro_rules! float_scalar_op {
+    ($op:expr, $a:expr, $b:expr) => {
+        match $op {
+            ElementwiseOp::Add => $a + $b,
+            ElementwiseOp::Sub => $a - $b,
+            ElementwiseOp::Mul => $a * $b,
+            ElementwiseOp::Div => $a / $b,
+        }
+    };
+}
+
+macro_rules! int_scalar_op {
+    ($op:expr, $a:expr, $b:expr) => {
+        match $op {
+            ElementwiseOp::Add => $a.wrapping_add($b),
+            ElementwiseOp::Sub => $a.wrapping_sub($b),
+            ElementwiseOp::Mul => $a.wrapping_mul($b),
+            ElementwiseOp::Div => $a.checked_div($b).unwrap_or(0),
+        }
+    };
+}
+
+macro_rules! scalar_elementwise {
+  ($scalar_op:ident: $($t:ty),*) => {
+    $( impl Elementwise for $t {
+      fn apply(op: ElementwiseOp, lhs: &[Self], rhs: &[Self], out: &mut Vec<Self>) {
+        out.extend(lhs.iter().zip(rhs.iter()).map(|(a, b)| $scalar_op!(op, *a, *b)));
+      }
+      fn apply_scalar(op: ElementwiseOp, lhs: &[Self], rhs: Self, out: &mut Vec<Self>) {
+        out.extend(lhs.iter().map(|a| $scalar_op!(op, *a, rhs)));
+      }
+    } )*
+  }
+}
+
+scalar_elementwise!(int_scalar_op: i8, u8, i16, u16, u32, i64, u64, i128, u128, isize, usize);
+
+/// SIMD implementation processing full lanes with `$simd` and the remainder element by
+/// element (operations flagged as scalar only skip the vector path altogether)
+macro_rules! simd_elementwise {
+    ($t:ty, $simd:ty, $lanes:expr, $scalar_op:ident, $simd_div:expr) => {
+        impl Elementwise for $t {
+            fn apply(op: ElementwiseOp, lhs: &[Self], rhs: &[Self], out: &mut Vec<Self>) {
+                let length = lhs.len().min(rhs.len());
+                let (lhs, rhs) = (&lhs[..length], &rhs[..length]);
+                out.reserve(length);
+                let vector = op != ElementwiseOp::Div || $simd_div;
+                let mut lhs_lanes = lhs.chunks_exact($lanes);
+                let mut rhs_lanes = rhs.chunks_exact($lanes);
+                if vector {
+                    for (a, b) in (&mut lhs_lanes).zip(&mut rhs_lanes) {
+                        let a = <$simd>::new(a.try_into().unwrap());
+                        let b = <$simd>::new(b.try_into().unwrap());
+                        out.extend_from_slice(&simd_op(op, a, b).to_array());
+                    }
+                }
+                let (lhs, rhs) = if vector {
+                    (lhs_lanes.remainder(), rhs_lanes.remainder())
+                } else {
+                    (lhs, rhs)
+                };
+                out.extend(
+                    lhs.iter()
+                        .zip(rhs.iter())
+                        .map(|(a, b)| $scalar_op!(op, *a, *b)),
+                );
+            }
+            fn apply_scalar(op: ElementwiseOp, lhs: &[Self], rhs: Self, out: &mut Vec<Self>) {
+                out.reserve(lhs.len());
+                let vector = op != ElementwiseOp::Div || $simd_div;
+                let mut lhs_lanes = lhs.chunks_exact($lanes);
+                if vector {
+                    let b = <$simd>::splat(rhs);
+                    for a in &mut lhs_lanes {
+                        let a = <$simd>::new(a.try_into().unwrap());
+                        out.extend_from_slice(&simd_op(op, a, b).to_array());
+                    }
+                }
+                let lhs = if vector { lhs_lanes.remainder() } else { lhs };
+                out.extend(lhs.iter().map(|a| $scalar_op!(op, *a, rhs)));
+            }
+        }
+    };
+}
+
+macro_rules! simd_op_fn {
+    ($name:ident, $simd:ty, $div:expr) => {
+        fn $name(op: ElementwiseOp, a: $simd, b: $simd) -> $simd {
+            match op {
+                ElementwiseOp::Add => a + b,
+                ElementwiseOp::Sub => a - b,
+                ElementwiseOp::Mul => a * b,
+                ElementwiseOp::Div => $div(a, b),
+            }
+        }
+    };
+}
+
+mod f32_lanes {
+    use super::*;
+    simd_op_fn!(simd_op, f32x8, |a: f32x8, b: f32x8| a / b);
+    simd_elementwise!(f32, f32x8, 8, float_scalar_op, true);
+}
+
+mod f64_lanes {
+    use super::*;
+    simd_op_fn!(simd_op, f64x4, |a: f64x4, b: f64x4| a / b);
+    simd_elementwise!(f64, f64x4, 4, float_scalar_op, true);
+}
+
+mod i32_lanes {
+    use super::*;
+    // integer division is never vectorized, this arm is unreachable
+    simd_op_fn!(simd_op, i32x8, |a: i32x8, _: i32x8| a);
+    simd_elementwise!(i32, i32x8, 8, int_scalar_op, false);
+}
+
+/// Operand
+/// The right hand side of an elementwise operation
+pub enum Operand<T> {
+    /// the same value for every element
+    Scalar(T),
+    /// the element at the same position in another stream
+    Source(Rc<dyn Source<T>>),
+}
+
+impl<T: Copy> Clone for Operand<T> {
+    fn clone(&self) -> Self {
+        match self {
+            Operand::Scalar(value) => Operand::Scalar(*value),
+            Operand::Source(source) => Operand::Source(source.clone()),
+        }
+    }
+}
+
+/// default number of elements processed at once
+const DEFAULT_BATCH_SIZE: usize = 1024;
+
+/// ElementwisePipe
+/// A pipe applying an arithmetic operation between its input stream and an operand
+///
+/// elements are grouped in batches of readily available items so that the vectorized
+/// kernels can process them, with a stream operand the output stops with the shortest
+/// of the two streams
+pub struct ElementwisePipe<T> {
+    op: ElementwiseOp,
+    rhs: Operand<T>,
+    batch_size: usize,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T: Elementwise> ElementwisePipe<T> {
+    /// constructor
+    pub fn new(op: ElementwiseOp, rhs: Operand<T>) -> Self {
+        Self {
+            op,
+            rhs,
+            batch_size: DEFAULT_BATCH_SIZE,
+            input: None,
+        }
+    }
+    /// operation against a scalar
+    pub fn with_scalar(op: ElementwiseOp, rhs: T) -> Self {
+        Self::new(op, Operand::Scalar(rhs))
+    }
+    /// operation against the elements of another source
+    pub fn with_source(op: ElementwiseOp, rhs: Rc<dyn Source<T>>) -> Self {
+        Self::new(op, Operand::Source(rhs))
+    }
+    /// multiply every element by a factor
+    pub fn scale(factor: T) -> Self {
+        Self::with_scalar(ElementwiseOp::Mul, factor)
+    }
+    /// set the maximum number of elements processed at once
+    pub fn set_batch_size(&mut self, batch_size: usize) -> Result<(), &'static str> {
+        if batch_size == 0 {
+            return Err("Batch size must be strictly positive");
+        }
+        self.batch_size = batch_size;
+        Ok(())
+    }
+}
+
+impl<T: Elementwise> Source<T> for ElementwisePipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let op = self.op;
+        let batches: Pin<Box<dyn Stream<Item = Vec<T>>>> = match &self.rhs {
+            Operand::Scalar(rhs) => {
+                let rhs = *rhs;
+                Box::pin(input.ready_chunks(self.batch_size).map(move |lhs| {
+                    let mut out = Vec::with_capacity(lhs.len());
+                    T::apply_scalar(op, &lhs, rhs, &mut out);
+                    out
+                }))
+            }
+            Operand::Source(rhs) => Box::pin(
+                input
+                    .zip(Pin::from(rhs.stream()))
+                    .ready_chunks(self.batch_size)
+                    .map(move |pairs| {
+                        let (lhs, rhs): (Vec<T>, Vec<T>) = pairs.into_iter().unzip();
+                        let mut out = Vec::with_capacity(lhs.len());
+                        T::apply(op, &lhs, &rhs, &mut out);
+                        out
+                    }),
+            ),
+        };
+        Box::new(batches.flat_map(stream::iter))
+    }
+}
+
+impl<T: Elementwise> Pipe<T, T> for ElementwisePipe<T> {
+    input_connection!(T);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::{DataBlob, MetaData};
+    use futures::executor::block_on;
+
+    fn make_source<T>(data: Vec<T>) -> Rc<dyn Source<T>>
+    where
+        T: Clone + 'static,
+    {
+        Rc::new(DataBlob::new(data, MetaData::default()))
+    }
+
+    fn run<T: Elementwise>(pipe: &ElementwisePipe<T>) -> Vec<T> {
+        block_on(Pin::from(pipe.stream()).collect())
+    }
+
+    #[test]
+    fn test_float_kernels() {
+        let lhs: Vec<f32> = (0..19).map(|x| x as f32).collect();
+        let rhs: Vec<f32> = (0..19).map(|x| 2.0 + x as f32).collect();
+        for op in [
+            ElementwiseOp::Add,
+            ElementwiseOp::Sub,
+            ElementwiseOp::Mul,
+            ElementwiseOp::Div,
+        ] {
+            let mut out = Vec::new();
+            f32::apply(op, &lhs, &rhs, &mut out);
+            let expected: Vec<f32> = lhs
+                .iter()
+                .zip(rhs.iter())
+                .map(|(a, b)| float_scalar_op!(op, *a, *b))
+                .collect();
+            assert_eq!(out, expected, "SIMD kernel disagrees with scalar path");
+        }
+    }
+
+    #[test]
+    fn test_int_kernels() {
+        let mut out = Vec::new();
+        i32::apply(ElementwiseOp::Div, &[8, 9, 1], &[2, 3, 0], &mut out);
+        assert_eq!(out, [4, 3, 0], "Failure on integer division");
+        out.clear();
+        i32::apply_scalar(
+            ElementwiseOp::Mul,
+            &(0..10).collect::<Vec<i32>>(),
+            3,
+            &mut out,
+        );
+        assert_eq!(out, (0..10).map(|x| 3 * x).collect::<Vec<i32>>());
+        out.clear();
+        i32::apply(ElementwiseOp::Add, &[i32::MAX], &[1], &mut out);
+        assert_eq!(out, [i32::MIN], "Integer overflow does not wrap");
+    }
+
+    #[test]
+    fn test_scalar_pipe() {
+        let mut pipe = ElementwisePipe::scale(0.5_f64);
+        assert!(run(&pipe).is_empty(), "Unconnected pipe produced data");
+        pipe.pipe(make_source((0..10).map(|x| x as f64).collect()))
+            .unwrap();
+        pipe.set_batch_size(3).unwrap();
+        let expected: Vec<f64> = (0..10).map(|x| x as f64 * 0.5).collect();
+        assert_eq!(run(&pipe), expected);
+        assert!(pipe.set_batch_size(0).is_err());
+    }
+
+    #[test]
+    fn test_source_pipe() {
+        let mut pipe =
+            ElementwisePipe::with_source(ElementwiseOp::Sub, make_source(vec![1_u8, 1, 1]));
+        pipe.pipe(make_source(vec![5_u8, 6, 7, 8])).unwrap();
+        assert_eq!(run(&pipe), [4, 5, 6], "Failure to subtract streams");
+        pipe.unpipe();
+        assert!(pipe.get_input().is_none());
+    }
+}
@@ -0,0 +1,798 @@
+//! linalg
+//!
+//! Linear algebra on the blobs of a stream
+//!
+//! a blob of two dimensions `[rows, cols]` is a matrix stored row after row, one of a
+//! single dimension (or none) is a column vector and results against it are vectors again.
+//! Matrices cannot hold missing values, products and solutions combine the units of their
+//! operands as arithmetic on blobs does
+
+use super::blob::combine_units;
+use super::elementwise::ElementwiseOp;
+use crate::data_bucket::{DataBlob, DataBucket, DataBucketBlob, MetaData};
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// a matrix held apart from its blob, row after row
+struct Matrix {
+    rows: usize,
+    cols: usize,
+    values: Vec<f64>,
+    vector: bool,
+}
+
+impl Matrix {
+    fn from_blob(blob: &DataBlob<f64>) -> Result<Self, &'static str> {
+        if blob.null_count() > 0 {
+            return Err("Matrices cannot hold missing values");
+        }
+        let values = blob.get_data().clone();
+        let (rows, cols, vector) = match blob.get_meta_data().dimensions[..] {
+            [] => (values.len(), 1, true),
+            [rows] => (rows, 1, true),
+            [rows, cols] => (rows, cols, false),
+            _ => return Err("Matrices have at most two dimensions"),
+        };
+        if rows * cols != values.len() {
+            return Err("Blob dimensions do not match its data");
+        }
+        Ok(Self {
+            rows,
+            cols,
+            values,
+            vector,
+        })
+    }
+    fn zeros(rows: usize, cols: usize) -> Self {
+        Self {
+            rows,
+            cols,
+            values: vec![0.0; rows * cols],
+            vector: false,
+        }
+    }
+    fn identity(size: usize) -> Self {
+        let mut identity = Self::zeros(size, size);
+        for i in 0..size {
+            identity.values[i * size + i] = 1.0;
+        }
+        identity
+    }
+    fn at(&self, row: usize, col: usize) -> f64 {
+        self.values[row * self.cols + col]
+    }
+    fn set(&mut self, row: usize, col: usize, value: f64) {
+        self.values[row * self.cols + col] = value;
+    }
+    fn square(&self) -> Result<usize, &'static str> {
+        match self.rows == self.cols {
+            true => Ok(self.rows),
+            false => Err("Matrix is not square"),
+        }
+    }
+    fn product(&self, rhs: &Matrix) -> Result<Matrix, &'static str> {
+        if self.cols != rhs.rows {
+            return Err("Matrix dimensions do not match for a product");
+        }
+        let mut out = Self::zeros(self.rows, rhs.cols);
+        for i in 0..self.rows {
+            for k in 0..self.cols {
+                let left = self.at(i, k);
+                let (row, out_row) = (k * rhs.cols, i * rhs.cols);
+                for j in 0..rhs.cols {
+                    out.values[out_row + j] += left * rhs.values[row + j];
+                }
+            }
+        }
+        out.vector = rhs.vector;
+        Ok(out)
+    }
+    /// the blob of the matrix with meta data derived from another
+    fn into_blob(self, meta: &MetaData, units: Option<String>) -> DataBlob<f64> {
+        let mut meta = meta.clone();
+        meta.units = units;
+        meta.dimensions = match self.vector {
+            true => vec![self.rows],
+            false => vec![self.rows, self.cols],
+        };
+        meta.unitary_dimensions = match self.vector {
+            true => vec![1],
+            false => vec![self.cols],
+        };
+        meta.axes.clear();
+        DataBlob::new(self.values, meta)
+    }
+}
+
+/// the LU factorization of a square matrix with partial pivoting
+struct Lu {
+    /// the unit lower factor below the diagonal, the upper factor from it
+    factors: Matrix,
+    /// the row of the matrix at every row of the factors
+    permutation: Vec<usize>,
+    /// the sign of the permutation
+    sign: f64,
+}
+
+impl Lu {
+    fn new(matrix: &Matrix) -> Result<Self, &'static str> {
+        let size = matrix.square()?;
+        let mut factors = Matrix::zeros(size, size);
+        factors.values.copy_from_slice(&matrix.values);
+        let (mut permutation, mut sign) = ((0..size).collect::<Vec<usize>>(), 1.0);
+        for col in 0..size {
+            let pivot = (col..size)
+                .max_by(|a, b| {
+                    factors
+                        .at(*a, col)
+                        .abs()
+                        .total_cmp(&factors.at(*b, col).abs())
+                })
+                .unwrap();
+            if factors.at(pivot, col) == 0.0 {
+                return Err("Matrix is singular");
+            }
+            if pivot != col {
+                for k in 0..size {
+                    factors.values.swap(pivot * size + k, col * size + k);
+                }
+                permutation.swap(pivot, col);
+                sign = -sign;
+            }
+            for row in col + 1..size {
+                let factor = factors.at(row, col) / factors.at(col, col);
+                factors.set(row, col, factor);
+                for k in col + 1..size {
+                    let value = factors.at(row, k) - factor * factors.at(col, k);
+                    factors.set(row, k, value);
+                }
+            }
+        }
+        Ok(Self {
+            factors,
+            permutation,
+            sign,
+        })
+    }
+    fn solve(&self, rhs: &Matrix) -> Result<Matrix, &'static str> {
+        let size = self.factors.rows;
+        if rhs.rows != size {
+            return Err("Right hand side does not match the matrix");
+        }
+        let mut out = Matrix::zeros(size, rhs.cols);
+        out.vector = rhs.vector;
+        for col in 0..rhs.cols {
+            let mut x: Vec<f64> = self
+                .permutation
+                .iter()
+                .map(|row| rhs.at(*row, col))
+                .collect();
+            for i in 0..size {
+                for k in 0..i {
+                    x[i] -= self.factors.at(i, k) * x[k];
+                }
+            }
+            for i in (0..size).rev() {
+                for k in i + 1..size {
+                    x[i] -= self.factors.at(i, k) * x[k];
+                }
+                x[i] /= self.factors.at(i, i);
+            }
+            for (i, value) in x.into_iter().enumerate() {
+                out.set(i, col, value);
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Householder QR factorization, Q of `rows x k` and R of `k x cols` with `k` the
+/// smaller dimension
+fn qr(matrix: &Matrix) -> (Matrix, Matrix) {
+    let (rows, cols) = (matrix.rows, matrix.cols);
+    let mut r = Matrix::zeros(rows, cols);
+    r.values.copy_from_slice(&matrix.values);
+    let mut q = Matrix::identity(rows);
+    let k = rows.min(cols);
+    for col in 0..k.min(rows.saturating_sub(1)) {
+        let norm = (col..rows)
+            .map(|i| r.at(i, col).powi(2))
+            .sum::<f64>()
+            .sqrt();
+        if norm == 0.0 {
+            continue;
+        }
+        let alpha = if r.at(col, col) > 0.0 { -norm } else { norm };
+        let mut v: Vec<f64> = (col..rows).map(|i| r.at(i, col)).collect();
+        v[0] -= alpha;
+        let length = v.iter().map(|x| x * x).sum::<f64>();
+        if length == 0.0 {
+            continue;
+        }
+        // reflect the remaining columns of R and accumulate the reflection in Q
+        for j in 0..cols {
+            let dot: f64 = (col..rows).map(|i| v[i - col] * r.at(i, j)).sum();
+            for i in col..rows {
+                let value = r.at(i, j) - 2.0 * v[i - col] * dot / length;
+                r.set(i, j, value);
+            }
+        }
+        for i in 0..rows {
+            let dot: f64 = (col..rows).map(|j| q.at(i, j) * v[j - col]).sum();
+            for j in col..rows {
+                let value = q.at(i, j) - 2.0 * dot * v[j - col] / length;
+                q.set(i, j, value);
+            }
+        }
+    }
+    let mut reduced_q = Matrix::zeros(rows, k);
+    for i in 0..rows {
+        for j in 0..k {
+            reduced_q.set(i, j, q.at(i, j));
+        }
+    }
+    let mut reduced_r = Matrix::zeros(k, cols);
+    for i in 0..k {
+        for j in i..cols {
+            reduced_r.set(i, j, r.at(i, j));
+        }
+    }
+    (reduced_q, reduced_r)
+}
+
+fn symmetric(matrix: &Matrix) -> Result<usize, &'static str> {
+    let size = matrix.square()?;
+    for i in 0..size {
+        for j in 0..i {
+            let (a, b) = (matrix.at(i, j), matrix.at(j, i));
+            if (a - b).abs() > 1e-10 * a.abs().max(b.abs()).max(1.0) {
+                return Err("Matrix is not symmetric");
+            }
+        }
+    }
+    Ok(size)
+}
+
+fn cholesky(matrix: &Matrix) -> Result<Matrix, &'static str> {
+    let size = symmetric(matrix)?;
+    let mut lower = Matrix::zeros(size, size);
+    for i in 0..size {
+        for j in 0..=i {
+            let sum: f64 = (0..j).map(|k| lower.at(i, k) * lower.at(j, k)).sum();
+            let value = matrix.at(i, j) - sum;
+            if i == j {
+                if value <= 0.0 {
+                    return Err("Matrix is not positive definite");
+                }
+                lower.set(i, i, value.sqrt());
+            } else {
+                lower.set(i, j, value / lower.at(j, j));
+            }
+        }
+    }
+    Ok(lower)
+}
+
+/// cyclic Jacobi eigen decomposition of a symmetric matrix, eigenvalues ascending and the
+/// eigenvectors in the columns of the matrix
+fn symmetric_eigen(matrix: &Matrix) -> Result<(Vec<f64>, Matrix), &'static str> {
+    let size = symmetric(matrix)?;
+    let mut a = Matrix::zeros(size, size);
+    a.values.copy_from_slice(&matrix.values);
+    let mut vectors = Matrix::identity(size);
+    let scale = matrix.values.iter().map(|x| x * x).sum::<f64>();
+    for _ in 0..100 {
+        let off: f64 = (0..size)
+            .flat_map(|i| (0..size).filter(move |j| *j != i).map(move |j| (i, j)))
+            .map(|(i, j)| a.at(i, j).powi(2))
+            .sum();
+        if off <= f64::EPSILON * f64::EPSILON * scale {
+            break;
+        }
+        for p in 0..size {
+            for q in p + 1..size {
+                if a.at(p, q) == 0.0 {
+                    continue;
+                }
+                let theta = (a.at(q, q) - a.at(p, p)) / (2.0 * a.at(p, q));
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let t = if theta == 0.0 { 1.0 } else { t };
+                let (c, s) = (1.0 / (t * t + 1.0).sqrt(), t / (t * t + 1.0).sqrt());
+                for k in 0..size {
+                    let (kp, kq) = (a.at(k, p), a.at(k, q));
+                    a.set(k, p, c * kp - s * kq);
+                    a.set(k, q, s * kp + c * kq);
+                }
+                for k in 0..size {
+                    let (pk, qk) = (a.at(p, k), a.at(q, k));
+                    a.set(p, k, c * pk - s * qk);
+                    a.set(q, k, s * pk + c * qk);
+                }
+                for k in 0..size {
+                    let (kp, kq) = (vectors.at(k, p), vectors.at(k, q));
+                    vectors.set(k, p, c * kp - s * kq);
+                    vectors.set(k, q, s * kp + c * kq);
+                }
+            }
+        }
+    }
+    let mut order: Vec<usize> = (0..size).collect();
+    order.sort_by(|i, j| a.at(*i, *i).total_cmp(&a.at(*j, *j)));
+    let values = order.iter().map(|i| a.at(*i, *i)).collect();
+    let mut sorted = Matrix::zeros(size, size);
+    for (col, from) in order.iter().enumerate() {
+        for row in 0..size {
+            sorted.set(row, col, vectors.at(row, *from));
+        }
+    }
+    Ok((values, sorted))
+}
+
+/// Decomposition
+/// The factorizations of matrices, emitted as buckets of their factors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Decomposition {
+    /// `P A = L U` with partial pivoting: the blobs `p` (a permutation matrix), `l` (unit
+    /// lower triangular) and `u` (upper triangular)
+    Lu,
+    /// `A = Q R` reduced to the smaller dimension: the blobs `q` (orthonormal columns) and
+    /// `r` (upper triangular)
+    Qr,
+    /// `A = L Lᵀ` of a symmetric positive definite matrix: the blob `l`
+    Cholesky,
+    /// `A = V diag(w) Vᵀ` of a symmetric matrix: the blobs `values` (ascending) and
+    /// `vectors` (in columns)
+    SymmetricEigen,
+}
+
+impl Decomposition {
+    fn name(self) -> &'static str {
+        match self {
+            Decomposition::Lu => "lu",
+            Decomposition::Qr => "qr",
+            Decomposition::Cholesky => "cholesky",
+            Decomposition::SymmetricEigen => "symmetric_eigen",
+        }
+    }
+}
+
+impl DataBlob<f64> {
+    /// the matrix product with another blob
+    pub fn matmul(&self, rhs: &DataBlob<f64>) -> Result<Self, &'static str> {
+        let product = Matrix::from_blob(self)?.product(&Matrix::from_blob(rhs)?)?;
+        let (meta, rhs_meta) = (self.get_meta_data(), rhs.get_meta_data());
+        let units = combine_units(
+            ElementwiseOp::Mul,
+            meta.units.as_deref(),
+            rhs_meta.units.as_deref(),
+        )?;
+        let mut blob = product.into_blob(meta, units);
+        blob.get_mut_meta_data()
+            .record("matmul", &[("rhs", rhs_meta.name.clone())]);
+        Ok(blob)
+    }
+    /// the transposed matrix
+    pub fn transpose(&self) -> Result<Self, &'static str> {
+        let matrix = Matrix::from_blob(self)?;
+        let mut out = Matrix::zeros(matrix.cols, matrix.rows);
+        for i in 0..matrix.rows {
+            for j in 0..matrix.cols {
+                out.set(j, i, matrix.at(i, j));
+            }
+        }
+        let meta = self.get_meta_data();
+        let mut blob = out.into_blob(meta, meta.units.clone());
+        blob.get_mut_meta_data().record("transpose", &[]);
+        Ok(blob)
+    }
+    /// the solution `x` of `A x = b` for the square matrix `A` of the blob, `b` a vector
+    /// or a matrix of right hand sides in columns
+    pub fn solve(&self, rhs: &DataBlob<f64>) -> Result<DataBlob<f64>, &'static str> {
+        let solution = Lu::new(&Matrix::from_blob(self)?)?.solve(&Matrix::from_blob(rhs)?)?;
+        solved(solution, self.get_meta_data(), rhs.get_meta_data())
+    }
+    /// the inverse of the square matrix
+    pub fn inverse(&self) -> Result<Self, &'static str> {
+        let matrix = Matrix::from_blob(self)?;
+        let inverse = Lu::new(&matrix)?.solve(&Matrix::identity(matrix.rows))?;
+        let meta = self.get_meta_data();
+        let units = combine_units(ElementwiseOp::Div, Some("1"), meta.units.as_deref())?;
+        let mut blob = inverse.into_blob(meta, units);
+        blob.get_mut_meta_data().record("inverse", &[]);
+        Ok(blob)
+    }
+    /// the determinant of the square matrix
+    pub fn determinant(&self) -> Result<f64, &'static str> {
+        let lu = match Lu::new(&Matrix::from_blob(self)?) {
+            Ok(lu) => lu,
+            Err("Matrix is singular") => return Ok(0.0),
+            Err(failure) => return Err(failure),
+        };
+        let size = lu.factors.rows;
+        Ok((0..size).map(|i| lu.factors.at(i, i)).product::<f64>() * lu.sign)
+    }
+    /// the factors of the matrix, named after their part in the decomposition
+    pub fn decompose(&self, decomposition: Decomposition) -> Result<DataBucket, &'static str> {
+        let matrix = Matrix::from_blob(self)?;
+        let meta = self.get_meta_data();
+        let units = meta.units.clone();
+        let factors = match decomposition {
+            Decomposition::Lu => {
+                let lu = Lu::new(&matrix)?;
+                let size = matrix.rows;
+                let (mut p, mut l, mut u) = (
+                    Matrix::zeros(size, size),
+                    Matrix::identity(size),
+                    Matrix::zeros(size, size),
+                );
+                for (row, from) in lu.permutation.iter().enumerate() {
+                    p.set(row, *from, 1.0);
+                    for col in 0..size {
+                        match col < row {
+                            true => l.set(row, col, lu.factors.at(row, col)),
+                            false => u.set(row, col, lu.factors.at(row, col)),
+                        }
+                    }
+                }
+                vec![("p", p, None), ("l", l, None), ("u", u, units)]
+            }
+            Decomposition::Qr => {
+                let (q, r) = qr(&matrix);
+                vec![("q", q, None), ("r", r, units)]
+            }
+            Decomposition::Cholesky => vec![("l", cholesky(&matrix)?, None)],
+            Decomposition::SymmetricEigen => {
+                let (values, vectors) = symmetric_eigen(&matrix)?;
+                let values = Matrix {
+                    rows: values.len(),
+                    cols: 1,
+                    values,
+                    vector: true,
+                };
+                vec![("values", values, units), ("vectors", vectors, None)]
+            }
+        };
+        let mut bucket = DataBucket::new();
+        for (name, factor, units) in factors {
+            let mut blob = factor.into_blob(meta, units);
+            let meta = blob.get_mut_meta_data();
+            meta.name = name.to_string();
+            meta.record("decompose", &[("method", decomposition.name().to_string())]);
+            bucket.add_blob(DataBucketBlob::Float64(blob));
+        }
+        Ok(bucket)
+    }
+}
+
+/// the blob of the solution of a system
+fn solved(solution: Matrix, a: &MetaData, b: &MetaData) -> Result<DataBlob<f64>, &'static str> {
+    let units = combine_units(ElementwiseOp::Div, b.units.as_deref(), a.units.as_deref())?;
+    let mut blob = solution.into_blob(b, units);
+    blob.get_mut_meta_data()
+        .record("solve", &[("matrix", a.name.clone())]);
+    Ok(blob)
+}
+
+/// MatrixOperand
+/// The other matrix of a linear algebra operation
+pub enum MatrixOperand {
+    /// the same matrix for every blob
+    Matrix(Box<DataBlob<f64>>),
+    /// the blob at the same position in another stream
+    Source(Rc<dyn Source<DataBlob<f64>>>),
+}
+
+type MatrixResults = Pin<Box<dyn Stream<Item = Result<DataBlob<f64>, &'static str>>>>;
+
+/// end a stream of results at its first failure
+fn until_failure(
+    results: MatrixResults,
+    error: &Rc<Cell<Option<&'static str>>>,
+) -> Box<dyn Stream<Item = DataBlob<f64>>> {
+    let error = error.clone();
+    error.set(None);
+    Box::new(results.scan((), move |_, result| {
+        futures::future::ready(result.map_err(|failure| error.set(Some(failure))).ok())
+    }))
+}
+
+/// MatMulPipe
+/// A pipe multiplying every blob of its input on the right by a matrix operand, the
+/// stream ends at the first pair of blobs that cannot be multiplied
+pub struct MatMulPipe {
+    rhs: Rc<MatrixOperand>,
+    input: Option<Rc<dyn Source<DataBlob<f64>>>>,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl MatMulPipe {
+    /// constructor
+    pub fn new(rhs: MatrixOperand) -> Self {
+        Self {
+            rhs: Rc::new(rhs),
+            input: None,
+            error: Rc::new(Cell::new(None)),
+        }
+    }
+    /// product with the same matrix for every blob
+    pub fn with_matrix(rhs: DataBlob<f64>) -> Self {
+        Self::new(MatrixOperand::Matrix(Box::new(rhs)))
+    }
+    /// product with the blobs of another source
+    pub fn with_source(rhs: Rc<dyn Source<DataBlob<f64>>>) -> Self {
+        Self::new(MatrixOperand::Source(rhs))
+    }
+    /// the failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl Source<DataBlob<f64>> for MatMulPipe {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBlob<f64>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let results: MatrixResults = match self.rhs.as_ref() {
+            MatrixOperand::Matrix(rhs) => {
+                let rhs = rhs.clone();
+                Box::pin(input.map(move |lhs| lhs.matmul(&rhs)))
+            }
+            MatrixOperand::Source(rhs) => Box::pin(
+                input
+                    .zip(Pin::from(rhs.stream()))
+                    .map(|(lhs, rhs)| lhs.matmul(&rhs)),
+            ),
+        };
+        until_failure(results, &self.error)
+    }
+}
+
+impl Pipe<DataBlob<f64>, DataBlob<f64>> for MatMulPipe {
+    input_connection!(DataBlob<f64>);
+}
+
+/// SolvePipe
+/// A pipe solving `A x = b` for every right hand side `b` of its input against a square
+/// matrix operand `A`, the stream ends at the first system that cannot be solved
+///
+/// a fixed matrix is factorized once when the stream starts
+pub struct SolvePipe {
+    matrix: Rc<MatrixOperand>,
+    input: Option<Rc<dyn Source<DataBlob<f64>>>>,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl SolvePipe {
+    /// constructor
+    pub fn new(matrix: MatrixOperand) -> Self {
+        Self {
+            matrix: Rc::new(matrix),
+            input: None,
+            error: Rc::new(Cell::new(None)),
+        }
+    }
+    /// systems of the same matrix for every blob
+    pub fn with_matrix(matrix: DataBlob<f64>) -> Self {
+        Self::new(MatrixOperand::Matrix(Box::new(matrix)))
+    }
+    /// systems of the matrices of another source
+    pub fn with_source(matrix: Rc<dyn Source<DataBlob<f64>>>) -> Self {
+        Self::new(MatrixOperand::Source(matrix))
+    }
+    /// the failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl Source<DataBlob<f64>> for SolvePipe {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBlob<f64>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let results: MatrixResults = match self.matrix.as_ref() {
+            MatrixOperand::Matrix(matrix) => {
+                let factorized = Matrix::from_blob(matrix).and_then(|matrix| Lu::new(&matrix));
+                let meta = matrix.get_meta_data().clone();
+                Box::pin(input.map(move |rhs| {
+                    let lu = factorized.as_ref().map_err(|failure| *failure)?;
+                    let solution = lu.solve(&Matrix::from_blob(&rhs)?)?;
+                    solved(solution, &meta, rhs.get_meta_data())
+                }))
+            }
+            MatrixOperand::Source(matrix) => Box::pin(
+                input
+                    .zip(Pin::from(matrix.stream()))
+                    .map(|(rhs, matrix)| matrix.solve(&rhs)),
+            ),
+        };
+        until_failure(results, &self.error)
+    }
+}
+
+impl Pipe<DataBlob<f64>, DataBlob<f64>> for SolvePipe {
+    input_connection!(DataBlob<f64>);
+}
+
+/// DecomposePipe
+/// A pipe factorizing every matrix of its input into a bucket of its factors, the stream
+/// ends at the first matrix that cannot be factorized
+pub struct DecomposePipe {
+    decomposition: Decomposition,
+    input: Option<Rc<dyn Source<DataBlob<f64>>>>,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl DecomposePipe {
+    /// constructor
+    pub fn new(decomposition: Decomposition) -> Self {
+        Self {
+            decomposition,
+            input: None,
+            error: Rc::new(Cell::new(None)),
+        }
+    }
+    /// the failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl Source<DataBucket> for DecomposePipe {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBucket>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let error = self.error.clone();
+        error.set(None);
+        let decomposition = self.decomposition;
+        Box::new(input.scan((), move |_, matrix| {
+            let result = matrix
+                .decompose(decomposition)
+                .map_err(|failure| error.set(Some(failure)));
+            futures::future::ready(result.ok())
+        }))
+    }
+}
+
+impl Pipe<DataBlob<f64>, DataBucket> for DecomposePipe {
+    input_connection!(DataBlob<f64>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn matrix(rows: usize, cols: usize, values: &[f64], units: Option<&str>) -> DataBlob<f64> {
+        let meta = MetaData {
+            name: "a".to_string(),
+            units: units.map(str::to_string),
+            dimensions: vec![rows, cols],
+            unitary_dimensions: vec![cols],
+            ..Default::default()
+        };
+        DataBlob::new(values.to_vec(), meta)
+    }
+
+    fn factor(bucket: &DataBucket, name: &str) -> DataBlob<f64> {
+        match bucket.get_blob(&name.to_string()) {
+            Some(DataBucketBlob::Float64(blob)) => blob.clone(),
+            _ => panic!("Missing factor {}", name),
+        }
+    }
+
+    fn close(a: &[f64], b: &[f64]) -> bool {
+        a.len() == b.len() && a.iter().zip(b).all(|(x, y)| (x - y).abs() < 1e-9)
+    }
+
+    #[test]
+    fn test_matrix_operations() {
+        let a = matrix(2, 3, &[1.0, 2.0, 3.0, 4.0, 5.0, 6.0], Some("m"));
+        let b = matrix(3, 1, &[1.0, 0.0, -1.0], Some("s"));
+        let product = a.matmul(&b).unwrap();
+        assert_eq!(product.get_data(), &vec![-2.0, -2.0]);
+        assert_eq!(product.get_meta_data().dimensions, vec![2, 1]);
+        assert_eq!(product.get_meta_data().units.as_deref(), Some("m*s"));
+        assert_eq!(
+            a.transpose().unwrap().get_meta_data().dimensions,
+            vec![3, 2]
+        );
+        assert!(a.matmul(&a).is_err());
+        let square = matrix(3, 3, &[2.0, 1.0, 1.0, 1.0, 3.0, 2.0, 1.0, 0.0, 0.0], None);
+        let rhs = DataBlob::new(vec![4.0, 5.0, 6.0], MetaData::default());
+        let x = square.solve(&rhs).unwrap();
+        assert_eq!(x.get_meta_data().dimensions, vec![3]);
+        assert!(close(
+            square.matmul(&x).unwrap().get_data(),
+            &[4.0, 5.0, 6.0]
+        ));
+        assert!((square.determinant().unwrap() - -1.0).abs() < 1e-12);
+        let identity = square.matmul(&square.inverse().unwrap()).unwrap();
+        assert!(close(
+            identity.get_data(),
+            &[1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]
+        ));
+        let singular = matrix(2, 2, &[1.0, 2.0, 2.0, 4.0], None);
+        assert_eq!(singular.determinant(), Ok(0.0));
+        assert_eq!(singular.inverse().err(), Some("Matrix is singular"));
+    }
+
+    #[test]
+    fn test_decompositions() {
+        let a = matrix(3, 3, &[4.0, 2.0, 0.6, 2.0, 5.0, 1.0, 0.6, 1.0, 3.0], None);
+        let lu = a.decompose(Decomposition::Lu).unwrap();
+        let pa = factor(&lu, "p").matmul(&a).unwrap();
+        let product = factor(&lu, "l").matmul(&factor(&lu, "u")).unwrap();
+        assert!(close(pa.get_data(), product.get_data()));
+        let tall = matrix(3, 2, &[1.0, 2.0, 3.0, 4.0, 5.0, 7.0], None);
+        let qr = tall.decompose(Decomposition::Qr).unwrap();
+        let (q, r) = (factor(&qr, "q"), factor(&qr, "r"));
+        assert_eq!(r.get_meta_data().dimensions, vec![2, 2]);
+        assert!(close(q.matmul(&r).unwrap().get_data(), tall.get_data()));
+        let qtq = q.transpose().unwrap().matmul(&q).unwrap();
+        assert!(close(qtq.get_data(), &[1.0, 0.0, 0.0, 1.0]));
+        let l = factor(&a.decompose(Decomposition::Cholesky).unwrap(), "l");
+        let llt = l.matmul(&l.transpose().unwrap()).unwrap();
+        assert!(close(llt.get_data(), a.get_data()));
+        let eigen = a.decompose(Decomposition::SymmetricEigen).unwrap();
+        let (values, vectors) = (factor(&eigen, "values"), factor(&eigen, "vectors"));
+        assert!(values.get_data().windows(2).all(|pair| pair[0] <= pair[1]));
+        let av = a.matmul(&vectors).unwrap();
+        for (at, value) in av.get_data().iter().enumerate() {
+            let expected = vectors.get_data()[at] * values.get_data()[at % 3];
+            assert!((value - expected).abs() < 1e-9);
+        }
+        let indefinite = matrix(2, 2, &[1.0, 2.0, 2.0, 1.0], None);
+        assert_eq!(
+            indefinite.decompose(Decomposition::Cholesky).err(),
+            Some("Matrix is not positive definite")
+        );
+    }
+
+    #[test]
+    fn test_linalg_pipes() {
+        let a = matrix(2, 2, &[2.0, 0.0, 0.0, 4.0], Some("N"));
+        let systems = [
+            DataBlob::new(vec![2.0, 8.0], MetaData::default()),
+            DataBlob::new(vec![1.0, 2.0, 3.0], MetaData::default()),
+            DataBlob::new(vec![4.0, 4.0], MetaData::default()),
+        ];
+        let mut solve = SolvePipe::with_matrix(a.clone());
+        solve
+            .pipe(Rc::new(MockSource::new().items(systems)))
+            .unwrap();
+        let solutions: Vec<DataBlob<f64>> = block_on(Pin::from(solve.stream()).collect());
+        assert_eq!(solutions.len(), 1);
+        assert_eq!(solutions[0].get_data(), &vec![1.0, 2.0]);
+        assert_eq!(
+            solve.error(),
+            Some("Right hand side does not match the matrix")
+        );
+        let mut product = MatMulPipe::with_source(Rc::new(MockSource::new().items([a.clone()])));
+        product
+            .pipe(Rc::new(MockSource::new().items([a.clone(), a])))
+            .unwrap();
+        let products: Vec<DataBlob<f64>> = block_on(Pin::from(product.stream()).collect());
+        assert_eq!(products.len(), 1);
+        assert_eq!(products[0].get_data(), &vec![4.0, 0.0, 0.0, 16.0]);
+        assert_eq!(products[0].get_meta_data().units.as_deref(), Some("N^2"));
+        let mut decompose = DecomposePipe::new(Decomposition::Qr);
+        let wide = matrix(1, 2, &[3.0, 4.0], None);
+        decompose
+            .pipe(Rc::new(MockSource::new().items([wide])))
+            .unwrap();
+        let buckets: Vec<DataBucket> = block_on(Pin::from(decompose.stream()).collect());
+        assert_eq!(factor(&buckets[0], "q").get_data(), &vec![1.0]);
+        assert_eq!(factor(&buckets[0], "r").get_data(), &vec![3.0, 4.0]);
+    }
+}
@@ -0,0 +1,470 @@
+//! series
+//!
+//! Reducing time series of events, kept in timestamp order, for plotting, and aligning
+//! irregular ones onto regular grids
+
+use crate::data_bucket::mask::ValidityMask;
+use crate::data_bucket::{DataBlob, MetaData};
+use crate::event::Event;
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Downsampling
+/// How a series is reduced to a number of points
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Downsampling {
+    /// Largest-Triangle-Three-Buckets: the first and last points and, from each of the
+    /// buckets between them, the point spanning the largest triangle with the points kept
+    /// around it
+    Lttb,
+    /// the smallest and largest values of each of `points / 2` buckets, in time order
+    MinMax,
+}
+
+/// the range of the positions of a bucket among `len` points cut into `count` buckets
+fn bucket(index: usize, count: usize, len: usize) -> std::ops::Range<usize> {
+    index * len / count..(index + 1) * len / count
+}
+
+/// keep at most `points` events of a series with Largest-Triangle-Three-Buckets, series
+/// short enough and thresholds below 3 keep every event
+pub fn lttb(series: &[Event<f64>], points: usize) -> Vec<Event<f64>> {
+    if points < 3 || series.len() <= points {
+        return series.to_vec();
+    }
+    let point = |event: &Event<f64>| (event.timestamp as f64, event.value);
+    // the first and last events are always kept, the others are cut into buckets
+    let inner = &series[1..series.len() - 1];
+    let count = points - 2;
+    let mut kept = vec![series[0].clone()];
+    let mut previous = point(&series[0]);
+    for index in 0..count {
+        let next = match index + 1 < count {
+            true => &inner[bucket(index + 1, count, inner.len())],
+            false => &series[series.len() - 1..],
+        };
+        let (times, values) = next
+            .iter()
+            .map(point)
+            .fold((0.0, 0.0), |sum, (t, v)| (sum.0 + t, sum.1 + v));
+        let average = (times / next.len() as f64, values / next.len() as f64);
+        let area = |event: &Event<f64>| {
+            let (t, v) = point(event);
+            ((previous.0 - average.0) * (v - previous.1)
+                - (previous.0 - t) * (average.1 - previous.1))
+                .abs()
+        };
+        let chosen = inner[bucket(index, count, inner.len())]
+            .iter()
+            .max_by(|a, b| area(a).total_cmp(&area(b)))
+            .unwrap();
+        previous = point(chosen);
+        kept.push(chosen.clone());
+    }
+    kept.push(series[series.len() - 1].clone());
+    kept
+}
+
+/// keep at most `points` events of a series with the smallest and largest values of each
+/// of `points / 2` buckets, series short enough and thresholds below 2 keep every event
+pub fn min_max(series: &[Event<f64>], points: usize) -> Vec<Event<f64>> {
+    if points < 2 || series.len() <= points {
+        return series.to_vec();
+    }
+    let count = points / 2;
+    let mut kept = Vec::with_capacity(points);
+    for index in 0..count {
+        let events = &series[bucket(index, count, series.len())];
+        let position = |pick: fn(&f64, &f64) -> bool| {
+            (1..events.len()).fold(0, |best, at| {
+                match pick(&events[at].value, &events[best].value) {
+                    true => at,
+                    false => best,
+                }
+            })
+        };
+        let (low, high) = (position(|a, b| a < b), position(|a, b| a > b));
+        kept.push(events[low.min(high)].clone());
+        if low != high {
+            kept.push(events[low.max(high)].clone());
+        }
+    }
+    kept
+}
+
+/// DownsamplePipe
+/// A pipe reducing a series of events to a number of points preserving its shape
+///
+/// the series is buffered until the input ends then emitted reduced, events are expected
+/// in timestamp order
+pub struct DownsamplePipe {
+    method: Downsampling,
+    points: usize,
+    input: Option<Rc<dyn Source<Event<f64>>>>,
+}
+
+impl DownsamplePipe {
+    /// constructor reducing with a method to at most a number of points
+    pub fn new(method: Downsampling, points: usize) -> Self {
+        Self {
+            method,
+            points,
+            input: None,
+        }
+    }
+    /// constructor reducing with Largest-Triangle-Three-Buckets
+    pub fn lttb(points: usize) -> Self {
+        Self::new(Downsampling::Lttb, points)
+    }
+    /// constructor reducing to the extremes of buckets
+    pub fn min_max(points: usize) -> Self {
+        Self::new(Downsampling::MinMax, points)
+    }
+}
+
+impl Source<Event<f64>> for DownsamplePipe {
+    fn stream(&self) -> Box<dyn Stream<Item = Event<f64>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let (method, points) = (self.method, self.points);
+        Box::new(
+            stream::once(input.collect::<Vec<_>>()).flat_map(move |series| {
+                stream::iter(match method {
+                    Downsampling::Lttb => lttb(&series, points),
+                    Downsampling::MinMax => min_max(&series, points),
+                })
+            }),
+        )
+    }
+}
+
+impl Pipe<Event<f64>, Event<f64>> for DownsamplePipe {
+    input_connection!(Event<f64>);
+}
+
+/// Interpolation
+/// How the values between the events of a series are estimated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    /// the value of the closest event, the earlier one on ties
+    Nearest,
+    /// the straight line between the events around
+    Linear,
+    /// a natural cubic spline through every event
+    Spline,
+}
+
+impl Interpolation {
+    fn name(self) -> &'static str {
+        match self {
+            Interpolation::Nearest => "nearest",
+            Interpolation::Linear => "linear",
+            Interpolation::Spline => "spline",
+        }
+    }
+}
+
+/// the second derivatives of the natural cubic spline through points of increasing times
+fn spline_curvatures(times: &[f64], values: &[f64]) -> Vec<f64> {
+    let n = times.len();
+    let mut curvatures = vec![0.0; n];
+    if n < 3 {
+        return curvatures;
+    }
+    // forward elimination of the tridiagonal system, the ends have no curvature
+    let (mut diagonal, mut right) = (vec![0.0; n], vec![0.0; n]);
+    for i in 1..n - 1 {
+        let (before, after) = (times[i] - times[i - 1], times[i + 1] - times[i]);
+        let slope = (values[i + 1] - values[i]) / after - (values[i] - values[i - 1]) / before;
+        let lower = if i > 1 { before / diagonal[i - 1] } else { 0.0 };
+        diagonal[i] = 2.0 * (before + after) - lower * before;
+        right[i] = 6.0 * slope - lower * right[i - 1];
+    }
+    for i in (1..n - 1).rev() {
+        let after = times[i + 1] - times[i];
+        curvatures[i] = (right[i] - after * curvatures[i + 1]) / diagonal[i];
+    }
+    curvatures
+}
+
+/// estimate the values of a series at times, the events must have strictly increasing
+/// timestamps and times outside of the series take the value of its closest end
+pub fn interpolate(series: &[Event<f64>], method: Interpolation, times: &[i64]) -> Vec<f64> {
+    let xs: Vec<f64> = series.iter().map(|event| event.timestamp as f64).collect();
+    let ys: Vec<f64> = series.iter().map(|event| event.value).collect();
+    let curvatures = match method {
+        Interpolation::Spline => spline_curvatures(&xs, &ys),
+        _ => Vec::new(),
+    };
+    times
+        .iter()
+        .map(|time| {
+            let after = series.partition_point(|event| event.timestamp < *time);
+            if after == series.len() {
+                return ys[series.len() - 1];
+            }
+            if after == 0 || series[after].timestamp == *time {
+                return ys[after];
+            }
+            let before = after - 1;
+            let (x0, x1, t) = (xs[before], xs[after], *time as f64);
+            match method {
+                Interpolation::Nearest if t - x0 <= x1 - t => ys[before],
+                Interpolation::Nearest => ys[after],
+                Interpolation::Linear => {
+                    ys[before] + (ys[after] - ys[before]) * (t - x0) / (x1 - x0)
+                }
+                Interpolation::Spline => {
+                    let (width, a, b) = (x1 - x0, (x1 - t) / (x1 - x0), (t - x0) / (x1 - x0));
+                    a * ys[before]
+                        + b * ys[after]
+                        + ((a * a * a - a) * curvatures[before]
+                            + (b * b * b - b) * curvatures[after])
+                            * width
+                            * width
+                            / 6.0
+                }
+            }
+        })
+        .collect()
+}
+
+/// the blob of the values of a series of events at the points of a grid, none for an
+/// empty series
+fn align(
+    mut series: Vec<Event<f64>>,
+    method: Interpolation,
+    start: Option<i64>,
+    step: i64,
+    name: &str,
+) -> Result<Option<DataBlob<f64>>, &'static str> {
+    // a stable sort keeps events sharing a timestamp in arrival order
+    series.sort_by_key(|event| event.timestamp);
+    let mut unique: Vec<Event<f64>> = Vec::with_capacity(series.len());
+    for event in series {
+        match unique.last_mut() {
+            Some(last) if last.timestamp == event.timestamp => *last = event,
+            _ => unique.push(event),
+        }
+    }
+    let (first, last) = match (unique.first(), unique.last()) {
+        (Some(first), Some(last)) => (first.timestamp, last.timestamp),
+        _ => return Ok(None),
+    };
+    let start = start.unwrap_or(first);
+    let span = last
+        .checked_sub(start)
+        .ok_or("Grid does not fit in timestamps")?;
+    let points = match span >= 0 {
+        true => usize::try_from(span as u64 / step as u64 + 1)
+            .map_err(|_| "Grid has too many points")?,
+        false => 0,
+    };
+    let times: Vec<i64> = (0..points as i64).map(|k| start + k * step).collect();
+    let observed = times.iter().map(|time| {
+        unique
+            .binary_search_by_key(time, |event| event.timestamp)
+            .is_ok()
+    });
+    let mask = ValidityMask::from_bools(observed.collect::<Vec<_>>());
+    let mut meta = MetaData {
+        name: name.to_string(),
+        dimensions: vec![times.len()],
+        ..Default::default()
+    };
+    meta.record(
+        "interpolate",
+        &[
+            ("method", method.name().to_string()),
+            ("start", start.to_string()),
+            ("step", step.to_string()),
+        ],
+    );
+    DataBlob::new(interpolate(&unique, method, &times), meta)
+        .with_mask(mask)
+        .map(Some)
+}
+
+/// InterpolatePipe
+/// A pipe aligning a series of events onto a regular grid of timestamps, emitting the
+/// values at the grid points as one blob once the input ends
+///
+/// the grid runs from its start (the first timestamp by default) every `step` ticks up to
+/// the last timestamp, events may arrive out of order and the last of events sharing a
+/// timestamp is kept. Grid points without an event of their own are filled and marked
+/// invalid in the mask of the blob, which still holds their estimated values
+pub struct InterpolatePipe {
+    method: Interpolation,
+    step: i64,
+    start: Option<i64>,
+    name: String,
+    input: Option<Rc<dyn Source<Event<f64>>>>,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl InterpolatePipe {
+    /// constructor for a grid of points `step` ticks apart
+    pub fn new(method: Interpolation, step: i64) -> Result<Self, &'static str> {
+        if step <= 0 {
+            return Err("Grid step must be positive");
+        }
+        Ok(Self {
+            method,
+            step,
+            start: None,
+            name: "values".to_string(),
+            input: None,
+            error: Rc::new(Cell::new(None)),
+        })
+    }
+    /// set the first timestamp of the grid
+    pub fn with_start(mut self, start: i64) -> Self {
+        self.start = Some(start);
+        self
+    }
+    /// set the name of the blob ("values" by default)
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+    /// the failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl Source<DataBlob<f64>> for InterpolatePipe {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBlob<f64>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let error = self.error.clone();
+        error.set(None);
+        let (method, step, start) = (self.method, self.step, self.start);
+        let name = self.name.clone();
+        Box::new(
+            stream::once(input.collect::<Vec<_>>()).filter_map(move |series| {
+                let blob = align(series, method, start, step, &name)
+                    .map_err(|failure| error.set(Some(failure)));
+                futures::future::ready(blob.ok().flatten())
+            }),
+        )
+    }
+}
+
+impl Pipe<Event<f64>, DataBlob<f64>> for InterpolatePipe {
+    input_connection!(Event<f64>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn series(values: &[f64]) -> Vec<Event<f64>> {
+        let events = values.iter().enumerate();
+        events
+            .map(|(at, value)| Event::new(at as i64, *value))
+            .collect()
+    }
+
+    fn values(events: &[Event<f64>]) -> Vec<f64> {
+        events.iter().map(|event| event.value).collect()
+    }
+
+    #[test]
+    fn test_lttb() {
+        let spiky = series(&[0.0, 1.0, 0.0, 0.0, 9.0, 0.0, 0.0, -5.0, 0.0, 1.0]);
+        let kept = lttb(&spiky, 4);
+        assert_eq!(values(&kept), vec![0.0, 9.0, -5.0, 1.0]);
+        let times: Vec<i64> = kept.iter().map(|event| event.timestamp).collect();
+        assert_eq!(times, vec![0, 4, 7, 9]);
+        assert_eq!(lttb(&spiky, 2), spiky);
+        assert_eq!(lttb(&spiky[..3], 3), spiky[..3].to_vec());
+        let line = series(&(0..1000).map(|at| at as f64).collect::<Vec<_>>());
+        let kept = lttb(&line, 100);
+        assert_eq!(kept.len(), 100);
+        assert!(kept
+            .windows(2)
+            .all(|pair| pair[0].timestamp < pair[1].timestamp));
+    }
+
+    #[test]
+    fn test_downsample_pipe() {
+        let input = series(&[3.0, 1.0, 2.0, 7.0, 5.0, 5.0, 4.0, 8.0, 6.0]);
+        let mut pipe = DownsamplePipe::min_max(6);
+        pipe.pipe(Rc::new(MockSource::new().items(input.clone())))
+            .unwrap();
+        let kept: Vec<Event<f64>> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(values(&kept), vec![3.0, 1.0, 7.0, 5.0, 4.0, 8.0]);
+        assert_eq!(kept[3].timestamp, 4);
+        assert_eq!(min_max(&series(&[1.0, 1.0, 1.0]), 2), series(&[1.0]));
+        let mut pipe = DownsamplePipe::lttb(3);
+        pipe.pipe(Rc::new(MockSource::new().items(input))).unwrap();
+        let kept: Vec<Event<f64>> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(values(&kept), vec![3.0, 7.0, 6.0]);
+    }
+
+    #[test]
+    fn test_interpolate() {
+        let series = vec![
+            Event::new(0, 0.0),
+            Event::new(10, 10.0),
+            Event::new(20, 0.0),
+        ];
+        let times = [-5, 0, 4, 5, 6, 10, 15, 25];
+        let nearest = interpolate(&series, Interpolation::Nearest, &times);
+        assert_eq!(nearest, vec![0.0, 0.0, 0.0, 0.0, 10.0, 10.0, 10.0, 0.0]);
+        let linear = interpolate(&series, Interpolation::Linear, &times);
+        assert_eq!(linear, vec![0.0, 0.0, 4.0, 5.0, 6.0, 10.0, 5.0, 0.0]);
+        // the natural spline through the three points peaks at their middle
+        let spline = interpolate(&series, Interpolation::Spline, &[5, 10, 15]);
+        assert!((spline[0] - 6.875).abs() < 1e-12);
+        assert_eq!(spline[1], 10.0);
+        assert!((spline[2] - 6.875).abs() < 1e-12);
+        let line: Vec<Event<f64>> = (0..6)
+            .map(|x| Event::new(x * x, 2.0 * (x * x) as f64))
+            .collect();
+        let spline = interpolate(&line, Interpolation::Spline, &[2, 7, 20]);
+        for (value, time) in spline.iter().zip([2.0, 7.0, 20.0]) {
+            assert!((value - 2.0 * time).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_interpolate_pipe() {
+        let events = [
+            Event::new(7, 7.0),
+            Event::new(1, 1.0),
+            Event::new(4, 0.0),
+            Event::new(4, 4.0),
+        ];
+        let mut pipe = InterpolatePipe::new(Interpolation::Linear, 2)
+            .unwrap()
+            .with_start(-1);
+        pipe.pipe(Rc::new(MockSource::new().items(events))).unwrap();
+        let blobs: Vec<DataBlob<f64>> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(pipe.error(), None);
+        assert_eq!(blobs.len(), 1);
+        assert_eq!(blobs[0].get_data(), &vec![1.0, 1.0, 3.0, 5.0, 7.0]);
+        let validity: Vec<bool> = (0..5).map(|at| blobs[0].is_valid(at)).collect();
+        assert_eq!(validity, vec![false, true, false, false, true]);
+        assert_eq!(blobs[0].null_count(), 3);
+        let entry = blobs[0].get_meta_data().provenance[0].to_string();
+        assert_eq!(entry, "interpolate(method=linear, start=-1, step=2)");
+        let mut empty = InterpolatePipe::new(Interpolation::Nearest, 1).unwrap();
+        empty
+            .pipe(Rc::new(MockSource::new().items(Vec::<Event<f64>>::new())))
+            .unwrap();
+        assert_eq!(block_on(Pin::from(empty.stream()).count()), 0);
+        assert!(InterpolatePipe::new(Interpolation::Spline, 0).is_err());
+    }
+}
@@ -0,0 +1,349 @@
+//! stats
+//!
+//! Statistics, histograms and normalization of numeric blobs with explicit missing values
+//!
+//! an element is missing when it is masked null or a floating point NaN, the `MissingPolicy`
+//! of a pipe decides whether missing elements are skipped, replaced by a fill value or
+//! propagate to the whole result of their blob
+
+use crate::data_bucket::mask::ValidityMask;
+use crate::data_bucket::DataBlob;
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// MissingPolicy
+/// How analysis pipes treat null and NaN elements
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum MissingPolicy {
+    /// leave missing elements out
+    #[default]
+    Skip,
+    /// make the result of a blob holding a missing element missing as well
+    Propagate,
+    /// replace missing elements by a value
+    Fill(f64),
+}
+
+/// the elements of a blob as floats, None where missing after applying the policy, and the
+/// number of missing elements in the blob
+fn resolve<T: Copy + Into<f64>>(
+    blob: &DataBlob<T>,
+    policy: MissingPolicy,
+) -> (Vec<Option<f64>>, usize) {
+    let mut missing = 0;
+    let values = blob
+        .get_data()
+        .iter()
+        .enumerate()
+        .map(|(index, value)| {
+            let value: f64 = (*value).into();
+            if blob.is_valid(index) && !value.is_nan() {
+                return Some(value);
+            }
+            missing += 1;
+            match policy {
+                MissingPolicy::Fill(fill) => Some(fill),
+                _ => None,
+            }
+        })
+        .collect();
+    (values, missing)
+}
+
+/// Stats
+/// Summary statistics of the elements of a blob
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Stats {
+    /// number of elements aggregated
+    pub count: usize,
+    /// number of missing elements in the blob
+    pub missing: usize,
+    pub mean: f64,
+    /// population variance
+    pub variance: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl Stats {
+    /// statistics of a blob (NaN aggregates when there is nothing to aggregate or when
+    /// propagating a missing element)
+    pub fn of<T: Copy + Into<f64>>(blob: &DataBlob<T>, policy: MissingPolicy) -> Self {
+        let (values, missing) = resolve(blob, policy);
+        let mut stats = Stats {
+            count: 0,
+            missing,
+            mean: f64::NAN,
+            variance: f64::NAN,
+            min: f64::NAN,
+            max: f64::NAN,
+        };
+        if policy == MissingPolicy::Propagate && missing > 0 {
+            return stats;
+        }
+        let (mut mean, mut squares) = (0.0, 0.0);
+        for value in values.into_iter().flatten() {
+            stats.count += 1;
+            let delta = value - mean;
+            mean += delta / stats.count as f64;
+            squares += delta * (value - mean);
+            stats.min = stats.min.min(value);
+            stats.max = stats.max.max(value);
+        }
+        if stats.count > 0 {
+            stats.mean = mean;
+            stats.variance = squares / stats.count as f64;
+        }
+        stats
+    }
+    /// population standard deviation
+    pub fn std_dev(&self) -> f64 {
+        self.variance.sqrt()
+    }
+}
+
+/// StatsPipe
+/// A pipe computing the statistics of every blob of its input
+pub struct StatsPipe<T> {
+    policy: MissingPolicy,
+    input: Option<Rc<dyn Source<DataBlob<T>>>>,
+}
+
+impl<T: Copy + Into<f64> + 'static> StatsPipe<T> {
+    /// constructor
+    pub fn new(policy: MissingPolicy) -> Self {
+        Self {
+            policy,
+            input: None,
+        }
+    }
+}
+
+impl<T: Copy + Into<f64> + 'static> Source<Stats> for StatsPipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = Stats>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let policy = self.policy;
+        Box::new(input.map(move |blob| Stats::of(&blob, policy)))
+    }
+}
+
+impl<T: Copy + Into<f64> + 'static> Pipe<DataBlob<T>, Stats> for StatsPipe<T> {
+    input_connection!(DataBlob<T>);
+}
+
+/// Histogram
+/// The counts of the elements of a blob in bins of equal width
+#[derive(Clone, Debug, PartialEq)]
+pub struct Histogram {
+    /// lower edge of the first bin
+    pub min: f64,
+    /// upper edge of the last bin
+    pub max: f64,
+    /// elements in each bin, the last bin includes its upper edge
+    pub counts: Vec<usize>,
+    /// elements outside of the bins
+    pub outliers: usize,
+    /// missing elements of the blob, when propagating they make every count zero
+    pub missing: usize,
+}
+
+/// HistogramPipe
+/// A pipe counting the elements of every blob of its input in fixed bins
+pub struct HistogramPipe<T> {
+    min: f64,
+    max: f64,
+    bins: usize,
+    policy: MissingPolicy,
+    input: Option<Rc<dyn Source<DataBlob<T>>>>,
+}
+
+impl<T: Copy + Into<f64> + 'static> HistogramPipe<T> {
+    /// constructor (returns an error for an empty range or no bins)
+    pub fn new(
+        min: f64,
+        max: f64,
+        bins: usize,
+        policy: MissingPolicy,
+    ) -> Result<Self, &'static str> {
+        if bins == 0 {
+            return Err("Histogram needs at least one bin");
+        }
+        if !min.is_finite() || !max.is_finite() || min >= max {
+            return Err("Histogram range must be finite and non empty");
+        }
+        Ok(Self {
+            min,
+            max,
+            bins,
+            policy,
+            input: None,
+        })
+    }
+}
+
+impl<T: Copy + Into<f64> + 'static> Source<Histogram> for HistogramPipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = Histogram>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let (min, max, bins, policy) = (self.min, self.max, self.bins, self.policy);
+        Box::new(input.map(move |blob| {
+            let (values, missing) = resolve(&blob, policy);
+            let mut histogram = Histogram {
+                min,
+                max,
+                counts: vec![0; bins],
+                outliers: 0,
+                missing,
+            };
+            if policy == MissingPolicy::Propagate && missing > 0 {
+                return histogram;
+            }
+            let width = (max - min) / bins as f64;
+            for value in values.into_iter().flatten() {
+                if value < min || value > max {
+                    histogram.outliers += 1;
+                    continue;
+                }
+                let bin = (((value - min) / width) as usize).min(bins - 1);
+                histogram.counts[bin] += 1;
+            }
+            histogram
+        }))
+    }
+}
+
+impl<T: Copy + Into<f64> + 'static> Pipe<DataBlob<T>, Histogram> for HistogramPipe<T> {
+    input_connection!(DataBlob<T>);
+}
+
+/// NormalizePipe
+/// A pipe rescaling every blob of its input to zero mean and unit standard deviation
+///
+/// skipped elements stay null in the output, when propagating a blob holding a missing
+/// element comes out entirely null, filled elements take part in the statistics
+pub struct NormalizePipe<T> {
+    policy: MissingPolicy,
+    input: Option<Rc<dyn Source<DataBlob<T>>>>,
+}
+
+impl<T: Copy + Into<f64> + 'static> NormalizePipe<T> {
+    /// constructor
+    pub fn new(policy: MissingPolicy) -> Self {
+        Self {
+            policy,
+            input: None,
+        }
+    }
+}
+
+impl<T: Copy + Into<f64> + 'static> Source<DataBlob<f64>> for NormalizePipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBlob<f64>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let policy = self.policy;
+        Box::new(input.map(move |blob| {
+            let stats = Stats::of(&blob, policy);
+            let (values, _) = resolve(&blob, policy);
+            let scale = match stats.std_dev() {
+                deviation if deviation > 0.0 => deviation,
+                _ => 1.0,
+            };
+            let propagate = policy == MissingPolicy::Propagate && stats.missing > 0;
+            let mask = ValidityMask::from_bools(values.iter().map(|v| v.is_some() && !propagate));
+            let data = values
+                .into_iter()
+                .map(|value| match value {
+                    Some(value) if !propagate => (value - stats.mean) / scale,
+                    _ => f64::NAN,
+                })
+                .collect();
+            let mut meta = blob.get_meta_data().clone();
+            meta.units = None;
+            meta.record("normalize", &[("policy", format!("{:?}", policy))]);
+            let normalized = DataBlob::new(data, meta);
+            match mask.null_count() {
+                0 => normalized,
+                _ => normalized
+                    .with_mask(mask)
+                    .expect("Mask built from the data"),
+            }
+        }))
+    }
+}
+
+impl<T: Copy + Into<f64> + 'static> Pipe<DataBlob<T>, DataBlob<f64>> for NormalizePipe<T> {
+    input_connection!(DataBlob<T>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::MetaData;
+    use crate::testing::{collect, MockSource};
+
+    fn blob() -> DataBlob<f64> {
+        DataBlob::from_options(
+            vec![Some(1.0), None, Some(f64::NAN), Some(3.0)],
+            MetaData::default(),
+        )
+    }
+
+    #[test]
+    fn test_stats_policies() {
+        let skipped = Stats::of(&blob(), MissingPolicy::Skip);
+        assert_eq!((skipped.count, skipped.missing), (2, 2));
+        assert_eq!((skipped.mean, skipped.variance), (2.0, 1.0));
+        assert_eq!((skipped.min, skipped.max), (1.0, 3.0));
+        let filled = Stats::of(&blob(), MissingPolicy::Fill(0.0));
+        assert_eq!((filled.count, filled.mean), (4, 1.0));
+        let propagated = Stats::of(&blob(), MissingPolicy::Propagate);
+        assert!(propagated.mean.is_nan(), "Missing values did not propagate");
+        let mut pipe = StatsPipe::new(MissingPolicy::Skip);
+        pipe.pipe(Rc::new(
+            MockSource::new().items([DataBlob::new(vec![2_i32, 4], MetaData::default())]),
+        ))
+        .unwrap();
+        assert_eq!(collect(&pipe)[0].mean, 3.0);
+    }
+
+    #[test]
+    fn test_histogram_policies() {
+        let mut pipe = HistogramPipe::new(0.0, 4.0, 2, MissingPolicy::Fill(5.0)).unwrap();
+        pipe.pipe(Rc::new(MockSource::new().items([blob()])))
+            .unwrap();
+        let histogram = &collect(&pipe)[0];
+        assert_eq!(histogram.counts, vec![1, 1]);
+        assert_eq!((histogram.outliers, histogram.missing), (2, 2));
+        let mut pipe = HistogramPipe::new(0.0, 4.0, 2, MissingPolicy::Propagate).unwrap();
+        pipe.pipe(Rc::new(MockSource::new().items([blob()])))
+            .unwrap();
+        assert_eq!(collect(&pipe)[0].counts, vec![0, 0]);
+        assert!(HistogramPipe::<f64>::new(1.0, 1.0, 2, MissingPolicy::Skip).is_err());
+    }
+
+    #[test]
+    fn test_normalize_policies() {
+        let mut pipe = NormalizePipe::new(MissingPolicy::Skip);
+        pipe.pipe(Rc::new(MockSource::new().items([blob()])))
+            .unwrap();
+        let normalized = &collect(&pipe)[0];
+        assert_eq!(
+            normalized.to_options(),
+            vec![Some(-1.0), None, None, Some(1.0)]
+        );
+        let mut pipe = NormalizePipe::new(MissingPolicy::Propagate);
+        pipe.pipe(Rc::new(MockSource::new().items([blob()])))
+            .unwrap();
+        assert_eq!(collect(&pipe)[0].null_count(), 4);
+    }
+}
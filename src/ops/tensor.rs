@@ -0,0 +1,172 @@
+//! tensor
+//!
+//! Slicing and reducing the blobs of a stream along named tensor axes
+
+use crate::data_bucket::tensor::TensorBlob;
+use crate::data_bucket::DataBlob;
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::ops::Range;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// apply a tensor operation to each blob of a stream, ending it at the first failure
+fn tensor_stream<T, F>(
+    input: &Option<Rc<dyn Source<DataBlob<T>>>>,
+    error: &Rc<Cell<Option<&'static str>>>,
+    operation: F,
+) -> Box<dyn Stream<Item = DataBlob<T>>>
+where
+    T: 'static,
+    F: Fn(&TensorBlob<T>) -> Result<TensorBlob<T>, &'static str> + 'static,
+{
+    let input = match input {
+        Some(input) => Pin::from(input.stream()),
+        None => return Box::new(stream::empty()),
+    };
+    let error = error.clone();
+    error.set(None);
+    Box::new(input.scan((), move |_, blob| {
+        let result = TensorBlob::from_blob(blob)
+            .and_then(|tensor| operation(&tensor))
+            .map(TensorBlob::into_blob)
+            .map_err(|failure| error.set(Some(failure)));
+        futures::future::ready(result.ok())
+    }))
+}
+
+enum Selection {
+    Range(Range<usize>),
+    Index(usize),
+}
+
+/// TensorSlicePipe
+/// A pipe cutting the tensors of its input along an axis, the stream ends at the first blob
+/// that is not a tensor or lacks the axis
+pub struct TensorSlicePipe<T> {
+    axis: String,
+    selection: Rc<Selection>,
+    input: Option<Rc<dyn Source<DataBlob<T>>>>,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl<T: Clone + 'static> TensorSlicePipe<T> {
+    /// constructor keeping a range of positions along the axis
+    pub fn new(axis: &str, range: Range<usize>) -> Self {
+        Self::with_selection(axis, Selection::Range(range))
+    }
+    /// constructor keeping one position along the axis and dropping the axis
+    pub fn select(axis: &str, index: usize) -> Self {
+        Self::with_selection(axis, Selection::Index(index))
+    }
+    fn with_selection(axis: &str, selection: Selection) -> Self {
+        Self {
+            axis: axis.to_string(),
+            selection: Rc::new(selection),
+            input: None,
+            error: Rc::new(Cell::new(None)),
+        }
+    }
+    /// the failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl<T: Clone + 'static> Source<DataBlob<T>> for TensorSlicePipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBlob<T>>> {
+        let (axis, selection) = (self.axis.clone(), self.selection.clone());
+        tensor_stream(&self.input, &self.error, move |tensor| {
+            match selection.as_ref() {
+                Selection::Range(range) => tensor.slice(&axis, range.clone()),
+                Selection::Index(index) => tensor.select(&axis, *index),
+            }
+        })
+    }
+}
+
+impl<T: Clone + 'static> Pipe<DataBlob<T>, DataBlob<T>> for TensorSlicePipe<T> {
+    input_connection!(DataBlob<T>);
+}
+
+type Reduction<T> = Rc<dyn Fn(&[T]) -> T>;
+
+/// TensorReducePipe
+/// A pipe reducing the tensors of its input along an axis, the stream ends at the first blob
+/// that is not a tensor or lacks the axis
+pub struct TensorReducePipe<T> {
+    axis: String,
+    reduction: Reduction<T>,
+    input: Option<Rc<dyn Source<DataBlob<T>>>>,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl<T: Clone + 'static> TensorReducePipe<T> {
+    /// constructor from the axis and a reduction of the valid values of each of its lanes
+    pub fn new<F: Fn(&[T]) -> T + 'static>(axis: &str, reduction: F) -> Self {
+        Self {
+            axis: axis.to_string(),
+            reduction: Rc::new(reduction),
+            input: None,
+            error: Rc::new(Cell::new(None)),
+        }
+    }
+    /// the failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl<T: Clone + 'static> Source<DataBlob<T>> for TensorReducePipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBlob<T>>> {
+        let (axis, reduction) = (self.axis.clone(), self.reduction.clone());
+        tensor_stream(&self.input, &self.error, move |tensor| {
+            tensor.reduce(&axis, |lane| reduction(lane))
+        })
+    }
+}
+
+impl<T: Clone + 'static> Pipe<DataBlob<T>, DataBlob<T>> for TensorReducePipe<T> {
+    input_connection!(DataBlob<T>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::MetaData;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn image(pixel: f32) -> DataBlob<f32> {
+        let axes = [("row", 2), ("column", 2), ("channel", 3)];
+        TensorBlob::new(vec![pixel; 12], &axes, MetaData::default())
+            .unwrap()
+            .into_blob()
+    }
+
+    #[test]
+    fn test_tensor_pipes() {
+        let images = [
+            image(1.0),
+            image(2.0),
+            DataBlob::new(vec![0.0], MetaData::default()),
+        ];
+        let mut red = TensorSlicePipe::select("channel", 0);
+        red.pipe(Rc::new(MockSource::new().items(images))).unwrap();
+        let mut rows = TensorReducePipe::new("column", |lane: &[f32]| lane.iter().sum());
+        rows.pipe(Rc::new(red)).unwrap();
+        let blobs: Vec<DataBlob<f32>> = block_on(Pin::from(rows.stream()).collect());
+        assert_eq!(blobs.len(), 2, "Reduced a blob that is not a tensor");
+        let tensor = TensorBlob::from_blob(blobs[1].clone()).unwrap();
+        assert_eq!(tensor.axes(), &["row".to_string()]);
+        assert_eq!(tensor.as_blob().get_data(), &vec![4.0, 4.0]);
+        assert_eq!(rows.error(), None);
+        let mut crop = TensorSlicePipe::new("row", 1..3);
+        crop.pipe(Rc::new(MockSource::new().items([image(1.0)])))
+            .unwrap();
+        assert_eq!(block_on(Pin::from(crop.stream()).count()), 0);
+        assert_eq!(crop.error(), Some("Range is out of the bounds of the axis"));
+    }
+}
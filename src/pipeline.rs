@@ -0,0 +1,346 @@
+//! pipeline
+//!
+//! Assembling the stages of a pipeline into a single runnable unit
+
+/// graph
+/// Sub module for pipelines wired by node names and validated before running
+pub mod graph;
+
+/// lineage
+/// Sub module for the lineage graph of blobs across pipeline runs
+pub mod lineage;
+
+/// template
+/// Sub module for reusable parameterized chains of pipes
+pub mod template;
+
+pub use graph::{GraphError, PipelineGraph, PipelineGraphRun};
+pub use lineage::{Lineage, LineageEdge, LineageNode, LineagePipe};
+pub use template::{PipelineTemplate, TemplatePipe};
+
+use crate::runtime::{Executor, ThreadPoolExecutor};
+use futures::channel::oneshot;
+use futures::executor::block_on;
+use futures::future::{join_all, LocalBoxFuture, Shared};
+use futures::stream;
+use futures::{FutureExt, Stream, StreamExt};
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Poll, Waker};
+
+#[derive(Default)]
+struct PauseState {
+    paused: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+}
+
+/// PauseHandle
+/// A cloneable handle pausing and resuming the sources of a running pipeline
+///
+/// streams gated by the handle stop pulling their input while paused, items already
+/// emitted keep flowing through the downstream stages
+#[derive(Clone, Default)]
+pub struct PauseHandle {
+    state: Arc<PauseState>,
+}
+
+impl PauseHandle {
+    /// constructor for a running handle
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// stop the gated streams from emitting
+    pub fn pause(&self) {
+        self.state.paused.store(true, Ordering::SeqCst);
+    }
+    /// let the gated streams emit again
+    pub fn resume(&self) {
+        self.state.paused.store(false, Ordering::SeqCst);
+        let wakers = std::mem::take(&mut *self.state.wakers.lock().unwrap());
+        wakers.into_iter().for_each(Waker::wake);
+    }
+    /// whether the handle is paused
+    pub fn is_paused(&self) -> bool {
+        self.state.paused.load(Ordering::SeqCst)
+    }
+    /// hold back the items of a stream while paused
+    pub fn gate<S: Stream>(&self, input: S) -> impl Stream<Item = S::Item> {
+        let state = self.state.clone();
+        let mut input = Box::pin(input);
+        stream::poll_fn(move |cx| {
+            if state.paused.load(Ordering::SeqCst) {
+                state.wakers.lock().unwrap().push(cx.waker().clone());
+                if state.paused.load(Ordering::SeqCst) {
+                    return Poll::Pending;
+                }
+            }
+            input.as_mut().poll_next(cx)
+        })
+    }
+}
+
+/// Shutdown
+/// A cloneable signal telling the stages of a running pipeline to wind down
+#[derive(Clone)]
+pub struct Shutdown {
+    signal: Shared<oneshot::Receiver<()>>,
+    flag: Arc<AtomicBool>,
+    pause: PauseHandle,
+}
+
+/// ShutdownTrigger
+/// The handle requesting the shutdown of a pipeline (dropping it also requests shutdown)
+pub struct ShutdownTrigger {
+    sender: Option<oneshot::Sender<()>>,
+    flag: Arc<AtomicBool>,
+}
+
+impl Shutdown {
+    /// create a shutdown signal and its trigger
+    pub fn new() -> (ShutdownTrigger, Shutdown) {
+        Self::with_pause(PauseHandle::new())
+    }
+    /// create a shutdown signal and its trigger, guarded streams also obeying a pause handle
+    pub fn with_pause(pause: PauseHandle) -> (ShutdownTrigger, Shutdown) {
+        let (sender, receiver) = oneshot::channel();
+        let flag = Arc::new(AtomicBool::new(false));
+        (
+            ShutdownTrigger {
+                sender: Some(sender),
+                flag: flag.clone(),
+            },
+            Shutdown {
+                signal: receiver.shared(),
+                flag,
+                pause,
+            },
+        )
+    }
+    /// whether shutdown was requested
+    pub fn is_requested(&self) -> bool {
+        self.flag.load(Ordering::SeqCst)
+    }
+    /// resolves once shutdown is requested
+    pub fn requested(&self) -> impl Future<Output = ()> {
+        self.signal.clone().map(|_| ())
+    }
+    /// whether the pipeline is paused
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+    /// end a stream as soon as shutdown is requested, holding its items back while the
+    /// pipeline is paused
+    pub fn guard<S: Stream>(&self, stream: S) -> impl Stream<Item = S::Item> {
+        self.pause.gate(stream).take_until(self.requested())
+    }
+}
+
+impl ShutdownTrigger {
+    /// request the shutdown
+    pub fn trigger(&mut self) {
+        self.flag.store(true, Ordering::SeqCst);
+        if let Some(sender) = self.sender.take() {
+            let _ = sender.send(());
+        }
+    }
+}
+
+impl Drop for ShutdownTrigger {
+    fn drop(&mut self) {
+        self.trigger();
+    }
+}
+
+pub(crate) type StageTask = Box<dyn FnOnce(Shutdown) -> LocalBoxFuture<'static, ()> + Send>;
+
+/// Pipeline
+/// A collection of named stages run together
+///
+/// sources and pipes are not thread safe, so each stage is a closure building and
+/// draining its part of the pipeline once it runs, stages are connected with channels
+/// (see `runtime::channel`) and receive the shutdown signal of the pipeline, sources
+/// wrapped in `Shutdown::guard` also stop emitting while the pipeline is paused
+pub struct Pipeline {
+    pub(crate) stages: Vec<(String, StageTask)>,
+    pub(crate) pause: PauseHandle,
+}
+
+impl Pipeline {
+    /// empty constructor
+    pub fn new() -> Self {
+        Self {
+            stages: Vec::new(),
+            pause: PauseHandle::new(),
+        }
+    }
+    /// add a stage (returns an error if the name is already taken)
+    pub fn add_stage<F, Fut>(&mut self, name: &str, stage: F) -> Result<(), &'static str>
+    where
+        F: FnOnce(Shutdown) -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        if self.stages.iter().any(|(other, _)| other == name) {
+            return Err("A stage with this name already exists");
+        }
+        self.stages.push((
+            name.to_string(),
+            Box::new(move |shutdown| stage(shutdown).boxed_local()),
+        ));
+        Ok(())
+    }
+    /// names of the stages in insertion order
+    pub fn stage_names(&self) -> impl Iterator<Item = &String> {
+        self.stages.iter().map(|(name, _)| name)
+    }
+    /// stop the guarded sources from emitting, in flight items keep draining
+    pub fn pause(&self) {
+        self.pause.pause();
+    }
+    /// let the guarded sources emit again
+    pub fn resume(&self) {
+        self.pause.resume();
+    }
+    /// whether the pipeline is paused
+    pub fn is_paused(&self) -> bool {
+        self.pause.is_paused()
+    }
+    /// a handle pausing and resuming the pipeline once it runs
+    pub fn pause_handle(&self) -> PauseHandle {
+        self.pause.clone()
+    }
+    /// run every stage concurrently on the current thread until they all complete
+    pub fn run(self) {
+        let (_trigger, shutdown) = Shutdown::with_pause(self.pause);
+        block_on(join_all(
+            self.stages
+                .into_iter()
+                .map(|(_, stage)| stage(shutdown.clone())),
+        ));
+    }
+    /// run every stage on a thread pool executor, blocking until they all complete
+    pub fn run_on_pool(self, executor: &ThreadPoolExecutor) -> Result<(), &'static str> {
+        self.run_with(executor)
+    }
+    /// spawn every stage on an executor, blocking until they all complete
+    pub fn run_with(self, executor: &dyn Executor) -> Result<(), &'static str> {
+        let (_trigger, shutdown) = Shutdown::with_pause(self.pause);
+        for (name, stage) in self.stages.into_iter() {
+            let shutdown = shutdown.clone();
+            executor.spawn_task(&name, Box::new(move || stage(shutdown)));
+        }
+        executor.wait()
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::{DataBlob, MetaData};
+    use crate::runtime::channel;
+    use crate::Source;
+    use std::pin::{pin, Pin};
+    use std::rc::Rc;
+    use std::sync::Mutex;
+
+    fn make_pipeline(received: Arc<Mutex<Vec<u8>>>) -> Pipeline {
+        let (sender, source) = channel::<u8>(1);
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .add_stage("produce", move |_| async move {
+                let input = Rc::new(DataBlob::new((0..20).collect(), MetaData::default()));
+                sender.forward(input).await.unwrap();
+            })
+            .unwrap();
+        pipeline
+            .add_stage("consume", move |_| async move {
+                let items: Vec<u8> = Pin::from(source.stream()).collect().await;
+                *received.lock().unwrap() = items;
+            })
+            .unwrap();
+        pipeline
+    }
+
+    #[test]
+    fn test_run_pipeline() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let pipeline = make_pipeline(received.clone());
+        assert_eq!(pipeline.stage_names().count(), 2);
+        pipeline.run();
+        assert_eq!(*received.lock().unwrap(), (0..20).collect::<Vec<u8>>());
+    }
+
+    #[test]
+    fn test_run_pipeline_on_pool() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let executor = ThreadPoolExecutor::new(2).unwrap();
+        make_pipeline(received.clone())
+            .run_on_pool(&executor)
+            .unwrap();
+        assert_eq!(received.lock().unwrap().len(), 20);
+    }
+
+    #[test]
+    fn test_run_pipeline_with_local_executor() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let executor = crate::runtime::LocalExecutor::new();
+        make_pipeline(received.clone()).run_with(&executor).unwrap();
+        assert_eq!(received.lock().unwrap().len(), 20);
+    }
+
+    #[test]
+    fn test_duplicate_stage() {
+        let mut pipeline = Pipeline::new();
+        pipeline.add_stage("a", |_| async {}).unwrap();
+        assert!(pipeline.add_stage("a", |_| async {}).is_err());
+    }
+
+    #[test]
+    fn test_shutdown_guard() {
+        let (mut trigger, shutdown) = Shutdown::new();
+        assert!(!shutdown.is_requested());
+        trigger.trigger();
+        assert!(shutdown.is_requested());
+        let items: Vec<u32> = block_on(shutdown.guard(futures::stream::iter(0..10)).collect());
+        assert!(items.is_empty(), "Guarded stream ignored shutdown");
+    }
+
+    #[test]
+    fn test_pause_and_resume() {
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let output = received.clone();
+        let (mut sender, source) = channel::<u8>(1);
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .add_stage("produce", move |shutdown| async move {
+                let mut items = pin!(shutdown.guard(futures::stream::iter(0..5)));
+                while let Some(item) = items.next().await {
+                    sender.send(item).await.unwrap();
+                }
+            })
+            .unwrap();
+        pipeline
+            .add_stage("consume", move |_| async move {
+                let mut items = Pin::from(source.stream());
+                while let Some(item) = items.next().await {
+                    output.lock().unwrap().push(item);
+                }
+            })
+            .unwrap();
+        pipeline.pause();
+        assert!(pipeline.is_paused());
+        let handle = pipeline.pause_handle();
+        let runner = std::thread::spawn(move || pipeline.run());
+        std::thread::sleep(std::time::Duration::from_millis(50));
+        assert!(received.lock().unwrap().is_empty(), "Paused source emitted");
+        handle.resume();
+        runner.join().unwrap();
+        assert_eq!(*received.lock().unwrap(), vec![0, 1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,372 @@
+//! graph
+//!
+//! Wiring sources, pipes and sinks by name and validating the wiring before anything runs
+//!
+//! nodes are added with their concrete types and connected by name, `build` checks that
+//! every connection joins nodes of matching item types, that pipes and sinks have exactly
+//! one input, that every source and pipe feeds at least one consumer and that the graph has
+//! no cycle, then connects every node in topological order
+
+use super::lineage::escape;
+use crate::{Pipe, Sink, Source};
+use futures::future::{join_all, LocalBoxFuture};
+use futures::FutureExt;
+use std::any::Any;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::rc::Rc;
+
+/// GraphError
+/// The reasons a graph fails to build, naming the offending nodes
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum GraphError {
+    /// two nodes share a name
+    DuplicateNode(String),
+    /// a connection names a node that does not exist
+    UnknownNode(String),
+    /// a connection leaves a sink or enters a source
+    InvalidConnection { from: String, to: String },
+    /// a pipe or sink has several inputs
+    MultipleInputs(String),
+    /// a pipe or sink has no input, or a source or pipe has no consumer
+    Dangling(String),
+    /// the item types of two connected nodes differ
+    TypeMismatch { from: String, to: String },
+    /// the nodes of a cycle, in order
+    Cycle(Vec<String>),
+    /// a node refused its input
+    Connection { node: String, reason: &'static str },
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::DuplicateNode(node) => write!(f, "Node {} is defined twice", node),
+            GraphError::UnknownNode(node) => write!(f, "Node {} does not exist", node),
+            GraphError::InvalidConnection { from, to } => {
+                write!(f, "Node {} cannot feed node {}", from, to)
+            }
+            GraphError::MultipleInputs(node) => write!(f, "Node {} has several inputs", node),
+            GraphError::Dangling(node) => write!(f, "Node {} is dangling", node),
+            GraphError::TypeMismatch { from, to } => {
+                write!(
+                    f,
+                    "Node {} produces items node {} does not accept",
+                    from, to
+                )
+            }
+            GraphError::Cycle(nodes) => write!(f, "Nodes {} form a cycle", nodes.join(" -> ")),
+            GraphError::Connection { node, reason } => {
+                write!(f, "Node {} refused its input: {}", node, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+type Output = Box<dyn Any>;
+type Drain = LocalBoxFuture<'static, Result<(), &'static str>>;
+type Connect<T> = Box<dyn FnOnce(&dyn Any) -> Result<T, Option<&'static str>>>;
+
+enum Node {
+    Source(Output),
+    Pipe(Connect<Output>),
+    Sink(Connect<Drain>),
+}
+
+impl Node {
+    fn has_input(&self) -> bool {
+        !matches!(self, Node::Source(_))
+    }
+    fn has_output(&self) -> bool {
+        !matches!(self, Node::Sink(_))
+    }
+}
+
+/// PipelineGraph
+/// A builder of pipelines wired by node names
+#[derive(Default)]
+pub struct PipelineGraph {
+    nodes: BTreeMap<String, Node>,
+    order: Vec<String>,
+    edges: Vec<(String, String)>,
+}
+
+impl PipelineGraph {
+    /// empty constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+    fn add_node(&mut self, name: &str, node: Node) -> Result<(), GraphError> {
+        if self.nodes.contains_key(name) {
+            return Err(GraphError::DuplicateNode(name.to_string()));
+        }
+        self.nodes.insert(name.to_string(), node);
+        self.order.push(name.to_string());
+        Ok(())
+    }
+    /// add a source node
+    pub fn add_source<T: 'static>(
+        &mut self,
+        name: &str,
+        source: Rc<dyn Source<T>>,
+    ) -> Result<(), GraphError> {
+        self.add_node(name, Node::Source(Box::new(source)))
+    }
+    /// add a pipe node
+    pub fn add_pipe<InT, OutT, P>(&mut self, name: &str, mut pipe: P) -> Result<(), GraphError>
+    where
+        InT: 'static,
+        OutT: 'static,
+        P: Pipe<InT, OutT> + 'static,
+    {
+        let connect: Connect<Output> = Box::new(move |input| {
+            let input = input.downcast_ref::<Rc<dyn Source<InT>>>().ok_or(None)?;
+            pipe.pipe(input.clone()).map_err(Some)?;
+            let output: Rc<dyn Source<OutT>> = Rc::new(pipe);
+            Ok(Box::new(output) as Output)
+        });
+        self.add_node(name, Node::Pipe(connect))
+    }
+    /// add a sink node
+    pub fn add_sink<T, S>(&mut self, name: &str, mut sink: S) -> Result<(), GraphError>
+    where
+        T: 'static,
+        S: Sink<T> + 'static,
+    {
+        let connect: Connect<Drain> = Box::new(move |input| {
+            let input = input.downcast_ref::<Rc<dyn Source<T>>>().ok_or(None)?;
+            sink.pipe(input.clone()).map_err(Some)?;
+            Ok(sink.drain())
+        });
+        self.add_node(name, Node::Sink(connect))
+    }
+    /// connect the output of a node to the input of another
+    pub fn connect(&mut self, from: &str, to: &str) {
+        self.edges.push((from.to_string(), to.to_string()));
+    }
+    /// the names of the nodes in insertion order
+    pub fn node_names(&self) -> impl Iterator<Item = &String> {
+        self.order.iter()
+    }
+    /// the nodes and connections in the DOT language, sources drawn as boxes and sinks as
+    /// double circles
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph pipeline {\n");
+        for name in self.order.iter() {
+            let shape = match self.nodes[name] {
+                Node::Source(_) => "box",
+                Node::Pipe(_) => "ellipse",
+                Node::Sink(_) => "doublecircle",
+            };
+            dot.push_str(&format!("  \"{}\" [shape={}];\n", escape(name), shape));
+        }
+        for (from, to) in self.edges.iter() {
+            dot.push_str(&format!("  \"{}\" -> \"{}\";\n", escape(from), escape(to)));
+        }
+        dot.push_str("}\n");
+        dot
+    }
+    /// the single input of every pipe and sink
+    fn inputs(&self) -> Result<HashMap<String, String>, GraphError> {
+        let mut inputs = HashMap::new();
+        for (from, to) in self.edges.iter() {
+            let source = self
+                .nodes
+                .get(from)
+                .ok_or_else(|| GraphError::UnknownNode(from.clone()))?;
+            let target = self
+                .nodes
+                .get(to)
+                .ok_or_else(|| GraphError::UnknownNode(to.clone()))?;
+            if !source.has_output() || !target.has_input() {
+                return Err(GraphError::InvalidConnection {
+                    from: from.clone(),
+                    to: to.clone(),
+                });
+            }
+            if inputs.insert(to.clone(), from.clone()).is_some() {
+                return Err(GraphError::MultipleInputs(to.clone()));
+            }
+        }
+        for name in self.order.iter() {
+            let node = &self.nodes[name];
+            let fed = inputs.contains_key(name);
+            let consumed = self.edges.iter().any(|(from, _)| from == name);
+            if (node.has_input() && !fed) || (node.has_output() && !consumed) {
+                return Err(GraphError::Dangling(name.clone()));
+            }
+        }
+        Ok(inputs)
+    }
+    /// the nodes in an order where every node follows its input
+    fn topological_order(
+        &self,
+        inputs: &HashMap<String, String>,
+    ) -> Result<Vec<String>, GraphError> {
+        let mut placed: Vec<String> = Vec::new();
+        let mut remaining: Vec<&String> = self.order.iter().collect();
+        while !remaining.is_empty() {
+            let before = remaining.len();
+            remaining.retain(|name| match inputs.get(*name) {
+                Some(input) if !placed.contains(input) => true,
+                _ => {
+                    placed.push((*name).clone());
+                    false
+                }
+            });
+            if remaining.len() == before {
+                let mut cycle = vec![remaining[0].clone()];
+                loop {
+                    let input = inputs[cycle.last().unwrap()].clone();
+                    if let Some(start) = cycle.iter().position(|name| *name == input) {
+                        let mut cycle = cycle.split_off(start);
+                        cycle.reverse();
+                        return Err(GraphError::Cycle(cycle));
+                    }
+                    cycle.push(input);
+                }
+            }
+        }
+        Ok(placed)
+    }
+    /// validate the graph and connect its nodes
+    pub fn build(mut self) -> Result<PipelineGraphRun, GraphError> {
+        let inputs = self.inputs()?;
+        let order = self.topological_order(&inputs)?;
+        let topology = self.to_dot();
+        let mut outputs: HashMap<String, Output> = HashMap::new();
+        let mut drains = Vec::new();
+        for name in order {
+            let node = self.nodes.remove(&name).unwrap();
+            let refusal = |reason: Option<&'static str>| match reason {
+                Some(reason) => GraphError::Connection {
+                    node: name.clone(),
+                    reason,
+                },
+                None => GraphError::TypeMismatch {
+                    from: inputs[&name].clone(),
+                    to: name.clone(),
+                },
+            };
+            match node {
+                Node::Source(output) => {
+                    outputs.insert(name.clone(), output);
+                }
+                Node::Pipe(connect) => {
+                    let output = connect(outputs[&inputs[&name]].as_ref()).map_err(refusal)?;
+                    outputs.insert(name.clone(), output);
+                }
+                Node::Sink(connect) => {
+                    drains.push(connect(outputs[&inputs[&name]].as_ref()).map_err(refusal)?);
+                }
+            }
+        }
+        Ok(PipelineGraphRun { drains, topology })
+    }
+}
+
+/// PipelineGraphRun
+/// A validated and connected graph ready to drain its sinks
+pub struct PipelineGraphRun {
+    drains: Vec<Drain>,
+    topology: String,
+}
+
+impl PipelineGraphRun {
+    /// the number of sinks of the graph
+    pub fn sinks(&self) -> usize {
+        self.drains.len()
+    }
+    /// the topology of the graph in the DOT language
+    pub fn to_dot(&self) -> &str {
+        &self.topology
+    }
+    /// drain every sink concurrently, returning the first failure
+    pub fn drain(self) -> LocalBoxFuture<'static, Result<(), &'static str>> {
+        join_all(self.drains)
+            .map(|results| results.into_iter().collect())
+            .boxed_local()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::ElementwisePipe;
+    use crate::pipes::FlatMapPipe;
+    use crate::testing::{CaptureSink, MockSource};
+    use futures::executor::block_on;
+
+    fn numbers_graph() -> (PipelineGraph, CaptureSink<i32>) {
+        let capture = CaptureSink::new();
+        let mut graph = PipelineGraph::new();
+        graph
+            .add_source("numbers", Rc::new(MockSource::new().items([1, 2, 3])))
+            .unwrap();
+        graph.add_pipe("double", ElementwisePipe::scale(2)).unwrap();
+        graph.add_sink("capture", capture.clone()).unwrap();
+        (graph, capture)
+    }
+
+    #[test]
+    fn test_build_and_drain() {
+        let (mut graph, capture) = numbers_graph();
+        graph.connect("numbers", "double");
+        graph.connect("double", "capture");
+        assert_eq!(
+            graph.add_pipe("double", ElementwisePipe::scale(3)),
+            Err(GraphError::DuplicateNode("double".to_string()))
+        );
+        let run = graph.build().unwrap();
+        assert_eq!(run.sinks(), 1);
+        assert_eq!(
+            run.to_dot(),
+            "digraph pipeline {\n  \"numbers\" [shape=box];\n  \"double\" [shape=ellipse];\n  \
+             \"capture\" [shape=doublecircle];\n  \"numbers\" -> \"double\";\n  \
+             \"double\" -> \"capture\";\n}\n"
+        );
+        block_on(run.drain()).unwrap();
+        capture.assert_items(&[2, 4, 6]);
+    }
+
+    #[test]
+    fn test_invalid_graphs() {
+        let (mut graph, _) = numbers_graph();
+        graph.connect("numbers", "double");
+        assert_eq!(
+            graph.build().err(),
+            Some(GraphError::Dangling("double".to_string()))
+        );
+        let (mut graph, _) = numbers_graph();
+        graph.connect("numbers", "capture");
+        graph.connect("double", "double");
+        let error = graph.build().err().unwrap();
+        assert_eq!(error, GraphError::Cycle(vec!["double".to_string()]));
+        assert_eq!(error.to_string(), "Nodes double form a cycle");
+        let (mut graph, _) = numbers_graph();
+        graph
+            .add_pipe(
+                "parse",
+                FlatMapPipe::new(|text: String| text.parse::<i32>().ok()),
+            )
+            .unwrap();
+        graph.connect("numbers", "parse");
+        graph.connect("parse", "double");
+        graph.connect("double", "capture");
+        assert_eq!(
+            graph.build().err(),
+            Some(GraphError::TypeMismatch {
+                from: "numbers".to_string(),
+                to: "parse".to_string()
+            })
+        );
+        let (mut graph, _) = numbers_graph();
+        graph.connect("numbers", "missing");
+        assert_eq!(
+            graph.build().err(),
+            Some(GraphError::UnknownNode("missing".to_string()))
+        );
+    }
+}
@@ -0,0 +1,341 @@
+//! lineage
+//!
+//! A pipeline wide graph of the blobs observed at named stages and how they derive from
+//! each other across runs
+//!
+//! blobs are observed by lineage taps placed between stages, a blob derives from the blob
+//! observed earlier in the same run whose provenance is the longest prefix of its own, the
+//! operations recorded in between label the edge, blobs combined from several inputs only
+//! link to the input they kept their provenance from
+
+use crate::data_bucket::provenance::ProvenanceEntry;
+use crate::data_bucket::{DataBlob, MetaData};
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::RefCell;
+use std::fmt::Write;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// LineageNode
+/// A blob observed at a stage of a run
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineageNode {
+    /// index of the node in the graph
+    pub id: usize,
+    /// name of the run the blob was observed in
+    pub run: String,
+    /// name of the stage the blob was observed at
+    pub stage: String,
+    /// name of the blob
+    pub blob: String,
+}
+
+/// LineageEdge
+/// The derivation of a blob from an earlier one
+#[derive(Clone, Debug, PartialEq)]
+pub struct LineageEdge {
+    pub from: usize,
+    pub to: usize,
+    /// operations applied between the two observations, oldest first
+    pub operations: Vec<ProvenanceEntry>,
+}
+
+#[derive(Default)]
+struct LineageState {
+    run: String,
+    nodes: Vec<LineageNode>,
+    provenances: Vec<Vec<ProvenanceEntry>>,
+    edges: Vec<LineageEdge>,
+}
+
+/// Lineage
+/// A shared recorder of the lineage graph of the blobs flowing through a pipeline
+#[derive(Clone, Default)]
+pub struct Lineage {
+    state: Rc<RefCell<LineageState>>,
+}
+
+/// escape a string for JSON and DOT string literals
+pub(crate) fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for character in text.chars() {
+        match character {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            character if (character as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", character as u32);
+            }
+            character => escaped.push(character),
+        }
+    }
+    escaped
+}
+
+impl Lineage {
+    /// constructor of an empty graph in an unnamed run
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// start a new run, blobs observed from now on belong to it and never derive from
+    /// blobs of earlier runs
+    pub fn start_run(&self, name: &str) {
+        self.state.borrow_mut().run = name.to_string();
+    }
+    /// record a blob observed at a stage, returns the id of its node
+    pub fn observe(&self, stage: &str, meta: &MetaData) -> usize {
+        let mut state = self.state.borrow_mut();
+        let id = state.nodes.len();
+        let parent = (0..id)
+            .filter(|node| state.nodes[*node].run == state.run)
+            .filter(|node| meta.provenance.starts_with(&state.provenances[*node]))
+            .filter(|node| {
+                !state.provenances[*node].is_empty() || state.nodes[*node].blob == meta.name
+            })
+            .max_by_key(|node| (state.provenances[*node].len(), *node));
+        if let Some(parent) = parent {
+            let operations = meta.provenance[state.provenances[parent].len()..].to_vec();
+            state.edges.push(LineageEdge {
+                from: parent,
+                to: id,
+                operations,
+            });
+        }
+        let run = state.run.clone();
+        state.nodes.push(LineageNode {
+            id,
+            run,
+            stage: stage.to_string(),
+            blob: meta.name.clone(),
+        });
+        state.provenances.push(meta.provenance.clone());
+        id
+    }
+    /// a pass through pipe observing every blob streamed through it at a stage
+    pub fn tap<T>(&self, stage: &str) -> LineagePipe<T> {
+        LineagePipe {
+            lineage: self.clone(),
+            stage: stage.to_string(),
+            input: None,
+        }
+    }
+    /// every node in order of observation
+    pub fn nodes(&self) -> Vec<LineageNode> {
+        self.state.borrow().nodes.clone()
+    }
+    /// every derivation in order of observation of the derived blob
+    pub fn edges(&self) -> Vec<LineageEdge> {
+        self.state.borrow().edges.clone()
+    }
+    /// the blobs a node derives from, closest first
+    pub fn ancestors(&self, node: usize) -> Vec<LineageNode> {
+        let state = self.state.borrow();
+        let mut ancestors = Vec::new();
+        let mut current = node;
+        while let Some(edge) = state.edges.iter().find(|edge| edge.to == current) {
+            ancestors.push(state.nodes[edge.from].clone());
+            current = edge.from;
+        }
+        ancestors
+    }
+    /// the graph as a JSON object holding `nodes` and `edges` arrays
+    pub fn to_json(&self) -> String {
+        let state = self.state.borrow();
+        let nodes: Vec<String> = state
+            .nodes
+            .iter()
+            .map(|node| {
+                format!(
+                    "{{\"id\":{},\"run\":\"{}\",\"stage\":\"{}\",\"blob\":\"{}\"}}",
+                    node.id,
+                    escape(&node.run),
+                    escape(&node.stage),
+                    escape(&node.blob)
+                )
+            })
+            .collect();
+        let edges: Vec<String> = state
+            .edges
+            .iter()
+            .map(|edge| {
+                let operations: Vec<String> = edge
+                    .operations
+                    .iter()
+                    .map(|entry| {
+                        let parameters: Vec<String> = entry
+                            .parameters
+                            .iter()
+                            .map(|(name, value)| {
+                                format!("\"{}\":\"{}\"", escape(name), escape(value))
+                            })
+                            .collect();
+                        format!(
+                            "{{\"operation\":\"{}\",\"parameters\":{{{}}}}}",
+                            escape(&entry.operation),
+                            parameters.join(",")
+                        )
+                    })
+                    .collect();
+                format!(
+                    "{{\"from\":{},\"to\":{},\"operations\":[{}]}}",
+                    edge.from,
+                    edge.to,
+                    operations.join(",")
+                )
+            })
+            .collect();
+        format!(
+            "{{\"nodes\":[{}],\"edges\":[{}]}}",
+            nodes.join(","),
+            edges.join(",")
+        )
+    }
+    /// the graph in the DOT language, one cluster per run
+    pub fn to_dot(&self) -> String {
+        let state = self.state.borrow();
+        let mut dot = String::from("digraph lineage {\n");
+        let mut runs: Vec<&str> = Vec::new();
+        for node in &state.nodes {
+            if !runs.contains(&node.run.as_str()) {
+                runs.push(&node.run);
+            }
+        }
+        for (index, run) in runs.iter().enumerate() {
+            let _ = writeln!(dot, "  subgraph cluster_{} {{", index);
+            let _ = writeln!(dot, "    label=\"{}\";", escape(run));
+            for node in state.nodes.iter().filter(|node| node.run == *run) {
+                let _ = writeln!(
+                    dot,
+                    "    n{} [label=\"{}\\n{}\"];",
+                    node.id,
+                    escape(&node.blob),
+                    escape(&node.stage)
+                );
+            }
+            dot.push_str("  }\n");
+        }
+        for edge in &state.edges {
+            let label: Vec<String> = edge
+                .operations
+                .iter()
+                .map(ProvenanceEntry::to_string)
+                .collect();
+            let _ = writeln!(
+                dot,
+                "  n{} -> n{} [label=\"{}\"];",
+                edge.from,
+                edge.to,
+                escape(&label.join("\n"))
+            );
+        }
+        dot.push_str("}\n");
+        dot
+    }
+}
+
+/// LineagePipe
+/// A pipe passing blobs through unchanged while observing them in a lineage graph
+pub struct LineagePipe<T> {
+    lineage: Lineage,
+    stage: String,
+    input: Option<Rc<dyn Source<DataBlob<T>>>>,
+}
+
+impl<T: 'static> Source<DataBlob<T>> for LineagePipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBlob<T>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let lineage = self.lineage.clone();
+        let stage = self.stage.clone();
+        Box::new(input.inspect(move |blob| {
+            lineage.observe(&stage, blob.get_meta_data());
+        }))
+    }
+}
+
+impl<T: 'static> Pipe<DataBlob<T>, DataBlob<T>> for LineagePipe<T> {
+    input_connection!(DataBlob<T>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::{ConvertUnitsPipe, MissingPolicy, NormalizePipe};
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn run(lineage: &Lineage, name: &str) -> Vec<DataBlob<f64>> {
+        lineage.start_run(name);
+        let meta = |name: &str| MetaData {
+            name: name.to_string(),
+            units: Some("km".to_string()),
+            ..Default::default()
+        };
+        let mut source = lineage.tap("source");
+        source
+            .pipe(Rc::new(MockSource::new().items([
+                DataBlob::new(vec![1.0, 2.0], meta("a")),
+                DataBlob::new(vec![3.0], meta("b")),
+            ])))
+            .unwrap();
+        let mut convert = ConvertUnitsPipe::new("m");
+        convert.pipe(Rc::new(source)).unwrap();
+        let mut normalize = NormalizePipe::new(MissingPolicy::Skip);
+        normalize.pipe(Rc::new(convert)).unwrap();
+        let mut output = lineage.tap("output");
+        output.pipe(Rc::new(normalize)).unwrap();
+        block_on(Pin::from(output.stream()).collect())
+    }
+
+    #[test]
+    fn test_lineage_across_runs() {
+        let lineage = Lineage::new();
+        run(&lineage, "first");
+        run(&lineage, "second");
+        let nodes = lineage.nodes();
+        assert_eq!(nodes.len(), 8);
+        let edges = lineage.edges();
+        assert_eq!(edges.len(), 4);
+        for edge in &edges {
+            assert_eq!(
+                nodes[edge.from].run, nodes[edge.to].run,
+                "Linked across runs"
+            );
+            assert_eq!(nodes[edge.from].blob, nodes[edge.to].blob);
+            let operations: Vec<&str> = edge
+                .operations
+                .iter()
+                .map(|e| e.operation.as_str())
+                .collect();
+            assert_eq!(operations, vec!["convert_units", "normalize"]);
+        }
+        let last = nodes.last().unwrap();
+        assert_eq!((last.stage.as_str(), last.blob.as_str()), ("output", "b"));
+        let ancestors = lineage.ancestors(last.id);
+        assert_eq!(ancestors.len(), 1);
+        assert_eq!(
+            (ancestors[0].stage.as_str(), ancestors[0].run.as_str()),
+            ("source", "second")
+        );
+    }
+
+    #[test]
+    fn test_lineage_exports() {
+        let lineage = Lineage::new();
+        run(&lineage, "nightly \"1\"");
+        let json = lineage.to_json();
+        assert!(json.starts_with("{\"nodes\":[{\"id\":0,\"run\":\"nightly \\\"1\\\"\""));
+        assert!(json.contains(
+            "{\"operation\":\"convert_units\",\"parameters\":{\"from\":\"km\",\"to\":\"m\"}}"
+        ));
+        let dot = lineage.to_dot();
+        assert!(dot.starts_with("digraph lineage {\n  subgraph cluster_0 {"));
+        assert!(dot.contains(
+            "n0 -> n1 [label=\"convert_units(from=km, to=m)\\nnormalize(policy=Skip)\"];"
+        ));
+    }
+}
@@ -0,0 +1,220 @@
+//! template
+//!
+//! Parameterized chains of pipes instantiated as many times as needed
+//!
+//! a template records how to build each of its steps from a configuration, every
+//! instantiation builds fresh pipes from the configuration it is given and fuses them into a
+//! single pipe that can be wired into a larger graph like any other pipe
+
+use crate::data_bucket::schema::StreamSchema;
+use crate::pipes::ComposedPipe;
+use crate::{Pipe, Source};
+use futures::Stream;
+use std::rc::Rc;
+
+type Build<C, InT, OutT> = Rc<dyn Fn(&C) -> Result<TemplatePipe<InT, OutT>, &'static str>>;
+
+/// PipelineTemplate
+/// A named chain of pipes built from a configuration of type `C`
+pub struct PipelineTemplate<C, InT, OutT> {
+    name: String,
+    steps: usize,
+    build: Build<C, InT, OutT>,
+}
+
+impl<C, InT, OutT> Clone for PipelineTemplate<C, InT, OutT> {
+    fn clone(&self) -> Self {
+        Self {
+            name: self.name.clone(),
+            steps: self.steps,
+            build: self.build.clone(),
+        }
+    }
+}
+
+impl<C: 'static, InT: 'static, OutT: 'static> PipelineTemplate<C, InT, OutT> {
+    /// constructor from the builder of the first step
+    pub fn new<P, F>(name: &str, step: F) -> Self
+    where
+        P: Pipe<InT, OutT> + 'static,
+        F: Fn(&C) -> Result<P, &'static str> + 'static,
+    {
+        Self {
+            name: name.to_string(),
+            steps: 1,
+            build: Rc::new(move |config| {
+                Ok(TemplatePipe {
+                    template: String::new(),
+                    pipe: Box::new(step(config)?),
+                })
+            }),
+        }
+    }
+    /// append a step consuming the output of the previous ones
+    pub fn then<NextT, P, F>(self, step: F) -> PipelineTemplate<C, InT, NextT>
+    where
+        NextT: 'static,
+        P: Pipe<OutT, NextT> + 'static,
+        F: Fn(&C) -> Result<P, &'static str> + 'static,
+    {
+        let previous = self.build;
+        PipelineTemplate {
+            name: self.name,
+            steps: self.steps + 1,
+            build: Rc::new(move |config| {
+                let composed = ComposedPipe::new(previous(config)?, step(config)?)?;
+                Ok(TemplatePipe {
+                    template: String::new(),
+                    pipe: Box::new(composed),
+                })
+            }),
+        }
+    }
+    /// the name of the template
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// the number of steps of the template
+    pub fn steps(&self) -> usize {
+        self.steps
+    }
+    /// build the steps from a configuration (returns an error if a step rejects it)
+    pub fn instantiate(&self, config: &C) -> Result<TemplatePipe<InT, OutT>, &'static str> {
+        let mut instance = (self.build)(config)?;
+        instance.template = self.name.clone();
+        Ok(instance)
+    }
+}
+
+/// TemplatePipe
+/// An instance of a pipeline template
+pub struct TemplatePipe<InT, OutT> {
+    template: String,
+    pipe: Box<dyn Pipe<InT, OutT>>,
+}
+
+impl<InT, OutT> TemplatePipe<InT, OutT> {
+    /// the name of the template instantiated
+    pub fn template(&self) -> &str {
+        &self.template
+    }
+}
+
+impl<InT, OutT> Source<OutT> for TemplatePipe<InT, OutT> {
+    fn stream(&self) -> Box<dyn Stream<Item = OutT>> {
+        self.pipe.stream()
+    }
+    fn schema(&self) -> Option<StreamSchema> {
+        self.pipe.schema()
+    }
+}
+
+impl<InT, OutT> Pipe<InT, OutT> for TemplatePipe<InT, OutT> {
+    fn pipe(&mut self, input: Rc<dyn Source<InT>>) -> Result<(), &'static str> {
+        self.pipe.pipe(input)
+    }
+    fn unpipe(&mut self) {
+        self.pipe.unpipe();
+    }
+    fn get_input(&self) -> Option<Rc<dyn Source<InT>>> {
+        self.pipe.get_input()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::schema::{BlobKind, DeclaredSource, SchemaPipe};
+    use crate::data_bucket::DataBucketBlob;
+    use crate::ops::ElementwisePipe;
+    use crate::pipes::FlatMapPipe;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::pin::Pin;
+
+    struct Config {
+        radix: u32,
+        max: i64,
+        scale: i64,
+    }
+
+    fn template() -> PipelineTemplate<Config, &'static str, i64> {
+        PipelineTemplate::new("parse-validate-normalize", |config: &Config| {
+            let radix = config.radix;
+            if !(2..=36).contains(&radix) {
+                return Err("Unsupported radix");
+            }
+            Ok(FlatMapPipe::new(move |text: &str| {
+                i64::from_str_radix(text, radix).ok()
+            }))
+        })
+        .then(|config: &Config| {
+            let max = config.max;
+            Ok(FlatMapPipe::new(move |value: i64| {
+                (value <= max).then_some(value)
+            }))
+        })
+        .then(|config: &Config| Ok(ElementwisePipe::scale(config.scale)))
+    }
+
+    #[test]
+    fn test_template_instances() {
+        let template = template();
+        assert_eq!(template.steps(), 3);
+        let input: Rc<dyn Source<&str>> = Rc::new(MockSource::new().items(["10", "ff", "7"]));
+        let mut decimal = template
+            .instantiate(&Config {
+                radix: 10,
+                max: 9,
+                scale: 2,
+            })
+            .unwrap();
+        let mut hexadecimal = template
+            .instantiate(&Config {
+                radix: 16,
+                max: 1000,
+                scale: -1,
+            })
+            .unwrap();
+        decimal.pipe(input.clone()).unwrap();
+        hexadecimal.pipe(input).unwrap();
+        assert_eq!(hexadecimal.template(), "parse-validate-normalize");
+        assert_eq!(
+            block_on(Pin::from(decimal.stream()).collect::<Vec<_>>()),
+            vec![14]
+        );
+        assert_eq!(
+            block_on(Pin::from(hexadecimal.stream()).collect::<Vec<_>>()),
+            vec![-16, -255, -7]
+        );
+        assert!(template
+            .instantiate(&Config {
+                radix: 99,
+                max: 0,
+                scale: 1,
+            })
+            .is_err());
+    }
+
+    #[test]
+    fn test_template_checks_inputs() {
+        let template = PipelineTemplate::new("floats", |_: &()| {
+            Ok(
+                SchemaPipe::new(FlatMapPipe::new(|blob: DataBucketBlob| Some(blob)))
+                    .expects(StreamSchema::Blob(BlobKind::Float64)),
+            )
+        })
+        .then(|_: &()| Ok(FlatMapPipe::new(|blob: DataBucketBlob| Some(blob))));
+        let mut instance = template.instantiate(&()).unwrap();
+        let strings = Rc::new(DeclaredSource::new(
+            Rc::new(MockSource::new().items(Vec::<DataBucketBlob>::new())),
+            StreamSchema::Blob(BlobKind::Str),
+        ));
+        assert!(
+            instance.pipe(strings).is_err(),
+            "Checks of the first step bypassed"
+        );
+        assert!(instance.get_input().is_none());
+    }
+}
@@ -0,0 +1,83 @@
+//! pipes
+//!
+//! General purpose pipes shaping and transforming data streams
+
+/// bloom
+/// Sub module for dropping repeated items with a Bloom filter
+pub mod bloom;
+
+/// buffer
+/// Sub module for prefetching items ahead of consumers
+pub mod buffer;
+
+/// compose
+/// Sub module for fusing pipes together
+pub mod compose;
+
+/// distinct
+/// Sub module for suppressing repeated items
+pub mod distinct;
+
+/// flat_map
+/// Sub module for expanding items into several items
+pub mod flat_map;
+
+/// inspect
+/// Sub module for observing streams
+pub mod inspect;
+
+/// ordered_merge
+/// Sub module for merging sorted streams
+pub mod ordered_merge;
+
+/// ordered_par_map
+/// Sub module for concurrent mapping with bounded parallelism
+pub mod ordered_par_map;
+
+/// par_map
+/// Sub module for data parallel mapping
+pub mod par_map;
+
+/// partition
+/// Sub module for routing items by key
+pub mod partition;
+
+/// record
+/// Sub module for recording streams and replaying them
+pub mod record;
+
+/// round_robin
+/// Sub module for balancing items over branches
+pub mod round_robin;
+
+/// scan
+/// Sub module for stateful running computations
+pub mod scan;
+
+/// swap
+/// Sub module for replacing pipes of live streams
+pub mod swap;
+
+/// take
+/// Sub module for bounding streams and skipping their head
+pub mod take;
+
+/// split
+/// Sub module for fanning a stream out to several outputs
+pub(crate) mod split;
+
+pub use bloom::{BloomDedupPipe, BloomFilter};
+pub use buffer::BufferPipe;
+pub use compose::{ComposedPipe, PipeExt};
+pub use distinct::DistinctUntilChangedPipe;
+pub use flat_map::FlatMapPipe;
+pub use inspect::InspectPipe;
+pub use ordered_merge::OrderedMergePipe;
+pub use ordered_par_map::OrderedParMapPipe;
+pub use par_map::ParMapPipe;
+pub use partition::PartitionPipe;
+pub use record::{RecordPipe, ReplaySource, ReplayTiming};
+pub use round_robin::RoundRobinSplit;
+pub use scan::ScanPipe;
+pub use swap::SwappablePipe;
+pub use take::{SkipPipe, TakePipe, TakeWhilePipe};
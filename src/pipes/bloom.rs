@@ -0,0 +1,189 @@
+//! bloom
+//!
+//! Dropping repeated items with a Bloom filter, in memory bounded by the number of keys
+//! expected rather than the number seen
+//!
+//! a Bloom filter never forgets a key it holds but may mistake a new key for one it holds,
+//! at a rate set when it is sized, so a deduplicating pipe passes every key at most once
+//! and drops a small share of the keys it never saw
+
+use crate::{Pipe, Source};
+use futures::future;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// the hash of a key in the family of hashes picked by a seed
+pub(crate) fn seeded_hash<K: Hash + ?Sized>(key: &K, seed: u64) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    seed.hash(&mut hasher);
+    key.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// BloomFilter
+/// A set of keys answering membership with false positives only
+#[derive(Clone, Debug, PartialEq)]
+pub struct BloomFilter {
+    bits: Vec<u64>,
+    len: u64,
+    hashes: u32,
+}
+
+impl BloomFilter {
+    /// constructor sized for a number of keys at a rate of false positives in `(0, 1)`
+    pub fn new(expected: u64, false_positive_rate: f64) -> Result<Self, &'static str> {
+        if !(false_positive_rate > 0.0 && false_positive_rate < 1.0) {
+            return Err("False positive rate must lie in (0, 1)");
+        }
+        let ln2 = std::f64::consts::LN_2;
+        let expected = expected.max(1) as f64;
+        let len = (-expected * false_positive_rate.ln() / (ln2 * ln2))
+            .ceil()
+            .max(64.0);
+        let hashes = (len / expected * ln2).round().clamp(1.0, 32.0) as u32;
+        let words = usize::try_from((len as u64).div_ceil(64))
+            .map_err(|_| "Bloom filter too large for this platform")?;
+        Ok(Self {
+            bits: vec![0; words],
+            len: words as u64 * 64,
+            hashes,
+        })
+    }
+    /// the positions of a key, by double hashing
+    fn positions<K: Hash + ?Sized>(&self, key: &K) -> impl Iterator<Item = u64> {
+        let (first, second) = (seeded_hash(key, 0), seeded_hash(key, 1) | 1);
+        let len = self.len;
+        (0..self.hashes as u64).map(move |i| first.wrapping_add(i.wrapping_mul(second)) % len)
+    }
+    /// whether the filter may hold a key
+    pub fn contains<K: Hash + ?Sized>(&self, key: &K) -> bool {
+        self.positions(key)
+            .all(|bit| self.bits[(bit / 64) as usize] & (1 << (bit % 64)) != 0)
+    }
+    /// add a key, returning whether the filter may have held it already
+    pub fn insert<K: Hash + ?Sized>(&mut self, key: &K) -> bool {
+        let mut present = true;
+        for bit in self.positions(key).collect::<Vec<u64>>() {
+            let (word, mask) = ((bit / 64) as usize, 1 << (bit % 64));
+            present &= self.bits[word] & mask != 0;
+            self.bits[word] |= mask;
+        }
+        present
+    }
+    /// number of bits of the filter
+    pub fn bit_len(&self) -> u64 {
+        self.len
+    }
+    /// number of hashes marking each key
+    pub fn hash_count(&self) -> u32 {
+        self.hashes
+    }
+    /// empty the filter
+    pub fn clear(&mut self) {
+        self.bits.fill(0);
+    }
+}
+
+/// BloomDedupPipe
+/// A pipe dropping the items whose key a Bloom filter may hold, adding the keys of the
+/// items it passes
+///
+/// every stream starts over from an empty filter, once many more keys than expected went
+/// through the filter drops most new keys as well
+pub struct BloomDedupPipe<T, K = T> {
+    filter: BloomFilter,
+    key: Rc<dyn Fn(&T) -> K>,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T: Hash + Clone + 'static> BloomDedupPipe<T> {
+    /// constructor comparing whole items, sized for a number of distinct items at a rate
+    /// of false positives in `(0, 1)`
+    pub fn new(expected: u64, false_positive_rate: f64) -> Result<Self, &'static str> {
+        Self::by_key(expected, false_positive_rate, T::clone)
+    }
+}
+
+impl<T, K: Hash> BloomDedupPipe<T, K> {
+    /// constructor comparing the keys extracted from the items
+    pub fn by_key<F: Fn(&T) -> K + 'static>(
+        expected: u64,
+        false_positive_rate: f64,
+        key: F,
+    ) -> Result<Self, &'static str> {
+        Ok(Self {
+            filter: BloomFilter::new(expected, false_positive_rate)?,
+            key: Rc::new(key),
+            input: None,
+        })
+    }
+    /// the empty filter every stream starts from
+    pub fn filter(&self) -> &BloomFilter {
+        &self.filter
+    }
+}
+
+impl<T: 'static, K: Hash + 'static> Source<T> for BloomDedupPipe<T, K> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let key = self.key.clone();
+        let mut filter = self.filter.clone();
+        Box::new(input.filter(move |item| future::ready(!filter.insert(&key(item)))))
+    }
+}
+
+impl<T: 'static, K: Hash + 'static> Pipe<T, T> for BloomDedupPipe<T, K> {
+    input_connection!(T);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_bloom_filter() {
+        let mut filter = BloomFilter::new(10_000, 0.01).unwrap();
+        assert_eq!(filter.hash_count(), 7);
+        assert!(filter.bit_len() >= 95_851);
+        let repeated = (0..10_000u32).filter(|key| filter.insert(key)).count();
+        assert!(repeated < 150, "{} keys taken as repeated", repeated);
+        assert!((0..10_000u32).all(|key| filter.contains(&key)));
+        let false_positives = (10_000..110_000u32)
+            .filter(|key| filter.contains(key))
+            .count();
+        assert!(
+            false_positives < 1_500,
+            "{} false positives",
+            false_positives
+        );
+        filter.clear();
+        assert!(!filter.contains(&0u32));
+        assert!(BloomFilter::new(10, 1.0).is_err());
+    }
+
+    #[test]
+    fn test_bloom_dedup_pipe() {
+        let words = ["a", "bb", "a", "ccc", "bb", "dd", "a"];
+        let mut pipe = BloomDedupPipe::new(100, 0.001).unwrap();
+        pipe.pipe(Rc::new(MockSource::new().items(words))).unwrap();
+        let items: Vec<&str> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(items, vec!["a", "bb", "ccc", "dd"]);
+        let again: Vec<&str> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(again, items, "Filter was not reset between streams");
+        let mut by_len = BloomDedupPipe::by_key(100, 0.001, |word: &&str| word.len()).unwrap();
+        by_len
+            .pipe(Rc::new(MockSource::new().items(words)))
+            .unwrap();
+        let items: Vec<&str> = block_on(Pin::from(by_len.stream()).collect());
+        assert_eq!(items, vec!["a", "bb", "ccc"]);
+    }
+}
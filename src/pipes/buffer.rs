@@ -0,0 +1,159 @@
+//! buffer
+//!
+//! Prefetching items from a slow producer ahead of a bursty consumer
+
+use crate::data_bucket::footprint::Footprint;
+use crate::memory::MemoryTracker;
+use crate::{Pipe, Source};
+use futures::channel::mpsc;
+use futures::future::FutureExt;
+use futures::stream;
+use futures::task::{LocalSpawn, LocalSpawnExt};
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// BufferPipe
+/// A pipe eagerly pulling up to a capacity of items from its input into a queue
+///
+/// by default the input is pulled whenever the output stream is polled, even while no item
+/// is requested, given a spawner the input is pulled on a task of its own so it also makes
+/// progress while the consumer is busy awaiting something else
+pub struct BufferPipe<T> {
+    capacity: usize,
+    spawner: Option<Rc<dyn LocalSpawn>>,
+    meter: Option<Meter<T>>,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+type Meter<T> = (MemoryTracker, String, fn(&T) -> usize);
+
+impl<T> BufferPipe<T> {
+    /// constructor for a queue holding up to `capacity` items
+    pub fn new(capacity: usize) -> Result<Self, &'static str> {
+        if capacity == 0 {
+            return Err("Buffer capacity must be strictly positive");
+        }
+        Ok(Self {
+            capacity,
+            spawner: None,
+            meter: None,
+            input: None,
+        })
+    }
+    /// pull the input on a task spawned with the given spawner
+    pub fn spawn_on<S: LocalSpawn + 'static>(mut self, spawner: S) -> Self {
+        self.spawner = Some(Rc::new(spawner));
+        self
+    }
+    /// maximum number of queued items
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+}
+
+impl<T: Footprint> BufferPipe<T> {
+    /// account for the queued items under a stage of a tracker
+    pub fn track(mut self, tracker: &MemoryTracker, stage: &str) -> Self {
+        self.meter = Some((tracker.clone(), stage.to_string(), T::footprint));
+        self
+    }
+}
+
+impl<T: 'static> Source<T> for BufferPipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        // a channel holds its buffer plus one slot per sender
+        let (sender, receiver) = mpsc::channel(self.capacity - 1);
+        let meter = self.meter.clone();
+        let forward = input
+            .map(move |item| {
+                let reservation = meter
+                    .as_ref()
+                    .map(|(tracker, stage, measure)| tracker.reserve(stage, measure(&item)));
+                Ok((item, reservation))
+            })
+            .forward(sender)
+            .map(|_| ());
+        // reservations are released as items leave the queue
+        let receiver = receiver.map(|(item, _)| item);
+        if let Some(spawner) = &self.spawner {
+            // a shut down spawner drops the forward along with its sender, ending the stream
+            let _ = spawner.spawn_local(forward);
+            return Box::new(receiver);
+        }
+        let prefetch = stream::once(forward).filter_map(|_| async { None });
+        Box::new(stream::select(receiver, prefetch))
+    }
+}
+
+impl<T: 'static> Pipe<T, T> for BufferPipe<T> {
+    input_connection!(T);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipes::InspectPipe;
+    use crate::testing::MockSource;
+    use futures::executor::{block_on, LocalPool};
+    use std::cell::Cell;
+
+    fn counted_input(count: usize) -> (Rc<Cell<usize>>, Rc<dyn Source<usize>>) {
+        let pulled = Rc::new(Cell::new(0));
+        let counter = pulled.clone();
+        let mut inspect = InspectPipe::new(move |_: &usize| counter.set(counter.get() + 1));
+        inspect
+            .pipe(Rc::new(MockSource::new().items(0..count)))
+            .unwrap();
+        (pulled, Rc::new(inspect))
+    }
+
+    #[test]
+    fn test_buffer_passes_items_through() {
+        let (_, input) = counted_input(100);
+        let mut pipe = BufferPipe::new(8).unwrap();
+        pipe.pipe(input).unwrap();
+        let items: Vec<usize> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(items, (0..100).collect::<Vec<_>>());
+        assert!(BufferPipe::<usize>::new(0).is_err());
+    }
+
+    #[test]
+    fn test_buffer_prefetches_on_spawned_task() {
+        let mut pool = LocalPool::new();
+        let (pulled, input) = counted_input(100);
+        let mut pipe = BufferPipe::new(4).unwrap().spawn_on(pool.spawner());
+        pipe.pipe(input).unwrap();
+        let mut output = Pin::from(pipe.stream());
+        assert_eq!(pool.run_until(output.next()), Some(0));
+        pool.run_until_stalled();
+        assert!(pulled.get() > 1, "Failure to prefetch items");
+        assert!(pulled.get() <= 6, "Prefetched beyond capacity");
+        let rest: Vec<usize> = pool.run_until(output.collect());
+        assert_eq!(rest, (1..100).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_buffer_tracks_queued_items() {
+        let mut pool = LocalPool::new();
+        let tracker = MemoryTracker::new();
+        let (_, input) = counted_input(100);
+        let mut pipe = BufferPipe::new(4)
+            .unwrap()
+            .spawn_on(pool.spawner())
+            .track(&tracker, "buffer");
+        pipe.pipe(input).unwrap();
+        let mut output = Pin::from(pipe.stream());
+        pool.run_until(output.next());
+        pool.run_until_stalled();
+        let queued = tracker.live() / std::mem::size_of::<usize>();
+        assert!((1..=5).contains(&queued), "Queued items not accounted for");
+        pool.run_until(output.for_each(|_| async {}));
+        assert_eq!(tracker.live(), 0);
+        assert_eq!(tracker.stage("buffer").peak, tracker.peak());
+    }
+}
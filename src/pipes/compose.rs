@@ -0,0 +1,166 @@
+//! compose
+//!
+//! Fusing chains of pipes into a single pipe
+
+use crate::data_bucket::schema::StreamSchema;
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::Stream;
+use std::cell::RefCell;
+use std::rc::Rc;
+
+/// a source streaming the output of a pipe shared with the composition connecting it
+struct SharedStage<InT, OutT> {
+    pipe: Rc<RefCell<dyn Pipe<InT, OutT>>>,
+}
+
+impl<InT, OutT> Source<OutT> for SharedStage<InT, OutT> {
+    fn stream(&self) -> Box<dyn Stream<Item = OutT>> {
+        self.pipe.borrow().stream()
+    }
+    fn schema(&self) -> Option<StreamSchema> {
+        self.pipe.borrow().schema()
+    }
+}
+
+/// ComposedPipe
+/// A pipe running two pipes back to back, the items of the first flowing directly into
+/// the second within the same stream
+///
+/// inputs are connected to the first pipe, so its own checks run against them, and the
+/// second pipe is connected again to the first so it checks the schema the first declares
+/// once it has an input
+pub struct ComposedPipe<InT, MidT, OutT> {
+    first: Rc<RefCell<dyn Pipe<InT, MidT>>>,
+    second: Box<dyn Pipe<MidT, OutT>>,
+}
+
+impl<InT: 'static, MidT: 'static, OutT: 'static> ComposedPipe<InT, MidT, OutT> {
+    /// constructor feeding the output of the first pipe to the second
+    pub fn new<P, Q>(first: P, mut second: Q) -> Result<Self, &'static str>
+    where
+        P: Pipe<InT, MidT> + 'static,
+        Q: Pipe<MidT, OutT> + 'static,
+    {
+        let first: Rc<RefCell<dyn Pipe<InT, MidT>>> = Rc::new(RefCell::new(first));
+        second.pipe(Rc::new(SharedStage {
+            pipe: first.clone(),
+        }))?;
+        Ok(Self {
+            first,
+            second: Box::new(second),
+        })
+    }
+}
+
+impl<InT, MidT, OutT: 'static> Source<OutT> for ComposedPipe<InT, MidT, OutT> {
+    fn stream(&self) -> Box<dyn Stream<Item = OutT>> {
+        if self.first.borrow().get_input().is_none() {
+            return Box::new(stream::empty());
+        }
+        self.second.stream()
+    }
+    fn schema(&self) -> Option<StreamSchema> {
+        self.second.schema()
+    }
+}
+
+impl<InT: 'static, MidT: 'static, OutT: 'static> Pipe<InT, OutT> for ComposedPipe<InT, MidT, OutT> {
+    /// connect the first pipe, then the second to the first again (returns an error,
+    /// leaving the first pipe unconnected, if either rejects its input)
+    fn pipe(&mut self, input: Rc<dyn Source<InT>>) -> Result<(), &'static str> {
+        self.first.borrow_mut().pipe(input)?;
+        let stage = Rc::new(SharedStage {
+            pipe: self.first.clone(),
+        });
+        self.second.pipe(stage).inspect_err(|_| {
+            self.first.borrow_mut().unpipe();
+        })
+    }
+    fn unpipe(&mut self) {
+        self.first.borrow_mut().unpipe();
+    }
+    fn get_input(&self) -> Option<Rc<dyn Source<InT>>> {
+        self.first.borrow().get_input()
+    }
+}
+
+/// PipeExt
+/// Combinators available on every pipe
+pub trait PipeExt<InT, MidT>: Pipe<InT, MidT> + Sized + 'static {
+    /// fuse this pipe with a pipe consuming its output
+    fn then<OutT, Q>(self, next: Q) -> Result<ComposedPipe<InT, MidT, OutT>, &'static str>
+    where
+        InT: 'static,
+        MidT: 'static,
+        OutT: 'static,
+        Q: Pipe<MidT, OutT> + 'static,
+    {
+        ComposedPipe::new(self, next)
+    }
+}
+
+impl<InT, MidT, P: Pipe<InT, MidT> + 'static> PipeExt<InT, MidT> for P {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::schema::{BlobKind, DeclaredSource, SchemaPipe};
+    use crate::data_bucket::DataBucketBlob;
+    use crate::ops::ElementwisePipe;
+    use crate::pipes::{FlatMapPipe, ParMapPipe};
+    use crate::testing::{collect, MockSource};
+
+    #[test]
+    fn test_then_chains_pipes() {
+        let mut composed = ElementwisePipe::scale(2)
+            .then(ParMapPipe::new(|item: i32| item.to_string()))
+            .unwrap()
+            .then(ParMapPipe::new(|text: String| text.len()))
+            .unwrap();
+        assert!(composed.get_input().is_none());
+        assert!(
+            collect(&composed).is_empty(),
+            "Unconnected pipe streams items"
+        );
+        composed
+            .pipe(Rc::new(MockSource::new().items([1, 5, 60])))
+            .unwrap();
+        assert_eq!(collect(&composed), vec![1, 2, 3]);
+        composed
+            .pipe(Rc::new(MockSource::new().items([500])))
+            .unwrap();
+        assert_eq!(collect(&composed), vec![4], "Failure to replace input");
+        composed.unpipe();
+        assert!(composed.get_input().is_none());
+        assert!(collect(&composed).is_empty());
+    }
+
+    #[test]
+    fn test_then_checks_inputs() {
+        let strings = || {
+            Rc::new(DeclaredSource::new(
+                Rc::new(MockSource::new().items(Vec::<DataBucketBlob>::new())),
+                StreamSchema::Blob(BlobKind::Str),
+            ))
+        };
+        let identity = || FlatMapPipe::new(|blob: DataBucketBlob| Some(blob));
+        let expects = |kind| SchemaPipe::new(identity()).expects(StreamSchema::Blob(kind));
+        let mut floats = expects(BlobKind::Float64).then(identity()).unwrap();
+        assert_eq!(
+            floats.pipe(strings()),
+            Err("Input declares blobs of a different type than expected")
+        );
+        assert!(floats.get_input().is_none());
+        let mut nested = expects(BlobKind::Float64)
+            .then(identity())
+            .unwrap()
+            .then(identity())
+            .unwrap();
+        assert!(nested.pipe(strings()).is_err());
+        assert!(nested.get_input().is_none());
+        let mut texts = expects(BlobKind::Str).then(identity()).unwrap();
+        texts.pipe(strings()).unwrap();
+        assert!(texts.get_input().is_some());
+    }
+}
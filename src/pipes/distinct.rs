@@ -0,0 +1,93 @@
+//! distinct
+//!
+//! Suppressing consecutive repetitions of the same item
+
+use crate::{Pipe, Source};
+use futures::future;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// DistinctUntilChangedPipe
+/// A pipe dropping items whose key equals the key of the item right before them, such as
+/// sensors repeating the same reading
+///
+/// only consecutive duplicates are dropped, an item coming back after a different one is
+/// passed through again
+pub struct DistinctUntilChangedPipe<T, K = T> {
+    key: Rc<dyn Fn(&T) -> K>,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T: PartialEq + Clone + 'static> DistinctUntilChangedPipe<T> {
+    /// constructor comparing whole items
+    pub fn new() -> Self {
+        Self::by_key(T::clone)
+    }
+}
+
+impl<T: PartialEq + Clone + 'static> Default for DistinctUntilChangedPipe<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T, K: PartialEq> DistinctUntilChangedPipe<T, K> {
+    /// constructor comparing the keys extracted from the items
+    pub fn by_key<F: Fn(&T) -> K + 'static>(key: F) -> Self {
+        Self {
+            key: Rc::new(key),
+            input: None,
+        }
+    }
+}
+
+impl<T: 'static, K: PartialEq + 'static> Source<T> for DistinctUntilChangedPipe<T, K> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let key = self.key.clone();
+        let mut last: Option<K> = None;
+        Box::new(input.filter(move |item| {
+            let current = key(item);
+            let changed = last.as_ref() != Some(&current);
+            last = Some(current);
+            future::ready(changed)
+        }))
+    }
+}
+
+impl<T: 'static, K: PartialEq + 'static> Pipe<T, T> for DistinctUntilChangedPipe<T, K> {
+    input_connection!(T);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_distinct_until_changed() {
+        let mut pipe = DistinctUntilChangedPipe::new();
+        pipe.pipe(Rc::new(MockSource::new().items([1, 1, 2, 2, 2, 1, 3, 3])))
+            .unwrap();
+        let items: Vec<i32> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(items, vec![1, 2, 1, 3]);
+        let again: Vec<i32> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(again, items, "State leaked across streams");
+    }
+
+    #[test]
+    fn test_distinct_by_key() {
+        let readings = [("a", 20), ("b", 20), ("c", 21), ("d", 20)];
+        let mut pipe = DistinctUntilChangedPipe::by_key(|reading: &(&str, i32)| reading.1);
+        pipe.pipe(Rc::new(MockSource::new().items(readings)))
+            .unwrap();
+        let names: Vec<&str> = block_on(Pin::from(pipe.stream()).map(|r| r.0).collect());
+        assert_eq!(names, vec!["a", "c", "d"]);
+    }
+}
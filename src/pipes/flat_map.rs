@@ -0,0 +1,85 @@
+//! flat_map
+//!
+//! Expanding every item of a stream into any number of items
+
+use crate::{Pipe, Source};
+use futures::stream::{self, LocalBoxStream};
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::rc::Rc;
+
+type Expansion<InT, OutT> = Rc<dyn Fn(InT) -> LocalBoxStream<'static, OutT>>;
+
+/// FlatMapPipe
+/// A pipe mapping every item to an iterator or a stream and flattening the results
+pub struct FlatMapPipe<InT, OutT> {
+    function: Expansion<InT, OutT>,
+    input: Option<Rc<dyn Source<InT>>>,
+}
+
+impl<InT, OutT: 'static> FlatMapPipe<InT, OutT> {
+    /// constructor from a function returning an iterable per item
+    pub fn new<F, I>(function: F) -> Self
+    where
+        F: Fn(InT) -> I + 'static,
+        I: IntoIterator<Item = OutT>,
+        I::IntoIter: 'static,
+    {
+        Self::from_stream(move |item| stream::iter(function(item)))
+    }
+    /// constructor from a function returning a stream per item, each stream is drained
+    /// before the next item is expanded
+    pub fn from_stream<F, S>(function: F) -> Self
+    where
+        F: Fn(InT) -> S + 'static,
+        S: Stream<Item = OutT> + 'static,
+    {
+        Self {
+            function: Rc::new(move |item| function(item).boxed_local()),
+            input: None,
+        }
+    }
+}
+
+impl<InT: 'static, OutT: 'static> Source<OutT> for FlatMapPipe<InT, OutT> {
+    fn stream(&self) -> Box<dyn Stream<Item = OutT>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let function = self.function.clone();
+        Box::new(input.flat_map(move |item| function(item)))
+    }
+}
+
+impl<InT: 'static, OutT: 'static> Pipe<InT, OutT> for FlatMapPipe<InT, OutT> {
+    input_connection!(InT);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{collect, MockSource};
+
+    #[test]
+    fn test_flat_map_iterators() {
+        let mut pipe = FlatMapPipe::new(|line: &'static str| line.split(','));
+        pipe.pipe(Rc::new(MockSource::new().items(["a,b", "", "c"])))
+            .unwrap();
+        assert_eq!(collect(&pipe), vec!["a", "b", "", "c"]);
+        let mut repeat = FlatMapPipe::new(|count: usize| vec![count; count]);
+        repeat
+            .pipe(Rc::new(MockSource::new().items([2, 0, 1])))
+            .unwrap();
+        assert_eq!(collect(&repeat), vec![2, 2, 1]);
+    }
+
+    #[test]
+    fn test_flat_map_streams() {
+        let mut pipe = FlatMapPipe::from_stream(|item: i32| {
+            Pin::from(MockSource::new().items([item, -item]).stream())
+        });
+        pipe.pipe(Rc::new(MockSource::new().items([1, 2]))).unwrap();
+        assert_eq!(collect(&pipe), vec![1, -1, 2, -2]);
+    }
+}
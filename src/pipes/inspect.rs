@@ -0,0 +1,61 @@
+//! inspect
+//!
+//! Observing the items of a stream without altering it
+
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// InspectPipe
+/// A pipe passing items through unchanged while calling a function on each of them
+pub struct InspectPipe<T> {
+    callback: Rc<dyn Fn(&T)>,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T> InspectPipe<T> {
+    /// constructor
+    pub fn new<F: Fn(&T) + 'static>(callback: F) -> Self {
+        Self {
+            callback: Rc::new(callback),
+            input: None,
+        }
+    }
+}
+
+impl<T: 'static> Source<T> for InspectPipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let callback = self.callback.clone();
+        Box::new(input.inspect(move |item| callback(item)))
+    }
+}
+
+impl<T: 'static> Pipe<T, T> for InspectPipe<T> {
+    input_connection!(T);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+    use std::cell::RefCell;
+
+    #[test]
+    fn test_inspect_pipe() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let observer = seen.clone();
+        let mut pipe = InspectPipe::new(move |item: &i32| observer.borrow_mut().push(*item));
+        pipe.pipe(Rc::new(MockSource::new().items([3, 1, 2])))
+            .unwrap();
+        let items: Vec<i32> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(items, vec![3, 1, 2], "Failure to pass items through");
+        assert_eq!(*seen.borrow(), vec![3, 1, 2], "Failure to inspect items");
+    }
+}
@@ -0,0 +1,123 @@
+//! ordered_merge
+//!
+//! Merging sorted streams into a single sorted stream
+
+use crate::Source;
+use futures::stream;
+use futures::Stream;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::Poll;
+
+/// OrderedMergePipe
+/// Merges several sources, each sorted by a key (typically a timestamp), into one stream
+/// sorted by that key, items with equal keys come out in the order the inputs were added
+///
+/// an item is only emitted once every unfinished input has an item ready, so one slow
+/// input holds back the whole merge
+pub struct OrderedMergePipe<T, K> {
+    key: Rc<dyn Fn(&T) -> K>,
+    inputs: Vec<Rc<dyn Source<T>>>,
+}
+
+impl<T, K: Ord> OrderedMergePipe<T, K> {
+    /// constructor from the key extractor
+    pub fn new<F: Fn(&T) -> K + 'static>(key: F) -> Self {
+        Self {
+            key: Rc::new(key),
+            inputs: Vec::new(),
+        }
+    }
+    /// add a sorted input to the merge
+    pub fn add_input(&mut self, input: Rc<dyn Source<T>>) {
+        self.inputs.push(input);
+    }
+    /// remove every input
+    pub fn clear_inputs(&mut self) {
+        self.inputs.clear();
+    }
+    /// the merged inputs
+    pub fn inputs(&self) -> &[Rc<dyn Source<T>>] {
+        &self.inputs
+    }
+}
+
+impl<T: 'static, K: Ord + 'static> Source<T> for OrderedMergePipe<T, K> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let mut streams: Vec<Option<Pin<Box<dyn Stream<Item = T>>>>> = self
+            .inputs
+            .iter()
+            .map(|input| Some(Pin::from(input.stream())))
+            .collect();
+        let mut heads: Vec<Option<T>> = streams.iter().map(|_| None).collect();
+        let key = self.key.clone();
+        Box::new(stream::poll_fn(move |cx| {
+            let mut pending = false;
+            for (input, head) in streams.iter_mut().zip(heads.iter_mut()) {
+                if head.is_some() {
+                    continue;
+                }
+                if let Some(stream) = input {
+                    match stream.as_mut().poll_next(cx) {
+                        Poll::Ready(Some(item)) => *head = Some(item),
+                        Poll::Ready(None) => *input = None,
+                        Poll::Pending => pending = true,
+                    }
+                }
+            }
+            if pending {
+                return Poll::Pending;
+            }
+            let next = heads
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, head)| head.as_ref().map(|item| (idx, key(item))))
+                .min_by(|lhs, rhs| lhs.1.cmp(&rhs.1))
+                .map(|(idx, _)| idx);
+            Poll::Ready(next.and_then(|idx| heads[idx].take()))
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::time::Duration;
+
+    #[test]
+    fn test_ordered_merge() {
+        let mut merge = OrderedMergePipe::new(|reading: &(i64, &str)| reading.0);
+        assert!(block_on(Pin::from(merge.stream()).collect::<Vec<_>>()).is_empty());
+        merge.add_input(Rc::new(MockSource::new().items([
+            (1, "a"),
+            (4, "a"),
+            (9, "a"),
+        ])));
+        merge.add_input(Rc::new(
+            MockSource::new()
+                .item((2, "b"))
+                .delay(Duration::from_millis(5))
+                .items([(4, "b"), (5, "b")]),
+        ));
+        merge.add_input(Rc::new(MockSource::new()));
+        merge.add_input(Rc::new(MockSource::new().items([(0, "c"), (10, "c")])));
+        let merged: Vec<(i64, &str)> = block_on(Pin::from(merge.stream()).collect());
+        assert_eq!(
+            merged,
+            vec![
+                (0, "c"),
+                (1, "a"),
+                (2, "b"),
+                (4, "a"),
+                (4, "b"),
+                (5, "b"),
+                (9, "a"),
+                (10, "c")
+            ]
+        );
+        assert_eq!(merge.inputs().len(), 4);
+    }
+}
@@ -0,0 +1,161 @@
+//! ordered_par_map
+//!
+//! Mapping items concurrently with a bounded number in flight while keeping their order
+
+use crate::{Pipe, Source};
+use futures::channel::oneshot;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use rayon::ThreadPool;
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// OrderedParMapPipe
+/// A pipe keeping up to a number of items mapped concurrently on a rayon pool, results
+/// are emitted in input order as soon as all results before them are ready
+///
+/// unlike `ParMapPipe` items are submitted one by one as they arrive rather than in
+/// batches of readily available items, which suits slow inputs and uneven mapping costs,
+/// a panic of the function is raised again on the consuming thread
+pub struct OrderedParMapPipe<InT, OutT> {
+    function: Arc<dyn Fn(InT) -> OutT + Send + Sync>,
+    parallelism: usize,
+    pool: Option<Arc<ThreadPool>>,
+    input: Option<Rc<dyn Source<InT>>>,
+}
+
+impl<InT, OutT> OrderedParMapPipe<InT, OutT> {
+    /// constructor for a pipe mapping up to `parallelism` items at once on the global
+    /// rayon pool
+    pub fn new<F>(function: F, parallelism: usize) -> Result<Self, &'static str>
+    where
+        F: Fn(InT) -> OutT + Send + Sync + 'static,
+    {
+        if parallelism == 0 {
+            return Err("Parallelism must be strictly positive");
+        }
+        Ok(Self {
+            function: Arc::new(function),
+            parallelism,
+            pool: None,
+            input: None,
+        })
+    }
+    /// run the function on a dedicated rayon pool instead of the global one
+    pub fn with_thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+    /// maximum number of items mapped at once
+    pub fn parallelism(&self) -> usize {
+        self.parallelism
+    }
+}
+
+impl<InT, OutT> Source<OutT> for OrderedParMapPipe<InT, OutT>
+where
+    InT: Send + 'static,
+    OutT: Send + 'static,
+{
+    fn stream(&self) -> Box<dyn Stream<Item = OutT>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let function = self.function.clone();
+        let pool = self.pool.clone();
+        Box::new(
+            input
+                .map(move |item| {
+                    let (sender, receiver) = oneshot::channel();
+                    let function = function.clone();
+                    let job = move || {
+                        let _ = sender.send(catch_unwind(AssertUnwindSafe(|| function(item))));
+                    };
+                    match &pool {
+                        Some(pool) => pool.spawn(job),
+                        None => rayon::spawn(job),
+                    }
+                    receiver
+                })
+                .buffered(self.parallelism)
+                .map(|result| match result {
+                    Ok(Ok(output)) => output,
+                    Ok(Err(panic)) => resume_unwind(panic),
+                    Err(_) => panic!("Mapping job was dropped"),
+                }),
+        )
+    }
+}
+
+impl<InT, OutT> Pipe<InT, OutT> for OrderedParMapPipe<InT, OutT>
+where
+    InT: Send + 'static,
+    OutT: Send + 'static,
+{
+    input_connection!(InT);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::time::Duration;
+
+    #[test]
+    fn test_ordered_par_map_keeps_order() {
+        let active = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let (counter, highest) = (active.clone(), peak.clone());
+        let pool = Arc::new(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(8)
+                .build()
+                .unwrap(),
+        );
+        let mut pipe = OrderedParMapPipe::new(
+            move |x: u64| {
+                let now = counter.fetch_add(1, Ordering::SeqCst) + 1;
+                highest.fetch_max(now, Ordering::SeqCst);
+                // later items finish first
+                std::thread::sleep(Duration::from_millis(10 - x % 10));
+                counter.fetch_sub(1, Ordering::SeqCst);
+                x * 3
+            },
+            3,
+        )
+        .unwrap()
+        .with_thread_pool(pool);
+        pipe.pipe(Rc::new(MockSource::new().items(0..30))).unwrap();
+        let out: Vec<u64> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(out, (0..30).map(|x| x * 3).collect::<Vec<_>>());
+        assert!(
+            peak.load(Ordering::SeqCst) > 1,
+            "Items were not mapped concurrently"
+        );
+        assert!(peak.load(Ordering::SeqCst) <= 3, "Parallelism exceeded");
+        assert!(OrderedParMapPipe::new(|x: u8| x, 0).is_err());
+    }
+
+    #[test]
+    fn test_ordered_par_map_propagates_panics() {
+        let mut pipe = OrderedParMapPipe::new(
+            |x: i32| {
+                assert!(x != 2, "bad item");
+                x
+            },
+            2,
+        )
+        .unwrap();
+        pipe.pipe(Rc::new(MockSource::new().items([1, 2, 3])))
+            .unwrap();
+        let result = catch_unwind(AssertUnwindSafe(|| {
+            block_on(Pin::from(pipe.stream()).collect::<Vec<i32>>())
+        }));
+        assert!(result.is_err(), "Panic was swallowed");
+    }
+}
@@ -0,0 +1,141 @@
+//! par_map
+//!
+//! Mapping batches of items in parallel on a rayon thread pool
+
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use rayon::prelude::*;
+use rayon::ThreadPool;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// default number of items mapped in parallel at once
+const DEFAULT_BATCH_SIZE: usize = 1024;
+
+/// ParMapPipe
+/// A pipe applying a function to every item, spreading batches of readily available
+/// items over the threads of a rayon pool while preserving the order of the stream
+pub struct ParMapPipe<InT, OutT> {
+    function: Arc<dyn Fn(InT) -> OutT + Send + Sync>,
+    batch_size: usize,
+    pool: Option<Arc<ThreadPool>>,
+    input: Option<Rc<dyn Source<InT>>>,
+}
+
+impl<InT, OutT> ParMapPipe<InT, OutT> {
+    /// constructor, batches run on the global rayon pool
+    pub fn new<F>(function: F) -> Self
+    where
+        F: Fn(InT) -> OutT + Send + Sync + 'static,
+    {
+        Self {
+            function: Arc::new(function),
+            batch_size: DEFAULT_BATCH_SIZE,
+            pool: None,
+            input: None,
+        }
+    }
+    /// run the batches on a dedicated rayon pool instead of the global one
+    pub fn with_thread_pool(mut self, pool: Arc<ThreadPool>) -> Self {
+        self.pool = Some(pool);
+        self
+    }
+    /// set the maximum number of items mapped at once
+    pub fn set_batch_size(&mut self, batch_size: usize) -> Result<(), &'static str> {
+        if batch_size == 0 {
+            return Err("Batch size must be strictly positive");
+        }
+        self.batch_size = batch_size;
+        Ok(())
+    }
+}
+
+impl<InT, OutT> Source<OutT> for ParMapPipe<InT, OutT>
+where
+    InT: Send + 'static,
+    OutT: Send + 'static,
+{
+    fn stream(&self) -> Box<dyn Stream<Item = OutT>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let function = self.function.clone();
+        let pool = self.pool.clone();
+        Box::new(
+            input
+                .ready_chunks(self.batch_size)
+                .map(move |batch| {
+                    let map = || -> Vec<OutT> {
+                        batch.into_par_iter().map(|item| function(item)).collect()
+                    };
+                    match &pool {
+                        Some(pool) => pool.install(map),
+                        None => map(),
+                    }
+                })
+                .flat_map(stream::iter),
+        )
+    }
+}
+
+impl<InT, OutT> Pipe<InT, OutT> for ParMapPipe<InT, OutT>
+where
+    InT: Send + 'static,
+    OutT: Send + 'static,
+{
+    input_connection!(InT);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::{DataBlob, MetaData};
+    use futures::executor::block_on;
+    use std::collections::HashSet;
+    use std::sync::Mutex;
+
+    #[test]
+    fn test_par_map() {
+        let mut pipe = ParMapPipe::new(|x: i64| x * x);
+        pipe.set_batch_size(64).unwrap();
+        pipe.pipe(Rc::new(DataBlob::new(
+            (0..1000).collect(),
+            MetaData::default(),
+        )))
+        .unwrap();
+        let out: Vec<i64> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(out, (0..1000).map(|x| x * x).collect::<Vec<i64>>());
+    }
+
+    #[test]
+    fn test_par_map_threads() {
+        let pool = Arc::new(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(4)
+                .build()
+                .unwrap(),
+        );
+        let threads = Arc::new(Mutex::new(HashSet::new()));
+        let seen = threads.clone();
+        let mut pipe = ParMapPipe::new(move |x: u32| {
+            seen.lock().unwrap().insert(std::thread::current().id());
+            std::thread::sleep(std::time::Duration::from_millis(1));
+            x + 1
+        })
+        .with_thread_pool(pool);
+        pipe.pipe(Rc::new(DataBlob::new(
+            (0..64).collect(),
+            MetaData::default(),
+        )))
+        .unwrap();
+        let out: Vec<u32> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(out, (1..65).collect::<Vec<u32>>(), "Order not preserved");
+        assert!(
+            threads.lock().unwrap().len() > 1,
+            "Work ran on a single thread"
+        );
+    }
+}
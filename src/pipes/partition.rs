@@ -0,0 +1,142 @@
+//! partition
+//!
+//! Routing the items of a stream to several outputs by the hash of a key
+
+use crate::pipes::split::{SplitOutput, Splitter};
+use crate::Source;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::marker::PhantomData;
+use std::rc::Rc;
+
+/// PartitionPipe
+/// Routes every item to one of a number of output sources chosen by hashing a key
+/// extracted from the item, items with equal keys always reach the same output
+///
+/// the input is streamed once and shared by the outputs, which can be consumed
+/// independently, items waiting for an output are queued until it is polled
+pub struct PartitionPipe<T, K> {
+    splitter: Rc<Splitter<T>>,
+    key: PhantomData<K>,
+}
+
+impl<T: 'static, K: Hash> PartitionPipe<T, K> {
+    /// constructor for `partitions` outputs keyed by a function
+    pub fn new<F>(partitions: usize, key: F) -> Result<Self, &'static str>
+    where
+        F: Fn(&T) -> K + 'static,
+    {
+        if partitions == 0 {
+            return Err("Number of partitions must be strictly positive");
+        }
+        let route = move |item: &T| {
+            let mut hasher = DefaultHasher::new();
+            key(item).hash(&mut hasher);
+            (hasher.finish() % partitions as u64) as usize
+        };
+        Ok(Self {
+            splitter: Rc::new(Splitter::new(partitions, Box::new(route))),
+            key: PhantomData,
+        })
+    }
+}
+
+impl<T: 'static, K> PartitionPipe<T, K> {
+    /// number of outputs
+    pub fn partitions(&self) -> usize {
+        self.splitter.outputs()
+    }
+    /// the source yielding the items of a partition
+    pub fn output(&self, partition: usize) -> Option<Rc<dyn Source<T>>> {
+        if partition >= self.partitions() {
+            return None;
+        }
+        Some(Rc::new(SplitOutput {
+            splitter: self.splitter.clone(),
+            index: partition,
+        }))
+    }
+    /// the sources of every partition in order
+    pub fn outputs(&self) -> Vec<Rc<dyn Source<T>>> {
+        (0..self.partitions())
+            .filter_map(|partition| self.output(partition))
+            .collect()
+    }
+    /// connect a source to the partitions, restarting any ongoing split
+    pub fn pipe(&mut self, input: Rc<dyn Source<T>>) -> Result<(), &'static str> {
+        self.splitter.set_input(Some(input));
+        Ok(())
+    }
+    /// sever input connection, outputs end once their queued items are consumed
+    pub fn unpipe(&mut self) {
+        self.splitter.set_input(None);
+    }
+    /// return a reference to the input source
+    pub fn get_input(&self) -> Option<Rc<dyn Source<T>>> {
+        self.splitter.get_input()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+    use futures::{join, StreamExt};
+    use std::pin::Pin;
+
+    #[test]
+    fn test_partition_by_key() {
+        let mut partition = PartitionPipe::new(3, |item: &(u32, u32)| item.0).unwrap();
+        let items: Vec<(u32, u32)> = (0..60).map(|idx| (idx % 7, idx)).collect();
+        partition
+            .pipe(Rc::new(MockSource::new().items(items.clone())))
+            .unwrap();
+        let outputs = partition.outputs();
+        assert!(partition.output(3).is_none());
+        let streams: Vec<_> = outputs
+            .iter()
+            .map(|output| Pin::from(output.stream()).collect::<Vec<_>>())
+            .collect();
+        let results = block_on(futures::future::join_all(streams));
+        assert_eq!(results.iter().map(Vec::len).sum::<usize>(), 60);
+        for (idx, result) in results.iter().enumerate() {
+            for item in result {
+                let owners: Vec<usize> = results
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, other)| other.iter().any(|other| other.0 == item.0))
+                    .map(|(owner, _)| owner)
+                    .collect();
+                assert_eq!(owners, vec![idx], "Key split across partitions");
+            }
+            let ordered: Vec<u32> = result.iter().map(|item| item.1).collect();
+            assert!(
+                ordered.windows(2).all(|pair| pair[0] < pair[1]),
+                "Order lost"
+            );
+        }
+    }
+
+    #[test]
+    fn test_partition_outputs_consumed_one_after_the_other() {
+        let mut partition = PartitionPipe::new(2, |item: &i32| *item % 2 == 0).unwrap();
+        partition
+            .pipe(Rc::new(MockSource::new().items(0..10)))
+            .unwrap();
+        let (first, second) = (partition.output(0).unwrap(), partition.output(1).unwrap());
+        let drained: Vec<i32> = block_on(Pin::from(first.stream()).collect());
+        let rest: Vec<i32> = block_on(Pin::from(second.stream()).collect());
+        assert_eq!(drained.len() + rest.len(), 10);
+        assert!(drained.iter().all(|item| item % 2 == drained[0] % 2));
+        partition.unpipe();
+        assert!(partition.get_input().is_none());
+        let (left, right) = block_on(async {
+            join!(
+                Pin::from(first.stream()).count(),
+                Pin::from(second.stream()).count()
+            )
+        });
+        assert_eq!(left + right, 0, "Unpiped partitions stream items");
+    }
+}
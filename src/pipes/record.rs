@@ -0,0 +1,302 @@
+//! record
+//!
+//! Capturing streams to files and replaying them with their original timing
+//!
+//! a recording starts with a magic header followed by one record per item: the time
+//! elapsed since the first item in nanoseconds and the byte length of the item (both u64
+//! little endian) then the item encoded with `BinaryElement`. Every stream of a recording
+//! pipe rewrites the recording, so the file holds the last stream
+
+use crate::data_bucket::encoding::BinaryElement;
+use crate::runtime::ThreadTimer;
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::{Cell, RefCell};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::time::{Duration, Instant};
+
+/// bytes opening every recording
+const MAGIC: &[u8; 8] = b"BVREC\0\0\x01";
+
+/// delays shorter than this are not slept through
+const MIN_SLEEP: Duration = Duration::from_micros(500);
+
+/// RecordPipe
+/// A pipe passing items through unchanged while writing each of them to a recording
+pub struct RecordPipe<T> {
+    path: PathBuf,
+    error: Rc<Cell<Option<&'static str>>>,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+/// a new recording holding only its header
+fn create_recording(path: &Path) -> Result<BufWriter<File>, &'static str> {
+    let file = File::create(path).map_err(|_| "Failure to create recording file")?;
+    let mut writer = BufWriter::new(file);
+    writer
+        .write_all(MAGIC)
+        .map_err(|_| "Failure to write recording header")?;
+    Ok(writer)
+}
+
+impl<T> RecordPipe<T> {
+    /// constructor creating (or truncating) the recording file, holding no items until the
+    /// pipe is streamed
+    pub fn create<P: AsRef<Path>>(path: P) -> Result<Self, &'static str> {
+        create_recording(path.as_ref())?
+            .flush()
+            .map_err(|_| "Failure to write recording header")?;
+        Ok(Self {
+            path: path.as_ref().to_path_buf(),
+            error: Rc::new(Cell::new(None)),
+            input: None,
+        })
+    }
+    /// the first error met while writing the recording of the last stream, items keep
+    /// flowing regardless
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl<T: BinaryElement + 'static> Source<T> for RecordPipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        self.error.set(None);
+        let writer = match create_recording(&self.path) {
+            Ok(writer) => Rc::new(RefCell::new(Some(writer))),
+            Err(failure) => {
+                self.error.set(Some(failure));
+                Rc::new(RefCell::new(None))
+            }
+        };
+        let flushed = writer.clone();
+        let error = self.error.clone();
+        let start: Cell<Option<Instant>> = Cell::new(None);
+        let mut record = Vec::new();
+        let recorded = input.inspect(move |item| {
+            let first = start.get().unwrap_or_else(Instant::now);
+            start.set(Some(first));
+            let elapsed = first.elapsed();
+            record.clear();
+            T::write_bytes(std::slice::from_ref(item), &mut record);
+            let mut writer = writer.borrow_mut();
+            let Some(writer) = writer.as_mut() else {
+                return;
+            };
+            let written = writer
+                .write_all(&(elapsed.as_nanos() as u64).to_le_bytes())
+                .and_then(|_| writer.write_all(&(record.len() as u64).to_le_bytes()))
+                .and_then(|_| writer.write_all(&record));
+            if written.is_err() && error.get().is_none() {
+                error.set(Some("Failure to write record"));
+            }
+        });
+        let error = self.error.clone();
+        let flush = stream::once(async move {
+            let flush = flushed.borrow_mut().as_mut().map(|writer| writer.flush());
+            if matches!(flush, Some(Err(_))) && error.get().is_none() {
+                error.set(Some("Failure to flush recording"));
+            }
+        })
+        .filter_map(|_| async { None });
+        Box::new(recorded.chain(flush))
+    }
+}
+
+impl<T: BinaryElement + 'static> Pipe<T, T> for RecordPipe<T> {
+    input_connection!(T);
+}
+
+/// ReplayTiming
+/// How the delays between recorded items are reproduced
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ReplayTiming {
+    /// wait as long as was recorded between items
+    Original,
+    /// divide recorded delays by a speed up factor
+    Scaled(f64),
+    /// yield every item without waiting
+    Immediate,
+}
+
+/// ReplaySource
+/// A source yielding the items of a recording
+pub struct ReplaySource<T> {
+    records: Rc<Vec<(Duration, T)>>,
+    timing: ReplayTiming,
+}
+
+impl<T: BinaryElement> ReplaySource<T> {
+    /// constructor reading a recording written by a `RecordPipe`
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, &'static str> {
+        let bytes = std::fs::read(path).map_err(|_| "Failure to read recording file")?;
+        Self::from_bytes(&bytes)
+    }
+    /// constructor decoding a recording held in memory
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, &'static str> {
+        let mut rest = bytes
+            .strip_prefix(MAGIC.as_slice())
+            .ok_or("Not a recording")?;
+        let mut records = Vec::new();
+        while !rest.is_empty() {
+            if rest.len() < 16 {
+                return Err("Truncated record header");
+            }
+            let elapsed = u64::from_le_bytes(rest[0..8].try_into().unwrap());
+            let length = u64::from_le_bytes(rest[8..16].try_into().unwrap()) as usize;
+            let payload = rest[16..].get(..length).ok_or("Truncated record")?;
+            let mut items = T::read_bytes(payload)?;
+            if items.len() != 1 {
+                return Err("Record does not hold exactly one item");
+            }
+            records.push((Duration::from_nanos(elapsed), items.pop().unwrap()));
+            rest = &rest[16 + length..];
+        }
+        Ok(Self {
+            records: Rc::new(records),
+            timing: ReplayTiming::Original,
+        })
+    }
+    /// set how the recorded delays are reproduced
+    pub fn set_timing(&mut self, timing: ReplayTiming) -> Result<(), &'static str> {
+        if let ReplayTiming::Scaled(speed) = timing {
+            if !(speed.is_finite() && speed > 0.0) {
+                return Err("Replay speed must be finite and strictly positive");
+            }
+        }
+        self.timing = timing;
+        Ok(())
+    }
+    /// number of recorded items
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+    /// whether the recording holds no items
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+}
+
+impl<T: Clone + 'static> Source<T> for ReplaySource<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let speed = match self.timing {
+            ReplayTiming::Original => 1.0,
+            ReplayTiming::Scaled(speed) => speed,
+            ReplayTiming::Immediate => {
+                let records = self.records.clone();
+                return Box::new(stream::iter(
+                    (0..records.len()).map(move |idx| records[idx].1.clone()),
+                ));
+            }
+        };
+        let records = self.records.clone();
+        let start: Rc<Cell<Option<Instant>>> = Rc::new(Cell::new(None));
+        // one timer thread for the whole stream, started with the first wait
+        let timer: Rc<RefCell<Option<ThreadTimer>>> = Rc::new(RefCell::new(None));
+        Box::new(stream::iter(0..records.len()).then(move |idx| {
+            let (offset, item) = records[idx].clone();
+            let (start, timer) = (start.clone(), timer.clone());
+            async move {
+                let first = start.get().unwrap_or_else(Instant::now);
+                start.set(Some(first));
+                let deadline = first + offset.div_f64(speed);
+                let remaining = deadline.saturating_duration_since(Instant::now());
+                if remaining > MIN_SLEEP {
+                    let sleep = timer
+                        .borrow_mut()
+                        .get_or_insert_with(ThreadTimer::new)
+                        .sleep_until(deadline);
+                    sleep.await;
+                }
+                item
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn recording_path(name: &str) -> std::path::PathBuf {
+        std::env::temp_dir().join(format!("bitvortex-{}-{}.rec", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_record_and_replay() {
+        let path = recording_path("replay");
+        let mut record = RecordPipe::create(&path).unwrap();
+        record
+            .pipe(Rc::new(stream_source(vec![1_i32, -2, 3])))
+            .unwrap();
+        let passed: Vec<i32> = block_on(Pin::from(record.stream()).collect());
+        assert_eq!(passed, vec![1, -2, 3], "Failure to pass items through");
+        assert_eq!(record.error(), None);
+        let mut replay = ReplaySource::<i32>::open(&path).unwrap();
+        replay.set_timing(ReplayTiming::Immediate).unwrap();
+        assert_eq!(replay.len(), 3);
+        let replayed: Vec<i32> = block_on(Pin::from(replay.stream()).collect());
+        assert_eq!(replayed, passed, "Failure to replay recording");
+        block_on(Pin::from(record.stream()).count());
+        let replay = ReplaySource::<i32>::open(&path).unwrap();
+        assert_eq!(replay.len(), 3, "Second stream appended to the recording");
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_replay_timing() {
+        let mut bytes = MAGIC.to_vec();
+        for (millis, text) in [(0_u64, "a"), (40, "b")] {
+            let mut payload = Vec::new();
+            String::write_bytes(&[text.to_string()], &mut payload);
+            bytes.extend_from_slice(&(millis * 1_000_000).to_le_bytes());
+            bytes.extend_from_slice(&(payload.len() as u64).to_le_bytes());
+            bytes.extend_from_slice(&payload);
+        }
+        let mut replay = ReplaySource::<String>::from_bytes(&bytes).unwrap();
+        let start = Instant::now();
+        let items: Vec<String> = block_on(Pin::from(replay.stream()).collect());
+        assert_eq!(items, vec!["a", "b"]);
+        assert!(
+            start.elapsed() >= Duration::from_millis(40),
+            "Replay too fast"
+        );
+        replay.set_timing(ReplayTiming::Scaled(4.0)).unwrap();
+        let start = Instant::now();
+        block_on(Pin::from(replay.stream()).count());
+        let elapsed = start.elapsed();
+        assert!(
+            elapsed >= Duration::from_millis(10),
+            "Scaled replay too fast"
+        );
+        assert!(
+            elapsed < Duration::from_millis(40),
+            "Scaled replay too slow"
+        );
+        assert!(replay.set_timing(ReplayTiming::Scaled(0.0)).is_err());
+    }
+
+    #[test]
+    fn test_replay_rejects_corrupt_recordings() {
+        assert!(ReplaySource::<i32>::from_bytes(b"garbage").is_err());
+        let mut bytes = MAGIC.to_vec();
+        bytes.extend_from_slice(&0_u64.to_le_bytes());
+        bytes.extend_from_slice(&8_u64.to_le_bytes());
+        bytes.extend_from_slice(&[0; 4]);
+        assert!(ReplaySource::<i32>::from_bytes(&bytes).is_err());
+    }
+
+    fn stream_source(items: Vec<i32>) -> impl Source<i32> {
+        crate::data_bucket::DataBlob::new(items, Default::default())
+    }
+}
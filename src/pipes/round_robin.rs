@@ -0,0 +1,100 @@
+//! round_robin
+//!
+//! Dealing the items of a stream evenly over several branches
+
+use crate::pipes::split::{SplitOutput, Splitter};
+use crate::Source;
+use std::rc::Rc;
+
+/// RoundRobinSplit
+/// Sends every item to the next of a number of output branches in turn, so branch `i`
+/// receives items `i`, `i + k`, `i + 2k`...
+///
+/// the input is streamed once and shared by the branches, which can be consumed
+/// independently, items waiting for a branch are queued until it is polled
+pub struct RoundRobinSplit<T> {
+    splitter: Rc<Splitter<T>>,
+}
+
+impl<T: 'static> RoundRobinSplit<T> {
+    /// constructor for `branches` outputs
+    pub fn new(branches: usize) -> Result<Self, &'static str> {
+        if branches == 0 {
+            return Err("Number of branches must be strictly positive");
+        }
+        let mut next = 0;
+        let route = move |_: &T| {
+            let branch = next;
+            next = (next + 1) % branches;
+            branch
+        };
+        Ok(Self {
+            splitter: Rc::new(Splitter::new(branches, Box::new(route))),
+        })
+    }
+    /// number of branches
+    pub fn branches(&self) -> usize {
+        self.splitter.outputs()
+    }
+    /// the source yielding the items of a branch
+    pub fn output(&self, branch: usize) -> Option<Rc<dyn Source<T>>> {
+        if branch >= self.branches() {
+            return None;
+        }
+        Some(Rc::new(SplitOutput {
+            splitter: self.splitter.clone(),
+            index: branch,
+        }))
+    }
+    /// the sources of every branch in order
+    pub fn outputs(&self) -> Vec<Rc<dyn Source<T>>> {
+        (0..self.branches())
+            .filter_map(|branch| self.output(branch))
+            .collect()
+    }
+    /// connect a source to the branches, restarting any ongoing split
+    pub fn pipe(&mut self, input: Rc<dyn Source<T>>) -> Result<(), &'static str> {
+        self.splitter.set_input(Some(input));
+        Ok(())
+    }
+    /// sever input connection, branches end once their queued items are consumed
+    pub fn unpipe(&mut self) {
+        self.splitter.set_input(None);
+    }
+    /// return a reference to the input source
+    pub fn get_input(&self) -> Option<Rc<dyn Source<T>>> {
+        self.splitter.get_input()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::ElementwisePipe;
+    use crate::testing::MockSource;
+    use crate::Pipe;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+    use std::pin::Pin;
+
+    #[test]
+    fn test_round_robin_split() {
+        let mut split = RoundRobinSplit::new(3).unwrap();
+        split.pipe(Rc::new(MockSource::new().items(0..10))).unwrap();
+        assert!(split.output(3).is_none());
+        let branches: Vec<_> = split
+            .outputs()
+            .into_iter()
+            .map(|output| {
+                let mut pipe = ElementwisePipe::scale(10);
+                pipe.pipe(output).unwrap();
+                Pin::from(pipe.stream()).collect::<Vec<i32>>()
+            })
+            .collect();
+        let results = block_on(futures::future::join_all(branches));
+        assert_eq!(results[0], vec![0, 30, 60, 90]);
+        assert_eq!(results[1], vec![10, 40, 70]);
+        assert_eq!(results[2], vec![20, 50, 80]);
+        assert!(RoundRobinSplit::<i32>::new(0).is_err());
+    }
+}
@@ -0,0 +1,92 @@
+//! scan
+//!
+//! Threading a running state through a stream
+
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::rc::Rc;
+
+type ScanFunction<T, S, OutT> = Rc<dyn Fn(&mut S, T) -> OutT>;
+
+/// ScanPipe
+/// A pipe updating a state with every item and emitting one output per item
+///
+/// every stream starts over from a copy of the initial state
+pub struct ScanPipe<T, S, OutT = S> {
+    initial: S,
+    function: ScanFunction<T, S, OutT>,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T, S, OutT> ScanPipe<T, S, OutT> {
+    /// constructor from the initial state and the update function
+    pub fn new<F>(initial: S, function: F) -> Self
+    where
+        F: Fn(&mut S, T) -> OutT + 'static,
+    {
+        Self {
+            initial,
+            function: Rc::new(function),
+            input: None,
+        }
+    }
+}
+
+impl<T: Clone + std::ops::Add<Output = T> + 'static> ScanPipe<T, T> {
+    /// a pipe emitting the cumulative sum of the stream starting from a value
+    pub fn cumulative_sum(initial: T) -> Self {
+        Self::new(initial, |sum: &mut T, item: T| {
+            *sum = sum.clone() + item;
+            sum.clone()
+        })
+    }
+}
+
+impl<T: 'static, S: Clone + 'static, OutT: 'static> Source<OutT> for ScanPipe<T, S, OutT> {
+    fn stream(&self) -> Box<dyn Stream<Item = OutT>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let function = self.function.clone();
+        let mut state = self.initial.clone();
+        Box::new(input.map(move |item| function(&mut state, item)))
+    }
+}
+
+impl<T: 'static, S: Clone + 'static, OutT: 'static> Pipe<T, OutT> for ScanPipe<T, S, OutT> {
+    input_connection!(T);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{collect, MockSource};
+
+    #[test]
+    fn test_cumulative_sum() {
+        let mut pipe = ScanPipe::cumulative_sum(10);
+        pipe.pipe(Rc::new(MockSource::new().items([1, 2, 3])))
+            .unwrap();
+        assert_eq!(collect(&pipe), vec![11, 13, 16]);
+        assert_eq!(
+            collect(&pipe),
+            vec![11, 13, 16],
+            "State leaked between streams"
+        );
+    }
+
+    #[test]
+    fn test_running_delta() {
+        let mut pipe = ScanPipe::new(None, |previous: &mut Option<f64>, item: f64| {
+            let delta = previous.map(|previous| item - previous);
+            *previous = Some(item);
+            delta
+        });
+        pipe.pipe(Rc::new(MockSource::new().items([1.0, 4.0, 2.5])))
+            .unwrap();
+        assert_eq!(collect(&pipe), vec![None, Some(3.0), Some(-1.5)]);
+    }
+}
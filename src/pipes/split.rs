@@ -0,0 +1,151 @@
+//! split
+//!
+//! Shared machinery fanning one input stream out to several output sources
+//!
+//! the input is streamed once for all outputs, whichever output is polled pulls the input
+//! and queues items routed to the other outputs, so items bound for an output that is
+//! never consumed are held until the splitter is dropped
+
+use crate::Source;
+use futures::stream;
+use futures::task::{waker_ref, ArcWake};
+use futures::Stream;
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll, Waker};
+
+/// the function picking an output index for every item
+pub(crate) type Route<T> = Box<dyn FnMut(&T) -> usize>;
+
+/// the wakers of every output waiting on the input
+#[derive(Default)]
+struct WakerSet {
+    wakers: Mutex<Vec<Waker>>,
+}
+
+impl WakerSet {
+    fn register(&self, waker: &Waker) {
+        let mut wakers = self.wakers.lock().unwrap();
+        if !wakers.iter().any(|other| other.will_wake(waker)) {
+            wakers.push(waker.clone());
+        }
+    }
+    fn wake_all(&self) {
+        let wakers = std::mem::take(&mut *self.wakers.lock().unwrap());
+        wakers.into_iter().for_each(Waker::wake);
+    }
+}
+
+impl ArcWake for WakerSet {
+    fn wake_by_ref(arc_self: &Arc<Self>) {
+        arc_self.wake_all();
+    }
+}
+
+struct State<T> {
+    upstream: Option<Pin<Box<dyn Stream<Item = T>>>>,
+    finished: bool,
+    queues: Vec<VecDeque<T>>,
+}
+
+impl<T> State<T> {
+    fn new(outputs: usize) -> Self {
+        Self {
+            upstream: None,
+            finished: false,
+            queues: (0..outputs).map(|_| VecDeque::new()).collect(),
+        }
+    }
+}
+
+/// Splitter
+/// Routes the items of an input stream into per output queues
+pub(crate) struct Splitter<T> {
+    input: RefCell<Option<Rc<dyn Source<T>>>>,
+    route: RefCell<Route<T>>,
+    state: RefCell<State<T>>,
+    wakers: Arc<WakerSet>,
+}
+
+impl<T: 'static> Splitter<T> {
+    pub(crate) fn new(outputs: usize, route: Route<T>) -> Self {
+        Self {
+            input: RefCell::new(None),
+            route: RefCell::new(route),
+            state: RefCell::new(State::new(outputs)),
+            wakers: Arc::new(WakerSet::default()),
+        }
+    }
+    pub(crate) fn outputs(&self) -> usize {
+        self.state.borrow().queues.len()
+    }
+    /// replace the input, restarting the split and dropping queued items
+    pub(crate) fn set_input(&self, input: Option<Rc<dyn Source<T>>>) {
+        *self.input.borrow_mut() = input;
+        let outputs = self.outputs();
+        *self.state.borrow_mut() = State::new(outputs);
+        self.wakers.wake_all();
+    }
+    pub(crate) fn get_input(&self) -> Option<Rc<dyn Source<T>>> {
+        self.input.borrow().clone()
+    }
+    fn poll_output(&self, output: usize, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        let mut state = self.state.borrow_mut();
+        loop {
+            if let Some(item) = state.queues[output].pop_front() {
+                return Poll::Ready(Some(item));
+            }
+            if state.finished {
+                return Poll::Ready(None);
+            }
+            if state.upstream.is_none() {
+                match self.input.borrow().as_ref() {
+                    Some(input) => state.upstream = Some(Pin::from(input.stream())),
+                    None => return Poll::Ready(None),
+                }
+            }
+            self.wakers.register(cx.waker());
+            let waker = waker_ref(&self.wakers);
+            let mut upstream_cx = Context::from_waker(&waker);
+            let polled = state
+                .upstream
+                .as_mut()
+                .unwrap()
+                .as_mut()
+                .poll_next(&mut upstream_cx);
+            match polled {
+                Poll::Ready(Some(item)) => {
+                    let target = (self.route.borrow_mut())(&item) % state.queues.len();
+                    state.queues[target].push_back(item);
+                    if target != output {
+                        self.wakers.wake_all();
+                    }
+                }
+                Poll::Ready(None) => {
+                    state.finished = true;
+                    state.upstream = None;
+                    self.wakers.wake_all();
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+/// SplitOutput
+/// One of the output sources of a splitter
+pub(crate) struct SplitOutput<T> {
+    pub(crate) splitter: Rc<Splitter<T>>,
+    pub(crate) index: usize,
+}
+
+impl<T: 'static> Source<T> for SplitOutput<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let splitter = self.splitter.clone();
+        let index = self.index;
+        Box::new(stream::poll_fn(move |cx| splitter.poll_output(index, cx)))
+    }
+}
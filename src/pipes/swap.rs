@@ -0,0 +1,320 @@
+//! swap
+//!
+//! Replacing the pipe of a live stream without restarting its input
+//!
+//! a swap requested while streaming cuts the input of the running pipe at the next item it
+//! pulls, lets the pipe drain (flushing whatever it buffered or read ahead), moves the state
+//! of stateful pipes over and resumes the same input stream through the new pipe, no item
+//! is lost or read twice, swaps are requested from the thread driving the stream
+
+use crate::state::{SharedStateStore, Stateful};
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::Stream;
+use std::any::Any;
+use std::cell::{Cell, RefCell};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Poll, Waker};
+
+type Transfer = Box<dyn FnOnce(&dyn Any) -> Result<(), &'static str>>;
+
+/// a pipe waiting to replace the running one
+struct Replacement<InT, OutT> {
+    pipe: Box<dyn Pipe<InT, OutT>>,
+    state: Option<Box<dyn Any>>,
+    transfer: Option<Transfer>,
+}
+
+struct Control<InT, OutT> {
+    current: RefCell<Box<dyn Pipe<InT, OutT>>>,
+    state: RefCell<Option<Box<dyn Any>>>,
+    pending: RefCell<Option<Replacement<InT, OutT>>>,
+    input: RefCell<Option<Rc<dyn Source<InT>>>>,
+    upstream: RefCell<Option<Pin<Box<dyn Stream<Item = InT>>>>>,
+    streaming: Cell<bool>,
+    waker: RefCell<Option<Waker>>,
+    swaps: Cell<usize>,
+    error: Cell<Option<&'static str>>,
+}
+
+impl<InT: 'static, OutT: 'static> Control<InT, OutT> {
+    /// put the replacement in place, connecting it to the feed
+    fn install(self: &Rc<Self>, replacement: Replacement<InT, OutT>) {
+        let Replacement {
+            mut pipe,
+            state,
+            transfer,
+        } = replacement;
+        if let (Some(transfer), Some(old)) = (transfer, self.state.borrow().as_ref()) {
+            if let Err(failure) = transfer(old.as_ref()) {
+                self.error.set(Some(failure));
+            }
+        }
+        if let Err(failure) = pipe.pipe(Rc::new(Feed {
+            control: self.clone(),
+        })) {
+            self.error.set(Some(failure));
+        }
+        *self.current.borrow_mut() = pipe;
+        *self.state.borrow_mut() = state;
+        self.swaps.set(self.swaps.get() + 1);
+    }
+    fn request(self: &Rc<Self>, replacement: Replacement<InT, OutT>) {
+        if !self.streaming.get() {
+            self.install(replacement);
+            return;
+        }
+        *self.pending.borrow_mut() = Some(replacement);
+        if let Some(waker) = self.waker.borrow_mut().take() {
+            waker.wake();
+        }
+    }
+}
+
+/// the input handed to the running pipe, ending as soon as a swap is pending
+struct Feed<InT, OutT> {
+    control: Rc<Control<InT, OutT>>,
+}
+
+impl<InT: 'static, OutT: 'static> Source<InT> for Feed<InT, OutT> {
+    fn stream(&self) -> Box<dyn Stream<Item = InT>> {
+        let control = self.control.clone();
+        Box::new(stream::poll_fn(move |cx| {
+            if control.pending.borrow().is_some() {
+                return Poll::Ready(None);
+            }
+            let mut upstream = control.upstream.borrow_mut();
+            let polled = match upstream.as_mut() {
+                Some(upstream) => upstream.as_mut().poll_next(cx),
+                None => return Poll::Ready(None),
+            };
+            if let Poll::Ready(None) = polled {
+                *upstream = None;
+            }
+            polled
+        }))
+    }
+}
+
+/// SwappablePipe
+/// A pipe delegating to an inner pipe that can be replaced while its stream runs
+pub struct SwappablePipe<InT, OutT> {
+    control: Rc<Control<InT, OutT>>,
+}
+
+impl<InT: 'static, OutT: 'static> SwappablePipe<InT, OutT> {
+    fn with_replacement(replacement: Replacement<InT, OutT>) -> Self {
+        let control = Rc::new(Control {
+            current: RefCell::new(Box::new(Placeholder)),
+            state: RefCell::new(None),
+            pending: RefCell::new(None),
+            input: RefCell::new(None),
+            upstream: RefCell::new(None),
+            streaming: Cell::new(false),
+            waker: RefCell::new(None),
+            swaps: Cell::new(0),
+            error: Cell::new(None),
+        });
+        control.install(replacement);
+        control.swaps.set(0);
+        Self { control }
+    }
+    /// constructor around an initial pipe
+    pub fn new<P: Pipe<InT, OutT> + 'static>(pipe: P) -> Self {
+        Self::with_replacement(Replacement {
+            pipe: Box::new(pipe),
+            state: None,
+            transfer: None,
+        })
+    }
+    /// constructor around an initial stateful pipe whose state carries over stateful swaps
+    pub fn new_stateful<K, V, P>(pipe: P) -> Self
+    where
+        K: 'static,
+        V: 'static,
+        P: Pipe<InT, OutT> + Stateful<K, V> + 'static,
+    {
+        let state: Box<dyn Any> = Box::new(pipe.state_store());
+        Self::with_replacement(Replacement {
+            pipe: Box::new(pipe),
+            state: Some(state),
+            transfer: None,
+        })
+    }
+    /// replace the inner pipe, once the running one drained if streaming
+    pub fn swap<P: Pipe<InT, OutT> + 'static>(&self, pipe: P) {
+        self.control.request(Replacement {
+            pipe: Box::new(pipe),
+            state: None,
+            transfer: None,
+        });
+    }
+    /// replace the inner pipe with a stateful pipe, restoring in its store a snapshot of the
+    /// state of the replaced pipe if it was stateful with the same key and value types
+    pub fn swap_stateful<K, V, P>(&self, pipe: P)
+    where
+        K: 'static,
+        V: 'static,
+        P: Pipe<InT, OutT> + Stateful<K, V> + 'static,
+    {
+        let store = pipe.state_store();
+        let target = store.clone();
+        let transfer: Transfer =
+            Box::new(
+                move |old: &dyn Any| match old.downcast_ref::<SharedStateStore<K, V>>() {
+                    Some(old) => {
+                        let snapshot = old.borrow().snapshot();
+                        target.borrow_mut().restore(snapshot)
+                    }
+                    None => Ok(()),
+                },
+            );
+        self.control.request(Replacement {
+            pipe: Box::new(pipe),
+            state: Some(Box::new(store)),
+            transfer: Some(transfer),
+        });
+    }
+    /// the number of swaps completed
+    pub fn swaps(&self) -> usize {
+        self.control.swaps.get()
+    }
+    /// whether a swap waits for the running pipe to drain
+    pub fn is_swapping(&self) -> bool {
+        self.control.pending.borrow().is_some()
+    }
+    /// the last failure to connect a new pipe or to transfer state
+    pub fn error(&self) -> Option<&'static str> {
+        self.control.error.get()
+    }
+}
+
+impl<InT: 'static, OutT: 'static> Source<OutT> for SwappablePipe<InT, OutT> {
+    fn stream(&self) -> Box<dyn Stream<Item = OutT>> {
+        let control = self.control.clone();
+        let input = match control.input.borrow().as_ref() {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        *control.upstream.borrow_mut() = Some(input);
+        control.streaming.set(true);
+        let mut inner = Pin::from(control.current.borrow().stream());
+        Box::new(stream::poll_fn(move |cx| loop {
+            *control.waker.borrow_mut() = Some(cx.waker().clone());
+            match inner.as_mut().poll_next(cx) {
+                Poll::Ready(None) => {
+                    let pending = control.pending.borrow_mut().take();
+                    match pending {
+                        Some(replacement) => {
+                            control.install(replacement);
+                            inner = Pin::from(control.current.borrow().stream());
+                        }
+                        None => {
+                            control.streaming.set(false);
+                            return Poll::Ready(None);
+                        }
+                    }
+                }
+                polled => return polled,
+            }
+        }))
+    }
+}
+
+impl<InT: 'static, OutT: 'static> Pipe<InT, OutT> for SwappablePipe<InT, OutT> {
+    fn pipe(&mut self, input: Rc<dyn Source<InT>>) -> Result<(), &'static str> {
+        *self.control.input.borrow_mut() = Some(input);
+        Ok(())
+    }
+    fn unpipe(&mut self) {
+        *self.control.input.borrow_mut() = None;
+    }
+    fn get_input(&self) -> Option<Rc<dyn Source<InT>>> {
+        self.control.input.borrow().clone()
+    }
+}
+
+/// the pipe held before the initial pipe is installed
+struct Placeholder;
+
+impl<OutT: 'static> Source<OutT> for Placeholder {
+    fn stream(&self) -> Box<dyn Stream<Item = OutT>> {
+        Box::new(stream::empty())
+    }
+}
+
+impl<InT, OutT: 'static> Pipe<InT, OutT> for Placeholder {
+    fn pipe(&mut self, _input: Rc<dyn Source<InT>>) -> Result<(), &'static str> {
+        Ok(())
+    }
+    fn unpipe(&mut self) {}
+    fn get_input(&self) -> Option<Rc<dyn Source<InT>>> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pipes::FlatMapPipe;
+    use crate::state::KeyedStatePipe;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+    use futures::StreamExt;
+
+    fn scale(factor: i32) -> FlatMapPipe<i32, i32> {
+        FlatMapPipe::new(move |item: i32| [item * factor])
+    }
+
+    #[test]
+    fn test_swap_mid_stream() {
+        let mut swappable = SwappablePipe::new(scale(2));
+        swappable
+            .pipe(Rc::new(MockSource::new().items([1, 2, 3, 4])))
+            .unwrap();
+        let mut stream = Pin::from(swappable.stream());
+        let mut items = vec![block_on(stream.next()).unwrap()];
+        swappable.swap(scale(10));
+        assert!(swappable.is_swapping());
+        items.extend(block_on(stream.collect::<Vec<_>>()));
+        assert_eq!(
+            items,
+            vec![2, 20, 30, 40],
+            "Items lost or duplicated by swap"
+        );
+        assert_eq!(swappable.swaps(), 1);
+        swappable.swap(scale(-1));
+        assert_eq!(
+            block_on(Pin::from(swappable.stream()).collect::<Vec<_>>()),
+            vec![-1, -2, -3, -4]
+        );
+    }
+
+    fn counter(step: u32) -> KeyedStatePipe<char, char, u32, (char, u32)> {
+        KeyedStatePipe::new(
+            |item: &char| *item,
+            move |count: Option<u32>, item| {
+                let count = count.unwrap_or(0) + step;
+                (Some(count), Some((item, count)))
+            },
+        )
+    }
+
+    #[test]
+    fn test_swap_transfers_state() {
+        let mut swappable = SwappablePipe::new_stateful(counter(1));
+        swappable
+            .pipe(Rc::new(MockSource::new().items(['a', 'b', 'a', 'a'])))
+            .unwrap();
+        let mut stream = Pin::from(swappable.stream());
+        let mut items = vec![
+            block_on(stream.next()).unwrap(),
+            block_on(stream.next()).unwrap(),
+        ];
+        swappable.swap_stateful(counter(100));
+        items.extend(block_on(stream.collect::<Vec<_>>()));
+        assert_eq!(items, vec![('a', 1), ('b', 1), ('a', 101), ('a', 201)]);
+        assert_eq!(swappable.error(), None);
+    }
+}
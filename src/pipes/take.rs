@@ -0,0 +1,136 @@
+//! take
+//!
+//! Bounding streams to a prefix or dropping their head
+
+use crate::{Pipe, Source};
+use futures::future;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// TakePipe
+/// A pipe passing through the first items of its input then ending the stream
+///
+/// the input is no longer polled once enough items were taken, so previews of endless
+/// sources end
+pub struct TakePipe<T> {
+    count: usize,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T> TakePipe<T> {
+    /// constructor keeping the first `count` items
+    pub fn new(count: usize) -> Self {
+        Self { count, input: None }
+    }
+}
+
+impl<T: 'static> Source<T> for TakePipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        match &self.input {
+            Some(input) => Box::new(Pin::from(input.stream()).take(self.count)),
+            None => Box::new(stream::empty()),
+        }
+    }
+}
+
+impl<T: 'static> Pipe<T, T> for TakePipe<T> {
+    input_connection!(T);
+}
+
+/// SkipPipe
+/// A pipe dropping the first items of its input, such as header lines
+pub struct SkipPipe<T> {
+    count: usize,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T> SkipPipe<T> {
+    /// constructor dropping the first `count` items
+    pub fn new(count: usize) -> Self {
+        Self { count, input: None }
+    }
+}
+
+impl<T: 'static> Source<T> for SkipPipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        match &self.input {
+            Some(input) => Box::new(Pin::from(input.stream()).skip(self.count)),
+            None => Box::new(stream::empty()),
+        }
+    }
+}
+
+impl<T: 'static> Pipe<T, T> for SkipPipe<T> {
+    input_connection!(T);
+}
+
+/// TakeWhilePipe
+/// A pipe passing items through until one fails a predicate, ending the stream there
+/// (the failing item is dropped)
+pub struct TakeWhilePipe<T> {
+    predicate: Rc<dyn Fn(&T) -> bool>,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T> TakeWhilePipe<T> {
+    /// constructor
+    pub fn new<F: Fn(&T) -> bool + 'static>(predicate: F) -> Self {
+        Self {
+            predicate: Rc::new(predicate),
+            input: None,
+        }
+    }
+}
+
+impl<T: 'static> Source<T> for TakeWhilePipe<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let predicate = self.predicate.clone();
+        Box::new(input.take_while(move |item| future::ready(predicate(item))))
+    }
+}
+
+impl<T: 'static> Pipe<T, T> for TakeWhilePipe<T> {
+    input_connection!(T);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sources::ConstantSource;
+    use crate::testing::{collect, MockSource};
+
+    #[test]
+    fn test_take_and_skip() {
+        let input: Rc<dyn Source<i32>> = Rc::new(MockSource::new().items(0..10));
+        let mut skip = SkipPipe::new(2);
+        skip.pipe(input.clone()).unwrap();
+        let mut take = TakePipe::new(3);
+        take.pipe(Rc::new(skip)).unwrap();
+        assert_eq!(collect(&take), vec![2, 3, 4]);
+        let mut beyond = SkipPipe::new(20);
+        beyond.pipe(input).unwrap();
+        assert!(collect(&beyond).is_empty());
+        assert!(collect(&TakePipe::<i32>::new(1)).is_empty());
+    }
+
+    #[test]
+    fn test_take_while() {
+        let mut pipe = TakeWhilePipe::new(|item: &i32| *item < 3);
+        pipe.pipe(Rc::new(MockSource::new().items([1, 2, 3, 1])))
+            .unwrap();
+        assert_eq!(collect(&pipe), vec![1, 2]);
+        let mut preview = TakePipe::new(4);
+        preview.pipe(Rc::new(ConstantSource::new(7.0))).unwrap();
+        assert_eq!(
+            collect(&preview),
+            vec![7.0; 4],
+            "Endless source not bounded"
+        );
+    }
+}
@@ -0,0 +1,577 @@
+//! plugin
+//!
+//! Pipes compiled separately and loaded from shared libraries at runtime (requires the
+//! `plugin` feature, libraries are only loaded on unix)
+//!
+//! a plugin exports a `bv_plugin_entry` function returning a `BvPlugin` table describing
+//! its pipes through the C interface of `include/bitvortex_plugin.h`, so plugins can be
+//! written in any language and built against any version of the crate sharing the ABI
+//! version. Items cross the interface as bytes: raw for `BV_ITEMS_BYTES` (Bytes), UTF-8
+//! for `BV_ITEMS_TEXT` (String) and 8 little endian bytes for `BV_ITEMS_NUMBER` (f64)
+
+use crate::{Pipe, Source};
+use bytes::Bytes;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::ffi::{c_char, c_int, c_void, CStr, CString};
+use std::marker::PhantomData;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// the version of the interface described here, plugins built for another one are refused
+pub const BV_PLUGIN_ABI_VERSION: u32 = 1;
+/// the symbol every plugin library exports
+pub const BV_PLUGIN_ENTRY: &str = "bv_plugin_entry";
+/// items are raw bytes
+pub const BV_ITEMS_BYTES: u32 = 0;
+/// items are UTF-8 text
+pub const BV_ITEMS_TEXT: u32 = 1;
+/// items are f64 in 8 little endian bytes
+pub const BV_ITEMS_NUMBER: u32 = 2;
+
+/// BvParam
+/// A parameter handed to a plugin pipe, every value written as text
+#[repr(C)]
+pub struct BvParam {
+    pub key: *const c_char,
+    pub value: *const c_char,
+}
+
+/// the callback a plugin calls to emit an output item
+pub type BvEmit = unsafe extern "C" fn(context: *mut c_void, data: *const u8, len: usize);
+
+/// BvPipeDescriptor
+/// The kind of a pipe and the functions implementing it, over a state created per stream
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub struct BvPipeDescriptor {
+    pub kind: *const c_char,
+    pub help: *const c_char,
+    /// BV_ITEMS_* of the consumed items
+    pub input: u32,
+    /// BV_ITEMS_* of the produced items
+    pub output: u32,
+    /// a new state for the parameters, null when they are refused
+    pub create: unsafe extern "C" fn(params: *const BvParam, count: usize) -> *mut c_void,
+    /// handle an item, emitting any number of outputs (non zero to fail the stream)
+    pub process: unsafe extern "C" fn(
+        state: *mut c_void,
+        data: *const u8,
+        len: usize,
+        emit: BvEmit,
+        context: *mut c_void,
+    ) -> c_int,
+    /// emit the last outputs once the input ended (may be null)
+    pub finish: Option<
+        unsafe extern "C" fn(state: *mut c_void, emit: BvEmit, context: *mut c_void) -> c_int,
+    >,
+    /// release a state
+    pub free: unsafe extern "C" fn(state: *mut c_void),
+}
+
+/// BvPlugin
+/// The table returned by the entry point of a plugin, valid while the library is loaded
+#[repr(C)]
+pub struct BvPlugin {
+    pub abi_version: u32,
+    pub name: *const c_char,
+    pub pipes: *const BvPipeDescriptor,
+    pub pipe_count: usize,
+}
+
+/// the entry point of a plugin
+pub type BvPluginEntry = unsafe extern "C" fn() -> *const BvPlugin;
+
+/// PluginItem
+/// The item types that cross the plugin interface
+pub trait PluginItem: Sized + 'static {
+    /// the BV_ITEMS_* code of the type
+    const ENCODING: u32;
+    fn encode(&self) -> Vec<u8>;
+    fn decode(bytes: &[u8]) -> Option<Self>;
+}
+
+impl PluginItem for Bytes {
+    const ENCODING: u32 = BV_ITEMS_BYTES;
+    fn encode(&self) -> Vec<u8> {
+        self.to_vec()
+    }
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        Some(Bytes::copy_from_slice(bytes))
+    }
+}
+
+impl PluginItem for String {
+    const ENCODING: u32 = BV_ITEMS_TEXT;
+    fn encode(&self) -> Vec<u8> {
+        self.as_bytes().to_vec()
+    }
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        String::from_utf8(bytes.to_vec()).ok()
+    }
+}
+
+impl PluginItem for f64 {
+    const ENCODING: u32 = BV_ITEMS_NUMBER;
+    fn encode(&self) -> Vec<u8> {
+        self.to_le_bytes().to_vec()
+    }
+    fn decode(bytes: &[u8]) -> Option<Self> {
+        Some(f64::from_le_bytes(bytes.try_into().ok()?))
+    }
+}
+
+/// a loaded shared library, unloaded once no plugin pipe uses it
+struct Library {
+    #[cfg(unix)]
+    handle: *mut c_void,
+}
+
+impl Drop for Library {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        unsafe {
+            libc::dlclose(self.handle);
+        }
+    }
+}
+
+/// PluginKind
+/// A pipe offered by a plugin
+#[derive(Clone)]
+pub struct PluginKind {
+    pub kind: String,
+    pub help: String,
+    /// BV_ITEMS_* of the consumed items
+    pub input: u32,
+    /// BV_ITEMS_* of the produced items
+    pub output: u32,
+    descriptor: BvPipeDescriptor,
+    /// keeps the library loaded while the functions of the descriptor are in use
+    _library: Option<Rc<Library>>,
+}
+
+/// Plugin
+/// The pipes of a plugin library
+pub struct Plugin {
+    name: String,
+    kinds: Vec<PluginKind>,
+}
+
+unsafe fn text(pointer: *const c_char) -> String {
+    if pointer.is_null() {
+        return String::new();
+    }
+    CStr::from_ptr(pointer).to_string_lossy().into_owned()
+}
+
+impl Plugin {
+    unsafe fn from_table(
+        table: *const BvPlugin,
+        library: Option<Rc<Library>>,
+    ) -> Result<Self, &'static str> {
+        let table = table.as_ref().ok_or("Plugin returned no table")?;
+        if table.abi_version != BV_PLUGIN_ABI_VERSION {
+            return Err("Unsupported plugin ABI version");
+        }
+        if table.pipes.is_null() && table.pipe_count > 0 {
+            return Err("Plugin returned no table");
+        }
+        let descriptors = match table.pipe_count {
+            0 => &[][..],
+            count => std::slice::from_raw_parts(table.pipes, count),
+        };
+        let mut kinds = Vec::new();
+        for descriptor in descriptors {
+            if descriptor.input > BV_ITEMS_NUMBER || descriptor.output > BV_ITEMS_NUMBER {
+                return Err("Plugin pipe has unknown item types");
+            }
+            kinds.push(PluginKind {
+                kind: text(descriptor.kind),
+                help: text(descriptor.help),
+                input: descriptor.input,
+                output: descriptor.output,
+                descriptor: *descriptor,
+                _library: library.clone(),
+            });
+        }
+        Ok(Self {
+            name: text(table.name),
+            kinds,
+        })
+    }
+    /// load the plugin linked into the current program with its entry point
+    ///
+    /// # Safety
+    /// `entry` must return a table following the plugin interface and staying valid for
+    /// the life of the program
+    pub unsafe fn from_entry(entry: BvPluginEntry) -> Result<Self, &'static str> {
+        Self::from_table(entry(), None)
+    }
+    /// load a plugin library
+    ///
+    /// loading a library runs its initialization code, only load trusted plugins
+    #[cfg(unix)]
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, &'static str> {
+        use std::os::unix::ffi::OsStrExt;
+        let path = CString::new(path.as_ref().as_os_str().as_bytes())
+            .map_err(|_| "Invalid plugin path")?;
+        unsafe {
+            let handle = libc::dlopen(path.as_ptr(), libc::RTLD_NOW | libc::RTLD_LOCAL);
+            if handle.is_null() {
+                return Err("Failure to load plugin library");
+            }
+            let library = Rc::new(Library { handle });
+            let symbol = CString::new(BV_PLUGIN_ENTRY).unwrap();
+            let entry = libc::dlsym(handle, symbol.as_ptr());
+            if entry.is_null() {
+                return Err("Plugin library has no entry point");
+            }
+            let entry: BvPluginEntry = std::mem::transmute(entry);
+            Self::from_table(entry(), Some(library))
+        }
+    }
+    /// load a plugin library, which is refused on platforms other than unix
+    #[cfg(not(unix))]
+    pub fn load<P: AsRef<Path>>(_: P) -> Result<Self, &'static str> {
+        Err("Plugin libraries are only loaded on unix")
+    }
+    /// the name of the plugin
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// the pipes of the plugin
+    pub fn kinds(&self) -> impl Iterator<Item = &PluginKind> {
+        self.kinds.iter()
+    }
+    /// instantiate a pipe with parameters
+    pub fn pipe<InT: PluginItem, OutT: PluginItem>(
+        &self,
+        kind: &str,
+        params: &[(&str, &str)],
+    ) -> Result<PluginPipe<InT, OutT>, &'static str> {
+        let kind = self
+            .kinds
+            .iter()
+            .find(|candidate| candidate.kind == kind)
+            .ok_or("Plugin has no such pipe")?;
+        kind.pipe(params)
+    }
+}
+
+impl PluginKind {
+    /// instantiate the pipe with parameters, refused when the plugin refuses them
+    pub fn pipe<InT: PluginItem, OutT: PluginItem>(
+        &self,
+        params: &[(&str, &str)],
+    ) -> Result<PluginPipe<InT, OutT>, &'static str> {
+        if self.input != InT::ENCODING || self.output != OutT::ENCODING {
+            return Err("Plugin pipe has other item types");
+        }
+        let mut owned = Vec::new();
+        for (key, value) in params {
+            let key = CString::new(*key).map_err(|_| "Invalid plugin parameter")?;
+            let value = CString::new(*value).map_err(|_| "Invalid plugin parameter")?;
+            owned.push((key, value));
+        }
+        let pipe = PluginPipe {
+            kind: self.clone(),
+            params: Rc::new(owned),
+            error: Rc::new(Cell::new(None)),
+            input: None,
+            items: PhantomData,
+        };
+        // creating a first state checks the parameters before anything runs
+        pipe.state().ok_or("Plugin refused its parameters")?;
+        Ok(pipe)
+    }
+}
+
+/// the state of a plugin pipe for one stream, released when the stream is dropped
+struct State {
+    pointer: *mut c_void,
+    kind: PluginKind,
+}
+
+impl State {
+    fn call<OutT: PluginItem>(
+        &self,
+        call: impl FnOnce(*mut c_void, BvEmit, *mut c_void) -> c_int,
+    ) -> Result<Vec<OutT>, &'static str> {
+        unsafe extern "C" fn collect(context: *mut c_void, data: *const u8, len: usize) {
+            let outputs = &mut *(context as *mut Vec<Vec<u8>>);
+            outputs.push(match len {
+                0 => Vec::new(),
+                len => std::slice::from_raw_parts(data, len).to_vec(),
+            });
+        }
+        let mut outputs: Vec<Vec<u8>> = Vec::new();
+        let context = &mut outputs as *mut Vec<Vec<u8>> as *mut c_void;
+        if call(self.pointer, collect, context) != 0 {
+            return Err("Plugin pipe failed");
+        }
+        outputs
+            .iter()
+            .map(|output| OutT::decode(output).ok_or("Plugin pipe emitted an invalid item"))
+            .collect()
+    }
+}
+
+impl Drop for State {
+    fn drop(&mut self) {
+        unsafe { (self.kind.descriptor.free)(self.pointer) }
+    }
+}
+
+/// PluginPipe
+/// A pipe implemented by a plugin
+///
+/// the stream ends at the first failure of the plugin, kept by `error`
+pub struct PluginPipe<InT, OutT> {
+    kind: PluginKind,
+    params: Rc<Vec<(CString, CString)>>,
+    error: Rc<Cell<Option<&'static str>>>,
+    input: Option<Rc<dyn Source<InT>>>,
+    items: PhantomData<OutT>,
+}
+
+impl<InT, OutT> PluginPipe<InT, OutT> {
+    fn state(&self) -> Option<State> {
+        let params: Vec<BvParam> = self
+            .params
+            .iter()
+            .map(|(key, value)| BvParam {
+                key: key.as_ptr(),
+                value: value.as_ptr(),
+            })
+            .collect();
+        let pointer = unsafe { (self.kind.descriptor.create)(params.as_ptr(), params.len()) };
+        (!pointer.is_null()).then(|| State {
+            pointer,
+            kind: self.kind.clone(),
+        })
+    }
+    /// the failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl<InT: PluginItem, OutT: PluginItem> Source<OutT> for PluginPipe<InT, OutT> {
+    fn stream(&self) -> Box<dyn Stream<Item = OutT>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        self.error.set(None);
+        let state = match self.state() {
+            Some(state) => Rc::new(state),
+            None => {
+                self.error.set(Some("Plugin refused its parameters"));
+                return Box::new(stream::empty());
+            }
+        };
+        let (process, finish) = (self.kind.descriptor.process, self.kind.descriptor.finish);
+        let processing = state.clone();
+        let outputs = input.map(move |item| {
+            let data = item.encode();
+            processing.call(|pointer, emit, context| unsafe {
+                process(pointer, data.as_ptr(), data.len(), emit, context)
+            })
+        });
+        let last = stream::once(async move {
+            match finish {
+                Some(finish) => {
+                    state.call(|pointer, emit, context| unsafe { finish(pointer, emit, context) })
+                }
+                None => Ok(Vec::new()),
+            }
+        });
+        let error = self.error.clone();
+        let failed = Rc::new(Cell::new(false));
+        Box::new(
+            outputs
+                .chain(last)
+                .take_while(move |outputs| {
+                    let fine = !failed.get();
+                    if let Err(message) = outputs {
+                        error.set(Some(message));
+                        failed.set(true);
+                    }
+                    futures::future::ready(fine && outputs.is_ok())
+                })
+                .flat_map(|outputs| stream::iter(outputs.unwrap_or_default())),
+        )
+    }
+}
+
+impl<InT: PluginItem, OutT: PluginItem> Pipe<InT, OutT> for PluginPipe<InT, OutT> {
+    input_connection!(InT);
+}
+
+/// load every library of a directory (files with the extension of the platform), with the
+/// outcome for each of them
+pub fn discover<P: AsRef<Path>>(directory: P) -> Vec<(PathBuf, Result<Plugin, &'static str>)> {
+    let mut paths: Vec<PathBuf> = match std::fs::read_dir(directory) {
+        Ok(entries) => entries
+            .filter_map(|entry| Some(entry.ok()?.path()))
+            .filter(|path| {
+                path.extension()
+                    .is_some_and(|extension| extension == std::env::consts::DLL_EXTENSION)
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let plugin = Plugin::load(&path);
+            (path, plugin)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    /// repeats every line `times` times then emits an end marker, failing on "boom"
+    struct Repeat {
+        times: usize,
+    }
+
+    unsafe extern "C" fn create(params: *const BvParam, count: usize) -> *mut c_void {
+        let mut times = 1;
+        for param in std::slice::from_raw_parts(params, count) {
+            match (text(param.key).as_str(), text(param.value).parse()) {
+                ("times", Ok(value)) => times = value,
+                _ => return std::ptr::null_mut(),
+            }
+        }
+        Box::into_raw(Box::new(Repeat { times })) as *mut c_void
+    }
+
+    unsafe extern "C" fn process(
+        state: *mut c_void,
+        data: *const u8,
+        len: usize,
+        emit: BvEmit,
+        context: *mut c_void,
+    ) -> c_int {
+        let state = &*(state as *mut Repeat);
+        if std::slice::from_raw_parts(data, len) == b"boom" {
+            return 1;
+        }
+        for _ in 0..state.times {
+            emit(context, data, len);
+        }
+        0
+    }
+
+    unsafe extern "C" fn finish(_: *mut c_void, emit: BvEmit, context: *mut c_void) -> c_int {
+        emit(context, b"end".as_ptr(), 3);
+        0
+    }
+
+    unsafe extern "C" fn free(state: *mut c_void) {
+        drop(Box::from_raw(state as *mut Repeat));
+    }
+
+    unsafe extern "C" fn entry() -> *const BvPlugin {
+        let pipes = Box::leak(Box::new([BvPipeDescriptor {
+            kind: c"repeat".as_ptr(),
+            help: c"lines repeated times times".as_ptr(),
+            input: BV_ITEMS_TEXT,
+            output: BV_ITEMS_TEXT,
+            create,
+            process,
+            finish: Some(finish),
+            free,
+        }]));
+        Box::leak(Box::new(BvPlugin {
+            abi_version: BV_PLUGIN_ABI_VERSION,
+            name: c"test".as_ptr(),
+            pipes: pipes.as_ptr(),
+            pipe_count: pipes.len(),
+        }))
+    }
+
+    fn lines(items: &[&str]) -> Rc<MockSource<String>> {
+        Rc::new(MockSource::new().items(items.iter().map(|item| item.to_string())))
+    }
+
+    #[test]
+    fn test_plugin_pipe() {
+        let plugin = unsafe { Plugin::from_entry(entry) }.unwrap();
+        assert_eq!(plugin.name(), "test");
+        let kinds: Vec<&str> = plugin.kinds().map(|kind| kind.kind.as_str()).collect();
+        assert_eq!(kinds, ["repeat"]);
+        let mut pipe = plugin
+            .pipe::<String, String>("repeat", &[("times", "2")])
+            .unwrap();
+        pipe.pipe(lines(&["a", "b"])).unwrap();
+        let outputs: Vec<String> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(outputs, ["a", "a", "b", "b", "end"]);
+        assert_eq!(pipe.error(), None);
+        pipe.pipe(lines(&["a", "boom", "b"])).unwrap();
+        let outputs: Vec<String> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(outputs, ["a", "a"]);
+        assert_eq!(pipe.error(), Some("Plugin pipe failed"));
+    }
+
+    #[test]
+    fn test_refusals() {
+        let plugin = unsafe { Plugin::from_entry(entry) }.unwrap();
+        let refused = |result: Result<PluginPipe<String, String>, _>| result.err();
+        assert_eq!(
+            refused(plugin.pipe("repeat", &[("times", "many")])),
+            Some("Plugin refused its parameters")
+        );
+        assert_eq!(
+            refused(plugin.pipe("echo", &[])),
+            Some("Plugin has no such pipe")
+        );
+        assert!(plugin.pipe::<f64, String>("repeat", &[]).is_err());
+        assert!(Plugin::load("/nonexistent/plugin.so").is_err());
+        let empty = std::env::temp_dir().join(format!("bv-plugins-{}", std::process::id()));
+        std::fs::create_dir_all(&empty).unwrap();
+        assert!(discover(&empty).is_empty());
+        std::fs::remove_dir_all(&empty).unwrap();
+    }
+
+    #[test]
+    fn test_registry() {
+        use crate::config::{ConfigError, NodeRegistry, PipelineConfig, Role};
+        let plugin = unsafe { Plugin::from_entry(entry) }.unwrap();
+        let mut registry = NodeRegistry::with_builtins();
+        registry.register_plugin(&plugin);
+        let info = registry.kind("repeat").unwrap();
+        assert_eq!(info.role, Role::Pipe);
+        assert_eq!((info.input, info.output), (Some("String"), Some("String")));
+        let graph = |times: &str| {
+            let description = format!(
+                "[nodes.a]\nkind = \"ramp\"\nlen = 2\n\
+                 [nodes.b]\nkind = \"format\"\ninput = \"a\"\n\
+                 [nodes.c]\nkind = \"repeat\"\ntimes = {}\ninput = \"b\"\n\
+                 [nodes.d]\nkind = \"log\"\ninput = \"c\"\n",
+                times
+            );
+            PipelineConfig::from_toml(&description)?.graph(&registry)
+        };
+        let run = graph("3").unwrap().build().unwrap();
+        assert_eq!(block_on(run.drain()), Ok(()));
+        assert!(matches!(
+            graph("\"many\""),
+            Err(ConfigError::Node {
+                reason: "Plugin refused its parameters",
+                ..
+            })
+        ));
+        assert!(matches!(graph("[1]"), Err(ConfigError::Parameter { .. })));
+    }
+}
@@ -0,0 +1,378 @@
+//! python
+//!
+//! Python classes for buckets, blobs and pipelines (requires the `python` feature)
+//!
+//! blobs export their data through the buffer protocol so `numpy.asarray(blob)` shares the
+//! memory held in rust, build the module with `pyo3/extension-module` enabled to produce an
+//! importable extension
+
+use crate::data_bucket::{BlobType, DataBlob, DataBucket, DataBucketBlob, MetaData};
+use crate::ops::{ElementwiseOp, ElementwisePipe};
+use crate::{Pipe, Source};
+use futures::StreamExt;
+use pyo3::buffer::{Element, PyBuffer};
+use pyo3::exceptions::{PyBufferError, PyKeyError, PyTypeError, PyValueError};
+use pyo3::ffi;
+use pyo3::prelude::*;
+use std::ffi::{c_int, c_void, CStr};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Arc;
+
+/// PyDataBlob
+/// A read only handle on a blob shared with the buckets holding it
+#[pyclass(name = "DataBlob", module = "bitvortex", frozen)]
+pub struct PyDataBlob {
+    blob: Arc<DataBucketBlob>,
+}
+
+/// layout of the memory of a blob as exported through the buffer protocol
+struct Layout {
+    data: *const u8,
+    len: usize,
+    item_size: usize,
+    format: &'static CStr,
+}
+
+macro_rules! blob_layout {
+    ($blob:expr, $($variant:ident => $format:expr),*) => {
+        match $blob {
+            $( DataBucketBlob::$variant(blob) => Some(Layout {
+                data: blob.get_data().as_ptr() as *const u8,
+                len: blob.get_data().len(),
+                item_size: std::mem::size_of_val(&blob.get_data()[..]) / blob.get_data().len().max(1),
+                format: $format,
+            }), )*
+            _ => None,
+        }
+    };
+}
+
+fn layout(blob: &DataBucketBlob) -> Option<Layout> {
+    blob_layout!(blob,
+        Bool => c"?", Int8 => c"b", U8 => c"B", Int16 => c"h", U16 => c"H",
+        Int32 => c"i", U32 => c"I", Int64 => c"q", U64 => c"Q", ISize => c"n",
+        USize => c"N", Float32 => c"f", Float64 => c"d", Float16 => c"e", Timestamp => c"q",
+        Complex32 => c"Zf", Complex64 => c"Zd")
+}
+
+/// copy a python buffer into a blob if it holds elements of type `T`
+fn from_buffer<T: Element + BlobType + Copy>(
+    name: &str,
+    data: &Bound<'_, PyAny>,
+) -> Option<PyResult<DataBucketBlob>> {
+    let buffer = PyBuffer::<T>::get(data).ok()?;
+    let shape = buffer.shape();
+    let count = shape.first().copied().unwrap_or(1);
+    let unit: usize = shape.iter().skip(1).product();
+    Some(buffer.to_vec(data.py()).map(|values| {
+        T::wrap(DataBlob::new(
+            values,
+            MetaData {
+                name: name.to_string(),
+                dimensions: vec![count],
+                unitary_dimensions: vec![unit],
+                ..Default::default()
+            },
+        ))
+    }))
+}
+
+fn from_values<T: BlobType>(name: &str, values: Vec<T>) -> DataBucketBlob {
+    let count = values.len();
+    T::wrap(DataBlob::new(
+        values,
+        MetaData {
+            name: name.to_string(),
+            dimensions: vec![count],
+            unitary_dimensions: vec![1],
+            ..Default::default()
+        },
+    ))
+}
+
+#[pymethods]
+impl PyDataBlob {
+    /// copy a buffer (such as a numpy array) or a list of numbers or strings into a blob
+    #[new]
+    fn new(name: &str, data: &Bound<'_, PyAny>) -> PyResult<Self> {
+        let blob = from_buffer::<f64>(name, data)
+            .or_else(|| from_buffer::<f32>(name, data))
+            .or_else(|| from_buffer::<i64>(name, data))
+            .or_else(|| from_buffer::<i32>(name, data))
+            .or_else(|| from_buffer::<u8>(name, data))
+            .unwrap_or_else(|| {
+                if let Ok(values) = data.extract::<Vec<i64>>() {
+                    Ok(from_values(name, values))
+                } else if let Ok(values) = data.extract::<Vec<f64>>() {
+                    Ok(from_values(name, values))
+                } else if let Ok(values) = data.extract::<Vec<String>>() {
+                    Ok(from_values(name, values))
+                } else {
+                    Err(PyTypeError::new_err("Unsupported blob data"))
+                }
+            })?;
+        Ok(Self {
+            blob: Arc::new(blob),
+        })
+    }
+    /// name of the blob
+    #[getter]
+    fn name(&self) -> String {
+        self.blob.get_meta_data().name.clone()
+    }
+    /// number of units in the blob
+    fn __len__(&self) -> usize {
+        self.blob.unit_count()
+    }
+    unsafe fn __getbuffer__(
+        slf: Bound<'_, Self>,
+        view: *mut ffi::Py_buffer,
+        flags: c_int,
+    ) -> PyResult<()> {
+        if view.is_null() {
+            return Err(PyBufferError::new_err("View is null"));
+        }
+        if flags & ffi::PyBUF_WRITABLE == ffi::PyBUF_WRITABLE {
+            return Err(PyBufferError::new_err("Blobs are read only"));
+        }
+        let blob = &slf.get().blob;
+        let layout =
+            layout(blob).ok_or_else(|| PyBufferError::new_err("Blob type has no buffer"))?;
+        let unit = blob
+            .get_meta_data()
+            .unitary_dimensions
+            .iter()
+            .product::<usize>();
+        let shape: Box<[isize]> = if unit > 1 {
+            Box::new([(layout.len / unit) as isize, unit as isize])
+        } else {
+            Box::new([layout.len as isize])
+        };
+        let item_size = layout.item_size as isize;
+        let strides: Box<[isize]> = if unit > 1 {
+            Box::new([unit as isize * item_size, item_size])
+        } else {
+            Box::new([item_size])
+        };
+        (*view).obj = slf.clone().into_any().into_ptr();
+        (*view).buf = layout.data as *mut c_void;
+        (*view).len = layout.len as isize * item_size;
+        (*view).readonly = 1;
+        (*view).itemsize = item_size;
+        (*view).format = if flags & ffi::PyBUF_FORMAT == ffi::PyBUF_FORMAT {
+            layout.format.as_ptr() as *mut _
+        } else {
+            std::ptr::null_mut()
+        };
+        (*view).ndim = shape.len() as c_int;
+        (*view).shape = Box::into_raw(shape) as *mut isize;
+        (*view).strides = Box::into_raw(strides) as *mut isize;
+        (*view).suboffsets = std::ptr::null_mut();
+        (*view).internal = std::ptr::null_mut();
+        Ok(())
+    }
+    unsafe fn __releasebuffer__(&self, view: *mut ffi::Py_buffer) {
+        let ndim = (*view).ndim as usize;
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            (*view).shape,
+            ndim,
+        )));
+        drop(Box::from_raw(std::ptr::slice_from_raw_parts_mut(
+            (*view).strides,
+            ndim,
+        )));
+    }
+}
+
+/// PyDataBucket
+/// A named collection of blobs
+#[pyclass(name = "DataBucket", module = "bitvortex")]
+#[derive(Default)]
+pub struct PyDataBucket {
+    bucket: DataBucket,
+}
+
+#[pymethods]
+impl PyDataBucket {
+    /// empty constructor
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+    /// add a blob, sharing its memory with the bucket
+    fn add(&mut self, blob: &PyDataBlob) -> PyResult<()> {
+        match self.bucket.add_shared_blob(blob.blob.clone()) {
+            None => Ok(()),
+            Some(_) => Err(PyValueError::new_err("Blob name already in bucket")),
+        }
+    }
+    /// get a blob by name
+    fn get(&self, name: &str) -> PyResult<PyDataBlob> {
+        self.bucket
+            .get_shared_blob(name)
+            .map(|blob| PyDataBlob { blob })
+            .ok_or_else(|| PyKeyError::new_err(name.to_string()))
+    }
+    /// remove a blob by name
+    fn remove(&mut self, name: &str) -> PyResult<()> {
+        self.bucket
+            .pop_blob(name.to_string())
+            .map(|_| ())
+            .ok_or_else(|| PyKeyError::new_err(name.to_string()))
+    }
+    /// sorted names of the blobs
+    fn names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.bucket.blob_names().cloned().collect();
+        names.sort();
+        names
+    }
+    fn __len__(&self) -> usize {
+        self.bucket.len()
+    }
+    fn __contains__(&self, name: &str) -> bool {
+        self.bucket.contains(name)
+    }
+}
+
+/// PyPipeline
+/// A chain of elementwise stages applied to float64 blobs
+#[pyclass(name = "Pipeline", module = "bitvortex")]
+#[derive(Default)]
+pub struct PyPipeline {
+    stages: Vec<(ElementwiseOp, f64)>,
+}
+
+/// run the elementwise stages over the values
+fn run_stages(values: Vec<f64>, meta: MetaData, stages: Vec<(ElementwiseOp, f64)>) -> Vec<f64> {
+    let mut source: Rc<dyn Source<f64>> = Rc::new(DataBlob::new(values, meta));
+    for (op, rhs) in stages {
+        let mut pipe = ElementwisePipe::with_scalar(op, rhs);
+        pipe.pipe(source)
+            .expect("Failure to connect pipeline stage");
+        source = Rc::new(pipe);
+    }
+    futures::executor::block_on(Pin::from(source.stream()).collect())
+}
+
+#[pymethods]
+impl PyPipeline {
+    /// empty constructor
+    #[new]
+    fn new() -> Self {
+        Self::default()
+    }
+    /// append a stage adding a value
+    fn add(mut slf: PyRefMut<'_, Self>, value: f64) -> PyRefMut<'_, Self> {
+        slf.stages.push((ElementwiseOp::Add, value));
+        slf
+    }
+    /// append a stage subtracting a value
+    fn sub(mut slf: PyRefMut<'_, Self>, value: f64) -> PyRefMut<'_, Self> {
+        slf.stages.push((ElementwiseOp::Sub, value));
+        slf
+    }
+    /// append a stage multiplying by a value
+    fn mul(mut slf: PyRefMut<'_, Self>, value: f64) -> PyRefMut<'_, Self> {
+        slf.stages.push((ElementwiseOp::Mul, value));
+        slf
+    }
+    /// append a stage dividing by a value
+    fn div(mut slf: PyRefMut<'_, Self>, value: f64) -> PyRefMut<'_, Self> {
+        slf.stages.push((ElementwiseOp::Div, value));
+        slf
+    }
+    /// number of stages
+    fn __len__(&self) -> usize {
+        self.stages.len()
+    }
+    /// run the pipeline over a float64 blob, releasing the interpreter while processing
+    fn run(&self, py: Python<'_>, blob: &PyDataBlob) -> PyResult<PyDataBlob> {
+        let input = match blob.blob.as_ref() {
+            DataBucketBlob::Float64(input) => input,
+            _ => return Err(PyTypeError::new_err("Pipelines run on float64 blobs")),
+        };
+        let meta = input.get_meta_data().clone();
+        let values = input.get_data().clone();
+        let stages = self.stages.clone();
+        let stage_meta = meta.clone();
+        let output = py.detach(move || run_stages(values, stage_meta, stages));
+        Ok(PyDataBlob {
+            blob: Arc::new(DataBucketBlob::Float64(DataBlob::new(output, meta))),
+        })
+    }
+}
+
+/// the `bitvortex` python module
+#[pymodule]
+fn bitvortex(module: &Bound<'_, PyModule>) -> PyResult<()> {
+    module.add_class::<PyDataBlob>()?;
+    module.add_class::<PyDataBucket>()?;
+    module.add_class::<PyPipeline>()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pyo3::types::PyDict;
+
+    fn run(code: &CStr, check: &CStr) {
+        Python::initialize();
+        Python::attach(|py| {
+            let module = PyModule::new(py, "bitvortex").unwrap();
+            bitvortex(&module).unwrap();
+            let globals = PyDict::new(py);
+            globals.set_item("bitvortex", module).unwrap();
+            py.run(code, Some(&globals), None).unwrap();
+            let result: bool = py
+                .eval(check, Some(&globals), None)
+                .unwrap()
+                .extract()
+                .unwrap();
+            assert!(result, "Failure of python check {:?}", check);
+        });
+    }
+
+    #[test]
+    fn test_blob_buffer() {
+        run(
+            c"blob = bitvortex.DataBlob('x', [1.5, 2.5, 3.5])\nview = memoryview(blob)",
+            c"view.tolist() == [1.5, 2.5, 3.5] and view.format == 'd' and view.readonly",
+        );
+        run(
+            c"blob = bitvortex.DataBlob('x', [1, 2])\nview = memoryview(blob)",
+            c"view.tolist() == [1, 2] and view.format == 'q' and len(blob) == 2",
+        );
+    }
+
+    #[test]
+    fn test_bucket_shares_blobs() {
+        run(
+            c"bucket = bitvortex.DataBucket()\nblob = bitvortex.DataBlob('x', [1.0])\nbucket.add(blob)",
+            c"'x' in bucket and len(bucket) == 1 and bucket.names() == ['x'] and \
+             memoryview(bucket.get('x')).tolist() == [1.0]",
+        );
+    }
+
+    #[test]
+    fn test_pipeline_run() {
+        run(
+            c"pipeline = bitvortex.Pipeline().mul(2.0).add(1.0)\n\
+              out = pipeline.run(bitvortex.DataBlob('x', [1.0, 2.0]))",
+            c"memoryview(out).tolist() == [3.0, 5.0] and out.name == 'x' and len(pipeline) == 2",
+        );
+    }
+
+    #[test]
+    fn test_bucket_shared_memory() {
+        Python::initialize();
+        Python::attach(|py| {
+            let blob = PyDataBlob::new("x", &vec![1.0, 2.0].into_pyobject(py).unwrap()).unwrap();
+            let mut bucket = PyDataBucket::new();
+            bucket.add(&blob).unwrap();
+            assert!(Arc::ptr_eq(&bucket.get("x").unwrap().blob, &blob.blob));
+            assert!(bucket.add(&blob).is_err(), "Accepted duplicate blob");
+        });
+    }
+}
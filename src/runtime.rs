@@ -0,0 +1,145 @@
+//! runtime
+//!
+//! Execution of pipelines on threads and asynchronous runtimes
+
+use futures::channel::oneshot;
+use futures::future::{BoxFuture, LocalBoxFuture};
+use futures::FutureExt;
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+
+/// channel
+/// Sub module for connecting pipeline stages running on different threads
+pub mod channel;
+
+/// local
+/// Sub module for the single threaded executor
+pub mod local;
+
+/// pool
+/// Sub module for the work stealing thread pool executor
+pub mod pool;
+
+/// tokio
+/// Sub module for running pipelines on a tokio runtime
+#[cfg(feature = "tokio")]
+pub mod tokio;
+
+/// async_std
+/// Sub module for running pipelines on the async-std runtime
+#[cfg(feature = "async-std")]
+pub mod async_std;
+
+/// smol
+/// Sub module for running pipelines on the smol runtime
+#[cfg(feature = "smol")]
+pub mod smol;
+
+pub use channel::{channel, ChannelSender, ChannelSource};
+pub use local::LocalExecutor;
+pub use pool::ThreadPoolExecutor;
+
+/// Task
+/// A closure building a future on the thread that will drive it
+pub type Task = Box<dyn FnOnce() -> LocalBoxFuture<'static, ()> + Send>;
+
+/// Executor
+/// A trait abstracting the runtime pipelines are spawned on
+///
+/// channels between tasks are runtime agnostic (see `runtime::channel`) so executors
+/// only need to provide spawning and timers
+pub trait Executor {
+    /// spawn a task under a name
+    fn spawn_task(&self, name: &str, task: Task);
+    /// a future resolving once the duration has elapsed
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()>;
+    /// block until every spawned task has completed (returns an error if any panicked)
+    fn wait(&self) -> Result<(), &'static str>;
+}
+
+/// a timer future backed by a sleeping thread, for executors without a timer of their own
+pub(crate) fn thread_sleep(duration: Duration) -> BoxFuture<'static, ()> {
+    let (sender, receiver) = oneshot::channel();
+    std::thread::spawn(move || {
+        std::thread::sleep(duration);
+        let _ = sender.send(());
+    });
+    receiver.map(|_| ()).boxed()
+}
+
+type Wakeup = (Instant, oneshot::Sender<()>);
+
+/// a timer thread serving every sleep of one stream, instead of a thread per sleep, the
+/// thread ends once the timer is dropped
+pub(crate) struct ThreadTimer {
+    requests: mpsc::Sender<Wakeup>,
+}
+
+impl ThreadTimer {
+    pub(crate) fn new() -> Self {
+        let (requests, receiver) = mpsc::channel::<Wakeup>();
+        std::thread::spawn(move || {
+            let mut pending: Vec<Wakeup> = Vec::new();
+            loop {
+                let next = pending.iter().map(|(deadline, _)| *deadline).min();
+                let request = match next {
+                    Some(deadline) => {
+                        receiver.recv_timeout(deadline.saturating_duration_since(Instant::now()))
+                    }
+                    None => receiver
+                        .recv()
+                        .map_err(|_| mpsc::RecvTimeoutError::Disconnected),
+                };
+                match request {
+                    Ok(wakeup) => pending.push(wakeup),
+                    Err(mpsc::RecvTimeoutError::Timeout) => {}
+                    Err(mpsc::RecvTimeoutError::Disconnected) => return,
+                }
+                let now = Instant::now();
+                let (due, waiting) = pending
+                    .drain(..)
+                    .partition(|(deadline, _)| *deadline <= now);
+                pending = waiting;
+                for (_, wakeup) in due {
+                    let _ = wakeup.send(());
+                }
+            }
+        });
+        Self { requests }
+    }
+    /// a future resolving once the deadline has passed
+    pub(crate) fn sleep_until(&self, deadline: Instant) -> BoxFuture<'static, ()> {
+        let (sender, receiver) = oneshot::channel();
+        let _ = self.requests.send((deadline, sender));
+        receiver.map(|_| ()).boxed()
+    }
+}
+
+/// a set of threads each blocking on their own task
+#[cfg(any(feature = "async-std", feature = "smol"))]
+#[derive(Default)]
+pub(crate) struct BlockingThreads {
+    threads: std::sync::Mutex<Vec<std::thread::JoinHandle<()>>>,
+}
+
+#[cfg(any(feature = "async-std", feature = "smol"))]
+impl BlockingThreads {
+    pub(crate) fn spawn<F: FnOnce() + Send + 'static>(&self, name: &str, run: F) {
+        let thread = std::thread::Builder::new()
+            .name(name.to_string())
+            .spawn(run)
+            .expect("Failure to spawn task thread");
+        self.threads.lock().unwrap().push(thread);
+    }
+    pub(crate) fn join(&self) -> Result<(), &'static str> {
+        let threads: Vec<std::thread::JoinHandle<()>> =
+            self.threads.lock().unwrap().drain(..).collect();
+        let mut result = Ok(());
+        for thread in threads {
+            if thread.join().is_err() {
+                result = Err("A pipeline task panicked");
+            }
+        }
+        result
+    }
+}
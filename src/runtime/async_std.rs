@@ -0,0 +1,73 @@
+//! async_std
+//!
+//! Running pipelines on the async-std runtime (requires the `async-std` feature)
+
+use super::{BlockingThreads, Executor, Task};
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use std::time::Duration;
+
+/// AsyncStdExecutor
+/// An executor driving every task with the async-std runtime on a thread of its own
+#[derive(Default)]
+pub struct AsyncStdExecutor {
+    threads: BlockingThreads,
+}
+
+impl AsyncStdExecutor {
+    /// constructor
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Executor for AsyncStdExecutor {
+    fn spawn_task(&self, name: &str, task: Task) {
+        self.threads
+            .spawn(name, move || async_std::task::block_on(task()));
+    }
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        async_std::task::sleep(duration).boxed()
+    }
+    fn wait(&self) -> Result<(), &'static str> {
+        self.threads.join()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::{DataBlob, MetaData};
+    use crate::pipeline::Pipeline;
+    use crate::runtime::channel;
+    use crate::Source;
+    use futures::StreamExt;
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::sync::{Arc, Mutex};
+
+    #[test]
+    fn test_run_pipeline() {
+        let executor = AsyncStdExecutor::new();
+        let nap = executor.sleep(Duration::from_millis(1));
+        let (sender, source) = channel::<u8>(1);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .add_stage("produce", move |_| async move {
+                nap.await;
+                let input = Rc::new(DataBlob::new((0..10).collect(), MetaData::default()));
+                sender.forward(input).await.unwrap();
+            })
+            .unwrap();
+        pipeline
+            .add_stage("consume", move |_| async move {
+                let items: Vec<u8> = Pin::from(source.stream()).collect().await;
+                *sink.lock().unwrap() = items;
+            })
+            .unwrap();
+        pipeline.run_with(&executor).unwrap();
+        assert_eq!(*received.lock().unwrap(), (0..10).collect::<Vec<u8>>());
+    }
+}
@@ -0,0 +1,167 @@
+//! channel
+//!
+//! Bounded channels turning the output of one stage into the source of another
+
+use crate::data_bucket::footprint::Footprint;
+use crate::memory::{MemoryReservation, MemoryTracker};
+use crate::Source;
+use futures::channel::mpsc;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+
+type Tracked<T> = (T, Option<MemoryReservation>);
+
+/// ChannelSender
+/// The sending half of a stage channel, drains a source into the channel
+pub struct ChannelSender<T> {
+    sender: mpsc::Sender<Tracked<T>>,
+    meter: Option<Meter<T>>,
+}
+
+struct Meter<T> {
+    tracker: MemoryTracker,
+    stage: String,
+    measure: fn(&T) -> usize,
+}
+
+impl<T> Clone for Meter<T> {
+    fn clone(&self) -> Self {
+        Self {
+            tracker: self.tracker.clone(),
+            stage: self.stage.clone(),
+            measure: self.measure,
+        }
+    }
+}
+
+impl<T> Meter<T> {
+    fn tag(&self, item: T) -> Tracked<T> {
+        let reservation = self.tracker.reserve(&self.stage, (self.measure)(&item));
+        (item, Some(reservation))
+    }
+}
+
+impl<T> Clone for ChannelSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            meter: self.meter.clone(),
+        }
+    }
+}
+
+impl<T: Footprint> ChannelSender<T> {
+    /// account for the items in flight in the channel under a stage of a tracker
+    pub fn track(mut self, tracker: &MemoryTracker, stage: &str) -> Self {
+        self.meter = Some(Meter {
+            tracker: tracker.clone(),
+            stage: stage.to_string(),
+            measure: T::footprint,
+        });
+        self
+    }
+}
+
+fn tag<T>(meter: &Option<Meter<T>>, item: T) -> Tracked<T> {
+    match meter {
+        Some(meter) => meter.tag(item),
+        None => (item, None),
+    }
+}
+
+impl<T: 'static> ChannelSender<T> {
+    /// push every item of the source into the channel (returns an error if the receiving
+    /// half was dropped)
+    pub async fn forward(self, input: Rc<dyn Source<T>>) -> Result<(), &'static str> {
+        let meter = self.meter;
+        Pin::from(input.stream())
+            .map(move |item| Ok(tag(&meter, item)))
+            .forward(self.sender)
+            .await
+            .map_err(|_| "Channel receiver was dropped")
+    }
+    /// push a single item into the channel
+    pub async fn send(&mut self, item: T) -> Result<(), &'static str> {
+        let item = tag(&self.meter, item);
+        futures::SinkExt::send(&mut self.sender, item)
+            .await
+            .map_err(|_| "Channel receiver was dropped")
+    }
+}
+
+/// ChannelSource
+/// The receiving half of a stage channel
+///
+/// a channel can only be drained once, streams requested after the first one are empty
+pub struct ChannelSource<T> {
+    receiver: RefCell<Option<mpsc::Receiver<Tracked<T>>>>,
+}
+
+impl<T: 'static> Source<T> for ChannelSource<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        match self.receiver.borrow_mut().take() {
+            // reservations are released as items leave the channel
+            Some(receiver) => Box::new(receiver.map(|(item, _)| item)),
+            None => Box::new(stream::empty()),
+        }
+    }
+}
+
+/// create a channel holding up to `buffer` items (plus one per sender) in flight
+pub fn channel<T>(buffer: usize) -> (ChannelSender<T>, ChannelSource<T>) {
+    let (sender, receiver) = mpsc::channel(buffer);
+    (
+        ChannelSender {
+            sender,
+            meter: None,
+        },
+        ChannelSource {
+            receiver: RefCell::new(Some(receiver)),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::{DataBlob, MetaData};
+    use futures::executor::block_on;
+    use futures::join;
+
+    #[test]
+    fn test_channel_forward() {
+        let (sender, source) = channel::<u16>(2);
+        let input = Rc::new(DataBlob::new((0..10).collect(), MetaData::default()));
+        let (sent, received) = block_on(async {
+            join!(
+                sender.forward(input),
+                Pin::from(source.stream()).collect::<Vec<u16>>()
+            )
+        });
+        assert!(sent.is_ok(), "Failure to forward items");
+        assert_eq!(received, (0..10).collect::<Vec<u16>>());
+        let again: Vec<u16> = block_on(Pin::from(source.stream()).collect());
+        assert!(again.is_empty(), "Channel drained twice");
+    }
+
+    #[test]
+    fn test_tracked_channel() {
+        let tracker = MemoryTracker::new();
+        let (sender, source) = channel::<DataBlob<f64>>(4);
+        let mut sender = sender.track(&tracker, "transfer");
+        let blob = DataBlob::new(vec![0.0; 128], MetaData::default());
+        let size = blob.footprint();
+        block_on(async {
+            sender.send(blob.clone()).await.unwrap();
+            sender.send(blob).await.unwrap();
+        });
+        assert_eq!(tracker.stage("transfer").live, 2 * size);
+        let mut received = Pin::from(source.stream());
+        block_on(received.next()).unwrap();
+        assert_eq!(tracker.live(), size, "Reservation not released on receipt");
+        assert_eq!(tracker.peak(), 2 * size);
+    }
+}
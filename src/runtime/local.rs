@@ -0,0 +1,113 @@
+//! local
+//!
+//! Cooperative execution of every task on the current thread
+
+use super::{thread_sleep, Executor, Task};
+use futures::executor::LocalPool;
+use futures::future::BoxFuture;
+use futures::task::LocalSpawnExt;
+use futures::FutureExt;
+use std::cell::{Cell, RefCell};
+use std::panic::AssertUnwindSafe;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// LocalExecutor
+/// An executor running every task cooperatively on the thread calling `wait`
+pub struct LocalExecutor {
+    pool: RefCell<LocalPool>,
+    panicked: Rc<Cell<usize>>,
+}
+
+impl LocalExecutor {
+    /// constructor
+    pub fn new() -> Self {
+        Self {
+            pool: RefCell::new(LocalPool::new()),
+            panicked: Rc::new(Cell::new(0)),
+        }
+    }
+}
+
+impl Default for LocalExecutor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Executor for LocalExecutor {
+    fn spawn_task(&self, _name: &str, task: Task) {
+        let panicked = self.panicked.clone();
+        let future = AssertUnwindSafe(task()).catch_unwind().map(move |result| {
+            if result.is_err() {
+                panicked.set(panicked.get() + 1);
+            }
+        });
+        self.pool
+            .borrow()
+            .spawner()
+            .spawn_local(future)
+            .expect("Failure to spawn on local pool");
+    }
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        thread_sleep(duration)
+    }
+    fn wait(&self) -> Result<(), &'static str> {
+        self.pool.borrow_mut().run();
+        if self.panicked.replace(0) > 0 {
+            Err("A pipeline task panicked")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Instant;
+
+    #[test]
+    fn test_local_executor() {
+        let executor = LocalExecutor::new();
+        let order = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let nap = executor.sleep(Duration::from_millis(5));
+        let first = order.clone();
+        let second = order.clone();
+        executor.spawn_task(
+            "slow",
+            Box::new(move || {
+                async move {
+                    nap.await;
+                    first.lock().unwrap().push("slow");
+                }
+                .boxed_local()
+            }),
+        );
+        executor.spawn_task(
+            "fast",
+            Box::new(move || async move { second.lock().unwrap().push("fast") }.boxed_local()),
+        );
+        let start = Instant::now();
+        executor.wait().unwrap();
+        assert!(
+            start.elapsed() >= Duration::from_millis(5),
+            "Sleep returned early"
+        );
+        assert_eq!(
+            *order.lock().unwrap(),
+            ["fast", "slow"],
+            "Tasks did not interleave"
+        );
+    }
+
+    #[test]
+    fn test_local_panic() {
+        let executor = LocalExecutor::new();
+        executor.spawn_task(
+            "broken",
+            Box::new(|| async { panic!("expected") }.boxed_local()),
+        );
+        assert!(executor.wait().is_err(), "Panic went unreported");
+    }
+}
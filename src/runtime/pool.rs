@@ -0,0 +1,244 @@
+//! pool
+//!
+//! A work stealing executor running pipeline stages on a rayon thread pool
+
+use super::{thread_sleep, Executor, Task};
+use crate::memory::MemoryTracker;
+use futures::executor::block_on;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::collections::{HashMap, VecDeque};
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::Duration;
+
+#[derive(Default)]
+struct Stage {
+    limit: Option<usize>,
+    running: usize,
+    pending: VecDeque<Task>,
+}
+
+#[derive(Default)]
+struct Progress {
+    outstanding: usize,
+    panicked: usize,
+}
+
+struct Shared {
+    pool: ThreadPool,
+    stages: Mutex<HashMap<String, Stage>>,
+    progress: Mutex<Progress>,
+    done: Condvar,
+    memory: MemoryTracker,
+}
+
+impl Shared {
+    fn launch(self: &Arc<Self>, stage: String, task: Task) {
+        let shared = self.clone();
+        self.pool.spawn(move || {
+            let result = panic::catch_unwind(AssertUnwindSafe(|| block_on(task())));
+            shared.finish(stage, result.is_err());
+        });
+    }
+    fn finish(self: &Arc<Self>, stage: String, panicked: bool) {
+        let next = {
+            let mut stages = self.stages.lock().unwrap();
+            let state = stages.get_mut(&stage).unwrap();
+            let next = state.pending.pop_front();
+            if next.is_none() {
+                state.running -= 1;
+            }
+            next
+        };
+        if let Some(task) = next {
+            self.launch(stage, task);
+        }
+        let mut progress = self.progress.lock().unwrap();
+        progress.outstanding -= 1;
+        progress.panicked += panicked as usize;
+        if progress.outstanding == 0 {
+            self.done.notify_all();
+        }
+    }
+}
+
+/// ThreadPoolExecutor
+/// An executor spreading pipeline stages over a work stealing thread pool
+///
+/// sources and pipes are not thread safe so every task is a closure building its part of
+/// the pipeline on the worker thread that runs it, stages running on different threads
+/// are connected with `runtime::channel`. Tasks waiting on a channel occupy their thread,
+/// so the pool needs at least as many threads as tasks communicating with each other.
+pub struct ThreadPoolExecutor {
+    shared: Arc<Shared>,
+}
+
+impl ThreadPoolExecutor {
+    /// constructor with the given number of worker threads (0 picks one per core)
+    pub fn new(threads: usize) -> Result<Self, &'static str> {
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(threads)
+            .build()
+            .map_err(|_| "Failure to build thread pool")?;
+        Ok(Self {
+            shared: Arc::new(Shared {
+                pool,
+                stages: Mutex::new(HashMap::new()),
+                progress: Mutex::new(Progress::default()),
+                done: Condvar::new(),
+                memory: MemoryTracker::new(),
+            }),
+        })
+    }
+    /// number of worker threads
+    pub fn threads(&self) -> usize {
+        self.shared.pool.current_num_threads()
+    }
+    /// the account of the memory held by the buffers of the stages, shared with the
+    /// channels and buffers that track their items with it
+    pub fn memory(&self) -> MemoryTracker {
+        self.shared.memory.clone()
+    }
+    /// limit the number of tasks of a stage running at the same time (None lifts the limit)
+    pub fn set_stage_limit(&self, stage: &str, limit: Option<usize>) -> Result<(), &'static str> {
+        if limit == Some(0) {
+            return Err("Stage limit must be strictly positive");
+        }
+        let mut stages = self.shared.stages.lock().unwrap();
+        stages.entry(stage.to_string()).or_default().limit = limit;
+        Ok(())
+    }
+    /// run a task as part of a stage, the closure is called on the worker thread
+    pub fn spawn<F, Fut>(&self, stage: &str, task: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        let task: Task = Box::new(move || task().boxed_local());
+        self.shared.progress.lock().unwrap().outstanding += 1;
+        let launch = {
+            let mut stages = self.shared.stages.lock().unwrap();
+            let state = stages.entry(stage.to_string()).or_default();
+            if state.limit.is_none_or(|limit| state.running < limit) {
+                state.running += 1;
+                Some(task)
+            } else {
+                state.pending.push_back(task);
+                None
+            }
+        };
+        if let Some(task) = launch {
+            self.shared.launch(stage.to_string(), task);
+        }
+    }
+    /// block until every spawned task has completed (returns an error if any panicked)
+    pub fn wait(&self) -> Result<(), &'static str> {
+        let mut progress = self.shared.progress.lock().unwrap();
+        while progress.outstanding > 0 {
+            progress = self.shared.done.wait(progress).unwrap();
+        }
+        let panicked = progress.panicked;
+        progress.panicked = 0;
+        if panicked > 0 {
+            Err("A pipeline task panicked")
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl Executor for ThreadPoolExecutor {
+    fn spawn_task(&self, name: &str, task: Task) {
+        self.spawn(name, task);
+    }
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        thread_sleep(duration)
+    }
+    fn wait(&self) -> Result<(), &'static str> {
+        ThreadPoolExecutor::wait(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::{DataBlob, MetaData};
+    use crate::runtime::channel;
+    use crate::Source;
+    use futures::StreamExt;
+    use std::pin::Pin;
+    use std::rc::Rc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn test_pipelined_stages() {
+        let executor = ThreadPoolExecutor::new(2).unwrap();
+        let (sender, source) = channel::<u32>(4);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        executor.spawn("produce", move || async move {
+            let input = Rc::new(DataBlob::new((0..100).collect(), MetaData::default()));
+            sender.forward(input).await.unwrap();
+        });
+        let sink = received.clone();
+        executor.spawn("consume", move || async move {
+            let items: Vec<u32> = Pin::from(source.stream()).collect().await;
+            *sink.lock().unwrap() = items;
+        });
+        executor.wait().unwrap();
+        assert_eq!(*received.lock().unwrap(), (0..100).collect::<Vec<u32>>());
+    }
+
+    #[test]
+    fn test_memory_aggregation() {
+        let executor = ThreadPoolExecutor::new(2).unwrap();
+        let memory = executor.memory();
+        let (sender, source) = channel::<DataBlob<u64>>(8);
+        let sender = sender.track(&memory, "produce");
+        executor.spawn("produce", move || async move {
+            let blobs = (0..4).map(|_| DataBlob::new(vec![0; 256], MetaData::default()));
+            let input = Rc::new(crate::testing::MockSource::new().items(blobs));
+            sender.forward(input).await.unwrap();
+        });
+        executor.wait().unwrap();
+        let held = executor.memory().stage("produce").live;
+        assert!(held >= 4 * 256 * 8, "Queued blobs not accounted for");
+        executor.spawn("consume", move || async move {
+            Pin::from(source.stream()).for_each(|_| async {}).await;
+        });
+        executor.wait().unwrap();
+        assert_eq!(memory.live(), 0);
+        assert_eq!(memory.peak(), held);
+    }
+
+    #[test]
+    fn test_stage_limit() {
+        let executor = ThreadPoolExecutor::new(4).unwrap();
+        executor.set_stage_limit("heavy", Some(2)).unwrap();
+        assert!(executor.set_stage_limit("heavy", Some(0)).is_err());
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        for _ in 0..8 {
+            let (running, peak) = (running.clone(), peak.clone());
+            executor.spawn("heavy", move || async move {
+                let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                peak.fetch_max(now, Ordering::SeqCst);
+                std::thread::sleep(std::time::Duration::from_millis(5));
+                running.fetch_sub(1, Ordering::SeqCst);
+            });
+        }
+        executor.wait().unwrap();
+        assert!(peak.load(Ordering::SeqCst) <= 2, "Stage limit exceeded");
+    }
+
+    #[test]
+    fn test_panicking_task() {
+        let executor = ThreadPoolExecutor::new(1).unwrap();
+        executor.spawn("broken", || async { panic!("expected failure") });
+        assert!(executor.wait().is_err(), "Panic went unreported");
+        executor.spawn("fine", || async {});
+        assert!(executor.wait().is_ok(), "Panic reported twice");
+    }
+}
@@ -0,0 +1,254 @@
+//! tokio
+//!
+//! Running pipelines inside a tokio runtime (requires the `tokio` feature)
+
+use super::{Executor, Task};
+use crate::pipeline::{Pipeline, Shutdown, ShutdownTrigger};
+use crate::Source;
+use futures::executor::block_on;
+use futures::future::BoxFuture;
+use futures::stream;
+use futures::{FutureExt, Stream, StreamExt};
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::Mutex;
+use std::time::Duration;
+use tokio::runtime::Handle;
+use tokio::sync::mpsc;
+use tokio::task::{JoinHandle, LocalSet};
+
+/// TokioSender
+/// The sending half of a tokio stage channel
+pub struct TokioSender<T> {
+    sender: mpsc::Sender<T>,
+}
+
+impl<T> Clone for TokioSender<T> {
+    fn clone(&self) -> Self {
+        Self {
+            sender: self.sender.clone(),
+        }
+    }
+}
+
+impl<T: 'static> TokioSender<T> {
+    /// push every item of the source into the channel (returns an error if the receiving
+    /// half was dropped)
+    pub async fn forward(self, input: Rc<dyn Source<T>>) -> Result<(), &'static str> {
+        let mut items = Pin::from(input.stream());
+        while let Some(item) = items.next().await {
+            self.send(item).await?;
+        }
+        Ok(())
+    }
+    /// push a single item into the channel
+    pub async fn send(&self, item: T) -> Result<(), &'static str> {
+        self.sender
+            .send(item)
+            .await
+            .map_err(|_| "Channel receiver was dropped")
+    }
+}
+
+/// TokioSource
+/// The receiving half of a tokio stage channel
+///
+/// a channel can only be drained once, streams requested after the first one are empty
+pub struct TokioSource<T> {
+    receiver: RefCell<Option<mpsc::Receiver<T>>>,
+}
+
+impl<T: 'static> Source<T> for TokioSource<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        match self.receiver.borrow_mut().take() {
+            Some(receiver) => Box::new(stream::unfold(receiver, |mut receiver| async move {
+                receiver.recv().await.map(|item| (item, receiver))
+            })),
+            None => Box::new(stream::empty()),
+        }
+    }
+}
+
+/// create a tokio channel holding up to `buffer` items in flight
+pub fn channel<T>(buffer: usize) -> (TokioSender<T>, TokioSource<T>) {
+    let (sender, receiver) = mpsc::channel(buffer.max(1));
+    (
+        TokioSender { sender },
+        TokioSource {
+            receiver: RefCell::new(Some(receiver)),
+        },
+    )
+}
+
+/// PipelineHandle
+/// A pipeline running on a tokio runtime
+pub struct PipelineHandle {
+    trigger: ShutdownTrigger,
+    tasks: Vec<(String, JoinHandle<()>)>,
+}
+
+impl PipelineHandle {
+    /// ask every stage to wind down
+    pub fn shutdown(&mut self) {
+        self.trigger.trigger();
+    }
+    /// wait for every stage to complete (returns an error naming the first stage that
+    /// panicked)
+    pub async fn join(self) -> Result<(), String> {
+        let mut result = Ok(());
+        for (name, task) in self.tasks.into_iter() {
+            if task.await.is_err() && result.is_ok() {
+                result = Err(format!("Stage {} panicked", name));
+            }
+        }
+        result
+    }
+    /// request shutdown and wait for every stage to complete
+    pub async fn shutdown_and_join(mut self) -> Result<(), String> {
+        self.shutdown();
+        self.join().await
+    }
+}
+
+impl Pipeline {
+    /// spawn every stage as a task of the runtime behind the handle
+    ///
+    /// stages are not `Send`, so each one is driven by a `LocalSet` on a blocking thread
+    /// of the runtime where tokio timers, io and channels are available
+    pub fn run_on(self, handle: &Handle) -> PipelineHandle {
+        let (trigger, shutdown) = Shutdown::with_pause(self.pause);
+        let tasks = self
+            .stages
+            .into_iter()
+            .map(|(name, stage)| {
+                let shutdown: Shutdown = shutdown.clone();
+                let runtime = handle.clone();
+                let task = handle.spawn_blocking(move || {
+                    runtime.block_on(LocalSet::new().run_until(stage(shutdown)));
+                });
+                (name, task)
+            })
+            .collect();
+        PipelineHandle { trigger, tasks }
+    }
+}
+
+/// TokioExecutor
+/// An executor spawning tasks on a tokio runtime, each driven by a `LocalSet` on a
+/// blocking thread of the runtime
+///
+/// `wait` blocks the calling thread and must not be called from within the runtime
+pub struct TokioExecutor {
+    handle: Handle,
+    tasks: Mutex<Vec<JoinHandle<()>>>,
+}
+
+impl TokioExecutor {
+    /// constructor
+    pub fn new(handle: Handle) -> Self {
+        Self {
+            handle,
+            tasks: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+impl Executor for TokioExecutor {
+    fn spawn_task(&self, _name: &str, task: Task) {
+        let runtime = self.handle.clone();
+        let handle = self.handle.spawn_blocking(move || {
+            runtime.block_on(LocalSet::new().run_until(task()));
+        });
+        self.tasks.lock().unwrap().push(handle);
+    }
+    fn sleep(&self, duration: Duration) -> BoxFuture<'static, ()> {
+        let _context = self.handle.enter();
+        tokio::time::sleep(duration).boxed()
+    }
+    fn wait(&self) -> Result<(), &'static str> {
+        let tasks: Vec<JoinHandle<()>> = self.tasks.lock().unwrap().drain(..).collect();
+        let mut result = Ok(());
+        for task in tasks {
+            if block_on(task).is_err() {
+                result = Err("A pipeline task panicked");
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_bucket::{DataBlob, MetaData};
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    #[test]
+    fn test_run_on_tokio() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let (sender, source) = channel::<i32>(2);
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let sink = received.clone();
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .add_stage("produce", move |_| async move {
+                let input = Rc::new(DataBlob::new((0..50).collect(), MetaData::default()));
+                sender.forward(input).await.unwrap();
+            })
+            .unwrap();
+        pipeline
+            .add_stage("consume", move |_| async move {
+                let items: Vec<i32> = Pin::from(source.stream()).collect().await;
+                *sink.lock().unwrap() = items;
+            })
+            .unwrap();
+        let handle = pipeline.run_on(runtime.handle());
+        runtime.block_on(handle.join()).unwrap();
+        assert_eq!(*received.lock().unwrap(), (0..50).collect::<Vec<i32>>());
+    }
+
+    #[test]
+    fn test_graceful_shutdown() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let mut pipeline = Pipeline::new();
+        pipeline
+            .add_stage("ticker", |shutdown| async move {
+                let ticks = stream::unfold((), |_| async {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                    Some(((), ()))
+                });
+                shutdown.guard(ticks).for_each(|_| async {}).await;
+            })
+            .unwrap();
+        let handle = pipeline.run_on(runtime.handle());
+        runtime
+            .block_on(async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                handle.shutdown_and_join().await
+            })
+            .unwrap();
+    }
+
+    #[test]
+    fn test_tokio_executor() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let executor = TokioExecutor::new(runtime.handle().clone());
+        let slept = Arc::new(Mutex::new(false));
+        let flag = slept.clone();
+        let nap = executor.sleep(Duration::from_millis(2));
+        executor.spawn_task(
+            "nap",
+            Box::new(move || {
+                async move {
+                    nap.await;
+                    *flag.lock().unwrap() = true;
+                }
+                .boxed_local()
+            }),
+        );
+        executor.wait().unwrap();
+        assert!(*slept.lock().unwrap(), "Task did not run");
+    }
+}
@@ -0,0 +1,24 @@
+//! sinks
+//!
+//! Elements draining data streams at the end of a pipeline
+
+/// assembler
+/// Sub module for building DataBuckets out of streams
+pub mod assembler;
+
+/// log
+/// Sub module for logging the items of streams
+pub mod log;
+
+/// sse
+/// Sub module for serving items as server-sent events
+pub mod sse;
+
+/// transactional
+/// Sub module for sinks committing their output with checkpoints
+pub mod transactional;
+
+pub use assembler::AssemblerSink;
+pub use log::{LogFormat, LogSink};
+pub use sse::SseSink;
+pub use transactional::{ExactlyOnceSink, TransactionalSink};
@@ -0,0 +1,249 @@
+//! assembler
+//!
+//! Draining named typed streams back into a DataBucket
+
+use crate::data_bucket::{
+    BlobType, DataBlob, DataBucket, DataBucketBlob, Link, LinkType, MetaData,
+};
+use crate::{Sink, Source};
+use futures::future::{join_all, LocalBoxFuture};
+use futures::StreamExt;
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+
+type BlobFuture = LocalBoxFuture<'static, DataBucketBlob>;
+type BlobDrain = Rc<dyn Fn(MetaData) -> BlobFuture>;
+
+/// AssemblerSink
+/// A sink draining several named typed streams into the blobs of a DataBucket
+///
+/// the dimensions of every blob are inferred from the observed item counts and the
+/// unitary dimensions of its meta data. As a `Sink` of blobs, the blobs of the piped input
+/// are added as they are next to the typed inputs and draining keeps the bucket for
+/// `take_bucket`
+pub struct AssemblerSink {
+    inputs: Vec<BlobDrain>,
+    metas: Vec<MetaData>,
+    bucket: Rc<RefCell<Option<DataBucket>>>,
+    input: Option<Rc<dyn Source<DataBucketBlob>>>,
+}
+
+impl AssemblerSink {
+    /// empty constructor
+    pub fn new() -> Self {
+        Self {
+            inputs: Vec::new(),
+            metas: Vec::new(),
+            bucket: Rc::new(RefCell::new(None)),
+            input: None,
+        }
+    }
+    /// connect a source that will be drained into a blob with the given name
+    pub fn add_input<T: BlobType + 'static>(
+        &mut self,
+        name: &str,
+        input: Rc<dyn Source<T>>,
+    ) -> Result<(), &'static str> {
+        let meta = MetaData {
+            name: name.to_string(),
+            unitary_dimensions: vec![1],
+            ..Default::default()
+        };
+        self.add_input_with_meta(meta, input)
+    }
+    /// connect a source that will be drained into a blob described by the given meta data
+    /// (its dimensions are overwritten when assembling)
+    pub fn add_input_with_meta<T: BlobType + 'static>(
+        &mut self,
+        meta: MetaData,
+        input: Rc<dyn Source<T>>,
+    ) -> Result<(), &'static str> {
+        if self.metas.iter().any(|other| other.name == meta.name) {
+            return Err("An input with this name already exists");
+        }
+        let drain = move |meta: MetaData| -> BlobFuture {
+            let stream = Pin::from(input.stream());
+            Box::pin(async move {
+                let data: Vec<T> = stream.collect().await;
+                T::wrap(DataBlob::new(data, meta))
+            })
+        };
+        self.inputs.push(Rc::new(drain));
+        self.metas.push(meta);
+        Ok(())
+    }
+    /// declare that each unit of the `linker` input corresponds to one unit of `linkee`
+    pub fn link(&mut self, linker: &str, linkee: &str) -> Result<(), &'static str> {
+        if !self.metas.iter().any(|meta| meta.name == linkee) {
+            return Err("Unknown linkee input");
+        }
+        let meta = self
+            .metas
+            .iter_mut()
+            .find(|meta| meta.name == linker)
+            .ok_or("Unknown linker input")?;
+        meta.links.push(Link {
+            nature: LinkType::OneToOne,
+            linker: linker.to_string(),
+            linkee: linkee.to_string(),
+        });
+        Ok(())
+    }
+    /// drain every input concurrently and build the resulting bucket (returns an error if
+    /// `OneToOne` linked blobs end up with different unit counts or a piped blob takes the
+    /// name of another blob)
+    pub async fn assemble(&self) -> Result<DataBucket, &'static str> {
+        self.assembly().await
+    }
+    /// the bucket assembled by the last drain of the sink, if it succeeded
+    pub fn take_bucket(&self) -> Option<DataBucket> {
+        self.bucket.take()
+    }
+    fn assembly(&self) -> LocalBoxFuture<'static, Result<DataBucket, &'static str>> {
+        let drains: Vec<BlobFuture> = self
+            .inputs
+            .iter()
+            .zip(self.metas.iter())
+            .map(|(drain, meta)| drain(meta.clone()))
+            .collect();
+        let piped = self.input.as_ref().map(|input| Pin::from(input.stream()));
+        Box::pin(async move {
+            let blobs = join_all(drains).await;
+            let mut bucket = DataBucket::new();
+            for mut blob in blobs.into_iter() {
+                let count = blob.unit_count();
+                let meta = blob.get_mut_meta_data();
+                meta.dimensions = vec![count];
+                if meta.unitary_dimensions.iter().product::<usize>() > 1 {
+                    meta.dimensions.extend(meta.unitary_dimensions.clone());
+                }
+                meta.record("assemble", &[("units", count.to_string())]);
+                bucket.add_blob(blob);
+            }
+            if let Some(mut piped) = piped {
+                while let Some(blob) = piped.next().await {
+                    if bucket.add_blob(blob).is_some() {
+                        return Err("Piped blob name already taken in bucket");
+                    }
+                }
+            }
+            for (name, blob) in bucket.iter() {
+                for link in blob.get_meta_data().links.iter() {
+                    let linkee = bucket
+                        .get_blob(&link.linkee)
+                        .ok_or("Linked blob missing from bucket")?;
+                    if matches!(link.nature, LinkType::OneToOne)
+                        && link.linker == *name
+                        && linkee.unit_count() != blob.unit_count()
+                    {
+                        return Err("OneToOne linked blobs have different unit counts");
+                    }
+                }
+            }
+            Ok(bucket)
+        })
+    }
+}
+
+impl Sink<DataBucketBlob> for AssemblerSink {
+    input_connection!(DataBucketBlob);
+    fn drain(&self) -> LocalBoxFuture<'static, Result<(), &'static str>> {
+        let (assembly, bucket) = (self.assembly(), self.bucket.clone());
+        Box::pin(async move {
+            bucket.replace(Some(assembly.await?));
+            Ok(())
+        })
+    }
+}
+
+impl Default for AssemblerSink {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    fn make_blob<T>(name: &str, data: Vec<T>, unit: usize) -> Rc<DataBlob<T>> {
+        let meta = MetaData {
+            name: name.to_string(),
+            unitary_dimensions: vec![unit],
+            ..Default::default()
+        };
+        Rc::new(DataBlob::new(data, meta))
+    }
+
+    #[test]
+    fn test_assemble_bucket() {
+        let mut sink = AssemblerSink::new();
+        sink.add_input::<f64>("time", make_blob("t", vec![0.0, 0.5, 1.0], 1))
+            .unwrap();
+        let pos = make_blob("p", vec![0, 0, 1, 1, 2, 2], 2);
+        let meta = pos.get_meta_data().clone();
+        sink.add_input_with_meta::<i32>(
+            MetaData {
+                name: "pos".to_string(),
+                ..meta
+            },
+            pos,
+        )
+        .unwrap();
+        sink.link("pos", "time").unwrap();
+        assert!(sink.link("pos", "missing").is_err(), "Linked unknown input");
+        let bucket = block_on(sink.assemble()).unwrap();
+        assert_eq!(bucket.len(), 2);
+        let pos = i32::unwrap_ref(bucket.get_blob(&"pos".to_string()).unwrap()).unwrap();
+        assert_eq!(pos.get_meta_data().dimensions, [3, 2]);
+        assert_eq!(pos.get_meta_data().links[0].linkee, "time");
+        let time = f64::unwrap_ref(bucket.get_blob(&"time".to_string()).unwrap()).unwrap();
+        assert_eq!(time.get_data(), &vec![0.0, 0.5, 1.0]);
+        assert_eq!(time.get_meta_data().dimensions, [3]);
+    }
+
+    #[test]
+    fn test_assemble_mismatched_links() {
+        let mut sink = AssemblerSink::new();
+        sink.add_input::<i8>("a", make_blob("a", vec![0, 1], 1))
+            .unwrap();
+        sink.add_input::<i8>("b", make_blob("b", vec![0], 1))
+            .unwrap();
+        assert!(sink
+            .add_input::<i8>("b", make_blob("b", vec![0], 1))
+            .is_err());
+        sink.link("b", "a").unwrap();
+        assert!(
+            block_on(sink.assemble()).is_err(),
+            "Assembled mismatched links"
+        );
+    }
+
+    #[test]
+    fn test_drain_piped_blobs() {
+        let mut sink = AssemblerSink::new();
+        sink.add_input::<u8>("a", make_blob("a", vec![0, 1], 1))
+            .unwrap();
+        let piped = DataBucketBlob::Float32((*make_blob("b", vec![2.5_f32], 1)).clone());
+        sink.pipe(Rc::new(
+            crate::testing::MockSource::new().items([piped.clone()]),
+        ))
+        .unwrap();
+        block_on(sink.drain()).unwrap();
+        let bucket = sink.take_bucket().unwrap();
+        assert!(bucket.contains("a") && bucket.contains("b"));
+        assert!(sink.take_bucket().is_none());
+        let renamed = MetaData {
+            name: "a".to_string(),
+            ..piped.get_meta_data().clone()
+        };
+        let mut clash = piped;
+        *clash.get_mut_meta_data() = renamed;
+        sink.pipe(Rc::new(crate::testing::MockSource::new().items([clash])))
+            .unwrap();
+        assert!(block_on(sink.drain()).is_err(), "Blob name clash drained");
+        assert!(sink.take_bucket().is_none());
+    }
+}
@@ -0,0 +1,252 @@
+//! log
+//!
+//! Watching the items flowing out of a pipeline as structured log records
+//!
+//! records are written one per line (or per block for the pretty format) to stderr, to a
+//! given writer or to the `log` facade, every record carries its level, the name of the
+//! sink and the index of the item in the stream
+
+use crate::Source;
+use futures::future::LocalBoxFuture;
+use futures::StreamExt;
+use std::cell::RefCell;
+use std::fmt::{Debug, Write as _};
+use std::io::Write;
+use std::pin::Pin;
+use std::rc::Rc;
+
+pub use ::log::Level;
+
+/// LogFormat
+/// The layout of log records
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LogFormat {
+    /// a header line followed by the item laid out over several lines
+    Pretty,
+    /// a JSON object per line
+    Json,
+    /// a single line per item
+    Compact,
+}
+
+type Render<T> = Rc<dyn Fn(&T, LogFormat) -> String>;
+
+#[derive(Clone)]
+enum Output {
+    Writer(Rc<RefCell<dyn Write>>),
+    Facade,
+}
+
+/// LogSink
+/// A sink logging the items it drains, keeping one in every so many with a sampling rate
+///
+/// sampling is deterministic, a rate of 0.25 logs the fourth, eighth, twelfth... items
+pub struct LogSink<T> {
+    name: String,
+    format: LogFormat,
+    level: Level,
+    rate: f64,
+    render: Render<T>,
+    output: Output,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T: 'static> LogSink<T> {
+    fn with_render(name: &str, render: Render<T>) -> Self {
+        Self {
+            name: name.to_string(),
+            format: LogFormat::Compact,
+            level: Level::Info,
+            rate: 1.0,
+            render,
+            output: Output::Writer(Rc::new(RefCell::new(std::io::stderr()))),
+            input: None,
+        }
+    }
+    /// set the layout of records (compact by default)
+    pub fn format(mut self, format: LogFormat) -> Self {
+        self.format = format;
+        self
+    }
+    /// set the level of records (info by default)
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = level;
+        self
+    }
+    /// log this fraction of the items, clamped to [0, 1] (all of them by default)
+    pub fn sample(mut self, rate: f64) -> Self {
+        self.rate = rate.clamp(0.0, 1.0);
+        self
+    }
+    /// write records to a writer instead of stderr
+    pub fn to_writer<W: Write + 'static>(mut self, writer: Rc<RefCell<W>>) -> Self {
+        self.output = Output::Writer(writer);
+        self
+    }
+    /// hand records to the `log` facade with the name of the sink as target, so they go
+    /// wherever the logger installed by the application sends them
+    pub fn to_log(mut self) -> Self {
+        self.output = Output::Facade;
+        self
+    }
+}
+
+impl<T: Debug + 'static> LogSink<T> {
+    /// constructor for items formatted with Debug (as a JSON string in the JSON format)
+    pub fn new(name: &str) -> Self {
+        Self::with_render(
+            name,
+            Rc::new(|item: &T, format| match format {
+                LogFormat::Pretty => format!("{:#?}", item),
+                LogFormat::Json => json_string(&format!("{:?}", item)),
+                LogFormat::Compact => format!("{:?}", item),
+            }),
+        )
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize + 'static> LogSink<T> {
+    /// constructor for items formatted with Serialize (as JSON in every format)
+    pub fn serialized(name: &str) -> Self {
+        Self::with_render(
+            name,
+            Rc::new(|item: &T, format| {
+                let json = match format {
+                    LogFormat::Pretty => serde_json::to_string_pretty(item),
+                    _ => serde_json::to_string(item),
+                };
+                json.unwrap_or_else(|error| json_string(&error.to_string()))
+            }),
+        )
+    }
+}
+
+/// a text as a quoted JSON string
+pub(crate) fn json_string(text: &str) -> String {
+    let mut quoted = String::with_capacity(text.len() + 2);
+    quoted.push('"');
+    for character in text.chars() {
+        match character {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            control if control < ' ' => {
+                let _ = write!(quoted, "\\u{:04x}", control as u32);
+            }
+            other => quoted.push(other),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn record(name: &str, level: Level, index: u64, format: LogFormat, item: String) -> String {
+    match format {
+        LogFormat::Pretty => format!("[{} {} #{}]\n{}", level, name, index, item),
+        LogFormat::Json => format!(
+            "{{\"level\":\"{}\",\"sink\":{},\"index\":{},\"item\":{}}}",
+            level,
+            json_string(name),
+            index,
+            item
+        ),
+        LogFormat::Compact => format!("[{} {} #{}] {}", level, name, index, item),
+    }
+}
+
+impl<T: 'static> crate::Sink<T> for LogSink<T> {
+    input_connection!(T);
+    fn drain(&self) -> LocalBoxFuture<'static, Result<(), &'static str>> {
+        let input = self.input.clone();
+        let (name, format, level, rate) = (self.name.clone(), self.format, self.level, self.rate);
+        let (render, output) = (self.render.clone(), self.output.clone());
+        Box::pin(async move {
+            let mut stream = Pin::from(input.ok_or("Sink has no input")?.stream());
+            let (mut index, mut credit) = (0, 0.0);
+            while let Some(item) = stream.next().await {
+                credit += rate;
+                if credit >= 1.0 {
+                    credit -= 1.0;
+                    let line = record(&name, level, index, format, render(&item, format));
+                    match &output {
+                        Output::Writer(writer) => writeln!(writer.borrow_mut(), "{}", line)
+                            .map_err(|_| "Failure to write log record")?,
+                        Output::Facade => ::log::log!(target: &name, level, "{}", line),
+                    }
+                }
+                index += 1;
+            }
+            if let Output::Writer(writer) = &output {
+                writer
+                    .borrow_mut()
+                    .flush()
+                    .map_err(|_| "Failure to write log record")?;
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use crate::Sink;
+    use futures::executor::block_on;
+
+    fn logged<T: Clone + 'static>(mut sink: LogSink<T>, items: Vec<T>) -> String {
+        let output = Rc::new(RefCell::new(Vec::new()));
+        sink = sink.to_writer(output.clone());
+        sink.pipe(Rc::new(MockSource::new().items(items))).unwrap();
+        block_on(sink.drain()).unwrap();
+        let text = String::from_utf8(output.borrow().clone()).unwrap();
+        text
+    }
+
+    #[test]
+    fn test_formats() {
+        let compact = logged(LogSink::new("parse"), vec![(1, "a")]);
+        assert_eq!(compact, "[INFO parse #0] (1, \"a\")\n");
+        let json = logged(
+            LogSink::new("parse")
+                .format(LogFormat::Json)
+                .level(Level::Warn),
+            vec!["say \"hi\"\n"],
+        );
+        assert_eq!(
+            json,
+            "{\"level\":\"WARN\",\"sink\":\"parse\",\"index\":0,\"item\":\"\\\"say \\\\\\\"hi\\\\\\\"\\\\n\\\"\"}\n"
+        );
+        let pretty = logged(
+            LogSink::new("parse").format(LogFormat::Pretty),
+            vec![(1, 2)],
+        );
+        assert_eq!(pretty, "[INFO parse #0]\n(\n    1,\n    2,\n)\n");
+    }
+
+    #[test]
+    fn test_sampling() {
+        let text = logged(LogSink::new("sampled").sample(0.25), (0..10).collect());
+        let indexes: Vec<&str> = text.lines().map(|line| &line[14..]).collect();
+        assert_eq!(indexes, vec!["#3] 3", "#7] 7"]);
+        assert!(logged(LogSink::new("none").sample(0.0), vec![1, 2]).is_empty());
+        let unconnected = LogSink::<u8>::new("unconnected");
+        assert_eq!(block_on(unconnected.drain()), Err("Sink has no input"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn test_serialized() {
+        let json = logged(
+            LogSink::serialized("values").format(LogFormat::Json),
+            vec![vec![1, 2]],
+        );
+        assert_eq!(
+            json,
+            "{\"level\":\"INFO\",\"sink\":\"values\",\"index\":0,\"item\":[1,2]}\n"
+        );
+    }
+}
@@ -0,0 +1,200 @@
+//! sse
+//!
+//! Pushing the items leaving a pipeline to browsers as server-sent events
+//!
+//! the sink serves an event stream at `/events` from a background thread, every item
+//! drained is sent to the clients connected at that moment (clients connecting later only
+//! receive the items that follow). Slow or gone clients are disconnected rather than
+//! holding the pipeline back
+
+use crate::http;
+use crate::{Sink, Source};
+use futures::future::LocalBoxFuture;
+use futures::StreamExt;
+use std::fmt::Display;
+use std::io::Write;
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+/// path of the event stream
+pub const EVENTS_PATH: &str = "/events";
+
+/// how long a client may take to accept an event before being disconnected
+const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+type Clients = Arc<Mutex<Vec<TcpStream>>>;
+
+/// SseSink
+/// A sink serving the items it drains as server-sent events, stopped when dropped
+///
+/// each event carries the index of the item in the stream as `id` and the formatted item
+/// as `data`, with an optional `event` name for clients listening to several kinds
+pub struct SseSink<T> {
+    server: http::Server,
+    clients: Clients,
+    format: Rc<dyn Fn(&T) -> String>,
+    event: Option<String>,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T: Display + 'static> SseSink<T> {
+    /// start serving events on an address (port 0 picks a free port), items formatted with
+    /// Display
+    pub fn bind<A: ToSocketAddrs>(address: A) -> Result<Self, &'static str> {
+        let clients: Clients = Arc::new(Mutex::new(Vec::new()));
+        let accepted = clients.clone();
+        let server =
+            http::Server::bind(address, "Failure to bind event server", move |connection| {
+                if let Ok(client) = subscribe(connection) {
+                    accepted.lock().unwrap().push(client);
+                }
+            })?;
+        Ok(Self {
+            server,
+            clients,
+            format: Rc::new(T::to_string),
+            event: None,
+            input: None,
+        })
+    }
+}
+
+impl<T> SseSink<T> {
+    /// format items with a function instead (such as a JSON serializer)
+    pub fn with_format<F: Fn(&T) -> String + 'static>(mut self, format: F) -> Self {
+        self.format = Rc::new(format);
+        self
+    }
+    /// name the events
+    pub fn with_event(mut self, event: &str) -> Self {
+        self.event = Some(event.to_string());
+        self
+    }
+    /// the address the sink listens on
+    pub fn local_addr(&self) -> SocketAddr {
+        self.server.local_addr()
+    }
+    /// number of connected clients
+    pub fn clients(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+}
+
+/// answer a request, keeping the connection if it subscribes to the events
+fn subscribe(mut connection: TcpStream) -> std::io::Result<TcpStream> {
+    let (method, path) = http::read_request(&mut connection)?;
+    if method != "GET" || path.split('?').next() != Some(EVENTS_PATH) {
+        http::respond(&mut connection, "404 Not Found", "text/plain", "")?;
+        return Err(std::io::ErrorKind::NotFound.into());
+    }
+    connection.set_write_timeout(Some(WRITE_TIMEOUT))?;
+    connection.write_all(
+        b"HTTP/1.1 200 OK\r\nContent-Type: text/event-stream\r\nCache-Control: no-cache\r\n\
+          Access-Control-Allow-Origin: *\r\nConnection: keep-alive\r\n\r\n",
+    )?;
+    connection.flush()?;
+    Ok(connection)
+}
+
+fn event(index: u64, name: Option<&str>, data: &str) -> String {
+    let mut event = format!("id: {}\n", index);
+    if let Some(name) = name {
+        event.push_str(&format!("event: {}\n", name));
+    }
+    for line in data.lines() {
+        event.push_str(&format!("data: {}\n", line));
+    }
+    if data.is_empty() {
+        event.push_str("data: \n");
+    }
+    event.push('\n');
+    event
+}
+
+impl<T: 'static> Sink<T> for SseSink<T> {
+    input_connection!(T);
+    fn drain(&self) -> LocalBoxFuture<'static, Result<(), &'static str>> {
+        let input = self.input.clone();
+        let (clients, format, name) = (
+            self.clients.clone(),
+            self.format.clone(),
+            self.event.clone(),
+        );
+        Box::pin(async move {
+            let mut stream = Pin::from(input.ok_or("Sink has no input")?.stream());
+            let mut index = 0;
+            while let Some(item) = stream.next().await {
+                let event = event(index, name.as_deref(), &format(&item));
+                clients
+                    .lock()
+                    .unwrap()
+                    .retain_mut(|client| client.write_all(event.as_bytes()).is_ok());
+                index += 1;
+            }
+            // closing the connections tells clients the stream ended
+            clients.lock().unwrap().clear();
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+    use std::io::Read;
+    use std::time::Instant;
+
+    fn connect(sink: &SseSink<String>, path: &str) -> TcpStream {
+        let mut client = TcpStream::connect(sink.local_addr()).unwrap();
+        write!(
+            client,
+            "GET {} HTTP/1.1\r\nAccept: text/event-stream\r\n\r\n",
+            path
+        )
+        .unwrap();
+        client
+    }
+
+    #[test]
+    fn test_events_reach_clients() {
+        let mut sink = SseSink::bind("127.0.0.1:0").unwrap().with_event("reading");
+        let mut client = connect(&sink, "/events?since=0");
+        let start = Instant::now();
+        while sink.clients() == 0 {
+            assert!(
+                start.elapsed() < Duration::from_secs(5),
+                "Client not subscribed"
+            );
+            std::thread::sleep(Duration::from_millis(1));
+        }
+        let items = ["21.5", "two\nlines"].map(str::to_string);
+        sink.pipe(Rc::new(MockSource::new().items(items))).unwrap();
+        block_on(sink.drain()).unwrap();
+        assert_eq!(sink.clients(), 0, "Clients kept after the stream ended");
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        let (head, events) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(head.contains("Content-Type: text/event-stream"));
+        assert_eq!(
+            events,
+            "id: 0\nevent: reading\ndata: 21.5\n\nid: 1\nevent: reading\ndata: two\ndata: lines\n\n"
+        );
+    }
+
+    #[test]
+    fn test_unknown_path() {
+        let sink = SseSink::<String>::bind("127.0.0.1:0").unwrap();
+        let mut client = connect(&sink, "/favicon.ico");
+        let mut response = String::new();
+        client.read_to_string(&mut response).unwrap();
+        assert!(response.starts_with("HTTP/1.1 404"));
+        assert_eq!(sink.clients(), 0);
+        assert_eq!(event(3, None, ""), "id: 3\ndata: \n\n");
+    }
+}
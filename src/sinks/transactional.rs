@@ -0,0 +1,241 @@
+//! transactional
+//!
+//! Sinks writing their output in transactions committed along with checkpoints
+//!
+//! the items between two checkpoint barriers form a transaction named after the checkpoint
+//! preceding it (0 for the first one), at a barrier the open transaction is pre committed
+//! and the barrier acknowledged, the transaction is committed once every sink acknowledged
+//! the checkpoint and aborted if the checkpoint failed, so after a failure the output holds
+//! exactly the transactions of completed checkpoints
+
+use crate::checkpoint::{CheckpointCoordinator, CheckpointStatus, Checkpointed, FINAL_CHECKPOINT};
+use crate::{Sink, Source};
+use futures::future::LocalBoxFuture;
+use futures::StreamExt;
+use std::cell::RefCell;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// TransactionalSink
+/// The protocol of sinks able to stage their output and later commit or discard it
+pub trait TransactionalSink<T> {
+    /// open a transaction receiving the next items
+    fn begin(&mut self, transaction: u64) -> Result<(), &'static str>;
+    /// add an item to the open transaction
+    fn write(&mut self, item: T) -> Result<(), &'static str>;
+    /// durably stage a transaction so it can still be committed after a failure
+    fn pre_commit(&mut self, transaction: u64) -> Result<(), &'static str>;
+    /// make a pre committed transaction visible
+    fn commit(&mut self, transaction: u64) -> Result<(), &'static str>;
+    /// discard an open or pre committed transaction
+    fn abort(&mut self, transaction: u64);
+}
+
+/// ExactlyOnceSink
+/// A sink driving a transactional sink through the checkpoints of its input
+///
+/// the sink registers with the coordinator and waits for the checkpoints it pre committed
+/// to settle when its input ends, every sink registered with the coordinator must receive
+/// each barrier for checkpoints to complete
+pub struct ExactlyOnceSink<T, S> {
+    sink: Rc<RefCell<S>>,
+    coordinator: CheckpointCoordinator,
+    input: Option<Rc<dyn Source<Checkpointed<T>>>>,
+}
+
+impl<T, S> ExactlyOnceSink<T, S> {
+    /// constructor registering the sink with the coordinator
+    pub fn new(sink: S, coordinator: CheckpointCoordinator) -> Self {
+        coordinator.register();
+        Self {
+            sink: Rc::new(RefCell::new(sink)),
+            coordinator,
+            input: None,
+        }
+    }
+    /// the driven sink
+    pub fn inner(&self) -> Rc<RefCell<S>> {
+        self.sink.clone()
+    }
+}
+
+/// a pre committed transaction waiting on its checkpoint
+struct Staged {
+    checkpoint: u64,
+    transaction: u64,
+}
+
+/// commit or abort the staged transactions whose checkpoint settled
+fn settle<T, S: TransactionalSink<T>>(
+    sink: &RefCell<S>,
+    coordinator: &CheckpointCoordinator,
+    staged: &mut Vec<Staged>,
+) -> Result<(), &'static str> {
+    while let Some(first) = staged.first() {
+        match coordinator.status(first.checkpoint) {
+            CheckpointStatus::Pending => break,
+            CheckpointStatus::Completed => sink.borrow_mut().commit(first.transaction)?,
+            CheckpointStatus::Failed => sink.borrow_mut().abort(first.transaction),
+        }
+        staged.remove(0);
+    }
+    Ok(())
+}
+
+/// abort the open transaction and every staged one not committed yet
+fn fail<T, S: TransactionalSink<T>>(
+    sink: &RefCell<S>,
+    coordinator: &CheckpointCoordinator,
+    staged: Vec<Staged>,
+    open: u64,
+) {
+    let mut sink = sink.borrow_mut();
+    sink.abort(open);
+    coordinator.fail(FINAL_CHECKPOINT);
+    for transaction in staged {
+        match coordinator.status(transaction.checkpoint) {
+            CheckpointStatus::Completed if sink.commit(transaction.transaction).is_ok() => {}
+            _ => {
+                coordinator.fail(transaction.checkpoint);
+                sink.abort(transaction.transaction);
+            }
+        }
+    }
+}
+
+impl<T: 'static, S: TransactionalSink<T> + 'static> Sink<Checkpointed<T>>
+    for ExactlyOnceSink<T, S>
+{
+    input_connection!(Checkpointed<T>);
+    fn drain(&self) -> LocalBoxFuture<'static, Result<(), &'static str>> {
+        let input = self.input.clone();
+        let sink = self.sink.clone();
+        let coordinator = self.coordinator.clone();
+        Box::pin(async move {
+            let mut stream = Pin::from(input.ok_or("Sink has no input")?.stream());
+            let mut staged = Vec::new();
+            let mut open = 0;
+            sink.borrow_mut().begin(open)?;
+            while let Some(item) = stream.next().await {
+                let written = settle(&sink, &coordinator, &mut staged).and_then(|_| match item {
+                    Checkpointed::Item(item) => sink.borrow_mut().write(item),
+                    Checkpointed::Barrier(checkpoint) => {
+                        sink.borrow_mut().pre_commit(open)?;
+                        coordinator.acknowledge(checkpoint);
+                        staged.push(Staged {
+                            checkpoint,
+                            transaction: open,
+                        });
+                        open = checkpoint;
+                        sink.borrow_mut().begin(open)
+                    }
+                });
+                if let Err(failure) = written {
+                    fail(&sink, &coordinator, staged, open);
+                    return Err(failure);
+                }
+            }
+            if let Err(failure) = sink.borrow_mut().pre_commit(open) {
+                fail(&sink, &coordinator, staged, open);
+                return Err(failure);
+            }
+            coordinator.acknowledge(FINAL_CHECKPOINT);
+            staged.push(Staged {
+                checkpoint: FINAL_CHECKPOINT,
+                transaction: open,
+            });
+            for transaction in staged {
+                let status = coordinator.settled(transaction.checkpoint).await;
+                let mut sink = sink.borrow_mut();
+                match status {
+                    CheckpointStatus::Completed => sink.commit(transaction.transaction)?,
+                    _ => sink.abort(transaction.transaction),
+                }
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::checkpoint::BarrierPipe;
+    use crate::testing::MockSource;
+    use crate::Pipe;
+    use futures::executor::block_on;
+    use std::collections::BTreeMap;
+
+    /// a sink staging items in memory and failing on a poisoned item
+    #[derive(Default)]
+    struct StagingSink {
+        open: Vec<char>,
+        staged: BTreeMap<u64, Vec<char>>,
+        committed: Vec<char>,
+        aborted: Vec<u64>,
+    }
+
+    impl TransactionalSink<char> for StagingSink {
+        fn begin(&mut self, _transaction: u64) -> Result<(), &'static str> {
+            self.open.clear();
+            Ok(())
+        }
+        fn write(&mut self, item: char) -> Result<(), &'static str> {
+            if item == 'x' {
+                return Err("Poisoned item");
+            }
+            self.open.push(item);
+            Ok(())
+        }
+        fn pre_commit(&mut self, transaction: u64) -> Result<(), &'static str> {
+            self.staged
+                .insert(transaction, std::mem::take(&mut self.open));
+            Ok(())
+        }
+        fn commit(&mut self, transaction: u64) -> Result<(), &'static str> {
+            let items = self
+                .staged
+                .remove(&transaction)
+                .ok_or("Unknown transaction")?;
+            self.committed.extend(items);
+            Ok(())
+        }
+        fn abort(&mut self, transaction: u64) {
+            self.staged.remove(&transaction);
+            self.aborted.push(transaction);
+        }
+    }
+
+    fn run(items: &[char]) -> (Result<(), &'static str>, Rc<RefCell<StagingSink>>) {
+        let coordinator = CheckpointCoordinator::new();
+        let mut barriers = BarrierPipe::new(coordinator.clone())
+            .with_interval(2)
+            .unwrap();
+        barriers
+            .pipe(Rc::new(MockSource::new().items(items.to_vec())))
+            .unwrap();
+        let mut sink = ExactlyOnceSink::new(StagingSink::default(), coordinator);
+        sink.pipe(Rc::new(barriers)).unwrap();
+        (block_on(sink.drain()), sink.inner())
+    }
+
+    #[test]
+    fn test_transactions_commit_with_checkpoints() {
+        let (result, sink) = run(&['a', 'b', 'c', 'd', 'e']);
+        assert!(result.is_ok());
+        assert_eq!(sink.borrow().committed, vec!['a', 'b', 'c', 'd', 'e']);
+        assert!(sink.borrow().staged.is_empty(), "Transactions left staged");
+    }
+
+    #[test]
+    fn test_failure_aborts_uncommitted_transactions() {
+        let (result, sink) = run(&['a', 'b', 'c', 'x', 'd']);
+        assert_eq!(result, Err("Poisoned item"));
+        assert_eq!(
+            sink.borrow().committed,
+            vec!['a', 'b'],
+            "Output of a failed checkpoint committed"
+        );
+        assert_eq!(sink.borrow().aborted, vec![1]);
+    }
+}
@@ -0,0 +1,26 @@
+//! sources
+//!
+//! Sources producing data streams
+
+/// combinators
+/// Sub module for sources combining other sources
+pub mod combinators;
+
+/// generators
+/// Sub module for synthetic signal sources
+pub mod generators;
+
+/// text
+/// Sub module for sources decoding text
+pub mod text;
+
+/// image
+/// Sub module for sources decoding images
+pub mod image;
+
+pub use combinators::{chain, cycle, interleave, Chain, Cycle, Interleave};
+pub use generators::{
+    BatchedSource, ConstantSource, Distribution, RampSource, RandomSource, SineSource,
+};
+pub use image::{Image, ImageSource, PixelFormat};
+pub use text::{TextDecoder, TextEncoding, TextSource};
@@ -0,0 +1,135 @@
+//! combinators
+//!
+//! Sources built by combining other sources
+//!
+//! chained and cycled inputs are only streamed once the combined stream reaches them and
+//! cycled inputs are streamed anew on every pass, inputs are combined as trait objects so
+//! combinators nest freely
+
+use crate::data_bucket::schema::StreamSchema;
+use crate::Source;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::Poll;
+
+type BoxedStream<T> = Pin<Box<dyn Stream<Item = T>>>;
+
+/// Chain
+/// A source streaming every item of a first source then every item of a second one
+pub struct Chain<T> {
+    first: Rc<dyn Source<T>>,
+    second: Rc<dyn Source<T>>,
+}
+
+/// stream the items of `first` followed by those of `second`
+pub fn chain<T>(first: Rc<dyn Source<T>>, second: Rc<dyn Source<T>>) -> Chain<T> {
+    Chain { first, second }
+}
+
+impl<T: 'static> Source<T> for Chain<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let second = self.second.clone();
+        let rest = stream::once(async move { Pin::from(second.stream()) }).flatten();
+        Box::new(Pin::from(self.first.stream()).chain(rest))
+    }
+    fn schema(&self) -> Option<StreamSchema> {
+        self.first.schema()
+    }
+}
+
+/// Interleave
+/// A source taking one item from each of its sources in turn
+///
+/// sources that end are skipped, the stream waits on the source whose turn it is even if
+/// others have items ready so the order is deterministic
+pub struct Interleave<T> {
+    sources: Vec<Rc<dyn Source<T>>>,
+}
+
+/// alternate between the items of several sources
+pub fn interleave<T>(sources: Vec<Rc<dyn Source<T>>>) -> Interleave<T> {
+    Interleave { sources }
+}
+
+impl<T: 'static> Source<T> for Interleave<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let mut streams: Vec<BoxedStream<T>> = self
+            .sources
+            .iter()
+            .map(|source| Pin::from(source.stream()))
+            .collect();
+        let mut turn = 0;
+        Box::new(stream::poll_fn(move |cx| {
+            while !streams.is_empty() {
+                match streams[turn].as_mut().poll_next(cx) {
+                    Poll::Ready(Some(item)) => {
+                        turn = (turn + 1) % streams.len();
+                        return Poll::Ready(Some(item));
+                    }
+                    Poll::Ready(None) => {
+                        drop(streams.remove(turn));
+                        if turn >= streams.len() {
+                            turn = 0;
+                        }
+                    }
+                    Poll::Pending => return Poll::Pending,
+                }
+            }
+            Poll::Ready(None)
+        }))
+    }
+    fn schema(&self) -> Option<StreamSchema> {
+        self.sources.first()?.schema()
+    }
+}
+
+/// Cycle
+/// A source streaming another source a number of times in a row
+pub struct Cycle<T> {
+    source: Rc<dyn Source<T>>,
+    passes: usize,
+}
+
+/// stream the items of a source `passes` times
+pub fn cycle<T>(source: Rc<dyn Source<T>>, passes: usize) -> Cycle<T> {
+    Cycle { source, passes }
+}
+
+impl<T: 'static> Source<T> for Cycle<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let source = self.source.clone();
+        Box::new(stream::iter(0..self.passes).flat_map(move |_| Pin::from(source.stream())))
+    }
+    fn schema(&self) -> Option<StreamSchema> {
+        self.source.schema()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{collect, MockSource};
+
+    fn items(range: std::ops::Range<i32>) -> Rc<dyn Source<i32>> {
+        Rc::new(MockSource::new().items(range))
+    }
+
+    #[test]
+    fn test_chain_and_cycle() {
+        assert_eq!(collect(&chain(items(0..2), items(5..7))), vec![0, 1, 5, 6]);
+        let repeated = cycle(items(0..3), 2);
+        assert_eq!(collect(&repeated), vec![0, 1, 2, 0, 1, 2]);
+        assert!(collect(&cycle(items(0..3), 0)).is_empty());
+        let nested = cycle(Rc::new(chain(items(0..1), items(9..10))), 2);
+        assert_eq!(collect(&nested), vec![0, 9, 0, 9]);
+    }
+
+    #[test]
+    fn test_interleave() {
+        let mixed = interleave(vec![items(0..3), items(10..11), items(20..24)]);
+        assert_eq!(collect(&mixed), vec![0, 10, 20, 1, 21, 2, 22, 23]);
+        assert!(collect(&interleave(Vec::<Rc<dyn Source<i32>>>::new())).is_empty());
+    }
+}
@@ -0,0 +1,395 @@
+//! generators
+//!
+//! Sources synthesising signals for tests and simulations
+//!
+//! generators are endless unless given a length with `with_len`, wrap them in a
+//! `BatchedSource` to receive `DataBlob`s instead of single values
+
+use crate::data_bucket::{DataBlob, MetaData};
+use crate::Source;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::f64::consts::TAU;
+use std::ops::Add;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// bound an endless stream to an optional number of items
+fn limit<T: 'static>(
+    items: impl Stream<Item = T> + 'static,
+    len: Option<usize>,
+) -> Box<dyn Stream<Item = T>> {
+    match len {
+        Some(len) => Box::new(items.take(len)),
+        None => Box::new(items),
+    }
+}
+
+/// SineSource
+/// A source sampling `offset + amplitude * sin(2 pi frequency t + phase)`
+#[derive(Clone, Debug)]
+pub struct SineSource {
+    frequency: f64,
+    sample_rate: f64,
+    amplitude: f64,
+    phase: f64,
+    offset: f64,
+    len: Option<usize>,
+}
+
+impl SineSource {
+    /// constructor for a unit sine of a frequency (in Hz) sampled at a rate (in Hz)
+    pub fn new(frequency: f64, sample_rate: f64) -> Result<Self, &'static str> {
+        if !(sample_rate.is_finite() && sample_rate > 0.0) {
+            return Err("Sample rate must be finite and strictly positive");
+        }
+        Ok(Self {
+            frequency,
+            sample_rate,
+            amplitude: 1.0,
+            phase: 0.0,
+            offset: 0.0,
+            len: None,
+        })
+    }
+    /// set the peak amplitude
+    pub fn with_amplitude(mut self, amplitude: f64) -> Self {
+        self.amplitude = amplitude;
+        self
+    }
+    /// set the phase at the first sample (in radians)
+    pub fn with_phase(mut self, phase: f64) -> Self {
+        self.phase = phase;
+        self
+    }
+    /// set the constant added to every sample
+    pub fn with_offset(mut self, offset: f64) -> Self {
+        self.offset = offset;
+        self
+    }
+    /// stop after a number of samples
+    pub fn with_len(mut self, len: usize) -> Self {
+        self.len = Some(len);
+        self
+    }
+}
+
+impl Source<f64> for SineSource {
+    fn stream(&self) -> Box<dyn Stream<Item = f64>> {
+        let generator = self.clone();
+        limit(
+            stream::iter(0_u64..).map(move |idx| {
+                let time = idx as f64 / generator.sample_rate;
+                generator.offset
+                    + generator.amplitude
+                        * (TAU * generator.frequency * time + generator.phase).sin()
+            }),
+            self.len,
+        )
+    }
+}
+
+/// RampSource
+/// A source counting up from a start value by a constant step
+#[derive(Clone, Debug)]
+pub struct RampSource<T> {
+    start: T,
+    step: T,
+    len: Option<usize>,
+}
+
+impl<T> RampSource<T> {
+    /// constructor
+    pub fn new(start: T, step: T) -> Self {
+        Self {
+            start,
+            step,
+            len: None,
+        }
+    }
+    /// stop after a number of values
+    pub fn with_len(mut self, len: usize) -> Self {
+        self.len = Some(len);
+        self
+    }
+}
+
+impl<T: Copy + Add<Output = T> + 'static> Source<T> for RampSource<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let step = self.step;
+        limit(
+            stream::unfold(self.start, move |value| async move {
+                Some((value, value + step))
+            }),
+            self.len,
+        )
+    }
+}
+
+/// ConstantSource
+/// A source repeating a single value
+#[derive(Clone, Debug)]
+pub struct ConstantSource<T> {
+    value: T,
+    len: Option<usize>,
+}
+
+impl<T> ConstantSource<T> {
+    /// constructor
+    pub fn new(value: T) -> Self {
+        Self { value, len: None }
+    }
+    /// stop after a number of values
+    pub fn with_len(mut self, len: usize) -> Self {
+        self.len = Some(len);
+        self
+    }
+}
+
+impl<T: Clone + 'static> Source<T> for ConstantSource<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        limit(stream::repeat(self.value.clone()), self.len)
+    }
+}
+
+/// Distribution
+/// The probability distributions a `RandomSource` can draw from
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Distribution {
+    /// uniform over `[low, high)`
+    Uniform { low: f64, high: f64 },
+    /// gaussian with a mean and a standard deviation
+    Normal { mean: f64, std_dev: f64 },
+    /// exponential with a rate (inverse of the mean)
+    Exponential { rate: f64 },
+}
+
+impl Distribution {
+    fn validate(&self) -> Result<(), &'static str> {
+        let valid = match *self {
+            Distribution::Uniform { low, high } => {
+                low.is_finite() && high.is_finite() && low < high
+            }
+            Distribution::Normal { mean, std_dev } => mean.is_finite() && std_dev >= 0.0,
+            Distribution::Exponential { rate } => rate.is_finite() && rate > 0.0,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err("Invalid distribution parameters")
+        }
+    }
+    fn sample(&self, rng: &mut SplitMix64) -> f64 {
+        match *self {
+            Distribution::Uniform { low, high } => low + (high - low) * rng.next_f64(),
+            Distribution::Normal { mean, std_dev } => {
+                // Box-Muller transform, 1 - u keeps the logarithm finite
+                let radius = (-2.0 * (1.0 - rng.next_f64()).ln()).sqrt();
+                mean + std_dev * radius * (TAU * rng.next_f64()).cos()
+            }
+            Distribution::Exponential { rate } => -(1.0 - rng.next_f64()).ln() / rate,
+        }
+    }
+}
+
+/// small, fast and seedable pseudo random generator (not cryptographically secure)
+#[derive(Clone, Debug)]
+struct SplitMix64 {
+    state: u64,
+}
+
+impl SplitMix64 {
+    fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut mixed = self.state;
+        mixed = (mixed ^ (mixed >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        mixed = (mixed ^ (mixed >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        mixed ^ (mixed >> 31)
+    }
+    /// uniform in `[0, 1)` from the 53 high bits
+    fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1_u64 << 53) as f64
+    }
+}
+
+/// RandomSource
+/// A source drawing pseudo random values from a distribution
+#[derive(Clone, Debug)]
+pub struct RandomSource {
+    distribution: Distribution,
+    seed: u64,
+    len: Option<usize>,
+}
+
+impl RandomSource {
+    /// constructor, seeded from the system clock
+    pub fn new(distribution: Distribution) -> Result<Self, &'static str> {
+        distribution.validate()?;
+        let seed = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos() as u64);
+        Ok(Self {
+            distribution,
+            seed,
+            len: None,
+        })
+    }
+    /// set the seed, streams of sources with equal seeds are identical
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = seed;
+        self
+    }
+    /// stop after a number of values
+    pub fn with_len(mut self, len: usize) -> Self {
+        self.len = Some(len);
+        self
+    }
+}
+
+impl Source<f64> for RandomSource {
+    fn stream(&self) -> Box<dyn Stream<Item = f64>> {
+        let distribution = self.distribution;
+        let mut rng = SplitMix64 { state: self.seed };
+        limit(
+            stream::repeat(()).map(move |_| distribution.sample(&mut rng)),
+            self.len,
+        )
+    }
+}
+
+/// BatchedSource
+/// A source gathering the values of another source into blobs of a fixed length
+pub struct BatchedSource<T> {
+    input: Rc<dyn Source<T>>,
+    batch_size: usize,
+    meta: MetaData,
+}
+
+impl<T> BatchedSource<T> {
+    /// constructor, every blob copies the meta data with its dimensions set to its length
+    /// (the last blob may be shorter)
+    pub fn new(
+        input: Rc<dyn Source<T>>,
+        batch_size: usize,
+        meta: MetaData,
+    ) -> Result<Self, &'static str> {
+        if batch_size == 0 {
+            return Err("Batch size must be strictly positive");
+        }
+        Ok(Self {
+            input,
+            batch_size,
+            meta,
+        })
+    }
+}
+
+impl<T: 'static> Source<DataBlob<T>> for BatchedSource<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBlob<T>>> {
+        let mut meta = self.meta.clone();
+        meta.record("batch", &[("batch_size", self.batch_size.to_string())]);
+        Box::new(
+            Pin::from(self.input.stream())
+                .chunks(self.batch_size)
+                .map(move |values| {
+                    let mut meta = meta.clone();
+                    meta.dimensions = vec![values.len()];
+                    meta.unitary_dimensions = vec![1];
+                    DataBlob::new(values, meta)
+                }),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::collect;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_sine_source() {
+        let sine = SineSource::new(1.0, 4.0)
+            .unwrap()
+            .with_amplitude(2.0)
+            .with_offset(1.0)
+            .with_len(5);
+        let samples = collect(&sine);
+        let expected = [1.0, 3.0, 1.0, -1.0, 1.0];
+        assert_eq!(samples.len(), 5);
+        for (sample, expected) in samples.iter().zip(expected) {
+            assert!((sample - expected).abs() < 1e-12, "Wrong sine sample");
+        }
+        assert!(SineSource::new(1.0, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_ramp_and_constant_sources() {
+        assert_eq!(
+            collect(&RampSource::new(3_i32, -2).with_len(4)),
+            [3, 1, -1, -3]
+        );
+        assert_eq!(collect(&ConstantSource::new("x").with_len(2)), ["x", "x"]);
+        let endless = ConstantSource::new(0_u8);
+        assert_eq!(block_on(Pin::from(endless.stream()).take(100).count()), 100);
+    }
+
+    #[test]
+    fn test_random_source() {
+        let uniform = RandomSource::new(Distribution::Uniform {
+            low: -1.0,
+            high: 1.0,
+        })
+        .unwrap()
+        .with_seed(7)
+        .with_len(1000);
+        let values = collect(&uniform);
+        assert!(values.iter().all(|value| (-1.0..1.0).contains(value)));
+        assert_eq!(values, collect(&uniform), "Seeded streams differ");
+        let normal = RandomSource::new(Distribution::Normal {
+            mean: 5.0,
+            std_dev: 2.0,
+        })
+        .unwrap()
+        .with_seed(1)
+        .with_len(10_000);
+        let values = collect(&normal);
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        let variance = values
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / values.len() as f64;
+        assert!((mean - 5.0).abs() < 0.1, "Wrong normal mean {}", mean);
+        assert!(
+            (variance - 4.0).abs() < 0.3,
+            "Wrong normal variance {}",
+            variance
+        );
+        let exponential = RandomSource::new(Distribution::Exponential { rate: 2.0 })
+            .unwrap()
+            .with_seed(3)
+            .with_len(10_000);
+        let values = collect(&exponential);
+        let mean = values.iter().sum::<f64>() / values.len() as f64;
+        assert!(values.iter().all(|value| *value >= 0.0));
+        assert!((mean - 0.5).abs() < 0.05, "Wrong exponential mean {}", mean);
+        assert!(RandomSource::new(Distribution::Exponential { rate: 0.0 }).is_err());
+    }
+
+    #[test]
+    fn test_batched_source() {
+        let meta = MetaData {
+            name: "ramp".to_string(),
+            ..Default::default()
+        };
+        let batched =
+            BatchedSource::new(Rc::new(RampSource::new(0, 1).with_len(5)), 2, meta).unwrap();
+        let blobs = collect(&batched);
+        assert_eq!(blobs.len(), 3);
+        assert_eq!(blobs[0].get_data(), &vec![0, 1]);
+        assert_eq!(blobs[2].get_data(), &vec![4]);
+        assert_eq!(blobs[2].get_meta_data().dimensions, vec![1]);
+        assert_eq!(blobs[2].get_meta_data().name, "ramp");
+    }
+}
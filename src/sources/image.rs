@@ -0,0 +1,243 @@
+//! image
+//!
+//! Sources decoding PNG and JPEG images into pixel blobs
+//!
+//! each image becomes a blob of dimensions `[height, width, channels]` with the axes
+//! `height`, `width` and `channel` (a tensor), rows from the top, pixels from the left and
+//! channels interleaved, one unit per pixel. Channels are gray, gray and alpha, RGB or RGBA
+
+use crate::data_bucket::{DataBlob, DataBucketBlob, MetaData};
+use crate::Source;
+use bytes::Bytes;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// inflate
+/// Sub module for the zlib decompression of PNG data
+pub mod inflate;
+
+/// png
+/// Sub module for decoding PNG images
+pub mod png;
+
+/// jpeg
+/// Sub module for decoding baseline JPEG images
+pub mod jpeg;
+
+/// Image
+/// The decoded pixels of an image
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    /// number of pixels in a row
+    pub width: usize,
+    /// number of rows
+    pub height: usize,
+    /// number of samples of a pixel
+    pub channels: usize,
+    /// bits of a sample, 8 or 16
+    pub depth: u8,
+    /// the samples of every pixel, row after row
+    pub samples: Vec<u16>,
+}
+
+impl Image {
+    /// decode a PNG or JPEG image, telling them apart by their signature
+    pub fn decode(bytes: &[u8]) -> Result<Self, &'static str> {
+        if png::is_png(bytes) {
+            png::decode(bytes)
+        } else if jpeg::is_jpeg(bytes) {
+            jpeg::decode(bytes)
+        } else {
+            Err("Unknown image format")
+        }
+    }
+    /// the image as a blob of samples in the given format
+    pub fn to_blob(&self, format: PixelFormat, name: &str) -> DataBucketBlob {
+        let meta = MetaData {
+            name: name.to_string(),
+            dimensions: vec![self.height, self.width, self.channels],
+            unitary_dimensions: vec![self.channels],
+            axes: ["height", "width", "channel"].map(str::to_string).to_vec(),
+            ..Default::default()
+        };
+        let shift = self.depth - 8;
+        match format {
+            PixelFormat::U8 => DataBucketBlob::U8(DataBlob::new(
+                self.samples
+                    .iter()
+                    .map(|sample| (sample >> shift) as u8)
+                    .collect(),
+                meta,
+            )),
+            PixelFormat::Float32 => {
+                let max = ((1u32 << self.depth) - 1) as f32;
+                DataBucketBlob::Float32(DataBlob::new(
+                    self.samples
+                        .iter()
+                        .map(|sample| *sample as f32 / max)
+                        .collect(),
+                    meta,
+                ))
+            }
+        }
+    }
+}
+
+/// PixelFormat
+/// The element types images are decoded into
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum PixelFormat {
+    /// `U8` blobs, 16 bit samples keep their most significant byte
+    #[default]
+    U8,
+    /// `Float32` blobs of samples scaled to [0, 1]
+    Float32,
+}
+
+/// the input of an image source
+enum ImageInput {
+    Files(Vec<PathBuf>),
+    Bytes(Bytes),
+    Stream(Rc<dyn Source<Bytes>>),
+}
+
+/// ImageSource
+/// A source decoding image files or encoded images into pixel blobs, the stream ends at the
+/// first image that cannot be read or decoded
+pub struct ImageSource {
+    input: ImageInput,
+    format: PixelFormat,
+    name: String,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl ImageSource {
+    fn with_input(input: ImageInput) -> Self {
+        Self {
+            input,
+            format: PixelFormat::default(),
+            name: "image".to_string(),
+            error: Rc::new(Cell::new(None)),
+        }
+    }
+    /// constructor reading an image file each time the source is streamed
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        Self::open_all([path])
+    }
+    /// constructor reading image files in order each time the source is streamed
+    pub fn open_all<P: AsRef<Path>, I: IntoIterator<Item = P>>(paths: I) -> Self {
+        let paths = paths.into_iter().map(|path| path.as_ref().to_path_buf());
+        Self::with_input(ImageInput::Files(paths.collect()))
+    }
+    /// constructor decoding an encoded image held in memory
+    pub fn from_bytes<B: Into<Bytes>>(bytes: B) -> Self {
+        Self::with_input(ImageInput::Bytes(bytes.into()))
+    }
+    /// constructor decoding each item of a byte stream as a whole encoded image
+    pub fn from_source(input: Rc<dyn Source<Bytes>>) -> Self {
+        Self::with_input(ImageInput::Stream(input))
+    }
+    /// set the element type of the blobs (`U8` by default)
+    pub fn with_format(mut self, format: PixelFormat) -> Self {
+        self.format = format;
+        self
+    }
+    /// set the name of the blobs ("image" by default)
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+    /// the read or decoding failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl Source<DataBucketBlob> for ImageSource {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBucketBlob>> {
+        let error = self.error.clone();
+        error.set(None);
+        let encoded: Pin<Box<dyn Stream<Item = Result<Bytes, &'static str>>>> = match &self.input {
+            ImageInput::Bytes(bytes) => Box::pin(stream::iter([Ok(bytes.clone())])),
+            ImageInput::Stream(input) => Box::pin(Pin::from(input.stream()).map(Ok)),
+            ImageInput::Files(paths) => Box::pin(stream::iter(paths.clone()).map(|path| {
+                std::fs::read(path)
+                    .map(Bytes::from)
+                    .map_err(|_| "Failure to read image file")
+            })),
+        };
+        let (format, name) = (self.format, self.name.clone());
+        Box::new(encoded.scan((), move |_, bytes| {
+            let blob = bytes
+                .and_then(|bytes| Image::decode(&bytes))
+                .map(|image| image.to_blob(format, &name))
+                .map_err(|failure| error.set(Some(failure)));
+            futures::future::ready(blob.ok())
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::jpeg::tests::{block, encode as jpeg, Unit};
+    use super::png::tests::{encode as png, header};
+    use super::*;
+    use crate::data_bucket::tensor::TensorBlob;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_image_source() {
+        let rgba = png(
+            header(2, 1, 16, 6, 0),
+            &[],
+            &[0, 255, 255, 0, 0, 0, 0, 255, 255, 0, 0, 128, 0, 1, 2, 3, 4],
+        );
+        let gray = jpeg(8, 8, &[0x11], &[Unit::Block(0, block(&[8 * 72]))], 0);
+        let inputs = [
+            Bytes::from(rgba),
+            Bytes::from(gray),
+            Bytes::from_static(b"BM"),
+        ];
+        let source = ImageSource::from_source(Rc::new(MockSource::new().items(inputs)));
+        let blobs: Vec<DataBucketBlob> = block_on(Pin::from(source.stream()).collect());
+        assert_eq!(blobs.len(), 2);
+        assert_eq!(source.error(), Some("Unknown image format"));
+        let pixels = match &blobs[0] {
+            DataBucketBlob::U8(blob) => blob.clone(),
+            _ => panic!("Image did not decode into U8 samples"),
+        };
+        assert_eq!(pixels.get_data(), &vec![255, 0, 0, 255, 0, 128, 1, 3]);
+        assert_eq!(pixels.unit_count(), 2);
+        let tensor = TensorBlob::from_blob(pixels).unwrap();
+        assert_eq!(tensor.shape(), &[1, 2, 4]);
+        assert_eq!(tensor.get(&[0, 1, 1]), Some(&128));
+        assert_eq!(blobs[1].get_meta_data().dimensions, vec![8, 8, 1]);
+    }
+
+    #[test]
+    fn test_float_files() {
+        let directory =
+            std::env::temp_dir().join(format!("bitvortex-image-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        let path = directory.join("gray.png");
+        std::fs::write(&path, png(header(2, 1, 8, 0, 0), &[], &[0, 0, 51])).unwrap();
+        let source = ImageSource::open_all([path.clone(), directory.join("missing.png")])
+            .with_format(PixelFormat::Float32)
+            .with_name("frame");
+        let blobs: Vec<DataBucketBlob> = block_on(Pin::from(source.stream()).collect());
+        std::fs::remove_dir_all(&directory).unwrap();
+        match &blobs[..] {
+            [DataBucketBlob::Float32(blob)] => {
+                assert_eq!(blob.get_data(), &vec![0.0, 0.2]);
+                assert_eq!(blob.get_meta_data().name, "frame");
+            }
+            _ => panic!("Image did not decode into Float32 samples"),
+        }
+        assert_eq!(source.error(), Some("Failure to read image file"));
+    }
+}
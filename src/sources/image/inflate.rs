@@ -0,0 +1,322 @@
+//! inflate
+//!
+//! Decompression of the zlib streams holding the pixels of PNG images (RFC 1950 and 1951)
+
+/// order in which the lengths of the code length alphabet are stored
+const CODE_LENGTH_ORDER: [usize; 19] = [
+    16, 17, 18, 0, 8, 7, 9, 6, 10, 5, 11, 4, 12, 3, 13, 2, 14, 1, 15,
+];
+const LENGTH_BASE: [u16; 29] = [
+    3, 4, 5, 6, 7, 8, 9, 10, 11, 13, 15, 17, 19, 23, 27, 31, 35, 43, 51, 59, 67, 83, 99, 115, 131,
+    163, 195, 227, 258,
+];
+const LENGTH_EXTRA: [u8; 29] = [
+    0, 0, 0, 0, 0, 0, 0, 0, 1, 1, 1, 1, 2, 2, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 5, 5, 5, 5, 0,
+];
+const DISTANCE_BASE: [u16; 30] = [
+    1, 2, 3, 4, 5, 7, 9, 13, 17, 25, 33, 49, 65, 97, 129, 193, 257, 385, 513, 769, 1025, 1537,
+    2049, 3073, 4097, 6145, 8193, 12289, 16385, 24577,
+];
+const DISTANCE_EXTRA: [u8; 30] = [
+    0, 0, 0, 0, 1, 1, 2, 2, 3, 3, 4, 4, 5, 5, 6, 6, 7, 7, 8, 8, 9, 9, 10, 10, 11, 11, 12, 12, 13,
+    13,
+];
+
+/// reads the bits of compressed data, least significant first
+struct BitReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    buffer: u64,
+    count: u32,
+}
+
+impl<'a> BitReader<'a> {
+    fn bits(&mut self, count: u32) -> Result<u32, &'static str> {
+        while self.count < count {
+            let byte = *self
+                .data
+                .get(self.offset)
+                .ok_or("Truncated compressed data")?;
+            self.offset += 1;
+            self.buffer |= (byte as u64) << self.count;
+            self.count += 8;
+        }
+        let value = (self.buffer & ((1 << count) - 1)) as u32;
+        self.buffer >>= count;
+        self.count -= count;
+        Ok(value)
+    }
+    /// drop the bits left in the current byte
+    fn align(&mut self) {
+        let partial = self.count % 8;
+        self.buffer >>= partial;
+        self.count -= partial;
+    }
+}
+
+/// a canonical Huffman code
+struct Huffman {
+    counts: [u16; 16],
+    symbols: Vec<u16>,
+}
+
+impl Huffman {
+    /// constructor from the code length of every symbol, 0 for unused symbols
+    fn new(lengths: &[u8]) -> Result<Self, &'static str> {
+        let mut counts = [0u16; 16];
+        for length in lengths {
+            counts[*length as usize] += 1;
+        }
+        counts[0] = 0;
+        let mut left = 1i32;
+        for count in counts.iter().skip(1) {
+            left = (left << 1) - *count as i32;
+            if left < 0 {
+                return Err("Invalid compressed data");
+            }
+        }
+        let mut offsets = [0u16; 16];
+        for length in 1..15 {
+            offsets[length + 1] = offsets[length] + counts[length];
+        }
+        let mut symbols = vec![0; lengths.len()];
+        for (symbol, length) in lengths.iter().enumerate() {
+            if *length != 0 {
+                symbols[offsets[*length as usize] as usize] = symbol as u16;
+                offsets[*length as usize] += 1;
+            }
+        }
+        Ok(Self { counts, symbols })
+    }
+    fn decode(&self, bits: &mut BitReader) -> Result<u16, &'static str> {
+        let (mut code, mut first, mut index) = (0i32, 0i32, 0i32);
+        for count in self.counts.iter().skip(1) {
+            code |= bits.bits(1)? as i32;
+            let count = *count as i32;
+            if code - first < count {
+                return Ok(self.symbols[(index + code - first) as usize]);
+            }
+            index += count;
+            first = (first + count) << 1;
+            code <<= 1;
+        }
+        Err("Invalid compressed data")
+    }
+}
+
+/// the codes of blocks compressed with the fixed Huffman codes
+fn fixed_codes() -> (Huffman, Huffman) {
+    let mut lengths = [8u8; 288];
+    lengths[144..256].fill(9);
+    lengths[256..280].fill(7);
+    (
+        Huffman::new(&lengths).unwrap(),
+        Huffman::new(&[5; 30]).unwrap(),
+    )
+}
+
+/// the codes of a block compressed with codes of its own
+fn dynamic_codes(bits: &mut BitReader) -> Result<(Huffman, Huffman), &'static str> {
+    let literals = bits.bits(5)? as usize + 257;
+    let distances = bits.bits(5)? as usize + 1;
+    let code_lengths = bits.bits(4)? as usize + 4;
+    let mut lengths = [0u8; 19];
+    for position in CODE_LENGTH_ORDER.iter().take(code_lengths) {
+        lengths[*position] = bits.bits(3)? as u8;
+    }
+    let code = Huffman::new(&lengths)?;
+    let mut lengths = Vec::with_capacity(literals + distances);
+    while lengths.len() < literals + distances {
+        let (length, repeat) = match code.decode(bits)? {
+            symbol @ 0..=15 => (symbol as u8, 1),
+            16 => {
+                let previous = *lengths.last().ok_or("Invalid compressed data")?;
+                (previous, 3 + bits.bits(2)? as usize)
+            }
+            17 => (0, 3 + bits.bits(3)? as usize),
+            _ => (0, 11 + bits.bits(7)? as usize),
+        };
+        if lengths.len() + repeat > literals + distances {
+            return Err("Invalid compressed data");
+        }
+        lengths.extend(std::iter::repeat_n(length, repeat));
+    }
+    if lengths[256] == 0 {
+        return Err("Invalid compressed data");
+    }
+    Ok((
+        Huffman::new(&lengths[..literals])?,
+        Huffman::new(&lengths[literals..])?,
+    ))
+}
+
+/// decompress a raw deflate stream, failing once the output would exceed a limit
+fn inflate(bits: &mut BitReader, limit: usize) -> Result<Vec<u8>, &'static str> {
+    let mut out = Vec::new();
+    loop {
+        let last = bits.bits(1)? == 1;
+        let (literals, distances) = match bits.bits(2)? {
+            0 => {
+                bits.align();
+                let len = bits.bits(16)?;
+                if len != !bits.bits(16)? & 0xffff {
+                    return Err("Invalid compressed data");
+                }
+                if out.len() + len as usize > limit {
+                    return Err("Decompressed data exceeds its expected size");
+                }
+                for _ in 0..len {
+                    out.push(bits.bits(8)? as u8);
+                }
+                if last {
+                    return Ok(out);
+                }
+                continue;
+            }
+            1 => fixed_codes(),
+            2 => dynamic_codes(bits)?,
+            _ => return Err("Invalid compressed data"),
+        };
+        loop {
+            let symbol = literals.decode(bits)? as usize;
+            if symbol == 256 {
+                break;
+            }
+            if out.len() >= limit {
+                return Err("Decompressed data exceeds its expected size");
+            }
+            if symbol < 256 {
+                out.push(symbol as u8);
+                continue;
+            }
+            let code = symbol - 257;
+            if code >= LENGTH_BASE.len() {
+                return Err("Invalid compressed data");
+            }
+            let length =
+                LENGTH_BASE[code] as usize + bits.bits(LENGTH_EXTRA[code] as u32)? as usize;
+            let code = distances.decode(bits)? as usize;
+            if code >= DISTANCE_BASE.len() {
+                return Err("Invalid compressed data");
+            }
+            let distance =
+                DISTANCE_BASE[code] as usize + bits.bits(DISTANCE_EXTRA[code] as u32)? as usize;
+            if distance > out.len() {
+                return Err("Invalid compressed data");
+            }
+            if out.len() + length > limit {
+                return Err("Decompressed data exceeds its expected size");
+            }
+            // copies may overlap their own output
+            let start = out.len() - distance;
+            for position in start..start + length {
+                out.push(out[position]);
+            }
+        }
+        if last {
+            return Ok(out);
+        }
+    }
+}
+
+/// decompress a zlib stream, failing once the output would exceed a limit
+pub fn zlib_decompress(data: &[u8], limit: usize) -> Result<Vec<u8>, &'static str> {
+    let (method, flags) = match data {
+        [method, flags, ..] => (*method, *flags),
+        _ => return Err("Truncated compressed data"),
+    };
+    if method & 0x0f != 8 || !(method as u16 * 256 + flags as u16).is_multiple_of(31) {
+        return Err("Invalid zlib header");
+    }
+    if flags & 0x20 != 0 {
+        return Err("Preset zlib dictionaries are not supported");
+    }
+    let mut bits = BitReader {
+        data: &data[2..],
+        offset: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let out = inflate(&mut bits, limit)?;
+    bits.align();
+    let mut checksum = 0;
+    for _ in 0..4 {
+        checksum = (checksum << 8) | bits.bits(8)?;
+    }
+    match adler32(&out) == checksum {
+        true => Ok(out),
+        false => Err("Compressed data fails its checksum"),
+    }
+}
+
+fn adler32(data: &[u8]) -> u32 {
+    let (mut a, mut b) = (1u32, 0u32);
+    for chunk in data.chunks(5552) {
+        for byte in chunk {
+            a += *byte as u32;
+            b += a;
+        }
+        a %= 65521;
+        b %= 65521;
+    }
+    (b << 16) | a
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_zlib_decompress() {
+        // zlib output for a short repetitive text, held in a single fixed Huffman block
+        let fixed = [
+            120, 156, 75, 202, 44, 41, 203, 47, 42, 73, 173, 80, 72, 194, 194, 42, 46, 41, 74, 77,
+            204, 45, 6, 0, 31, 122, 15, 21,
+        ];
+        let text = b"bitvortex bitvortex bitvortex streams";
+        assert_eq!(zlib_decompress(&fixed, 100).unwrap(), text);
+        assert_eq!(
+            zlib_decompress(&fixed, 20),
+            Err("Decompressed data exceeds its expected size")
+        );
+        let mut corrupted = fixed;
+        corrupted[27] ^= 1;
+        assert_eq!(
+            zlib_decompress(&corrupted, 100),
+            Err("Compressed data fails its checksum")
+        );
+        assert!(zlib_decompress(&fixed[..20], 100).is_err());
+        // zlib output at level 9 for the squares modulo 97, held in a dynamic Huffman block
+        let dynamic = [
+            120, 218, 237, 144, 219, 141, 197, 32, 12, 5, 91, 153, 18, 98, 27, 12, 244, 223, 216,
+            14, 148, 176, 223, 87, 138, 80, 18, 206, 251, 35, 24, 28, 162, 201, 73, 53, 227, 208,
+            131, 29, 20, 57, 24, 139, 149, 36, 21, 116, 114, 68, 20, 235, 35, 146, 89, 28, 209,
+            114, 21, 240, 125, 48, 63, 54, 61, 201, 197, 222, 204, 65, 38, 91, 53, 165, 38, 17,
+            236, 166, 101, 108, 42, 9, 145, 156, 98, 79, 214, 97, 121, 214, 123, 222, 167, 63, 189,
+            234, 11, 18, 42, 65, 154, 228, 120, 66, 202, 41, 170, 180, 6, 218, 104, 166, 229, 190,
+            230, 70, 184, 65, 226, 133, 234, 27, 208, 152, 134, 53, 178, 193, 251, 149, 200, 91,
+            199, 82, 86, 171, 91, 210, 170, 22, 174, 87, 222, 9, 142, 91, 4, 223, 111, 147, 223,
+            38, 255, 222, 228, 15, 190, 81, 150, 197,
+        ];
+        let squares: Vec<String> = (0..300).map(|i| (i * i % 97).to_string()).collect();
+        let squares = squares.join(" ");
+        assert_eq!(zlib_decompress(&dynamic, 1000).unwrap(), squares.as_bytes());
+    }
+
+    #[test]
+    fn test_stored_blocks() {
+        let data = b"raw pixels";
+        let mut stream = vec![0x78, 0x01, 0x00, 4, 0, !4, !0];
+        stream.extend_from_slice(&data[..4]);
+        stream.extend_from_slice(&[0x01, 6, 0, !6, !0]);
+        stream.extend_from_slice(&data[4..]);
+        stream.extend_from_slice(&adler32(data).to_be_bytes());
+        assert_eq!(zlib_decompress(&stream, 10).unwrap(), data);
+        stream[3] = 5;
+        assert_eq!(zlib_decompress(&stream, 10), Err("Invalid compressed data"));
+        assert_eq!(
+            zlib_decompress(&[0x78, 0x00], 10),
+            Err("Invalid zlib header")
+        );
+    }
+}
@@ -0,0 +1,756 @@
+//! jpeg
+//!
+//! Decoding of baseline JPEG images
+//!
+//! sequential Huffman coded images of 8 bit samples are understood, gray or YCbCr (RGB with
+//! an Adobe marker saying so) with any sampling factors, interleaved or not and with restart
+//! intervals. Chroma is upsampled by repeating its samples, progressive, lossless,
+//! arithmetic coded and CMYK images are refused
+
+use super::Image;
+
+/// natural position of the coefficients of a block in their zigzag order
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// whether bytes start with a JPEG start of image marker
+pub fn is_jpeg(bytes: &[u8]) -> bool {
+    bytes.starts_with(&[0xff, 0xd8, 0xff])
+}
+
+/// reads the bits of entropy coded data, most significant first, removing stuffed bytes
+///
+/// a marker ends the data, bits past it read as 0
+struct Bits<'a> {
+    data: &'a [u8],
+    offset: usize,
+    buffer: u8,
+    count: u32,
+}
+
+impl<'a> Bits<'a> {
+    fn bit(&mut self) -> u32 {
+        if self.count == 0 {
+            self.buffer = match self.data.get(self.offset..self.offset + 2) {
+                Some([0xff, 0x00]) => {
+                    self.offset += 2;
+                    0xff
+                }
+                Some([0xff, _]) => 0,
+                _ => match self.data.get(self.offset) {
+                    Some(byte) => {
+                        self.offset += 1;
+                        *byte
+                    }
+                    None => 0,
+                },
+            };
+            self.count = 8;
+        }
+        self.count -= 1;
+        ((self.buffer >> self.count) & 1) as u32
+    }
+    fn bits(&mut self, count: u8) -> u32 {
+        (0..count).fold(0, |value, _| (value << 1) | self.bit())
+    }
+    /// drop the bits left in the current byte and read the restart marker that follows
+    fn restart(&mut self) -> Result<(), &'static str> {
+        self.count = 0;
+        while self.data.get(self.offset..self.offset + 2) == Some(&[0xff, 0xff]) {
+            self.offset += 1;
+        }
+        match self.data.get(self.offset..self.offset + 2) {
+            Some([0xff, 0xd0..=0xd7]) => {
+                self.offset += 2;
+                Ok(())
+            }
+            _ => Err("Missing JPEG restart marker"),
+        }
+    }
+}
+
+/// a Huffman code of the entropy coded data
+struct Huffman {
+    first_codes: [i32; 17],
+    last_codes: [i32; 17],
+    offsets: [usize; 17],
+    symbols: Vec<u8>,
+}
+
+impl Huffman {
+    /// constructor from the number of codes of each length and their symbols in code order
+    fn new(counts: &[u8], symbols: &[u8]) -> Result<Self, &'static str> {
+        let (mut first_codes, mut last_codes, mut offsets) = ([0; 17], [-1; 17], [0; 17]);
+        let (mut code, mut index) = (0i32, 0usize);
+        for length in 1..=16 {
+            let count = counts[length - 1] as usize;
+            first_codes[length] = code;
+            offsets[length] = index;
+            if count > 0 {
+                last_codes[length] = code + count as i32 - 1;
+            }
+            code = (code + count as i32) << 1;
+            index += count;
+            if code > 1 << (length + 1) {
+                return Err("Invalid JPEG Huffman table");
+            }
+        }
+        if index != symbols.len() {
+            return Err("Invalid JPEG Huffman table");
+        }
+        Ok(Self {
+            first_codes,
+            last_codes,
+            offsets,
+            symbols: symbols.to_vec(),
+        })
+    }
+    fn decode(&self, bits: &mut Bits) -> Result<u8, &'static str> {
+        let mut code = 0i32;
+        for length in 1..=16 {
+            code = (code << 1) | bits.bit() as i32;
+            if code <= self.last_codes[length] {
+                let index = self.offsets[length] + (code - self.first_codes[length]) as usize;
+                return Ok(self.symbols[index]);
+            }
+        }
+        Err("Invalid JPEG Huffman code")
+    }
+}
+
+/// the signed value of a coefficient stored in a number of bits
+fn extend(value: u32, size: u8) -> i32 {
+    match size {
+        0 => 0,
+        _ if value < 1 << (size - 1) => value as i32 - (1 << size) + 1,
+        _ => value as i32,
+    }
+}
+
+struct Component {
+    id: u8,
+    horizontal: usize,
+    vertical: usize,
+    table: usize,
+    /// samples of the component, a whole number of blocks wide and high
+    samples: Vec<u8>,
+    stride: usize,
+}
+
+struct Frame {
+    width: usize,
+    height: usize,
+    components: Vec<Component>,
+    horizontal: usize,
+    vertical: usize,
+    mcus_wide: usize,
+    mcus_high: usize,
+}
+
+impl Frame {
+    fn parse(data: &[u8]) -> Result<Self, &'static str> {
+        if data.len() < 6 {
+            return Err("Invalid JPEG frame");
+        }
+        if data[0] != 8 {
+            return Err("Only 8 bit JPEG samples are supported");
+        }
+        let height = u16::from_be_bytes([data[1], data[2]]) as usize;
+        let width = u16::from_be_bytes([data[3], data[4]]) as usize;
+        let count = data[5] as usize;
+        if width == 0 || height == 0 {
+            return Err("Image has no pixels");
+        }
+        if count != 1 && count != 3 {
+            return Err("Only gray and YCbCr JPEG images are supported");
+        }
+        if data.len() != 6 + 3 * count {
+            return Err("Invalid JPEG frame");
+        }
+        let mut components = Vec::new();
+        for component in data[6..].chunks_exact(3) {
+            let (horizontal, vertical) =
+                ((component[1] >> 4) as usize, (component[1] & 15) as usize);
+            if !(1..=4).contains(&horizontal) || !(1..=4).contains(&vertical) || component[2] > 3 {
+                return Err("Invalid JPEG frame");
+            }
+            components.push(Component {
+                id: component[0],
+                horizontal,
+                vertical,
+                table: component[2] as usize,
+                samples: Vec::new(),
+                stride: 0,
+            });
+        }
+        let horizontal = components.iter().map(|c| c.horizontal).max().unwrap();
+        let vertical = components.iter().map(|c| c.vertical).max().unwrap();
+        let mcus_wide = width.div_ceil(8 * horizontal);
+        let mcus_high = height.div_ceil(8 * vertical);
+        for component in components.iter_mut() {
+            component.stride = mcus_wide * component.horizontal * 8;
+            component.samples = vec![0; component.stride * mcus_high * component.vertical * 8];
+        }
+        Ok(Self {
+            width,
+            height,
+            components,
+            horizontal,
+            vertical,
+            mcus_wide,
+            mcus_high,
+        })
+    }
+}
+
+/// the tables scans are decoded with
+struct Tables {
+    quantization: [Option<[u16; 64]>; 4],
+    dc: [Option<Huffman>; 4],
+    ac: [Option<Huffman>; 4],
+    restart: usize,
+}
+
+impl Tables {
+    fn parse_quantization(&mut self, mut data: &[u8]) -> Result<(), &'static str> {
+        while let [info, rest @ ..] = data {
+            let (wide, id) = (info >> 4, (info & 15) as usize);
+            let size = if wide == 1 { 128 } else { 64 };
+            if wide > 1 || id > 3 || rest.len() < size {
+                return Err("Invalid JPEG quantization table");
+            }
+            let mut table = [0u16; 64];
+            for (index, value) in table.iter_mut().enumerate() {
+                *value = match wide {
+                    1 => u16::from_be_bytes([rest[2 * index], rest[2 * index + 1]]),
+                    _ => rest[index] as u16,
+                };
+            }
+            self.quantization[id] = Some(table);
+            data = &rest[size..];
+        }
+        Ok(())
+    }
+    fn parse_huffman(&mut self, mut data: &[u8]) -> Result<(), &'static str> {
+        while let [info, rest @ ..] = data {
+            let (class, id) = (info >> 4, (info & 15) as usize);
+            if class > 1 || id > 3 || rest.len() < 16 {
+                return Err("Invalid JPEG Huffman table");
+            }
+            let count: usize = rest[..16].iter().map(|count| *count as usize).sum();
+            let symbols = rest
+                .get(16..16 + count)
+                .ok_or("Invalid JPEG Huffman table")?;
+            let table = Huffman::new(&rest[..16], symbols)?;
+            match class {
+                0 => self.dc[id] = Some(table),
+                _ => self.ac[id] = Some(table),
+            }
+            data = &rest[16 + count..];
+        }
+        Ok(())
+    }
+}
+
+/// the cosines of the inverse DCT, scaled by the normalization of their frequency
+fn idct_table() -> [[f32; 8]; 8] {
+    let mut table = [[0.0; 8]; 8];
+    for (position, row) in table.iter_mut().enumerate() {
+        for (frequency, value) in row.iter_mut().enumerate() {
+            let scale = if frequency == 0 { 0.5_f32.sqrt() } else { 1.0 };
+            let angle = (2 * position + 1) as f32 * frequency as f32 * std::f32::consts::PI / 16.0;
+            *value = scale * angle.cos() / 2.0;
+        }
+    }
+    table
+}
+
+/// decode the coefficients of a block into its samples
+fn decode_block(
+    bits: &mut Bits,
+    (dc, ac): (&Huffman, &Huffman),
+    quantization: &[u16; 64],
+    prediction: &mut i32,
+    idct: &[[f32; 8]; 8],
+) -> Result<[u8; 64], &'static str> {
+    let mut coefficients = [0.0f32; 64];
+    let size = dc.decode(bits)?;
+    if size > 11 {
+        return Err("Invalid JPEG block");
+    }
+    *prediction = prediction.wrapping_add(extend(bits.bits(size), size));
+    coefficients[0] = *prediction as f32 * quantization[0] as f32;
+    let mut index = 1;
+    while index < 64 {
+        let symbol = ac.decode(bits)?;
+        let (run, size) = ((symbol >> 4) as usize, symbol & 15);
+        if size == 0 {
+            if run == 15 {
+                index += 16;
+                continue;
+            }
+            break;
+        }
+        index += run;
+        if index > 63 || size > 10 {
+            return Err("Invalid JPEG block");
+        }
+        let value = extend(bits.bits(size), size) as f32 * quantization[index] as f32;
+        coefficients[ZIGZAG[index]] = value;
+        index += 1;
+    }
+    let mut columns = [0.0f32; 64];
+    for row in 0..8 {
+        for x in 0..8 {
+            let frequencies = &coefficients[row * 8..row * 8 + 8];
+            columns[row * 8 + x] = (0..8).map(|u| frequencies[u] * idct[x][u]).sum();
+        }
+    }
+    let mut samples = [0u8; 64];
+    for y in 0..8 {
+        for x in 0..8 {
+            let value: f32 = (0..8).map(|v| columns[v * 8 + x] * idct[y][v]).sum();
+            samples[y * 8 + x] = (value + 128.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+    Ok(samples)
+}
+
+/// decode the entropy coded data of a scan, returning the number of bytes it spans
+fn decode_scan(
+    data: &[u8],
+    header: &[u8],
+    frame: &mut Frame,
+    tables: &Tables,
+) -> Result<usize, &'static str> {
+    let count = *header.first().ok_or("Invalid JPEG scan")? as usize;
+    if count == 0 || header.len() != 4 + 2 * count {
+        return Err("Invalid JPEG scan");
+    }
+    if header[1 + 2 * count..] != [0, 63, 0] {
+        return Err("Only baseline JPEG images are supported");
+    }
+    let mut scan = Vec::new();
+    for selector in header[1..1 + 2 * count].chunks_exact(2) {
+        let index = frame.components.iter().position(|c| c.id == selector[0]);
+        let index = index.ok_or("JPEG scan of an unknown component")?;
+        let (dc, ac) = ((selector[1] >> 4) as usize, (selector[1] & 15) as usize);
+        let missing = "JPEG scan uses a missing table";
+        let quantization = frame.components[index].table;
+        let quantization = tables.quantization[quantization].as_ref().ok_or(missing)?;
+        let dc = tables.dc.get(dc).and_then(Option::as_ref).ok_or(missing)?;
+        let ac = tables.ac.get(ac).and_then(Option::as_ref).ok_or(missing)?;
+        scan.push((index, (dc, ac), quantization));
+    }
+    // a scan of a single component holds its blocks in raster order rather than by MCU
+    let (units_wide, units_high) = match scan.as_slice() {
+        [(index, ..)] => {
+            let component = &frame.components[*index];
+            let width = (frame.width * component.horizontal).div_ceil(frame.horizontal);
+            let height = (frame.height * component.vertical).div_ceil(frame.vertical);
+            (width.div_ceil(8), height.div_ceil(8))
+        }
+        _ => (frame.mcus_wide, frame.mcus_high),
+    };
+    let idct = idct_table();
+    let mut bits = Bits {
+        data,
+        offset: 0,
+        buffer: 0,
+        count: 0,
+    };
+    let mut predictions = vec![0i32; scan.len()];
+    for unit in 0..units_wide * units_high {
+        if tables.restart > 0 && unit > 0 && unit % tables.restart == 0 {
+            bits.restart()?;
+            predictions.fill(0);
+        }
+        let (unit_x, unit_y) = (unit % units_wide, unit / units_wide);
+        for (position, (index, codes, quantization)) in scan.iter().enumerate() {
+            let component = &mut frame.components[*index];
+            let (wide, high) = match count {
+                1 => (1, 1),
+                _ => (component.horizontal, component.vertical),
+            };
+            for block_y in 0..high {
+                for block_x in 0..wide {
+                    let prediction = &mut predictions[position];
+                    let block = decode_block(&mut bits, *codes, quantization, prediction, &idct)?;
+                    let x = (unit_x * wide + block_x) * 8;
+                    let y = (unit_y * high + block_y) * 8;
+                    for (row, samples) in block.chunks_exact(8).enumerate() {
+                        let start = (y + row) * component.stride + x;
+                        component.samples[start..start + 8].copy_from_slice(samples);
+                    }
+                }
+            }
+        }
+    }
+    Ok(bits.offset)
+}
+
+/// decode a JPEG image
+pub fn decode(bytes: &[u8]) -> Result<Image, &'static str> {
+    if !is_jpeg(bytes) {
+        return Err("Not a JPEG image");
+    }
+    let mut tables = Tables {
+        quantization: [None; 4],
+        dc: [None, None, None, None],
+        ac: [None, None, None, None],
+        restart: 0,
+    };
+    let mut frame: Option<Frame> = None;
+    let mut transform = None;
+    let mut scanned = false;
+    let mut position = 2;
+    loop {
+        // skip to the next marker, past fill bytes and anything a scan left behind
+        while position + 1 < bytes.len()
+            && (bytes[position] != 0xff || matches!(bytes[position + 1], 0x00 | 0xff))
+        {
+            position += 1;
+        }
+        if position + 1 >= bytes.len() {
+            match scanned {
+                true => break,
+                false => return Err("Truncated JPEG image"),
+            }
+        }
+        let marker = bytes[position + 1];
+        position += 2;
+        match marker {
+            0xd9 => break,
+            0x01 | 0xd0..=0xd8 => continue,
+            _ => {}
+        }
+        let len = bytes
+            .get(position..position + 2)
+            .ok_or("Truncated JPEG image")?;
+        let len = u16::from_be_bytes([len[0], len[1]]) as usize;
+        let data = bytes
+            .get(position + 2..position + len.max(2))
+            .ok_or("Truncated JPEG image")?;
+        position += len.max(2);
+        match marker {
+            0xdb => tables.parse_quantization(data)?,
+            0xc4 => tables.parse_huffman(data)?,
+            0xdd if data.len() == 2 => {
+                tables.restart = u16::from_be_bytes([data[0], data[1]]) as usize
+            }
+            0xdd => return Err("Invalid JPEG restart interval"),
+            0xc0 | 0xc1 if frame.is_some() => return Err("JPEG image has several frames"),
+            0xc0 | 0xc1 => frame = Some(Frame::parse(data)?),
+            0xc2 | 0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => {
+                return Err("Only baseline JPEG images are supported")
+            }
+            0xee if data.len() >= 12 && data.starts_with(b"Adobe") => transform = Some(data[11]),
+            0xda => {
+                let frame = frame.as_mut().ok_or("JPEG scan before its frame")?;
+                position += decode_scan(&bytes[position..], data, frame, &tables)?;
+                scanned = true;
+            }
+            _ => {}
+        }
+    }
+    let frame = frame.ok_or("JPEG image has no frame")?;
+    let channels = frame.components.len();
+    let mut samples = Vec::with_capacity(frame.width * frame.height * channels);
+    let mut pixel = [0f32; 3];
+    for y in 0..frame.height {
+        for x in 0..frame.width {
+            for (value, component) in pixel.iter_mut().zip(frame.components.iter()) {
+                let column = x * component.horizontal / frame.horizontal;
+                let row = y * component.vertical / frame.vertical;
+                *value = component.samples[row * component.stride + column] as f32;
+            }
+            if channels == 1 {
+                samples.push(pixel[0] as u16);
+                continue;
+            }
+            let rgb = match transform {
+                Some(0) => pixel,
+                _ => {
+                    let (luma, blue, red) = (pixel[0], pixel[1] - 128.0, pixel[2] - 128.0);
+                    [
+                        luma + 1.402 * red,
+                        luma - 0.344136 * blue - 0.714136 * red,
+                        luma + 1.772 * blue,
+                    ]
+                }
+            };
+            samples.extend(
+                rgb.iter()
+                    .map(|value| value.round().clamp(0.0, 255.0) as u16),
+            );
+        }
+    }
+    Ok(Image {
+        width: frame.width,
+        height: frame.height,
+        channels,
+        depth: 8,
+        samples,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// the coded content of a scan
+    pub(crate) enum Unit {
+        /// a block of a component with its quantized coefficients in zigzag order
+        Block(usize, Vec<i32>),
+        Restart,
+    }
+
+    /// the bits of entropy coded data with stuffed bytes
+    struct BitWriter {
+        bytes: Vec<u8>,
+        byte: u8,
+        count: u32,
+    }
+
+    impl BitWriter {
+        fn put(&mut self, value: u32, count: u32) {
+            for bit in (0..count).rev() {
+                self.byte = (self.byte << 1) | ((value >> bit) & 1) as u8;
+                self.count += 1;
+                if self.count == 8 {
+                    self.bytes.push(self.byte);
+                    if self.byte == 0xff {
+                        self.bytes.push(0);
+                    }
+                    (self.byte, self.count) = (0, 0);
+                }
+            }
+        }
+        fn flush(&mut self) {
+            while self.count != 0 {
+                self.put(1, 1);
+            }
+        }
+    }
+
+    /// bits holding a coefficient and their number
+    fn magnitude(value: i32) -> (u32, u32) {
+        let size = 32 - value.unsigned_abs().leading_zeros();
+        match value < 0 {
+            true => ((value - 1) as u32 & ((1 << size) - 1), size),
+            false => (value as u32, size),
+        }
+    }
+
+    fn segment(marker: u8, data: &[u8]) -> Vec<u8> {
+        let mut segment = vec![0xff, marker];
+        segment.extend_from_slice(&(data.len() as u16 + 2).to_be_bytes());
+        segment.extend_from_slice(data);
+        segment
+    }
+
+    /// a JPEG image of components with the given sampling factors and unit quantization,
+    /// coded with 4 bit codes for every DC size and 8 bit codes for every AC symbol
+    pub(crate) fn encode(
+        width: u16,
+        height: u16,
+        sampling: &[u8],
+        units: &[Unit],
+        restart: u16,
+    ) -> Vec<u8> {
+        let mut jpeg = vec![0xff, 0xd8];
+        let mut quantization = vec![0];
+        quantization.extend([1; 64]);
+        jpeg.extend(segment(0xdb, &quantization));
+        let mut frame = vec![8];
+        frame.extend(height.to_be_bytes());
+        frame.extend(width.to_be_bytes());
+        frame.push(sampling.len() as u8);
+        for (index, factors) in sampling.iter().enumerate() {
+            frame.extend([index as u8 + 1, *factors, 0]);
+        }
+        jpeg.extend(segment(0xc0, &frame));
+        let mut dc = vec![0x00, 0, 0, 0, 12];
+        dc.extend([0; 12]);
+        dc.extend(0..12);
+        jpeg.extend(segment(0xc4, &dc));
+        let symbols: Vec<u8> = (0..=255u8)
+            .filter(|symbol| matches!(symbol & 15, 1..=10) || *symbol == 0 || *symbol == 0xf0)
+            .collect();
+        let mut ac = vec![0x10, 0, 0, 0, 0, 0, 0, 0, symbols.len() as u8];
+        ac.extend([0; 8]);
+        ac.extend(symbols.iter());
+        jpeg.extend(segment(0xc4, &ac));
+        if restart > 0 {
+            jpeg.extend(segment(0xdd, &restart.to_be_bytes()));
+        }
+        let mut scan = vec![sampling.len() as u8];
+        for index in 0..sampling.len() {
+            scan.extend([index as u8 + 1, 0x00]);
+        }
+        scan.extend([0, 63, 0]);
+        jpeg.extend(segment(0xda, &scan));
+        let mut bits = BitWriter {
+            bytes: Vec::new(),
+            byte: 0,
+            count: 0,
+        };
+        let code = |symbol: u8| symbols.iter().position(|s| *s == symbol).unwrap() as u32;
+        let mut predictions = vec![0; sampling.len()];
+        let mut restarts = 0;
+        for unit in units {
+            let (component, coefficients) = match unit {
+                Unit::Block(component, coefficients) => (*component, coefficients),
+                Unit::Restart => {
+                    bits.flush();
+                    bits.bytes.extend([0xff, 0xd0 + restarts % 8]);
+                    restarts += 1;
+                    predictions.fill(0);
+                    continue;
+                }
+            };
+            let (value, size) = magnitude(coefficients[0] - predictions[component]);
+            predictions[component] = coefficients[0];
+            bits.put(size, 4);
+            bits.put(value, size);
+            let mut run = 0;
+            for coefficient in coefficients.iter().skip(1) {
+                if *coefficient == 0 {
+                    run += 1;
+                    continue;
+                }
+                while run > 15 {
+                    bits.put(code(0xf0), 8);
+                    run -= 16;
+                }
+                let (value, size) = magnitude(*coefficient);
+                bits.put(code((run << 4) | size as u8), 8);
+                bits.put(value, size);
+                run = 0;
+            }
+            if run > 0 {
+                bits.put(code(0), 8);
+            }
+        }
+        bits.flush();
+        jpeg.extend(bits.bytes);
+        jpeg.extend([0xff, 0xd9]);
+        jpeg
+    }
+
+    /// the samples of a block from the definition of the inverse DCT
+    fn reference(coefficients: &[i32]) -> Vec<f64> {
+        let mut natural = [0.0; 64];
+        for (index, coefficient) in coefficients.iter().enumerate() {
+            natural[ZIGZAG[index]] = *coefficient as f64;
+        }
+        let scale = |frequency: usize| if frequency == 0 { 0.5_f64.sqrt() } else { 1.0 };
+        let cosine = |position: usize, frequency: usize| {
+            ((2 * position + 1) as f64 * frequency as f64 * std::f64::consts::PI / 16.0).cos()
+        };
+        let mut samples = Vec::new();
+        for y in 0..8 {
+            for x in 0..8 {
+                let mut value = 0.0;
+                for v in 0..8 {
+                    for u in 0..8 {
+                        let term = natural[v * 8 + u] * cosine(x, u) * cosine(y, v);
+                        value += scale(u) * scale(v) * term;
+                    }
+                }
+                samples.push((value / 4.0 + 128.0).clamp(0.0, 255.0));
+            }
+        }
+        samples
+    }
+
+    /// coefficients in zigzag order from the first ones
+    pub(crate) fn block(first: &[i32]) -> Vec<i32> {
+        let mut coefficients = first.to_vec();
+        coefficients.resize(64, 0);
+        coefficients
+    }
+
+    #[test]
+    fn test_gray_blocks() {
+        let flat = block(&[8 * 72]);
+        let mut waves = block(&[-160, -200, 0, 90]);
+        // a coefficient after a run of more than 16 zeros
+        waves[40] = 30;
+        let jpeg = encode(
+            16,
+            8,
+            &[0x11],
+            &[Unit::Block(0, flat), Unit::Block(0, waves.clone())],
+            0,
+        );
+        let image = decode(&jpeg).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (16, 8, 1));
+        assert!(image
+            .samples
+            .chunks(16)
+            .all(|row| row[..8].iter().all(|s| *s == 200)));
+        let expected = reference(&waves);
+        for y in 0..8 {
+            for x in 0..8 {
+                let decoded = image.samples[y * 16 + 8 + x] as f64;
+                assert!((decoded - expected[y * 8 + x]).abs() <= 1.0, "{} {}", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn test_subsampled_color() {
+        // two 4:2:0 MCUs of flat blocks, with a restart between them
+        let mut units = Vec::new();
+        for (mcu, luma) in [[-400, -200, 0, 200], [400, 400, 400, 400]]
+            .iter()
+            .enumerate()
+        {
+            if mcu > 0 {
+                units.push(Unit::Restart);
+            }
+            units.extend(luma.iter().map(|dc| Unit::Block(0, block(&[*dc]))));
+            units.push(Unit::Block(1, block(&[-80])));
+            units.push(Unit::Block(2, block(&[160])));
+        }
+        let image = decode(&encode(32, 16, &[0x22, 0x11, 0x11], &units, 1)).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (32, 16, 3));
+        let pixel = |x: usize, y: usize| &image.samples[(y * 32 + x) * 3..(y * 32 + x) * 3 + 3];
+        let rgb = |luma: f32| {
+            let (blue, red) = (-10.0, 20.0);
+            [
+                luma + 1.402 * red,
+                luma - 0.344136 * blue - 0.714136 * red,
+                luma + 1.772 * blue,
+            ]
+            .map(|value| value.round() as u16)
+        };
+        assert_eq!(pixel(0, 0), rgb(78.0));
+        assert_eq!(pixel(9, 2), rgb(103.0));
+        assert_eq!(pixel(3, 12), rgb(128.0));
+        assert_eq!(pixel(15, 15), rgb(153.0));
+        assert_eq!(pixel(31, 15), rgb(178.0));
+    }
+
+    #[test]
+    fn test_refusals() {
+        let jpeg = encode(8, 8, &[0x11], &[Unit::Block(0, block(&[0]))], 0);
+        assert_eq!(decode(&jpeg).unwrap().samples, vec![128; 64]);
+        let mut progressive = jpeg.clone();
+        let frame = progressive
+            .windows(2)
+            .position(|w| w == [0xff, 0xc0])
+            .unwrap();
+        progressive[frame + 1] = 0xc2;
+        assert_eq!(
+            decode(&progressive).err(),
+            Some("Only baseline JPEG images are supported")
+        );
+        assert_eq!(decode(&jpeg[..40]).err(), Some("Truncated JPEG image"));
+        assert_eq!(decode(b"GIF89a").err(), Some("Not a JPEG image"));
+    }
+}
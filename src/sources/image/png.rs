@@ -0,0 +1,375 @@
+//! png
+//!
+//! Decoding of PNG images
+//!
+//! every bit depth, color type and interlacing of the specification is understood, palettes
+//! expand to RGB (RGBA with a transparency chunk) and gray samples of less than 8 bits scale
+//! to 8 bits, the transparency of gray and RGB images and ancillary chunks are ignored
+
+use super::inflate::zlib_decompress;
+use super::Image;
+
+const SIGNATURE: [u8; 8] = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a];
+
+/// origin and spacing of the pixels of the 7 passes of Adam7 interlacing
+const ADAM7: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// whether bytes start with the PNG signature
+pub fn is_png(bytes: &[u8]) -> bool {
+    bytes.starts_with(&SIGNATURE)
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+struct Header {
+    width: usize,
+    height: usize,
+    depth: u8,
+    color: u8,
+    interlaced: bool,
+}
+
+impl Header {
+    fn parse(data: &[u8]) -> Result<Self, &'static str> {
+        if data.len() != 13 {
+            return Err("Invalid PNG header");
+        }
+        let width = u32::from_be_bytes(data[0..4].try_into().unwrap()) as usize;
+        let height = u32::from_be_bytes(data[4..8].try_into().unwrap()) as usize;
+        let (depth, color) = (data[8], data[9]);
+        let valid = match color {
+            0 => matches!(depth, 1 | 2 | 4 | 8 | 16),
+            3 => matches!(depth, 1 | 2 | 4 | 8),
+            2 | 4 | 6 => matches!(depth, 8 | 16),
+            _ => false,
+        };
+        if !valid || data[10] != 0 || data[11] != 0 || data[12] > 1 {
+            return Err("Invalid PNG header");
+        }
+        if width == 0 || height == 0 {
+            return Err("Image has no pixels");
+        }
+        Ok(Self {
+            width,
+            height,
+            depth,
+            color,
+            interlaced: data[12] == 1,
+        })
+    }
+    /// samples per pixel stored in the image data
+    fn samples(&self) -> usize {
+        match self.color {
+            2 => 3,
+            4 => 2,
+            6 => 4,
+            _ => 1,
+        }
+    }
+    /// bytes of a row of a number of pixels, without its filter byte
+    fn row_bytes(&self, pixels: usize) -> Option<usize> {
+        let bits = pixels.checked_mul(self.samples() * self.depth as usize)?;
+        Some(bits.div_ceil(8))
+    }
+    /// the passes of the image, with their origin, spacing and size in pixels
+    fn passes(&self) -> Vec<(usize, usize, usize, usize, usize, usize)> {
+        let passes: &[_] = match self.interlaced {
+            true => &ADAM7,
+            false => &[(0, 0, 1, 1)],
+        };
+        passes
+            .iter()
+            .map(|&(x, y, dx, dy)| {
+                let width = (self.width + dx - 1 - x) / dx;
+                let height = (self.height + dy - 1 - y) / dy;
+                (x, y, dx, dy, width, height)
+            })
+            .filter(|pass| pass.4 > 0 && pass.5 > 0)
+            .collect()
+    }
+}
+
+fn paeth(left: u8, up: u8, corner: u8) -> u8 {
+    let estimate = left as i16 + up as i16 - corner as i16;
+    let (to_left, to_up) = ((estimate - left as i16).abs(), (estimate - up as i16).abs());
+    let to_corner = (estimate - corner as i16).abs();
+    if to_left <= to_up && to_left <= to_corner {
+        left
+    } else if to_up <= to_corner {
+        up
+    } else {
+        corner
+    }
+}
+
+/// reverse the filter of a row in place given the previous row of its pass
+fn unfilter(filter: u8, row: &mut [u8], previous: &[u8], step: usize) -> Result<(), &'static str> {
+    for index in 0..row.len() {
+        let left = if index >= step { row[index - step] } else { 0 };
+        let up = previous.get(index).copied().unwrap_or(0);
+        let corner = match index >= step {
+            true => previous.get(index - step).copied().unwrap_or(0),
+            false => 0,
+        };
+        let prediction = match filter {
+            0 => 0,
+            1 => left,
+            2 => up,
+            3 => ((left as u16 + up as u16) / 2) as u8,
+            4 => paeth(left, up, corner),
+            _ => return Err("Invalid PNG filter"),
+        };
+        row[index] = row[index].wrapping_add(prediction);
+    }
+    Ok(())
+}
+
+/// the sample at a position of an unfiltered row
+fn sample(row: &[u8], index: usize, depth: u8) -> u16 {
+    match depth {
+        16 => u16::from_be_bytes([row[2 * index], row[2 * index + 1]]),
+        8 => row[index] as u16,
+        _ => {
+            let bit = index * depth as usize;
+            let shift = 8 - depth as usize - bit % 8;
+            ((row[bit / 8] >> shift) & ((1 << depth) - 1)) as u16
+        }
+    }
+}
+
+/// decode a PNG image
+pub fn decode(bytes: &[u8]) -> Result<Image, &'static str> {
+    if !is_png(bytes) {
+        return Err("Not a PNG image");
+    }
+    let mut rest = &bytes[SIGNATURE.len()..];
+    let mut header = None;
+    let mut palette: Vec<[u16; 4]> = Vec::new();
+    let mut transparent = false;
+    let mut compressed = Vec::new();
+    loop {
+        if rest.len() < 12 {
+            return Err("Truncated PNG image");
+        }
+        let len = u32::from_be_bytes(rest[0..4].try_into().unwrap()) as usize;
+        if rest.len() - 12 < len {
+            return Err("Truncated PNG image");
+        }
+        let (kind, data) = (&rest[4..8], &rest[8..8 + len]);
+        let crc = u32::from_be_bytes(rest[8 + len..12 + len].try_into().unwrap());
+        if crc32(&rest[4..8 + len]) != crc {
+            return Err("PNG chunk fails its checksum");
+        }
+        rest = &rest[12 + len..];
+        match kind {
+            b"IHDR" => header = Some(Header::parse(data)?),
+            b"PLTE" => {
+                if !len.is_multiple_of(3) || len / 3 > 256 {
+                    return Err("Invalid PNG palette");
+                }
+                let colors = data.chunks_exact(3);
+                palette = colors
+                    .map(|color| [color[0] as u16, color[1] as u16, color[2] as u16, 255])
+                    .collect();
+            }
+            b"tRNS" if header.as_ref().is_some_and(|header| header.color == 3) => {
+                if len > palette.len() {
+                    return Err("Invalid PNG transparency");
+                }
+                for (color, alpha) in palette.iter_mut().zip(data) {
+                    color[3] = *alpha as u16;
+                }
+                transparent = true;
+            }
+            b"IDAT" => compressed.extend_from_slice(data),
+            b"IEND" => break,
+            _ if kind[0].is_ascii_uppercase() => return Err("Unknown critical PNG chunk"),
+            _ => {}
+        }
+    }
+    let header = header.ok_or("PNG image has no header")?;
+    if header.color == 3 && palette.is_empty() {
+        return Err("PNG image has no palette");
+    }
+    let passes = header.passes();
+    let mut expected = 0usize;
+    for pass in passes.iter() {
+        let row = header.row_bytes(pass.4).ok_or("Image is too large")?;
+        let size = (row + 1).checked_mul(pass.5).ok_or("Image is too large")?;
+        expected = expected.checked_add(size).ok_or("Image is too large")?;
+    }
+    let filtered = zlib_decompress(&compressed, expected)?;
+    if filtered.len() != expected {
+        return Err("PNG image data does not match its size");
+    }
+    let channels = match (header.color, transparent) {
+        (3, true) => 4,
+        (3, false) => 3,
+        _ => header.samples(),
+    };
+    let pixels = header
+        .width
+        .checked_mul(header.height)
+        .ok_or("Image is too large")?;
+    let mut samples = vec![0u16; pixels.checked_mul(channels).ok_or("Image is too large")?];
+    let step = (header.samples() * header.depth as usize).div_ceil(8);
+    let mut offset = 0;
+    for (x, y, dx, dy, width, height) in passes {
+        let len = header.row_bytes(width).unwrap();
+        let mut previous = vec![0u8; len];
+        for row in 0..height {
+            let filter = filtered[offset];
+            let mut current = filtered[offset + 1..offset + 1 + len].to_vec();
+            offset += len + 1;
+            unfilter(filter, &mut current, &previous, step)?;
+            for column in 0..width {
+                let pixel = (y + row * dy) * header.width + x + column * dx;
+                let out = &mut samples[pixel * channels..(pixel + 1) * channels];
+                if header.color == 3 {
+                    let index = sample(&current, column, header.depth) as usize;
+                    let color = palette.get(index).ok_or("PNG palette index out of range")?;
+                    out.copy_from_slice(&color[..channels]);
+                    continue;
+                }
+                for (channel, value) in out.iter_mut().enumerate() {
+                    let value_at = column * channels + channel;
+                    *value = sample(&current, value_at, header.depth);
+                    if header.depth < 8 {
+                        *value = *value * 255 / ((1 << header.depth) - 1);
+                    }
+                }
+            }
+            previous = current;
+        }
+    }
+    Ok(Image {
+        width: header.width,
+        height: header.height,
+        channels,
+        depth: header.depth.max(8),
+        samples,
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// a PNG chunk with its checksum
+    fn chunk(kind: &[u8; 4], data: &[u8]) -> Vec<u8> {
+        let mut chunk = (data.len() as u32).to_be_bytes().to_vec();
+        chunk.extend_from_slice(kind);
+        chunk.extend_from_slice(data);
+        chunk.extend_from_slice(&crc32(&chunk[4..]).to_be_bytes());
+        chunk
+    }
+
+    /// a PNG image holding filtered rows in a stored zlib stream
+    pub(crate) fn encode(header: [u8; 13], extra: &[Vec<u8>], filtered: &[u8]) -> Vec<u8> {
+        let mut compressed = vec![0x78, 0x01];
+        let mut blocks = filtered.chunks(65535).peekable();
+        if filtered.is_empty() {
+            compressed.extend_from_slice(&[1, 0, 0, 0xff, 0xff]);
+        }
+        while let Some(block) = blocks.next() {
+            compressed.push(blocks.peek().is_none() as u8);
+            let len = block.len() as u16;
+            compressed.extend_from_slice(&len.to_le_bytes());
+            compressed.extend_from_slice(&(!len).to_le_bytes());
+            compressed.extend_from_slice(block);
+        }
+        let (mut a, mut b) = (1u32, 0u32);
+        for byte in filtered {
+            a = (a + *byte as u32) % 65521;
+            b = (b + a) % 65521;
+        }
+        compressed.extend_from_slice(&((b << 16) | a).to_be_bytes());
+        let mut png = SIGNATURE.to_vec();
+        png.extend(chunk(b"IHDR", &header));
+        for extra in extra {
+            png.extend_from_slice(extra);
+        }
+        png.extend(chunk(b"IDAT", &compressed));
+        png.extend(chunk(b"IEND", &[]));
+        png
+    }
+
+    pub(crate) fn header(width: u32, height: u32, depth: u8, color: u8, interlace: u8) -> [u8; 13] {
+        let mut header = [0; 13];
+        header[0..4].copy_from_slice(&width.to_be_bytes());
+        header[4..8].copy_from_slice(&height.to_be_bytes());
+        header[8..].copy_from_slice(&[depth, color, 0, 0, interlace]);
+        header
+    }
+
+    #[test]
+    fn test_filters() {
+        // 3x2 RGB rows, the first with the sub filter and the second with paeth
+        let filtered = [
+            1, 10, 20, 30, 5, 5, 5, 5, 5, 5, //
+            4, 1, 1, 1, 0, 0, 0, 2, 2, 2,
+        ];
+        let png = encode(header(3, 2, 8, 2, 0), &[], &filtered);
+        let image = decode(&png).unwrap();
+        assert_eq!((image.width, image.height, image.channels), (3, 2, 3));
+        assert_eq!(
+            image.samples,
+            vec![10, 20, 30, 15, 25, 35, 20, 30, 40, 11, 21, 31, 15, 25, 35, 22, 32, 42]
+        );
+        let mut corrupted = png.clone();
+        corrupted[20] ^= 1;
+        assert_eq!(
+            decode(&corrupted).err(),
+            Some("PNG chunk fails its checksum")
+        );
+        assert_eq!(decode(&png[..40]).err(), Some("Truncated PNG image"));
+    }
+
+    #[test]
+    fn test_palette_and_depths() {
+        let palette = chunk(b"PLTE", &[0, 0, 0, 255, 0, 0, 0, 0, 255]);
+        let alpha = chunk(b"tRNS", &[0, 128]);
+        // 2 bit indices 0, 1, 2, 1 packed in one byte
+        let png = encode(header(4, 1, 2, 3, 0), &[palette, alpha], &[0, 0b0001_1001]);
+        let image = decode(&png).unwrap();
+        assert_eq!(image.channels, 4);
+        let expected = [0, 0, 0, 0, 255, 0, 0, 128, 0, 0, 255, 255, 255, 0, 0, 128];
+        assert_eq!(image.samples, expected);
+        let gray = encode(header(4, 1, 1, 0, 0), &[], &[0, 0b1010_0000]);
+        assert_eq!(decode(&gray).unwrap().samples, vec![255, 0, 255, 0]);
+        let deep = encode(header(1, 1, 16, 4, 0), &[], &[0, 0x12, 0x34, 0xff, 0xff]);
+        let image = decode(&deep).unwrap();
+        assert_eq!(
+            (image.depth, image.samples.clone()),
+            (16, vec![0x1234, 0xffff])
+        );
+        let weird = encode(header(1, 1, 4, 2, 0), &[], &[0, 0, 0]);
+        assert_eq!(decode(&weird).err(), Some("Invalid PNG header"));
+    }
+
+    #[test]
+    fn test_interlaced() {
+        // a 3x3 gray image, Adam7 stores pixel (0, 0) in pass 1, (2, 0) in pass 4, (0, 2)
+        // and (2, 2) in pass 5, (1, 0) and (1, 2) in pass 6 and the middle row in pass 7
+        let filtered = [0, 1, 0, 3, 0, 7, 9, 0, 2, 0, 8, 0, 4, 5, 6];
+        let image = decode(&encode(header(3, 3, 8, 0, 1), &[], &filtered)).unwrap();
+        assert_eq!(image.samples, vec![1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+}
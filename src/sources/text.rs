@@ -0,0 +1,317 @@
+//! text
+//!
+//! Sources decoding text files and byte streams into lines
+//!
+//! the encoding is sniffed from a byte order mark at the start of the input (UTF-8, UTF-16
+//! little or big endian) and falls back to the default encoding of the source otherwise,
+//! malformed sequences decode to U+FFFD, lines end at `\n` with a trailing `\r` removed
+
+use crate::Source;
+use bytes::Bytes;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// size of the chunks read from files
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// TextEncoding
+/// The character encodings understood by text sources
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum TextEncoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    /// ISO-8859-1, every byte is the code point of the same value
+    Latin1,
+}
+
+impl TextEncoding {
+    /// the encoding announced by a byte order mark at the start of some bytes and the length
+    /// of the mark, None if the bytes do not start with one
+    pub fn sniff(bytes: &[u8]) -> Option<(Self, usize)> {
+        if bytes.starts_with(&[0xEF, 0xBB, 0xBF]) {
+            Some((TextEncoding::Utf8, 3))
+        } else if bytes.starts_with(&[0xFF, 0xFE]) {
+            Some((TextEncoding::Utf16Le, 2))
+        } else if bytes.starts_with(&[0xFE, 0xFF]) {
+            Some((TextEncoding::Utf16Be, 2))
+        } else {
+            None
+        }
+    }
+}
+
+/// TextDecoder
+/// An incremental decoder of bytes arriving in chunks, sequences split between chunks are
+/// held back until the next one
+#[derive(Clone, Debug)]
+pub struct TextDecoder {
+    fallback: TextEncoding,
+    encoding: Option<TextEncoding>,
+    pending: Vec<u8>,
+}
+
+impl TextDecoder {
+    /// constructor decoding with a fallback encoding when the input has no byte order mark
+    pub fn new(fallback: TextEncoding) -> Self {
+        Self {
+            fallback,
+            encoding: None,
+            pending: Vec::new(),
+        }
+    }
+    /// the encoding in use, None until enough bytes were seen to sniff it
+    pub fn encoding(&self) -> Option<TextEncoding> {
+        self.encoding
+    }
+    /// decode a chunk, `last` flushes incomplete sequences as U+FFFD
+    pub fn decode(&mut self, chunk: &[u8], last: bool) -> String {
+        self.pending.extend_from_slice(chunk);
+        let encoding = match self.encoding {
+            Some(encoding) => encoding,
+            // a shorter input could still be the prefix of a byte order mark
+            None if self.pending.len() < 3 && !last => return String::new(),
+            None => {
+                let (encoding, mark) =
+                    TextEncoding::sniff(&self.pending).unwrap_or((self.fallback, 0));
+                self.pending.drain(..mark);
+                self.encoding = Some(encoding);
+                encoding
+            }
+        };
+        let mut text = String::new();
+        let used = match encoding {
+            TextEncoding::Utf8 => decode_utf8(&self.pending, last, &mut text),
+            TextEncoding::Utf16Le => {
+                decode_utf16(&self.pending, u16::from_le_bytes, last, &mut text)
+            }
+            TextEncoding::Utf16Be => {
+                decode_utf16(&self.pending, u16::from_be_bytes, last, &mut text)
+            }
+            TextEncoding::Latin1 => {
+                text.extend(self.pending.iter().map(|byte| *byte as char));
+                self.pending.len()
+            }
+        };
+        self.pending.drain(..used);
+        text
+    }
+}
+
+/// decode the complete UTF-8 sequences of some bytes, returns the number of bytes consumed
+fn decode_utf8(bytes: &[u8], last: bool, text: &mut String) -> usize {
+    let mut rest = bytes;
+    loop {
+        match std::str::from_utf8(rest) {
+            Ok(valid) => {
+                text.push_str(valid);
+                return bytes.len();
+            }
+            Err(error) => {
+                let (valid, invalid) = rest.split_at(error.valid_up_to());
+                text.push_str(std::str::from_utf8(valid).unwrap());
+                match error.error_len() {
+                    Some(len) => {
+                        text.push(char::REPLACEMENT_CHARACTER);
+                        rest = &invalid[len..];
+                    }
+                    None if last => {
+                        text.push(char::REPLACEMENT_CHARACTER);
+                        return bytes.len();
+                    }
+                    None => return bytes.len() - invalid.len(),
+                }
+            }
+        }
+    }
+}
+
+/// decode the complete UTF-16 code units of some bytes, returns the number of bytes consumed
+fn decode_utf16(bytes: &[u8], unit: fn([u8; 2]) -> u16, last: bool, text: &mut String) -> usize {
+    let mut units: Vec<u16> = bytes
+        .chunks_exact(2)
+        .map(|pair| unit([pair[0], pair[1]]))
+        .collect();
+    let mut used = units.len() * 2;
+    // keep a high surrogate waiting for its pair
+    if !last && units.last().is_some_and(|u| (0xD800..0xDC00).contains(u)) {
+        units.pop();
+        used -= 2;
+    }
+    text.extend(char::decode_utf16(units).map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER)));
+    if last && used < bytes.len() {
+        text.push(char::REPLACEMENT_CHARACTER);
+        used = bytes.len();
+    }
+    used
+}
+
+/// the input of a text source
+enum TextInput {
+    File(PathBuf),
+    Bytes(Bytes),
+    Stream(Rc<dyn Source<Bytes>>),
+}
+
+/// TextSource
+/// A source decoding a file or a stream of bytes into its lines
+pub struct TextSource {
+    input: TextInput,
+    fallback: TextEncoding,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl TextSource {
+    fn with_input(input: TextInput) -> Self {
+        Self {
+            input,
+            fallback: TextEncoding::default(),
+            error: Rc::new(Cell::new(None)),
+        }
+    }
+    /// constructor reading a file each time the source is streamed
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        Self::with_input(TextInput::File(path.as_ref().to_path_buf()))
+    }
+    /// constructor decoding bytes held in memory
+    pub fn from_bytes<B: Into<Bytes>>(bytes: B) -> Self {
+        Self::with_input(TextInput::Bytes(bytes.into()))
+    }
+    /// constructor decoding the chunks of a byte stream
+    pub fn from_source(input: Rc<dyn Source<Bytes>>) -> Self {
+        Self::with_input(TextInput::Stream(input))
+    }
+    /// set the encoding of inputs without a byte order mark (UTF-8 by default)
+    pub fn with_encoding(mut self, encoding: TextEncoding) -> Self {
+        self.fallback = encoding;
+        self
+    }
+    /// the read failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl Source<String> for TextSource {
+    fn stream(&self) -> Box<dyn Stream<Item = String>> {
+        let error = self.error.clone();
+        error.set(None);
+        let chunks: Pin<Box<dyn Stream<Item = Bytes>>> = match &self.input {
+            TextInput::Bytes(bytes) => {
+                Box::pin(stream::once(futures::future::ready(bytes.clone())))
+            }
+            TextInput::Stream(input) => Pin::from(input.stream()),
+            TextInput::File(path) => match File::open(path) {
+                Ok(file) => Box::pin(stream::unfold(Some(file), move |file| {
+                    let error = error.clone();
+                    async move {
+                        let mut file = file?;
+                        let mut chunk = vec![0; CHUNK_SIZE];
+                        match file.read(&mut chunk) {
+                            Ok(0) => None,
+                            Ok(len) => {
+                                chunk.truncate(len);
+                                Some((Bytes::from(chunk), Some(file)))
+                            }
+                            Err(_) => {
+                                error.set(Some("Failure to read text file"));
+                                None
+                            }
+                        }
+                    }
+                })),
+                Err(_) => {
+                    error.set(Some("Failure to open text file"));
+                    return Box::new(stream::empty());
+                }
+            },
+        };
+        let mut decoder = TextDecoder::new(self.fallback);
+        let mut line = String::new();
+        let lines = chunks
+            .map(Some)
+            .chain(stream::once(futures::future::ready(None)))
+            .map(move |chunk| {
+                let last = chunk.is_none();
+                let text = decoder.decode(chunk.as_deref().unwrap_or_default(), last);
+                let mut lines = Vec::new();
+                for character in text.chars() {
+                    if character == '\n' {
+                        if line.ends_with('\r') {
+                            line.pop();
+                        }
+                        lines.push(std::mem::take(&mut line));
+                    } else {
+                        line.push(character);
+                    }
+                }
+                if last && !line.is_empty() {
+                    lines.push(std::mem::take(&mut line));
+                }
+                stream::iter(lines)
+            })
+            .flatten();
+        Box::new(lines)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    fn lines(source: &TextSource) -> Vec<String> {
+        block_on(Pin::from(source.stream()).collect())
+    }
+
+    #[test]
+    fn test_sniffed_encodings() {
+        let utf8 = TextSource::from_bytes(b"\xEF\xBB\xBFcaf\xC3\xA9\r\nok".to_vec());
+        assert_eq!(lines(&utf8), vec!["café", "ok"]);
+        let mut utf16: Vec<u8> = vec![0xFF, 0xFE];
+        utf16.extend("a\n😀".encode_utf16().flat_map(u16::to_le_bytes));
+        assert_eq!(lines(&TextSource::from_bytes(utf16)), vec!["a", "😀"]);
+        let mut utf16: Vec<u8> = vec![0xFE, 0xFF];
+        utf16.extend("é".encode_utf16().flat_map(u16::to_be_bytes));
+        assert_eq!(lines(&TextSource::from_bytes(utf16)), vec!["é"]);
+        let latin1 =
+            TextSource::from_bytes(b"caf\xE9".to_vec()).with_encoding(TextEncoding::Latin1);
+        assert_eq!(lines(&latin1), vec!["café"]);
+        assert_eq!(
+            lines(&TextSource::from_bytes(b"caf\xE9".to_vec())),
+            vec!["caf\u{FFFD}"]
+        );
+    }
+
+    #[test]
+    fn test_split_chunks() {
+        let text = "\u{FEFF}héllo\nwörld 😀\n".encode_utf16();
+        let bytes: Vec<u8> = text.flat_map(u16::to_be_bytes).collect();
+        let chunks: Vec<Bytes> = bytes.chunks(3).map(Bytes::copy_from_slice).collect();
+        let source = TextSource::from_source(Rc::new(MockSource::new().items(chunks)));
+        assert_eq!(lines(&source), vec!["héllo", "wörld 😀"]);
+        let utf8 = "a😀b".as_bytes();
+        let chunks: Vec<Bytes> = utf8.chunks(1).map(Bytes::copy_from_slice).collect();
+        let source = TextSource::from_source(Rc::new(MockSource::new().items(chunks)));
+        assert_eq!(lines(&source), vec!["a😀b"]);
+    }
+
+    #[test]
+    fn test_text_file() {
+        let path = std::env::temp_dir().join("bitvortex_text_source.txt");
+        std::fs::write(&path, b"first\nsecond\n").unwrap();
+        let source = TextSource::open(&path);
+        assert_eq!(lines(&source), vec!["first", "second"]);
+        std::fs::remove_file(&path).unwrap();
+        assert!(lines(&source).is_empty());
+        assert_eq!(source.error(), Some("Failure to open text file"));
+    }
+}
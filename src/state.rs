@@ -0,0 +1,292 @@
+//! state
+//!
+//! Keyed state held by stateful pipes in pluggable stores
+//!
+//! stores are shared behind `SharedStateStore` handles so the state of a running pipe can
+//! be sized, inspected, checkpointed with `snapshot` and reloaded with `restore`, all store
+//! operations are fallible so backends can live outside of memory
+
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// ttl
+/// Sub module for an in memory state store with expiry and eviction
+pub mod ttl;
+
+pub use ttl::TtlStateStore;
+
+/// sled
+/// Sub module for a disk backed state store
+#[cfg(feature = "sled")]
+pub mod sled;
+
+#[cfg(feature = "sled")]
+pub use self::sled::SledStateStore;
+
+/// StateStore
+/// A key value store holding the state of a stateful pipe
+pub trait StateStore<K, V> {
+    /// the value held for a key
+    fn get(&self, key: &K) -> Result<Option<V>, &'static str>;
+    /// set the value of a key, returning the previous one
+    fn put(&mut self, key: K, value: V) -> Result<Option<V>, &'static str>;
+    /// remove a key, returning its value
+    fn remove(&mut self, key: &K) -> Result<Option<V>, &'static str>;
+    /// every entry of the store, in no particular order
+    fn iter(&self) -> Box<dyn Iterator<Item = (K, V)> + '_>;
+    /// the number of entries
+    fn len(&self) -> usize;
+    /// remove every entry
+    fn clear(&mut self) -> Result<(), &'static str>;
+    /// whether the store holds no entry
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+    /// a copy of every entry, to checkpoint the store
+    fn snapshot(&self) -> Vec<(K, V)> {
+        self.iter().collect()
+    }
+    /// replace the content of the store with a snapshot
+    fn restore(&mut self, snapshot: Vec<(K, V)>) -> Result<(), &'static str> {
+        self.clear()?;
+        for (key, value) in snapshot {
+            self.put(key, value)?;
+        }
+        Ok(())
+    }
+}
+
+/// a state store shared between a pipe and its owner
+pub type SharedStateStore<K, V> = Rc<RefCell<dyn StateStore<K, V>>>;
+
+/// MemoryStateStore
+/// A state store held in a hash map
+#[derive(Clone, Debug)]
+pub struct MemoryStateStore<K, V> {
+    entries: HashMap<K, V>,
+}
+
+impl<K, V> MemoryStateStore<K, V> {
+    /// empty constructor
+    pub fn new() -> Self {
+        Self {
+            entries: HashMap::new(),
+        }
+    }
+}
+
+impl<K, V> Default for MemoryStateStore<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> StateStore<K, V> for MemoryStateStore<K, V> {
+    fn get(&self, key: &K) -> Result<Option<V>, &'static str> {
+        Ok(self.entries.get(key).cloned())
+    }
+    fn put(&mut self, key: K, value: V) -> Result<Option<V>, &'static str> {
+        Ok(self.entries.insert(key, value))
+    }
+    fn remove(&mut self, key: &K) -> Result<Option<V>, &'static str> {
+        Ok(self.entries.remove(key))
+    }
+    fn iter(&self) -> Box<dyn Iterator<Item = (K, V)> + '_> {
+        Box::new(
+            self.entries
+                .iter()
+                .map(|(key, value)| (key.clone(), value.clone())),
+        )
+    }
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+    fn clear(&mut self) -> Result<(), &'static str> {
+        self.entries.clear();
+        Ok(())
+    }
+}
+
+/// Stateful
+/// Pipes keeping keyed state in a state store
+pub trait Stateful<K, V> {
+    /// the store holding the state
+    fn state_store(&self) -> SharedStateStore<K, V>;
+    /// replace the store holding the state, the state is not carried over
+    fn set_state_store(&mut self, store: SharedStateStore<K, V>);
+}
+
+type KeyFunction<T, K> = Rc<dyn Fn(&T) -> K>;
+type UpdateFunction<T, S, OutT> = Rc<dyn Fn(Option<S>, T) -> (Option<S>, Option<OutT>)>;
+
+/// KeyedStatePipe
+/// A pipe processing every item along with the state held for its key, the update
+/// function returns the new state of the key (`None` clearing it) and an optional output
+///
+/// a failing store ends the stream, the failure is then available from `error`
+pub struct KeyedStatePipe<T, K, S, OutT> {
+    key: KeyFunction<T, K>,
+    update: UpdateFunction<T, S, OutT>,
+    store: SharedStateStore<K, S>,
+    error: Rc<Cell<Option<&'static str>>>,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T, K, S, OutT> KeyedStatePipe<T, K, S, OutT>
+where
+    K: Hash + Eq + Clone + 'static,
+    S: Clone + 'static,
+{
+    /// constructor keeping the state in memory
+    pub fn new<F, U>(key: F, update: U) -> Self
+    where
+        F: Fn(&T) -> K + 'static,
+        U: Fn(Option<S>, T) -> (Option<S>, Option<OutT>) + 'static,
+    {
+        Self {
+            key: Rc::new(key),
+            update: Rc::new(update),
+            store: Rc::new(RefCell::new(MemoryStateStore::new())),
+            error: Rc::new(Cell::new(None)),
+            input: None,
+        }
+    }
+}
+
+impl<T, K, S, OutT> KeyedStatePipe<T, K, S, OutT> {
+    /// the store failure that ended the last stream
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl<T, K, S, OutT> Stateful<K, S> for KeyedStatePipe<T, K, S, OutT> {
+    fn state_store(&self) -> SharedStateStore<K, S> {
+        self.store.clone()
+    }
+    fn set_state_store(&mut self, store: SharedStateStore<K, S>) {
+        self.store = store;
+    }
+}
+
+impl<T: 'static, K: 'static, S: 'static, OutT: 'static> Source<OutT>
+    for KeyedStatePipe<T, K, S, OutT>
+{
+    fn stream(&self) -> Box<dyn Stream<Item = OutT>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let key = self.key.clone();
+        let update = self.update.clone();
+        let store = self.store.clone();
+        let error = self.error.clone();
+        error.set(None);
+        let step = move |item: T| -> Result<Option<OutT>, &'static str> {
+            let key = key(&item);
+            let mut store = store.borrow_mut();
+            let (state, output) = update(store.get(&key)?, item);
+            match state {
+                Some(state) => store.put(key, state)?,
+                None => store.remove(&key)?,
+            };
+            Ok(output)
+        };
+        Box::new(
+            input
+                .map(step)
+                .take_while(move |result| {
+                    if let Err(failure) = result {
+                        error.set(Some(*failure));
+                    }
+                    futures::future::ready(result.is_ok())
+                })
+                .filter_map(|result| futures::future::ready(result.ok().flatten())),
+        )
+    }
+}
+
+impl<T: 'static, K: 'static, S: 'static, OutT: 'static> Pipe<T, OutT>
+    for KeyedStatePipe<T, K, S, OutT>
+{
+    input_connection!(T);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{collect, MockSource};
+
+    #[test]
+    fn test_memory_state_store() {
+        let mut store = MemoryStateStore::new();
+        assert!(store.is_empty());
+        assert_eq!(store.put("a", 1), Ok(None));
+        assert_eq!(store.put("a", 2), Ok(Some(1)));
+        store.put("b", 3).unwrap();
+        assert_eq!(store.get(&"a"), Ok(Some(2)));
+        let mut snapshot = store.snapshot();
+        snapshot.sort();
+        assert_eq!(snapshot, vec![("a", 2), ("b", 3)]);
+        assert_eq!(store.remove(&"a"), Ok(Some(2)));
+        assert_eq!(store.len(), 1);
+        store.restore(snapshot).unwrap();
+        assert_eq!(store.len(), 2, "Failure to restore snapshot");
+    }
+
+    /// a store refusing every write
+    struct ReadOnlyStore;
+
+    impl StateStore<char, u32> for ReadOnlyStore {
+        fn get(&self, _key: &char) -> Result<Option<u32>, &'static str> {
+            Ok(None)
+        }
+        fn put(&mut self, _key: char, _value: u32) -> Result<Option<u32>, &'static str> {
+            Err("Read only store")
+        }
+        fn remove(&mut self, _key: &char) -> Result<Option<u32>, &'static str> {
+            Ok(None)
+        }
+        fn iter(&self) -> Box<dyn Iterator<Item = (char, u32)> + '_> {
+            Box::new(std::iter::empty())
+        }
+        fn len(&self) -> usize {
+            0
+        }
+        fn clear(&mut self) -> Result<(), &'static str> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_keyed_state_pipe() {
+        let mut pipe = KeyedStatePipe::new(
+            |word: &char| *word,
+            |count: Option<u32>, word| {
+                let count = count.unwrap_or(0) + 1;
+                (Some(count), (count == 2).then_some(word))
+            },
+        );
+        pipe.pipe(Rc::new(
+            MockSource::new().items(['a', 'b', 'a', 'c', 'b', 'a']),
+        ))
+        .unwrap();
+        assert_eq!(
+            collect(&pipe),
+            vec!['a', 'b'],
+            "Failure to track duplicates"
+        );
+        let store = pipe.state_store();
+        assert_eq!(store.borrow().len(), 3);
+        assert_eq!(store.borrow().get(&'a'), Ok(Some(3)));
+        pipe.set_state_store(Rc::new(RefCell::new(ReadOnlyStore)));
+        assert!(collect(&pipe).is_empty());
+        assert_eq!(pipe.error(), Some("Read only store"));
+    }
+}
@@ -0,0 +1,138 @@
+//! sled
+//!
+//! A disk backed state store on a sled database (requires the `sled` feature)
+//!
+//! keys and values are encoded with `BinaryElement`, so the state of a pipeline reopening
+//! the same database carries over restarts, entries iterate in the byte order of their
+//! encoded keys
+
+use super::StateStore;
+use crate::data_bucket::encoding::BinaryElement;
+use std::marker::PhantomData;
+use std::path::Path;
+
+fn encode<T: BinaryElement + Clone>(value: &T) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    T::write_bytes(std::slice::from_ref(value), &mut bytes);
+    bytes
+}
+
+fn decode<T: BinaryElement>(bytes: &[u8]) -> Result<T, &'static str> {
+    let mut values = T::read_bytes(bytes)?;
+    if values.len() != 1 {
+        return Err("Stored entry does not hold a single element");
+    }
+    Ok(values.pop().unwrap())
+}
+
+/// SledStateStore
+/// A state store persisting its entries in a sled tree
+pub struct SledStateStore<K, V> {
+    tree: sled::Tree,
+    entries: PhantomData<(K, V)>,
+}
+
+impl<K, V> SledStateStore<K, V> {
+    /// constructor opening (or creating) a database at a path
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, &'static str> {
+        let db = sled::open(path).map_err(|_| "Failure to open state database")?;
+        Ok(Self::from_tree((*db).clone()))
+    }
+    /// constructor for a database deleted when dropped
+    pub fn temporary() -> Result<Self, &'static str> {
+        let db = sled::Config::new()
+            .temporary(true)
+            .open()
+            .map_err(|_| "Failure to open state database")?;
+        Ok(Self::from_tree((*db).clone()))
+    }
+    /// constructor over a tree of an open database, to keep several stores in one database
+    pub fn from_tree(tree: sled::Tree) -> Self {
+        Self {
+            tree,
+            entries: PhantomData,
+        }
+    }
+    /// write every pending change to disk
+    pub fn flush(&self) -> Result<(), &'static str> {
+        self.tree
+            .flush()
+            .map(|_| ())
+            .map_err(|_| "Failure to flush state database")
+    }
+}
+
+fn previous<V: BinaryElement>(
+    bytes: sled::Result<Option<sled::IVec>>,
+) -> Result<Option<V>, &'static str> {
+    match bytes.map_err(|_| "Failure to access state database")? {
+        Some(bytes) => decode(&bytes).map(Some),
+        None => Ok(None),
+    }
+}
+
+impl<K, V> StateStore<K, V> for SledStateStore<K, V>
+where
+    K: BinaryElement + Clone,
+    V: BinaryElement + Clone,
+{
+    fn get(&self, key: &K) -> Result<Option<V>, &'static str> {
+        previous(self.tree.get(encode(key)))
+    }
+    fn put(&mut self, key: K, value: V) -> Result<Option<V>, &'static str> {
+        previous(self.tree.insert(encode(&key), encode(&value)))
+    }
+    fn remove(&mut self, key: &K) -> Result<Option<V>, &'static str> {
+        previous(self.tree.remove(encode(key)))
+    }
+    /// entries failing to read or decode are skipped
+    fn iter(&self) -> Box<dyn Iterator<Item = (K, V)> + '_> {
+        Box::new(self.tree.iter().filter_map(|entry| {
+            let (key, value) = entry.ok()?;
+            Some((decode(&key).ok()?, decode(&value).ok()?))
+        }))
+    }
+    fn len(&self) -> usize {
+        self.tree.len()
+    }
+    fn clear(&mut self) -> Result<(), &'static str> {
+        self.tree
+            .clear()
+            .map_err(|_| "Failure to clear state database")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sled_state_survives_reopening() {
+        let path = std::env::temp_dir().join(format!("bitvortex-state-{}", std::process::id()));
+        {
+            let mut store = SledStateStore::<String, i64>::open(&path).unwrap();
+            store.clear().unwrap();
+            assert_eq!(store.put("a".to_string(), 1), Ok(None));
+            assert_eq!(store.put("a".to_string(), 2), Ok(Some(1)));
+            store.put("b".to_string(), -3).unwrap();
+            store.flush().unwrap();
+        }
+        let mut store = SledStateStore::<String, i64>::open(&path).unwrap();
+        assert_eq!(
+            store.get(&"a".to_string()),
+            Ok(Some(2)),
+            "State lost on restart"
+        );
+        assert_eq!(
+            store.snapshot(),
+            vec![("a".to_string(), 2), ("b".to_string(), -3)]
+        );
+        assert_eq!(store.remove(&"b".to_string()), Ok(Some(-3)));
+        assert_eq!(store.len(), 1);
+        drop(store);
+        std::fs::remove_dir_all(&path).unwrap();
+        let mut temporary = SledStateStore::<u32, f64>::temporary().unwrap();
+        temporary.put(7, 0.5).unwrap();
+        assert_eq!(temporary.get(&7), Ok(Some(0.5)));
+    }
+}
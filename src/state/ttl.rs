@@ -0,0 +1,228 @@
+//! ttl
+//!
+//! An in memory state store forgetting entries after a time to live and evicting the least
+//! recently used entries beyond a maximum size
+//!
+//! the time to live of an entry counts from its last write, expired entries are dropped
+//! lazily when the store is accessed, reads and writes both count as uses
+
+use super::StateStore;
+use std::cell::RefCell;
+use std::collections::{BTreeMap, HashMap};
+use std::hash::Hash;
+use std::time::{Duration, Instant};
+
+struct Entry<V> {
+    value: V,
+    /// the tick of the write, identifying the entry in the expiries
+    written: u64,
+    /// the tick of the last use, identifying the entry in the recency order
+    used: u64,
+    expires: Option<Instant>,
+}
+
+struct Entries<K, V> {
+    entries: HashMap<K, Entry<V>>,
+    recency: BTreeMap<u64, K>,
+    expiries: BTreeMap<(Instant, u64), K>,
+    tick: u64,
+    evictions: usize,
+}
+
+impl<K: Hash + Eq + Clone, V> Entries<K, V> {
+    fn next_tick(&mut self) -> u64 {
+        self.tick += 1;
+        self.tick
+    }
+    fn touch(&mut self, key: &K) {
+        let tick = self.next_tick();
+        if let Some(entry) = self.entries.get_mut(key) {
+            self.recency.remove(&entry.used);
+            entry.used = tick;
+            self.recency.insert(tick, key.clone());
+        }
+    }
+    fn take(&mut self, key: &K) -> Option<V> {
+        let entry = self.entries.remove(key)?;
+        self.recency.remove(&entry.used);
+        if let Some(expires) = entry.expires {
+            self.expiries.remove(&(expires, entry.written));
+        }
+        Some(entry.value)
+    }
+    fn purge_expired(&mut self, now: Instant) {
+        while let Some(entry) = self.expiries.first_entry() {
+            if entry.key().0 > now {
+                break;
+            }
+            let key = entry.remove();
+            self.take(&key);
+            self.evictions += 1;
+        }
+    }
+    fn insert(&mut self, key: K, value: V, ttl: Option<Duration>, max: Option<usize>) -> Option<V> {
+        let now = Instant::now();
+        self.purge_expired(now);
+        let previous = self.take(&key);
+        let tick = self.next_tick();
+        let expires = ttl.map(|ttl| now + ttl);
+        if let Some(expires) = expires {
+            self.expiries.insert((expires, tick), key.clone());
+        }
+        self.recency.insert(tick, key.clone());
+        self.entries.insert(
+            key,
+            Entry {
+                value,
+                written: tick,
+                used: tick,
+                expires,
+            },
+        );
+        while max.is_some_and(|max| self.entries.len() > max) {
+            let (_, oldest) = self.recency.pop_first().unwrap();
+            self.take(&oldest);
+            self.evictions += 1;
+        }
+        previous
+    }
+}
+
+/// TtlStateStore
+/// A state store held in memory with per entry time to live and least recently used
+/// eviction beyond a maximum number of entries
+pub struct TtlStateStore<K, V> {
+    ttl: Option<Duration>,
+    max_entries: Option<usize>,
+    entries: RefCell<Entries<K, V>>,
+}
+
+impl<K, V> TtlStateStore<K, V> {
+    /// constructor for a store keeping entries forever
+    pub fn new() -> Self {
+        Self {
+            ttl: None,
+            max_entries: None,
+            entries: RefCell::new(Entries {
+                entries: HashMap::new(),
+                recency: BTreeMap::new(),
+                expiries: BTreeMap::new(),
+                tick: 0,
+                evictions: 0,
+            }),
+        }
+    }
+    /// set the time to live of the entries written without one
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = Some(ttl);
+        self
+    }
+    /// set the number of entries above which the least recently used are evicted
+    pub fn with_max_entries(mut self, max_entries: usize) -> Result<Self, &'static str> {
+        if max_entries == 0 {
+            return Err("Maximum entry count must be strictly positive");
+        }
+        self.max_entries = Some(max_entries);
+        Ok(self)
+    }
+    /// the number of entries expired or evicted so far
+    pub fn evictions(&self) -> usize {
+        self.entries.borrow().evictions
+    }
+}
+
+impl<K: Hash + Eq + Clone, V> TtlStateStore<K, V> {
+    /// set the value of a key with its own time to live, returning the previous value
+    pub fn put_with_ttl(&mut self, key: K, value: V, ttl: Duration) -> Option<V> {
+        self.entries
+            .get_mut()
+            .insert(key, value, Some(ttl), self.max_entries)
+    }
+    /// drop every expired entry
+    pub fn purge_expired(&mut self) {
+        self.entries.get_mut().purge_expired(Instant::now());
+    }
+}
+
+impl<K, V> Default for TtlStateStore<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: Hash + Eq + Clone, V: Clone> StateStore<K, V> for TtlStateStore<K, V> {
+    fn get(&self, key: &K) -> Result<Option<V>, &'static str> {
+        let mut entries = self.entries.borrow_mut();
+        entries.purge_expired(Instant::now());
+        entries.touch(key);
+        Ok(entries.entries.get(key).map(|entry| entry.value.clone()))
+    }
+    fn put(&mut self, key: K, value: V) -> Result<Option<V>, &'static str> {
+        Ok(self
+            .entries
+            .get_mut()
+            .insert(key, value, self.ttl, self.max_entries))
+    }
+    fn remove(&mut self, key: &K) -> Result<Option<V>, &'static str> {
+        Ok(self.entries.get_mut().take(key))
+    }
+    /// entries by least recent use
+    fn iter(&self) -> Box<dyn Iterator<Item = (K, V)> + '_> {
+        let mut entries = self.entries.borrow_mut();
+        entries.purge_expired(Instant::now());
+        let items: Vec<(K, V)> = entries
+            .recency
+            .values()
+            .map(|key| (key.clone(), entries.entries[key].value.clone()))
+            .collect();
+        Box::new(items.into_iter())
+    }
+    fn len(&self) -> usize {
+        let mut entries = self.entries.borrow_mut();
+        entries.purge_expired(Instant::now());
+        entries.entries.len()
+    }
+    fn clear(&mut self) -> Result<(), &'static str> {
+        let entries = self.entries.get_mut();
+        entries.entries.clear();
+        entries.recency.clear();
+        entries.expiries.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lru_eviction() {
+        let mut store = TtlStateStore::new().with_max_entries(2).unwrap();
+        store.put("a", 1).unwrap();
+        store.put("b", 2).unwrap();
+        assert_eq!(store.get(&"a"), Ok(Some(1)));
+        store.put("c", 3).unwrap();
+        assert_eq!(
+            store.get(&"b"),
+            Ok(None),
+            "Failure to evict least recently used"
+        );
+        assert_eq!(store.snapshot(), vec![("a", 1), ("c", 3)]);
+        assert_eq!(store.evictions(), 1);
+        assert!(TtlStateStore::<u8, u8>::new().with_max_entries(0).is_err());
+    }
+
+    #[test]
+    fn test_ttl_expiry() {
+        let mut store = TtlStateStore::new().with_ttl(Duration::ZERO);
+        store.put(1, 'a').unwrap();
+        store.put_with_ttl(2, 'b', Duration::from_secs(3600));
+        assert_eq!(store.get(&1), Ok(None), "Failure to expire entry");
+        assert_eq!(store.get(&2), Ok(Some('b')));
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.put_with_ttl(2, 'c', Duration::ZERO), Some('b'));
+        store.purge_expired();
+        assert!(store.is_empty());
+        assert_eq!(store.evictions(), 2);
+    }
+}
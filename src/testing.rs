@@ -0,0 +1,28 @@
+//! testing
+//!
+//! Scripted sources and recording sinks for unit testing pipes
+
+/// mock
+/// Sub module for scripted sources
+pub mod mock;
+
+/// capture
+/// Sub module for sinks recording their input
+pub mod capture;
+
+/// laws
+/// Sub module for property based checks of Pipe implementations
+#[cfg(feature = "proptest")]
+pub mod laws;
+
+pub use capture::CaptureSink;
+pub use mock::{MockSource, MockStep};
+
+use crate::Source;
+use futures::StreamExt;
+use std::pin::Pin;
+
+/// drain a source into a vector, blocking the current thread until its stream ends
+pub fn collect<T: 'static>(source: &dyn Source<T>) -> Vec<T> {
+    futures::executor::block_on(Pin::from(source.stream()).collect())
+}
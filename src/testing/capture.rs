@@ -0,0 +1,152 @@
+//! capture
+//!
+//! A sink keeping every item it receives for later assertions
+
+use crate::{Sink, Source};
+use futures::future::LocalBoxFuture;
+use futures::StreamExt;
+use std::cell::{Ref, RefCell};
+use std::fmt::Debug;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// CaptureSink
+/// A sink recording the items of its input, clones share the recorded items
+pub struct CaptureSink<T> {
+    items: Rc<RefCell<Vec<T>>>,
+    input: Option<Rc<dyn Source<T>>>,
+}
+
+impl<T> CaptureSink<T> {
+    /// empty constructor
+    pub fn new() -> Self {
+        Self {
+            items: Rc::new(RefCell::new(Vec::new())),
+            input: None,
+        }
+    }
+    /// the items received so far
+    pub fn items(&self) -> Ref<'_, Vec<T>> {
+        self.items.borrow()
+    }
+    /// take the items received so far, leaving the sink empty
+    pub fn take(&self) -> Vec<T> {
+        self.items.take()
+    }
+    /// forget the items received so far
+    pub fn clear(&self) {
+        self.items.borrow_mut().clear();
+    }
+    /// number of items received
+    pub fn len(&self) -> usize {
+        self.items.borrow().len()
+    }
+    /// whether no items were received
+    pub fn is_empty(&self) -> bool {
+        self.items.borrow().is_empty()
+    }
+    /// panic unless exactly this many items were received
+    pub fn assert_len(&self, len: usize) {
+        assert_eq!(self.len(), len, "Unexpected number of captured items");
+    }
+    /// panic unless every item satisfies the predicate
+    pub fn assert_all<F: Fn(&T) -> bool>(&self, predicate: F)
+    where
+        T: Debug,
+    {
+        if let Some((idx, item)) = self
+            .items
+            .borrow()
+            .iter()
+            .enumerate()
+            .find(|(_, item)| !predicate(item))
+        {
+            panic!("Captured item {} fails the predicate: {:?}", idx, item);
+        }
+    }
+}
+
+impl<T: PartialEq + Debug> CaptureSink<T> {
+    /// panic unless exactly these items were received in this order
+    pub fn assert_items(&self, expected: &[T]) {
+        assert_eq!(
+            self.items.borrow().as_slice(),
+            expected,
+            "Unexpected captured items"
+        );
+    }
+    /// panic unless the item was received
+    pub fn assert_contains(&self, item: &T) {
+        assert!(
+            self.items.borrow().contains(item),
+            "Item {:?} was not captured",
+            item
+        );
+    }
+}
+
+impl<T> Default for CaptureSink<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for CaptureSink<T> {
+    fn clone(&self) -> Self {
+        Self {
+            items: self.items.clone(),
+            input: self.input.clone(),
+        }
+    }
+}
+
+impl<T: 'static> Sink<T> for CaptureSink<T> {
+    input_connection!(T);
+    fn drain(&self) -> LocalBoxFuture<'static, Result<(), &'static str>> {
+        let input = self.input.clone();
+        let items = self.items.clone();
+        Box::pin(async move {
+            let mut stream = Pin::from(input.ok_or("Sink has no input")?.stream());
+            while let Some(item) = stream.next().await {
+                items.borrow_mut().push(item);
+            }
+            Ok(())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::ElementwisePipe;
+    use crate::testing::MockSource;
+    use crate::Pipe;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_capture_pipe_output() {
+        let mut pipe = ElementwisePipe::scale(2);
+        pipe.pipe(Rc::new(MockSource::new().items([1, 2, 3])))
+            .unwrap();
+        let mut sink = CaptureSink::new();
+        assert!(block_on(sink.drain()).is_err(), "Drained without input");
+        sink.pipe(Rc::new(pipe)).unwrap();
+        let shared = sink.clone();
+        block_on(sink.drain()).unwrap();
+        shared.assert_items(&[2, 4, 6]);
+        shared.assert_len(3);
+        shared.assert_contains(&4);
+        shared.assert_all(|item| item % 2 == 0);
+        assert_eq!(sink.take(), vec![2, 4, 6]);
+        assert!(sink.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "fails the predicate")]
+    fn test_capture_failed_assertion() {
+        let mut sink = CaptureSink::new();
+        sink.pipe(Rc::new(MockSource::new().items([1, 2]))).unwrap();
+        block_on(sink.drain()).unwrap();
+        sink.assert_all(|item| *item < 2);
+    }
+}
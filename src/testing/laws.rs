@@ -0,0 +1,169 @@
+//! laws
+//!
+//! Property based checks of the invariants every Pipe implementation should uphold
+//! (requires the `proptest` feature)
+//!
+//! each check builds fresh pipes with the given constructor, feeds them generated inputs
+//! through `MockSource`s and panics with the minimal failing input if a law is broken,
+//! pipes are expected to be deterministic
+
+use crate::testing::{collect, MockSource};
+use crate::{Pipe, Source};
+use proptest::arbitrary::{any, Arbitrary};
+use proptest::prop_assert;
+use proptest::strategy::Strategy;
+use proptest::test_runner::{TestCaseError, TestRunner};
+use std::fmt::Debug;
+use std::rc::Rc;
+
+fn same_source<T>(lhs: &Rc<dyn Source<T>>, rhs: &Rc<dyn Source<T>>) -> bool {
+    std::ptr::addr_eq(Rc::as_ptr(lhs), Rc::as_ptr(rhs))
+}
+
+fn run<S, F>(inputs: S, test: F)
+where
+    S: Strategy,
+    S::Value: Debug,
+    F: Fn(S::Value) -> Result<(), TestCaseError>,
+{
+    if let Err(failure) = TestRunner::default().run(&inputs, test) {
+        panic!("Pipe law violated: {}", failure);
+    }
+}
+
+/// check that pipes start unconnected with an empty stream, that `pipe` connects the given
+/// source, that piping again replaces it, that `unpipe` restores an unconnected empty pipe
+/// and that a re-piped pipe produces the same output again
+pub fn check_connection_laws<InT, OutT, P, F, S>(make_pipe: F, inputs: S)
+where
+    InT: Clone + Debug + 'static,
+    OutT: PartialEq + Debug + 'static,
+    P: Pipe<InT, OutT>,
+    F: Fn() -> P,
+    S: Strategy<Value = Vec<InT>>,
+{
+    run(inputs, |items| {
+        let mut pipe = make_pipe();
+        prop_assert!(pipe.get_input().is_none(), "New pipe has an input");
+        prop_assert!(collect(&pipe).is_empty(), "Unconnected pipe streams items");
+        let first: Rc<dyn Source<InT>> = Rc::new(MockSource::new().items(items.clone()));
+        let second: Rc<dyn Source<InT>> = Rc::new(MockSource::new().items(items.clone()));
+        prop_assert!(pipe.pipe(first.clone()).is_ok(), "Failure to pipe");
+        prop_assert!(
+            pipe.get_input()
+                .is_some_and(|input| same_source(&input, &first)),
+            "Input is not the piped source"
+        );
+        let output = collect(&pipe);
+        prop_assert!(pipe.pipe(second.clone()).is_ok(), "Failure to pipe again");
+        prop_assert!(
+            pipe.get_input()
+                .is_some_and(|input| same_source(&input, &second)),
+            "Piping again did not replace the input"
+        );
+        prop_assert!(collect(&pipe) == output, "Output changed with a new input");
+        pipe.unpipe();
+        prop_assert!(pipe.get_input().is_none(), "Unpipe left an input");
+        prop_assert!(collect(&pipe).is_empty(), "Unpiped pipe streams items");
+        prop_assert!(pipe.pipe(first).is_ok(), "Failure to re-pipe");
+        prop_assert!(collect(&pipe) == output, "Re-piped output differs");
+        Ok(())
+    });
+}
+
+/// check that the pipe produces the expected number of items for every input
+pub fn check_output_count<InT, OutT, P, F, S, C>(make_pipe: F, inputs: S, expected: C)
+where
+    InT: Clone + Debug + 'static,
+    OutT: 'static,
+    P: Pipe<InT, OutT>,
+    F: Fn() -> P,
+    S: Strategy<Value = Vec<InT>>,
+    C: Fn(&[InT]) -> usize,
+{
+    run(inputs, |items| {
+        let mut pipe = make_pipe();
+        prop_assert!(pipe
+            .pipe(Rc::new(MockSource::new().items(items.clone())))
+            .is_ok());
+        let count = collect(&pipe).len();
+        prop_assert!(
+            count == expected(&items),
+            "Pipe produced {} items instead of {}",
+            count,
+            expected(&items)
+        );
+        Ok(())
+    });
+}
+
+/// check the connection laws of a pipe meant to leave its input unchanged and that it
+/// outputs exactly its input for arbitrary streams
+pub fn check_identity_laws<T, P, F>(make_pipe: F)
+where
+    T: Arbitrary + Clone + PartialEq + Debug + 'static,
+    P: Pipe<T, T>,
+    F: Fn() -> P,
+{
+    check_connection_laws(&make_pipe, any::<Vec<T>>());
+    run(any::<Vec<T>>(), |items| {
+        let mut pipe = make_pipe();
+        prop_assert!(pipe
+            .pipe(Rc::new(MockSource::new().items(items.clone())))
+            .is_ok());
+        prop_assert!(collect(&pipe) == items, "Identity pipe altered its input");
+        Ok(())
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ops::ElementwisePipe;
+    use crate::pipes::ParMapPipe;
+    use futures::Stream;
+    use proptest::collection::vec;
+
+    #[test]
+    fn test_library_pipes_obey_laws() {
+        check_identity_laws(|| ElementwisePipe::scale(1_i32));
+        check_identity_laws(|| ParMapPipe::new(|item: u8| item));
+        check_connection_laws(|| ElementwisePipe::scale(0.5_f64), vec(-1e6..1e6, 0..64));
+        check_output_count(
+            || ParMapPipe::new(|item: i64| item * 2),
+            vec(any::<i64>().prop_map(|item| item / 4), 0..200),
+            |items| items.len(),
+        );
+    }
+
+    /// a pipe that forgets to drop its input when unpiped
+    struct StickyPipe {
+        input: Option<Rc<dyn Source<u8>>>,
+    }
+
+    impl Source<u8> for StickyPipe {
+        fn stream(&self) -> Box<dyn Stream<Item = u8>> {
+            match &self.input {
+                Some(input) => input.stream(),
+                None => Box::new(futures::stream::empty()),
+            }
+        }
+    }
+
+    impl Pipe<u8, u8> for StickyPipe {
+        fn pipe(&mut self, input: Rc<dyn Source<u8>>) -> Result<(), &'static str> {
+            self.input = Some(input);
+            Ok(())
+        }
+        fn unpipe(&mut self) {}
+        fn get_input(&self) -> Option<Rc<dyn Source<u8>>> {
+            self.input.clone()
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Unpipe left an input")]
+    fn test_broken_pipe_violates_laws() {
+        check_identity_laws(|| StickyPipe { input: None });
+    }
+}
@@ -0,0 +1,162 @@
+//! mock
+//!
+//! A source replaying a script of items, delays and failures
+
+use crate::runtime::thread_sleep;
+use crate::Source;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::Cell;
+use std::rc::Rc;
+use std::time::Duration;
+
+/// MockStep
+/// One step of the script of a `MockSource`
+#[derive(Clone, Debug, PartialEq)]
+pub enum MockStep<T> {
+    /// yield an item
+    Item(T),
+    /// wait before the next step
+    Delay(Duration),
+    /// panic with a message, simulating a crashing upstream
+    Panic(&'static str),
+}
+
+/// MockSource
+/// A source playing the same script on every stream and counting how often it was streamed
+pub struct MockSource<T> {
+    script: Rc<Vec<MockStep<T>>>,
+    streams: Rc<Cell<usize>>,
+}
+
+impl<T> MockSource<T> {
+    /// empty constructor
+    pub fn new() -> Self {
+        Self {
+            script: Rc::new(Vec::new()),
+            streams: Rc::new(Cell::new(0)),
+        }
+    }
+    /// constructor from a full script
+    pub fn from_script(script: Vec<MockStep<T>>) -> Self {
+        Self {
+            script: Rc::new(script),
+            streams: Rc::new(Cell::new(0)),
+        }
+    }
+    /// the script played by the source
+    pub fn script(&self) -> &[MockStep<T>] {
+        &self.script
+    }
+    /// number of times the source was streamed
+    pub fn stream_count(&self) -> usize {
+        self.streams.get()
+    }
+}
+
+impl<T: Clone> MockSource<T> {
+    fn step(mut self, step: MockStep<T>) -> Self {
+        Rc::make_mut(&mut self.script).push(step);
+        self
+    }
+    /// append an item to the script
+    pub fn item(self, item: T) -> Self {
+        self.step(MockStep::Item(item))
+    }
+    /// append several items to the script
+    pub fn items<I: IntoIterator<Item = T>>(self, items: I) -> Self {
+        items.into_iter().fold(self, |mock, item| mock.item(item))
+    }
+    /// append a delay to the script
+    pub fn delay(self, duration: Duration) -> Self {
+        self.step(MockStep::Delay(duration))
+    }
+    /// append a panic to the script
+    pub fn panic(self, message: &'static str) -> Self {
+        self.step(MockStep::Panic(message))
+    }
+}
+
+impl<U: Clone, E: Clone> MockSource<Result<U, E>> {
+    /// append a successful item to the script
+    pub fn ok(self, item: U) -> Self {
+        self.item(Ok(item))
+    }
+    /// append an injected error to the script
+    pub fn error(self, error: E) -> Self {
+        self.item(Err(error))
+    }
+}
+
+impl<T> Default for MockSource<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Clone for MockSource<T> {
+    fn clone(&self) -> Self {
+        Self {
+            script: self.script.clone(),
+            streams: self.streams.clone(),
+        }
+    }
+}
+
+impl<T: Clone + 'static> Source<T> for MockSource<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        self.streams.set(self.streams.get() + 1);
+        let script = self.script.clone();
+        Box::new(stream::iter(0..script.len()).filter_map(move |idx| {
+            let step = script[idx].clone();
+            async move {
+                match step {
+                    MockStep::Item(item) => Some(item),
+                    MockStep::Delay(duration) => {
+                        thread_sleep(duration).await;
+                        None
+                    }
+                    MockStep::Panic(message) => panic!("{}", message),
+                }
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::pin::Pin;
+    use std::time::Instant;
+
+    #[test]
+    fn test_mock_script() {
+        let mock = MockSource::new()
+            .item(1)
+            .delay(Duration::from_millis(20))
+            .items([2, 3]);
+        let start = Instant::now();
+        let items: Vec<i32> = block_on(Pin::from(mock.stream()).collect());
+        assert_eq!(items, vec![1, 2, 3]);
+        assert!(
+            start.elapsed() >= Duration::from_millis(20),
+            "Delay skipped"
+        );
+        block_on(Pin::from(mock.clone().stream()).count());
+        assert_eq!(mock.stream_count(), 2, "Failure to count streams");
+        assert_eq!(mock.script().len(), 4);
+    }
+
+    #[test]
+    fn test_mock_errors_and_panics() {
+        let mock = MockSource::<Result<i32, &str>>::new().ok(1).error("broken");
+        let items: Vec<_> = block_on(Pin::from(mock.stream()).collect());
+        assert_eq!(items, vec![Ok(1), Err("broken")]);
+        let crashing = MockSource::new().item(1).panic("upstream crashed");
+        let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+            block_on(Pin::from(crashing.stream()).collect::<Vec<i32>>())
+        }));
+        assert!(result.is_err(), "Scripted panic did not fire");
+    }
+}
@@ -0,0 +1,19 @@
+//! text
+//!
+//! Pipes turning streams of strings into structured data
+
+/// parse
+/// Sub module for parsing text into typed values
+pub mod parse;
+
+/// regex
+/// Sub module for regular expressions and capture group extraction
+pub mod regex;
+
+/// tokenize
+/// Sub module for splitting text into tokens and n-grams
+pub mod tokenize;
+
+pub use parse::{ParseError, ParsePipe};
+pub use regex::{Captures, Regex, RegexExtractPipe};
+pub use tokenize::{ngrams, TokenizePipe, Tokenizer};
@@ -0,0 +1,159 @@
+//! parse
+//!
+//! Parsing strings into typed values with a side output for failures
+//!
+//! items that fail to parse are neither dropped silently nor ending the stream, they are
+//! set aside with their position and the parser message on the errors output
+
+use crate::window::{SideOutput, SideOutputState};
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::fmt;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::rc::Rc;
+use std::str::FromStr;
+
+/// ParseError
+/// An item that could not be parsed and where it was met
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+    /// position of the item in the stream, starting at 1 to match line numbers
+    pub line: usize,
+    /// the item as received
+    pub text: String,
+    /// the message of the parser
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "line {}: cannot parse {:?}: {}",
+            self.line, self.text, self.message
+        )
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// ParsePipe
+/// A pipe parsing the strings of its input with `FromStr`, routing failures to its errors
+/// output
+///
+/// the errors output ends with the stream of the pipe and only makes progress while that
+/// stream is polled, so both should be consumed concurrently (for instance with `join`)
+pub struct ParsePipe<T> {
+    trim: bool,
+    errors: Rc<SideOutputState<ParseError>>,
+    input: Option<Rc<dyn Source<String>>>,
+    output: PhantomData<T>,
+}
+
+impl<T> ParsePipe<T>
+where
+    T: FromStr + 'static,
+    T::Err: fmt::Display,
+{
+    /// constructor
+    pub fn new() -> Self {
+        Self {
+            trim: false,
+            errors: Rc::new(SideOutputState::new()),
+            input: None,
+            output: PhantomData,
+        }
+    }
+    /// trim surrounding whitespace before parsing
+    pub fn trimmed(mut self) -> Self {
+        self.trim = true;
+        self
+    }
+    /// the source of the items that failed to parse
+    pub fn errors(&self) -> SideOutput<ParseError> {
+        SideOutput::new(self.errors.clone())
+    }
+}
+
+impl<T> Default for ParsePipe<T>
+where
+    T: FromStr + 'static,
+    T::Err: fmt::Display,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Source<T> for ParsePipe<T>
+where
+    T: FromStr + 'static,
+    T::Err: fmt::Display,
+{
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        self.errors.set_open(true);
+        let trim = self.trim;
+        let errors = self.errors.clone();
+        let parsed = input.enumerate().filter_map(move |(index, text)| {
+            let value = if trim { text.trim() } else { &text }.parse::<T>();
+            let value = value
+                .map_err(|failure| {
+                    errors.push(ParseError {
+                        line: index + 1,
+                        message: failure.to_string(),
+                        text,
+                    })
+                })
+                .ok();
+            futures::future::ready(value)
+        });
+        let errors = self.errors.clone();
+        let close =
+            stream::once(async move { errors.set_open(false) }).filter_map(|_| async { None });
+        Box::new(parsed.chain(close))
+    }
+}
+
+impl<T> Pipe<String, T> for ParsePipe<T>
+where
+    T: FromStr + 'static,
+    T::Err: fmt::Display,
+{
+    input_connection!(String);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_parse_with_errors() {
+        let mut pipe = ParsePipe::<i32>::new().trimmed();
+        pipe.pipe(Rc::new(
+            MockSource::new().items(["1", " 2 ", "three", "4", "5.0"].map(str::to_string)),
+        ))
+        .unwrap();
+        let errors = pipe.errors();
+        let (values, errors) = block_on(async {
+            futures::join!(
+                Pin::from(pipe.stream()).collect::<Vec<_>>(),
+                Pin::from(errors.stream()).collect::<Vec<_>>()
+            )
+        });
+        assert_eq!(values, vec![1, 2, 4]);
+        let lines: Vec<(usize, &str)> = errors.iter().map(|e| (e.line, e.text.as_str())).collect();
+        assert_eq!(lines, vec![(3, "three"), (5, "5.0")]);
+        assert_eq!(
+            errors[0].to_string(),
+            "line 3: cannot parse \"three\": invalid digit found in string"
+        );
+    }
+}
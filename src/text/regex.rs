@@ -0,0 +1,214 @@
+//! regex
+//!
+//! Regular expressions and the extraction of their capture groups from text streams
+//!
+//! patterns are compiled and matched by the `regex` crate, in time linear in the length of
+//! the text
+
+use crate::data_bucket::schema::{BlobKind, StreamSchema};
+use crate::data_bucket::{DataBlob, DataBucket, DataBucketBlob, MetaData};
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::ops::Range;
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// the byte ranges of the groups of a match, group 0 is the whole match and groups that did
+/// not take part in the match are None
+pub type Captures = Vec<Option<Range<usize>>>;
+
+/// Regex
+/// A compiled regular expression
+#[derive(Clone, Debug)]
+pub struct Regex(regex::Regex);
+
+/// the byte ranges of the groups of a match of the `regex` crate
+fn ranges(captures: regex::Captures) -> Captures {
+    captures
+        .iter()
+        .map(|group| group.map(|group| group.range()))
+        .collect()
+}
+
+impl Regex {
+    /// constructor parsing a pattern (returns an error if it is invalid)
+    pub fn new(pattern: &str) -> Result<Self, &'static str> {
+        Ok(Self(
+            regex::Regex::new(pattern).map_err(|_| "Invalid regular expression")?,
+        ))
+    }
+    /// number of groups including the whole match
+    pub fn group_count(&self) -> usize {
+        self.0.captures_len()
+    }
+    /// the index and name of every named group
+    pub fn group_names(&self) -> impl Iterator<Item = (usize, &str)> + '_ {
+        self.0
+            .capture_names()
+            .enumerate()
+            .filter_map(|(index, name)| Some((index, name?)))
+    }
+    /// whether the expression matches somewhere in a text
+    pub fn is_match(&self, text: &str) -> bool {
+        self.0.is_match(text)
+    }
+    /// the groups of the leftmost match starting at or after a byte offset
+    pub fn captures_at(&self, text: &str, start: usize) -> Option<Captures> {
+        self.0.captures_at(text, start).map(ranges)
+    }
+    /// the groups of every successive non overlapping match in a text
+    pub fn captures_iter(&self, text: &str) -> Vec<Captures> {
+        self.0.captures_iter(text).map(ranges).collect()
+    }
+    /// the byte range of every successive non overlapping match in a text
+    pub fn find_iter(&self, text: &str) -> Vec<Range<usize>> {
+        self.0.find_iter(text).map(|found| found.range()).collect()
+    }
+}
+
+/// RegexExtractPipe
+/// A pipe matching a regular expression against the strings of its input and yielding a
+/// bucket for every match, holding one single element `Str` blob per named group, groups
+/// that did not take part in the match hold a null element
+pub struct RegexExtractPipe {
+    regex: Rc<Regex>,
+    input: Option<Rc<dyn Source<String>>>,
+}
+
+impl RegexExtractPipe {
+    /// constructor parsing a pattern (returns an error if it is invalid or has no named group)
+    pub fn new(pattern: &str) -> Result<Self, &'static str> {
+        Self::from_regex(Regex::new(pattern)?)
+    }
+    /// constructor from a compiled expression (returns an error if it has no named group)
+    pub fn from_regex(regex: Regex) -> Result<Self, &'static str> {
+        if regex.group_names().next().is_none() {
+            return Err("Regular expression has no named capture group");
+        }
+        Ok(Self {
+            regex: Rc::new(regex),
+            input: None,
+        })
+    }
+    /// the expression matched by the pipe
+    pub fn regex(&self) -> &Regex {
+        &self.regex
+    }
+}
+
+/// the bucket of the named groups of a match
+fn extract(regex: &Regex, text: &str, captures: &Captures) -> DataBucket {
+    let mut bucket = DataBucket::new();
+    for (index, name) in regex.group_names() {
+        let meta = MetaData {
+            name: name.to_string(),
+            dimensions: vec![1],
+            unitary_dimensions: vec![1],
+            ..Default::default()
+        };
+        let value = captures[index].clone().map(|range| text[range].to_string());
+        bucket.add_blob(DataBucketBlob::Str(DataBlob::from_options(
+            vec![value],
+            meta,
+        )));
+    }
+    bucket
+}
+
+impl Source<DataBucket> for RegexExtractPipe {
+    fn stream(&self) -> Box<dyn Stream<Item = DataBucket>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let regex = self.regex.clone();
+        Box::new(input.flat_map(move |text| {
+            let buckets: Vec<DataBucket> = regex
+                .captures_iter(&text)
+                .iter()
+                .map(|captures| extract(&regex, &text, captures))
+                .collect();
+            stream::iter(buckets)
+        }))
+    }
+    fn schema(&self) -> Option<StreamSchema> {
+        Some(StreamSchema::bucket(
+            self.regex
+                .group_names()
+                .map(|(_, name)| (name, BlobKind::Str)),
+        ))
+    }
+}
+
+impl Pipe<String, DataBucket> for RegexExtractPipe {
+    input_connection!(String);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+
+    #[test]
+    fn test_regex_matching() {
+        let regex = Regex::new(r"(?P<key>\w+)=(?P<value>[^ ]*)").unwrap();
+        let text = "a=1 bé=two empty=";
+        let pairs: Vec<(&str, &str)> = regex
+            .captures_iter(text)
+            .into_iter()
+            .map(|c| (&text[c[1].clone().unwrap()], &text[c[2].clone().unwrap()]))
+            .collect();
+        assert_eq!(pairs, vec![("a", "1"), ("bé", "two"), ("empty", "")]);
+        let lazy = Regex::new(r"<.+?>").unwrap();
+        assert_eq!(lazy.find_iter("<a><b>"), vec![0..3, 3..6]);
+        let anchored = Regex::new(r"^\d{2,3}\b").unwrap();
+        assert!(
+            anchored.is_match("123 go") && !anchored.is_match("1234") && !anchored.is_match("x12")
+        );
+        let optional = Regex::new(r"(a)|(b)").unwrap();
+        assert_eq!(
+            optional.captures_at("b", 0).unwrap(),
+            vec![Some(0..1), None, Some(0..1)]
+        );
+        assert_eq!(Regex::new(r"x*").unwrap().find_iter("ab").len(), 3);
+        assert!(Regex::new(r"(unclosed").is_err());
+        // long lines neither overflow the stack nor backtrack exponentially
+        let line = "x".repeat(200_000);
+        let whole = Regex::new(r"(?P<msg>.*)$").unwrap();
+        assert_eq!(whole.find_iter(&line), vec![0..200_000]);
+        assert!(!Regex::new(r"^(x+x+)+y$").unwrap().is_match(&line));
+    }
+
+    #[test]
+    fn test_extract_pipe() {
+        let mut pipe =
+            RegexExtractPipe::new(r"(?P<level>[A-Z]+) (?P<code>\d+)?:? ?(?P<message>.*)").unwrap();
+        pipe.pipe(Rc::new(MockSource::new().items([
+            "ERROR 42: disk full".to_string(),
+            "lowercase only".to_string(),
+            "INFO started".to_string(),
+        ])))
+        .unwrap();
+        let buckets: Vec<DataBucket> = block_on(Pin::from(pipe.stream()).collect());
+        assert_eq!(buckets.len(), 2);
+        let text = |bucket: &DataBucket, name: &str| match bucket.get_blob(&name.to_string()) {
+            Some(DataBucketBlob::Str(blob)) => blob.to_options().remove(0),
+            _ => panic!("Missing blob {}", name),
+        };
+        assert_eq!(text(&buckets[0], "code").as_deref(), Some("42"));
+        assert_eq!(text(&buckets[0], "message").as_deref(), Some("disk full"));
+        assert_eq!(text(&buckets[1], "code"), None);
+        assert_eq!(text(&buckets[1], "level").as_deref(), Some("INFO"));
+        assert_eq!(
+            pipe.schema(),
+            Some(StreamSchema::bucket([
+                ("level", BlobKind::Str),
+                ("code", BlobKind::Str),
+                ("message", BlobKind::Str)
+            ]))
+        );
+        assert!(RegexExtractPipe::new(r"(\d+)").is_err());
+    }
+}
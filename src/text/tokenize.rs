@@ -0,0 +1,169 @@
+//! tokenize
+//!
+//! Splitting strings into tokens and n-grams
+//!
+//! tokens and n-grams never span two items of a stream, n-grams join their tokens with a
+//! single space
+
+use super::regex::Regex;
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::pin::Pin;
+use std::rc::Rc;
+
+/// Tokenizer
+/// The rules splitting a string into tokens
+#[derive(Clone, Debug, Default)]
+pub enum Tokenizer {
+    /// runs of non whitespace characters
+    #[default]
+    Whitespace,
+    /// runs of characters that are neither whitespace nor punctuation
+    Punctuation,
+    /// the successive matches of a regular expression
+    Pattern(Regex),
+}
+
+impl Tokenizer {
+    /// constructor of a tokenizer yielding the matches of a pattern (returns an error if it
+    /// is invalid)
+    pub fn pattern(pattern: &str) -> Result<Self, &'static str> {
+        Ok(Tokenizer::Pattern(Regex::new(pattern)?))
+    }
+    /// the non empty tokens of a text in order
+    pub fn tokenize<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        match self {
+            Tokenizer::Whitespace => text.split_whitespace().collect(),
+            Tokenizer::Punctuation => text
+                .split(|c: char| {
+                    c.is_whitespace() || c.is_ascii_punctuation() || PUNCTUATION.contains(&c)
+                })
+                .filter(|token| !token.is_empty())
+                .collect(),
+            Tokenizer::Pattern(regex) => regex
+                .find_iter(text)
+                .into_iter()
+                .filter(|range| !range.is_empty())
+                .map(|range| &text[range])
+                .collect(),
+        }
+    }
+}
+
+/// common non ASCII punctuation
+const PUNCTUATION: [char; 15] = [
+    '¡', '¿', '«', '»', '‘', '’', '“', '”', '–', '—', '…', '·', '،', '。', '、',
+];
+
+/// the n-grams of successive tokens, empty if there are fewer than n tokens
+pub fn ngrams(tokens: &[&str], n: usize) -> Vec<String> {
+    if n == 0 {
+        return Vec::new();
+    }
+    tokens.windows(n).map(|window| window.join(" ")).collect()
+}
+
+/// TokenizePipe
+/// A pipe splitting the strings of its input into tokens, or into n-grams of tokens
+pub struct TokenizePipe {
+    tokenizer: Rc<Tokenizer>,
+    lowercase: bool,
+    n: usize,
+    input: Option<Rc<dyn Source<String>>>,
+}
+
+impl TokenizePipe {
+    /// constructor yielding single tokens
+    pub fn new(tokenizer: Tokenizer) -> Self {
+        Self {
+            tokenizer: Rc::new(tokenizer),
+            lowercase: false,
+            n: 1,
+            input: None,
+        }
+    }
+    /// lowercase every token
+    pub fn lowercase(mut self) -> Self {
+        self.lowercase = true;
+        self
+    }
+    /// yield n-grams of tokens instead of single tokens (returns an error for n = 0)
+    pub fn with_ngrams(mut self, n: usize) -> Result<Self, &'static str> {
+        if n == 0 {
+            return Err("N-grams need at least one token");
+        }
+        self.n = n;
+        Ok(self)
+    }
+}
+
+impl Source<String> for TokenizePipe {
+    fn stream(&self) -> Box<dyn Stream<Item = String>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let tokenizer = self.tokenizer.clone();
+        let (lowercase, n) = (self.lowercase, self.n);
+        Box::new(input.flat_map(move |text| {
+            let text = if lowercase { text.to_lowercase() } else { text };
+            stream::iter(ngrams(&tokenizer.tokenize(&text), n))
+        }))
+    }
+}
+
+impl Pipe<String, String> for TokenizePipe {
+    input_connection!(String);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::MockSource;
+    use futures::executor::block_on;
+    use std::collections::BTreeMap;
+
+    fn lines() -> Rc<MockSource<String>> {
+        Rc::new(
+            MockSource::new().items(["The cat, the hat.".to_string(), "  «Cat» sat… ".to_string()]),
+        )
+    }
+
+    #[test]
+    fn test_tokenizers() {
+        let text = "a.b, c-d  e";
+        assert_eq!(
+            Tokenizer::Whitespace.tokenize(text),
+            vec!["a.b,", "c-d", "e"]
+        );
+        assert_eq!(
+            Tokenizer::Punctuation.tokenize(text),
+            vec!["a", "b", "c", "d", "e"]
+        );
+        let numbers = Tokenizer::pattern(r"\d+(\.\d+)?").unwrap();
+        assert_eq!(numbers.tokenize("x 1.5 y 22"), vec!["1.5", "22"]);
+        assert_eq!(ngrams(&["a", "b", "c"], 2), vec!["a b", "b c"]);
+        assert!(ngrams(&["a"], 2).is_empty());
+    }
+
+    #[test]
+    fn test_word_count() {
+        let mut pipe = TokenizePipe::new(Tokenizer::Punctuation).lowercase();
+        pipe.pipe(lines()).unwrap();
+        let tokens: Vec<String> = block_on(Pin::from(pipe.stream()).collect());
+        let mut counts = BTreeMap::new();
+        for token in tokens {
+            *counts.entry(token).or_insert(0) += 1;
+        }
+        assert_eq!(counts["cat"], 2);
+        assert_eq!(counts["the"], 2);
+        assert_eq!(counts.len(), 4);
+        let mut bigrams = TokenizePipe::new(Tokenizer::Punctuation)
+            .with_ngrams(2)
+            .unwrap();
+        bigrams.pipe(lines()).unwrap();
+        let bigrams: Vec<String> = block_on(Pin::from(bigrams.stream()).collect());
+        assert_eq!(bigrams, vec!["The cat", "cat the", "the hat", "Cat sat"]);
+    }
+}
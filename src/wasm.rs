@@ -0,0 +1,129 @@
+//! wasm
+//!
+//! Adapters between browser streams and pipelines (requires the `wasm` feature and the
+//! `wasm32` target)
+
+use crate::Source;
+use bytes::Bytes;
+use futures::stream;
+use futures::{Stream, StreamExt};
+use js_sys::Uint8Array;
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use wasm_bindgen::{JsCast, JsValue};
+use wasm_streams::ReadableStream;
+
+/// JsStreamSource
+/// A source reading the chunks of a JS `ReadableStream` and converting them to items
+///
+/// a JS stream can only be read once, streams requested after the first one are empty,
+/// the stream ends at the first JS error or at the first chunk the conversion rejects and
+/// the failure is reported by `error`
+pub struct JsStreamSource<T> {
+    raw: RefCell<Option<web_sys::ReadableStream>>,
+    convert: Rc<dyn Fn(JsValue) -> Option<T>>,
+    error: Rc<Cell<Option<&'static str>>>,
+}
+
+impl<T> JsStreamSource<T> {
+    /// constructor from a raw JS stream and a chunk conversion
+    pub fn new<F>(raw: web_sys::ReadableStream, convert: F) -> Self
+    where
+        F: Fn(JsValue) -> Option<T> + 'static,
+    {
+        Self {
+            raw: RefCell::new(Some(raw)),
+            convert: Rc::new(convert),
+            error: Rc::new(Cell::new(None)),
+        }
+    }
+    /// the error that ended the last stream, if any
+    pub fn error(&self) -> Option<&'static str> {
+        self.error.get()
+    }
+}
+
+impl JsStreamSource<Bytes> {
+    /// a source of the byte chunks of a stream of `Uint8Array`s (such as a fetch body)
+    pub fn bytes(raw: web_sys::ReadableStream) -> Self {
+        Self::new(raw, |chunk| {
+            chunk
+                .dyn_into::<Uint8Array>()
+                .ok()
+                .map(|array| Bytes::from(array.to_vec()))
+        })
+    }
+}
+
+impl JsStreamSource<String> {
+    /// a source of the string chunks of a stream (such as a `TextDecoderStream` output)
+    pub fn strings(raw: web_sys::ReadableStream) -> Self {
+        Self::new(raw, |chunk| chunk.as_string())
+    }
+}
+
+impl<T: 'static> Source<T> for JsStreamSource<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let raw = match self.raw.borrow_mut().take() {
+            Some(raw) => raw,
+            None => return Box::new(stream::empty()),
+        };
+        let convert = self.convert.clone();
+        let error = self.error.clone();
+        error.set(None);
+        Box::new(
+            ReadableStream::from_raw(raw)
+                .into_stream()
+                .map(move |chunk| {
+                    let item = match chunk {
+                        Ok(value) => convert(value).ok_or("Failure to convert a JS stream chunk"),
+                        Err(_) => Err("JS stream raised an error"),
+                    };
+                    item.inspect_err(|message| error.set(Some(message))).ok()
+                })
+                .take_while(|item| futures::future::ready(item.is_some()))
+                .filter_map(futures::future::ready),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+    use std::pin::Pin;
+    use wasm_bindgen_test::wasm_bindgen_test;
+
+    #[wasm_bindgen_test]
+    async fn test_string_stream() {
+        let chunks = stream::iter(["vor", "tex"]).map(|chunk| Ok(JsValue::from_str(chunk)));
+        let raw = ReadableStream::from_stream(chunks).into_raw();
+        let source = JsStreamSource::strings(raw);
+        let items: Vec<String> = Pin::from(source.stream()).collect().await;
+        assert_eq!(items, ["vor", "tex"]);
+        assert_eq!(source.error(), None);
+        let again: Vec<String> = Pin::from(source.stream()).collect().await;
+        assert!(again.is_empty(), "JS stream read twice");
+    }
+
+    #[wasm_bindgen_test]
+    async fn test_stream_failures() {
+        let chunks = stream::iter([
+            Ok(JsValue::from_str("vor")),
+            Ok(JsValue::from_f64(1.0)),
+            Ok(JsValue::from_str("tex")),
+        ]);
+        let source = JsStreamSource::strings(ReadableStream::from_stream(chunks).into_raw());
+        let items: Vec<String> = Pin::from(source.stream()).collect().await;
+        assert_eq!(items, ["vor"]);
+        assert_eq!(source.error(), Some("Failure to convert a JS stream chunk"));
+        let chunks = stream::iter([
+            Ok(JsValue::from_str("vor")),
+            Err(JsValue::from_str("aborted")),
+        ]);
+        let source = JsStreamSource::strings(ReadableStream::from_stream(chunks).into_raw());
+        let items: Vec<String> = Pin::from(source.stream()).collect().await;
+        assert_eq!(items, ["vor"]);
+        assert_eq!(source.error(), Some("JS stream raised an error"));
+    }
+}
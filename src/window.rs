@@ -0,0 +1,746 @@
+//! window
+//!
+//! Grouping the events of a timed stream into windows of event time
+//!
+//! a window covers the timestamps in `[start, end)` and is emitted once a watermark at or
+//! past its end arrives (or the input ends) unless another trigger is set, events whose
+//! windows were all closed already are set aside on the late output of the pipe
+
+use crate::event::{Event, Timed};
+use crate::{Pipe, Source};
+use futures::stream;
+use futures::{Stream, StreamExt};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, VecDeque};
+use std::pin::Pin;
+use std::rc::Rc;
+use std::task::{Context, Poll, Waker};
+use std::time::Instant;
+
+/// trigger
+/// Sub module deciding when windows fire
+pub mod trigger;
+
+pub use trigger::{
+    CountTrigger, EarlyLateTrigger, PaneStatus, ProcessingTimeTrigger, Trigger, WatermarkTrigger,
+};
+
+/// WindowKind
+/// How events are assigned to windows
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WindowKind {
+    /// consecutive non overlapping windows of a fixed size
+    Tumbling { size: i64 },
+    /// windows of a fixed size starting every `slide` ticks, an event belongs to every
+    /// window covering it
+    Sliding { size: i64, slide: i64 },
+    /// windows per key spanning bursts of activity, an event less than `gap` ticks after
+    /// another event of the same session extends it, so a session closes after `gap` ticks
+    /// without events of its key
+    Session { gap: i64 },
+}
+
+impl WindowKind {
+    fn validate(&self) -> Result<(), &'static str> {
+        let valid = match *self {
+            WindowKind::Tumbling { size } => size > 0,
+            WindowKind::Sliding { size, slide } => size > 0 && slide > 0,
+            WindowKind::Session { gap } => gap > 0,
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err("Window sizes must be strictly positive")
+        }
+    }
+    /// the `(start, end)` of every window covering a timestamp, by increasing start (for
+    /// sessions the window opened by the event, before merging)
+    fn assign(&self, timestamp: i64) -> Vec<(i64, i64)> {
+        match *self {
+            WindowKind::Tumbling { size } => {
+                let start = timestamp.div_euclid(size) * size;
+                vec![(start, start.saturating_add(size))]
+            }
+            WindowKind::Sliding { size, slide } => {
+                let mut start = timestamp.div_euclid(slide) * slide;
+                let mut windows = Vec::new();
+                while start.saturating_add(size) > timestamp {
+                    windows.push((start, start.saturating_add(size)));
+                    start = match start.checked_sub(slide) {
+                        Some(start) => start,
+                        None => break,
+                    };
+                }
+                windows.reverse();
+                windows
+            }
+            WindowKind::Session { gap } => vec![(timestamp, timestamp.saturating_add(gap))],
+        }
+    }
+}
+
+/// Firing
+/// Why a window was emitted
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Firing {
+    /// the trigger fired the window before the watermark passed its end
+    Early,
+    /// the watermark passed the end of the window
+    OnTime,
+    /// an event arrived within the allowed lateness after the window fired, the window is
+    /// emitted again with every event received so far
+    Late,
+}
+
+/// Window
+/// The events of one key falling within a window
+#[derive(Clone, Debug, PartialEq)]
+pub struct Window<T, K = ()> {
+    pub key: K,
+    pub start: i64,
+    pub end: i64,
+    pub events: Vec<Event<T>>,
+    pub firing: Firing,
+}
+
+type KeyFunction<T, K> = Rc<dyn Fn(&T) -> K>;
+
+/// Accumulator
+/// What a window keeps of the events of one key, the events themselves for `WindowPipe`
+/// or a sketch summarizing them
+pub(crate) trait Accumulator<T> {
+    /// the summary emitted every time the window fires
+    type Output;
+    /// take an event into account
+    fn add(&mut self, event: &Event<T>);
+    /// take the events of a session merged into this one into account
+    fn merge(&mut self, other: Self);
+    /// the summary of the events so far, the state can be moved out of a closing window
+    fn emit(&mut self, close: bool) -> Self::Output;
+}
+
+/// the events of the window by increasing timestamp once sessions merged
+impl<T: Clone> Accumulator<T> for Vec<Event<T>> {
+    type Output = Vec<Event<T>>;
+    fn add(&mut self, event: &Event<T>) {
+        self.push(event.clone());
+    }
+    fn merge(&mut self, mut other: Self) {
+        self.append(&mut other);
+        self.sort_by_key(|event| event.timestamp);
+    }
+    fn emit(&mut self, close: bool) -> Self::Output {
+        match close {
+            true => std::mem::take(self),
+            false => self.clone(),
+        }
+    }
+}
+
+/// Fired
+/// The summary of one key emitted by a window firing
+pub(crate) struct Fired<K, O> {
+    pub(crate) key: K,
+    pub(crate) start: i64,
+    pub(crate) end: i64,
+    pub(crate) output: O,
+    pub(crate) firing: Firing,
+}
+
+/// the state of one key in one window
+struct Pane<K, A> {
+    key: K,
+    state: A,
+    events: usize,
+    pending: usize,
+    firings: usize,
+    fired_on_time: bool,
+    last_firing: Instant,
+}
+
+impl<K: Clone, A> Pane<K, A> {
+    fn new(key: K, state: A, fired_on_time: bool) -> Self {
+        Self {
+            key,
+            state,
+            events: 0,
+            pending: 0,
+            firings: 0,
+            fired_on_time,
+            last_firing: Instant::now(),
+        }
+    }
+    fn status(&self, start: i64, end: i64, watermark: i64) -> PaneStatus {
+        PaneStatus {
+            start,
+            end,
+            watermark,
+            events: self.events,
+            pending: self.pending,
+            firings: self.firings,
+            fired_on_time: self.fired_on_time,
+            since_firing: self.last_firing.elapsed(),
+        }
+    }
+    fn add<T>(&mut self, event: &Event<T>)
+    where
+        A: Accumulator<T>,
+    {
+        self.state.add(event);
+        self.events += 1;
+        self.pending += 1;
+    }
+    /// emit the accumulated state, moving it out of a closing pane
+    fn fire<T>(&mut self, start: i64, end: i64, watermark: i64, close: bool) -> Fired<K, A::Output>
+    where
+        A: Accumulator<T>,
+    {
+        let firing = if end > watermark {
+            Firing::Early
+        } else if self.fired_on_time {
+            Firing::Late
+        } else {
+            Firing::OnTime
+        };
+        self.fired_on_time |= end <= watermark;
+        self.pending = 0;
+        self.firings += 1;
+        self.last_firing = Instant::now();
+        Fired {
+            key: self.key.clone(),
+            start,
+            end,
+            output: self.state.emit(close),
+            firing,
+        }
+    }
+}
+
+/// the open windows of a stream
+struct WindowState<T, K, A> {
+    kind: WindowKind,
+    key: KeyFunction<T, K>,
+    init: Rc<dyn Fn() -> A>,
+    trigger: Rc<dyn Trigger>,
+    allowed_lateness: i64,
+    watermark: i64,
+    open: BTreeMap<(i64, i64), Vec<Pane<K, A>>>,
+    late: Rc<SideOutputState<Event<T>>>,
+}
+
+impl<T, K: PartialEq + Clone, A: Accumulator<T>> WindowState<T, K, A> {
+    fn on_item(&mut self, item: Timed<T>) -> Vec<Fired<K, A::Output>> {
+        match item {
+            Timed::Event(event) => self.on_event(event),
+            Timed::Watermark(watermark) => self.on_watermark(watermark),
+        }
+    }
+    /// whether a window no longer accepts events
+    fn is_closed(&self, end: i64) -> bool {
+        end.saturating_add(self.allowed_lateness) <= self.watermark
+    }
+    fn on_event(&mut self, event: Event<T>) -> Vec<Fired<K, A::Output>> {
+        let key = (self.key)(&event.value);
+        if let WindowKind::Session { .. } = self.kind {
+            return self.on_session_event(event, key);
+        }
+        let watermark = self.watermark;
+        let mut windows = Vec::new();
+        let mut accepted = false;
+        for (start, end) in self.kind.assign(event.timestamp) {
+            if self.is_closed(end) {
+                continue;
+            }
+            accepted = true;
+            let panes = self.open.entry((end, start)).or_default();
+            let idx = match panes.iter().position(|pane| pane.key == key) {
+                Some(idx) => idx,
+                None => {
+                    panes.push(Pane::new(key.clone(), (self.init)(), end <= watermark));
+                    panes.len() - 1
+                }
+            };
+            let pane = &mut panes[idx];
+            pane.add(&event);
+            if self.trigger.on_event(&pane.status(start, end, watermark)) {
+                windows.push(pane.fire(start, end, watermark, false));
+            }
+        }
+        if !accepted {
+            self.late.push(event);
+        }
+        windows
+    }
+    /// merge the window opened by an event with the overlapping sessions of its key
+    fn on_session_event(&mut self, event: Event<T>, key: K) -> Vec<Fired<K, A::Output>> {
+        let (mut start, mut end) = self.kind.assign(event.timestamp)[0];
+        if self.is_closed(end) {
+            self.late.push(event);
+            return Vec::new();
+        }
+        let overlapping: Vec<(i64, i64)> = self
+            .open
+            .iter()
+            .filter(|(&(other_end, other_start), panes)| {
+                other_start < end && start < other_end && panes.iter().any(|pane| pane.key == key)
+            })
+            .map(|(&window, _)| window)
+            .collect();
+        let mut merged = Pane::new(key.clone(), (self.init)(), false);
+        for window in overlapping {
+            let panes = self.open.get_mut(&window).unwrap();
+            let idx = panes.iter().position(|pane| pane.key == key).unwrap();
+            let pane = panes.remove(idx);
+            if panes.is_empty() {
+                self.open.remove(&window);
+            }
+            merged.state.merge(pane.state);
+            merged.events += pane.events;
+            merged.pending += pane.pending;
+            merged.firings += pane.firings;
+            merged.last_firing = merged.last_firing.min(pane.last_firing);
+            start = start.min(window.1);
+            end = end.max(window.0);
+        }
+        let mut opened = (self.init)();
+        opened.add(&event);
+        merged.state.merge(opened);
+        merged.events += 1;
+        merged.pending += 1;
+        merged.fired_on_time = end <= self.watermark;
+        let status = merged.status(start, end, self.watermark);
+        let panes = self.open.entry((end, start)).or_default();
+        panes.push(merged);
+        if !self.trigger.on_event(&status) {
+            return Vec::new();
+        }
+        vec![panes
+            .last_mut()
+            .unwrap()
+            .fire(start, end, self.watermark, false)]
+    }
+    fn on_watermark(&mut self, watermark: i64) -> Vec<Fired<K, A::Output>> {
+        if watermark <= self.watermark {
+            return Vec::new();
+        }
+        self.watermark = watermark;
+        let mut windows = Vec::new();
+        let lateness = self.allowed_lateness;
+        let mut closed = Vec::new();
+        for (&(end, start), panes) in self.open.iter_mut() {
+            let close = end.saturating_add(lateness) <= watermark;
+            for pane in panes.iter_mut() {
+                let fire = self
+                    .trigger
+                    .on_watermark(&pane.status(start, end, watermark));
+                if fire || (close && pane.pending > 0) {
+                    windows.push(pane.fire(start, end, watermark, close));
+                }
+            }
+            if close {
+                closed.push((end, start));
+            }
+        }
+        for window in closed {
+            self.open.remove(&window);
+        }
+        windows
+    }
+    fn finish(&mut self) -> Vec<Fired<K, A::Output>> {
+        std::mem::take(&mut self.open)
+            .into_iter()
+            .flat_map(|((end, start), panes)| {
+                panes
+                    .into_iter()
+                    .filter(|pane| pane.pending > 0)
+                    .map(move |mut pane| pane.fire(start, end, i64::MAX, true))
+            })
+            .collect()
+    }
+}
+
+/// Windowing
+/// How a pipe assigns the events of a timed stream to windows and keys, when the windows
+/// fire and where the events arriving after their windows closed go
+pub(crate) struct Windowing<T, K> {
+    kind: WindowKind,
+    key: KeyFunction<T, K>,
+    trigger: Rc<dyn Trigger>,
+    allowed_lateness: i64,
+    late: Rc<SideOutputState<Event<T>>>,
+}
+
+impl<T, K> Windowing<T, K> {
+    /// constructor for separate windows per key, firing when the watermark passes their end
+    pub(crate) fn new<F: Fn(&T) -> K + 'static>(
+        kind: WindowKind,
+        key: F,
+    ) -> Result<Self, &'static str> {
+        kind.validate()?;
+        Ok(Self {
+            kind,
+            key: Rc::new(key),
+            trigger: Rc::new(WatermarkTrigger),
+            allowed_lateness: 0,
+            late: Rc::new(SideOutputState::new()),
+        })
+    }
+    pub(crate) fn kind(&self) -> WindowKind {
+        self.kind
+    }
+    pub(crate) fn set_trigger(&mut self, trigger: impl Trigger + 'static) {
+        self.trigger = Rc::new(trigger);
+    }
+    pub(crate) fn set_allowed_lateness(
+        &mut self,
+        allowed_lateness: i64,
+    ) -> Result<(), &'static str> {
+        if allowed_lateness < 0 {
+            return Err("Allowed lateness must be positive");
+        }
+        self.allowed_lateness = allowed_lateness;
+        Ok(())
+    }
+    pub(crate) fn late_output(&self) -> SideOutput<Event<T>> {
+        SideOutput::new(self.late.clone())
+    }
+}
+
+impl<T: 'static, K: PartialEq + Clone + 'static> Windowing<T, K> {
+    /// the firings of the windows of a stream, every pane accumulating into a new state
+    pub(crate) fn fire<A, F>(
+        &self,
+        input: Pin<Box<dyn Stream<Item = Timed<T>>>>,
+        init: F,
+    ) -> impl Stream<Item = Fired<K, A::Output>>
+    where
+        A: Accumulator<T> + 'static,
+        F: Fn() -> A + 'static,
+    {
+        self.late.set_open(true);
+        let state = Rc::new(RefCell::new(WindowState {
+            kind: self.kind,
+            key: self.key.clone(),
+            init: Rc::new(init),
+            trigger: self.trigger.clone(),
+            allowed_lateness: self.allowed_lateness,
+            watermark: i64::MIN,
+            open: BTreeMap::new(),
+            late: self.late.clone(),
+        }));
+        let remaining = state.clone();
+        let windows = input.flat_map(move |item| stream::iter(state.borrow_mut().on_item(item)));
+        let flush = stream::once(async move {
+            let windows = remaining.borrow_mut().finish();
+            remaining.borrow().late.set_open(false);
+            stream::iter(windows)
+        });
+        windows.chain(flush.flatten())
+    }
+}
+
+/// the queue behind a side output, fed by the stream of the pipe owning it
+pub(crate) struct SideOutputState<T> {
+    queue: RefCell<VecDeque<T>>,
+    open: RefCell<bool>,
+    waker: RefCell<Option<Waker>>,
+}
+
+impl<T> SideOutputState<T> {
+    pub(crate) fn new() -> Self {
+        Self {
+            queue: RefCell::new(VecDeque::new()),
+            open: RefCell::new(false),
+            waker: RefCell::new(None),
+        }
+    }
+    pub(crate) fn push(&self, item: T) {
+        self.queue.borrow_mut().push_back(item);
+        self.wake();
+    }
+    pub(crate) fn set_open(&self, open: bool) {
+        *self.open.borrow_mut() = open;
+        self.wake();
+    }
+    fn wake(&self) {
+        if let Some(waker) = self.waker.borrow_mut().take() {
+            waker.wake();
+        }
+    }
+    fn poll_item(&self, cx: &mut Context<'_>) -> Poll<Option<T>> {
+        if let Some(item) = self.queue.borrow_mut().pop_front() {
+            return Poll::Ready(Some(item));
+        }
+        if !*self.open.borrow() {
+            return Poll::Ready(None);
+        }
+        *self.waker.borrow_mut() = Some(cx.waker().clone());
+        Poll::Pending
+    }
+}
+
+/// SideOutput
+/// A source yielding the items a pipe set aside while its own stream is consumed
+///
+/// the side output ends with the stream of the pipe and only makes progress while that
+/// stream is polled, so both should be consumed concurrently (for instance with `join`)
+pub struct SideOutput<T> {
+    state: Rc<SideOutputState<T>>,
+}
+
+impl<T> SideOutput<T> {
+    pub(crate) fn new(state: Rc<SideOutputState<T>>) -> Self {
+        Self { state }
+    }
+}
+
+impl<T: 'static> Source<T> for SideOutput<T> {
+    fn stream(&self) -> Box<dyn Stream<Item = T>> {
+        let state = self.state.clone();
+        Box::new(stream::poll_fn(move |cx| state.poll_item(cx)))
+    }
+}
+
+/// WindowPipe
+/// A pipe collecting the events of a timed stream into windows, optionally per key, and
+/// emitting each window when the watermark passes its end
+///
+/// windows are emitted by increasing end then start, and in order of first appearance of
+/// their keys, with an allowed lateness a window stays open after firing and fires again
+/// for every event arriving before the watermark passes its end plus the lateness, events
+/// arriving after that are routed to the late output
+pub struct WindowPipe<T, K = ()> {
+    windowing: Windowing<T, K>,
+    input: Option<Rc<dyn Source<Timed<T>>>>,
+}
+
+impl<T> WindowPipe<T, ()> {
+    /// constructor for windows over every event
+    pub fn new(kind: WindowKind) -> Result<Self, &'static str> {
+        WindowPipe::keyed(kind, |_: &T| ())
+    }
+}
+
+impl<T, K> WindowPipe<T, K> {
+    /// constructor for separate windows per key
+    pub fn keyed<F: Fn(&T) -> K + 'static>(kind: WindowKind, key: F) -> Result<Self, &'static str> {
+        Ok(Self {
+            windowing: Windowing::new(kind, key)?,
+            input: None,
+        })
+    }
+    /// how events are assigned to windows
+    pub fn kind(&self) -> WindowKind {
+        self.windowing.kind()
+    }
+    /// set the trigger deciding when windows fire
+    pub fn set_trigger(&mut self, trigger: impl Trigger + 'static) {
+        self.windowing.set_trigger(trigger);
+    }
+    /// set how long (in ticks of event time) windows accept events after firing
+    pub fn set_allowed_lateness(&mut self, allowed_lateness: i64) -> Result<(), &'static str> {
+        self.windowing.set_allowed_lateness(allowed_lateness)
+    }
+    /// the source of the events arriving after every window covering them closed
+    pub fn late_output(&self) -> SideOutput<Event<T>> {
+        self.windowing.late_output()
+    }
+}
+
+impl<T, K> Source<Window<T, K>> for WindowPipe<T, K>
+where
+    T: Clone + 'static,
+    K: PartialEq + Clone + 'static,
+{
+    fn stream(&self) -> Box<dyn Stream<Item = Window<T, K>>> {
+        let input = match &self.input {
+            Some(input) => Pin::from(input.stream()),
+            None => return Box::new(stream::empty()),
+        };
+        let windows = self.windowing.fire(input, Vec::new).map(|fired| Window {
+            key: fired.key,
+            start: fired.start,
+            end: fired.end,
+            events: fired.output,
+            firing: fired.firing,
+        });
+        Box::new(windows)
+    }
+}
+
+impl<T, K> Pipe<Timed<T>, Window<T, K>> for WindowPipe<T, K>
+where
+    T: Clone + 'static,
+    K: PartialEq + Clone + 'static,
+{
+    input_connection!(Timed<T>);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event::WatermarkPipe;
+    use crate::testing::{collect, MockSource};
+    use futures::executor::block_on;
+
+    fn spans<T, K>(windows: &[Window<T, K>]) -> Vec<(i64, i64, usize)> {
+        windows
+            .iter()
+            .map(|window| (window.start, window.end, window.events.len()))
+            .collect()
+    }
+
+    #[test]
+    fn test_tumbling_windows_close_on_watermarks() {
+        let mut pipe = WindowPipe::new(WindowKind::Tumbling { size: 10 }).unwrap();
+        pipe.pipe(Rc::new(MockSource::new().items([
+            Timed::Event(Event::new(3, 'a')),
+            Timed::Event(Event::new(12, 'b')),
+            Timed::Event(Event::new(-1, 'c')),
+            Timed::Watermark(10),
+            Timed::Event(Event::new(7, 'l')),
+            Timed::Event(Event::new(15, 'd')),
+        ])))
+        .unwrap();
+        let late = pipe.late_output();
+        let (windows, late) = block_on(async {
+            futures::join!(
+                Pin::from(pipe.stream()).collect::<Vec<_>>(),
+                Pin::from(late.stream()).collect::<Vec<_>>()
+            )
+        });
+        assert_eq!(spans(&windows), vec![(-10, 0, 1), (0, 10, 1), (10, 20, 2)]);
+        assert_eq!(
+            late,
+            vec![Event::new(7, 'l')],
+            "Failure to route late event"
+        );
+        assert_eq!(windows[2].events[1].value, 'd');
+        assert!(WindowPipe::<()>::new(WindowKind::Tumbling { size: 0 }).is_err());
+    }
+
+    #[test]
+    fn test_sliding_windows_with_keys() {
+        let mut watermarks = WatermarkPipe::new(0).unwrap();
+        watermarks
+            .pipe(Rc::new(MockSource::new().items([
+                Event::new(0, ("x", 1)),
+                Event::new(1, ("y", 2)),
+                Event::new(4, ("x", 3)),
+            ])))
+            .unwrap();
+        let mut pipe = WindowPipe::keyed(
+            WindowKind::Sliding { size: 4, slide: 2 },
+            |value: &(&str, i32)| value.0,
+        )
+        .unwrap();
+        pipe.pipe(Rc::new(watermarks)).unwrap();
+        let windows = collect(&pipe);
+        let summary: Vec<(&str, i64, i64, i32)> = windows
+            .iter()
+            .map(|window| {
+                let sum = window.events.iter().map(|event| event.value.1).sum();
+                (window.key, window.start, window.end, sum)
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                ("x", -2, 2, 1),
+                ("y", -2, 2, 2),
+                ("x", 0, 4, 1),
+                ("y", 0, 4, 2),
+                ("x", 2, 6, 3),
+                ("x", 4, 8, 3),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_allowed_lateness_refires_windows() {
+        let mut pipe = WindowPipe::new(WindowKind::Tumbling { size: 10 }).unwrap();
+        pipe.set_allowed_lateness(5).unwrap();
+        assert!(pipe.set_allowed_lateness(-1).is_err());
+        pipe.pipe(Rc::new(MockSource::new().items([
+            Timed::Event(Event::new(1, 1)),
+            Timed::Watermark(12),
+            Timed::Event(Event::new(2, 2)),
+            Timed::Watermark(15),
+            Timed::Event(Event::new(3, 3)),
+        ])))
+        .unwrap();
+        let late = pipe.late_output();
+        let (windows, late) = block_on(async {
+            futures::join!(
+                Pin::from(pipe.stream()).collect::<Vec<_>>(),
+                Pin::from(late.stream()).collect::<Vec<_>>()
+            )
+        });
+        let firings: Vec<(Firing, usize)> = windows
+            .iter()
+            .map(|window| (window.firing, window.events.len()))
+            .collect();
+        assert_eq!(firings, vec![(Firing::OnTime, 1), (Firing::Late, 2)]);
+        assert_eq!(late, vec![Event::new(3, 3)]);
+    }
+
+    #[test]
+    fn test_session_windows_per_key() {
+        let mut pipe =
+            WindowPipe::keyed(WindowKind::Session { gap: 5 }, |user: &char| *user).unwrap();
+        pipe.pipe(Rc::new(MockSource::new().items([
+            Timed::Event(Event::new(0, 'a')),
+            Timed::Event(Event::new(1, 'b')),
+            Timed::Event(Event::new(7, 'a')),
+            Timed::Event(Event::new(3, 'a')),
+            Timed::Watermark(10),
+            Timed::Event(Event::new(20, 'b')),
+            Timed::Event(Event::new(25, 'b')),
+        ])))
+        .unwrap();
+        let windows: Vec<(char, i64, i64, usize)> = collect(&pipe)
+            .iter()
+            .map(|window| (window.key, window.start, window.end, window.events.len()))
+            .collect();
+        assert_eq!(
+            windows,
+            vec![
+                ('b', 1, 6, 1),
+                ('a', 0, 12, 3),
+                ('b', 20, 25, 1),
+                ('b', 25, 30, 1)
+            ],
+            "Failure to merge sessions"
+        );
+        assert!(WindowPipe::<()>::new(WindowKind::Session { gap: 0 }).is_err());
+    }
+
+    #[test]
+    fn test_early_and_late_firings() {
+        let mut pipe = WindowPipe::new(WindowKind::Tumbling { size: 10 }).unwrap();
+        pipe.set_allowed_lateness(10).unwrap();
+        pipe.set_trigger(EarlyLateTrigger::new().with_early(CountTrigger::new(2).unwrap()));
+        pipe.pipe(Rc::new(MockSource::new().items([
+            Timed::Event(Event::new(1, 1)),
+            Timed::Event(Event::new(2, 2)),
+            Timed::Event(Event::new(3, 3)),
+            Timed::Watermark(10),
+            Timed::Event(Event::new(4, 4)),
+            Timed::Event(Event::new(15, 5)),
+        ])))
+        .unwrap();
+        let firings: Vec<(i64, Firing, usize)> = collect(&pipe)
+            .iter()
+            .map(|window| (window.start, window.firing, window.events.len()))
+            .collect();
+        assert_eq!(
+            firings,
+            vec![
+                (0, Firing::Early, 2),
+                (0, Firing::OnTime, 3),
+                (0, Firing::Late, 4),
+                (10, Firing::OnTime, 1),
+            ]
+        );
+    }
+}
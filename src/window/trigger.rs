@@ -0,0 +1,215 @@
+//! trigger
+//!
+//! Deciding when window pipes emit their windows
+//!
+//! a trigger is consulted for every pane (the events of one key in one window) after an
+//! event is added to it and after every advance of the watermark, each firing emits the
+//! events accumulated in the pane so far, processing time is only observed when items
+//! reach the window pipe
+
+use std::time::Duration;
+
+/// PaneStatus
+/// What a trigger knows of a pane when deciding whether to fire it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PaneStatus {
+    pub start: i64,
+    pub end: i64,
+    pub watermark: i64,
+    /// events in the pane
+    pub events: usize,
+    /// events added since the last firing
+    pub pending: usize,
+    /// firings so far
+    pub firings: usize,
+    /// whether the pane fired since the watermark passed the end of its window
+    pub fired_on_time: bool,
+    /// processing time elapsed since the last firing or the creation of the pane
+    pub since_firing: Duration,
+}
+
+impl PaneStatus {
+    /// whether the watermark passed the end of the window
+    pub fn is_complete(&self) -> bool {
+        self.end <= self.watermark
+    }
+}
+
+/// Trigger
+/// Decides when the panes of a window pipe fire
+pub trait Trigger {
+    /// whether to fire after an event was added to the pane
+    fn on_event(&self, status: &PaneStatus) -> bool;
+    /// whether to fire after the watermark advanced
+    fn on_watermark(&self, status: &PaneStatus) -> bool;
+}
+
+/// WatermarkTrigger
+/// Fires once when the watermark passes the end of the window, then for every late event
+/// (the default trigger of window pipes)
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WatermarkTrigger;
+
+impl Trigger for WatermarkTrigger {
+    fn on_event(&self, status: &PaneStatus) -> bool {
+        status.is_complete()
+    }
+    fn on_watermark(&self, status: &PaneStatus) -> bool {
+        status.is_complete() && !status.fired_on_time && status.pending > 0
+    }
+}
+
+/// CountTrigger
+/// Fires every time a given number of events accumulated since the last firing
+#[derive(Clone, Copy, Debug)]
+pub struct CountTrigger {
+    count: usize,
+}
+
+impl CountTrigger {
+    /// constructor
+    pub fn new(count: usize) -> Result<Self, &'static str> {
+        if count == 0 {
+            return Err("Trigger count must be strictly positive");
+        }
+        Ok(Self { count })
+    }
+}
+
+impl Trigger for CountTrigger {
+    fn on_event(&self, status: &PaneStatus) -> bool {
+        status.pending >= self.count
+    }
+    fn on_watermark(&self, _status: &PaneStatus) -> bool {
+        false
+    }
+}
+
+/// ProcessingTimeTrigger
+/// Fires panes with pending events once a processing time interval elapsed since their
+/// last firing
+#[derive(Clone, Copy, Debug)]
+pub struct ProcessingTimeTrigger {
+    interval: Duration,
+}
+
+impl ProcessingTimeTrigger {
+    /// constructor
+    pub fn new(interval: Duration) -> Self {
+        Self { interval }
+    }
+    fn is_due(&self, status: &PaneStatus) -> bool {
+        status.pending > 0 && status.since_firing >= self.interval
+    }
+}
+
+impl Trigger for ProcessingTimeTrigger {
+    fn on_event(&self, status: &PaneStatus) -> bool {
+        self.is_due(status)
+    }
+    fn on_watermark(&self, status: &PaneStatus) -> bool {
+        self.is_due(status)
+    }
+}
+
+/// EarlyLateTrigger
+/// Fires when the watermark passes the end of the window, consulting an early trigger for
+/// speculative firings before that and a late trigger for the events arriving after
+///
+/// without an early trigger the window does not fire before the watermark, without a late
+/// trigger every late event fires the window
+#[derive(Default)]
+pub struct EarlyLateTrigger {
+    early: Option<Box<dyn Trigger>>,
+    late: Option<Box<dyn Trigger>>,
+}
+
+impl EarlyLateTrigger {
+    /// constructor firing on the watermark only
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// set the trigger consulted before the watermark passes the end of the window
+    pub fn with_early<T: Trigger + 'static>(mut self, early: T) -> Self {
+        self.early = Some(Box::new(early));
+        self
+    }
+    /// set the trigger consulted after the on time firing
+    pub fn with_late<T: Trigger + 'static>(mut self, late: T) -> Self {
+        self.late = Some(Box::new(late));
+        self
+    }
+}
+
+impl Trigger for EarlyLateTrigger {
+    fn on_event(&self, status: &PaneStatus) -> bool {
+        if !status.is_complete() {
+            return self
+                .early
+                .as_ref()
+                .is_some_and(|early| early.on_event(status));
+        }
+        match &self.late {
+            Some(late) => late.on_event(status),
+            None => true,
+        }
+    }
+    fn on_watermark(&self, status: &PaneStatus) -> bool {
+        if !status.is_complete() {
+            return self
+                .early
+                .as_ref()
+                .is_some_and(|early| early.on_watermark(status));
+        }
+        if !status.fired_on_time {
+            return status.pending > 0;
+        }
+        self.late
+            .as_ref()
+            .is_some_and(|late| late.on_watermark(status))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(end: i64, watermark: i64, pending: usize) -> PaneStatus {
+        PaneStatus {
+            start: 0,
+            end,
+            watermark,
+            events: pending,
+            pending,
+            firings: 0,
+            fired_on_time: false,
+            since_firing: Duration::ZERO,
+        }
+    }
+
+    #[test]
+    fn test_triggers() {
+        assert!(WatermarkTrigger.on_watermark(&status(10, 10, 1)));
+        assert!(!WatermarkTrigger.on_watermark(&status(10, 9, 1)));
+        assert!(!WatermarkTrigger.on_event(&status(10, 9, 1)));
+        let count = CountTrigger::new(2).unwrap();
+        assert!(!count.on_event(&status(10, 0, 1)));
+        assert!(count.on_event(&status(10, 0, 2)));
+        assert!(CountTrigger::new(0).is_err());
+        let timer = ProcessingTimeTrigger::new(Duration::ZERO);
+        assert!(timer.on_watermark(&status(10, 0, 1)));
+        assert!(
+            !timer.on_watermark(&status(10, 0, 0)),
+            "Fired an empty pane"
+        );
+        let early_late = EarlyLateTrigger::new()
+            .with_early(count)
+            .with_late(CountTrigger::new(3).unwrap());
+        assert!(early_late.on_event(&status(10, 0, 2)));
+        assert!(early_late.on_watermark(&status(10, 10, 1)));
+        let mut late = status(10, 12, 2);
+        late.fired_on_time = true;
+        assert!(!early_late.on_event(&late));
+        assert!(!early_late.on_watermark(&late));
+    }
+}